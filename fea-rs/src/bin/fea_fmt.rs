@@ -0,0 +1,72 @@
+//! format a fea file, printing the result to stdout
+//!
+//! With `--write`, the file is instead formatted in place; with `--check`, we
+//! only report whether the file is already formatted.
+use std::{env, ffi::OsStr, path::PathBuf};
+
+macro_rules! exit_err {
+    ($($arg:tt)*) => ({
+        eprintln!($($arg)*);
+        std::process::exit(1);
+    })
+}
+
+fn main() {
+    let args = Args::get_from_env_or_exit();
+    let raw_fea = match std::fs::read_to_string(&args.path) {
+        Ok(text) => text,
+        Err(e) => exit_err!("failed to read {}: '{e}'", args.path.display()),
+    };
+    let formatted = match fea_rs::util::format::format_fea(&raw_fea) {
+        Ok(formatted) => formatted,
+        Err(errs) => exit_err!("{}", errs.display()),
+    };
+
+    match args.mode {
+        Mode::Print => print!("{formatted}"),
+        Mode::Write if formatted != raw_fea => {
+            if let Err(e) = std::fs::write(&args.path, formatted) {
+                exit_err!("failed to write {}: '{e}'", args.path.display());
+            }
+        }
+        Mode::Write => (),
+        Mode::Check if formatted != raw_fea => {
+            exit_err!("{} is not formatted", args.path.display())
+        }
+        Mode::Check => (),
+    }
+}
+
+enum Mode {
+    Print,
+    Write,
+    Check,
+}
+
+struct Args {
+    path: PathBuf,
+    mode: Mode,
+}
+
+impl Args {
+    fn get_from_env_or_exit() -> Self {
+        let mut path = None;
+        let mut mode = Mode::Print;
+        for arg in env::args().skip(1) {
+            match arg.as_str() {
+                "-w" | "--write" => mode = Mode::Write,
+                "--check" => mode = Mode::Check,
+                _ if path.is_none() => path = Some(PathBuf::from(arg)),
+                _ => exit_err!("unexpected argument '{arg}'"),
+            }
+        }
+
+        let path = match path {
+            Some(p) if p.is_file() && p.extension() == Some(OsStr::new("fea")) => p,
+            Some(p) => exit_err!("path {:?} is not an existing .fea file, exiting", p),
+            None => exit_err!("Usage: fea-fmt [--write | --check] FILE.fea"),
+        };
+
+        Args { path, mode }
+    }
+}
@@ -16,8 +16,9 @@ pub use compiler::Compiler;
 pub use feature_writer::{FeatureBuilder, FeatureProvider, NopFeatureProvider, PendingLookup};
 pub use language_system::LanguageSystem;
 pub use lookups::{
-    Builder, CursivePosBuilder, FeatureKey, LookupId, MarkToBaseBuilder, MarkToLigBuilder,
-    MarkToMarkBuilder, PairPosBuilder, PreviouslyAssignedClass,
+    AlternateSubBuilder, Builder, CursivePosBuilder, FeatureKey, LigatureSubBuilder, LookupId,
+    MarkToBaseBuilder, MarkToLigBuilder, MarkToMarkBuilder, MultipleSubBuilder, PairPosBuilder,
+    PreviouslyAssignedClass, SingleSubBuilder,
 };
 pub use metrics::{Anchor, CaretValue, ValueRecord};
 pub use opts::Opts;
@@ -257,6 +257,7 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
                 hhea: self.tables.hhea.clone(),
                 vhea: self.tables.vhea.clone(),
                 os2: self.tables.os2.as_ref().map(|raw| raw.build()),
+                mvar: self.tables.mvar.build(axis_count),
                 gdef,
                 base: self.tables.base.as_ref().map(|raw| raw.build()),
                 name: name_builder.build(),
@@ -284,6 +285,10 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
         writer.add_features(&mut builder);
         let mut external_features = builder.finish();
         external_features.merge_into(&mut self.lookups, &mut self.features, &self.insert_markers);
+        // conditionsets first used by the feature writer sort after those in the FEA
+        for conditionset in &external_features.conditionsets {
+            self.conditionset_defs.register_use(conditionset);
+        }
         external_features.lig_carets
     }
 
@@ -391,7 +396,7 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
         self.script = None;
     }
 
-    fn start_lookup_block(&mut self, name: &Token) {
+    fn start_lookup_block(&mut self, name: &Token, use_extension: bool) {
         if let Some((id, _name)) = self.lookups.finish_current() {
             assert!(_name.is_none(), "lookup blocks cannot be nested");
             self.add_lookup_to_current_feature_if_present(id);
@@ -402,7 +407,7 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
         }
 
         self.vertical_feature.begin_lookup_block();
-        self.lookups.start_named(name.text.clone());
+        self.lookups.start_named(name.text.clone(), use_extension);
     }
 
     fn end_lookup_block(&mut self) {
@@ -539,16 +544,29 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
         }
     }
 
-    fn ensure_current_lookup_type(&mut self, kind: Kind) -> &mut SomeLookup {
+    /// Returns the current lookup, first starting a new one if it can't hold
+    /// rules of this kind.
+    ///
+    /// Reports an error at `range` if this kind of rule can't start a lookup.
+    fn ensure_current_lookup_type(
+        &mut self,
+        kind: Kind,
+        range: Range<usize>,
+    ) -> Option<&mut SomeLookup> {
         if !self.lookups.has_current_kind(kind) || !self.lookups.has_same_flags(self.lookup_flags) {
             //FIXME: find another way of ensuring that named lookup blocks don't
             //contain mismatched rules
             //assert!(!self.lookups.is_named(), "ensure rule type in validation");
-            if let Some(lookup) = self.lookups.start_lookup(kind, self.lookup_flags) {
-                self.add_lookup_to_current_feature_if_present(lookup);
+            match self.lookups.start_lookup(kind, self.lookup_flags) {
+                Ok(Some(lookup)) => self.add_lookup_to_current_feature_if_present(lookup),
+                Ok(None) => (),
+                Err(kind) => {
+                    self.error(range, format!("'{kind}' rules cannot start a lookup"));
+                    return None;
+                }
             }
         }
-        self.lookups.current_mut().expect("we just created it")
+        Some(self.lookups.current_mut().expect("we just created it"))
     }
 
     fn add_lookup_to_current_feature_if_present(&mut self, lookup: LookupId) {
@@ -596,7 +614,10 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
             // This is explicitly forbidden in the OpenType spec, and
             // explicitly encouraged in the FEA spec, and everyone else does it.
             // see https://github.com/adobe-type-tools/afdko/issues/1438
-            let lookup = self.ensure_current_lookup_type(Kind::GsubType2);
+            let Some(lookup) = self.ensure_current_lookup_type(Kind::GsubType2, node.range())
+            else {
+                return;
+            };
             for target in target.iter() {
                 lookup.add_gsub_type_2(target, vec![]);
             }
@@ -604,12 +625,18 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
             && self.lookups.has_same_flags(self.lookup_flags)
         {
             // we combine chains of mixed single & multi-sub rules into multi-sub lookups
-            let lookup = self.ensure_current_lookup_type(Kind::GsubType2);
+            let Some(lookup) = self.ensure_current_lookup_type(Kind::GsubType2, node.range())
+            else {
+                return;
+            };
             for (target, replacement) in target.iter().zip(replacement.into_iter_for_target()) {
                 lookup.add_gsub_type_2(target, vec![replacement]);
             }
         } else {
-            let lookup = self.ensure_current_lookup_type(Kind::GsubType1);
+            let Some(lookup) = self.ensure_current_lookup_type(Kind::GsubType1, node.range())
+            else {
+                return;
+            };
             for (target, replacement) in target.iter().zip(replacement.into_iter_for_target()) {
                 lookup.add_gsub_type_1(target, replacement);
             }
@@ -673,14 +700,18 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
         // if this is the first multi-sub rule after a sequence of single-sub rules,
         // we need to promote the current single-sub lookup before continuing.
         self.lookups.promote_single_sub_to_multi_if_necessary();
-        let lookup = self.ensure_current_lookup_type(Kind::GsubType2);
+        let Some(lookup) = self.ensure_current_lookup_type(Kind::GsubType2, node.range()) else {
+            return;
+        };
         lookup.add_gsub_type_2(target_id, replacement);
     }
 
     fn add_alternate_sub(&mut self, node: &typed::Gsub3) {
         let target = self.resolve_glyph(&node.target());
         let alts = self.resolve_glyph_class(&node.alternates());
-        let lookup = self.ensure_current_lookup_type(Kind::GsubType3);
+        let Some(lookup) = self.ensure_current_lookup_type(Kind::GsubType3, node.range()) else {
+            return;
+        };
         lookup.add_gsub_type_3(target, alts.iter().collect());
     }
 
@@ -690,7 +721,9 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
             .map(|g| self.resolve_glyph_or_class(&g))
             .collect::<Vec<_>>();
         let replacement = self.resolve_glyph(&node.replacement());
-        let lookup = self.ensure_current_lookup_type(Kind::GsubType4);
+        let Some(lookup) = self.ensure_current_lookup_type(Kind::GsubType4, node.range()) else {
+            return;
+        };
 
         for target in sequence_enumerator(&target) {
             lookup.add_gsub_type_4(target, replacement);
@@ -710,7 +743,7 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
                     .map(|inp| self.resolve_glyph_or_class(&inp.target()))
                     .collect::<Vec<_>>();
                 let replacement = self.resolve_glyph(&rule.replacement_glyphs().next().unwrap());
-                let lookup = self.ensure_current_lookup_type(Kind::GsubType6);
+                let lookup = self.ensure_current_lookup_type(Kind::GsubType6, node.range())?;
                 let mut to_return = None;
                 for target in sequence_enumerator(&target) {
                     to_return = Some(
@@ -728,7 +761,8 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
                     if let Some((target, replacement)) =
                         self.validate_single_sub_inputs(&target, Some(&replacement))
                     {
-                        let lookup = self.ensure_current_lookup_type(Kind::GsubType6);
+                        let lookup =
+                            self.ensure_current_lookup_type(Kind::GsubType6, node.range())?;
                         Some(
                             lookup
                                 .as_gsub_contextual()
@@ -743,7 +777,7 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
                     // outside contexts, and exists in some fonts.
 
                     let targets = self.resolve_glyph_or_class(&target);
-                    let lookup = self.ensure_current_lookup_type(Kind::GsubType6);
+                    let lookup = self.ensure_current_lookup_type(Kind::GsubType6, node.range())?;
                     let mut lookup_id = None;
                     for target in targets.iter() {
                         lookup_id = Some(
@@ -768,7 +802,8 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
                             .collect()
                     };
                     if let Some(target_id) = self.resolve_glyph_or_class(&target).iter().next() {
-                        let lookup = self.ensure_current_lookup_type(Kind::GsubType6);
+                        let lookup =
+                            self.ensure_current_lookup_type(Kind::GsubType6, node.range())?;
                         Some(
                             lookup
                                 .as_gsub_contextual()
@@ -808,8 +843,9 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
             })
             .collect::<Vec<_>>();
 
-        let lookup = self.ensure_current_lookup_type(Kind::GsubType6);
-        lookup.add_contextual_rule(backtrack, context, lookahead);
+        if let Some(lookup) = self.ensure_current_lookup_type(Kind::GsubType6, node.range()) {
+            lookup.add_contextual_rule(backtrack, context, lookahead);
+        }
     }
 
     fn add_contextual_sub_ignore(&mut self, node: &typed::GsubIgnore) {
@@ -823,24 +859,83 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
         let lookahead = self.resolve_lookahead_sequence(node.lookahead().items());
         let input = node.input().items().next().unwrap();
         let target = input.target();
-        let replacement = node.inline_rule().and_then(|r| r.replacements().next());
-        //FIXME: warn if there are actual lookups here, we don't support that
-        if let Some((target, replacement)) =
+        let context = if let Some(lookup) = input.lookups().next() {
+            self.resolve_reverse_sub_lookup(&target, &lookup)
+        } else {
+            let replacement = node.inline_rule().and_then(|r| r.replacements().next());
             self.validate_single_sub_inputs(&target, replacement.as_ref())
-        {
-            let context = target
-                .iter()
-                .zip(replacement.into_iter_for_target())
-                .collect();
-            self.ensure_current_lookup_type(Kind::GsubType8)
-                .add_gsub_type_8(backtrack, context, lookahead);
+                .map(|(target, replacement)| {
+                    target
+                        .iter()
+                        .zip(replacement.into_iter_for_target())
+                        .collect()
+                })
+        };
+        let Some(context) = context else {
+            return;
+        };
+        if let Some(lookup) = self.ensure_current_lookup_type(Kind::GsubType8, node.range()) {
+            lookup.add_gsub_type_8(backtrack, context, lookahead);
         }
     }
 
+    /// The substitutions a named lookup in an rsub rule makes to the input glyphs.
+    ///
+    /// ReverseChainSingleSubst can't reference other lookups, so we copy the
+    /// mapping of the (single substitution) lookup into the rule.
+    fn resolve_reverse_sub_lookup(
+        &mut self,
+        target: &typed::GlyphOrClass,
+        lookup: &typed::LookupRef,
+    ) -> Option<BTreeMap<GlyphId16, GlyphId16>> {
+        let id = self.lookups.get_named(&lookup.label().text).unwrap(); // validated already
+        let mapping = match id {
+            LookupId::Empty => Default::default(),
+            LookupId::Gpos(_) => {
+                self.error(
+                    lookup.label().range(),
+                    "Invalid lookup: expected GSUB, found GPOS",
+                );
+                return None;
+            }
+            _ => match self.lookups.single_sub_mapping(id) {
+                Some(mapping) => mapping,
+                None => {
+                    self.error(
+                        lookup.label().range(),
+                        "rsub rules can only reference single substitution lookups",
+                    );
+                    return None;
+                }
+            },
+        };
+        // Like feaLib, every input glyph must be substituted by the lookup
+        let target_ids = self.resolve_glyph_or_class(target);
+        let unmapped: Vec<_> = target_ids
+            .iter()
+            .filter(|gid| !mapping.contains_key(gid))
+            .map(|gid| format!("'{}'", self.reverse_glyph_map.get(&gid).unwrap()))
+            .collect();
+        if !unmapped.is_empty() {
+            self.error(
+                target.range(),
+                format!(
+                    "lookup '{}' does not substitute {}",
+                    lookup.label().text,
+                    unmapped.join(", ")
+                ),
+            );
+            return None;
+        }
+        Some(target_ids.iter().map(|gid| (gid, mapping[&gid])).collect())
+    }
+
     fn add_single_pos(&mut self, node: &typed::Gpos1) {
         let ids = self.resolve_glyph_or_class(&node.target());
         let record = self.resolve_value_record(&node.value());
-        let lookup = self.ensure_current_lookup_type(Kind::GposType1);
+        let Some(lookup) = self.ensure_current_lookup_type(Kind::GposType1, node.range()) else {
+            return;
+        };
         for id in ids.iter() {
             lookup.add_gpos_type_1(id, record.clone());
         }
@@ -860,7 +955,9 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
             .unwrap_or_default()
             .for_pair_pos(in_vert_feature);
 
-        let lookup = self.ensure_current_lookup_type(Kind::GposType2);
+        let Some(lookup) = self.ensure_current_lookup_type(Kind::GposType2, node.range()) else {
+            return;
+        };
 
         if (first_ids.is_class() || second_ids.is_class()) && node.enum_().is_none() {
             lookup.add_gpos_type_2_class(
@@ -889,7 +986,9 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
         // will fail.
         let entry = self.resolve_anchor(&node.entry());
         let exit = self.resolve_anchor(&node.exit());
-        let lookup = self.ensure_current_lookup_type(Kind::GposType3);
+        let Some(lookup) = self.ensure_current_lookup_type(Kind::GposType3, node.range()) else {
+            return;
+        };
         for id in ids.iter() {
             lookup.add_gpos_type_3(id, entry.clone(), exit.clone())
         }
@@ -897,7 +996,12 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
 
     fn add_mark_to_base(&mut self, node: &typed::Gpos4) {
         let base_ids = self.resolve_glyph_or_class(&node.base());
-        let _ = self.ensure_current_lookup_type(Kind::GposType4);
+        if self
+            .ensure_current_lookup_type(Kind::GposType4, node.range())
+            .is_none()
+        {
+            return;
+        }
         for mark in node.attachments() {
             let base_anchor = self.resolve_anchor(&mark.anchor());
 
@@ -948,7 +1052,12 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
 
         let mut components = Vec::new();
         for component in node.ligature_components() {
-            let _lookup = self.ensure_current_lookup_type(Kind::GposType5);
+            if self
+                .ensure_current_lookup_type(Kind::GposType5, node.range())
+                .is_none()
+            {
+                return;
+            }
 
             let mut anchor_records = BTreeMap::new();
             for attachment in component.attachments() {
@@ -1007,7 +1116,12 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
     //significantly.
     fn add_mark_to_mark(&mut self, node: &typed::Gpos6) {
         let base_ids = self.resolve_glyph_or_class(&node.base());
-        let _ = self.ensure_current_lookup_type(Kind::GposType6);
+        if self
+            .ensure_current_lookup_type(Kind::GposType6, node.range())
+            .is_none()
+        {
+            return;
+        }
         for mark in node.attachments() {
             let base_anchor = self.resolve_anchor(&mark.anchor());
             let mark_class_node = mark.mark_class_name().expect("checked in validation");
@@ -1075,11 +1189,14 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
                 // only one item
                 if let Some(value) = trailing_value_record.clone().or_else(|| item.valuerecord()) {
                     let value = self.resolve_value_record(&value);
-                    let anon_id = self
-                        .ensure_current_lookup_type(Kind::GposType8)
-                        .as_gpos_contextual()
-                        .add_anon_gpos_type_1(&glyphs, value);
-                    lookups.push(anon_id);
+                    if let Some(lookup) =
+                        self.ensure_current_lookup_type(Kind::GposType8, node.range())
+                    {
+                        let anon_id = lookup
+                            .as_gpos_contextual()
+                            .add_anon_gpos_type_1(&glyphs, value);
+                        lookups.push(anon_id);
+                    }
                 }
 
                 for lookup in item.lookups() {
@@ -1096,8 +1213,9 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
                 (glyphs, lookups)
             })
             .collect();
-        self.ensure_current_lookup_type(Kind::GposType8)
-            .add_contextual_rule(backtrack, context, lookahead);
+        if let Some(lookup) = self.ensure_current_lookup_type(Kind::GposType8, node.range()) {
+            lookup.add_contextual_rule(backtrack, context, lookahead);
+        }
     }
 
     fn add_contextual_pos_ignore(&mut self, node: &typed::GposIgnore) {
@@ -1114,8 +1232,9 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
             .items()
             .map(|item| (self.resolve_glyph_or_class(&item.target()), Vec::new()))
             .collect();
-        let lookup = self.ensure_current_lookup_type(kind);
-        lookup.add_contextual_rule(backtrack, context, lookahead);
+        if let Some(lookup) = self.ensure_current_lookup_type(kind, rule.range()) {
+            lookup.add_contextual_rule(backtrack, context, lookahead);
+        }
     }
 
     /// Resolve a value record, ignoring zero values
@@ -1552,7 +1671,17 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
                     }
                 }
                 typed::Os2TableItem::Metric(val) => {
-                    let value = val.metric().parse_simple().expect("checked in validation");
+                    let mvar_tag = match val.keyword().kind {
+                        Kind::TypoAscenderKw => b"hasc",
+                        Kind::TypoDescenderKw => b"hdsc",
+                        Kind::TypoLineGapKw => b"hlgp",
+                        Kind::XHeightKw => b"xhgt",
+                        Kind::CapHeightKw => b"cpht",
+                        Kind::WinAscentKw => b"hcla",
+                        Kind::WinDescentKw => b"hcld",
+                        _ => unreachable!("checked at parse time"),
+                    };
+                    let value = self.resolve_table_metric(&val.metric(), mvar_tag);
                     match val.keyword().kind {
                         Kind::TypoAscenderKw => os2.s_typo_ascender = value,
                         Kind::TypoDescenderKw => os2.s_typo_descender = value,
@@ -1692,19 +1821,26 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
         }
     }
 
+    // There are no MVAR tags specific to the hhea ascender, descender & line gap;
+    // like HarfBuzz we use the ones that are also used for the typo metrics in OS/2.
     fn resolve_hhea(&mut self, table: &typed::HheaTable) {
         let mut hhea = tables::hhea::Hhea::default();
         for record in table.metrics() {
             let keyword = record.keyword();
-            let value = record
-                .metric()
-                .parse_simple()
-                .expect("checked during validation");
+            let metric = record.metric();
             match keyword.kind {
-                Kind::CaretOffsetKw => hhea.caret_offset = value,
-                Kind::AscenderKw => hhea.ascender = value.into(),
-                Kind::DescenderKw => hhea.descender = value.into(),
-                Kind::LineGapKw => hhea.line_gap = value.into(),
+                Kind::CaretOffsetKw => {
+                    hhea.caret_offset = self.resolve_table_metric(&metric, b"hcof")
+                }
+                Kind::AscenderKw => {
+                    hhea.ascender = self.resolve_table_metric(&metric, b"hasc").into()
+                }
+                Kind::DescenderKw => {
+                    hhea.descender = self.resolve_table_metric(&metric, b"hdsc").into()
+                }
+                Kind::LineGapKw => {
+                    hhea.line_gap = self.resolve_table_metric(&metric, b"hlgp").into()
+                }
                 other => panic!("bug in parser, unexpected token '{}'", other),
             }
         }
@@ -1715,21 +1851,38 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
         let mut vhea = tables::vhea::Vhea::default();
         for record in table.metrics() {
             let keyword = record.keyword();
-            let value = record
-                .metric()
-                .parse_simple()
-                .expect("checked during validation");
+            let metric = record.metric();
 
             match keyword.kind {
-                Kind::VertTypoAscenderKw => vhea.ascender = value.into(),
-                Kind::VertTypoDescenderKw => vhea.descender = value.into(),
-                Kind::VertTypoLineGapKw => vhea.line_gap = value.into(),
+                Kind::VertTypoAscenderKw => {
+                    vhea.ascender = self.resolve_table_metric(&metric, b"vasc").into()
+                }
+                Kind::VertTypoDescenderKw => {
+                    vhea.descender = self.resolve_table_metric(&metric, b"vdsc").into()
+                }
+                Kind::VertTypoLineGapKw => {
+                    vhea.line_gap = self.resolve_table_metric(&metric, b"vlgp").into()
+                }
                 other => panic!("bug in parser, unexpected token '{}'", other),
             }
         }
         self.tables.vhea = Some(vhea);
     }
 
+    /// Resolve a metric in the hhea, vhea or OS/2 table, returning the default value.
+    ///
+    /// If the metric is variable, its deltas are added to the MVAR table.
+    fn resolve_table_metric(&mut self, metric: &typed::Metric, mvar_tag: &[u8; 4]) -> i16 {
+        let Metric {
+            default,
+            device_or_deltas,
+        } = self.resolve_metric(metric);
+        if let DeviceOrDeltas::Deltas(deltas) = device_or_deltas {
+            self.tables.mvar.add(Tag::new(mvar_tag), deltas);
+        }
+        default
+    }
+
     fn resolve_vmtx(&mut self, table: &typed::VmtxTable) {
         let mut vmtx = super::tables::VmtxBuilder::default();
         for item in table.statements() {
@@ -1853,9 +2006,8 @@ impl<'a, F: FeatureProvider, V: VariationInfo> CompilationCtx<'a, F, V> {
     }
 
     fn resolve_lookup_block(&mut self, lookup: typed::LookupBlock) {
-        self.start_lookup_block(lookup.label());
+        self.start_lookup_block(lookup.label(), lookup.use_extension().is_some());
 
-        //let use_extension = lookup.use_extension().is_some();
         for item in lookup.statements() {
             self.resolve_statement(item);
         }
@@ -2259,4 +2411,15 @@ mod tests {
             ]
         );
     }
+
+    // extension lookups come from 'useExtension', never from rules
+    #[test]
+    fn extension_kind_cannot_start_lookup() {
+        let mut lookups = AllLookups::default();
+        assert_eq!(
+            lookups.start_lookup(Kind::GsubType7, LookupFlagInfo::default()),
+            Err(Kind::GsubType7)
+        );
+        assert!(!lookups.has_current());
+    }
 }
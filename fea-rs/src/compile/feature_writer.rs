@@ -3,7 +3,7 @@
 use std::collections::{BTreeMap, BTreeSet, HashMap};
 
 use write_fonts::{
-    tables::layout::LookupFlag,
+    tables::layout::{ConditionSet, LookupFlag},
     types::{GlyphId16, Tag},
 };
 
@@ -14,6 +14,7 @@ use super::{
     language_system::{DefaultLanguageSystems, LanguageSystem},
     lookups::{
         AllLookups, FeatureKey, FilterSetId, LookupBuilder, LookupId, LookupIdMap, PositionLookup,
+        SubstitutionLookup,
     },
     tables::{GdefBuilder, Tables},
     CaretValue,
@@ -37,8 +38,11 @@ pub struct FeatureBuilder<'a> {
     pub(crate) language_systems: &'a DefaultLanguageSystems,
     pub(crate) tables: &'a mut Tables,
     pub(crate) lookups: Vec<(LookupId, PositionLookup)>,
+    pub(crate) gsub_lookups: Vec<(LookupId, SubstitutionLookup)>,
     pub(crate) features: BTreeMap<FeatureKey, FeatureLookups>,
     pub(crate) lig_carets: BTreeMap<GlyphId16, Vec<CaretValue>>,
+    // in the order they were first used, for sorting the FeatureVariations
+    conditionsets: Vec<ConditionSet>,
     mark_filter_sets: &'a mut HashMap<GlyphSet, FilterSetId>,
 }
 
@@ -51,6 +55,16 @@ pub trait GposSubtableBuilder: Sized {
     ) -> ExternalGposLookup;
 }
 
+/// The subtable builders for the GSUB lookups a client can add.
+pub trait GsubSubtableBuilder: Sized {
+    #[doc(hidden)]
+    fn to_sub_lookup(
+        flags: LookupFlag,
+        filter_set: Option<FilterSetId>,
+        subtables: Vec<Self>,
+    ) -> ExternalGsubLookup;
+}
+
 /// A lookup generated outside of user FEA
 ///
 /// This will be merged into any user-provided features during compilation.
@@ -90,6 +104,11 @@ impl<T> PendingLookup<T> {
 /// This only exists so that we can avoid making our internal types `pub`.
 pub struct ExternalGposLookup(PositionLookup);
 
+/// An externally created GSUB lookup.
+///
+/// This only exists so that we can avoid making our internal types `pub`.
+pub struct ExternalGsubLookup(SubstitutionLookup);
+
 impl<'a> FeatureBuilder<'a> {
     pub(crate) fn new(
         language_systems: &'a DefaultLanguageSystems,
@@ -100,7 +119,9 @@ impl<'a> FeatureBuilder<'a> {
             language_systems,
             tables,
             lookups: Default::default(),
+            gsub_lookups: Default::default(),
             features: Default::default(),
+            conditionsets: Default::default(),
             mark_filter_sets,
             lig_carets: Default::default(),
         }
@@ -121,7 +142,7 @@ impl<'a> FeatureBuilder<'a> {
         self.lig_carets = lig_carets;
     }
 
-    /// Add a lookup to the lookup list.
+    /// Add a lookup to the GPOS lookup list.
     ///
     /// The `LookupId` that is returned can then be included in features (i.e,
     /// passed to [`add_feature`](Self::add_feature).)
@@ -133,11 +154,37 @@ impl<'a> FeatureBuilder<'a> {
         } = lookup;
         let filter_set_id = mark_filter_set.map(|cls| self.get_filter_set_id(cls));
         let lookup = T::to_pos_lookup(flags, filter_set_id, subtables);
-        let next_id = LookupId::External(self.lookups.len());
+        let next_id = self.next_lookup_id();
         self.lookups.push((next_id, lookup.0));
         next_id
     }
 
+    /// Add a lookup to the GSUB lookup list.
+    ///
+    /// These lookups are appended after those in the FEA. As with
+    /// [`add_lookup`](Self::add_lookup), the returned `LookupId` can then be
+    /// included in features.
+    pub fn add_gsub_lookup<T: GsubSubtableBuilder>(
+        &mut self,
+        lookup: PendingLookup<T>,
+    ) -> LookupId {
+        let PendingLookup {
+            subtables,
+            flags,
+            mark_filter_set,
+        } = lookup;
+        let filter_set_id = mark_filter_set.map(|cls| self.get_filter_set_id(cls));
+        let lookup = T::to_sub_lookup(flags, filter_set_id, subtables);
+        let next_id = self.next_lookup_id();
+        self.gsub_lookups.push((next_id, lookup.0));
+        next_id
+    }
+
+    // GPOS and GSUB lookups share one sequence of temporary ids
+    fn next_lookup_id(&self) -> LookupId {
+        LookupId::External(self.lookups.len() + self.gsub_lookups.len())
+    }
+
     /// Add lookups to every default language system.
     ///
     /// Convenience method for recurring pattern.
@@ -156,6 +203,27 @@ impl<'a> FeatureBuilder<'a> {
         self.features.entry(key).or_default().base = lookups;
     }
 
+    /// Add lookups to a feature that are only applied when `conditions` are met.
+    ///
+    /// These are compiled into the `FeatureVariations` table, alongside any
+    /// variations declared in the FEA. As with [`add_feature`](Self::add_feature),
+    /// this must be called once for each language system.
+    pub fn add_feature_variation(
+        &mut self,
+        key: FeatureKey,
+        conditions: ConditionSet,
+        lookups: Vec<LookupId>,
+    ) {
+        if !self.conditionsets.contains(&conditions) {
+            self.conditionsets.push(conditions.clone());
+        }
+        self.features
+            .entry(key)
+            .or_default()
+            .variations
+            .insert(conditions, lookups);
+    }
+
     fn get_filter_set_id(&mut self, cls: GlyphSet) -> FilterSetId {
         let next_id = self.mark_filter_sets.len();
         *self.mark_filter_sets.entry(cls).or_insert_with(|| {
@@ -169,14 +237,18 @@ impl<'a> FeatureBuilder<'a> {
     pub(crate) fn finish(self) -> ExternalFeatures {
         let FeatureBuilder {
             lookups,
+            gsub_lookups,
             features,
             lig_carets,
+            conditionsets,
             ..
         } = self;
         ExternalFeatures {
             features,
             lookups,
+            gsub_lookups,
             lig_carets,
+            conditionsets,
         }
     }
 }
@@ -195,6 +267,20 @@ where
     }
 }
 
+impl<T> GsubSubtableBuilder for T
+where
+    T: Default,
+    LookupBuilder<T>: Into<SubstitutionLookup>,
+{
+    fn to_sub_lookup(
+        flags: LookupFlag,
+        filter_set: Option<FilterSetId>,
+        subtables: Vec<Self>,
+    ) -> ExternalGsubLookup {
+        ExternalGsubLookup(LookupBuilder::new_with_lookups(flags, filter_set, subtables).into())
+    }
+}
+
 // features that can be added by a feature writer
 const CURS: Tag = Tag::new(b"curs");
 const MARK: Tag = Tag::new(b"mark");
@@ -207,8 +293,11 @@ const DIST: Tag = Tag::new(b"dist");
 /// All of the state that is generated by the external provider
 pub(crate) struct ExternalFeatures {
     pub(crate) lookups: Vec<(LookupId, PositionLookup)>,
+    pub(crate) gsub_lookups: Vec<(LookupId, SubstitutionLookup)>,
     pub(crate) features: BTreeMap<FeatureKey, FeatureLookups>,
     pub(crate) lig_carets: BTreeMap<GlyphId16, Vec<CaretValue>>,
+    /// The condition sets used by feature variations, in order of first use
+    pub(crate) conditionsets: Vec<ConditionSet>,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, PartialOrd, Eq, Ord)]
@@ -229,6 +318,9 @@ struct MergeCtx<'a> {
     // that were explicit
     insert_markers: &'a HashMap<Tag, InsertionPoint>,
     ext_lookups: BTreeMap<LookupId, PositionLookup>,
+    ext_gsub_lookups: Vec<(LookupId, SubstitutionLookup)>,
+    // the final ids of the GSUB lookups, once appended
+    appended_gsub: BTreeMap<LookupId, LookupId>,
     ext_features: BTreeMap<FeatureKey, FeatureLookups>,
     // ready for insertion
     processed_lookups: Vec<(InsertionPoint, Vec<(LookupId, PositionLookup)>)>,
@@ -259,9 +351,11 @@ impl MergeCtx<'_> {
         // lookups and features in groups, replicating how they would be
         // handled by the various feature writers.
 
+        self.do_gsub();
         self.do_curs();
         self.do_kern_and_dist();
         self.do_marks();
+        self.do_other_features();
 
         // okay so now 'processed_lookups' should contain insertion points for
         // all of our lookups
@@ -279,6 +373,9 @@ impl MergeCtx<'_> {
         // lookup list, keeping track of how the ids change.
 
         let mut map = LookupIdMap::default();
+        for (temp_id, final_id) in &self.appended_gsub {
+            map.insert(*temp_id, *final_id);
+        }
         let mut inserted_so_far = 0;
 
         // 'adjustments' stores the state we need to remap existing ids, if needed.
@@ -325,6 +422,16 @@ impl MergeCtx<'_> {
         self.all_lookups.remap_ids(&map);
     }
 
+    // the GSUB lookups aren't from any of the ufo2ft writers we emulate, and
+    // we don't know where the FEA would have them; as fontTools does when it
+    // adds feature variations, we append them to the GSUB lookup list.
+    fn do_gsub(&mut self) {
+        for (temp_id, lookup) in std::mem::take(&mut self.ext_gsub_lookups) {
+            let final_id = self.all_lookups.push_gsub(lookup);
+            self.appended_gsub.insert(temp_id, final_id);
+        }
+    }
+
     fn do_curs(&mut self) {
         let curs_pos = self
             .insert_markers
@@ -403,6 +510,21 @@ impl MergeCtx<'_> {
         self.finalize_lookups_for_feature(MKMK, inserts[3].unwrap());
     }
 
+    // features not written by the ufo2ft writers we emulate above (for instance
+    // conditional alternates) are appended, in the order of their tags.
+    fn do_other_features(&mut self) {
+        let others = self
+            .ext_features
+            .keys()
+            .map(|key| key.feature)
+            .filter(|tag| ![CURS, KERN, DIST, ABVM, BLWM, MARK, MKMK].contains(tag))
+            .collect::<BTreeSet<_>>();
+        for feature in others {
+            let pos = self.insertion_point_for_append();
+            self.finalize_lookups_for_feature(feature, pos);
+        }
+    }
+
     fn finalize_lookups_for_feature(&mut self, feature: Tag, pos: InsertionPoint) {
         let lookups = self.take_lookups_for_features(&[feature]);
         if !lookups.is_empty() {
@@ -415,14 +537,28 @@ impl MergeCtx<'_> {
             .iter()
             .filter(|(feat, _)| features.contains(&feat.feature))
             .flat_map(|(_, lookups)| lookups.iter_ids())
+            .filter(|id| !self.appended_gsub.contains_key(id))
             .collect()
     }
 
     fn take_lookups_for_features(&mut self, features: &[Tag]) -> Vec<(LookupId, PositionLookup)> {
-        self.lookup_ids_for_features(features)
-            .into_iter()
-            .map(|id| (id, self.ext_lookups.remove(&id).unwrap()))
-            .collect()
+        let mut lookups = Vec::new();
+        for id in self.lookup_ids_for_features(features) {
+            match self.ext_lookups.remove(&id) {
+                Some(lookup) => lookups.push((id, lookup)),
+                // a lookup can be shared by features in different groups; it
+                // is inserted along with the first of them to be handled.
+                None if self.was_taken(id) => (),
+                None => panic!("external feature references unknown lookup {id:?}"),
+            }
+        }
+        lookups
+    }
+
+    fn was_taken(&self, id: LookupId) -> bool {
+        self.processed_lookups
+            .iter()
+            .any(|(_, lookups)| lookups.iter().any(|(taken, _)| *taken == id))
     }
 
     fn insertion_point_for_append(&mut self) -> InsertionPoint {
@@ -447,6 +583,8 @@ impl ExternalFeatures {
             all_lookups,
             all_feats,
             ext_lookups: self.lookups.iter().cloned().collect(),
+            ext_gsub_lookups: self.gsub_lookups.clone(),
+            appended_gsub: Default::default(),
             ext_features: self.features.clone(),
             insert_markers: markers,
             processed_lookups: Default::default(),
@@ -460,6 +598,8 @@ impl ExternalFeatures {
 mod tests {
     use super::*;
 
+    use write_fonts::{tables::layout::ConditionFormat1, types::F2Dot14};
+
     use crate::compile::tags::{LANG_DFLT, SCRIPT_DFLT};
 
     impl AllFeatures {
@@ -527,8 +667,10 @@ mod tests {
 
         let mut external_features = ExternalFeatures {
             lookups,
+            gsub_lookups: Default::default(),
             features,
             lig_carets: Default::default(),
+            conditionsets: Default::default(),
         };
 
         let mut all_features = AllFeatures::default();
@@ -563,9 +705,107 @@ mod tests {
         }
         ExternalFeatures {
             lookups,
+            gsub_lookups: Default::default(),
             features,
             lig_carets: Default::default(),
+            conditionsets: Default::default(),
+        }
+    }
+
+    fn condition_set(axis_index: u16, min: f32, max: f32) -> ConditionSet {
+        ConditionSet::new(vec![ConditionFormat1 {
+            axis_index,
+            filter_range_min_value: F2Dot14::from_f32(min),
+            filter_range_max_value: F2Dot14::from_f32(max),
+        }
+        .into()])
+    }
+
+    #[test]
+    fn merge_external_gsub_lookups() {
+        const RVRN: Tag = Tag::new(b"rvrn");
+        let rvrn = FeatureKey::new(RVRN, LANG_DFLT, SCRIPT_DFLT);
+        let kern = FeatureKey::new(KERN, LANG_DFLT, SCRIPT_DFLT);
+
+        let mut all = AllLookups::default();
+        all.push_gsub(SubstitutionLookup::Single(Default::default()));
+        let mut all_feats = AllFeatures::default();
+        all_feats.get_or_insert(rvrn).base = vec![LookupId::Gsub(0)];
+
+        // GSUB and GPOS lookups from the writer share one sequence of ids
+        let mut external = mock_external_features(&[KERN]);
+        let gsub_id = LookupId::External(1);
+        external
+            .gsub_lookups
+            .push((gsub_id, SubstitutionLookup::Single(Default::default())));
+        external.features.entry(rvrn).or_default().base = vec![gsub_id];
+
+        external.merge_into(&mut all, &mut all_feats, &make_markers_with_order([]));
+
+        assert_eq!(
+            all_feats.features[&rvrn].base,
+            [LookupId::Gsub(0), LookupId::Gsub(1)]
+        );
+        assert_eq!(all_feats.features[&kern].base, [LookupId::Gpos(0)]);
+    }
+
+    #[test]
+    fn merge_external_feature_variations() {
+        const CPSP: Tag = Tag::new(b"cpsp");
+        let wide = condition_set(1, 0.5, 1.0);
+        let bold = condition_set(0, 0.5, 1.0);
+        let kern = FeatureKey::new(KERN, LANG_DFLT, SCRIPT_DFLT);
+        let cpsp = FeatureKey::new(CPSP, LANG_DFLT, SCRIPT_DFLT);
+
+        // the FEA has a kern feature with a variation for 'wide'
+        let mut all = AllLookups::default();
+        all.splice_gpos(
+            0,
+            (0..2).map(|_| PositionLookup::Single(Default::default())),
+        );
+        let mut all_feats = AllFeatures::default();
+        let fea_kern = all_feats.get_or_insert(kern);
+        fea_kern.base = vec![LookupId::Gpos(0)];
+        fea_kern
+            .variations
+            .insert(wide.clone(), vec![LookupId::Gpos(1)]);
+
+        // the writer adds to both, and conditionally registers a new feature
+        let mut external = mock_external_features(&[KERN]);
+        for id in [1, 2] {
+            external.lookups.push((
+                LookupId::External(id),
+                PositionLookup::Single(Default::default()),
+            ));
         }
+        external
+            .features
+            .get_mut(&kern)
+            .unwrap()
+            .variations
+            .insert(wide.clone(), vec![LookupId::External(1)]);
+        external
+            .features
+            .entry(cpsp)
+            .or_default()
+            .variations
+            .insert(bold.clone(), vec![LookupId::External(2)]);
+
+        external.merge_into(&mut all, &mut all_feats, &make_markers_with_order([]));
+
+        let kern = &all_feats.features[&kern];
+        assert_eq!(kern.base, [LookupId::Gpos(0), LookupId::Gpos(2)]);
+        assert_eq!(
+            kern.variations[&wide],
+            [LookupId::Gpos(1), LookupId::Gpos(3)]
+        );
+        assert_eq!(
+            all_feats.features[&cpsp].variations[&bold],
+            [LookupId::Gpos(4)]
+        );
+        assert!(all_feats.features.values().all(|feature| feature
+            .iter_ids()
+            .all(|id| !matches!(id, LookupId::External(_)))));
     }
 
     fn make_markers_with_order<const N: usize>(order: [Tag; N]) -> HashMap<Tag, InsertionPoint> {
@@ -19,7 +19,8 @@ use super::{
 pub(crate) struct FeatureLookups {
     /// the base (not variation specific) lookups
     pub(crate) base: Vec<LookupId>,
-    variations: HashMap<ConditionSet, Vec<LookupId>>,
+    /// the lookups that are only applied under a given set of conditions
+    pub(crate) variations: HashMap<ConditionSet, Vec<LookupId>>,
 }
 
 /// A type to store accumulated features during compilation
@@ -240,9 +241,14 @@ impl AllFeatures {
         features: BTreeMap<FeatureKey, FeatureLookups>,
     ) {
         for (key, lookups) in features {
-            self.get_or_insert(key).base.extend(lookups.base);
-            if !lookups.variations.is_empty() {
-                panic!("specifying feature variations from feature writer is not yet supported");
+            let feature = self.get_or_insert(key);
+            feature.base.extend(lookups.base);
+            for (conditions, ids) in lookups.variations {
+                feature
+                    .variations
+                    .entry(conditions)
+                    .or_default()
+                    .extend(ids);
             }
         }
     }
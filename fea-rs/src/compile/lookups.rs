@@ -47,7 +47,7 @@ pub use gpos_builders::{
     CursivePosBuilder, MarkToBaseBuilder, MarkToLigBuilder, MarkToMarkBuilder, PairPosBuilder,
     PreviouslyAssignedClass,
 };
-use gsub_builders::{
+pub use gsub_builders::{
     AlternateSubBuilder, LigatureSubBuilder, MultipleSubBuilder, SingleSubBuilder,
 };
 pub(crate) use helpers::ClassDefBuilder2;
@@ -81,6 +81,8 @@ pub(crate) type FilterSetId = u16;
 pub(crate) struct AllLookups {
     current: Option<SomeLookup>,
     current_name: Option<SmolStr>,
+    /// Set for the duration of a lookup block with the `useExtension` keyword
+    current_use_extension: bool,
     gpos: Vec<PositionLookup>,
     gsub: Vec<SubstitutionLookup>,
     named: HashMap<SmolStr, LookupId>,
@@ -90,6 +92,11 @@ pub(crate) struct AllLookups {
 pub(crate) struct LookupBuilder<T> {
     flags: LookupFlag,
     mark_set: Option<FilterSetId>,
+    /// If `true`, each subtable is wrapped in an extension subtable.
+    ///
+    /// Other lookups are promoted to extensions (and PairPos and MarkBase
+    /// subtables split) by write-fonts only when their offsets would overflow.
+    use_extension: bool,
     subtables: Vec<T>,
 }
 
@@ -128,6 +135,22 @@ impl_into_pos_lookup!(MarkToMarkBuilder, MarkToMark);
 impl_into_pos_lookup!(MarkToLigBuilder, MarkToLig);
 impl_into_pos_lookup!(CursivePosBuilder, Cursive);
 
+// as above, for the GSUB lookups a client can add externally
+macro_rules! impl_into_sub_lookup {
+    ($builder:ty, $variant:ident) => {
+        impl From<LookupBuilder<$builder>> for SubstitutionLookup {
+            fn from(src: LookupBuilder<$builder>) -> SubstitutionLookup {
+                SubstitutionLookup::$variant(src)
+            }
+        }
+    };
+}
+
+impl_into_sub_lookup!(SingleSubBuilder, Single);
+impl_into_sub_lookup!(MultipleSubBuilder, Multiple);
+impl_into_sub_lookup!(AlternateSubBuilder, Alternate);
+impl_into_sub_lookup!(LigatureSubBuilder, Ligature);
+
 #[derive(Clone, Debug)]
 pub(crate) enum SubstitutionLookup {
     Single(LookupBuilder<SingleSubBuilder>),
@@ -212,6 +235,7 @@ impl<T: Default> LookupBuilder<T> {
         LookupBuilder {
             flags,
             mark_set,
+            use_extension: false,
             subtables: vec![Default::default()],
         }
     }
@@ -224,10 +248,17 @@ impl<T: Default> LookupBuilder<T> {
         Self {
             flags,
             mark_set,
+            use_extension: false,
             subtables,
         }
     }
 
+    /// Set whether this lookup should be compiled as an extension lookup.
+    pub(crate) fn with_extension(mut self, use_extension: bool) -> Self {
+        self.use_extension = use_extension;
+        self
+    }
+
     //TODO: if we keep this, make it unwrap and ensure we always have a subtable
     pub fn last_mut(&mut self) -> Option<&mut T> {
         self.subtables.last_mut()
@@ -248,11 +279,13 @@ impl<U> LookupBuilder<U> {
         let LookupBuilder {
             flags,
             mark_set,
+            use_extension,
             subtables,
         } = self;
         LookupBuilder {
             flags,
             mark_set,
+            use_extension,
             subtables: subtables.into_iter().map(Into::into).collect(),
         }
     }
@@ -292,6 +325,32 @@ impl PositionLookup {
         }
     }
 
+    fn set_use_extension(&mut self, use_extension: bool) {
+        match self {
+            PositionLookup::Single(lookup) => lookup.use_extension = use_extension,
+            PositionLookup::Pair(lookup) => lookup.use_extension = use_extension,
+            PositionLookup::Cursive(lookup) => lookup.use_extension = use_extension,
+            PositionLookup::MarkToBase(lookup) => lookup.use_extension = use_extension,
+            PositionLookup::MarkToLig(lookup) => lookup.use_extension = use_extension,
+            PositionLookup::MarkToMark(lookup) => lookup.use_extension = use_extension,
+            PositionLookup::Contextual(lookup) => lookup.use_extension = use_extension,
+            PositionLookup::ChainedContextual(lookup) => lookup.use_extension = use_extension,
+        }
+    }
+
+    fn use_extension(&self) -> bool {
+        match self {
+            PositionLookup::Single(lookup) => lookup.use_extension,
+            PositionLookup::Pair(lookup) => lookup.use_extension,
+            PositionLookup::Cursive(lookup) => lookup.use_extension,
+            PositionLookup::MarkToBase(lookup) => lookup.use_extension,
+            PositionLookup::MarkToLig(lookup) => lookup.use_extension,
+            PositionLookup::MarkToMark(lookup) => lookup.use_extension,
+            PositionLookup::Contextual(lookup) => lookup.use_extension,
+            PositionLookup::ChainedContextual(lookup) => lookup.use_extension,
+        }
+    }
+
     fn force_subtable_break(&mut self) {
         match self {
             PositionLookup::Single(lookup) => lookup.force_subtable_break(),
@@ -315,6 +374,30 @@ impl SubstitutionLookup {
         }
     }
 
+    fn set_use_extension(&mut self, use_extension: bool) {
+        match self {
+            SubstitutionLookup::Single(lookup) => lookup.use_extension = use_extension,
+            SubstitutionLookup::Multiple(lookup) => lookup.use_extension = use_extension,
+            SubstitutionLookup::Alternate(lookup) => lookup.use_extension = use_extension,
+            SubstitutionLookup::Ligature(lookup) => lookup.use_extension = use_extension,
+            SubstitutionLookup::Contextual(lookup) => lookup.use_extension = use_extension,
+            SubstitutionLookup::Reverse(lookup) => lookup.use_extension = use_extension,
+            SubstitutionLookup::ChainedContextual(lookup) => lookup.use_extension = use_extension,
+        }
+    }
+
+    fn use_extension(&self) -> bool {
+        match self {
+            SubstitutionLookup::Single(lookup) => lookup.use_extension,
+            SubstitutionLookup::Multiple(lookup) => lookup.use_extension,
+            SubstitutionLookup::Alternate(lookup) => lookup.use_extension,
+            SubstitutionLookup::Ligature(lookup) => lookup.use_extension,
+            SubstitutionLookup::Contextual(lookup) => lookup.use_extension,
+            SubstitutionLookup::Reverse(lookup) => lookup.use_extension,
+            SubstitutionLookup::ChainedContextual(lookup) => lookup.use_extension,
+        }
+    }
+
     fn force_subtable_break(&mut self) {
         match self {
             SubstitutionLookup::Single(lookup) => lookup.force_subtable_break(),
@@ -351,7 +434,8 @@ impl Builder for PositionLookup {
     type Output = write_gpos::PositionLookup;
 
     fn build(self, var_store: &mut VariationStoreBuilder) -> Self::Output {
-        match self {
+        let use_extension = self.use_extension();
+        let lookup = match self {
             PositionLookup::Single(lookup) => {
                 write_gpos::PositionLookup::Single(lookup.build(var_store))
             }
@@ -376,6 +460,11 @@ impl Builder for PositionLookup {
             PositionLookup::ChainedContextual(lookup) => {
                 write_gpos::PositionLookup::ChainContextual(lookup.build(var_store).into_concrete())
             }
+        };
+        if use_extension {
+            gpos_extension(lookup)
+        } else {
+            lookup
         }
     }
 }
@@ -384,7 +473,8 @@ impl Builder for SubstitutionLookup {
     type Output = write_gsub::SubstitutionLookup;
 
     fn build(self, _var_store: &mut VariationStoreBuilder) -> Self::Output {
-        match self {
+        let use_extension = self.use_extension();
+        let lookup = match self {
             SubstitutionLookup::Single(lookup) => {
                 write_gsub::SubstitutionLookup::Single(lookup.build(_var_store))
             }
@@ -408,10 +498,60 @@ impl Builder for SubstitutionLookup {
             SubstitutionLookup::Reverse(lookup) => {
                 write_gsub::SubstitutionLookup::Reverse(lookup.build(_var_store))
             }
+        };
+        if use_extension {
+            gsub_extension(lookup)
+        } else {
+            lookup
         }
     }
 }
 
+/// Wrap each subtable of a lookup in an extension subtable.
+fn into_extension<T, U: Default>(lookup: RawLookup<T>, wrap: impl Fn(T) -> U) -> RawLookup<U> {
+    let subtables = lookup
+        .subtables
+        .into_iter()
+        .map(|subtable| wrap(subtable.into_inner()))
+        .collect();
+    let mut out = RawLookup::new(lookup.lookup_flag, subtables);
+    out.mark_filtering_set = lookup.mark_filtering_set;
+    out
+}
+
+fn gpos_extension(lookup: write_gpos::PositionLookup) -> write_gpos::PositionLookup {
+    use write_gpos::{ExtensionPosFormat1 as Ext, ExtensionSubtable, PositionLookup as Lookup};
+    let extension: RawLookup<ExtensionSubtable> = match lookup {
+        Lookup::Single(lookup) => into_extension(lookup, |sub| Ext::new(1, sub).into()),
+        Lookup::Pair(lookup) => into_extension(lookup, |sub| Ext::new(2, sub).into()),
+        Lookup::Cursive(lookup) => into_extension(lookup, |sub| Ext::new(3, sub).into()),
+        Lookup::MarkToBase(lookup) => into_extension(lookup, |sub| Ext::new(4, sub).into()),
+        Lookup::MarkToLig(lookup) => into_extension(lookup, |sub| Ext::new(5, sub).into()),
+        Lookup::MarkToMark(lookup) => into_extension(lookup, |sub| Ext::new(6, sub).into()),
+        Lookup::Contextual(lookup) => into_extension(lookup, |sub| Ext::new(7, sub).into()),
+        Lookup::ChainContextual(lookup) => into_extension(lookup, |sub| Ext::new(8, sub).into()),
+        Lookup::Extension(lookup) => lookup,
+    };
+    Lookup::Extension(extension)
+}
+
+fn gsub_extension(lookup: write_gsub::SubstitutionLookup) -> write_gsub::SubstitutionLookup {
+    use write_gsub::{
+        ExtensionSubstFormat1 as Ext, ExtensionSubtable, SubstitutionLookup as Lookup,
+    };
+    let extension: RawLookup<ExtensionSubtable> = match lookup {
+        Lookup::Single(lookup) => into_extension(lookup, |sub| Ext::new(1, sub).into()),
+        Lookup::Multiple(lookup) => into_extension(lookup, |sub| Ext::new(2, sub).into()),
+        Lookup::Alternate(lookup) => into_extension(lookup, |sub| Ext::new(3, sub).into()),
+        Lookup::Ligature(lookup) => into_extension(lookup, |sub| Ext::new(4, sub).into()),
+        Lookup::Contextual(lookup) => into_extension(lookup, |sub| Ext::new(5, sub).into()),
+        Lookup::ChainContextual(lookup) => into_extension(lookup, |sub| Ext::new(6, sub).into()),
+        Lookup::Reverse(lookup) => into_extension(lookup, |sub| Ext::new(8, sub).into()),
+        Lookup::Extension(lookup) => lookup,
+    };
+    Lookup::Extension(extension)
+}
+
 impl AllLookups {
     fn push(&mut self, lookup: SomeLookup) -> LookupId {
         match lookup {
@@ -474,6 +614,11 @@ impl AllLookups {
         LookupId::Gpos(self.gpos.len())
     }
 
+    /// Add a lookup to the end of the GSUB list, returning its id
+    pub(crate) fn push_gsub(&mut self, lookup: SubstitutionLookup) -> LookupId {
+        self.push(SomeLookup::GsubLookup(lookup))
+    }
+
     /// insert a sequence of lookups into the GPOS list at a specific pos.
     ///
     /// After calling this, any existing items after `pos` will have invalid
@@ -512,13 +657,23 @@ impl AllLookups {
     }
 
     // doesn't start it, just stashes the name
-    pub(crate) fn start_named(&mut self, name: SmolStr) {
+    pub(crate) fn start_named(&mut self, name: SmolStr, use_extension: bool) {
         self.current_name = Some(name);
+        self.current_use_extension = use_extension;
     }
 
-    pub(crate) fn start_lookup(&mut self, kind: Kind, flags: LookupFlagInfo) -> Option<LookupId> {
+    /// Start a new lookup for rules of this kind, finishing the current one.
+    ///
+    /// Returns the id of the finished lookup, if any, or the kind back if
+    /// rules of this kind can't start a lookup.
+    pub(crate) fn start_lookup(
+        &mut self,
+        kind: Kind,
+        flags: LookupFlagInfo,
+    ) -> Result<Option<LookupId>, Kind> {
+        let mut new_one = SomeLookup::new(kind, flags.flags, flags.mark_filter_set)?;
         let finished_id = self.current.take().map(|lookup| self.push(lookup));
-        let mut new_one = SomeLookup::new(kind, flags.flags, flags.mark_filter_set);
+        new_one.set_use_extension(self.current_use_extension);
 
         let new_id = if is_gpos_rule(kind) {
             LookupId::Gpos(self.gpos.len())
@@ -533,10 +688,11 @@ impl AllLookups {
             SomeLookup::GsubLookup(_) | SomeLookup::GposLookup(_) => (),
         }
         self.current = Some(new_one);
-        finished_id
+        Ok(finished_id)
     }
 
     pub(crate) fn finish_current(&mut self) -> Option<(LookupId, Option<SmolStr>)> {
+        self.current_use_extension = false;
         if let Some(lookup) = self.current.take() {
             let id = self.push(lookup);
             if let Some(name) = self.current_name.take() {
@@ -565,6 +721,7 @@ impl AllLookups {
         let promoted = LookupBuilder {
             flags: lookup.flags,
             mark_set: lookup.mark_set,
+            use_extension: lookup.use_extension,
             subtables: lookup
                 .subtables
                 .into_iter()
@@ -646,6 +803,24 @@ impl AllLookups {
         }
     }
 
+    /// The mapping of a single substitution lookup, or `None` for any other kind of lookup.
+    ///
+    /// If a glyph is covered by more than one subtable, the first one wins, as when
+    /// the lookup is applied.
+    pub(crate) fn single_sub_mapping(
+        &self,
+        id: LookupId,
+    ) -> Option<BTreeMap<GlyphId16, GlyphId16>> {
+        let Some(SubstitutionLookup::Single(lookup)) = self.get_gsub_lookup(&id) else {
+            return None;
+        };
+        let mut mapping = BTreeMap::new();
+        for (target, replacement) in lookup.subtables.iter().flat_map(|sub| sub.iter_pairs()) {
+            mapping.entry(target).or_insert(replacement);
+        }
+        Some(mapping)
+    }
+
     fn get_gsub_lookup(&self, id: &LookupId) -> Option<&SubstitutionLookup> {
         match id {
             LookupId::Gsub(idx) => self.gsub.get(*idx),
@@ -829,14 +1004,22 @@ impl LookupFlagInfo {
 }
 
 impl SomeLookup {
-    fn new(kind: Kind, flags: LookupFlag, filter: Option<FilterSetId>) -> Self {
+    /// Returns the kind back if it isn't one of the rules a lookup is built from.
+    ///
+    /// Notably there are no extension (GSUB type 7) rules: a lookup block asks
+    /// for its lookup to be an extension with `useExtension`.
+    fn new(kind: Kind, flags: LookupFlag, filter: Option<FilterSetId>) -> Result<Self, Kind> {
         // special kinds:
         match kind {
             Kind::GposType7 | Kind::GposType8 => {
-                return SomeLookup::GposContextual(ContextualLookupBuilder::new(flags, filter))
+                return Ok(SomeLookup::GposContextual(ContextualLookupBuilder::new(
+                    flags, filter,
+                )))
             }
             Kind::GsubType5 | Kind::GsubType6 => {
-                return SomeLookup::GsubContextual(ContextualLookupBuilder::new(flags, filter))
+                return Ok(SomeLookup::GsubContextual(ContextualLookupBuilder::new(
+                    flags, filter,
+                )))
             }
             _ => (),
         }
@@ -849,10 +1032,9 @@ impl SomeLookup {
                 Kind::GposType4 => PositionLookup::MarkToBase(LookupBuilder::new(flags, filter)),
                 Kind::GposType5 => PositionLookup::MarkToLig(LookupBuilder::new(flags, filter)),
                 Kind::GposType6 => PositionLookup::MarkToMark(LookupBuilder::new(flags, filter)),
-                Kind::GposNode => unimplemented!("other gpos type?"),
-                other => panic!("illegal kind for lookup: '{}'", other),
+                other => return Err(other),
             };
-            SomeLookup::GposLookup(lookup)
+            Ok(SomeLookup::GposLookup(lookup))
         } else {
             let lookup = match kind {
                 Kind::GsubType1 => SubstitutionLookup::Single(LookupBuilder::new(flags, filter)),
@@ -862,11 +1044,19 @@ impl SomeLookup {
                 Kind::GsubType5 => {
                     SubstitutionLookup::Contextual(LookupBuilder::new(flags, filter))
                 }
-                Kind::GsubType7 => unimplemented!("extension"),
                 Kind::GsubType8 => SubstitutionLookup::Reverse(LookupBuilder::new(flags, filter)),
-                other => panic!("illegal kind for lookup: '{}'", other),
+                other => return Err(other),
             };
-            SomeLookup::GsubLookup(lookup)
+            Ok(SomeLookup::GsubLookup(lookup))
+        }
+    }
+
+    fn set_use_extension(&mut self, use_extension: bool) {
+        match self {
+            SomeLookup::GsubLookup(lookup) => lookup.set_use_extension(use_extension),
+            SomeLookup::GposLookup(lookup) => lookup.set_use_extension(use_extension),
+            SomeLookup::GsubContextual(lookup) => lookup.use_extension = use_extension,
+            SomeLookup::GposContextual(lookup) => lookup.use_extension = use_extension,
         }
     }
 
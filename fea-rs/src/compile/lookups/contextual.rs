@@ -29,6 +29,7 @@ use super::{
 pub(crate) struct ContextualLookupBuilder<T> {
     pub(super) flags: LookupFlag,
     pub(super) mark_set: Option<FilterSetId>,
+    pub(super) use_extension: bool,
     subtables: Vec<ContextBuilder>,
     /// anonymous lookups that are not modifiable
     ///
@@ -70,6 +71,7 @@ impl<T> ContextualLookupBuilder<T> {
         ContextualLookupBuilder {
             flags,
             mark_set,
+            use_extension: false,
             subtables: vec![Default::default()],
             root_id: LookupId::Empty,
             finished_anon_lookups: Default::default(),
@@ -81,6 +83,7 @@ impl<T> ContextualLookupBuilder<T> {
         let ContextualLookupBuilder {
             flags,
             mark_set,
+            use_extension,
             subtables,
             mut finished_anon_lookups,
             current_anon_lookups,
@@ -88,13 +91,19 @@ impl<T> ContextualLookupBuilder<T> {
         } = self;
         finished_anon_lookups.extend(current_anon_lookups);
         let lookup = if subtables.iter().any(ContextBuilder::is_chain_rule) {
-            ChainOrNot::Chain(LookupBuilder::new_with_lookups(
-                flags,
-                mark_set,
-                subtables.into_iter().map(ChainContextBuilder).collect(),
-            ))
+            ChainOrNot::Chain(
+                LookupBuilder::new_with_lookups(
+                    flags,
+                    mark_set,
+                    subtables.into_iter().map(ChainContextBuilder).collect(),
+                )
+                .with_extension(use_extension),
+            )
         } else {
-            ChainOrNot::Context(LookupBuilder::new_with_lookups(flags, mark_set, subtables))
+            ChainOrNot::Context(
+                LookupBuilder::new_with_lookups(flags, mark_set, subtables)
+                    .with_extension(use_extension),
+            )
         };
         (lookup, finished_anon_lookups)
     }
@@ -11,12 +11,15 @@ use crate::common::GlyphOrClass;
 
 use super::Builder;
 
-#[derive(Clone, Debug, Default)]
+/// A builder for GSUB type 1 (SingleSubst) subtables
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SingleSubBuilder {
     items: BTreeMap<GlyphId16, GlyphId16>,
 }
 
 impl SingleSubBuilder {
+    /// Substitute `target` with `replacement`
     pub fn insert(&mut self, target: GlyphId16, replacement: GlyphId16) {
         self.items.insert(target, replacement);
     }
@@ -76,7 +79,9 @@ impl Builder for SingleSubBuilder {
     }
 }
 
-#[derive(Clone, Debug, Default)]
+/// A builder for GSUB type 2 (MultipleSubst) subtables
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct MultipleSubBuilder {
     items: BTreeMap<GlyphId16, Vec<GlyphId16>>,
 }
@@ -96,10 +101,12 @@ impl Builder for MultipleSubBuilder {
 }
 
 impl MultipleSubBuilder {
+    /// Substitute `target` with the sequence `replacement`
     pub fn insert(&mut self, target: GlyphId16, replacement: Vec<GlyphId16>) {
         self.items.insert(target, replacement);
     }
 
+    /// Returns `true` if this rule doesn't conflict with one already added
     pub fn can_add(&self, target: GlyphId16, replacement: &[GlyphId16]) -> bool {
         match self.items.get(&target) {
             None => true,
@@ -108,12 +115,15 @@ impl MultipleSubBuilder {
     }
 }
 
-#[derive(Clone, Debug, Default)]
+/// A builder for GSUB type 3 (AlternateSubst) subtables
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AlternateSubBuilder {
     items: BTreeMap<GlyphId16, Vec<GlyphId16>>,
 }
 
 impl AlternateSubBuilder {
+    /// Offer the alternates in `replacement` for `target`
     pub fn insert(&mut self, target: GlyphId16, replacement: Vec<GlyphId16>) {
         self.items.insert(target, replacement);
     }
@@ -144,12 +154,19 @@ impl Builder for AlternateSubBuilder {
     }
 }
 
-#[derive(Clone, Debug, Default)]
+/// A builder for GSUB type 4 (LigatureSubst) subtables
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct LigatureSubBuilder {
     items: BTreeMap<GlyphId16, Vec<(Vec<GlyphId16>, GlyphId16)>>,
 }
 
 impl LigatureSubBuilder {
+    /// Substitute the sequence `target` with `replacement`
+    ///
+    /// # Panics
+    ///
+    /// If `target` is empty.
     pub fn insert(&mut self, target: Vec<GlyphId16>, replacement: GlyphId16) {
         let (first, rest) = target.split_first().unwrap();
         let entry = self.items.entry(*first).or_default();
@@ -162,6 +179,7 @@ impl LigatureSubBuilder {
         }
     }
 
+    /// Returns `true` if this rule doesn't conflict with one already added
     pub fn can_add(&self, target: &[GlyphId16], replacement: GlyphId16) -> bool {
         let Some((first, rest)) = target.split_first() else {
             return false;
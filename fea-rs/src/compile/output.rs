@@ -35,6 +35,8 @@ pub struct Compilation {
     pub vhea: Option<wtables::vhea::Vhea>,
     /// The `OS/2` table, if one was generated
     pub os2: Option<wtables::os2::Os2>,
+    /// The `MVAR` table, if any metrics in `hhea`, `vhea` or `OS/2` were variable
+    pub mvar: Option<wtables::mvar::Mvar>,
     /// The `GDEF` table, if one was generated
     pub gdef: Option<wtables::gdef::Gdef>,
     /// The `BASE` table, if one was generated
@@ -61,6 +63,7 @@ impl Compilation {
             || self.hhea.is_some()
             || self.vhea.is_some()
             || self.os2.is_some()
+            || self.mvar.is_some()
             || self.base.is_some()
             || self.name.is_some()
             || self.stat.is_some()
@@ -161,6 +164,7 @@ impl Compilation {
         add_if_some!(self.hhea);
         add_if_some!(self.vhea);
         add_if_some!(self.os2);
+        add_if_some!(self.mvar);
         add_if_some!(self.gdef);
         add_if_some!(self.base);
         add_if_some!(self.name);
@@ -22,12 +22,14 @@ use crate::common::GlyphId16;
 
 mod base;
 mod gdef;
+mod mvar;
 mod name;
 mod os2;
 mod stat;
 
 pub(crate) use base::{BaseBuilder, ScriptRecord};
 pub(crate) use gdef::{GdefBuilder, GlyphClassDefExt};
+pub(crate) use mvar::MvarBuilder;
 pub(crate) use name::{NameBuilder, NameSpec};
 pub(crate) use os2::{CodePageRange, Os2Builder};
 pub(crate) use stat::{AxisLocation, AxisRecord, AxisValue, StatBuilder, StatFallbackName};
@@ -44,6 +46,7 @@ pub(crate) struct Tables {
     pub base: Option<BaseBuilder>,
     pub os2: Option<Os2Builder>,
     pub stat: Option<StatBuilder>,
+    pub mvar: MvarBuilder,
 }
 
 #[derive(Clone, Debug, Default)]
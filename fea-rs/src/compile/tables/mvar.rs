@@ -0,0 +1,58 @@
+//! Building the MVAR table
+
+use std::collections::BTreeMap;
+
+use write_fonts::{
+    tables::{
+        mvar::{Mvar, ValueRecord},
+        variations::{ivs_builder::VariationStoreBuilder, VariationRegion},
+    },
+    types::{MajorMinor, Tag},
+};
+
+/// Deltas for variable metrics in the hhea, vhea & OS/2 tables
+#[derive(Clone, Debug, Default)]
+pub(crate) struct MvarBuilder {
+    deltas: BTreeMap<Tag, Vec<(VariationRegion, i16)>>,
+}
+
+impl MvarBuilder {
+    /// Add the deltas for a metric.
+    ///
+    /// hhea and OS/2 metrics share some tags, and may vary differently; as in
+    /// fontTools the last deltas added for a tag win.
+    pub(crate) fn add(&mut self, tag: Tag, deltas: Vec<(VariationRegion, i16)>) {
+        // don't encode no-op deltas
+        if deltas.iter().all(|(_, delta)| *delta == 0) {
+            self.deltas.remove(&tag);
+        } else {
+            self.deltas.insert(tag, deltas);
+        }
+    }
+
+    pub(crate) fn build(&self, axis_count: u16) -> Option<Mvar> {
+        if self.deltas.is_empty() {
+            return None;
+        }
+        let mut builder = VariationStoreBuilder::new(axis_count);
+        let delta_ids = self
+            .deltas
+            .iter()
+            .map(|(tag, deltas)| (*tag, builder.add_deltas(deltas.clone())))
+            .collect::<Vec<_>>();
+        let (varstore, index_map) = builder.build();
+
+        let records = delta_ids
+            .into_iter()
+            .map(|(tag, temp_id)| {
+                let varidx = index_map.get(temp_id).unwrap();
+                ValueRecord::new(
+                    tag,
+                    varidx.delta_set_outer_index,
+                    varidx.delta_set_inner_index,
+                )
+            })
+            .collect();
+        Some(Mvar::new(MajorMinor::VERSION_1_0, Some(varstore), records))
+    }
+}
@@ -307,34 +307,17 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
     }
 
     fn validate_hhea(&mut self, node: &typed::HheaTable) {
-        self.ensure_metrics_are_non_variable(node.metrics());
         for metric in node.metrics() {
             self.validate_metric(&metric.metric());
         }
     }
 
     fn validate_vhea(&mut self, node: &typed::VheaTable) {
-        self.ensure_metrics_are_non_variable(node.metrics());
         for metric in node.metrics() {
             self.validate_metric(&metric.metric());
         }
     }
 
-    //TODO: remove me when we are compiling this
-    fn ensure_metrics_are_non_variable(
-        &mut self,
-        metrics: impl Iterator<Item = typed::MetricRecord>,
-    ) {
-        for record in metrics {
-            if record.metric().parse_simple().is_none() {
-                self.error(
-                    record.metric().range(),
-                    "variable metrics not yet supported",
-                );
-            }
-        }
-    }
-
     fn validate_vmtx(&mut self, node: &typed::VmtxTable) {
         for statement in node.statements() {
             self.validate_glyph(&statement.glyph());
@@ -396,17 +379,12 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
                     };
                 }
                 typed::Os2TableItem::Metric(i) => {
-                    if matches!(i.keyword().kind, Kind::WinAscentKw | Kind::WinDescentKw) {
-                        let val = i.metric();
-                        match val.parse_simple() {
-                            None => {
-                                self.error(val.range(), "variable metrics not yet supports in OS/2")
-                            }
-                            Some(x) if x.is_negative() => {
-                                self.error(val.range(), "expected positive number")
-                            }
-                            Some(_) => (),
-                        }
+                    let val = i.metric();
+                    self.validate_metric(&val);
+                    if matches!(i.keyword().kind, Kind::WinAscentKw | Kind::WinDescentKw)
+                        && val.parse_simple().is_some_and(i16::is_negative)
+                    {
+                        self.error(val.range(), "expected positive number")
                     }
                 }
                 typed::Os2TableItem::Number(item) => {
@@ -1068,8 +1046,19 @@ impl<'a, V: VariationInfo> ValidationCtx<'a, V> {
                         let target = item.target();
                         self.validate_glyph_or_class(&target);
                         input_class = item.target().is_class();
-                        if let Some(lookup) = item.lookups().next() {
-                            self.error(lookup.range(), "explicit lookups in rsub rules are not supported, although they should be. Please file an issue at https://github.com/cmyr/fea-rs/issues");
+                        for (j, lookup) in item.lookups().enumerate() {
+                            if rule.inline_rule().is_some() {
+                                self.error(
+                                    lookup.range(),
+                                    "named lookup not allowed in statement that includes inline rule",
+                                );
+                            } else if j > 0 {
+                                self.error(
+                                    lookup.range(),
+                                    "rsub rules can reference only one lookup",
+                                );
+                            }
+                            self.validate_lookup_ref(&lookup);
                         }
                     }
                 }
@@ -106,6 +106,29 @@ pub fn parse_string(text: impl Into<Arc<str>>) -> (ParseTree, DiagnosticSet) {
     .unwrap()
 }
 
+/// Parse a single block of FEA from memory, without resolving includes.
+///
+/// Unlike [`parse_string`], any include statements are left in the tree as-is;
+/// this is useful for tools (like the formatter) that only care about the text
+/// of a single file.
+pub(crate) fn parse_single_source(text: impl Into<Arc<str>>) -> (crate::Node, DiagnosticSet) {
+    const SRC_NAME: &str = "parse::parse_single_source";
+    let text = text.into();
+    let mut sources = source::SourceLoader::new(Box::new(move |_: &Path| {
+        Ok::<_, SourceLoadError>(text.clone())
+    }));
+    let id = sources
+        .source_for_path(Path::new(SRC_NAME), None)
+        .expect("resolver is infallible");
+    let (node, messages, _) = context::parse_src(sources.get(&id).unwrap(), None);
+    let diagnostics = DiagnosticSet {
+        messages,
+        sources: sources.into_inner(),
+        max_to_print: usize::MAX,
+    };
+    (node, diagnostics)
+}
+
 /// Parse an arbitrary block of FEA text with a specific parsing function.
 ///
 /// This can be used to parse any part of the grammar, including elements that
@@ -178,6 +178,16 @@ fn parse_rsub(parser: &mut Parser, recovery: TokenSet) -> AstKind {
         return AstKind::GsubNode;
     }
 
+    // the marked glyph may reference a single substitution lookup
+    while parser.eat(Kind::LookupKw) {
+        if !parser.eat(Kind::Ident) {
+            parser.err("expected named lookup");
+            parser.eat_until(recovery);
+            parser.expect_semi();
+            return AstKind::GsubNode;
+        }
+    }
+
     super::greedy(glyph::eat_glyph_or_glyph_class)(parser, recovery);
 
     if parser.matches(0, Kind::SingleQuote) {
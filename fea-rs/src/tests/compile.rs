@@ -1,13 +1,35 @@
 //! tests of the full compiler, including expected successes and failures
 
-use std::path::{Path, PathBuf};
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use crate::{
-    compile::{error::CompilerError, Compiler, MockVariationInfo, NopFeatureProvider, Opts},
-    util::ttx::{self as test_utils, Filter, Report, TestCase, TestResult},
+    compile::{
+        error::CompilerError, Compiler, FeatureBuilder, FeatureProvider, MockVariationInfo,
+        NopFeatureProvider, NopVariationInfo, Opts, PairPosBuilder, PendingLookup,
+        SingleSubBuilder, ValueRecord,
+    },
+    parse::SourceLoadError,
+    util::{
+        format::format_fea,
+        ttx::{self as test_utils, Filter, Report, TestCase, TestFeatureProvider, TestResult},
+    },
     GlyphMap,
 };
 use fontdrasil::types::GlyphName;
+use write_fonts::{
+    read::{
+        tables::gpos::{Gpos, PairPos, PositionSubtables},
+        FontData, FontRead,
+    },
+    tables::layout::{
+        Condition, ConditionFormat1, ConditionSet, FeatureList, FeatureVariations, LookupFlag,
+    },
+    types::{F2Dot14, GlyphId16, Tag},
+};
 
 static ROOT_TEST_DIR: &str = "./test-data/compile-tests";
 static GOOD_DIR: &str = "good";
@@ -64,6 +86,409 @@ fn should_pass() -> Result<(), Report> {
     test_utils::finalize_results(results).into_error()
 }
 
+/// Compile `fea` against a font with these glyphs (after .notdef) and write
+/// its GPOS table.
+fn compile_gpos(glyphs: &[String], fea: String) -> Vec<u8> {
+    let glyph_map: GlyphMap = std::iter::once(".notdef")
+        .chain(glyphs.iter().map(String::as_str))
+        .map(GlyphName::new)
+        .collect();
+    let (tree, _) = crate::parse::parse_string(fea);
+    let compilation = match crate::compile::compile::<NopVariationInfo, NopFeatureProvider>(
+        &tree,
+        &glyph_map,
+        None,
+        None,
+        Opts::new(),
+    ) {
+        Ok((compilation, _)) => compilation,
+        Err(errs) => panic!("{}", errs.to_string(false)),
+    };
+    write_fonts::dump_table(compilation.gpos.as_ref().unwrap()).unwrap()
+}
+
+// a single PairPosFormat1 subtable this big can't be addressed with 16-bit
+// offsets, so it has to be split up when the table is written.
+#[test]
+fn resolve_pair_pos_offset_overflow() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    const N_FIRST: usize = 300;
+    const N_SECOND: usize = 60;
+    let firsts = (0..N_FIRST).map(|i| format!("f{i}")).collect::<Vec<_>>();
+    let seconds = (0..N_SECOND).map(|i| format!("s{i}")).collect::<Vec<_>>();
+
+    let mut fea = String::from("feature kern {\n");
+    for (i, first) in firsts.iter().enumerate() {
+        for (j, second) in seconds.iter().enumerate() {
+            // distinct values, so the pair sets can't be shared
+            writeln!(fea, "    pos {first} {second} {};", i + j + 1).unwrap();
+        }
+    }
+    fea.push_str("} kern;\n");
+
+    let glyphs = firsts.into_iter().chain(seconds).collect::<Vec<_>>();
+    let gpos = compile_gpos(&glyphs, fea);
+    assert!(gpos.len() > u16::MAX as usize);
+
+    // every offset must resolve, and lead to the pairs we asked for
+    let gpos = Gpos::read(FontData::new(&gpos)).unwrap();
+    let lookups = gpos.lookup_list().unwrap();
+    assert_eq!(lookups.lookup_count(), 1);
+    let PositionSubtables::Pair(subtables) = lookups.lookups().get(0).unwrap().subtables().unwrap()
+    else {
+        panic!("expected a pair pos lookup");
+    };
+    assert!(subtables.len() > 1, "subtable was not split");
+    let mut n_pairs = 0;
+    for subtable in subtables.iter() {
+        let PairPos::Format1(subtable) = subtable.unwrap() else {
+            panic!("expected glyph pairs");
+        };
+        let coverage = subtable.coverage().unwrap();
+        for (first, pair_set) in coverage.iter().zip(subtable.pair_sets().iter()) {
+            for record in pair_set.unwrap().pair_value_records().iter() {
+                let record = record.unwrap();
+                let (i, j) = (
+                    first.to_u16() as usize - 1,
+                    record.second_glyph().to_u16() as usize - N_FIRST - 1,
+                );
+                assert_eq!(record.value_record1().x_advance(), Some((i + j + 1) as i16));
+                n_pairs += 1;
+            }
+        }
+    }
+    assert_eq!(n_pairs, N_FIRST * N_SECOND);
+}
+
+// with a distinct anchor for every base and mark class, the base array of a
+// single MarkBasePosFormat1 subtable can't reach all of its anchors, so the
+// subtable has to be split up by mark class when the table is written.
+#[test]
+fn resolve_mark_base_offset_overflow() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    const N_BASES: usize = 1000;
+    const N_CLASSES: usize = 20;
+    let bases = (0..N_BASES).map(|i| format!("b{i}")).collect::<Vec<_>>();
+    let marks = (0..N_CLASSES).map(|i| format!("m{i}")).collect::<Vec<_>>();
+
+    let mut fea = String::new();
+    for (class, mark) in marks.iter().enumerate() {
+        // the mark anchor tells us which class a subtable's class index is
+        writeln!(fea, "markClass {mark} <anchor 0 {class}> @MC{class};").unwrap();
+    }
+    fea.push_str("feature mark {\n");
+    for (i, base) in bases.iter().enumerate() {
+        write!(fea, "    pos base {base}").unwrap();
+        for class in 0..N_CLASSES {
+            write!(fea, " <anchor {i} {class}> mark @MC{class}").unwrap();
+        }
+        fea.push_str(";\n");
+    }
+    fea.push_str("} mark;\n");
+
+    let glyphs = bases.into_iter().chain(marks).collect::<Vec<_>>();
+    let gpos = compile_gpos(&glyphs, fea);
+    assert!(gpos.len() > u16::MAX as usize);
+
+    let gpos = Gpos::read(FontData::new(&gpos)).unwrap();
+    let lookups = gpos.lookup_list().unwrap();
+    assert_eq!(lookups.lookup_count(), 1);
+    let PositionSubtables::MarkToBase(subtables) =
+        lookups.lookups().get(0).unwrap().subtables().unwrap()
+    else {
+        panic!("expected a mark to base lookup");
+    };
+    assert!(subtables.len() > 1, "subtable was not split");
+    let mut n_anchors = 0;
+    for subtable in subtables.iter() {
+        let subtable = subtable.unwrap();
+        let mark_array = subtable.mark_array().unwrap();
+        let mut classes = vec![0; subtable.mark_class_count() as usize];
+        for record in mark_array.mark_records() {
+            let anchor = record.mark_anchor(mark_array.offset_data()).unwrap();
+            classes[record.mark_class() as usize] = anchor.y_coordinate();
+        }
+        let base_coverage = subtable.base_coverage().unwrap();
+        let base_array = subtable.base_array().unwrap();
+        for (base, record) in base_coverage.iter().zip(base_array.base_records().iter()) {
+            let anchors = record.unwrap().base_anchors(base_array.offset_data());
+            for (anchor, class) in anchors.iter().zip(&classes) {
+                let anchor = anchor.unwrap().unwrap();
+                assert_eq!(
+                    (anchor.x_coordinate(), anchor.y_coordinate()),
+                    (base.to_u16() as i16 - 1, *class)
+                );
+                n_anchors += 1;
+            }
+        }
+    }
+    assert_eq!(n_anchors, N_BASES * N_CLASSES);
+}
+
+// lookups whose subtables are together too big to follow the lookup list
+// have to be promoted to extension lookups when the table is written.
+#[test]
+fn resolve_offset_overflow_with_extension() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    const N_LOOKUPS: usize = 4;
+    const N_FIRST: usize = 100;
+    const N_SECOND: usize = 60;
+    let firsts = (0..N_FIRST).map(|i| format!("f{i}")).collect::<Vec<_>>();
+    let seconds = (0..N_SECOND).map(|i| format!("s{i}")).collect::<Vec<_>>();
+
+    let mut fea = String::from("feature kern {\n");
+    for lookup in 0..N_LOOKUPS {
+        writeln!(fea, "    lookup kern{lookup} {{").unwrap();
+        for (i, first) in firsts.iter().enumerate() {
+            for (j, second) in seconds.iter().enumerate() {
+                let value = lookup * N_FIRST * N_SECOND + i * N_SECOND + j + 1;
+                writeln!(fea, "        pos {first} {second} {value};").unwrap();
+            }
+        }
+        writeln!(fea, "    }} kern{lookup};").unwrap();
+    }
+    fea.push_str("} kern;\n");
+
+    let glyphs = firsts.into_iter().chain(seconds).collect::<Vec<_>>();
+    let gpos = compile_gpos(&glyphs, fea);
+    assert!(gpos.len() > u16::MAX as usize);
+
+    let gpos = Gpos::read(FontData::new(&gpos)).unwrap();
+    let lookups = gpos.lookup_list().unwrap();
+    assert_eq!(lookups.lookup_count() as usize, N_LOOKUPS);
+    let mut n_extensions = 0;
+    for (lookup_idx, lookup) in lookups.lookups().iter().enumerate() {
+        let lookup = lookup.unwrap();
+        n_extensions += (lookup.lookup_type() == 9) as usize;
+        let PositionSubtables::Pair(subtables) = lookup.subtables().unwrap() else {
+            panic!("expected a pair pos lookup");
+        };
+        let mut n_pairs = 0;
+        for subtable in subtables.iter() {
+            let PairPos::Format1(subtable) = subtable.unwrap() else {
+                panic!("expected glyph pairs");
+            };
+            let coverage = subtable.coverage().unwrap();
+            for (first, pair_set) in coverage.iter().zip(subtable.pair_sets().iter()) {
+                for record in pair_set.unwrap().pair_value_records().iter() {
+                    let record = record.unwrap();
+                    let (i, j) = (
+                        first.to_u16() as usize - 1,
+                        record.second_glyph().to_u16() as usize - N_FIRST - 1,
+                    );
+                    let value = lookup_idx * N_FIRST * N_SECOND + i * N_SECOND + j + 1;
+                    assert_eq!(record.value_record1().x_advance(), Some(value as i16));
+                    n_pairs += 1;
+                }
+            }
+        }
+        assert_eq!(n_pairs, N_FIRST * N_SECOND);
+    }
+    assert!(n_extensions > 0, "no lookup was promoted to an extension");
+}
+
+/// Adds conditional lookups to both GSUB and GPOS, for when the font is wide.
+struct ConditionalFeatureProvider;
+
+impl FeatureProvider for ConditionalFeatureProvider {
+    fn add_features(&self, builder: &mut FeatureBuilder) {
+        let wide = ConditionSet::new(vec![ConditionFormat1::new(
+            1,
+            F2Dot14::from_f32(0.5),
+            F2Dot14::from_f32(1.0),
+        )
+        .into()]);
+        let mut sub = SingleSubBuilder::default();
+        sub.insert(GlyphId16::new(2), GlyphId16::new(1));
+        let rvrn =
+            builder.add_gsub_lookup(PendingLookup::new(vec![sub], LookupFlag::empty(), None));
+        let mut kern = PairPosBuilder::default();
+        kern.insert_pair(
+            GlyphId16::new(1),
+            ValueRecord::new().with_x_advance(-10),
+            GlyphId16::new(2),
+            ValueRecord::new(),
+        );
+        let kern = builder.add_lookup(PendingLookup::new(vec![kern], LookupFlag::empty(), None));
+        for langsys in builder.language_systems().collect::<Vec<_>>() {
+            builder.add_feature_variation(
+                langsys.to_feature_key(Tag::new(b"rvrn")),
+                wide.clone(),
+                vec![rvrn],
+            );
+            builder.add_feature_variation(
+                langsys.to_feature_key(Tag::new(b"kern")),
+                wide.clone(),
+                vec![kern],
+            );
+        }
+    }
+}
+
+type VariationSummary = Vec<(Vec<(u16, f32, f32)>, Vec<(Tag, Vec<u16>)>)>;
+
+/// The conditions of each FeatureVariations record, and the lookups of the
+/// features it substitutes.
+fn summarize_feature_variations(
+    features: &FeatureList,
+    variations: &FeatureVariations,
+) -> VariationSummary {
+    variations
+        .feature_variation_records
+        .iter()
+        .map(|record| {
+            let conditions = record
+                .condition_set
+                .as_ref()
+                .unwrap()
+                .conditions
+                .iter()
+                .map(|condition| match &**condition {
+                    Condition::Format1AxisRange(range) => (
+                        range.axis_index,
+                        range.filter_range_min_value.to_f32(),
+                        range.filter_range_max_value.to_f32(),
+                    ),
+                    other => panic!("unexpected condition {other:?}"),
+                })
+                .collect();
+            let substitutions = record
+                .feature_table_substitution
+                .as_ref()
+                .unwrap()
+                .substitutions
+                .iter()
+                .map(|sub| {
+                    (
+                        features.feature_records[sub.feature_index as usize].feature_tag,
+                        sub.alternate_feature.lookup_list_indices.clone(),
+                    )
+                })
+                .collect();
+            (conditions, substitutions)
+        })
+        .collect()
+}
+
+// feature variations from a feature writer are merged with those in the FEA
+#[test]
+fn feature_writer_variations_in_compiled_tables() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let glyph_map: GlyphMap = [".notdef", "a", "b"]
+        .into_iter()
+        .map(GlyphName::new)
+        .collect();
+    let var_info = test_utils::make_var_info();
+    let fea = "\
+        languagesystem DFLT dflt;
+        conditionset heavy {
+            wght 600 1000;
+        } heavy;
+        variation rvrn heavy {
+            sub a by b;
+        } rvrn;
+    ";
+    let (tree, _) = crate::parse::parse_string(fea);
+    let compilation = match crate::compile::compile(
+        &tree,
+        &glyph_map,
+        Some(&var_info),
+        Some(&ConditionalFeatureProvider),
+        Opts::new(),
+    ) {
+        Ok((compilation, _)) => compilation,
+        Err(errs) => panic!("{}", errs.to_string(false)),
+    };
+
+    let rvrn = Tag::new(b"rvrn");
+    let kern = Tag::new(b"kern");
+    let gsub = compilation.gsub.unwrap();
+    // the writer's lookup follows the one from the FEA
+    assert_eq!(gsub.lookup_list.lookups.len(), 2);
+    assert_eq!(
+        summarize_feature_variations(
+            &gsub.feature_list,
+            gsub.feature_variations.as_ref().unwrap()
+        ),
+        vec![
+            (vec![(0, 0.5, 1.0)], vec![(rvrn, vec![0])]),
+            (vec![(1, 0.5, 1.0)], vec![(rvrn, vec![1])]),
+        ]
+    );
+    let gpos = compilation.gpos.unwrap();
+    assert_eq!(
+        summarize_feature_variations(
+            &gpos.feature_list,
+            gpos.feature_variations.as_ref().unwrap()
+        ),
+        vec![(vec![(1, 0.5, 1.0)], vec![(kern, vec![0])])]
+    );
+}
+
+// formatting must be stable, and must not change what a file compiles to
+#[test]
+fn formatting_preserves_compilation() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    for (glyph_map, var_info, tests) in iter_test_groups(GOOD_DIR) {
+        for path in tests {
+            let text = std::fs::read_to_string(&path).unwrap();
+            let formatted = match format_fea(&text) {
+                Ok(formatted) => formatted,
+                Err(errs) => panic!("failed to format {}: {}", path.display(), errs.display()),
+            };
+            assert_eq!(
+                format_fea(&formatted).unwrap(),
+                formatted,
+                "formatting {} is not idempotent",
+                path.display()
+            );
+            let before = compile_in_memory(&path, text, &glyph_map, &var_info);
+            let after = compile_in_memory(&path, formatted, &glyph_map, &var_info);
+            assert!(
+                before == after,
+                "formatting changed the compiled output of {}",
+                path.display()
+            );
+        }
+    }
+}
+
+/// Compile `text` as if it were the contents of the file at `path`.
+fn compile_in_memory(
+    path: &Path,
+    text: String,
+    glyph_map: &GlyphMap,
+    var_info: &MockVariationInfo,
+) -> Vec<u8> {
+    let root = path.to_path_buf();
+    let dir = path.parent().unwrap().to_path_buf();
+    let text: Arc<str> = text.into();
+    let resolver = move |path: &Path| -> Result<Arc<str>, SourceLoadError> {
+        if path == root.as_path() {
+            return Ok(text.clone());
+        }
+        std::fs::read_to_string(dir.join(path))
+            .map(Into::into)
+            .map_err(|cause| SourceLoadError::new(path.into(), cause))
+    };
+    let (tree, errs) =
+        crate::parse::parse_root(path.to_path_buf(), Some(glyph_map), Box::new(resolver)).unwrap();
+    if errs.has_errors() {
+        panic!("{}", errs.display());
+    }
+    let var_info = test_utils::is_variable(path).then_some(var_info);
+    let feature_writer = test_utils::needs_feature_provider(path).then_some(&TestFeatureProvider);
+    let (compilation, _) = crate::compile::compile(
+        &tree,
+        glyph_map,
+        var_info,
+        feature_writer,
+        Opts::new().make_post_table(true),
+    )
+    .unwrap_or_else(|errs| panic!("{}", errs.display()));
+    compilation.to_binary(glyph_map).unwrap()
+}
+
 fn iter_test_groups(
     test_dir: &str,
 ) -> impl Iterator<Item = (GlyphMap, MockVariationInfo, Vec<PathBuf>)> + '_ {
@@ -1,5 +1,6 @@
 //! helpers and utilties (mostly for testing/debugging?)
 
+pub mod format;
 pub(crate) mod highlighting;
 pub mod paths;
 #[cfg(any(test, feature = "diff"))]
@@ -0,0 +1,339 @@
+//! Formatting FEA source.
+//!
+//! The formatter works directly on the token tree, which is lossless: every
+//! token (including comments) is written back out, and only the whitespace
+//! between tokens is changed. Whitespace is never inserted between two tokens
+//! that were adjacent in the input unless at least one of them is punctuation,
+//! so formatting can never change how a file is tokenized.
+
+use crate::{parse, DiagnosticSet, Kind, Node, NodeOrToken, Token};
+
+/// The indentation used for each level of nesting.
+const INDENT: usize = 4;
+/// The maximum line length we try to respect when wrapping glyph classes.
+const MAX_LINE_LEN: usize = 100;
+
+/// Format a block of FEA source.
+///
+/// The input is parsed on its own; include statements are not resolved, and
+/// are left untouched.
+///
+/// Returns the parse diagnostics if the input contains any errors; we don't
+/// attempt to format input that we can't parse.
+pub fn format_fea(text: &str) -> Result<String, DiagnosticSet> {
+    let (root, diagnostics) = parse::parse_single_source(text);
+    if diagnostics.has_errors() {
+        return Err(diagnostics);
+    }
+    Ok(format_node(&root))
+}
+
+/// Format a node in the token tree.
+///
+/// This is the workhorse for [`format_fea`]; the node is generally the root of
+/// a parsed file.
+pub fn format_node(root: &Node) -> String {
+    let mut formatter = Formatter::default();
+    formatter.visit(root);
+    formatter.finish()
+}
+
+#[derive(Default)]
+struct Formatter {
+    out: String,
+    /// The current column in the output.
+    col: usize,
+    /// The current block nesting depth.
+    depth: usize,
+    /// For each open glyph class, the column of its first member.
+    brackets: Vec<usize>,
+    /// The kind of the last token or comment that was written.
+    prev: Option<Kind>,
+    /// The number of newlines in the input since the last token or comment.
+    newlines: usize,
+    /// Whether there was any whitespace in the input since the last token.
+    saw_whitespace: bool,
+    /// Whether the next token should start a new line.
+    line_break: bool,
+    /// If we're in a glyph class definition, the padding used to align its `=`.
+    eq_padding: Option<usize>,
+}
+
+impl Formatter {
+    fn visit(&mut self, node: &Node) {
+        let padding = class_def_padding(node);
+        for (child, padding) in node.iter_children().zip(padding) {
+            match child {
+                NodeOrToken::Token(token) => self.token(token),
+                NodeOrToken::Node(node) if node.kind() == Kind::GlyphClassDefNode => {
+                    let prev = self.eq_padding.replace(padding);
+                    self.visit(node);
+                    self.eq_padding = prev;
+                }
+                NodeOrToken::Node(node) => self.visit(node),
+            }
+        }
+    }
+
+    fn token(&mut self, token: &Token) {
+        match token.kind {
+            Kind::Whitespace => {
+                self.newlines += token.text.matches('\n').count();
+                self.saw_whitespace = true;
+                return;
+            }
+            Kind::Comment => {
+                self.comment(token.as_str());
+                return;
+            }
+            Kind::RBrace => self.depth = self.depth.saturating_sub(1),
+            _ => (),
+        }
+
+        let text = token.as_str();
+        if self.line_break || token.kind == Kind::RBrace {
+            let blank_line =
+                self.newlines > 1 && self.prev != Some(Kind::LBrace) && token.kind != Kind::RBrace;
+            self.newline(blank_line);
+        } else if self.prev.is_some() {
+            let spaces = self.spaces_before(token.kind);
+            // only glyph classes are wrapped; other long lines are left alone.
+            if spaces > 0
+                && !self.brackets.is_empty()
+                && self.col + spaces + text.chars().count() > MAX_LINE_LEN
+            {
+                self.newline(false);
+            } else {
+                self.write_spaces(spaces);
+            }
+        }
+        self.write(text);
+
+        match token.kind {
+            Kind::LBrace => self.depth += 1,
+            Kind::LSquare => self.brackets.push(self.col),
+            Kind::RSquare => {
+                self.brackets.pop();
+            }
+            _ => (),
+        }
+        self.line_break = matches!(token.kind, Kind::Semi | Kind::LBrace);
+        self.prev = Some(token.kind);
+        self.newlines = 0;
+        self.saw_whitespace = false;
+    }
+
+    /// Comments stay at the end of the line they were on, or on their own line.
+    fn comment(&mut self, text: &str) {
+        if self.prev.is_some() {
+            if self.newlines == 0 {
+                self.write_spaces(1);
+            } else {
+                let blank_line = self.newlines > 1 && self.prev != Some(Kind::LBrace);
+                self.newline(blank_line);
+            }
+        }
+        self.write(text.trim_end());
+        self.line_break = true;
+        self.prev = Some(Kind::Comment);
+        self.newlines = 0;
+        self.saw_whitespace = false;
+    }
+
+    /// The number of spaces to write between the previous token and this one.
+    fn spaces_before(&self, kind: Kind) -> usize {
+        let prev = self.prev.unwrap_or(Kind::Whitespace);
+        match (prev, kind, self.eq_padding) {
+            // these are unambiguous, so we can always add space around them
+            (Kind::RBrace, Kind::Semi, _) => 0,
+            (_, Kind::LBrace, _) | (Kind::RBrace, _, _) => 1,
+            (_, Kind::Eq, Some(padding)) => 1 + padding,
+            (Kind::Eq, _, Some(_)) => 1,
+            // never add space between tokens that were adjacent
+            _ if !self.saw_whitespace => 0,
+            // keep empty items in a list, like in the GDEF GlyphClassDef statement
+            (Kind::Comma, Kind::Comma | Kind::Semi, _) => 1,
+            (Kind::LSquare | Kind::LParen, _, _)
+            | (_, Kind::RSquare | Kind::RParen | Kind::Semi | Kind::Comma, _) => 0,
+            _ => 1,
+        }
+    }
+
+    fn newline(&mut self, blank_line: bool) {
+        let trimmed_len = self.out.trim_end_matches(' ').len();
+        self.out.truncate(trimmed_len);
+        self.out.push('\n');
+        if blank_line {
+            self.out.push('\n');
+        }
+        self.col = 0;
+        // continuation lines in a glyph class line up with its first member
+        let indent = match self.brackets.last() {
+            Some(col) => *col,
+            None => self.depth * INDENT,
+        };
+        self.write_spaces(indent);
+    }
+
+    fn write_spaces(&mut self, n: usize) {
+        self.out.push_str(&" ".repeat(n));
+        self.col += n;
+    }
+
+    fn write(&mut self, text: &str) {
+        self.out.push_str(text);
+        self.col = match text.rfind('\n') {
+            Some(idx) => text[idx + 1..].chars().count(),
+            None => self.col + text.chars().count(),
+        };
+    }
+
+    fn finish(mut self) -> String {
+        let trimmed_len = self.out.trim_end().len();
+        self.out.truncate(trimmed_len);
+        if !self.out.is_empty() {
+            self.out.push('\n');
+        }
+        self.out
+    }
+}
+
+/// For each child of this node, the padding needed to align the `=` of runs of
+/// consecutive glyph class definitions.
+///
+/// A run is broken by a blank line, a comment, or any other statement.
+fn class_def_padding(node: &Node) -> Vec<usize> {
+    fn finish_run(run: &mut Vec<(usize, usize)>, padding: &mut [usize]) {
+        let max_len = run.iter().map(|(_, len)| *len).max().unwrap_or_default();
+        for (idx, len) in run.drain(..) {
+            padding[idx] = max_len - len;
+        }
+    }
+
+    let mut padding = Vec::new();
+    let mut run = Vec::new();
+    for (i, child) in node.iter_children().enumerate() {
+        padding.push(0);
+        let name_len = match child {
+            NodeOrToken::Node(node) if node.kind() == Kind::GlyphClassDefNode => {
+                class_def_name_len(node)
+            }
+            NodeOrToken::Token(token)
+                if token.kind == Kind::Whitespace && token.text.matches('\n').count() < 2 =>
+            {
+                continue
+            }
+            _ => None,
+        };
+        match name_len {
+            Some(len) => run.push((i, len)),
+            None => finish_run(&mut run, &mut padding),
+        }
+    }
+    finish_run(&mut run, &mut padding);
+    padding
+}
+
+/// The length of the class name, if this definition can be aligned with its neighbours.
+fn class_def_name_len(node: &Node) -> Option<usize> {
+    node.iter_tokens()
+        .find(|token| token.kind != Kind::Whitespace)
+        .filter(|token| token.kind == Kind::NamedGlyphClass)
+        .map(|token| token.text.chars().count())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(text: &str) -> String {
+        match format_fea(text) {
+            Ok(formatted) => formatted,
+            Err(errs) => panic!("{}", errs.display()),
+        }
+    }
+
+    #[test]
+    fn indent_blocks() {
+        let fea = "languagesystem DFLT dflt;\nfeature liga{lookup foo{sub f i by f_i;}foo;\n\n\n  sub f l by f_l ;} liga;";
+        let expected = "\
+languagesystem DFLT dflt;
+feature liga {
+    lookup foo {
+        sub f i by f_i;
+    } foo;
+
+    sub f l by f_l;
+} liga;
+";
+        assert_eq!(format(fea), expected);
+    }
+
+    #[test]
+    fn preserve_comments() {
+        let fea = "# leading\nfeature kern { # trailing\n pos a b -10; # after\n\n    # own line\npos\tc d 5;\n} kern;";
+        let expected = "\
+# leading
+feature kern { # trailing
+    pos a b -10; # after
+
+    # own line
+    pos c d 5;
+} kern;
+";
+        assert_eq!(format(fea), expected);
+    }
+
+    #[test]
+    fn align_class_defs() {
+        let fea = "@a=[x y];\n@longer = [ z ];\n\n@other = @a;";
+        let expected = "\
+@a      = [x y];
+@longer = [z];
+
+@other = @a;
+";
+        assert_eq!(format(fea), expected);
+    }
+
+    #[test]
+    fn wrap_long_classes() {
+        let glyphs = (0..40).map(|i| format!("glyph{i}")).collect::<Vec<_>>();
+        let fea = format!("@glyphs = [{}];", glyphs.join(" "));
+        let formatted = format(&fea);
+        let lines = formatted.lines().collect::<Vec<_>>();
+        assert!(lines.len() > 1);
+        // the closing bracket and semicolon are never wrapped
+        let (last, wrapped) = lines.split_last().unwrap();
+        assert!(wrapped.iter().all(|line| line.len() <= MAX_LINE_LEN));
+        assert!(last.ends_with("];"));
+        let first_glyph = lines[0].find("glyph0").unwrap();
+        assert!(lines[1..]
+            .iter()
+            .all(|line| line.len() - line.trim_start().len() == first_glyph));
+        assert_eq!(format(&formatted), formatted);
+    }
+
+    #[test]
+    fn adjacent_tokens_stay_adjacent() {
+        let fea = "feature test {\n    sub [a-z]' b by c;\n    sub \\a by \\b;\n} test;\n";
+        assert_eq!(format(fea), fea);
+    }
+
+    #[test]
+    fn includes_are_preserved() {
+        let fea = "include(  features/kern.fea );\nfeature liga{ include (liga.fea); } liga;";
+        let expected = "\
+include(features/kern.fea);
+feature liga {
+    include (liga.fea);
+} liga;
+";
+        assert_eq!(format(fea), expected);
+    }
+
+    #[test]
+    fn refuse_bad_input() {
+        assert!(format_fea("feature liga { sub a by ; } liga;").is_err());
+    }
+}
@@ -569,7 +569,7 @@ pub(crate) fn make_var_info() -> MockVariationInfo {
     MockVariationInfo::new(&[("wght", 200, 200, 1000), ("wdth", 100, 100, 200)])
 }
 
-struct TestFeatureProvider;
+pub(crate) struct TestFeatureProvider;
 
 impl FeatureProvider for TestFeatureProvider {
     // we always add one lookup each for 'kern' and 'mark', which
@@ -1,16 +1,25 @@
 //! Generates a [avar](https://learn.microsoft.com/en-us/typography/opentype/spec/avar) table.
 
+use std::collections::HashMap;
+
 use fontdrasil::{
     coords::NormalizedCoord,
     orchestration::{Access, Work},
     types::Axis,
 };
-use fontir::orchestration::{Persistable, WorkId as FeWorkId};
+use fontir::{
+    ir::StaticMetadata,
+    orchestration::{Persistable, WorkId as FeWorkId},
+};
 use log::debug;
 use write_fonts::{
     read::FontRead,
-    tables::avar::{Avar, AxisValueMap, SegmentMaps},
+    tables::{
+        avar::{Avar, AxisValueMap, SegmentMaps},
+        variations::{ivs_builder::VariationStoreBuilder, DeltaSetIndexMap, ItemVariationStore},
+    },
     types::F2Dot14,
+    OtRound,
 };
 
 use crate::{
@@ -122,6 +131,56 @@ fn to_segment_map(axis: &Axis) -> SegmentMaps {
     SegmentMaps::new(mappings)
 }
 
+/// The axis index map and variation store of avar 2, if there are axis mappings.
+///
+/// Each axis gets a delta set that moves it, in F2Dot14 units, from the input
+/// location of each mapping to the output. Matches fontTools
+/// [_add_avar](https://github.com/fonttools/fonttools/blob/main/Lib/fontTools/varLib/__init__.py).
+fn axis_mapping_varstore(
+    static_metadata: &StaticMetadata,
+) -> Result<Option<(DeltaSetIndexMap, ItemVariationStore)>, Error> {
+    let Some((model, sources)) = static_metadata
+        .axis_mapping_sources()
+        .map_err(Error::AxisMappingModelError)?
+    else {
+        return Ok(None);
+    };
+    let axes = &static_metadata.axes;
+
+    let mut builder = VariationStoreBuilder::new(axes.len() as u16);
+    let mut delta_ids = Vec::with_capacity(axes.len());
+    for i in 0..axes.len() {
+        // values are rounded before computing deltas, as fontTools does
+        let axis_sources: HashMap<_, _> = sources
+            .iter()
+            .map(|(loc, movement)| {
+                let value: f64 = (movement[i] * 16384.0).ot_round();
+                (loc.clone(), vec![value])
+            })
+            .collect();
+        let deltas = model
+            .deltas(&axis_sources)
+            .map_err(Error::DeltaError)?
+            .into_iter()
+            .filter(|(region, _)| !region.is_default())
+            .map(|(region, values)| {
+                // deltas may span the full [-2, 2] range, which doesn't fit in an i16
+                let delta: f64 = values[0].ot_round();
+                (region.to_write_fonts_variation_region(axes), delta as i32)
+            })
+            .collect();
+        delta_ids.push(builder.add_deltas(deltas));
+    }
+    let (var_store, varidx_map) = builder.build();
+    // unwrap since VariationStoreBuilder guarantees that any temporary index returned by
+    // add_deltas will exist in the returned map
+    let axis_index_map = delta_ids
+        .into_iter()
+        .map(|id| varidx_map.get(id).unwrap())
+        .collect();
+    Ok(Some((axis_index_map, var_store)))
+}
+
 impl Work<Context, AnyWorkId, Error> for AvarWork {
     fn id(&self) -> AnyWorkId {
         WorkId::Avar.into()
@@ -141,29 +200,43 @@ impl Work<Context, AnyWorkId, Error> for AvarWork {
             debug!("Skip avar; this is not a variable font");
             return Ok(());
         }
-        let axis_segment_maps: Vec<_> = static_metadata.axes.iter().map(to_segment_map).collect();
-        // only when all the segment maps are uninteresting, we can omit avar
-        let avar = if axis_segment_maps.iter().any(|segmap| !segmap.is_identity()) {
-            PossiblyEmptyAvar::NonEmpty(Avar::new(axis_segment_maps))
-        } else {
-            PossiblyEmptyAvar::Empty
-        };
-        context.avar.set(avar);
+        context.avar.set(generate_avar(&static_metadata)?);
         Ok(())
     }
 }
 
+fn generate_avar(static_metadata: &StaticMetadata) -> Result<PossiblyEmptyAvar, Error> {
+    let axis_segment_maps: Vec<_> = static_metadata.axes.iter().map(to_segment_map).collect();
+    let avar = if let Some((axis_index_map, var_store)) = axis_mapping_varstore(static_metadata)? {
+        // version 2 is implied by having the axis index map and variation store
+        let mut avar = Avar::new(axis_segment_maps);
+        avar.axis_index_map = axis_index_map.into();
+        avar.var_store = var_store.into();
+        PossiblyEmptyAvar::NonEmpty(avar)
+    } else if axis_segment_maps.iter().any(|segmap| !segmap.is_identity()) {
+        // only when all the segment maps are uninteresting, we can omit avar
+        PossiblyEmptyAvar::NonEmpty(Avar::new(axis_segment_maps))
+    } else {
+        PossiblyEmptyAvar::Empty
+    };
+    Ok(avar)
+}
+
 #[cfg(test)]
 mod tests {
     use fontdrasil::{
-        coords::{CoordConverter, DesignCoord, UserCoord},
+        coords::{CoordConverter, DesignCoord, NormalizedCoord, UserCoord},
         types::Axis,
     };
+    use fontir::ir::{AxisMapping, StaticMetadata};
     use std::{cmp, str::FromStr};
-    use write_fonts::tables::avar::SegmentMaps;
-    use write_fonts::types::Tag;
+    use write_fonts::{
+        read::{tables::avar::Avar as ReadAvar, FontRead},
+        tables::avar::SegmentMaps,
+        types::{F2Dot14, MajorMinor, Tag},
+    };
 
-    use super::{default_segment_map, to_segment_map};
+    use super::{default_segment_map, generate_avar, to_segment_map, PossiblyEmptyAvar};
 
     fn axis(mappings: Vec<(UserCoord, DesignCoord)>, default_idx: usize) -> Axis {
         let default_idx = cmp::min(mappings.len() - 1, default_idx);
@@ -257,4 +330,56 @@ mod tests {
             dump(to_segment_map(&axis(mappings, 3)))
         );
     }
+
+    #[test]
+    fn axis_mappings_produce_avar2() {
+        let mut wght = crate::test_util::axis(100.0, 400.0, 900.0);
+        wght.tag = Tag::new(b"wght");
+        let mut xopq = crate::test_util::axis(0.0, 0.0, 100.0);
+        xopq.tag = Tag::new(b"XOPQ");
+        let mut static_metadata = StaticMetadata::new(
+            1000,
+            Default::default(),
+            vec![wght, xopq],
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            None,
+        )
+        .unwrap();
+        // at max weight, also move to max XOPQ
+        static_metadata.axis_mappings = vec![AxisMapping {
+            input: vec![(Tag::new(b"wght"), NormalizedCoord::new(1.0))].into(),
+            output: vec![
+                (Tag::new(b"wght"), NormalizedCoord::new(1.0)),
+                (Tag::new(b"XOPQ"), NormalizedCoord::new(1.0)),
+            ]
+            .into(),
+        }];
+
+        let PossiblyEmptyAvar::NonEmpty(avar) = generate_avar(&static_metadata).unwrap() else {
+            panic!("axis mappings should produce an avar");
+        };
+        let bytes = write_fonts::dump_table(&avar).unwrap();
+        let avar = ReadAvar::read(bytes.as_slice().into()).unwrap();
+        assert_eq!(MajorMinor::VERSION_2_0, avar.version());
+
+        let axis_index_map = avar.axis_index_map().unwrap().unwrap();
+        let var_store = avar.var_store().unwrap().unwrap();
+        let actual: Vec<_> = [0.0, 0.5, 1.0]
+            .into_iter()
+            .map(|wght| {
+                let coords = [F2Dot14::from_f32(wght), F2Dot14::ZERO];
+                (0..2)
+                    .map(|axis| {
+                        let idx = axis_index_map.get(axis).unwrap();
+                        var_store.compute_delta(idx, &coords).unwrap()
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        assert_eq!(vec![vec![0, 0], vec![0, 8192], vec![0, 16384]], actual);
+    }
 }
@@ -0,0 +1,1503 @@
+//! Generates a [CFF](https://learn.microsoft.com/en-us/typography/opentype/spec/cff) table
+//! for static fonts or a [CFF2](https://learn.microsoft.com/en-us/typography/opentype/spec/cff2)
+//! table for variable fonts.
+//!
+//! write-fonts doesn't know how to write either so we serialize them here. See
+//! <https://adobe-type-tools.github.io/font-tech-notes/pdfs/5176.CFF.pdf> and
+//! <https://adobe-type-tools.github.io/font-tech-notes/pdfs/5177.Type2.pdf>.
+//!
+//! Side bearings in hmtx, and the bounds in head, are computed from the same rounded
+//! outlines we write here, see `charstring_bbox`. So are the top side bearings in vmtx,
+//! which with CFF outlines come with a VORG table holding each glyph's vertical origin;
+//! see [crate::vertical_metrics].
+
+use std::collections::{HashMap, HashSet};
+
+use fontdrasil::{
+    orchestration::{Access, AccessBuilder, Work},
+    types::GlyphName,
+};
+use fontir::{
+    ir::{self, PostscriptHints, StaticMetadata},
+    orchestration::WorkId as FeWorkId,
+    variations::{VariationModel, VariationRegion},
+};
+use kurbo::{BezPath, PathEl, Point, Shape, Vec2};
+use log::trace;
+use write_fonts::{
+    dump_table,
+    read::tables::postscript::STANDARD_STRINGS,
+    tables::{
+        glyf::Bbox,
+        variations::{ItemVariationData, ItemVariationStore, VariationRegionList},
+    },
+    types::NameId,
+    OtRound,
+};
+
+use crate::{
+    error::{Error, GlyphProblem},
+    orchestration::{AnyWorkId, BeWork, Context, WorkId},
+};
+
+/// The Type 2 charstring argument stack limit
+const CFF_MAX_STACK: usize = 48;
+
+/// The CFF2 charstring argument stack limit
+const CFF2_MAX_STACK: usize = 513;
+
+/// Don't consider sequences of more than this many operators for subroutines
+const MAX_SUBR_LEN: usize = 32;
+
+// Type 2 charstring operators
+const OP_VMOVETO: u8 = 4;
+const OP_RLINETO: u8 = 5;
+const OP_HLINETO: u8 = 6;
+const OP_VLINETO: u8 = 7;
+const OP_RRCURVETO: u8 = 8;
+const OP_RETURN: u8 = 11;
+const OP_ENDCHAR: u8 = 14;
+const OP_VSINDEX: u8 = 15;
+const OP_BLEND: u8 = 16;
+const OP_RMOVETO: u8 = 21;
+const OP_HMOVETO: u8 = 22;
+const OP_CALLGSUBR: u8 = 29;
+
+// DICT operators, escaped operators are 12 followed by the low byte
+const DICT_FULL_NAME: u16 = 2;
+const DICT_FAMILY_NAME: u16 = 3;
+const DICT_FONT_BBOX: u16 = 5;
+const DICT_BLUE_VALUES: u16 = 6;
+const DICT_OTHER_BLUES: u16 = 7;
+const DICT_STD_HW: u16 = 10;
+const DICT_STD_VW: u16 = 11;
+const DICT_CHARSET: u16 = 15;
+const DICT_CHARSTRINGS: u16 = 17;
+const DICT_PRIVATE: u16 = 18;
+const DICT_DEFAULT_WIDTH_X: u16 = 20;
+const DICT_NOMINAL_WIDTH_X: u16 = 21;
+const DICT_VSTORE: u16 = 24;
+const DICT_FONT_MATRIX: u16 = 0x0c07;
+const DICT_STEM_SNAP_H: u16 = 0x0c0c;
+const DICT_STEM_SNAP_V: u16 = 0x0c0d;
+const DICT_FD_ARRAY: u16 = 0x0c24;
+
+#[derive(Debug)]
+struct CffWork {}
+
+pub fn create_cff_work() -> Box<BeWork> {
+    Box::new(CffWork {})
+}
+
+/// The segments of an outline, reduced to what a charstring can express
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Segment {
+    Move,
+    Line,
+    Curve,
+}
+
+impl Segment {
+    fn num_points(&self) -> usize {
+        match self {
+            Segment::Move | Segment::Line => 1,
+            Segment::Curve => 3,
+        }
+    }
+}
+
+/// A glyph outline at one location, with absolute coordinates rounded to integers
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Outline {
+    segments: Vec<Segment>,
+    points: Vec<Point>,
+}
+
+impl Outline {
+    fn new<'a>(contours: impl IntoIterator<Item = &'a BezPath>) -> Outline {
+        let mut outline = Outline::default();
+        let mut current = Point::ZERO;
+        for el in contours.into_iter().flat_map(|c| c.elements()) {
+            match *el {
+                PathEl::MoveTo(p) => outline.push(Segment::Move, &[p]),
+                PathEl::LineTo(p) => outline.push(Segment::Line, &[p]),
+                // elevate to a cubic, which is exact
+                PathEl::QuadTo(q, p) => outline.push(
+                    Segment::Curve,
+                    &[
+                        current + (2.0 / 3.0) * (q - current),
+                        p + (2.0 / 3.0) * (q - p),
+                        p,
+                    ],
+                ),
+                PathEl::CurveTo(c0, c1, p) => outline.push(Segment::Curve, &[c0, c1, p]),
+                // charstring contours are implicitly closed
+                PathEl::ClosePath => continue,
+            }
+            current = match *el {
+                PathEl::MoveTo(p) | PathEl::LineTo(p) | PathEl::QuadTo(_, p) => p,
+                PathEl::CurveTo(_, _, p) => p,
+                PathEl::ClosePath => unreachable!(),
+            };
+        }
+        outline
+    }
+
+    fn push(&mut self, segment: Segment, points: &[Point]) {
+        self.segments.push(segment);
+        self.points.extend(
+            points
+                .iter()
+                .map(|p| Point::new(p.x.ot_round(), p.y.ot_round())),
+        );
+    }
+
+    /// For each segment, true if it is a line back to the start of its contour that ends the contour.
+    ///
+    /// Such lines are implied when the contour closes so there is no need to write them.
+    fn closing_lines(&self) -> Vec<bool> {
+        let mut result = vec![false; self.segments.len()];
+        let mut start = Point::ZERO;
+        let mut point_idx = 0;
+        for (i, segment) in self.segments.iter().enumerate() {
+            point_idx += segment.num_points();
+            let end = self.points[point_idx - 1];
+            match segment {
+                Segment::Move => start = end,
+                Segment::Line => {
+                    let ends_contour = self
+                        .segments
+                        .get(i + 1)
+                        .map(|next| *next == Segment::Move)
+                        .unwrap_or(true);
+                    result[i] = ends_contour && end == start;
+                }
+                Segment::Curve => (),
+            }
+        }
+        result
+    }
+
+    fn retain_segments(&mut self, keep: &[bool]) {
+        let mut segments = Vec::with_capacity(self.segments.len());
+        let mut points = Vec::with_capacity(self.points.len());
+        let mut point_idx = 0;
+        for (segment, keep) in self.segments.iter().zip(keep) {
+            let num_points = segment.num_points();
+            if *keep {
+                segments.push(*segment);
+                points.extend_from_slice(&self.points[point_idx..point_idx + num_points]);
+            }
+            point_idx += num_points;
+        }
+        self.segments = segments;
+        self.points = points;
+    }
+
+    fn to_bezpath(&self) -> BezPath {
+        let mut path = BezPath::new();
+        let mut points = self.points.iter().copied();
+        for segment in self.segments.iter() {
+            match segment {
+                Segment::Move => {
+                    if !path.elements().is_empty() {
+                        path.close_path();
+                    }
+                    path.move_to(points.next().unwrap());
+                }
+                Segment::Line => path.line_to(points.next().unwrap()),
+                Segment::Curve => path.curve_to(
+                    points.next().unwrap(),
+                    points.next().unwrap(),
+                    points.next().unwrap(),
+                ),
+            }
+        }
+        if !path.elements().is_empty() {
+            path.close_path();
+        }
+        path
+    }
+
+    /// The bounding box of the outline as written to the font.
+    ///
+    /// Curves are measured by their extrema, not their control points, which is what
+    /// the hmtx side bearings and head bounds of a CFF-flavored font expect.
+    fn bbox(&self) -> Option<Bbox> {
+        if self.points.is_empty() {
+            return None;
+        }
+        let rect = self.to_bezpath().bounding_box();
+        Some(Bbox {
+            x_min: rect.x0.floor() as i16,
+            y_min: rect.y0.floor() as i16,
+            x_max: rect.x1.ceil() as i16,
+            y_max: rect.y1.ceil() as i16,
+        })
+    }
+}
+
+/// The bounding box of the default instance of a glyph when written as a charstring
+pub(crate) fn charstring_bbox(instance: &ir::GlyphInstance) -> Option<Bbox> {
+    let mut outline = Outline::new(instance.contours.iter());
+    let closing_lines = outline.closing_lines();
+    outline.retain_segments(&closing_lines.iter().map(|c| !c).collect::<Vec<_>>());
+    outline.bbox()
+}
+
+/// The outline of a glyph ready to be written as a charstring.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct GlyphOutline {
+    default: Outline,
+    /// Deltas for every point of the default outline, per region
+    deltas: Vec<(VariationRegion, Vec<Vec2>)>,
+}
+
+impl GlyphOutline {
+    fn new(glyph: &ir::Glyph, static_metadata: &StaticMetadata) -> Result<Self, Error> {
+        let default_location = static_metadata.default_location();
+        let Some(default_instance) = glyph.sources().get(default_location) else {
+            return Err(Error::GlyphError(
+                glyph.name.clone(),
+                GlyphProblem::MissingDefault,
+            ));
+        };
+        let mut default = Outline::new(default_instance.contours.iter());
+        if glyph.sources().len() == 1 {
+            let keep = default
+                .closing_lines()
+                .iter()
+                .map(|c| !c)
+                .collect::<Vec<_>>();
+            default.retain_segments(&keep);
+            return Ok(GlyphOutline {
+                default,
+                deltas: Vec::new(),
+            });
+        }
+
+        let mut outlines = HashMap::new();
+        for (loc, inst) in glyph.sources() {
+            let outline = Outline::new(inst.contours.iter());
+            if outline.segments != default.segments {
+                return Err(Error::GlyphError(
+                    glyph.name.clone(),
+                    GlyphProblem::InconsistentPathElements,
+                ));
+            }
+            outlines.insert(loc.clone(), outline);
+        }
+
+        // Only drop closing lines that are redundant everywhere
+        let mut keep = vec![false; default.segments.len()];
+        for outline in outlines.values() {
+            for (keep, closes) in keep.iter_mut().zip(outline.closing_lines()) {
+                *keep |= !closes;
+            }
+        }
+        for outline in outlines.values_mut() {
+            outline.retain_segments(&keep);
+        }
+        default.retain_segments(&keep);
+
+        // Use the global model if we can, otherwise build one specific to this glyph's locations
+        let global_model = &static_metadata.variation_model;
+        let sub_model;
+        let model = if global_model.num_locations() == glyph.sources().len()
+            && global_model
+                .locations()
+                .all(|l| glyph.sources().contains_key(l))
+        {
+            global_model
+        } else {
+            let locations: HashSet<_> = glyph.sources().keys().cloned().collect();
+            sub_model = VariationModel::new(locations, static_metadata.axes.clone())
+                .map_err(|e| Error::VariationModelError(glyph.name.clone(), e))?;
+            &sub_model
+        };
+
+        let point_seqs = outlines
+            .into_iter()
+            .map(|(loc, outline)| (loc, outline.points))
+            .collect();
+        let deltas = model
+            .deltas::<Point, Vec2>(&point_seqs)
+            .map_err(|e| Error::GlyphDeltaError(glyph.name.clone(), e))?
+            .into_iter()
+            .filter(|(region, deltas)| {
+                !region.is_default() && deltas.iter().any(|d| *d != Vec2::ZERO)
+            })
+            .map(|(region, deltas)| {
+                let deltas = deltas
+                    .into_iter()
+                    .map(|d| Vec2::new(d.x.ot_round(), d.y.ot_round()))
+                    .collect();
+                (region, deltas)
+            })
+            .collect();
+        Ok(GlyphOutline { default, deltas })
+    }
+}
+
+/// A charstring operand, possibly varying over the designspace
+#[derive(Debug, Clone, PartialEq)]
+struct Operand {
+    default: i32,
+    /// One delta per region of the glyph's variation data
+    deltas: Vec<i32>,
+}
+
+impl Operand {
+    fn is_zero(&self) -> bool {
+        self.default == 0 && self.deltas.iter().all(|d| *d == 0)
+    }
+
+    fn varies(&self) -> bool {
+        self.deltas.iter().any(|d| *d != 0)
+    }
+}
+
+/// Push a number onto a Type 2 charstring
+fn encode_charstring_number(out: &mut Vec<u8>, value: i32) {
+    match value {
+        -107..=107 => out.push((value + 139) as u8),
+        108..=1131 => {
+            let value = value - 108;
+            out.extend([(value >> 8) as u8 + 247, value as u8]);
+        }
+        -1131..=-108 => {
+            let value = -value - 108;
+            out.extend([(value >> 8) as u8 + 251, value as u8]);
+        }
+        _ => {
+            out.push(28);
+            out.extend((value as i16).to_be_bytes());
+        }
+    }
+}
+
+/// Produces the operators of a charstring, combining consecutive operators where possible.
+///
+/// Each operator, with its operands, is kept separately so that we can look for subroutines.
+#[derive(Debug)]
+struct CharstringBuilder {
+    max_stack: usize,
+    /// An advance width to emit before the first operator, CFF only.
+    ///
+    /// Written as a prefix so it never ends up in a subroutine.
+    width: Option<i32>,
+    pending: Option<(u8, Vec<Operand>)>,
+    commands: Vec<Vec<u8>>,
+}
+
+impl CharstringBuilder {
+    fn new(max_stack: usize, width: Option<i32>) -> Self {
+        CharstringBuilder {
+            max_stack,
+            width,
+            pending: None,
+            commands: Vec::new(),
+        }
+    }
+
+    fn stack_use(width_on_stack: bool, operands: &[Operand]) -> usize {
+        let width = width_on_stack as usize;
+        if operands.iter().any(Operand::varies) {
+            let num_regions = operands[0].deltas.len();
+            operands.len() * (num_regions + 1) + 1 + width
+        } else {
+            operands.len() + width
+        }
+    }
+
+    /// Can an operator be appended to the pending operator rather than starting a new one
+    fn can_extend(pending_op: u8, num_pending: usize, op: u8) -> bool {
+        match (pending_op, op) {
+            (OP_RLINETO, OP_RLINETO) | (OP_RRCURVETO, OP_RRCURVETO) => true,
+            // hlineto and vlineto take alternating horizontal and vertical lines
+            (OP_HLINETO, OP_HLINETO) | (OP_VLINETO, OP_VLINETO) => num_pending.is_multiple_of(2),
+            (OP_HLINETO, OP_VLINETO) | (OP_VLINETO, OP_HLINETO) => !num_pending.is_multiple_of(2),
+            _ => false,
+        }
+    }
+
+    fn push(&mut self, op: u8, operands: Vec<Operand>) {
+        if let Some((pending_op, pending)) = self.pending.as_mut() {
+            if Self::can_extend(*pending_op, pending.len(), op) {
+                let mut combined = pending.clone();
+                combined.extend(operands.iter().cloned());
+                let width_on_stack = self.width.is_some() && self.commands.is_empty();
+                if Self::stack_use(width_on_stack, &combined) <= self.max_stack {
+                    *pending = combined;
+                    return;
+                }
+            }
+        }
+        self.flush();
+        self.pending = Some((op, operands));
+    }
+
+    fn flush(&mut self) {
+        let Some((op, operands)) = self.pending.take() else {
+            return;
+        };
+        let mut command = Vec::new();
+        for operand in operands.iter() {
+            encode_charstring_number(&mut command, operand.default);
+        }
+        if operands.iter().any(Operand::varies) {
+            for operand in operands.iter() {
+                for delta in operand.deltas.iter() {
+                    encode_charstring_number(&mut command, *delta);
+                }
+            }
+            encode_charstring_number(&mut command, operands.len() as i32);
+            command.push(OP_BLEND);
+        }
+        command.push(op);
+        self.commands.push(command);
+    }
+
+    fn move_to(&mut self, dx: Operand, dy: Operand) {
+        if dy.is_zero() {
+            self.push(OP_HMOVETO, vec![dx]);
+        } else if dx.is_zero() {
+            self.push(OP_VMOVETO, vec![dy]);
+        } else {
+            self.push(OP_RMOVETO, vec![dx, dy]);
+        }
+    }
+
+    fn line_to(&mut self, dx: Operand, dy: Operand) {
+        if dy.is_zero() {
+            self.push(OP_HLINETO, vec![dx]);
+        } else if dx.is_zero() {
+            self.push(OP_VLINETO, vec![dy]);
+        } else {
+            self.push(OP_RLINETO, vec![dx, dy]);
+        }
+    }
+
+    fn curve_to(&mut self, operands: Vec<Operand>) {
+        self.push(OP_RRCURVETO, operands);
+    }
+
+    /// The operators of the charstring, plus anything that must surround them
+    fn build(mut self, endchar: bool) -> Charstring {
+        self.flush();
+        let mut prefix = Vec::new();
+        if let Some(width) = self.width {
+            encode_charstring_number(&mut prefix, width);
+        }
+        let suffix = if endchar {
+            vec![OP_ENDCHAR]
+        } else {
+            Vec::new()
+        };
+        Charstring {
+            prefix,
+            commands: self.commands,
+            suffix,
+        }
+    }
+}
+
+/// A charstring, split into operators that might be moved into a subroutine
+/// and bytes that must not be.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Charstring {
+    prefix: Vec<u8>,
+    commands: Vec<Vec<u8>>,
+    suffix: Vec<u8>,
+}
+
+impl Charstring {
+    /// Build a charstring from a glyph outline.
+    ///
+    /// `deltas` are per point, one per variation region in the order of the variation data
+    /// the charstring uses.
+    fn new(
+        glyph_name: &GlyphName,
+        outline: &Outline,
+        deltas: &[&[Vec2]],
+        width: Option<i32>,
+        max_stack: usize,
+        endchar: bool,
+    ) -> Result<Charstring, Error> {
+        let mut builder = CharstringBuilder::new(max_stack, width);
+        let operand = |value: f64, deltas: Vec<f64>| -> Result<Operand, Error> {
+            let to_i16 = |v: f64| -> Result<i32, Error> {
+                if v < i16::MIN as f64 || v > i16::MAX as f64 {
+                    return Err(Error::OutOfBounds {
+                        what: format!("charstring operand for '{glyph_name}'"),
+                        value: format!("{v}"),
+                    });
+                }
+                Ok(v as i32)
+            };
+            Ok(Operand {
+                default: to_i16(value)?,
+                deltas: deltas.into_iter().map(to_i16).collect::<Result<_, _>>()?,
+            })
+        };
+
+        let mut point_idx = 0;
+        let xy = |point_idx: usize| -> Result<(Operand, Operand), Error> {
+            let curr = outline.points[point_idx];
+            let prev = point_idx
+                .checked_sub(1)
+                .map(|i| outline.points[i])
+                .unwrap_or_default();
+            let (dx, dy): (Vec<_>, Vec<_>) = deltas
+                .iter()
+                .map(|deltas| {
+                    let curr = deltas[point_idx];
+                    let prev = point_idx
+                        .checked_sub(1)
+                        .map(|i| deltas[i])
+                        .unwrap_or_default();
+                    (curr.x - prev.x, curr.y - prev.y)
+                })
+                .unzip();
+            Ok((operand(curr.x - prev.x, dx)?, operand(curr.y - prev.y, dy)?))
+        };
+        for segment in outline.segments.iter() {
+            match segment {
+                Segment::Move => {
+                    let (dx, dy) = xy(point_idx)?;
+                    builder.move_to(dx, dy);
+                }
+                Segment::Line => {
+                    let (dx, dy) = xy(point_idx)?;
+                    builder.line_to(dx, dy);
+                }
+                Segment::Curve => {
+                    let mut operands = Vec::with_capacity(6);
+                    for i in point_idx..point_idx + 3 {
+                        let (dx, dy) = xy(i)?;
+                        operands.push(dx);
+                        operands.push(dy);
+                    }
+                    builder.curve_to(operands);
+                }
+            }
+            point_idx += segment.num_points();
+        }
+        Ok(builder.build(endchar))
+    }
+}
+
+/// The subroutine bias for a given number of subroutines, per the Type 2 spec
+fn subr_bias(num_subrs: usize) -> i32 {
+    if num_subrs < 1240 {
+        107
+    } else if num_subrs < 33900 {
+        1131
+    } else {
+        32768
+    }
+}
+
+/// Move operator sequences that repeat into global subroutines.
+///
+/// A greedy approach: every sequence of up to [MAX_SUBR_LEN] operators that occurs more than
+/// once is a candidate, we take candidates in order of estimated savings so long as they still
+/// save space after accounting for the occurrences earlier choices have claimed. Subroutines
+/// don't nest.
+///
+/// Returns the global subroutines and the final charstrings.
+fn subroutinize(charstrings: &[Charstring], subrs_return: bool) -> (Vec<Vec<u8>>, Vec<Vec<u8>>) {
+    // An approximation of the bytes to call a subroutine, and to store one
+    const CALL_COST: usize = 3;
+    const SUBR_OVERHEAD: usize = 3;
+
+    // Intern commands so we can look for repeats of slices of ids
+    let mut interned: HashMap<&[u8], u32> = HashMap::new();
+    let mut command_bytes: Vec<&[u8]> = Vec::new();
+    let glyph_ids: Vec<Vec<u32>> = charstrings
+        .iter()
+        .map(|cs| {
+            cs.commands
+                .iter()
+                .map(|c| {
+                    *interned.entry(c.as_slice()).or_insert_with(|| {
+                        command_bytes.push(c.as_slice());
+                        command_bytes.len() as u32 - 1
+                    })
+                })
+                .collect()
+        })
+        .collect();
+
+    // (glyph index, start) of every occurrence of every sequence
+    let mut occurrences: HashMap<&[u32], Vec<(usize, usize)>> = HashMap::new();
+    for (glyph_idx, ids) in glyph_ids.iter().enumerate() {
+        for start in 0..ids.len() {
+            for end in start + 1..=ids.len().min(start + MAX_SUBR_LEN) {
+                occurrences
+                    .entry(&ids[start..end])
+                    .or_default()
+                    .push((glyph_idx, start));
+            }
+        }
+    }
+
+    let seq_len =
+        |seq: &[u32]| -> usize { seq.iter().map(|id| command_bytes[*id as usize].len()).sum() };
+    let savings = |len: usize, count: usize| -> isize {
+        (count * len) as isize - (count * CALL_COST + len + SUBR_OVERHEAD) as isize
+    };
+    let mut candidates: Vec<_> = occurrences
+        .into_iter()
+        .map(|(seq, mut occurrences)| {
+            // Occurrences are in order of glyph then start, overlapping ones can't all be used
+            let mut prev: Option<(usize, usize)> = None;
+            occurrences.retain(|(glyph_idx, start)| {
+                if let Some((prev_glyph, prev_start)) = prev {
+                    if prev_glyph == *glyph_idx && prev_start + seq.len() > *start {
+                        return false;
+                    }
+                }
+                prev = Some((*glyph_idx, *start));
+                true
+            });
+            (seq, occurrences)
+        })
+        .filter(|(_, occurrences)| occurrences.len() > 1)
+        .map(|(seq, occurrences)| {
+            let len = seq_len(seq);
+            (savings(len, occurrences.len()), seq, occurrences)
+        })
+        .filter(|(savings, ..)| *savings > 0)
+        .collect();
+    // Highest savings first, break ties by position so output is stable
+    candidates.sort_by(|(s1, seq1, o1), (s2, seq2, o2)| {
+        s2.cmp(s1)
+            .then_with(|| o1[0].cmp(&o2[0]))
+            .then_with(|| seq1.len().cmp(&seq2.len()))
+    });
+
+    let mut taken: Vec<Vec<bool>> = glyph_ids.iter().map(|ids| vec![false; ids.len()]).collect();
+    // per glyph, start => (subr index, length in commands)
+    let mut calls: Vec<HashMap<usize, (usize, usize)>> = vec![HashMap::new(); glyph_ids.len()];
+    let mut subrs: Vec<(usize, &[u32])> = Vec::new();
+    for (_, seq, occurrences) in candidates {
+        let mut usable = Vec::new();
+        for (glyph_idx, start) in occurrences {
+            let range = start..start + seq.len();
+            if taken[glyph_idx][range.clone()].iter().any(|t| *t) {
+                continue;
+            }
+            usable.push((glyph_idx, start));
+        }
+        if usable.len() < 2 || savings(seq_len(seq), usable.len()) <= 0 {
+            continue;
+        }
+        let subr_idx = subrs.len();
+        for (glyph_idx, start) in usable.iter() {
+            taken[*glyph_idx][*start..*start + seq.len()]
+                .iter_mut()
+                .for_each(|t| *t = true);
+            calls[*glyph_idx].insert(*start, (subr_idx, seq.len()));
+        }
+        subrs.push((usable.len(), seq));
+    }
+
+    // Most used subroutines get the smallest (cheapest to encode) indices
+    let mut order: Vec<_> = (0..subrs.len()).collect();
+    order.sort_by_key(|i| std::cmp::Reverse(subrs[*i].0));
+    let mut new_index = vec![0; subrs.len()];
+    for (new, old) in order.iter().enumerate() {
+        new_index[*old] = new;
+    }
+    let bias = subr_bias(subrs.len());
+
+    let global_subrs = order
+        .iter()
+        .map(|i| {
+            let mut subr: Vec<u8> = subrs[*i]
+                .1
+                .iter()
+                .flat_map(|id| command_bytes[*id as usize].iter().copied())
+                .collect();
+            if subrs_return {
+                subr.push(OP_RETURN);
+            }
+            subr
+        })
+        .collect();
+
+    let charstrings = charstrings
+        .iter()
+        .zip(glyph_ids.iter().zip(calls))
+        .map(|(cs, (ids, calls))| {
+            let mut bytes = cs.prefix.clone();
+            let mut i = 0;
+            while i < ids.len() {
+                if let Some((subr_idx, len)) = calls.get(&i) {
+                    encode_charstring_number(&mut bytes, new_index[*subr_idx] as i32 - bias);
+                    bytes.push(OP_CALLGSUBR);
+                    i += len;
+                } else {
+                    bytes.extend_from_slice(&cs.commands[i]);
+                    i += 1;
+                }
+            }
+            bytes.extend_from_slice(&cs.suffix);
+            bytes
+        })
+        .collect();
+
+    (global_subrs, charstrings)
+}
+
+/// Write a CFF INDEX; CFF2 uses a 32-bit count, CFF a 16-bit count
+pub(crate) fn write_index(out: &mut Vec<u8>, items: &[Vec<u8>], cff2: bool) {
+    if cff2 {
+        out.extend((items.len() as u32).to_be_bytes());
+    } else {
+        out.extend((items.len() as u16).to_be_bytes());
+    }
+    if items.is_empty() {
+        return;
+    }
+    let data_len: usize = items.iter().map(Vec::len).sum();
+    let off_size = offset_size(data_len + 1);
+    out.push(off_size);
+    let mut offset = 1;
+    for item in std::iter::once(&Vec::new()).chain(items.iter()) {
+        offset += item.len();
+        out.extend(&(offset as u32).to_be_bytes()[4 - off_size as usize..]);
+    }
+    for item in items {
+        out.extend(item);
+    }
+}
+
+fn index_len(items: &[Vec<u8>], cff2: bool) -> usize {
+    let mut buf = Vec::new();
+    write_index(&mut buf, items, cff2);
+    buf.len()
+}
+
+/// The number of bytes needed to store an offset
+fn offset_size(max_offset: usize) -> u8 {
+    match max_offset {
+        0..=0xFF => 1,
+        0x100..=0xFFFF => 2,
+        0x10000..=0xFFFFFF => 3,
+        _ => 4,
+    }
+}
+
+/// Accumulates the bytes of a DICT
+#[derive(Debug, Default)]
+struct DictWriter(Vec<u8>);
+
+impl DictWriter {
+    fn int(&mut self, value: i32) -> &mut Self {
+        match value {
+            -107..=107 => self.0.push((value + 139) as u8),
+            108..=1131 => {
+                let value = value - 108;
+                self.0.extend([(value >> 8) as u8 + 247, value as u8]);
+            }
+            -1131..=-108 => {
+                let value = -value - 108;
+                self.0.extend([(value >> 8) as u8 + 251, value as u8]);
+            }
+            -32768..=32767 => {
+                self.0.push(28);
+                self.0.extend((value as i16).to_be_bytes());
+            }
+            _ => {
+                self.0.push(29);
+                self.0.extend(value.to_be_bytes());
+            }
+        }
+        self
+    }
+
+    /// Offsets are always written in 5 bytes so DICT size doesn't depend on their value
+    fn offset(&mut self, value: usize) -> &mut Self {
+        self.0.push(29);
+        self.0.extend((value as i32).to_be_bytes());
+        self
+    }
+
+    fn real(&mut self, value: f64) -> &mut Self {
+        let mut nibbles: Vec<u8> = Vec::new();
+        for c in format!("{value}").chars() {
+            nibbles.push(match c {
+                '0'..='9' => c as u8 - b'0',
+                '.' => 0xa,
+                '-' => 0xe,
+                _ => unreachable!("Display for f64 only emits digits, '.' and '-'"),
+            });
+        }
+        nibbles.push(0xf);
+        if nibbles.len() % 2 == 1 {
+            nibbles.push(0xf);
+        }
+        self.0.push(30);
+        self.0
+            .extend(nibbles.chunks(2).map(|pair| (pair[0] << 4) | pair[1]));
+        self
+    }
+
+    fn op(&mut self, op: u16) -> &mut Self {
+        if op > 0xff {
+            self.0.extend(op.to_be_bytes());
+        } else {
+            self.0.push(op as u8);
+        }
+        self
+    }
+
+    fn font_matrix(&mut self, units_per_em: u16) -> &mut Self {
+        if units_per_em == 1000 {
+            return self; // the default
+        }
+        let scale = 1.0 / units_per_em as f64;
+        self.real(scale)
+            .int(0)
+            .int(0)
+            .real(scale)
+            .int(0)
+            .int(0)
+            .op(DICT_FONT_MATRIX)
+    }
+
+    /// Write values as a delta array, each relative to the one before; nothing if there are none
+    fn deltas(&mut self, values: &[i16], op: u16) -> &mut Self {
+        if values.is_empty() {
+            return self;
+        }
+        let mut previous = 0;
+        for value in values.iter().map(|v| *v as i32) {
+            self.int(value - previous);
+            previous = value;
+        }
+        self.op(op)
+    }
+
+    /// The alignment zones and stems of a Private DICT, whichever the source has.
+    ///
+    /// Like ufo2ft the first of the stem snaps is also the StdHW/StdVW.
+    fn hints(&mut self, hints: &PostscriptHints) -> &mut Self {
+        self.deltas(&hints.blue_values, DICT_BLUE_VALUES)
+            .deltas(&hints.other_blues, DICT_OTHER_BLUES);
+        if let Some(std_hw) = hints.stem_snap_h.first() {
+            self.int(*std_hw as i32).op(DICT_STD_HW);
+        }
+        if let Some(std_vw) = hints.stem_snap_v.first() {
+            self.int(*std_vw as i32).op(DICT_STD_VW);
+        }
+        self.deltas(&hints.stem_snap_h, DICT_STEM_SNAP_H)
+            .deltas(&hints.stem_snap_v, DICT_STEM_SNAP_V)
+    }
+
+    fn into_bytes(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+/// Assigns string ids, reusing the standard strings where possible
+#[derive(Debug, Default)]
+struct StringTable {
+    custom: Vec<Vec<u8>>,
+    ids: HashMap<String, u16>,
+}
+
+impl StringTable {
+    fn sid(&mut self, s: &str) -> u16 {
+        if let Some(sid) = STANDARD_STRINGS.iter().position(|std| *std == s) {
+            return sid as u16;
+        }
+        if let Some(sid) = self.ids.get(s) {
+            return *sid;
+        }
+        let sid = (STANDARD_STRINGS.len() + self.custom.len()) as u16;
+        // CFF strings are Latin-1, as are sane glyph and font names
+        self.custom
+            .push(s.chars().map(|c| c as u32 as u8).collect::<Vec<_>>());
+        self.ids.insert(s.to_string(), sid);
+        sid
+    }
+}
+
+/// Maps the variation regions used by charstrings to the CFF2 variation store.
+///
+/// Every distinct list of regions gets an ItemVariationData, which charstrings
+/// select using vsindex.
+#[derive(Debug, Default)]
+struct CharstringVariations {
+    regions: Vec<VariationRegion>,
+    var_data: Vec<Vec<u16>>,
+}
+
+impl CharstringVariations {
+    /// The vsindex for a set of regions
+    fn vsindex(&mut self, regions: impl Iterator<Item = VariationRegion>) -> usize {
+        let region_indices: Vec<u16> = regions
+            .map(|region| {
+                let idx = self
+                    .regions
+                    .iter()
+                    .position(|r| *r == region)
+                    .unwrap_or_else(|| {
+                        self.regions.push(region);
+                        self.regions.len() - 1
+                    });
+                idx as u16
+            })
+            .collect();
+        self.var_data
+            .iter()
+            .position(|d| *d == region_indices)
+            .unwrap_or_else(|| {
+                self.var_data.push(region_indices);
+                self.var_data.len() - 1
+            })
+    }
+
+    /// The VariationStore as written into CFF2, prefixed with its length
+    fn to_bytes(&self, static_metadata: &StaticMetadata) -> Result<Vec<u8>, Error> {
+        let regions = self
+            .regions
+            .iter()
+            .map(|r| r.to_write_fonts_variation_region(&static_metadata.axes))
+            .collect();
+        let store = ItemVariationStore::new(
+            VariationRegionList::new(static_metadata.axes.len() as u16, regions),
+            self.var_data
+                .iter()
+                .map(|region_indexes| {
+                    Some(ItemVariationData::new(
+                        0,
+                        0,
+                        region_indexes.clone(),
+                        Vec::new(),
+                    ))
+                })
+                .collect(),
+        );
+        let store = dump_table(&store).map_err(|e| Error::DumpTableError {
+            e,
+            context: "CFF2 VariationStore".into(),
+        })?;
+        let mut bytes = (store.len() as u16).to_be_bytes().to_vec();
+        bytes.extend(store);
+        Ok(bytes)
+    }
+}
+
+fn name(static_metadata: &StaticMetadata, name_id: NameId) -> Option<&str> {
+    static_metadata
+        .names
+        .iter()
+        .find(|(key, _)| key.name_id == name_id)
+        .map(|(_, value)| value.as_str())
+}
+
+/// The most common advance width is the default, which can be omitted from charstrings
+fn default_width(widths: &[i32]) -> i32 {
+    let mut counts: HashMap<i32, usize> = HashMap::new();
+    for width in widths {
+        *counts.entry(*width).or_default() += 1;
+    }
+    counts
+        .into_iter()
+        .max_by_key(|(width, count)| (*count, std::cmp::Reverse(*width)))
+        .map(|(width, _)| width)
+        .unwrap_or_default()
+}
+
+/// Build a CFF table from the default outlines of every glyph.
+///
+/// `glyphs` are in glyph order and are (name, advance width, outline).
+fn build_cff(
+    static_metadata: &StaticMetadata,
+    glyphs: &[(GlyphName, i32, GlyphOutline)],
+) -> Result<Vec<u8>, Error> {
+    let widths: Vec<_> = glyphs.iter().map(|(_, width, _)| *width).collect();
+    let default_width_x = default_width(&widths);
+    let nominal_width_x = default_width_x;
+
+    let mut bbox: Option<Bbox> = None;
+    let mut charstrings = Vec::with_capacity(glyphs.len());
+    for (name, width, outline) in glyphs {
+        if let Some(glyph_bbox) = outline.default.bbox() {
+            bbox = Some(bbox.map(|b| b.union(glyph_bbox)).unwrap_or(glyph_bbox));
+        }
+        let width = (*width != default_width_x).then_some(*width - nominal_width_x);
+        charstrings.push(Charstring::new(
+            name,
+            &outline.default,
+            &[],
+            width,
+            CFF_MAX_STACK,
+            true,
+        )?);
+    }
+    let (global_subrs, charstrings) = subroutinize(&charstrings, true);
+
+    let mut strings = StringTable::default();
+    let font_name = name(static_metadata, NameId::POSTSCRIPT_NAME).unwrap_or("Untitled");
+    let full_name = name(static_metadata, NameId::FULL_NAME).map(|n| strings.sid(n));
+    let family_name = name(static_metadata, NameId::FAMILY_NAME).map(|n| strings.sid(n));
+    let charset: Vec<u8> = std::iter::once(0u8) // format 0
+        .chain(glyphs.iter().skip(1).flat_map(|(name, ..)| {
+            let name = static_metadata
+                .postscript_names
+                .get(name)
+                .unwrap_or(name)
+                .as_str();
+            strings.sid(name).to_be_bytes()
+        }))
+        .collect();
+
+    let mut private = DictWriter::default();
+    private
+        .hints(&static_metadata.misc.postscript_hints)
+        .int(default_width_x)
+        .op(DICT_DEFAULT_WIDTH_X)
+        .int(nominal_width_x)
+        .op(DICT_NOMINAL_WIDTH_X);
+    let private = private.into_bytes();
+
+    let bbox = bbox.unwrap_or_default();
+    let top_dict = |charset_offset, charstrings_offset, private_offset| {
+        let mut top = DictWriter::default();
+        if let Some(sid) = full_name {
+            top.int(sid as i32).op(DICT_FULL_NAME);
+        }
+        if let Some(sid) = family_name {
+            top.int(sid as i32).op(DICT_FAMILY_NAME);
+        }
+        top.font_matrix(static_metadata.units_per_em)
+            .int(bbox.x_min as i32)
+            .int(bbox.y_min as i32)
+            .int(bbox.x_max as i32)
+            .int(bbox.y_max as i32)
+            .op(DICT_FONT_BBOX)
+            .offset(charset_offset)
+            .op(DICT_CHARSET)
+            .offset(charstrings_offset)
+            .op(DICT_CHARSTRINGS)
+            .int(private.len() as i32)
+            .offset(private_offset)
+            .op(DICT_PRIVATE);
+        top.into_bytes()
+    };
+
+    let name_index = vec![font_name.as_bytes().to_vec()];
+    let header_len = 4;
+    let charset_offset = header_len
+        + index_len(&name_index, false)
+        + index_len(&[top_dict(0, 0, 0)], false)
+        + index_len(&strings.custom, false)
+        + index_len(&global_subrs, false);
+    let charstrings_offset = charset_offset + charset.len();
+    let private_offset = charstrings_offset + index_len(&charstrings, false);
+    let total_len = private_offset + private.len();
+
+    let mut cff = vec![1, 0, header_len as u8, offset_size(total_len)];
+    write_index(&mut cff, &name_index, false);
+    write_index(
+        &mut cff,
+        &[top_dict(charset_offset, charstrings_offset, private_offset)],
+        false,
+    );
+    write_index(&mut cff, &strings.custom, false);
+    write_index(&mut cff, &global_subrs, false);
+    debug_assert_eq!(charset_offset, cff.len());
+    cff.extend(charset);
+    write_index(&mut cff, &charstrings, false);
+    debug_assert_eq!(private_offset, cff.len());
+    cff.extend(private);
+    Ok(cff)
+}
+
+/// Build a CFF2 table, with blends for glyphs that vary.
+///
+/// `glyphs` are in glyph order and are (name, outline).
+fn build_cff2(
+    static_metadata: &StaticMetadata,
+    glyphs: &[(GlyphName, GlyphOutline)],
+) -> Result<Vec<u8>, Error> {
+    let mut variations = CharstringVariations::default();
+    let mut charstrings = Vec::with_capacity(glyphs.len());
+    for (name, outline) in glyphs {
+        let deltas: Vec<_> = outline.deltas.iter().map(|(_, d)| d.as_slice()).collect();
+        let mut charstring =
+            Charstring::new(name, &outline.default, &deltas, None, CFF2_MAX_STACK, false)?;
+        if !deltas.is_empty() {
+            let vsindex =
+                variations.vsindex(outline.deltas.iter().map(|(region, _)| region.clone()));
+            if vsindex != 0 {
+                encode_charstring_number(&mut charstring.prefix, vsindex as i32);
+                charstring.prefix.push(OP_VSINDEX);
+            }
+        }
+        charstrings.push(charstring);
+    }
+    let (global_subrs, charstrings) = subroutinize(&charstrings, false);
+
+    let vstore = if variations.var_data.is_empty() {
+        None
+    } else {
+        Some(variations.to_bytes(static_metadata)?)
+    };
+
+    // The hints are those of the default master, we don't blend them
+    let mut private = DictWriter::default();
+    private.hints(&static_metadata.misc.postscript_hints);
+    let private = private.into_bytes();
+    let font_dict = |private_offset| {
+        let mut font_dict = DictWriter::default();
+        font_dict
+            .int(private.len() as i32)
+            .offset(private_offset)
+            .op(DICT_PRIVATE);
+        font_dict.into_bytes()
+    };
+
+    let top_dict = |vstore_offset, charstrings_offset, fd_array_offset| {
+        let mut top = DictWriter::default();
+        top.font_matrix(static_metadata.units_per_em)
+            .offset(charstrings_offset)
+            .op(DICT_CHARSTRINGS)
+            .offset(fd_array_offset)
+            .op(DICT_FD_ARRAY);
+        if vstore.is_some() {
+            top.offset(vstore_offset).op(DICT_VSTORE);
+        }
+        top.into_bytes()
+    };
+
+    let header_len = 5;
+    let top_dict_len = top_dict(0, 0, 0).len();
+    let vstore_offset = header_len + top_dict_len + index_len(&global_subrs, true);
+    let charstrings_offset = vstore_offset + vstore.as_ref().map(Vec::len).unwrap_or_default();
+    let fd_array_offset = charstrings_offset + index_len(&charstrings, true);
+    let private_offset = fd_array_offset + index_len(&[font_dict(0)], true);
+
+    let mut cff2 = vec![2, 0, header_len as u8];
+    cff2.extend((top_dict_len as u16).to_be_bytes());
+    cff2.extend(top_dict(vstore_offset, charstrings_offset, fd_array_offset));
+    write_index(&mut cff2, &global_subrs, true);
+    debug_assert_eq!(vstore_offset, cff2.len());
+    if let Some(vstore) = vstore {
+        cff2.extend(vstore);
+    }
+    write_index(&mut cff2, &charstrings, true);
+    write_index(&mut cff2, &[font_dict(private_offset)], true);
+    debug_assert_eq!(private_offset, cff2.len());
+    cff2.extend(private);
+    Ok(cff2)
+}
+
+impl Work<Context, AnyWorkId, Error> for CffWork {
+    fn id(&self) -> AnyWorkId {
+        WorkId::Cff.into()
+    }
+
+    fn read_access(&self) -> Access<AnyWorkId> {
+        AccessBuilder::new()
+            .variant(FeWorkId::StaticMetadata)
+            .variant(FeWorkId::GlyphOrder)
+            .variant(FeWorkId::ALL_GLYPHS)
+            .build()
+    }
+
+    fn write_access(&self) -> Access<AnyWorkId> {
+        AccessBuilder::new()
+            .variant(WorkId::Cff)
+            .variant(WorkId::Cff2)
+            .build()
+    }
+
+    fn also_completes(&self) -> Vec<AnyWorkId> {
+        vec![WorkId::Cff2.into()]
+    }
+
+    /// Generate [CFF](https://learn.microsoft.com/en-us/typography/opentype/spec/cff)
+    /// for a static font or [CFF2](https://learn.microsoft.com/en-us/typography/opentype/spec/cff2)
+    /// for a variable one.
+    fn exec(&self, context: &Context) -> Result<(), Error> {
+        let static_metadata = context.ir.static_metadata.get();
+        let glyph_order = context.ir.glyph_order.get();
+
+        let mut glyphs = Vec::with_capacity(glyph_order.len());
+        for glyph_name in glyph_order.names() {
+            let glyph = context.ir.get_glyph(glyph_name.clone());
+            let outline = GlyphOutline::new(&glyph, &static_metadata)?;
+            let width: u16 = glyph.default_instance().width.ot_round();
+            glyphs.push((glyph_name.clone(), width as i32, outline));
+        }
+
+        if static_metadata.axes.is_empty() {
+            let cff = build_cff(&static_metadata, &glyphs)?;
+            trace!("CFF is {} bytes", cff.len());
+            context.cff.set(cff.into());
+        } else {
+            let glyphs: Vec<_> = glyphs
+                .into_iter()
+                .map(|(name, _, outline)| (name, outline))
+                .collect();
+            let cff2 = build_cff2(&static_metadata, &glyphs)?;
+            trace!("CFF2 is {} bytes", cff2.len());
+            context.cff2.set(cff2.into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fontir::variations::Tent;
+    use write_fonts::{
+        read::tables::postscript::dict::{self, Entry},
+        types::{Fixed, Tag},
+    };
+
+    use super::*;
+
+    fn operand(default: i32, deltas: &[i32]) -> Operand {
+        Operand {
+            default,
+            deltas: deltas.to_vec(),
+        }
+    }
+
+    fn encode(value: i32) -> Vec<u8> {
+        let mut buf = Vec::new();
+        encode_charstring_number(&mut buf, value);
+        buf
+    }
+
+    #[test]
+    fn charstring_numbers() {
+        assert_eq!(vec![139], encode(0));
+        assert_eq!(vec![32], encode(-107));
+        assert_eq!(vec![246], encode(107));
+        assert_eq!(vec![247, 0], encode(108));
+        assert_eq!(vec![254, 255], encode(-1131));
+        assert_eq!(vec![28, 0x04, 0x6c], encode(1132));
+    }
+
+    #[test]
+    fn dict_real() {
+        let mut dict = DictWriter::default();
+        dict.real(-0.5);
+        assert_eq!(vec![30, 0xe0, 0xa5, 0xff], dict.into_bytes());
+    }
+
+    #[test]
+    fn private_dict_hints() {
+        let mut dict = DictWriter::default();
+        dict.hints(&PostscriptHints {
+            blue_values: vec![-16, 0, 737, 753],
+            other_blues: vec![-58, -42],
+            stem_snap_h: vec![80, 92],
+            stem_snap_v: vec![100],
+        });
+        let dict = dict.into_bytes();
+
+        let fixed = |values: &[i32]| {
+            values
+                .iter()
+                .map(|v| Fixed::from_i32(*v))
+                .collect::<Vec<_>>()
+        };
+        let blues = |entry: &Entry| match entry {
+            Entry::BlueValues(blues) | Entry::OtherBlues(blues) => blues
+                .values()
+                .iter()
+                .flat_map(|(bottom, top)| [*bottom, *top])
+                .collect(),
+            Entry::StdHw(value) | Entry::StdVw(value) => vec![*value],
+            Entry::StemSnapH(snaps) | Entry::StemSnapV(snaps) => snaps.values().to_vec(),
+            _ => panic!("unexpected {entry:?}"),
+        };
+        let entries: Vec<_> = dict::entries(&dict, None)
+            .map(|entry| entry.unwrap())
+            .collect();
+        let values: Vec<Vec<Fixed>> = entries.iter().map(blues).collect();
+        assert_eq!(
+            vec![
+                fixed(&[-16, 0, 737, 753]),
+                fixed(&[-58, -42]),
+                fixed(&[80]),
+                fixed(&[100]),
+                fixed(&[80, 92]),
+                fixed(&[100]),
+            ],
+            values
+        );
+        assert!(matches!(
+            entries.as_slice(),
+            [
+                Entry::BlueValues(_),
+                Entry::OtherBlues(_),
+                Entry::StdHw(_),
+                Entry::StdVw(_),
+                Entry::StemSnapH(_),
+                Entry::StemSnapV(_)
+            ]
+        ));
+    }
+
+    #[test]
+    fn no_hints_no_private_dict_entries() {
+        let mut dict = DictWriter::default();
+        dict.hints(&PostscriptHints::default());
+        assert!(dict.into_bytes().is_empty());
+    }
+
+    #[test]
+    fn combines_alternating_lines() {
+        let mut builder = CharstringBuilder::new(CFF_MAX_STACK, None);
+        builder.move_to(operand(10, &[]), operand(10, &[]));
+        builder.line_to(operand(100, &[]), operand(0, &[]));
+        builder.line_to(operand(0, &[]), operand(100, &[]));
+        builder.line_to(operand(-100, &[]), operand(0, &[]));
+        let charstring = builder.build(true);
+        assert_eq!(
+            vec![vec![149, 149, OP_RMOVETO], vec![239, 239, 39, OP_HLINETO]],
+            charstring.commands
+        );
+        assert_eq!(vec![OP_ENDCHAR], charstring.suffix);
+    }
+
+    #[test]
+    fn width_precedes_first_operator() {
+        let mut builder = CharstringBuilder::new(CFF_MAX_STACK, Some(-5));
+        builder.move_to(operand(0, &[]), operand(10, &[]));
+        let charstring = builder.build(true);
+        assert_eq!(vec![134], charstring.prefix);
+        assert_eq!(vec![vec![149, OP_VMOVETO]], charstring.commands);
+
+        // and before endchar if there is no outline
+        let charstring = CharstringBuilder::new(CFF_MAX_STACK, Some(-5)).build(true);
+        assert_eq!(vec![134], charstring.prefix);
+        assert!(charstring.commands.is_empty());
+        assert_eq!(vec![OP_ENDCHAR], charstring.suffix);
+    }
+
+    #[test]
+    fn blends_varying_operands() {
+        let mut builder = CharstringBuilder::new(CFF2_MAX_STACK, None);
+        builder.line_to(operand(10, &[5]), operand(20, &[0]));
+        let charstring = builder.build(false);
+        // 10 20 5 0 2 blend rlineto
+        assert_eq!(
+            vec![vec![149, 159, 144, 139, 141, OP_BLEND, OP_RLINETO]],
+            charstring.commands
+        );
+        assert!(charstring.suffix.is_empty());
+    }
+
+    #[test]
+    fn respects_stack_limit() {
+        let mut builder = CharstringBuilder::new(CFF_MAX_STACK, None);
+        for _ in 0..10 {
+            builder.curve_to(vec![operand(1, &[]); 6]);
+        }
+        let charstring = builder.build(true);
+        // 48 operands fit 8 curves
+        assert_eq!(2, charstring.commands.len());
+        assert_eq!(8 * 6 + 1, charstring.commands[0].len());
+    }
+
+    #[test]
+    fn omits_implied_closing_line() {
+        let mut outline = Outline::new(&[BezPath::from_svg("M0,0 L10,0 L10,10 L0,0 Z").unwrap()]);
+        assert_eq!(vec![false, false, false, true], outline.closing_lines());
+        let keep: Vec<_> = outline.closing_lines().iter().map(|c| !c).collect();
+        outline.retain_segments(&keep);
+        assert_eq!(
+            vec![Segment::Move, Segment::Line, Segment::Line],
+            outline.segments
+        );
+    }
+
+    #[test]
+    fn quadratics_become_cubics() {
+        let outline = Outline::new(&[BezPath::from_svg("M0,0 Q30,30 60,0 Z").unwrap()]);
+        assert_eq!(vec![Segment::Move, Segment::Curve], outline.segments);
+        assert_eq!(
+            vec![
+                Point::new(0.0, 0.0),
+                Point::new(20.0, 20.0),
+                Point::new(40.0, 20.0),
+                Point::new(60.0, 0.0)
+            ],
+            outline.points
+        );
+    }
+
+    #[test]
+    fn bbox_uses_curve_extrema() {
+        let outline = Outline::new(&[BezPath::from_svg("M0,0 C0,100 100,100 100,0 Z").unwrap()]);
+        assert_eq!(
+            Some(Bbox {
+                x_min: 0,
+                y_min: 0,
+                x_max: 100,
+                y_max: 75,
+            }),
+            outline.bbox()
+        );
+    }
+
+    #[test]
+    fn subroutinizes_repeats() {
+        let shared: Vec<_> = (0..4)
+            .map(|i| vec![200 + i, 200, 200, 200, OP_RLINETO])
+            .collect();
+        let charstrings: Vec<_> = (0..3)
+            .map(|i| Charstring {
+                prefix: Vec::new(),
+                commands: std::iter::once(vec![139 + i, OP_HMOVETO])
+                    .chain(shared.iter().cloned())
+                    .collect(),
+                suffix: vec![OP_ENDCHAR],
+            })
+            .collect();
+        let (subrs, charstrings) = subroutinize(&charstrings, true);
+        assert_eq!(1, subrs.len());
+        assert_eq!(
+            shared
+                .iter()
+                .flatten()
+                .copied()
+                .chain(std::iter::once(OP_RETURN))
+                .collect::<Vec<_>>(),
+            subrs[0]
+        );
+        // -107 is the first subr
+        assert_eq!(
+            vec![139, OP_HMOVETO, 32, OP_CALLGSUBR, OP_ENDCHAR],
+            charstrings[0]
+        );
+    }
+
+    #[test]
+    fn distinct_regions_get_distinct_var_data() {
+        let region = |peak: f64| {
+            let mut region = VariationRegion::default();
+            region.insert(Tag::new(b"wght"), Tent::from((0.0, peak, peak)));
+            region
+        };
+        let mut variations = CharstringVariations::default();
+        assert_eq!(0, variations.vsindex([region(1.0)].into_iter()));
+        assert_eq!(
+            1,
+            variations.vsindex([region(0.5), region(1.0)].into_iter())
+        );
+        assert_eq!(0, variations.vsindex([region(1.0)].into_iter()));
+        assert_eq!(vec![vec![0], vec![1, 0]], variations.var_data);
+    }
+}
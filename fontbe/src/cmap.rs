@@ -1,9 +1,20 @@
 //! Generates a [cmap](https://learn.microsoft.com/en-us/typography/opentype/spec/cmap) table.
 
-use fontdrasil::orchestration::{Access, AccessBuilder, Work};
-use fontir::orchestration::WorkId as FeWorkId;
+use std::collections::{BTreeMap, HashMap};
 
-use write_fonts::{tables::cmap::Cmap, types::GlyphId16};
+use fontdrasil::{
+    orchestration::{Access, AccessBuilder, Work},
+    types::GlyphName,
+};
+use fontir::{ir::GlyphOrder, orchestration::WorkId as FeWorkId};
+use log::warn;
+use write_fonts::{
+    tables::cmap::{
+        Cmap, Cmap14, CmapSubtable, DefaultUvs, EncodingRecord, NonDefaultUvs, PlatformId,
+        UnicodeRange, UvsMapping, VariationSelector,
+    },
+    types::{GlyphId, GlyphId16, Uint24},
+};
 
 use crate::{
     error::Error,
@@ -24,6 +35,7 @@ impl Work<Context, AnyWorkId, Error> for CmapWork {
 
     fn read_access(&self) -> Access<AnyWorkId> {
         AccessBuilder::new()
+            .variant(FeWorkId::StaticMetadata)
             .variant(FeWorkId::GlyphOrder)
             .variant(FeWorkId::ALL_GLYPHS)
             .build()
@@ -34,7 +46,7 @@ impl Work<Context, AnyWorkId, Error> for CmapWork {
         // cmap only accomodates single codepoint : glyph mappings; collect all of those
         let glyph_order = context.ir.glyph_order.get();
 
-        let mappings = glyph_order
+        let mappings: Vec<(char, GlyphId)> = glyph_order
             .names()
             .map(|glyph_name| context.ir.get_glyph(glyph_name.clone()))
             .enumerate()
@@ -49,10 +61,110 @@ impl Work<Context, AnyWorkId, Error> for CmapWork {
                         )
                     })
                     .collect::<Vec<_>>()
-            });
+            })
+            .collect();
+
+        let mut cmap = Cmap::from_mappings(mappings.iter().copied())?;
+
+        let static_metadata = context.ir.static_metadata.get();
+        if !static_metadata.variation_sequences.is_empty() {
+            let default_gids: HashMap<_, _> = mappings
+                .into_iter()
+                .map(|(c, gid)| (c as u32, gid.to_u32() as u16))
+                .collect();
+            let cmap14 = create_cmap14(
+                &static_metadata.variation_sequences,
+                &glyph_order,
+                &default_gids,
+            );
+            // Unicode (0), Unicode Variation Sequences (5) sorts after the other Unicode records
+            let idx = cmap
+                .encoding_records
+                .iter()
+                .position(|r| r.platform_id != PlatformId::Unicode)
+                .unwrap_or(cmap.encoding_records.len());
+            cmap.encoding_records.insert(
+                idx,
+                EncodingRecord::new(
+                    PlatformId::Unicode,
+                    UNICODE_VARIATION_SEQUENCES_ENCODING,
+                    CmapSubtable::Format14(cmap14),
+                ),
+            );
+        }
 
-        let cmap = Cmap::from_mappings(mappings)?;
         context.cmap.set(cmap);
         Ok(())
     }
 }
+
+// <https://learn.microsoft.com/en-us/typography/opentype/spec/cmap#unicode-platform-platform-id--0>
+const UNICODE_VARIATION_SEQUENCES_ENCODING: u16 = 5;
+
+/// Build a [format 14](https://learn.microsoft.com/en-us/typography/opentype/spec/cmap#format-14-unicode-variation-sequences)
+/// subtable.
+///
+/// Sequences that map to the same glyph as their base alone are default, the rest
+/// are non-default.
+fn create_cmap14(
+    variation_sequences: &BTreeMap<u32, BTreeMap<u32, GlyphName>>,
+    glyph_order: &GlyphOrder,
+    default_gids: &HashMap<u32, u16>,
+) -> Cmap14 {
+    let mut length = 10;
+    let mut var_selectors = Vec::new();
+    for (selector, mappings) in variation_sequences.iter() {
+        let mut default_uvs = Vec::new();
+        let mut non_default_uvs = Vec::new();
+        for (base, glyph_name) in mappings.iter() {
+            let Some(gid) = glyph_order.glyph_id(glyph_name) else {
+                warn!(
+                    "Variation sequence U+{base:04X} U+{selector:04X} uses '{glyph_name}', which isn't in the font"
+                );
+                continue;
+            };
+            let gid = gid.to_u16();
+            if default_gids.get(base) == Some(&gid) {
+                default_uvs.push(*base);
+            } else {
+                non_default_uvs.push(UvsMapping::new(Uint24::new(*base), gid));
+            }
+        }
+        if default_uvs.is_empty() && non_default_uvs.is_empty() {
+            continue;
+        }
+
+        // Default UVS are stored as ranges of consecutive codepoints, each at most 256 long
+        let mut ranges: Vec<UnicodeRange> = Vec::new();
+        for base in default_uvs {
+            match ranges.last_mut() {
+                Some(range)
+                    if range.additional_count < u8::MAX
+                        && range.start_unicode_value.to_u32()
+                            + range.additional_count as u32
+                            + 1
+                            == base =>
+                {
+                    range.additional_count += 1;
+                }
+                _ => ranges.push(UnicodeRange::new(Uint24::new(base), 0)),
+            }
+        }
+
+        length += 11;
+        let default_uvs = (!ranges.is_empty()).then(|| {
+            length += 4 + 4 * ranges.len() as u32;
+            DefaultUvs::new(ranges.len() as u32, ranges)
+        });
+        let non_default_uvs = (!non_default_uvs.is_empty()).then(|| {
+            length += 4 + 5 * non_default_uvs.len() as u32;
+            NonDefaultUvs::new(non_default_uvs.len() as u32, non_default_uvs)
+        });
+        var_selectors.push(VariationSelector::new(
+            Uint24::new(*selector),
+            default_uvs,
+            non_default_uvs,
+        ));
+    }
+    Cmap14::new(length, var_selectors.len() as u32, var_selectors)
+}
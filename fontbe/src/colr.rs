@@ -1,11 +1,38 @@
 //! Generates a [COLR](https://learn.microsoft.com/en-us/typography/opentype/spec/colr) table.
 
+use std::collections::{BTreeSet, HashMap};
+
+use fontdrasil::{
+    coords::NormalizedLocation,
+    orchestration::{Access, AccessBuilder, Work},
+    types::{Axis, GlyphName},
+};
+use fontir::{
+    ir::{self, GlyphOrder, PaintGraph, FOREGROUND_PALETTE_INDEX},
+    orchestration::WorkId as FeWorkId,
+    variations::VariationModel,
+};
+use write_fonts::{
+    read::tables::variations::NO_VARIATION_INDEX,
+    tables::{
+        colr::{
+            Affine2x3, BaseGlyph, BaseGlyphList, BaseGlyphPaint, ColorLine, ColorStop, Colr,
+            CompositeMode, Extend, Layer, LayerList, Paint, VarAffine2x3, VarColorLine,
+            VarColorStop,
+        },
+        variations::{ivs_builder::VariationStoreBuilder, DeltaSetIndexMap, VariationRegion},
+    },
+    types::{F2Dot14, FWord, Fixed, GlyphId16, UfWord},
+    OtRound,
+};
+
 use crate::{
-    error::Error,
+    error::{Error, GlyphProblem},
     orchestration::{AnyWorkId, BeWork, Context, WorkId},
 };
-use fontdrasil::orchestration::{Access, Work};
-use fontir::orchestration::WorkId as FeWorkId;
+
+/// The most layers a single PaintColrLayers can reference
+const MAX_PAINT_COLR_LAYER_COUNT: usize = u8::MAX as usize;
 
 #[derive(Debug)]
 struct ColrWork {}
@@ -14,21 +41,1079 @@ pub fn create_colr_work() -> Box<BeWork> {
     Box::new(ColrWork {})
 }
 
+/// The locations a color glyph is defined at, default first.
+struct ColorGlyphSources {
+    glyph_name: GlyphName,
+    locations: Vec<NormalizedLocation>,
+}
+
+/// A color line whose stops have been assigned variation indices, if they vary.
+struct ColorLineStops {
+    extend: Extend,
+    /// (offset, palette index, alpha, var index base)
+    stops: Vec<(F2Dot14, u16, F2Dot14, Option<u32>)>,
+}
+
+impl ColorLineStops {
+    fn is_variable(&self) -> bool {
+        self.stops.iter().any(|(.., var_idx)| var_idx.is_some())
+    }
+
+    fn to_static(&self) -> ColorLine {
+        let stops = self
+            .stops
+            .iter()
+            .map(|(offset, palette_index, alpha, _)| {
+                ColorStop::new(*offset, *palette_index, *alpha)
+            })
+            .collect::<Vec<_>>();
+        ColorLine::new(self.extend, stops.len() as u16, stops)
+    }
+
+    fn to_variable(&self) -> VarColorLine {
+        let stops = self
+            .stops
+            .iter()
+            .map(|(offset, palette_index, alpha, var_idx)| {
+                VarColorStop::new(
+                    *offset,
+                    *palette_index,
+                    *alpha,
+                    var_idx.unwrap_or(NO_VARIATION_INDEX),
+                )
+            })
+            .collect::<Vec<_>>();
+        VarColorLine::new(self.extend, stops.len() as u16, stops)
+    }
+}
+
+/// Helper to build a COLR table from a [PaintGraph].
+///
+/// Color glyphs that are a stack of opaque solid glyphs that do not vary are
+/// written as COLRv0 records, everything else goes into the COLRv1 BaseGlyphList.
+struct ColrBuilder<'a> {
+    glyph_order: &'a GlyphOrder,
+    /// The number of entries in each CPAL palette
+    num_palette_entries: usize,
+    /// Variation axes
+    axes: Vec<Axis>,
+    /// Sparse variation models, keyed by the set of locations they define
+    models: HashMap<BTreeSet<NormalizedLocation>, VariationModel>,
+
+    base_glyph_records: Vec<BaseGlyph>,
+    layer_records: Vec<Layer>,
+
+    base_glyph_paints: Vec<BaseGlyphPaint>,
+    layers: Vec<Paint>,
+    /// Every run of at least two layers in [Self::layers], to the index of the first layer
+    ///
+    /// See <https://github.com/fonttools/fonttools/blob/9a5f9c7/Lib/fontTools/colorLib/builder.py#L392>
+    layer_reuse: HashMap<Vec<Paint>, u32>,
+
+    var_store: VariationStoreBuilder,
+    /// The delta set for each variation index, var index bases point into this
+    var_indices: Vec<u32>,
+    /// Paints that vary in the same way share variation indices
+    var_index_bases: HashMap<Vec<Vec<(VariationRegion, i32)>>, u32>,
+}
+
+impl<'a> ColrBuilder<'a> {
+    fn new(
+        glyph_order: &'a GlyphOrder,
+        num_palette_entries: usize,
+        global_model: VariationModel,
+    ) -> Self {
+        let axes = global_model.axes().cloned().collect::<Vec<_>>();
+        let global_locations = global_model.locations().cloned().collect::<BTreeSet<_>>();
+        let mut models = HashMap::new();
+        models.insert(global_locations, global_model);
+        ColrBuilder {
+            glyph_order,
+            num_palette_entries,
+            var_store: VariationStoreBuilder::new(axes.len() as u16),
+            axes,
+            models,
+            base_glyph_records: Default::default(),
+            layer_records: Default::default(),
+            base_glyph_paints: Default::default(),
+            layers: Default::default(),
+            layer_reuse: Default::default(),
+            var_indices: Default::default(),
+            var_index_bases: Default::default(),
+        }
+    }
+
+    fn glyph_id(&self, glyph_name: &GlyphName) -> Result<GlyphId16, Error> {
+        self.glyph_order
+            .glyph_id(glyph_name)
+            .ok_or_else(|| Error::MissingGlyphId(glyph_name.clone()))
+    }
+
+    fn palette_index(&self, palette_index: u16) -> Result<u16, Error> {
+        if palette_index != FOREGROUND_PALETTE_INDEX
+            && palette_index as usize >= self.num_palette_entries
+        {
+            return Err(Error::OutOfBounds {
+                what: "palette index".to_string(),
+                value: format!("{palette_index} of {}", self.num_palette_entries),
+            });
+        }
+        Ok(palette_index)
+    }
+
+    /// Add a color glyph, must be called in glyph id order
+    fn add_color_glyph(
+        &mut self,
+        glyph_name: &GlyphName,
+        color_glyph: &ir::ColorGlyph,
+    ) -> Result<(), Error> {
+        let gid = self.glyph_id(glyph_name)?;
+
+        let mut sources = color_glyph.sources.iter().collect::<Vec<_>>();
+        sources.sort_by(|(l1, _), (l2, _)| (!l1.is_default(), l1).cmp(&(!l2.is_default(), l2)));
+        if !sources.first().is_some_and(|(loc, _)| loc.is_default()) {
+            return Err(Error::GlyphError(
+                glyph_name.clone(),
+                GlyphProblem::MissingDefault,
+            ));
+        }
+        let paints = sources.iter().map(|(_, p)| *p).collect::<Vec<_>>();
+
+        if paints.iter().all(|p| *p == paints[0]) {
+            if let Some(layers) = v0_layers(paints[0]) {
+                let first_layer_index = self.layer_records.len() as u16;
+                for (glyph_name, palette_index) in layers {
+                    let layer = Layer::new(self.glyph_id(glyph_name)?, palette_index);
+                    self.layer_records.push(layer);
+                }
+                self.base_glyph_records.push(BaseGlyph::new(
+                    gid,
+                    first_layer_index,
+                    self.layer_records.len() as u16 - first_layer_index,
+                ));
+                return Ok(());
+            }
+        }
+
+        let sources = ColorGlyphSources {
+            glyph_name: glyph_name.clone(),
+            locations: sources.into_iter().map(|(loc, _)| loc.clone()).collect(),
+        };
+        let paint = self.paint(&sources, &paints)?;
+        self.base_glyph_paints.push(BaseGlyphPaint::new(gid, paint));
+        Ok(())
+    }
+
+    /// Create the paint for the same node of a paint graph at every location.
+    fn paint(
+        &mut self,
+        sources: &ColorGlyphSources,
+        paints: &[&ir::Paint],
+    ) -> Result<Paint, Error> {
+        let default = paints[0];
+        if paints[1..].iter().any(|p| !same_structure(default, p)) {
+            return Err(Error::GlyphError(
+                sources.glyph_name.clone(),
+                GlyphProblem::InconsistentPaint,
+            ));
+        }
+
+        let mut children = Vec::new();
+        for i in 0..child_paints(default).len() {
+            let at_each_location = paints
+                .iter()
+                .map(|p| child_paints(p)[i])
+                .collect::<Vec<_>>();
+            children.push(self.paint(sources, &at_each_location)?);
+        }
+
+        if let ir::Paint::Layers(..) = default {
+            return Ok(self.colr_layers(children));
+        }
+        let mut children = children.into_iter();
+        let mut child = move || children.next().unwrap();
+
+        let mut values = paints.iter().map(|p| var_fields(p)).collect::<Vec<_>>();
+        // Prefer the uniform scale formats when we can, they store only one scale
+        let uniform_scale =
+            matches!(default, ir::Paint::Scale { .. }) && values.iter().all(|v| v[0] == v[1]);
+        if uniform_scale {
+            values.iter_mut().for_each(|v| {
+                v.remove(1);
+            });
+        }
+        let var_idx = self.var_index_base(sources, &values)?;
+        let v = &values[0];
+
+        let paint = match default {
+            ir::Paint::Layers(..) => unreachable!("Layers are handled above"),
+            ir::Paint::Solid { palette_index, .. } => {
+                let palette_index = self.palette_index(*palette_index)?;
+                match var_idx {
+                    Some(var_idx) => Paint::var_solid(palette_index, f2dot14(v[0]), var_idx),
+                    None => Paint::solid(palette_index, f2dot14(v[0])),
+                }
+            }
+            ir::Paint::LinearGradient { .. } => {
+                let color_line = self.color_line(sources, paints)?;
+                if var_idx.is_some() || color_line.is_variable() {
+                    Paint::var_linear_gradient(
+                        color_line.to_variable(),
+                        fword(v[0]),
+                        fword(v[1]),
+                        fword(v[2]),
+                        fword(v[3]),
+                        fword(v[4]),
+                        fword(v[5]),
+                        var_idx.unwrap_or(NO_VARIATION_INDEX),
+                    )
+                } else {
+                    Paint::linear_gradient(
+                        color_line.to_static(),
+                        fword(v[0]),
+                        fword(v[1]),
+                        fword(v[2]),
+                        fword(v[3]),
+                        fword(v[4]),
+                        fword(v[5]),
+                    )
+                }
+            }
+            ir::Paint::RadialGradient { .. } => {
+                let color_line = self.color_line(sources, paints)?;
+                if var_idx.is_some() || color_line.is_variable() {
+                    Paint::var_radial_gradient(
+                        color_line.to_variable(),
+                        fword(v[0]),
+                        fword(v[1]),
+                        ufword(v[2]),
+                        fword(v[3]),
+                        fword(v[4]),
+                        ufword(v[5]),
+                        var_idx.unwrap_or(NO_VARIATION_INDEX),
+                    )
+                } else {
+                    Paint::radial_gradient(
+                        color_line.to_static(),
+                        fword(v[0]),
+                        fword(v[1]),
+                        ufword(v[2]),
+                        fword(v[3]),
+                        fword(v[4]),
+                        ufword(v[5]),
+                    )
+                }
+            }
+            ir::Paint::SweepGradient { .. } => {
+                let color_line = self.color_line(sources, paints)?;
+                if var_idx.is_some() || color_line.is_variable() {
+                    Paint::var_sweep_gradient(
+                        color_line.to_variable(),
+                        fword(v[0]),
+                        fword(v[1]),
+                        f2dot14(v[2]),
+                        f2dot14(v[3]),
+                        var_idx.unwrap_or(NO_VARIATION_INDEX),
+                    )
+                } else {
+                    Paint::sweep_gradient(
+                        color_line.to_static(),
+                        fword(v[0]),
+                        fword(v[1]),
+                        f2dot14(v[2]),
+                        f2dot14(v[3]),
+                    )
+                }
+            }
+            ir::Paint::Glyph { name, .. } => Paint::glyph(child(), self.glyph_id(name)?),
+            ir::Paint::ColrGlyph(name) => Paint::colr_glyph(self.glyph_id(name)?),
+            ir::Paint::Transform { .. } => {
+                let [xx, yx, xy, yy, dx, dy] = [0, 1, 2, 3, 4, 5].map(|i| fixed(v[i]));
+                match var_idx {
+                    Some(var_idx) => Paint::var_transform(
+                        child(),
+                        VarAffine2x3::new(xx, yx, xy, yy, dx, dy, var_idx),
+                    ),
+                    None => Paint::transform(child(), Affine2x3::new(xx, yx, xy, yy, dx, dy)),
+                }
+            }
+            ir::Paint::Translate { .. } => match var_idx {
+                Some(var_idx) => Paint::var_translate(child(), fword(v[0]), fword(v[1]), var_idx),
+                None => Paint::translate(child(), fword(v[0]), fword(v[1])),
+            },
+            ir::Paint::Scale { center, .. } => match (uniform_scale, center.is_some(), var_idx) {
+                (true, false, None) => Paint::scale_uniform(child(), f2dot14(v[0])),
+                (true, false, Some(var_idx)) => {
+                    Paint::var_scale_uniform(child(), f2dot14(v[0]), var_idx)
+                }
+                (true, true, None) => Paint::scale_uniform_around_center(
+                    child(),
+                    f2dot14(v[0]),
+                    fword(v[1]),
+                    fword(v[2]),
+                ),
+                (true, true, Some(var_idx)) => Paint::var_scale_uniform_around_center(
+                    child(),
+                    f2dot14(v[0]),
+                    fword(v[1]),
+                    fword(v[2]),
+                    var_idx,
+                ),
+                (false, false, None) => Paint::scale(child(), f2dot14(v[0]), f2dot14(v[1])),
+                (false, false, Some(var_idx)) => {
+                    Paint::var_scale(child(), f2dot14(v[0]), f2dot14(v[1]), var_idx)
+                }
+                (false, true, None) => Paint::scale_around_center(
+                    child(),
+                    f2dot14(v[0]),
+                    f2dot14(v[1]),
+                    fword(v[2]),
+                    fword(v[3]),
+                ),
+                (false, true, Some(var_idx)) => Paint::var_scale_around_center(
+                    child(),
+                    f2dot14(v[0]),
+                    f2dot14(v[1]),
+                    fword(v[2]),
+                    fword(v[3]),
+                    var_idx,
+                ),
+            },
+            ir::Paint::Rotate { center, .. } => match (center.is_some(), var_idx) {
+                (false, None) => Paint::rotate(child(), f2dot14(v[0])),
+                (false, Some(var_idx)) => Paint::var_rotate(child(), f2dot14(v[0]), var_idx),
+                (true, None) => {
+                    Paint::rotate_around_center(child(), f2dot14(v[0]), fword(v[1]), fword(v[2]))
+                }
+                (true, Some(var_idx)) => Paint::var_rotate_around_center(
+                    child(),
+                    f2dot14(v[0]),
+                    fword(v[1]),
+                    fword(v[2]),
+                    var_idx,
+                ),
+            },
+            ir::Paint::Skew { center, .. } => match (center.is_some(), var_idx) {
+                (false, None) => Paint::skew(child(), f2dot14(v[0]), f2dot14(v[1])),
+                (false, Some(var_idx)) => {
+                    Paint::var_skew(child(), f2dot14(v[0]), f2dot14(v[1]), var_idx)
+                }
+                (true, None) => Paint::skew_around_center(
+                    child(),
+                    f2dot14(v[0]),
+                    f2dot14(v[1]),
+                    fword(v[2]),
+                    fword(v[3]),
+                ),
+                (true, Some(var_idx)) => Paint::var_skew_around_center(
+                    child(),
+                    f2dot14(v[0]),
+                    f2dot14(v[1]),
+                    fword(v[2]),
+                    fword(v[3]),
+                    var_idx,
+                ),
+            },
+            ir::Paint::Composite { mode, .. } => {
+                let source = child();
+                let backdrop = child();
+                Paint::composite(source, composite_mode(*mode), backdrop)
+            }
+        };
+        Ok(paint)
+    }
+
+    /// Assign variation indices to the stops of the color line of a gradient at every location
+    fn color_line(
+        &mut self,
+        sources: &ColorGlyphSources,
+        paints: &[&ir::Paint],
+    ) -> Result<ColorLineStops, Error> {
+        let color_lines = paints
+            .iter()
+            .map(|p| match p {
+                ir::Paint::LinearGradient { color_line, .. }
+                | ir::Paint::RadialGradient { color_line, .. }
+                | ir::Paint::SweepGradient { color_line, .. } => color_line,
+                _ => unreachable!("Only gradients have color lines"),
+            })
+            .collect::<Vec<_>>();
+
+        let mut stops = Vec::with_capacity(color_lines[0].stops.len());
+        for (i, stop) in color_lines[0].stops.iter().enumerate() {
+            let values = color_lines
+                .iter()
+                .map(|cl| {
+                    let stop = &cl.stops[i];
+                    vec![to_f2dot14(stop.offset), to_f2dot14(stop.alpha)]
+                })
+                .collect::<Vec<_>>();
+            let var_idx = self.var_index_base(sources, &values)?;
+            stops.push((
+                f2dot14(values[0][0]),
+                self.palette_index(stop.palette_index)?,
+                f2dot14(values[0][1]),
+                var_idx,
+            ));
+        }
+        Ok(ColorLineStops {
+            extend: extend(color_lines[0].extend),
+            stops,
+        })
+    }
+
+    /// Add variation deltas for a run of values, if they vary, returning the
+    /// variation index of the first value.
+    ///
+    /// values\[i\] are the values at sources.locations\[i\], encoded as they will be written.
+    fn var_index_base(
+        &mut self,
+        sources: &ColorGlyphSources,
+        values: &[Vec<i32>],
+    ) -> Result<Option<u32>, Error> {
+        if values.iter().all(|v| *v == values[0]) {
+            return Ok(None);
+        }
+        let locations = sources.locations.iter().cloned().collect::<BTreeSet<_>>();
+        if !self.models.contains_key(&locations) {
+            // this glyph defines its own set of locations, a new sparse model is needed
+            let model = VariationModel::new(locations.iter().cloned().collect(), self.axes.clone())
+                .map_err(|e| Error::VariationModelError(sources.glyph_name.clone(), e))?;
+            self.models.insert(locations.clone(), model);
+        }
+        let model = self.models.get(&locations).unwrap();
+
+        let mut all_deltas = Vec::with_capacity(values[0].len());
+        for field in 0..values[0].len() {
+            let point_seqs = sources
+                .locations
+                .iter()
+                .zip(values)
+                .map(|(loc, v)| (loc.clone(), vec![v[field] as f64]))
+                .collect::<HashMap<_, _>>();
+            let deltas = model
+                .deltas(&point_seqs)
+                .map_err(|e| Error::GlyphDeltaError(sources.glyph_name.clone(), e))?
+                .into_iter()
+                .filter_map(|(region, values)| {
+                    if region.is_default() {
+                        return None;
+                    }
+                    // Only 1 value per region for our input
+                    assert!(values.len() == 1, "{} values?!", values.len());
+                    Some((
+                        region.to_write_fonts_variation_region(&self.axes),
+                        // Fixed deltas don't fit in i16 so round to an f64 first
+                        OtRound::<f64>::ot_round(values[0]) as i32,
+                    ))
+                })
+                .collect::<Vec<_>>();
+            all_deltas.push(deltas);
+        }
+
+        if let Some(var_idx) = self.var_index_bases.get(&all_deltas) {
+            return Ok(Some(*var_idx));
+        }
+        let var_idx = self.var_indices.len() as u32;
+        for deltas in all_deltas.iter() {
+            let temp_id = self.var_store.add_deltas(deltas.clone());
+            self.var_indices.push(temp_id);
+        }
+        self.var_index_bases.insert(all_deltas, var_idx);
+        Ok(Some(var_idx))
+    }
+
+    /// Add layers to the LayerList, reusing layers already present where possible.
+    ///
+    /// Rust version of <https://github.com/fonttools/fonttools/blob/9a5f9c7/Lib/fontTools/colorLib/builder.py#L365-L406>
+    fn colr_layers(&mut self, mut layers: Vec<Paint>) -> Paint {
+        // Replace runs of layers we've seen before with a reference to them, longest first
+        'reuse: loop {
+            for len in (2..=layers.len().min(MAX_PAINT_COLR_LAYER_COUNT)).rev() {
+                for lbound in (0..=layers.len() - len).rev() {
+                    let ubound = lbound + len;
+                    let Some(first_layer_index) = self.layer_reuse.get(&layers[lbound..ubound])
+                    else {
+                        continue;
+                    };
+                    let reused = Paint::colr_layers(len as u8, *first_layer_index);
+                    layers.splice(lbound..ubound, [reused]);
+                    continue 'reuse;
+                }
+            }
+            break;
+        }
+        if layers.len() == 1 {
+            return layers.pop().unwrap();
+        }
+
+        // Too many layers for one PaintColrLayers, nest them
+        while layers.len() > MAX_PAINT_COLR_LAYER_COUNT {
+            let chunks = layers
+                .chunks(MAX_PAINT_COLR_LAYER_COUNT)
+                .map(|c| c.to_vec())
+                .collect::<Vec<_>>();
+            layers = chunks.into_iter().map(|c| self.colr_layers(c)).collect();
+        }
+
+        let first_layer_index = self.layers.len() as u32;
+        for lbound in 0..layers.len() {
+            for ubound in lbound + 2..=layers.len() {
+                self.layer_reuse
+                    .entry(layers[lbound..ubound].to_vec())
+                    .or_insert(first_layer_index + lbound as u32);
+            }
+        }
+        let num_layers = layers.len() as u8;
+        self.layers.extend(layers);
+        Paint::colr_layers(num_layers, first_layer_index)
+    }
+
+    fn build(self) -> Colr {
+        let mut colr = Colr::new(
+            self.base_glyph_records.len() as u16,
+            (!self.base_glyph_records.is_empty()).then_some(self.base_glyph_records),
+            (!self.layer_records.is_empty()).then_some(self.layer_records.clone()),
+            self.layer_records.len() as u16,
+        );
+        if self.base_glyph_paints.is_empty() {
+            return colr;
+        }
+
+        colr.base_glyph_list = Some(BaseGlyphList::new(
+            self.base_glyph_paints.len() as u32,
+            self.base_glyph_paints,
+        ))
+        .into();
+        // write-fonts only considers the LayerList, ClipList and variation data when
+        // deciding if the table is v1 so always write a LayerList, even if empty
+        colr.layer_list = Some(LayerList::new(self.layers.len() as u32, self.layers)).into();
+        if !self.var_indices.is_empty() {
+            let (var_store, remap) = self.var_store.build();
+            let var_indices = self
+                .var_indices
+                .into_iter()
+                .map(|temp_id| u32::from(remap.get(temp_id).unwrap()))
+                .collect::<Vec<_>>();
+            // If every index maps to itself we can skip the map
+            if var_indices
+                .iter()
+                .enumerate()
+                .any(|(i, var_idx)| i as u32 != *var_idx)
+            {
+                colr.var_index_map =
+                    Some(var_indices.into_iter().collect::<DeltaSetIndexMap>()).into();
+            }
+            colr.item_variation_store = Some(var_store).into();
+        }
+        colr
+    }
+}
+
+/// If a paint can be expressed as COLRv0 layers return (glyph, palette index) for each layer.
+///
+/// That is, if it's a stack of glyphs filled with opaque solid colors.
+/// See <https://github.com/fonttools/fonttools/blob/9a5f9c7/Lib/fontTools/colorLib/builder.py#L178-L188>
+fn v0_layers(paint: &ir::Paint) -> Option<Vec<(&GlyphName, u16)>> {
+    fn v0_layer(paint: &ir::Paint) -> Option<(&GlyphName, u16)> {
+        match paint {
+            ir::Paint::Glyph { name, paint } => match paint.as_ref() {
+                ir::Paint::Solid {
+                    palette_index,
+                    alpha,
+                } if *alpha == 1.0 => Some((name, *palette_index)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+    match paint {
+        ir::Paint::Layers(layers) => layers.iter().map(v0_layer).collect(),
+        ir::Paint::Glyph { .. } => v0_layer(paint).map(|layer| vec![layer]),
+        _ => None,
+    }
+}
+
+/// The paints nested directly within a paint
+fn child_paints(paint: &ir::Paint) -> Vec<&ir::Paint> {
+    match paint {
+        ir::Paint::Layers(layers) => layers.iter().collect(),
+        ir::Paint::Solid { .. }
+        | ir::Paint::LinearGradient { .. }
+        | ir::Paint::RadialGradient { .. }
+        | ir::Paint::SweepGradient { .. }
+        | ir::Paint::ColrGlyph(..) => Vec::new(),
+        ir::Paint::Glyph { paint, .. }
+        | ir::Paint::Transform { paint, .. }
+        | ir::Paint::Translate { paint, .. }
+        | ir::Paint::Scale { paint, .. }
+        | ir::Paint::Rotate { paint, .. }
+        | ir::Paint::Skew { paint, .. } => vec![paint.as_ref()],
+        ir::Paint::Composite {
+            source, backdrop, ..
+        } => vec![source.as_ref(), backdrop.as_ref()],
+    }
+}
+
+/// Whether two paints differ only in values that can vary, ignoring child paints.
+fn same_structure(p1: &ir::Paint, p2: &ir::Paint) -> bool {
+    let same_color_lines = |c1: &ir::ColorLine, c2: &ir::ColorLine| {
+        c1.extend == c2.extend
+            && c1.stops.len() == c2.stops.len()
+            && c1
+                .stops
+                .iter()
+                .zip(c2.stops.iter())
+                .all(|(s1, s2)| s1.palette_index == s2.palette_index)
+    };
+    match (p1, p2) {
+        (ir::Paint::Layers(l1), ir::Paint::Layers(l2)) => l1.len() == l2.len(),
+        (
+            ir::Paint::Solid {
+                palette_index: i1, ..
+            },
+            ir::Paint::Solid {
+                palette_index: i2, ..
+            },
+        ) => i1 == i2,
+        (
+            ir::Paint::LinearGradient { color_line: c1, .. },
+            ir::Paint::LinearGradient { color_line: c2, .. },
+        )
+        | (
+            ir::Paint::RadialGradient { color_line: c1, .. },
+            ir::Paint::RadialGradient { color_line: c2, .. },
+        )
+        | (
+            ir::Paint::SweepGradient { color_line: c1, .. },
+            ir::Paint::SweepGradient { color_line: c2, .. },
+        ) => same_color_lines(c1, c2),
+        (ir::Paint::Glyph { name: n1, .. }, ir::Paint::Glyph { name: n2, .. })
+        | (ir::Paint::ColrGlyph(n1), ir::Paint::ColrGlyph(n2)) => n1 == n2,
+        (ir::Paint::Transform { .. }, ir::Paint::Transform { .. })
+        | (ir::Paint::Translate { .. }, ir::Paint::Translate { .. }) => true,
+        (ir::Paint::Scale { center: c1, .. }, ir::Paint::Scale { center: c2, .. })
+        | (ir::Paint::Rotate { center: c1, .. }, ir::Paint::Rotate { center: c2, .. })
+        | (ir::Paint::Skew { center: c1, .. }, ir::Paint::Skew { center: c2, .. }) => {
+            c1.is_some() == c2.is_some()
+        }
+        (ir::Paint::Composite { mode: m1, .. }, ir::Paint::Composite { mode: m2, .. }) => m1 == m2,
+        _ => false,
+    }
+}
+
+/// The values of a paint that can vary, encoded as they will be written and
+/// in the order they occur in the variable form of the paint.
+///
+/// Deltas are computed on the encoded values, as fontTools does.
+fn var_fields(paint: &ir::Paint) -> Vec<i32> {
+    match paint {
+        ir::Paint::Layers(..) | ir::Paint::Glyph { .. } | ir::Paint::ColrGlyph(..) => Vec::new(),
+        ir::Paint::Composite { .. } => Vec::new(),
+        ir::Paint::Solid { alpha, .. } => vec![to_f2dot14(*alpha)],
+        ir::Paint::LinearGradient { p0, p1, p2, .. } => [p0.x, p0.y, p1.x, p1.y, p2.x, p2.y]
+            .into_iter()
+            .map(to_fword)
+            .collect(),
+        ir::Paint::RadialGradient { c0, r0, c1, r1, .. } => vec![
+            to_fword(c0.x),
+            to_fword(c0.y),
+            to_ufword(*r0),
+            to_fword(c1.x),
+            to_fword(c1.y),
+            to_ufword(*r1),
+        ],
+        ir::Paint::SweepGradient {
+            center,
+            start_angle,
+            end_angle,
+            ..
+        } => vec![
+            to_fword(center.x),
+            to_fword(center.y),
+            to_angle(*start_angle),
+            to_angle(*end_angle),
+        ],
+        ir::Paint::Transform { transform, .. } => {
+            transform.as_coeffs().into_iter().map(to_fixed).collect()
+        }
+        ir::Paint::Translate { dx, dy, .. } => vec![to_fword(*dx), to_fword(*dy)],
+        ir::Paint::Scale {
+            scale_x,
+            scale_y,
+            center,
+            ..
+        } => [to_f2dot14(*scale_x), to_f2dot14(*scale_y)]
+            .into_iter()
+            .chain(center_fields(center))
+            .collect(),
+        ir::Paint::Rotate { angle, center, .. } => [to_angle(*angle)]
+            .into_iter()
+            .chain(center_fields(center))
+            .collect(),
+        ir::Paint::Skew {
+            x_skew_angle,
+            y_skew_angle,
+            center,
+            ..
+        } => [to_angle(*x_skew_angle), to_angle(*y_skew_angle)]
+            .into_iter()
+            .chain(center_fields(center))
+            .collect(),
+    }
+}
+
+fn center_fields(center: &Option<kurbo::Point>) -> impl Iterator<Item = i32> {
+    center
+        .iter()
+        .flat_map(|c| [to_fword(c.x), to_fword(c.y)])
+        .collect::<Vec<_>>()
+        .into_iter()
+}
+
+fn to_fword(v: f64) -> i32 {
+    OtRound::<i16>::ot_round(v) as i32
+}
+
+fn to_ufword(v: f64) -> i32 {
+    OtRound::<u16>::ot_round(v) as i32
+}
+
+fn to_f2dot14(v: f64) -> i32 {
+    F2Dot14::from_f32(v as f32).to_bits() as i32
+}
+
+/// Angles are stored as multiples of 180 degrees
+fn to_angle(degrees: f64) -> i32 {
+    to_f2dot14(degrees / 180.0)
+}
+
+fn to_fixed(v: f64) -> i32 {
+    Fixed::from_f64(v).to_bits()
+}
+
+fn fword(v: i32) -> FWord {
+    FWord::new(v as i16)
+}
+
+fn ufword(v: i32) -> UfWord {
+    UfWord::new(v as u16)
+}
+
+fn f2dot14(v: i32) -> F2Dot14 {
+    F2Dot14::from_bits(v as i16)
+}
+
+fn fixed(v: i32) -> Fixed {
+    Fixed::from_bits(v)
+}
+
+fn extend(extend: ir::ExtendMode) -> Extend {
+    match extend {
+        ir::ExtendMode::Pad => Extend::Pad,
+        ir::ExtendMode::Repeat => Extend::Repeat,
+        ir::ExtendMode::Reflect => Extend::Reflect,
+    }
+}
+
+fn composite_mode(mode: ir::CompositeMode) -> CompositeMode {
+    match mode {
+        ir::CompositeMode::Clear => CompositeMode::Clear,
+        ir::CompositeMode::Src => CompositeMode::Src,
+        ir::CompositeMode::Dest => CompositeMode::Dest,
+        ir::CompositeMode::SrcOver => CompositeMode::SrcOver,
+        ir::CompositeMode::DestOver => CompositeMode::DestOver,
+        ir::CompositeMode::SrcIn => CompositeMode::SrcIn,
+        ir::CompositeMode::DestIn => CompositeMode::DestIn,
+        ir::CompositeMode::SrcOut => CompositeMode::SrcOut,
+        ir::CompositeMode::DestOut => CompositeMode::DestOut,
+        ir::CompositeMode::SrcAtop => CompositeMode::SrcAtop,
+        ir::CompositeMode::DestAtop => CompositeMode::DestAtop,
+        ir::CompositeMode::Xor => CompositeMode::Xor,
+        ir::CompositeMode::Plus => CompositeMode::Plus,
+        ir::CompositeMode::Screen => CompositeMode::Screen,
+        ir::CompositeMode::Overlay => CompositeMode::Overlay,
+        ir::CompositeMode::Darken => CompositeMode::Darken,
+        ir::CompositeMode::Lighten => CompositeMode::Lighten,
+        ir::CompositeMode::ColorDodge => CompositeMode::ColorDodge,
+        ir::CompositeMode::ColorBurn => CompositeMode::ColorBurn,
+        ir::CompositeMode::HardLight => CompositeMode::HardLight,
+        ir::CompositeMode::SoftLight => CompositeMode::SoftLight,
+        ir::CompositeMode::Difference => CompositeMode::Difference,
+        ir::CompositeMode::Exclusion => CompositeMode::Exclusion,
+        ir::CompositeMode::Multiply => CompositeMode::Multiply,
+        ir::CompositeMode::HslHue => CompositeMode::HslHue,
+        ir::CompositeMode::HslSaturation => CompositeMode::HslSaturation,
+        ir::CompositeMode::HslColor => CompositeMode::HslColor,
+        ir::CompositeMode::HslLuminosity => CompositeMode::HslLuminosity,
+    }
+}
+
 impl Work<Context, AnyWorkId, Error> for ColrWork {
     fn id(&self) -> AnyWorkId {
         WorkId::Colr.into()
     }
 
     fn read_access(&self) -> Access<AnyWorkId> {
-        Access::Variant(FeWorkId::ColorPalettes.into())
+        AccessBuilder::new()
+            .variant(FeWorkId::StaticMetadata)
+            .variant(FeWorkId::GlyphOrder)
+            .variant(FeWorkId::ColorPalettes)
+            .variant(FeWorkId::PaintGraph)
+            .build()
     }
 
     /// Generate [COLR](https://learn.microsoft.com/en-us/typography/opentype/spec/colr)
     fn exec(&self, context: &Context) -> Result<(), Error> {
-        // Temporary: if there is a palette generate an empty COLR, just so we can easily see a ttx_diff
-        if context.ir.colors.try_get().is_some() {
-            context.colr.set(Default::default());
-        }
+        let Some(paint_graph) = context.ir.paint_graph.try_get() else {
+            return Ok(());
+        };
+        let static_metadata = context.ir.static_metadata.get();
+        let glyph_order = context.ir.glyph_order.get();
+        let num_palette_entries = context
+            .ir
+            .colors
+            .try_get()
+            .map(|c| c.palettes[0].len())
+            .unwrap_or_default();
+
+        let colr = build_colr(
+            &paint_graph,
+            &glyph_order,
+            num_palette_entries,
+            static_metadata.variation_model.clone(),
+        )?;
+        context.colr.set(colr);
         Ok(())
     }
 }
+
+fn build_colr(
+    paint_graph: &PaintGraph,
+    glyph_order: &GlyphOrder,
+    num_palette_entries: usize,
+    global_model: VariationModel,
+) -> Result<Colr, Error> {
+    // Records are sorted by glyph id
+    let mut color_glyphs = paint_graph
+        .base_glyphs
+        .iter()
+        .map(|(name, color_glyph)| {
+            glyph_order
+                .glyph_id(name)
+                .map(|gid| (gid, name, color_glyph))
+                .ok_or_else(|| Error::MissingGlyphId(name.clone()))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    color_glyphs.sort_by_key(|(gid, ..)| *gid);
+
+    let mut builder = ColrBuilder::new(glyph_order, num_palette_entries, global_model);
+    for (_, name, color_glyph) in color_glyphs {
+        builder.add_color_glyph(name, color_glyph)?;
+    }
+    Ok(builder.build())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, str::FromStr};
+
+    use fontdrasil::coords::{CoordConverter, UserCoord};
+    use fontir::ir::{ColorGlyph, ColorLine, ColorStop, ExtendMode};
+    use write_fonts::types::Tag;
+
+    use super::*;
+
+    fn wght_axis() -> Axis {
+        let min = UserCoord::new(400.0);
+        let max = UserCoord::new(700.0);
+        Axis {
+            name: "Weight".to_string(),
+            tag: Tag::from_str("wght").unwrap(),
+            min,
+            default: min,
+            max,
+            hidden: false,
+            converter: CoordConverter::unmapped(min, min, max),
+        }
+    }
+
+    fn regular() -> NormalizedLocation {
+        NormalizedLocation::for_pos(&[("wght", 0.0)])
+    }
+
+    fn bold() -> NormalizedLocation {
+        NormalizedLocation::for_pos(&[("wght", 1.0)])
+    }
+
+    fn solid_glyph(name: &str, palette_index: u16, alpha: f64) -> ir::Paint {
+        ir::Paint::Glyph {
+            name: name.into(),
+            paint: Box::new(ir::Paint::Solid {
+                palette_index,
+                alpha,
+            }),
+        }
+    }
+
+    fn compile(color_glyphs: Vec<(&str, ColorGlyph)>) -> Colr {
+        let glyph_order = ["notdef", "A", "B", "C", "D"]
+            .into_iter()
+            .map(GlyphName::from)
+            .collect::<GlyphOrder>();
+        let paint_graph = PaintGraph {
+            base_glyphs: color_glyphs
+                .into_iter()
+                .map(|(name, cg)| (name.into(), cg))
+                .collect(),
+        };
+        let model =
+            VariationModel::new(HashSet::from([regular(), bold()]), vec![wght_axis()]).unwrap();
+        build_colr(&paint_graph, &glyph_order, 2, model).unwrap()
+    }
+
+    fn base_glyph_paints(colr: &Colr) -> Vec<&Paint> {
+        colr.base_glyph_list
+            .as_ref()
+            .unwrap()
+            .base_glyph_paint_records
+            .iter()
+            .map(|r| r.paint.as_ref())
+            .collect()
+    }
+
+    #[test]
+    fn opaque_solid_layers_are_v0() {
+        let colr = compile(vec![(
+            "A",
+            ColorGlyph::new(
+                regular(),
+                ir::Paint::Layers(vec![solid_glyph("B", 0, 1.0), solid_glyph("C", 1, 1.0)]),
+            ),
+        )]);
+        assert!(colr.base_glyph_list.is_none());
+        assert_eq!(
+            Some(&vec![BaseGlyph::new(GlyphId16::new(1), 0, 2)]),
+            colr.base_glyph_records.as_ref()
+        );
+        assert_eq!(
+            Some(&vec![
+                Layer::new(GlyphId16::new(2), 0),
+                Layer::new(GlyphId16::new(3), 1)
+            ]),
+            colr.layer_records.as_ref()
+        );
+    }
+
+    #[test]
+    fn translucent_layers_are_v1() {
+        let colr = compile(vec![(
+            "A",
+            ColorGlyph::new(
+                regular(),
+                ir::Paint::Layers(vec![solid_glyph("B", 0, 0.5), solid_glyph("C", 1, 1.0)]),
+            ),
+        )]);
+        assert!(colr.base_glyph_records.is_none());
+        assert_eq!(vec![&Paint::colr_layers(2, 0)], base_glyph_paints(&colr));
+    }
+
+    #[test]
+    fn reuses_layers() {
+        let layers = vec![
+            solid_glyph("B", 0, 0.5),
+            solid_glyph("C", 1, 0.5),
+            solid_glyph("D", 0, 0.5),
+        ];
+        let colr = compile(vec![
+            (
+                "A",
+                ColorGlyph::new(regular(), ir::Paint::Layers(layers.clone())),
+            ),
+            (
+                "B",
+                ColorGlyph::new(regular(), ir::Paint::Layers(layers[1..].to_vec())),
+            ),
+            (
+                "C",
+                ColorGlyph::new(
+                    regular(),
+                    ir::Paint::Layers(
+                        layers
+                            .iter()
+                            .cloned()
+                            .chain([solid_glyph("A", 1, 0.5)])
+                            .collect(),
+                    ),
+                ),
+            ),
+        ]);
+
+        // A creates layers, B reuses a slice of them, C nests all of them
+        assert_eq!(5, colr.layer_list.as_ref().unwrap().paints.len());
+        assert_eq!(
+            vec![
+                &Paint::colr_layers(3, 0),
+                &Paint::colr_layers(2, 1),
+                &Paint::colr_layers(2, 3),
+            ],
+            base_glyph_paints(&colr)
+        );
+        assert_eq!(
+            Paint::colr_layers(3, 0),
+            *colr.layer_list.as_ref().unwrap().paints[3]
+        );
+    }
+
+    #[test]
+    fn variable_gradient() {
+        let linear_gradient = |x1: f64, alpha: f64| ir::Paint::Glyph {
+            name: "A".into(),
+            paint: Box::new(ir::Paint::LinearGradient {
+                color_line: ColorLine {
+                    extend: ExtendMode::Pad,
+                    stops: vec![
+                        ColorStop {
+                            offset: 0.0,
+                            palette_index: 0,
+                            alpha: 1.0,
+                        },
+                        ColorStop {
+                            offset: 1.0,
+                            palette_index: 1,
+                            alpha,
+                        },
+                    ],
+                },
+                p0: kurbo::Point::new(0.0, 0.0),
+                p1: kurbo::Point::new(x1, 0.0),
+                p2: kurbo::Point::new(0.0, 100.0),
+            }),
+        };
+        let colr = compile(vec![(
+            "A",
+            ColorGlyph {
+                sources: HashMap::from([
+                    (regular(), linear_gradient(100.0, 1.0)),
+                    (bold(), linear_gradient(200.0, 0.5)),
+                ]),
+            },
+        )]);
+
+        let [Paint::Glyph(glyph)] = base_glyph_paints(&colr).as_slice() else {
+            panic!("Expected a single PaintGlyph");
+        };
+        let Paint::VarLinearGradient(gradient) = glyph.paint.as_ref() else {
+            panic!("Expected a variable gradient, got {:?}", glyph.paint);
+        };
+        assert_eq!(FWord::new(100), gradient.x1);
+        let stops = &gradient.color_line.color_stops;
+        assert_eq!(
+            vec![NO_VARIATION_INDEX, 6],
+            stops.iter().map(|s| s.var_index_base).collect::<Vec<_>>()
+        );
+        // The gradient takes indices 0..6, the second stop the next 2
+        assert_eq!(0, gradient.var_index_base);
+        assert!(colr.item_variation_store.is_some());
+    }
+}
@@ -85,18 +85,28 @@ pub enum Error {
         new_class: SmolStr,
         glyph: GlyphName,
     },
+    #[error("Variation model error for axis mappings: {0}")]
+    AxisMappingModelError(VariationModelError),
     #[error("No variation model for '{0:?}'")]
     NoVariationModel(NormalizedLocation),
     #[error("Delta error '{0:?}'")]
     DeltaError(DeltaError),
     #[error("No glyph id for '{0}'")]
     MissingGlyphId(GlyphName),
+    #[error("Script '{0}' has no default language system to add feature variations to")]
+    NoDefaultLangSys(Tag),
     #[error("Error making CMap: {0}")]
     CmapConflict(#[from] CmapConflict),
     #[error("Progress stalled computing composite bbox: {0:?}")]
     CompositesStalled(Vec<GlyphName>),
     #[error("Inconsistent palette lengths observed: {0:?}")]
     InconsistentPaletteLength(Vec<usize>),
+    #[error("Checksum mismatch for '{tag}', the table directory says {expected:#010x} but the data sums to {actual:#010x}")]
+    ChecksumMismatch {
+        tag: Tag,
+        expected: u32,
+        actual: u32,
+    },
 }
 
 #[derive(Debug)]
@@ -107,6 +117,7 @@ pub enum GlyphProblem {
     MissingDefault,
     NoComponents,
     NotInGlyphOrder,
+    InconsistentPaint,
 }
 
 impl Display for GlyphProblem {
@@ -120,6 +131,9 @@ impl Display for GlyphProblem {
             GlyphProblem::MissingDefault => "has no default master",
             GlyphProblem::NoComponents => "has no components",
             GlyphProblem::NotInGlyphOrder => "has no entry in glyph order",
+            GlyphProblem::InconsistentPaint => {
+                "has different paints at different points in designspace"
+            }
         };
         f.write_str(message)
     }
@@ -50,6 +50,7 @@ use crate::{
     },
 };
 
+mod feature_variations;
 mod kern;
 mod marks;
 mod ot_tags;
@@ -575,6 +576,7 @@ impl Work<Context, AnyWorkId, Error> for FeatureCompilationWork {
     fn read_access(&self) -> Access<AnyWorkId> {
         AccessBuilder::new()
             .variant(FeWorkId::GlyphOrder)
+            .variant(FeWorkId::FeatureVariations)
             .variant(WorkId::FeaturesAst)
             .variant(WorkId::GatherBeKerning)
             .variant(WorkId::Marks)
@@ -614,6 +616,15 @@ impl Work<Context, AnyWorkId, Error> for FeatureCompilationWork {
             gdef.glyph_class_def.set(class_def);
         }
 
+        if let Some(feature_variations) = context.ir.feature_variations.try_get() {
+            feature_variations::add_feature_variations(
+                &mut result.gsub,
+                &feature_variations,
+                &static_metadata,
+                &glyph_order,
+            )?;
+        }
+
         debug!(
             "Built features, gpos? {} gsub? {} gdef? {}",
             result.gpos.is_some(),
@@ -648,7 +659,7 @@ impl Work<Context, AnyWorkId, Error> for FeatureCompilationWork {
         // so we can merge later on
         if result.has_non_layout_tables() {
             let extras = ExtraFeaTables::from(result);
-            // we're currently only handling 'name'; if other tables are in
+            // we're currently only handling 'name' and 'MVAR'; if other tables are in
             // here we probably need to do something with them too, so let's warn
             extras.log_unhandled_extras();
             context.extra_fea_tables.set(extras);
@@ -754,9 +765,10 @@ mod tests {
     use fea_rs::compile::VariationInfo;
     use fontdrasil::{
         coords::{CoordConverter, DesignCoord, NormalizedCoord, UserCoord},
-        types::Axis,
+        types::{Axis, GlyphName},
     };
     use fontir::ir::StaticMetadata;
+    use write_fonts::read::{tables::mvar::Mvar, FontRead};
 
     use super::*;
 
@@ -836,4 +848,88 @@ mod tests {
         let region_values: Vec<_> = regions.into_iter().map(|(_, v)| v + default).collect();
         assert_eq!((15, vec![10, 20]), (default, region_values));
     }
+
+    #[test]
+    fn variable_table_metrics_go_to_mvar() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let static_metadata = weight_variable_static_metadata(300.0, 400.0, 700.0);
+        let var_info = FeaVariationInfo::new(&static_metadata);
+        let (tree, _) = fea_rs::parse::parse_string(
+            r#"
+            table hhea {
+                Ascender (wght=300:780 wght=400:800 wght=700:850);
+            } hhea;
+
+            table OS/2 {
+                TypoAscender (wght=300:780 wght=400:800 wght=700:850);
+                XHeight 500;
+            } OS/2;
+            "#,
+        );
+        let glyph_map: GlyphMap = [GlyphName::new(".notdef")].into_iter().collect();
+        let compilation = match fea_rs::compile::compile::<_, NopFeatureProvider>(
+            &tree,
+            &glyph_map,
+            Some(&var_info),
+            None,
+            Opts::new(),
+        ) {
+            Ok((compilation, _)) => compilation,
+            Err(errs) => panic!("{}", errs.display()),
+        };
+
+        assert_eq!(800, compilation.hhea.unwrap().ascender.to_i16());
+        let os2 = compilation.os2.unwrap();
+        assert_eq!((800, Some(500)), (os2.s_typo_ascender, os2.sx_height));
+        // hhea and OS/2 ascender share the same deltas
+        let mvar = compilation.mvar.unwrap();
+        assert_eq!(
+            vec![Tag::new(b"hasc")],
+            mvar.value_records
+                .iter()
+                .map(|rec| rec.value_tag)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn last_variation_of_shared_mvar_tag_wins() {
+        let static_metadata = weight_variable_static_metadata(300.0, 400.0, 700.0);
+        let var_info = FeaVariationInfo::new(&static_metadata);
+        let (tree, _) = fea_rs::parse::parse_string(
+            r#"
+            table hhea {
+                Ascender (wght=300:780 wght=400:800 wght=700:850);
+            } hhea;
+
+            table OS/2 {
+                TypoAscender (wght=300:790 wght=400:800 wght=700:900);
+            } OS/2;
+            "#,
+        );
+        let glyph_map: GlyphMap = [GlyphName::new(".notdef")].into_iter().collect();
+        let compilation = match fea_rs::compile::compile::<_, NopFeatureProvider>(
+            &tree,
+            &glyph_map,
+            Some(&var_info),
+            None,
+            Opts::new(),
+        ) {
+            Ok((compilation, _)) => compilation,
+            Err(errs) => panic!("{}", errs.display()),
+        };
+
+        let bytes = write_fonts::dump_table(&compilation.mvar.unwrap()).unwrap();
+        let mvar = Mvar::read(bytes.as_slice().into()).unwrap();
+        let varstore = mvar.item_variation_store().unwrap().unwrap();
+        let record = &mvar.value_records()[0];
+        let var_data = varstore
+            .item_variation_data()
+            .get(record.delta_set_outer_index() as usize)
+            .unwrap()
+            .unwrap();
+        let mut deltas: Vec<_> = var_data.delta_set(record.delta_set_inner_index()).collect();
+        deltas.sort();
+        assert_eq!(vec![-10, 100], deltas);
+    }
 }
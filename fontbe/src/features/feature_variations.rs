@@ -0,0 +1,788 @@
+//! Compile [FeatureVariations] into GSUB.
+//!
+//! A port of fontTools [featureVars](https://github.com/fonttools/fonttools/blob/main/Lib/fontTools/varLib/featureVars.py)
+//! so that where rules overlap we produce the same regions, lookups and records as fontmake.
+
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BTreeSet, HashMap},
+};
+
+use fontdrasil::types::GlyphName;
+use fontir::ir::{ConditionalSubstitution, FeatureVariations, GlyphOrder, StaticMetadata};
+use indexmap::{map::Entry, IndexMap};
+use log::debug;
+use ordered_float::OrderedFloat;
+use write_fonts::{
+    tables::{
+        gsub::{ExtensionSubtable, Gsub, SingleSubst, SubstitutionLookup},
+        layout::{
+            ChainedSequenceContext, ConditionFormat1, ConditionSet, Feature, FeatureList,
+            FeatureRecord, FeatureTableSubstitution, FeatureTableSubstitutionRecord,
+            FeatureVariationRecord, LangSys, Lookup, LookupFlag, Script, ScriptList, ScriptRecord,
+            SequenceContext, SequenceLookupRecord,
+        },
+    },
+    types::{F2Dot14, GlyphId16, Tag},
+};
+
+use crate::error::Error;
+
+const DFLT_SCRIPT: Tag = Tag::new(b"DFLT");
+
+type Substitutions = BTreeMap<GlyphName, GlyphName>;
+
+/// A box in normalized space, axes not present are unconstrained.
+type Region = BTreeMap<Tag, (OrderedFloat<f64>, OrderedFloat<f64>)>;
+
+/// The indices of the rules that contribute to a region
+type Rank = BTreeSet<usize>;
+
+/// Add the substitutions of feature_variations to gsub, creating gsub if necessary.
+pub(crate) fn add_feature_variations(
+    gsub: &mut Option<Gsub>,
+    feature_variations: &FeatureVariations,
+    static_metadata: &StaticMetadata,
+    glyph_order: &GlyphOrder,
+) -> Result<(), Error> {
+    let axis_indices: HashMap<_, _> = static_metadata
+        .axes
+        .iter()
+        .enumerate()
+        .map(|(i, axis)| (axis.tag, i as u16))
+        .collect();
+    let conditional_substitutions = feature_variations
+        .rules
+        .iter()
+        .filter_map(|rule| to_region(rule, &axis_indices))
+        .collect::<Vec<_>>();
+    let overlaid = overlay_feature_variations(conditional_substitutions);
+    if overlaid.is_empty() {
+        debug!("No rules can apply, not adding feature variations");
+        return Ok(());
+    }
+
+    // Each distinct set of substitutions gets a lookup, in sorted order
+    let all_substitutions: Vec<_> = overlaid
+        .iter()
+        .flat_map(|(_, substitutions)| substitutions.iter())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .cloned()
+        .collect();
+    let lookups = all_substitutions
+        .iter()
+        .map(|substitutions| single_sub_lookup(substitutions, glyph_order))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let gsub = gsub.get_or_insert_with(empty_gsub);
+    let process_last = !feature_variations.process_first();
+    let first_index = if process_last {
+        gsub.lookup_list.lookups.len()
+    } else {
+        0
+    };
+    if first_index + lookups.len() > u16::MAX as usize {
+        return Err(Error::OutOfBounds {
+            what: "GSUB lookups".to_string(),
+            value: format!("{}", first_index + lookups.len()),
+        });
+    }
+    let lookup_indices: HashMap<_, _> = all_substitutions
+        .iter()
+        .enumerate()
+        .map(|(i, substitutions)| (substitutions, (first_index + i) as u16))
+        .collect();
+    if process_last {
+        gsub.lookup_list
+            .lookups
+            .extend(lookups.into_iter().map(Into::into));
+    } else {
+        // Ahead of everything else, https://github.com/googlefonts/fontmake/issues/950
+        shift_lookup_indices(gsub, lookups.len() as u16);
+        gsub.lookup_list
+            .lookups
+            .splice(0..0, lookups.into_iter().map(Into::into));
+    }
+
+    let feature_indices = feature_indices(gsub, &feature_variations.features)?;
+
+    if axis_indices.is_empty() {
+        // Nowhere to vary, whatever applies everywhere is simply part of the feature
+        for (region, substitutions) in overlaid.iter() {
+            if !region.is_empty() {
+                continue;
+            }
+            let lookups: Vec<_> = substitutions.iter().map(|s| lookup_indices[s]).collect();
+            for feature_index in feature_indices.iter() {
+                let feature =
+                    &mut gsub.feature_list.feature_records[*feature_index as usize].feature;
+                feature.lookup_list_indices =
+                    combine(process_last, &feature.lookup_list_indices, &lookups);
+            }
+        }
+        return Ok(());
+    }
+
+    let mut new_records = Vec::new();
+    for (region, substitutions) in overlaid.iter() {
+        let lookups: Vec<_> = substitutions.iter().map(|s| lookup_indices[s]).collect();
+        let condition_set = ConditionSet::new(
+            region
+                .iter()
+                .map(|(tag, (min, max))| {
+                    ConditionFormat1 {
+                        axis_index: axis_indices[tag],
+                        filter_range_min_value: F2Dot14::from_f32(min.into_inner() as _),
+                        filter_range_max_value: F2Dot14::from_f32(max.into_inner() as _),
+                    }
+                    .into()
+                })
+                .collect(),
+        );
+        let records = feature_indices
+            .iter()
+            .map(|feature_index| {
+                let existing = &gsub.feature_list.feature_records[*feature_index as usize]
+                    .feature
+                    .lookup_list_indices;
+                FeatureTableSubstitutionRecord::new(
+                    *feature_index,
+                    Feature::new(None, combine(process_last, existing, &lookups)),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        // Conditions from fea, e.g. a conditionset block, may already have a record
+        let existing_record = gsub.feature_variations.as_mut().and_then(|fv| {
+            fv.feature_variation_records.iter_mut().find(|record| {
+                record.condition_set.as_ref().cloned().unwrap_or_default() == condition_set
+            })
+        });
+        if let Some(existing_record) = existing_record {
+            match existing_record.feature_table_substitution.as_mut() {
+                Some(substitution) => substitution.substitutions.extend(records),
+                None => {
+                    existing_record.feature_table_substitution =
+                        Some(FeatureTableSubstitution::new(records)).into()
+                }
+            }
+            continue;
+        }
+        new_records.push(FeatureVariationRecord::new(
+            (!condition_set.conditions.is_empty()).then_some(condition_set),
+            Some(FeatureTableSubstitution::new(records)),
+        ));
+    }
+    debug!(
+        "Adding {} feature variation records for {:?}",
+        new_records.len(),
+        feature_variations.features
+    );
+    match gsub.feature_variations.as_mut() {
+        Some(fv) => fv.feature_variation_records.extend(new_records),
+        None => {
+            gsub.feature_variations = Some(write_fonts::tables::layout::FeatureVariations::new(
+                new_records,
+            ))
+            .into()
+        }
+    }
+    Ok(())
+}
+
+fn combine(process_last: bool, existing: &[u16], ours: &[u16]) -> Vec<u16> {
+    if process_last {
+        existing.iter().chain(ours).copied().collect()
+    } else {
+        ours.iter().chain(existing).copied().collect()
+    }
+}
+
+/// The region a rule applies to, or None if it can never apply.
+///
+/// Conditions on axes that don't vary, such as point axes, are met if they include the default.
+fn to_region(
+    rule: &ConditionalSubstitution,
+    axis_indices: &HashMap<Tag, u16>,
+) -> Option<(Vec<Region>, Substitutions)> {
+    let regions: Vec<_> = rule
+        .condition_sets
+        .iter()
+        .filter_map(|condition_set| {
+            let mut region = Region::new();
+            for condition in condition_set.iter() {
+                let (min, max) = (condition.min.into_inner(), condition.max.into_inner());
+                if !axis_indices.contains_key(&condition.axis) {
+                    if min.0 > 0.0 || max.0 < 0.0 {
+                        return None;
+                    }
+                    continue;
+                }
+                region.insert(condition.axis, (min, max));
+            }
+            Some(region)
+        })
+        .collect();
+    (!regions.is_empty() && !rule.substitutions.is_empty())
+        .then(|| (regions, rule.substitutions.clone()))
+}
+
+/// Drop axes that are unconstrained
+fn cleanup_region(region: Region) -> Region {
+    region
+        .into_iter()
+        .filter(|(_, range)| *range != (OrderedFloat(-1.0), OrderedFloat(1.0)))
+        .collect()
+}
+
+/// Compute overlaps between all conditional substitutions.
+///
+/// Returns the regions to emit a record for, each with the substitutions, in rule order,
+/// that apply there. Regions where more rules apply come first. Port of fontTools
+/// [overlayFeatureVariations](https://github.com/fonttools/fonttools/blob/a6b2ac2d5/Lib/fontTools/varLib/featureVars.py#L123).
+fn overlay_feature_variations(
+    conditional_substitutions: Vec<(Vec<Region>, Substitutions)>,
+) -> Vec<(Region, Vec<Substitutions>)> {
+    // Merge rules with the same substitutions, that makes for fewer lookups
+    let mut by_substitutions: IndexMap<Substitutions, Vec<Region>> = IndexMap::new();
+    for (regions, substitutions) in conditional_substitutions {
+        by_substitutions
+            .entry(substitutions)
+            .or_default()
+            .extend(regions);
+    }
+
+    // Merge rules with the same region, that's cheaper. Walk backwards so
+    // earlier rules win if they conflict.
+    let mut by_region: IndexMap<Vec<Region>, Substitutions> = IndexMap::new();
+    for (substitutions, regions) in by_substitutions.into_iter().rev() {
+        let mut regions: Vec<_> = regions.into_iter().map(cleanup_region).collect();
+        regions.sort();
+        match by_region.entry(regions) {
+            Entry::Occupied(mut e) => e.get_mut().extend(substitutions),
+            Entry::Vacant(e) => {
+                e.insert(substitutions);
+            }
+        }
+    }
+    let conditional_substitutions: Vec<_> = by_region.into_iter().rev().collect();
+
+    // Overlay, tracking which rules contribute to each box
+    let mut box_map: IndexMap<Region, Rank> = IndexMap::from([(Region::new(), Rank::new())]);
+    for (i, (curr_region, _)) in conditional_substitutions.iter().enumerate() {
+        let mut new_map: IndexMap<Region, Rank> = IndexMap::from([(Region::new(), Rank::new())]);
+        for (bot, rank) in box_map.iter() {
+            for top in curr_region.iter() {
+                let (intersection, remainder) = overlay_box(top, bot);
+                if let Some(intersection) = intersection {
+                    let new_rank = new_map.entry(intersection).or_default();
+                    new_rank.extend(rank.iter().copied());
+                    new_rank.insert(i);
+                }
+                if let Some(remainder) = remainder {
+                    new_map
+                        .entry(remainder)
+                        .or_default()
+                        .extend(rank.iter().copied());
+                }
+            }
+        }
+        box_map = new_map;
+    }
+
+    let mut items: Vec<_> = box_map
+        .into_iter()
+        .filter(|(_, rank)| !rank.is_empty())
+        .collect();
+    items.sort_by_key(|(_, rank)| Reverse(rank.len()));
+    items
+        .into_iter()
+        .map(|(region, rank)| {
+            let substitutions = rank
+                .into_iter()
+                .map(|i| conditional_substitutions[i].1.clone())
+                .collect();
+            (region, substitutions)
+        })
+        .collect()
+}
+
+/// Overlays top on bot.
+///
+/// Returns the intersection of top and bot, None if they don't intersect, and the
+/// remainder of bot, None if it's empty. The remainder may not be exact, since
+/// it might not be a simple box, but it is inclusive of the exact remainder.
+fn overlay_box(top: &Region, bot: &Region) -> (Option<Region>, Option<Region>) {
+    let mut intersection = top.clone();
+    intersection.extend(bot.iter().map(|(tag, range)| (*tag, *range)));
+    for (tag, (min1, max1)) in top.iter() {
+        let Some((min2, max2)) = bot.get(tag) else {
+            continue;
+        };
+        let min = *min1.max(min2);
+        let max = *max1.min(max2);
+        if min >= max {
+            return (None, Some(bot.clone()));
+        }
+        intersection.insert(*tag, (min, max));
+    }
+
+    // The remainder is empty if every axis range of bot is within the intersection.
+    // If all but one are within, and that one sticks out on one side only, we can shrink
+    // bot to just the part that sticks out. Otherwise the remainder isn't a box so we
+    // return bot in full.
+    let mut remainder = bot.clone();
+    let mut extruding = top.keys().any(|tag| !bot.contains_key(tag));
+    let mut fully_inside = !extruding;
+    for (tag, (min2, max2)) in bot.iter() {
+        // An axis top doesn't constrain lies fully within it
+        if !top.contains_key(tag) {
+            continue;
+        }
+        let (min1, max1) = intersection[tag];
+        if min1 <= *min2 && *max2 <= max1 {
+            continue;
+        }
+        // More than one axis overlapping, the remainder isn't a box
+        if extruding {
+            return (Some(intersection), Some(bot.clone()));
+        }
+        extruding = true;
+        fully_inside = false;
+
+        let range = if min1 <= *min2 {
+            // The right side survives
+            (max1.max(*min2), *max2)
+        } else if *max2 <= max1 {
+            // The left side survives
+            (*min2, min1.min(*max2))
+        } else {
+            // Remainder sticks out both sides, we can't cut either
+            return (Some(intersection), Some(bot.clone()));
+        };
+        remainder.insert(*tag, range);
+    }
+
+    if fully_inside {
+        return (Some(intersection), None);
+    }
+    (Some(intersection), Some(remainder))
+}
+
+fn single_sub_lookup(
+    substitutions: &Substitutions,
+    glyph_order: &GlyphOrder,
+) -> Result<SubstitutionLookup, Error> {
+    let gid = |name: &GlyphName| {
+        glyph_order
+            .glyph_id(name)
+            .ok_or_else(|| Error::MissingGlyphId(name.clone()))
+    };
+    let mapping = substitutions
+        .iter()
+        .map(|(from, to)| Ok((gid(from)?, gid(to)?)))
+        .collect::<Result<BTreeMap<_, _>, Error>>()?;
+
+    // If every glyph moves by the same amount we can use format 1
+    let delta = mapping
+        .iter()
+        .map(|(from, to)| to.to_u16() as i32 - from.to_u16() as i32)
+        .reduce(|acc, delta| if acc == delta { acc } else { i32::MAX })
+        .and_then(|delta| i16::try_from(delta).ok());
+    let coverage = mapping.keys().copied().collect();
+    let subtable = match delta {
+        Some(delta) => SingleSubst::format_1(coverage, delta),
+        None => SingleSubst::format_2(
+            coverage,
+            mapping.values().copied().collect::<Vec<GlyphId16>>(),
+        ),
+    };
+    Ok(SubstitutionLookup::Single(Lookup::new(
+        LookupFlag::empty(),
+        vec![subtable],
+    )))
+}
+
+/// A GSUB with just a default language system for the default script.
+fn empty_gsub() -> Gsub {
+    Gsub::new(
+        ScriptList::new(vec![ScriptRecord::new(
+            DFLT_SCRIPT,
+            Script::new(Some(LangSys::new(vec![])), vec![]),
+        )]),
+        FeatureList::new(vec![]),
+        Default::default(),
+    )
+}
+
+/// The indices of the features we should register our lookups under.
+///
+/// Features that don't yet exist are added to every language system.
+fn feature_indices(gsub: &mut Gsub, features: &[Tag]) -> Result<Vec<u16>, Error> {
+    let existing: BTreeSet<_> = gsub
+        .feature_list
+        .feature_records
+        .iter()
+        .map(|record| record.feature_tag)
+        .filter(|tag| features.contains(tag))
+        .collect();
+    let new: BTreeSet<_> = features
+        .iter()
+        .filter(|tag| !existing.contains(*tag))
+        .copied()
+        .collect();
+
+    if !new.is_empty() {
+        let first_new = gsub.feature_list.feature_records.len();
+        gsub.feature_list.feature_records.extend(
+            new.iter()
+                .map(|tag| FeatureRecord::new(*tag, Feature::new(None, vec![]))),
+        );
+        let remap = sort_feature_list(gsub);
+        let new_indices: Vec<_> = (first_new..remap.len())
+            .map(|i| remap[&(i as u16)])
+            .collect();
+        for script_record in gsub.script_list.script_records.iter_mut() {
+            let script = &mut script_record.script;
+            let Some(default_lang_sys) = script.default_lang_sys.as_mut() else {
+                return Err(Error::NoDefaultLangSys(script_record.script_tag));
+            };
+            default_lang_sys
+                .feature_indices
+                .extend(new_indices.iter().copied());
+            for lang_sys_record in script.lang_sys_records.iter_mut() {
+                lang_sys_record
+                    .lang_sys
+                    .feature_indices
+                    .extend(new_indices.iter().copied());
+            }
+        }
+    }
+
+    Ok(gsub
+        .feature_list
+        .feature_records
+        .iter()
+        .enumerate()
+        .filter(|(_, record)| features.contains(&record.feature_tag))
+        .map(|(i, _)| i as u16)
+        .collect())
+}
+
+/// Sort features by tag, as the spec requires, and update references to them.
+///
+/// Returns the mapping from old to new index.
+fn sort_feature_list(gsub: &mut Gsub) -> HashMap<u16, u16> {
+    let mut records: Vec<_> = std::mem::take(&mut gsub.feature_list.feature_records)
+        .into_iter()
+        .enumerate()
+        .collect();
+    // Ties are broken by original index, the sort is stable
+    records.sort_by_key(|(_, record)| record.feature_tag);
+    let remap: HashMap<_, _> = records
+        .iter()
+        .enumerate()
+        .map(|(new, (old, _))| (*old as u16, new as u16))
+        .collect();
+    gsub.feature_list.feature_records = records.into_iter().map(|(_, record)| record).collect();
+
+    let remap_lang_sys = |lang_sys: &mut LangSys| {
+        if lang_sys.required_feature_index != 0xFFFF {
+            lang_sys.required_feature_index = remap[&lang_sys.required_feature_index];
+        }
+        for index in lang_sys.feature_indices.iter_mut() {
+            *index = remap[index];
+        }
+    };
+    for script_record in gsub.script_list.script_records.iter_mut() {
+        let script = &mut script_record.script;
+        if let Some(default_lang_sys) = script.default_lang_sys.as_mut() {
+            remap_lang_sys(default_lang_sys);
+        }
+        for lang_sys_record in script.lang_sys_records.iter_mut() {
+            remap_lang_sys(&mut lang_sys_record.lang_sys);
+        }
+    }
+    if let Some(feature_variations) = gsub.feature_variations.as_mut() {
+        for record in feature_variations.feature_variation_records.iter_mut() {
+            if let Some(substitution) = record.feature_table_substitution.as_mut() {
+                for substitution in substitution.substitutions.iter_mut() {
+                    substitution.feature_index = remap[&substitution.feature_index];
+                }
+            }
+        }
+    }
+    remap
+}
+
+/// Make room for lookups inserted at the start of the lookup list
+fn shift_lookup_indices(gsub: &mut Gsub, shift: u16) {
+    for record in gsub.feature_list.feature_records.iter_mut() {
+        for index in record.feature.lookup_list_indices.iter_mut() {
+            *index += shift;
+        }
+    }
+    if let Some(feature_variations) = gsub.feature_variations.as_mut() {
+        for record in feature_variations.feature_variation_records.iter_mut() {
+            if let Some(substitution) = record.feature_table_substitution.as_mut() {
+                for substitution in substitution.substitutions.iter_mut() {
+                    for index in substitution
+                        .alternate_feature
+                        .lookup_list_indices
+                        .iter_mut()
+                    {
+                        *index += shift;
+                    }
+                }
+            }
+        }
+    }
+    let shift_records = |records: &mut Vec<SequenceLookupRecord>| {
+        for record in records.iter_mut() {
+            record.lookup_list_index += shift;
+        }
+    };
+    for lookup in gsub.lookup_list.lookups.iter_mut() {
+        match &mut **lookup {
+            SubstitutionLookup::Contextual(lookup) => {
+                for subtable in lookup.subtables.iter_mut() {
+                    shift_sequence_context(subtable, &shift_records);
+                }
+            }
+            SubstitutionLookup::ChainContextual(lookup) => {
+                for subtable in lookup.subtables.iter_mut() {
+                    shift_chained_sequence_context(subtable, &shift_records);
+                }
+            }
+            SubstitutionLookup::Extension(lookup) => {
+                for subtable in lookup.subtables.iter_mut() {
+                    match &mut **subtable {
+                        ExtensionSubtable::Contextual(ext) => {
+                            shift_sequence_context(&mut ext.extension, &shift_records)
+                        }
+                        ExtensionSubtable::ChainContextual(ext) => {
+                            shift_chained_sequence_context(&mut ext.extension, &shift_records)
+                        }
+                        _ => (),
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+fn shift_sequence_context(
+    context: &mut SequenceContext,
+    shift_records: &impl Fn(&mut Vec<SequenceLookupRecord>),
+) {
+    match context {
+        SequenceContext::Format1(table) => {
+            for rule_set in table.seq_rule_sets.iter_mut() {
+                let Some(rule_set) = rule_set.as_mut() else {
+                    continue;
+                };
+                for rule in rule_set.seq_rules.iter_mut() {
+                    shift_records(&mut rule.seq_lookup_records);
+                }
+            }
+        }
+        SequenceContext::Format2(table) => {
+            for rule_set in table.class_seq_rule_sets.iter_mut() {
+                let Some(rule_set) = rule_set.as_mut() else {
+                    continue;
+                };
+                for rule in rule_set.class_seq_rules.iter_mut() {
+                    shift_records(&mut rule.seq_lookup_records);
+                }
+            }
+        }
+        SequenceContext::Format3(table) => shift_records(&mut table.seq_lookup_records),
+    }
+}
+
+fn shift_chained_sequence_context(
+    context: &mut ChainedSequenceContext,
+    shift_records: &impl Fn(&mut Vec<SequenceLookupRecord>),
+) {
+    match context {
+        ChainedSequenceContext::Format1(table) => {
+            for rule_set in table.chained_seq_rule_sets.iter_mut() {
+                let Some(rule_set) = rule_set.as_mut() else {
+                    continue;
+                };
+                for rule in rule_set.chained_seq_rules.iter_mut() {
+                    shift_records(&mut rule.seq_lookup_records);
+                }
+            }
+        }
+        ChainedSequenceContext::Format2(table) => {
+            for rule_set in table.chained_class_seq_rule_sets.iter_mut() {
+                let Some(rule_set) = rule_set.as_mut() else {
+                    continue;
+                };
+                for rule in rule_set.chained_class_seq_rules.iter_mut() {
+                    shift_records(&mut rule.seq_lookup_records);
+                }
+            }
+        }
+        ChainedSequenceContext::Format3(table) => shift_records(&mut table.seq_lookup_records),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn region(ranges: &[(&str, f64, f64)]) -> Region {
+        ranges
+            .iter()
+            .map(|(tag, min, max)| {
+                (
+                    Tag::new_checked(tag.as_bytes()).unwrap(),
+                    (OrderedFloat(*min), OrderedFloat(*max)),
+                )
+            })
+            .collect()
+    }
+
+    fn subs(pairs: &[(&str, &str)]) -> Substitutions {
+        pairs
+            .iter()
+            .map(|(from, to)| ((*from).into(), (*to).into()))
+            .collect()
+    }
+
+    // <https://github.com/fonttools/fonttools/blob/a6b2ac2d5/Lib/fontTools/varLib/featureVars.py#L233-L249>
+    #[test]
+    fn overlay_box_cases() {
+        let top = region(&[("wdth", 0.0, 1.0)]);
+        // Fully inside
+        assert_eq!(
+            (Some(region(&[("wdth", 0.5, 1.0)])), None),
+            overlay_box(&top, &region(&[("wdth", 0.5, 1.0)]))
+        );
+        // Disjoint
+        assert_eq!(
+            (None, Some(region(&[("wdth", -1.0, -0.5)]))),
+            overlay_box(&top, &region(&[("wdth", -1.0, -0.5)]))
+        );
+        // Sticks out to the left, so it is cut down to that
+        assert_eq!(
+            (
+                Some(region(&[("wdth", 0.0, 0.5)])),
+                Some(region(&[("wdth", -1.0, 0.0)]))
+            ),
+            overlay_box(&top, &region(&[("wdth", -1.0, 0.5)]))
+        );
+        // Top constrains an axis bot doesn't so bot survives in full
+        assert_eq!(
+            (
+                Some(region(&[("wdth", 0.0, 1.0), ("wght", 0.5, 1.0)])),
+                Some(region(&[("wght", 0.5, 1.0)]))
+            ),
+            overlay_box(&top, &region(&[("wght", 0.5, 1.0)]))
+        );
+        // Bot constrains an axis top doesn't, that axis lies fully within top
+        assert_eq!(
+            (
+                Some(region(&[("wdth", 0.0, 1.0), ("wght", 0.2, 0.5)])),
+                None
+            ),
+            overlay_box(
+                &region(&[("wght", 0.0, 1.0)]),
+                &region(&[("wdth", 0.0, 1.0), ("wght", 0.2, 0.5)])
+            )
+        );
+    }
+
+    #[test]
+    fn overlay_disjoint() {
+        let a = subs(&[("a", "a.alt")]);
+        let b = subs(&[("b", "b.alt")]);
+        // boxes with the same number of rules keep the order of the box map,
+        // where the latest rule's box comes first, as in fontTools
+        assert_eq!(
+            vec![
+                (region(&[("wght", 0.5, 1.0)]), vec![b.clone()]),
+                (region(&[("wght", 0.0, 0.5)]), vec![a.clone()]),
+            ],
+            overlay_feature_variations(vec![
+                (vec![region(&[("wght", 0.0, 0.5)])], a),
+                (vec![region(&[("wght", 0.5, 1.0)])], b),
+            ])
+        );
+    }
+
+    #[test]
+    fn overlay_overlapping() {
+        let a = subs(&[("a", "a.alt")]);
+        let b = subs(&[("b", "b.alt")]);
+        assert_eq!(
+            vec![
+                (region(&[("wght", 0.5, 0.75)]), vec![a.clone(), b.clone()]),
+                // the remainder of b isn't cut down; it was overlaid on the
+                // whole space, not on a
+                (region(&[("wght", 0.5, 1.0)]), vec![b.clone()]),
+                (region(&[("wght", 0.0, 0.5)]), vec![a.clone()]),
+            ],
+            overlay_feature_variations(vec![
+                (vec![region(&[("wght", 0.0, 0.75)])], a),
+                (vec![region(&[("wght", 0.5, 1.0)])], b),
+            ])
+        );
+    }
+
+    #[test]
+    fn overlay_on_box_with_more_axes() {
+        let a = subs(&[("a", "a.alt")]);
+        let b = subs(&[("b", "b.alt")]);
+        // b covers all of a so nothing of a is left over
+        assert_eq!(
+            vec![
+                (
+                    region(&[("wdth", 0.0, 1.0), ("wght", 0.2, 0.5)]),
+                    vec![a.clone(), b.clone()]
+                ),
+                (region(&[("wght", 0.0, 1.0)]), vec![b.clone()]),
+            ],
+            overlay_feature_variations(vec![
+                (vec![region(&[("wdth", 0.0, 1.0), ("wght", 0.2, 0.5)])], a),
+                (vec![region(&[("wght", 0.0, 1.0)])], b),
+            ])
+        );
+    }
+
+    #[test]
+    fn overlay_same_region_earlier_wins() {
+        assert_eq!(
+            vec![(
+                region(&[("wght", 0.5, 1.0)]),
+                vec![subs(&[("a", "a.first"), ("b", "b.alt")])]
+            )],
+            overlay_feature_variations(vec![
+                (
+                    vec![region(&[("wght", 0.5, 1.0)])],
+                    subs(&[("a", "a.first")])
+                ),
+                (
+                    vec![region(&[("wght", 0.5, 1.0)])],
+                    subs(&[("a", "a.second"), ("b", "b.alt")])
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn overlay_drops_unconstrained_axes() {
+        let a = subs(&[("a", "a.alt")]);
+        assert_eq!(
+            vec![(region(&[("wght", 0.5, 1.0)]), vec![a.clone()])],
+            overlay_feature_variations(vec![(
+                vec![region(&[("wdth", -1.0, 1.0), ("wght", 0.5, 1.0)])],
+                a
+            )])
+        );
+    }
+}
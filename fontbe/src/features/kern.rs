@@ -9,18 +9,13 @@ use fea_rs::{
     compile::{FeatureKey, FeatureProvider, PairPosBuilder, ValueRecord as ValueRecordBuilder},
     GlyphSet, ParseTree,
 };
-use fontdrasil::{
-    coords::NormalizedLocation,
-    orchestration::{Access, AccessBuilder, Work},
-    types::GlyphName,
-};
+use fontdrasil::orchestration::{Access, AccessBuilder, Work};
 use fontir::{
-    ir::{self, GlyphOrder, KernGroup, KerningGroups, KerningInstance, StaticMetadata},
+    ir::{self, GlyphOrder, StaticMetadata},
     orchestration::WorkId as FeWorkId,
 };
 use icu_properties::props::BidiClass;
 use log::debug;
-use ordered_float::OrderedFloat;
 use write_fonts::{
     read::{tables::gsub::Gsub, ReadError},
     tables::{gdef::GlyphClassDef, layout::LookupFlag},
@@ -147,7 +142,7 @@ impl Work<Context, AnyWorkId, Error> for GatherIrKerningWork {
             .map(|(_, ki)| (ki.location.clone(), ki.as_ref().to_owned()))
             .collect();
 
-        align_kerning(&ir_groups, &mut kern_by_pos);
+        ir::align_kerning(&ir_groups, &mut kern_by_pos);
         let mut adjustments: HashMap<ir::KernPair, KernAdjustments> = Default::default();
 
         // We want to add items to locations in the same order as the group locations
@@ -203,113 +198,6 @@ impl Work<Context, AnyWorkId, Error> for GatherIrKerningWork {
     }
 }
 
-/// 'align' the kerning, ensuring each pair is defined for each location.
-///
-/// missing pairs are filled in via the UFO kerning value lookup algorithm:
-///
-/// <https://unifiedfontobject.org/versions/ufo3/kerning.plist/#kerning-value-lookup-algorithm>
-///
-/// in pythonland this happens in ufo2ft, here:
-/// <https://github.com/googlefonts/ufo2ft/blob/5fd168e65a0b0a/Lib/ufo2ft/featureWriters/kernFeatureWriter.py#L442>
-fn align_kerning(
-    groups: &KerningGroups,
-    instances: &mut HashMap<NormalizedLocation, KerningInstance>,
-) {
-    // all pairs defined in at least one instance
-    let all_known_pairs = instances
-        .values()
-        .flat_map(|instance| instance.kerns.keys())
-        .cloned()
-        .collect::<HashSet<_>>();
-
-    let side1_glyph_to_group_map = groups
-        .groups
-        .iter()
-        .filter(|(group, _)| matches!(group, KernGroup::Side1(_)))
-        .flat_map(|(group, glyphs)| glyphs.iter().map(move |glyph| (glyph, group)))
-        .collect::<HashMap<_, _>>();
-    let side2_glyph_to_group_map = groups
-        .groups
-        .iter()
-        .filter(|(group, _)| matches!(group, KernGroup::Side2(_)))
-        .flat_map(|(group, glyphs)| glyphs.iter().map(move |glyph| (glyph, group)))
-        .collect::<HashMap<_, _>>();
-
-    for instance in instances.values_mut() {
-        align_instance(
-            &all_known_pairs,
-            &mut instance.kerns,
-            &side1_glyph_to_group_map,
-            &side2_glyph_to_group_map,
-        )
-    }
-}
-
-fn align_instance(
-    all_pairs: &HashSet<ir::KernPair>,
-    instance: &mut BTreeMap<ir::KernPair, OrderedFloat<f64>>,
-    side1_glyphs: &HashMap<&GlyphName, &KernGroup>,
-    side2_glyphs: &HashMap<&GlyphName, &KernGroup>,
-) {
-    let mut buf = Vec::new();
-    // iterate the pairs that are not present in this instance
-    for pair in all_pairs.iter().filter(|pair| !instance.contains_key(pair)) {
-        let value = lookup_kerning_value(pair, instance, side1_glyphs, side2_glyphs);
-
-        // accumulate any additions and add at the end, otherwise newly added
-        // additions could influence the calculation of subsequent values
-        buf.push((pair, value));
-    }
-    // when done all pairs, add them to the instance
-    for (pair, value) in buf {
-        instance.insert(pair.to_owned(), value);
-    }
-}
-
-// <https://github.com/fonttools/fonttools/blob/a3b9eddcafca/Lib/fontTools/ufoLib/kerning.py#L1>
-fn lookup_kerning_value(
-    pair: &ir::KernPair,
-    kerning: &BTreeMap<ir::KernPair, OrderedFloat<f64>>,
-    side1_glyphs: &HashMap<&GlyphName, &KernGroup>,
-    side2_glyphs: &HashMap<&GlyphName, &KernGroup>,
-) -> OrderedFloat<f64> {
-    // if already a group, return it, else look for group for glyph
-    fn get_group_if_glyph(
-        side: &ir::KernSide,
-        map: &HashMap<&GlyphName, &KernGroup>,
-    ) -> Option<ir::KernSide> {
-        match side {
-            ir::KernSide::Glyph(glyph) => map
-                .get(&glyph)
-                .map(|group| ir::KernSide::Group((*group).clone())),
-            ir::KernSide::Group(_) => Some(side.to_owned()),
-        }
-    }
-
-    let (first, second) = pair;
-    // for each side: if it's a group, we only check the group.
-    // if it's a glyph, we check both the glyph as well as the group containing that glyph.
-    let first_group = get_group_if_glyph(first, side1_glyphs);
-    let second_group = get_group_if_glyph(second, side2_glyphs);
-    let first = Some(first).filter(|side| side.is_glyph());
-    let second = Some(second).filter(|side| side.is_glyph());
-
-    for (first, second) in [
-        (first.cloned(), second_group.clone()),
-        (first_group.clone(), second.cloned()),
-        (first_group.clone(), second_group.clone()),
-    ] {
-        if let Some(pair) = first.zip(second) {
-            if let Some(value) = kerning.get(&pair) {
-                return *value;
-            }
-        }
-    }
-
-    // then fallback to zero
-    0.0.into()
-}
-
 impl Work<Context, AnyWorkId, Error> for KerningFragmentWork {
     fn id(&self) -> AnyWorkId {
         WorkId::KernFragment(self.segment).into()
@@ -1080,6 +968,7 @@ mod tests {
     use std::{path::Path, sync::Arc};
 
     use fea_rs::compile::NopVariationInfo;
+    use fontdrasil::types::GlyphName;
     use fontir::ir::GdefCategories;
     use write_fonts::read::FontRead;
 
@@ -1876,46 +1765,6 @@ mod tests {
         );
     }
 
-    // we had a bug where we were updating the kerning values in place, which
-    // meant the order in which we handled pairs could influence the results
-    #[test]
-    fn alignment_determinism() {
-        let g1 = GlyphName::new("a");
-        let g2 = GlyphName::new("b");
-        let side1 = ir::KernGroup::Side1("aa".into());
-        let side2 = ir::KernGroup::Side2("bb".into());
-        let side1_glyphs = HashMap::from([(&g1, &side1)]);
-        let side2_glyphs = HashMap::from([(&g2, &side2)]);
-
-        let glyph_glyph: ir::KernPair = (g1.clone().into(), g2.clone().into());
-        let glyph_group: ir::KernPair = (g1.clone().into(), side2.clone().into());
-        let group_glyph: ir::KernPair = (side1.clone().into(), g2.clone().into());
-        let group_group: ir::KernPair = (side1.clone().into(), side2.clone().into());
-
-        let all_pairs = HashSet::from([
-            glyph_glyph.clone(),
-            glyph_group.clone(),
-            group_glyph.clone(),
-            group_group.clone(),
-        ]);
-        let mut kerns = BTreeMap::new();
-        kerns.insert(group_group.clone(), OrderedFloat::from(-70.));
-        kerns.insert(group_glyph.clone(), 10.0.into());
-        // explanation:
-        // we need to align glyph_glyph and glyph_group.
-        // - if we do glyph_group first, we will use the group_group value of
-        //   -70, and then when we do glyph_glyph we will use this value, since
-        //   glyph_group is preferred to group_glyph
-        // - but if we do glyph_glyph first, we will use the value from
-        //   group_glyph, which is set.
-
-        // run a few times because triggering depended on hashmap iteration order
-        for _ in 0..20 {
-            align_instance(&all_pairs, &mut kerns, &side1_glyphs, &side2_glyphs);
-            assert_eq!(kerns.get(&glyph_glyph).map(|x| x.0), Some(10.0f64));
-        }
-    }
-
     #[test]
     // https://github.com/googlefonts/fontc/issues/1121
     fn default_language_systems() {
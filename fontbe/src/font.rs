@@ -1,23 +1,26 @@
 //! Merge tables into a font
 
+use std::{collections::BTreeMap, ops::Range};
+
 use fontdrasil::orchestration::{Access, AccessBuilder, Work};
 use fontir::orchestration::WorkId as FeWorkId;
 use log::debug;
 use write_fonts::{
-    read::TopLevelTable,
+    read::{tables::compute_checksum, TopLevelTable},
     tables::{
         avar::Avar, cmap::Cmap, colr::Colr, cpal::Cpal, fvar::Fvar, gasp::Gasp, gdef::Gdef,
         glyf::Glyf, gpos::Gpos, gsub::Gsub, gvar::Gvar, head::Head, hhea::Hhea, hmtx::Hmtx,
         hvar::Hvar, loca::Loca, maxp::Maxp, meta::Meta, mvar::Mvar, name::Name, os2::Os2,
-        post::Post, stat::Stat,
+        post::Post, stat::Stat, vhea::Vhea, vmtx::Vmtx,
     },
-    types::Tag,
+    types::{Tag, CFF_SFNT_VERSION, TT_SFNT_VERSION},
     FontBuilder,
 };
 
 use crate::{
     error::Error,
     orchestration::{to_bytes, AnyWorkId, BeWork, Context, WorkId},
+    vertical_tables::{Vorg, Vvar},
 };
 
 #[derive(Debug)]
@@ -32,8 +35,13 @@ enum TableType {
     Variable,
 }
 
+const CFF_TAG: Tag = Tag::new(b"CFF ");
+const CFF2_TAG: Tag = Tag::new(b"CFF2");
+
 const TABLES_TO_MERGE: &[(WorkId, Tag, TableType)] = &[
     (WorkId::Avar, Avar::TAG, TableType::Variable),
+    (WorkId::Cff, CFF_TAG, TableType::Static),
+    (WorkId::Cff2, CFF2_TAG, TableType::Variable),
     (WorkId::Cmap, Cmap::TAG, TableType::Static),
     (WorkId::Colr, Colr::TAG, TableType::Static),
     (WorkId::Cpal, Cpal::TAG, TableType::Static),
@@ -53,14 +61,21 @@ const TABLES_TO_MERGE: &[(WorkId, Tag, TableType)] = &[
     (WorkId::Os2, Os2::TAG, TableType::Static),
     (WorkId::Post, Post::TAG, TableType::Static),
     (WorkId::Stat, Stat::TAG, TableType::Variable),
+    (WorkId::Varc, Tag::new(b"VARC"), TableType::Variable),
     (WorkId::Hvar, Hvar::TAG, TableType::Variable),
     (WorkId::Mvar, Mvar::TAG, TableType::Variable),
     (WorkId::Meta, Meta::TAG, TableType::Static),
+    (WorkId::Vhea, Vhea::TAG, TableType::Static),
+    (WorkId::Vmtx, Vmtx::TAG, TableType::Static),
+    (WorkId::Vorg, Vorg::TAG, TableType::Static),
+    (WorkId::Vvar, Vvar::TAG, TableType::Variable),
 ];
 
 fn has(context: &Context, id: WorkId) -> bool {
     match id {
         WorkId::Avar => context.avar.try_get().is_some(),
+        WorkId::Cff => context.cff.try_get().is_some(),
+        WorkId::Cff2 => context.cff2.try_get().is_some(),
         WorkId::Cmap => context.cmap.try_get().is_some(),
         WorkId::Colr => context.colr.try_get().is_some(),
         WorkId::Cpal => context.cpal.try_get().is_some(),
@@ -80,9 +95,14 @@ fn has(context: &Context, id: WorkId) -> bool {
         WorkId::Os2 => context.os2.try_get().is_some(),
         WorkId::Post => context.post.try_get().is_some(),
         WorkId::Stat => context.stat.try_get().is_some(),
+        WorkId::Varc => context.varc.try_get().is_some(),
         WorkId::Hvar => context.hvar.try_get().is_some(),
         WorkId::Mvar => context.mvar.try_get().is_some(),
         WorkId::Meta => context.meta.try_get().is_some(),
+        WorkId::Vhea => context.vhea.try_get().is_some(),
+        WorkId::Vmtx => context.vmtx.try_get().is_some(),
+        WorkId::Vorg => context.vorg.try_get().is_some(),
+        WorkId::Vvar => context.vvar.try_get().is_some(),
         _ => false,
     }
 }
@@ -91,6 +111,8 @@ fn bytes_for(context: &Context, id: WorkId) -> Result<Option<Vec<u8>>, Error> {
     // TODO: to_vec copies :(
     let bytes = match id {
         WorkId::Avar => context.avar.get().as_ref().as_ref().and_then(to_bytes),
+        WorkId::Cff => Some(context.cff.get().as_ref().get().to_vec()),
+        WorkId::Cff2 => Some(context.cff2.get().as_ref().get().to_vec()),
         WorkId::Cmap => to_bytes(context.cmap.get().as_ref()),
         WorkId::Colr => to_bytes(context.colr.get().as_ref()),
         WorkId::Cpal => to_bytes(context.cpal.get().as_ref()),
@@ -110,9 +132,14 @@ fn bytes_for(context: &Context, id: WorkId) -> Result<Option<Vec<u8>>, Error> {
         WorkId::Os2 => to_bytes(context.os2.get().as_ref()),
         WorkId::Post => to_bytes(context.post.get().as_ref()),
         WorkId::Stat => to_bytes(context.stat.get().as_ref()),
+        WorkId::Varc => Some(context.varc.get().as_ref().get().to_vec()),
         WorkId::Hvar => to_bytes(context.hvar.get().as_ref()),
         WorkId::Mvar => to_bytes(context.mvar.get().as_ref()),
         WorkId::Meta => to_bytes(context.meta.get().as_ref()),
+        WorkId::Vhea => to_bytes(context.vhea.get().as_ref()),
+        WorkId::Vmtx => Some(context.vmtx.get().as_ref().get().to_vec()),
+        WorkId::Vorg => to_bytes(context.vorg.get().as_ref()),
+        WorkId::Vvar => to_bytes(context.vvar.get().as_ref()),
         _ => panic!("Missing a match for {id:?}"),
     };
     Ok(bytes)
@@ -126,6 +153,8 @@ impl Work<Context, AnyWorkId, Error> for FontWork {
     fn read_access(&self) -> Access<AnyWorkId> {
         AccessBuilder::new()
             .variant(WorkId::Avar)
+            .variant(WorkId::Cff)
+            .variant(WorkId::Cff2)
             .variant(WorkId::Cmap)
             .variant(WorkId::Colr)
             .variant(WorkId::Cpal)
@@ -145,9 +174,14 @@ impl Work<Context, AnyWorkId, Error> for FontWork {
             .variant(WorkId::Os2)
             .variant(WorkId::Post)
             .variant(WorkId::Stat)
+            .variant(WorkId::Varc)
             .variant(WorkId::Hvar)
             .variant(WorkId::Mvar)
             .variant(WorkId::Meta)
+            .variant(WorkId::Vhea)
+            .variant(WorkId::Vmtx)
+            .variant(WorkId::Vorg)
+            .variant(WorkId::Vvar)
             .variant(WorkId::LocaFormat)
             .variant(FeWorkId::StaticMetadata)
             .build()
@@ -155,11 +189,9 @@ impl Work<Context, AnyWorkId, Error> for FontWork {
 
     /// Glue binary tables into a font
     fn exec(&self, context: &Context) -> Result<(), Error> {
-        // Lets go right ahead and believe those bytes are a font
-        let mut builder = FontBuilder::default();
-
         // A fancier implementation would mmap the files. We basic.
         let is_static = context.ir.static_metadata.get().axes.is_empty();
+        let mut tables = BTreeMap::new();
         for (work_id, tag, table_type) in TABLES_TO_MERGE {
             if is_static && matches!(table_type, TableType::Variable) {
                 debug!("Skip {tag} because this is a static font");
@@ -171,16 +203,114 @@ impl Work<Context, AnyWorkId, Error> for FontWork {
             }
             debug!("Grabbing {tag} for final font");
             if let Some(bytes) = bytes_for(context, work_id.clone())? {
-                builder.add_raw(*tag, bytes);
+                tables.insert(*tag, bytes);
             } else {
                 debug!("No content for {tag}");
             }
         }
 
+        // Lets go right ahead and believe those bytes are a font
+        let sfnt_version = if tables.contains_key(&CFF_TAG) || tables.contains_key(&CFF2_TAG) {
+            CFF_SFNT_VERSION
+        } else {
+            TT_SFNT_VERSION
+        };
         debug!("Building font");
-        let font = builder.build();
+        let font = assemble(sfnt_version, tables);
         debug!("Assembled {} byte font", font.len());
         context.font.set(font.into());
         Ok(())
     }
 }
+
+/// Write tables into a font file with a [Table Directory] that starts with sfnt_version.
+///
+/// This is [FontBuilder::build] except that always writes the TrueType sfnt version;
+/// we still borrow its table order.
+///
+/// [Table Directory]: https://learn.microsoft.com/en-us/typography/opentype/spec/otff#table-directory
+fn assemble(sfnt_version: u32, tables: BTreeMap<Tag, Vec<u8>>) -> Vec<u8> {
+    const TABLE_RECORD_LEN: usize = 16;
+    // See <https://learn.microsoft.com/en-us/typography/opentype/spec/head>
+    const HEAD_CHECKSUM_ADJUSTMENT: Range<usize> = 8..12;
+
+    let mut ordering = FontBuilder::default();
+    for (tag, data) in tables.iter() {
+        ordering.add_raw(*tag, data.as_slice());
+    }
+    let table_order = ordering.ordered_tags();
+
+    let num_tables = tables.len() as u16;
+    let entry_selector = num_tables.checked_ilog2().unwrap_or_default() as u16;
+    let search_range = (1u16 << entry_selector) * TABLE_RECORD_LEN as u16;
+    let range_shift = (num_tables * TABLE_RECORD_LEN as u16).saturating_sub(search_range);
+
+    // The table records are sorted by tag, the tables themselves are in table_order
+    let mut position = 12 + tables.len() * TABLE_RECORD_LEN;
+    let mut records = BTreeMap::new();
+    let mut checksum = 0u32;
+    for tag in table_order.iter() {
+        let data = &tables[tag];
+        // head is checksummed with checksumAdjustment zeroed
+        let table_checksum = if *tag == Head::TAG && data.len() >= HEAD_CHECKSUM_ADJUSTMENT.end {
+            let mut head = data.clone();
+            head[HEAD_CHECKSUM_ADJUSTMENT].fill(0);
+            compute_checksum(&head)
+        } else {
+            compute_checksum(data)
+        };
+        checksum = checksum.wrapping_add(table_checksum);
+        records.insert(*tag, (table_checksum, position as u32, data.len() as u32));
+        position += data.len().next_multiple_of(4);
+    }
+
+    let mut font = Vec::with_capacity(position);
+    font.extend(sfnt_version.to_be_bytes());
+    for value in [num_tables, search_range, entry_selector, range_shift] {
+        font.extend(value.to_be_bytes());
+    }
+    for (tag, (table_checksum, offset, length)) in records {
+        font.extend(tag.to_be_bytes());
+        for value in [table_checksum, offset, length] {
+            font.extend(value.to_be_bytes());
+        }
+    }
+    checksum = checksum.wrapping_add(compute_checksum(&font));
+
+    let checksum_adjustment = 0xB1B0_AFBAu32.wrapping_sub(checksum);
+    for tag in table_order {
+        let start = font.len();
+        font.extend(&tables[&tag]);
+        if tag == Head::TAG && font.len() - start >= HEAD_CHECKSUM_ADJUSTMENT.end {
+            font[start..][HEAD_CHECKSUM_ADJUSTMENT]
+                .copy_from_slice(&checksum_adjustment.to_be_bytes());
+        }
+        font.resize(font.len().next_multiple_of(4), 0);
+    }
+    font
+}
+
+#[cfg(test)]
+mod tests {
+    use write_fonts::{dump_table, read::FontRef};
+
+    use super::*;
+
+    #[test]
+    fn assemble_with_cff_sfnt_version() {
+        let tables = BTreeMap::from([
+            (Head::TAG, dump_table(&Head::default()).unwrap()),
+            (CFF_TAG, vec![1, 0, 4, 4, 0]),
+        ]);
+        let font = assemble(CFF_SFNT_VERSION, tables);
+
+        let font_ref = FontRef::new(&font).unwrap();
+        assert_eq!(CFF_SFNT_VERSION, font_ref.table_directory.sfnt_version());
+        assert_eq!(
+            Some(&[1, 0, 4, 4, 0][..]),
+            font_ref.table_data(CFF_TAG).map(|data| data.as_bytes())
+        );
+        // checksumAdjustment makes the whole font sum to the magic number
+        assert_eq!(0xB1B0_AFBA, compute_checksum(&font));
+    }
+}
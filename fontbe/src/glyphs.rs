@@ -14,6 +14,7 @@ use fontdrasil::{
     types::GlyphName,
 };
 use fontir::{
+    incremental::Reuse,
     ir::{self, GlyphOrder},
     orchestration::{Flags, WorkId as FeWorkId},
     variations::{VariationModel, VariationRegion},
@@ -27,11 +28,9 @@ use write_fonts::{
         types::F2Dot14,
     },
     tables::{
-        glyf::{
-            Bbox, Component, ComponentFlags, CompositeGlyph, GlyfLocaBuilder, Glyph as RawGlyph,
-            SimpleGlyph,
-        },
+        glyf::{Bbox, Component, ComponentFlags, CompositeGlyph, Glyph as RawGlyph, SimpleGlyph},
         gvar::{iup::iup_delta_optimize, GlyphDelta},
+        loca::Loca,
     },
     types::GlyphId16,
     OtRound,
@@ -40,6 +39,8 @@ use write_fonts::{
 use crate::{
     error::{Error, GlyphProblem},
     orchestration::{AnyWorkId, BeWork, Context, Glyph, GvarFragment, WorkId},
+    overlaps::has_overlaps,
+    vertical_metrics::{glyph_vertical_metrics, VerticalMetrics},
 };
 
 type Deltas = Vec<(VariationRegion, Vec<GlyphDelta>)>;
@@ -53,6 +54,91 @@ pub fn create_glyf_work(glyph_name: GlyphName) -> Box<BeWork> {
     Box::new(GlyphWork { glyph_name })
 }
 
+/// Restores the glyf and gvar fragments of a prior compilation, falling back to running work
+#[derive(Debug)]
+struct RestoreGlyphWork {
+    work: Box<BeWork>,
+    glyph_name: GlyphName,
+    glyph_order: Option<Arc<GlyphOrder>>,
+    reuse: Arc<Reuse>,
+}
+
+/// Wrap glyf work for glyph_name such that it restores the prior fragments if it can.
+///
+/// Only glyphs whose IR was restored are eligible. Composites reference components by
+/// glyph id so they are only eligible if glyph order matches the prior glyph_order.
+pub fn create_restore_glyf_work(
+    glyph_name: GlyphName,
+    glyph_order: Option<Arc<GlyphOrder>>,
+    reuse: Arc<Reuse>,
+) -> Box<BeWork> {
+    Box::new(RestoreGlyphWork {
+        work: create_glyf_work(glyph_name.clone()),
+        glyph_name,
+        glyph_order,
+        reuse,
+    })
+}
+
+impl RestoreGlyphWork {
+    fn restore(&self, context: &Context) -> bool {
+        if !self.reuse.restored_glyph(&self.glyph_name) {
+            return false;
+        }
+        let ir_glyph = context
+            .ir
+            .glyphs
+            .get(&FeWorkId::Glyph(self.glyph_name.clone()));
+        let has_components = ir_glyph
+            .sources()
+            .values()
+            .any(|inst| !inst.components.is_empty());
+        if has_components {
+            let Some(prior_order) = &self.glyph_order else {
+                return false;
+            };
+            if !context.ir.glyph_order.get().names().eq(prior_order.names()) {
+                return false;
+            }
+        }
+        context
+            .glyphs
+            .restore(&WorkId::GlyfFragment(self.glyph_name.clone()).into())
+            .is_some()
+            && context
+                .gvar_fragments
+                .restore(&WorkId::GvarFragment(self.glyph_name.clone()).into())
+                .is_some()
+    }
+}
+
+impl Work<Context, AnyWorkId, Error> for RestoreGlyphWork {
+    fn id(&self) -> AnyWorkId {
+        self.work.id()
+    }
+
+    fn read_access(&self) -> Access<AnyWorkId> {
+        self.work.read_access()
+    }
+
+    fn write_access(&self) -> Access<AnyWorkId> {
+        self.work.write_access()
+    }
+
+    fn also_completes(&self) -> Vec<AnyWorkId> {
+        self.work.also_completes()
+    }
+
+    fn exec(&self, context: &Context) -> Result<(), Error> {
+        if self.restore(context) {
+            trace!("Restored BE glyph '{}'", self.glyph_name);
+            self.reuse.glyph_binary_restored();
+            return Ok(());
+        }
+        self.work.exec(context)
+    }
+}
+
 /// Can glyph instance reuse the metrics of other?
 ///
 /// To be safe the component should have:
@@ -132,9 +218,11 @@ fn create_composite(
     glyph: &ir::Glyph,
     default_location: &NormalizedLocation,
     components: &[(GlyphName, NormalizedLocation, Affine)],
+    overlap_compound: bool,
 ) -> Result<CompositeGlyph, Error> {
     let mut errors = vec![];
     let mut set_use_my_metrics = false;
+    let mut is_first = true;
     let Some(default_glyph) = glyph.sources().get(default_location) else {
         return Err(Error::GlyphError(
             glyph.name.clone(),
@@ -160,6 +248,11 @@ fn create_composite(
                     })
                 })
                 .map(|(mut component, bbox)| {
+                    // OVERLAP_COMPOUND is only meaningful on the first component
+                    if is_first {
+                        component.flags.overlap_compound = overlap_compound;
+                        is_first = false;
+                    }
                     if !set_use_my_metrics {
                         let component_glyph = context
                             .ir
@@ -199,7 +292,7 @@ fn create_composite(
 
 /// * <https://github.com/fonttools/fonttools/blob/3b9a73ff8379ab49d3ce35aaaaf04b3a7d9d1655/Lib/fontTools/ttLib/tables/_g_l_y_f.py#L335-L367>
 /// * <https://docs.microsoft.com/en-us/typography/opentype/spec/tt_instructing_glyphs#phantoms>
-fn add_phantom_points(advance: u16, points: &mut Vec<Point>) {
+fn add_phantom_points(advance: u16, vertical: Option<VerticalMetrics>, points: &mut Vec<Point>) {
     // FontTools says
     //      leftSideX = glyph.xMin - leftSideBearing
     //      rightSideX = leftSideX + horizontalAdvanceWidth
@@ -207,14 +300,26 @@ fn add_phantom_points(advance: u16, points: &mut Vec<Point>) {
     points.push(Point::new(0.0, 0.0)); // leftSideX, 0
     points.push(Point::new(advance as f64, 0.0)); // rightSideX, 0
 
-    // TODO: vertical phantom points
-    points.push(Point::new(0.0, 0.0));
-    points.push(Point::new(0.0, 0.0));
+    // FontTools says
+    //      topSideY = topSideBearing + glyph.yMax
+    //      bottomSideY = topSideY - verticalAdvanceWidth
+    // We always set tsb to origin - yMax so topSideY = origin.
+    // Without vertical metrics the points don't vary so we leave them at 0.
+    let (top, bottom) = vertical
+        .map(|vertical| {
+            let top: i16 = vertical.origin.ot_round();
+            let advance: u16 = vertical.advance.ot_round();
+            (top as f64, top as f64 - advance as f64)
+        })
+        .unwrap_or_default();
+    points.push(Point::new(0.0, top)); // 0, topSideY
+    points.push(Point::new(0.0, bottom)); // 0, bottomSideY
 }
 
 /// See <https://github.com/fonttools/fonttools/blob/86291b6ef62ad4bdb48495a4b915a597a9652dcf/Lib/fontTools/ttLib/tables/_g_l_y_f.py#L369>
 fn point_seqs_for_simple_glyph(
     ir_glyph: &ir::Glyph,
+    vertical: Option<&HashMap<NormalizedLocation, VerticalMetrics>>,
     instances: HashMap<NormalizedLocation, SimpleGlyph>,
 ) -> HashMap<NormalizedLocation, Vec<Point>> {
     instances
@@ -227,7 +332,11 @@ fn point_seqs_for_simple_glyph(
                 .map(|cp| Point::new(cp.x as f64, cp.y as f64))
                 .collect();
 
-            add_phantom_points(ir_glyph.sources()[&loc].width.ot_round(), &mut points);
+            add_phantom_points(
+                ir_glyph.sources()[&loc].width.ot_round(),
+                vertical.map(|vertical| vertical[&loc]),
+                &mut points,
+            );
 
             (loc, points)
         })
@@ -235,7 +344,10 @@ fn point_seqs_for_simple_glyph(
 }
 
 /// See <https://github.com/fonttools/fonttools/blob/86291b6ef62ad4bdb48495a4b915a597a9652dcf/Lib/fontTools/ttLib/tables/_g_l_y_f.py#L369>
-fn point_seqs_for_composite_glyph(ir_glyph: &ir::Glyph) -> HashMap<NormalizedLocation, Vec<Point>> {
+fn point_seqs_for_composite_glyph(
+    ir_glyph: &ir::Glyph,
+    vertical: Option<&HashMap<NormalizedLocation, VerticalMetrics>>,
+) -> HashMap<NormalizedLocation, Vec<Point>> {
     ir_glyph
         .sources()
         .iter()
@@ -247,7 +359,11 @@ fn point_seqs_for_composite_glyph(ir_glyph: &ir::Glyph) -> HashMap<NormalizedLoc
                 let [.., dx, dy] = component.transform.as_coeffs();
                 points.push((dx, dy).into());
             }
-            add_phantom_points(inst.width.ot_round(), &mut points);
+            add_phantom_points(
+                inst.width.ot_round(),
+                vertical.map(|vertical| vertical[loc]),
+                &mut points,
+            );
 
             (loc.clone(), points)
         })
@@ -351,13 +467,29 @@ impl Work<Context, AnyWorkId, Error> for GlyphWork {
 
         let should_iup = glyph.should_iup(); // we partially borrow it later
 
+        // Overlaps render poorly in variable fonts unless flagged, fontmake flags every glyph
+        let overlap = !static_metadata.axes.is_empty()
+            && (!context.flags.contains(Flags::DETECT_OVERLAPS)
+                || glyph_overlaps(context, ir_glyph));
+
+        let vertical = if static_metadata.misc.build_vertical {
+            Some(glyph_vertical_metrics(
+                ir_glyph,
+                &context.ir.global_metrics.get(),
+                &static_metadata.axes,
+            )?)
+        } else {
+            None
+        };
+
         let (name, point_seqs, contour_ends) = match glyph {
             CheckedGlyph::Composite { name, components } => {
-                let composite = create_composite(context, ir_glyph, default_location, &components)?;
+                let composite =
+                    create_composite(context, ir_glyph, default_location, &components, overlap)?;
                 context
                     .glyphs
                     .set_unconditionally(Glyph::new(name.clone(), composite));
-                let point_seqs = point_seqs_for_composite_glyph(ir_glyph);
+                let point_seqs = point_seqs_for_composite_glyph(ir_glyph, vertical.as_ref());
                 (name, point_seqs, Vec::new())
             }
             CheckedGlyph::Contour { name, paths } => {
@@ -385,9 +517,9 @@ impl Work<Context, AnyWorkId, Error> for GlyphWork {
                         GlyphProblem::MissingDefault,
                     ));
                 };
-                context
-                    .glyphs
-                    .set_unconditionally(Glyph::new(name.clone(), base_glyph.clone()));
+                context.glyphs.set_unconditionally(
+                    Glyph::new(name.clone(), base_glyph.clone()).with_overlap_simple(overlap),
+                );
 
                 let mut num_points = 0;
                 let mut contour_ends = Vec::with_capacity(base_glyph.contours.len());
@@ -398,7 +530,7 @@ impl Work<Context, AnyWorkId, Error> for GlyphWork {
                 }
                 (
                     name,
-                    point_seqs_for_simple_glyph(ir_glyph, instances),
+                    point_seqs_for_simple_glyph(ir_glyph, vertical.as_ref(), instances),
                     contour_ends,
                 )
             }
@@ -689,6 +821,117 @@ impl CheckedGlyph {
     }
 }
 
+/// Add the outline of glyph at location, with components decomposed, to path.
+///
+/// Components that have no source at location contribute their default outline.
+fn add_outline(
+    context: &Context,
+    glyph: &ir::Glyph,
+    location: &NormalizedLocation,
+    transform: Affine,
+    path: &mut BezPath,
+) {
+    let instance = glyph
+        .sources()
+        .get(location)
+        .unwrap_or_else(|| glyph.default_instance());
+    for contour in instance.contours.iter() {
+        path.extend(transform * contour.clone());
+    }
+    for component in instance.components.iter() {
+        let base = context
+            .ir
+            .glyphs
+            .get(&FeWorkId::Glyph(component.base.clone()));
+        add_outline(
+            context,
+            &base,
+            location,
+            transform * component.transform,
+            path,
+        );
+    }
+}
+
+/// Whether the outline of glyph, components included, overlaps itself at any of its sources
+fn glyph_overlaps(context: &Context, glyph: &ir::Glyph) -> bool {
+    glyph.sources().keys().any(|location| {
+        let mut path = BezPath::new();
+        add_outline(context, glyph, location, Affine::IDENTITY, &mut path);
+        has_overlaps(&path)
+    })
+}
+
+/// Set OVERLAP_SIMPLE on the first point of a compiled simple glyph.
+///
+/// write-fonts has no way to set the flag so we patch the bytes. If the first flag
+/// repeats it has to be split from the run, which may grow the glyph by a byte.
+///
+/// See <https://learn.microsoft.com/en-us/typography/opentype/spec/glyf#simple-glyph-description>
+pub(crate) fn set_overlap_simple(mut bytes: Vec<u8>) -> Vec<u8> {
+    let read_u16 = |bytes: &[u8], pos: usize| u16::from_be_bytes([bytes[pos], bytes[pos + 1]]);
+    let num_contours = read_u16(&bytes, 0) as i16;
+    assert!(
+        num_contours > 0,
+        "Only simple glyphs with contours have points"
+    );
+    let num_contours = num_contours as usize;
+    let end_pts = 10;
+    let num_points = read_u16(&bytes, end_pts + 2 * (num_contours - 1)) as usize + 1;
+    let instruction_len = read_u16(&bytes, end_pts + 2 * num_contours) as usize;
+    let flags_start = end_pts + 2 * num_contours + 2 + instruction_len;
+
+    // Find where the data ends so we can redo any padding
+    let mut pos = flags_start;
+    let mut point = 0;
+    let mut coords_len = 0;
+    while point < num_points {
+        let flag = glyf::SimpleGlyphFlags::from_bits_truncate(bytes[pos]);
+        pos += 1;
+        let mut count = 1;
+        if flag.contains(glyf::SimpleGlyphFlags::REPEAT_FLAG) {
+            count += bytes[pos] as usize;
+            pos += 1;
+        }
+        let x_len = if flag.contains(glyf::SimpleGlyphFlags::X_SHORT_VECTOR) {
+            1
+        } else if flag.contains(glyf::SimpleGlyphFlags::X_IS_SAME_OR_POSITIVE_X_SHORT_VECTOR) {
+            0
+        } else {
+            2
+        };
+        let y_len = if flag.contains(glyf::SimpleGlyphFlags::Y_SHORT_VECTOR) {
+            1
+        } else if flag.contains(glyf::SimpleGlyphFlags::Y_IS_SAME_OR_POSITIVE_Y_SHORT_VECTOR) {
+            0
+        } else {
+            2
+        };
+        coords_len += count * (x_len + y_len);
+        point += count;
+    }
+    bytes.truncate(pos + coords_len);
+
+    let overlap = glyf::SimpleGlyphFlags::OVERLAP_SIMPLE.bits();
+    let repeat = glyf::SimpleGlyphFlags::REPEAT_FLAG.bits();
+    let first = bytes[flags_start];
+    if first & repeat == 0 {
+        bytes[flags_start] |= overlap;
+    } else {
+        let single = first & !repeat;
+        let replacement = match bytes[flags_start + 1] {
+            1 => vec![single | overlap, single],
+            n => vec![single | overlap, first, n - 1],
+        };
+        bytes.splice(flags_start..flags_start + 2, replacement);
+    }
+
+    if bytes.len() % 2 == 1 {
+        bytes.push(0);
+    }
+    bytes
+}
+
 fn affine_for(component: &Component) -> Affine {
     let glyf::Anchor::Offset { x: dx, y: dy } = component.anchor else {
         panic!("Only offset anchor is supported");
@@ -834,19 +1077,25 @@ impl Work<Context, AnyWorkId, Error> for GlyfLocaWork {
         compute_composite_bboxes(context)?;
 
         let glyph_order = context.ir.glyph_order.get();
-        let mut builder = GlyfLocaBuilder::new();
 
+        // Glyphs may have overlap flags write-fonts doesn't know about so we glue the bytes
+        // of each glyph together ourselves rather than use a GlyfLocaBuilder
+        let mut raw_glyf = Vec::new();
+        let mut offsets = vec![0];
         for name in glyph_order.names() {
             let glyph = context
                 .glyphs
                 .get(&WorkId::GlyfFragment(name.clone()).into());
-            builder.add_glyph(&glyph.data).unwrap();
+            raw_glyf.extend(glyph.to_bytes());
+            if raw_glyf.len() % 2 == 1 {
+                raw_glyf.push(0);
+            }
+            offsets.push(raw_glyf.len() as u32);
         }
 
-        let (glyf, loca, loca_format) = builder.build();
+        let loca = Loca::new(offsets);
         let raw_loca = write_fonts::dump_table(&loca).unwrap();
-        let raw_glyf = write_fonts::dump_table(&glyf).unwrap();
-        context.loca_format.set(loca_format.into());
+        context.loca_format.set(loca.format().into());
         context.glyf.set(raw_glyf.into());
         context.loca.set(raw_loca.into());
 
@@ -865,7 +1114,10 @@ mod tests {
     use fontir::ir;
     use kurbo::{Affine, BezPath, PathEl};
     use rstest::rstest;
-    use write_fonts::types::Tag;
+    use write_fonts::{
+        read::{FontData, FontRead},
+        types::Tag,
+    };
 
     /// Returns a glyph instance and another one that can be its component
     fn create_reusable_component() -> (ir::GlyphInstance, ir::GlyphInstance) {
@@ -1003,4 +1255,32 @@ mod tests {
         };
         assert_eq!((0, 1), (x, y));
     }
+
+    fn read_simple(bytes: &[u8]) -> glyf::SimpleGlyph<'_> {
+        glyf::SimpleGlyph::read(FontData::new(bytes)).unwrap()
+    }
+
+    #[rstest]
+    #[case::no_repeat(&[(0.0, 0.0), (100.0, 0.0), (100.0, 100.0), (0.0, 100.0)])]
+    #[case::repeat_once(&[(10.0, 10.0), (20.0, 20.0), (100.0, 0.0)])]
+    #[case::repeat_many(&[(10.0, 10.0), (20.0, 20.0), (30.0, 30.0), (40.0, 40.0), (100.0, 0.0)])]
+    fn set_overlap_simple_keeps_points(#[case] points: &[(f64, f64)]) {
+        let mut path = BezPath::new();
+        path.move_to(points[0]);
+        for pt in &points[1..] {
+            path.line_to(*pt);
+        }
+        path.close_path();
+        let bytes = write_fonts::dump_table(&SimpleGlyph::from_bezpath(&path).unwrap()).unwrap();
+
+        let flagged = set_overlap_simple(bytes.clone());
+
+        assert_eq!(0, flagged.len() % 2);
+        assert!(!read_simple(&bytes).has_overlapping_contours());
+        assert!(read_simple(&flagged).has_overlapping_contours());
+        assert_eq!(
+            read_simple(&bytes).points().collect::<Vec<_>>(),
+            read_simple(&flagged).points().collect::<Vec<_>>()
+        );
+    }
 }
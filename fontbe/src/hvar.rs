@@ -10,13 +10,16 @@ use fontdrasil::{
     orchestration::{Access, Work},
     types::{Axis, GlyphName},
 };
-use fontir::{ir::Glyph, orchestration::WorkId as FeWorkId, variations::VariationModel};
+use fontir::{orchestration::WorkId as FeWorkId, variations::VariationModel};
 use write_fonts::types::MajorMinor;
 use write_fonts::{
     dump_table,
     tables::{
         hvar::Hvar,
-        variations::{ivs_builder::VariationStoreBuilder, DeltaSetIndexMap, VariationRegion},
+        variations::{
+            ivs_builder::VariationStoreBuilder, DeltaSetIndexMap, ItemVariationStore,
+            VariationRegion,
+        },
     },
     types::Tag,
     validate::Validate,
@@ -47,22 +50,22 @@ where
     Ok(data.len())
 }
 
-/// Helper to collect advance width deltas for all glyphs in a font
-struct AdvanceWidthDeltas {
+/// Helper to collect the deltas of a per-glyph metric, such as advance width, for all glyphs in a font
+pub(crate) struct GlyphMetricDeltas {
     /// Variation axes
     axes: Vec<Axis>,
     /// Set of axis tags
     axis_tags: BTreeSet<Tag>,
     /// Sparse variation models, keyed by the set of locations they define
     models: HashMap<BTreeSet<NormalizedLocation>, VariationModel>,
-    /// Glyph's advance width deltas sorted by glyph order
+    /// Glyph's metric deltas sorted by glyph order
     deltas: Vec<Vec<(VariationRegion, i16)>>,
     /// All the glyph locations that are defined in the font
     glyph_locations: HashSet<NormalizedLocation>,
 }
 
-impl AdvanceWidthDeltas {
-    fn new<'a>(
+impl GlyphMetricDeltas {
+    pub(crate) fn new<'a>(
         global_model: VariationModel,
         glyph_locations: impl IntoIterator<Item = &'a NormalizedLocation>,
     ) -> Self {
@@ -78,7 +81,7 @@ impl AdvanceWidthDeltas {
         let global_locations = global_model.locations().cloned().collect::<BTreeSet<_>>();
         let mut models = HashMap::new();
         models.insert(global_locations, global_model);
-        AdvanceWidthDeltas {
+        GlyphMetricDeltas {
             axes,
             axis_tags,
             models,
@@ -87,18 +90,19 @@ impl AdvanceWidthDeltas {
         }
     }
 
-    fn add(&mut self, glyph: &Glyph) -> Result<(), Error> {
-        let mut advance_widths: HashMap<_, Vec<f64>> = glyph
-            .sources()
-            .iter()
-            // widths must be rounded before the computing deltas to match fontmake
-            // https://github.com/googlefonts/fontc/issues/1043
-            .map(|(loc, src)| (loc.subset_axes(&self.axis_tags), vec![src.width.ot_round()]))
+    /// Add the next glyph in glyph order, given its metric at each of its locations.
+    pub(crate) fn add(
+        &mut self,
+        name: &GlyphName,
+        values: impl IntoIterator<Item = (NormalizedLocation, f64)>,
+    ) -> Result<(), Error> {
+        let mut values: HashMap<_, Vec<f64>> = values
+            .into_iter()
+            .map(|(loc, value)| (loc.subset_axes(&self.axis_tags), vec![value]))
             .collect();
-        let name = glyph.name.clone();
         let i = self.deltas.len();
-        if advance_widths.len() == 1 {
-            assert!(advance_widths.keys().next().unwrap().is_default());
+        if values.len() == 1 {
+            assert!(values.keys().next().unwrap().is_default());
             // this glyph has no variations (it's only defined at the default location),
             // therefore the deltas returned from VariationModel will be an empty Vec.
             // However, when this is the first .notdef glyph we would like to treat it
@@ -112,12 +116,12 @@ impl AdvanceWidthDeltas {
             // So, to match the VarRegionList produced by fontTools, we need to make the deltaset
             // for the first .notdef glyph similarly "dense", by copying its default instance to
             // all other glyph locations...
-            if i == 0 && name == GlyphName::NOTDEF {
-                let notdef_width = advance_widths.values().next().unwrap()[0];
+            if i == 0 && *name == GlyphName::NOTDEF {
+                let notdef_value = values.values().next().unwrap()[0];
                 for loc in self.glyph_locations.iter() {
-                    advance_widths
+                    values
                         .entry(loc.clone())
-                        .or_insert_with(|| vec![notdef_width]);
+                        .or_insert_with(|| vec![notdef_value]);
                 }
             } else {
                 // spare the model the work of computing no-op deltas
@@ -125,15 +129,14 @@ impl AdvanceWidthDeltas {
                 return Ok(());
             }
         }
-        let locations = advance_widths.keys().cloned().collect::<BTreeSet<_>>();
+        let locations = values.keys().cloned().collect::<BTreeSet<_>>();
         let model = self.models.entry(locations).or_insert_with(|| {
             // this glyph defines its own set of locations, a new sparse model is needed
-            VariationModel::new(advance_widths.keys().cloned().collect(), self.axes.clone())
-                .unwrap()
+            VariationModel::new(values.keys().cloned().collect(), self.axes.clone()).unwrap()
         });
         self.deltas.push(
             model
-                .deltas(&advance_widths)
+                .deltas(&values)
                 .map_err(|e| Error::GlyphDeltaError(name.clone(), e))?
                 .into_iter()
                 .filter_map(|(region, values)| {
@@ -156,11 +159,74 @@ impl AdvanceWidthDeltas {
         self.models.len() == 1
     }
 
-    fn iter(&self) -> impl Iterator<Item = &Vec<(VariationRegion, i16)>> {
+    /// True if no glyph varies
+    pub(crate) fn is_empty(&self) -> bool {
+        self.deltas
+            .iter()
+            .all(|deltas| deltas.iter().all(|(_, delta)| *delta == 0))
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &Vec<(VariationRegion, i16)>> {
         self.deltas.iter()
     }
 }
 
+/// Build a variation store holding the deltas of every glyph, and the map from glyph id to deltas.
+///
+/// If it's smaller the store is indexed directly by glyph id and there is no map.
+pub(crate) fn build_varstore(
+    glyph_deltas: &GlyphMetricDeltas,
+    axis_count: u16,
+) -> Result<(ItemVariationStore, Option<DeltaSetIndexMap>), Error> {
+    // if we have a single model, we can try to build a VariationStore with implicit variation
+    // indices (a single ItemVariationData, outer index 0, inner index => gid).
+    let mut var_idxes = Vec::new();
+    let direct_store = if glyph_deltas.is_single_model() {
+        let mut direct_builder = VariationStoreBuilder::new_with_implicit_indices(axis_count);
+        for deltas in glyph_deltas.iter() {
+            var_idxes.push(direct_builder.add_deltas(deltas.clone()));
+        }
+        // sanity checks
+        assert_eq!(var_idxes.len(), glyph_deltas.deltas.len());
+        assert!(var_idxes
+            .drain(..)
+            .enumerate()
+            .all(|(i, idx)| i as u32 == idx));
+        // we don't use the returned (identity) map in this case
+        Some(direct_builder.build().0)
+    } else {
+        None
+    };
+
+    // also build an indirect VariationStore with a DeltaSetIndexMap to map gid => varidx
+    let mut indirect_builder = VariationStoreBuilder::new(axis_count);
+    for deltas in glyph_deltas.iter() {
+        var_idxes.push(indirect_builder.add_deltas(deltas.clone()));
+    }
+    let (indirect_store, varidx_map) = indirect_builder.build();
+
+    // unwrap since VariationStoreBuilder guarantees that any temporary index returned by
+    // add_deltas will exist in the returned map
+    let varidx_map: DeltaSetIndexMap = var_idxes
+        .into_iter()
+        .map(|idx| varidx_map.get(idx).unwrap())
+        .collect();
+
+    // Default to indirect, switch to direct if it's available and smaller
+    let (mut varidx_map, mut varstore) = (Some(varidx_map), indirect_store);
+    if let Some(direct_store) = direct_store {
+        let direct_store_size = table_size(&direct_store)?;
+        let indirect_store_size = table_size(&varstore)?;
+        let varidx_map_size = table_size(&varidx_map)?;
+
+        if direct_store_size <= indirect_store_size + varidx_map_size {
+            varidx_map = None;
+            varstore = direct_store;
+        }
+    }
+    Ok((varstore, varidx_map))
+}
+
 impl Work<Context, AnyWorkId, Error> for HvarWork {
     fn id(&self) -> AnyWorkId {
         WorkId::Hvar.into()
@@ -186,57 +252,19 @@ impl Work<Context, AnyWorkId, Error> for HvarWork {
             .collect();
         let glyph_locations = glyphs.iter().flat_map(|glyph| glyph.sources().keys());
 
-        let mut glyph_width_deltas = AdvanceWidthDeltas::new(var_model.clone(), glyph_locations);
+        let mut glyph_width_deltas = GlyphMetricDeltas::new(var_model.clone(), glyph_locations);
         for glyph in glyphs.into_iter() {
-            glyph_width_deltas.add(glyph.as_ref())?;
-        }
-
-        // if we have a single model, we can try to build a VariationStore with implicit variation
-        // indices (a single ItemVariationData, outer index 0, inner index => gid).
-        let mut var_idxes = Vec::new();
-        let direct_store = if glyph_width_deltas.is_single_model() {
-            let mut direct_builder = VariationStoreBuilder::new_with_implicit_indices(axis_count);
-            for deltas in glyph_width_deltas.iter() {
-                var_idxes.push(direct_builder.add_deltas(deltas.clone()));
-            }
-            // sanity checks
-            assert_eq!(var_idxes.len(), glyph_order.len());
-            assert!(var_idxes
-                .drain(..)
-                .enumerate()
-                .all(|(i, idx)| i as u32 == idx));
-            // we don't use the returned (identity) map in this case
-            Some(direct_builder.build().0)
-        } else {
-            None
-        };
-
-        // also build an indirect VariationStore with a DeltaSetIndexMap to map gid => varidx
-        let mut indirect_builder = VariationStoreBuilder::new(axis_count);
-        for deltas in glyph_width_deltas.iter() {
-            var_idxes.push(indirect_builder.add_deltas(deltas.clone()));
-        }
-        let (indirect_store, varidx_map) = indirect_builder.build();
-
-        // unwrap since VariationStoreBuilder guarantees that any temporary index returned by
-        // add_deltas will exist in the returned map
-        let varidx_map: DeltaSetIndexMap = var_idxes
-            .into_iter()
-            .map(|idx| varidx_map.get(idx).unwrap())
-            .collect();
-
-        // Default to indirect, switch to direct if it's available and smaller
-        let (mut varidx_map, mut varstore) = (Some(varidx_map), indirect_store);
-        if let Some(direct_store) = direct_store {
-            let direct_store_size = table_size(&direct_store)?;
-            let indirect_store_size = table_size(&varstore)?;
-            let varidx_map_size = table_size(&varidx_map)?;
-
-            if direct_store_size <= indirect_store_size + varidx_map_size {
-                varidx_map = None;
-                varstore = direct_store;
-            }
+            glyph_width_deltas.add(
+                &glyph.name,
+                glyph
+                    .sources()
+                    .iter()
+                    // widths must be rounded before the computing deltas to match fontmake
+                    // https://github.com/googlefonts/fontc/issues/1043
+                    .map(|(loc, src)| (loc.clone(), src.width.ot_round())),
+            )?;
         }
+        let (varstore, varidx_map) = build_varstore(&glyph_width_deltas, axis_count)?;
 
         let hvar = Hvar::new(MajorMinor::VERSION_1_0, varstore, varidx_map, None, None);
         context.hvar.set(hvar);
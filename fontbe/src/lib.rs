@@ -1,5 +1,6 @@
 //! Backend of the `fontc` font compiler.
 pub mod avar;
+pub mod cff;
 pub mod cmap;
 pub mod colr;
 pub mod cpal;
@@ -18,8 +19,14 @@ pub mod mvar;
 pub mod name;
 pub mod orchestration;
 pub mod os2;
+pub mod overlaps;
 pub mod paths;
 pub mod post;
 pub mod stat;
 #[cfg(test)]
 mod test_util;
+pub mod varc;
+pub mod vertical_metrics;
+pub mod vertical_tables;
+pub mod vvar;
+pub mod woff;
@@ -9,7 +9,7 @@ use std::{
 };
 
 use fontdrasil::orchestration::{Access, AccessBuilder, Work};
-use fontir::orchestration::WorkId as FeWorkId;
+use fontir::orchestration::{Flags, WorkId as FeWorkId};
 use write_fonts::{
     dump_table,
     tables::{
@@ -24,6 +24,7 @@ use write_fonts::{
 };
 
 use crate::{
+    cff::charstring_bbox,
     error::Error,
     orchestration::{AnyWorkId, BeWork, Context, Glyph, WorkId},
 };
@@ -82,33 +83,7 @@ impl GlyphLimits {
 
 impl FontLimits {
     fn update(&mut self, id: GlyphId16, advance: u16, glyph: &Glyph) {
-        // max advance width should consider every glyph that has an hmtx entry
-        self.advance_width_max = max(self.advance_width_max, advance);
-
-        // min side bearings are only for non-empty glyphs
-        // we will presume only simple glyphs with no contours are empty
-        if let Some(bbox) = glyph.data.bbox() {
-            let left_side_bearing = bbox.x_min;
-            // aw - (lsb + xMax - xMin) ... but if lsb == xMin then just advance - xMax?
-            let right_side_bearing: i16 = match advance as i32 - bbox.x_max as i32 {
-                value if value < i16::MIN as i32 => i16::MIN,
-                value if value > i16::MAX as i32 => i16::MAX,
-                value => value as i16,
-            };
-            self.min_left_side_bearing = self
-                .min_left_side_bearing
-                .map(|v| min(v, left_side_bearing))
-                .or(Some(left_side_bearing));
-            self.min_right_side_bearing = self
-                .min_right_side_bearing
-                .map(|v| min(v, right_side_bearing))
-                .or(Some(right_side_bearing));
-            self.x_max_extent = self
-                .x_max_extent
-                .map(|v| max(v, bbox.x_max))
-                .or(Some(bbox.x_max));
-            self.bbox = self.bbox.map(|b| b.union(bbox)).or(Some(bbox));
-        }
+        self.update_metrics(advance, glyph.data.bbox());
 
         let glyph_info = match &glyph.data {
             RawGlyph::Simple(simple) => {
@@ -142,6 +117,39 @@ impl FontLimits {
         self.glyph_info.insert(id, glyph_info);
     }
 
+    /// Update the metrics of the font for a glyph with the given advance and bounds.
+    ///
+    /// Separate from [`FontLimits::update`] because CFF outlines have no glyf [Glyph].
+    fn update_metrics(&mut self, advance: u16, bbox: Option<Bbox>) {
+        // max advance width should consider every glyph that has an hmtx entry
+        self.advance_width_max = max(self.advance_width_max, advance);
+
+        // min side bearings are only for non-empty glyphs
+        // we will presume only simple glyphs with no contours are empty
+        if let Some(bbox) = bbox {
+            let left_side_bearing = bbox.x_min;
+            // aw - (lsb + xMax - xMin) ... but if lsb == xMin then just advance - xMax?
+            let right_side_bearing: i16 = match advance as i32 - bbox.x_max as i32 {
+                value if value < i16::MIN as i32 => i16::MIN,
+                value if value > i16::MAX as i32 => i16::MAX,
+                value => value as i16,
+            };
+            self.min_left_side_bearing = self
+                .min_left_side_bearing
+                .map(|v| min(v, left_side_bearing))
+                .or(Some(left_side_bearing));
+            self.min_right_side_bearing = self
+                .min_right_side_bearing
+                .map(|v| min(v, right_side_bearing))
+                .or(Some(right_side_bearing));
+            self.x_max_extent = self
+                .x_max_extent
+                .map(|v| max(v, bbox.x_max))
+                .or(Some(bbox.x_max));
+            self.bbox = self.bbox.map(|b| b.union(bbox)).or(Some(bbox));
+        }
+    }
+
     // FontTools maxp <https://github.com/fonttools/fonttools/blob/e8146a6d0725d398cfa110cba683946ee762f8e2/Lib/fontTools/ttLib/tables/_m_a_x_p.py#L53>
     fn update_composite_limits(&mut self) -> GlyphLimits {
         let mut pending = self
@@ -193,6 +201,35 @@ impl FontLimits {
     }
 }
 
+/// Remove the run of metrics at the end that repeat the last advance, returning their side bearings.
+///
+/// Only the side bearing need be stored for these, which saves some bytes in hmtx and vmtx.
+pub(crate) fn split_off_side_bearings(long_metrics: &mut Vec<LongMetric>) -> Vec<i16> {
+    // If there's a run at the end with matching advances we can save some bytes
+    let num_side_bearing_only = if !long_metrics.is_empty() {
+        let last_advance = long_metrics.last().unwrap().advance;
+        let mut run = 0;
+        for metric in long_metrics.iter().rev() {
+            if metric.advance != last_advance {
+                break;
+            }
+            run += 1;
+        }
+
+        // Carve 1 less than the length of the run off so the last metric retained has the advance
+        // that repeats
+        run - 1
+    } else {
+        0
+    };
+
+    long_metrics
+        .split_off(long_metrics.len() - num_side_bearing_only)
+        .into_iter()
+        .map(|metric| metric.side_bearing)
+        .collect()
+}
+
 impl Work<Context, AnyWorkId, Error> for MetricAndLimitWork {
     fn id(&self) -> AnyWorkId {
         WorkId::Hmtx.into()
@@ -238,48 +275,30 @@ impl Work<Context, AnyWorkId, Error> for MetricAndLimitWork {
             .at(static_metadata.default_location());
 
         let mut glyph_limits = FontLimits::default();
+        let cff_outlines = context.flags.contains(Flags::CFF_OUTLINES);
 
         let mut long_metrics: Vec<LongMetric> = glyph_order
             .iter()
             .map(|(gid, gn)| {
-                let advance: u16 = context
-                    .ir
-                    .get_glyph(gn.clone())
-                    .default_instance()
-                    .width
-                    .ot_round();
-                let glyph = context.glyphs.get(&WorkId::GlyfFragment(gn.clone()).into());
-                glyph_limits.update(gid, advance, &glyph);
+                let ir_glyph = context.ir.get_glyph(gn.clone());
+                let advance: u16 = ir_glyph.default_instance().width.ot_round();
+                let bbox = if cff_outlines {
+                    let bbox = charstring_bbox(ir_glyph.default_instance());
+                    glyph_limits.update_metrics(advance, bbox);
+                    bbox
+                } else {
+                    let glyph = context.glyphs.get(&WorkId::GlyfFragment(gn.clone()).into());
+                    glyph_limits.update(gid, advance, &glyph);
+                    glyph.data.bbox()
+                };
                 LongMetric {
                     advance,
-                    side_bearing: glyph.data.bbox().map(|bbox| bbox.x_min).unwrap_or_default(),
+                    side_bearing: bbox.map(|bbox| bbox.x_min).unwrap_or_default(),
                 }
             })
             .collect();
 
-        // If there's a run at the end with matching advances we can save some bytes
-        let num_lsb_only = if !long_metrics.is_empty() {
-            let last_advance = long_metrics.last().unwrap().advance;
-            let mut lsb_run = 0;
-            for metric in long_metrics.iter().rev() {
-                if metric.advance != last_advance {
-                    break;
-                }
-                lsb_run += 1;
-            }
-
-            // Carve 1 less than the length of the run off so the last metric retained has the advance
-            // that repeats
-            lsb_run - 1
-        } else {
-            0
-        };
-
-        let lsbs = long_metrics
-            .split_off(long_metrics.len() - num_lsb_only)
-            .into_iter()
-            .map(|metric| metric.side_bearing)
-            .collect();
+        let lsbs = split_off_side_bearings(&mut long_metrics);
 
         // Before we cede ownership of Hmtx grab a few notes for Hhea
         let min_left_side_bearing = glyph_limits
@@ -321,24 +340,30 @@ impl Work<Context, AnyWorkId, Error> for MetricAndLimitWork {
         context.hmtx.set(raw_hmtx);
 
         // Might as well do maxp while we're here
+        let num_glyphs = glyph_order.len().try_into().unwrap();
         let composite_limits = glyph_limits.update_composite_limits();
-        let maxp = Maxp {
-            num_glyphs: glyph_order.len().try_into().unwrap(),
-            // maxp computes it's version based on whether fields are set
-            // if you fail to set any of them it gets angry with you so set all of them
-            max_points: Some(glyph_limits.max_points),
-            max_contours: Some(glyph_limits.max_contours),
-            max_composite_points: Some(composite_limits.max_points),
-            max_composite_contours: Some(composite_limits.max_contours),
-            max_zones: Some(1),
-            max_twilight_points: Some(0),
-            max_storage: Some(0),
-            max_function_defs: Some(0),
-            max_instruction_defs: Some(0),
-            max_stack_elements: Some(0),
-            max_size_of_instructions: Some(0),
-            max_component_elements: Some(glyph_limits.max_component_elements),
-            max_component_depth: Some(composite_limits.max_depth),
+        let maxp = if cff_outlines {
+            // CFF fonts use version 0.5, which only has the glyph count
+            Maxp::new(num_glyphs)
+        } else {
+            Maxp {
+                num_glyphs,
+                // maxp computes it's version based on whether fields are set
+                // if you fail to set any of them it gets angry with you so set all of them
+                max_points: Some(glyph_limits.max_points),
+                max_contours: Some(glyph_limits.max_contours),
+                max_composite_points: Some(composite_limits.max_points),
+                max_composite_contours: Some(composite_limits.max_contours),
+                max_zones: Some(1),
+                max_twilight_points: Some(0),
+                max_storage: Some(0),
+                max_function_defs: Some(0),
+                max_instruction_defs: Some(0),
+                max_stack_elements: Some(0),
+                max_size_of_instructions: Some(0),
+                max_component_elements: Some(glyph_limits.max_component_elements),
+                max_component_depth: Some(composite_limits.max_depth),
+            }
         };
         context.maxp.set(maxp);
 
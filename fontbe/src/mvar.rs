@@ -14,6 +14,9 @@ use fontir::{
 };
 use write_fonts::types::MajorMinor;
 use write_fonts::{
+    dump_table,
+    from_obj::FromObjRef,
+    read::{tables::mvar::Mvar as ReadMvar, FontData, FontRead, ReadError},
     tables::{
         mvar::{Mvar, ValueRecord},
         variations::{ivs_builder::VariationStoreBuilder, VariationRegion},
@@ -98,6 +101,44 @@ impl MvarBuilder {
         Ok(())
     }
 
+    /// Take the deltas of every metric the MVAR compiled from FEA has, in place of
+    /// those computed from sources, as FEA is where the user spelled them out.
+    fn add_fea_deltas(&mut self, mvar: &Mvar) -> Result<(), Error> {
+        let bytes = dump_table(mvar).map_err(|e| Error::DumpTableError {
+            e,
+            context: "MVAR from FEA".to_string(),
+        })?;
+        let mvar = ReadMvar::read(FontData::new(&bytes))?;
+        let Some(varstore) = mvar.item_variation_store().transpose()? else {
+            return Ok(());
+        };
+        let regions = varstore.variation_region_list()?.variation_regions();
+        for record in mvar.value_records() {
+            let Some(var_data) = varstore
+                .item_variation_data()
+                .get(record.delta_set_outer_index() as usize)
+                .transpose()?
+            else {
+                continue;
+            };
+            let deltas = var_data
+                .region_indexes()
+                .iter()
+                .zip(var_data.delta_set(record.delta_set_inner_index()))
+                .map(|(region_idx, delta)| {
+                    let region = regions.get(region_idx.get() as usize)?;
+                    // the deltas were i16 when fea-rs added them
+                    Ok((
+                        VariationRegion::from_obj_ref(&region, FontData::new(&[])),
+                        delta as i16,
+                    ))
+                })
+                .collect::<Result<Vec<_>, ReadError>>()?;
+            self.deltas.insert(record.value_tag(), deltas);
+        }
+        Ok(())
+    }
+
     fn build(self) -> Option<Mvar> {
         let mut builder = VariationStoreBuilder::new(self.axes.len() as u16);
         let delta_ids = self
@@ -133,6 +174,7 @@ impl Work<Context, AnyWorkId, Error> for MvarWork {
         AccessBuilder::new()
             .variant(FeWorkId::StaticMetadata)
             .variant(FeWorkId::GlobalMetrics)
+            .variant(WorkId::ExtraFeaTables)
             .build()
     }
 
@@ -146,12 +188,25 @@ impl Work<Context, AnyWorkId, Error> for MvarWork {
 
         let mut mvar_builder = MvarBuilder::new(var_model.clone());
         for (metric, values) in metrics.iter() {
+            // vertical metrics only mean something if we build vertical tables
+            if metric.is_vertical() && !static_metadata.misc.build_vertical {
+                continue;
+            }
             // some of the GlobalMetric variants are not MVAR-relevant, e.g.
             // hhea ascender/descender/lineGap so we just skip those
             if let Some(mvar_tag) = metric.mvar_tag() {
                 mvar_builder.add_sources(mvar_tag, values)?;
             }
         }
+        if let Some(mvar) = context
+            .extra_fea_tables
+            .try_get()
+            .as_ref()
+            .and_then(|tables| tables.mvar.as_ref())
+        {
+            log::info!("merging MVAR deltas from FEA");
+            mvar_builder.add_fea_deltas(mvar)?;
+        }
         if let Some(mvar) = mvar_builder.build() {
             context.mvar.set(mvar);
         }
@@ -258,6 +313,50 @@ mod tests {
         assert_eq!(delta_sets(&vardata), vec![vec![50]]);
     }
 
+    #[test]
+    fn fea_deltas_replace_those_from_sources() {
+        let regular = NormalizedLocation::for_pos(&[("wght", 0.0)]);
+        let bold = NormalizedLocation::for_pos(&[("wght", 1.0)]);
+        let axes = vec![axis("wght", 400.0, 400.0, 700.0)];
+        let mut fea_builder = new_mvar_builder(vec![&regular, &bold], axes.clone());
+        add_sources(
+            &mut fea_builder,
+            "xhgt",
+            &[(&regular, 500.0), (&bold, 580.0)],
+        );
+        let fea_mvar = fea_builder.build().unwrap();
+
+        let mut builder = new_mvar_builder(vec![&regular, &bold], axes);
+        add_sources(&mut builder, "xhgt", &[(&regular, 500.0), (&bold, 550.0)]);
+        add_sources(&mut builder, "cpht", &[(&regular, 700.0), (&bold, 720.0)]);
+        builder.add_fea_deltas(&fea_mvar).unwrap();
+
+        let bytes = dump_table(&builder.build().unwrap()).unwrap();
+        let mvar = read_mvar::Mvar::read(FontData::new(&bytes)).unwrap();
+        let varstore = mvar.item_variation_store().unwrap().unwrap();
+        let deltas: Vec<_> = mvar
+            .value_records()
+            .iter()
+            .map(|rec| {
+                let var_data = varstore
+                    .item_variation_data()
+                    .get(rec.delta_set_outer_index() as usize)
+                    .unwrap()
+                    .unwrap();
+                (
+                    rec.value_tag(),
+                    var_data
+                        .delta_set(rec.delta_set_inner_index())
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            vec![(Tag::new(b"cpht"), vec![20]), (Tag::new(b"xhgt"), vec![80])],
+            deltas
+        );
+    }
+
     #[test]
     fn no_variations_no_mvar() {
         let regular = NormalizedLocation::for_pos(&[("wght", 0.0)]);
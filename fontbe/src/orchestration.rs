@@ -67,7 +67,13 @@ use write_fonts::{
     FontWrite,
 };
 
-use crate::{avar::PossiblyEmptyAvar, error::Error, paths::Paths};
+use crate::{
+    avar::PossiblyEmptyAvar,
+    error::Error,
+    glyphs::set_overlap_simple,
+    paths::Paths,
+    vertical_tables::{Vorg, Vvar},
+};
 
 type KernBlock = usize;
 
@@ -80,6 +86,8 @@ pub enum WorkId {
     Features,
     FeaturesAst,
     Avar,
+    Cff,
+    Cff2,
     Cmap,
     Colr,
     Cpal,
@@ -110,6 +118,11 @@ pub enum WorkId {
     Os2,
     Post,
     Stat,
+    Varc,
+    Vhea,
+    Vmtx,
+    Vorg,
+    Vvar,
     ExtraFeaTables,
 }
 
@@ -128,6 +141,8 @@ impl Identifier for WorkId {
             WorkId::Meta => "BeMeta",
             WorkId::FeaturesAst => "BeFeaturesAst",
             WorkId::Avar => "BeAvar",
+            WorkId::Cff => "BeCff",
+            WorkId::Cff2 => "BeCff2",
             WorkId::Cmap => "BeCmap",
             WorkId::Colr => "BeColr",
             WorkId::Cpal => "BeCpal",
@@ -157,6 +172,11 @@ impl Identifier for WorkId {
             WorkId::Os2 => "BeOs2",
             WorkId::Post => "BePost",
             WorkId::Stat => "BeStat",
+            WorkId::Varc => "BeVarc",
+            WorkId::Vhea => "BeVhea",
+            WorkId::Vmtx => "BeVmtx",
+            WorkId::Vorg => "BeVorg",
+            WorkId::Vvar => "BeVvar",
             WorkId::ExtraFeaTables => "ExtraFeaTables",
         }
     }
@@ -219,6 +239,7 @@ pub struct ExtraFeaTables {
     pub hhea: Option<Hhea>,
     pub vhea: Option<Vhea>,
     pub os2: Option<Os2>,
+    pub mvar: Option<Mvar>,
     pub base: Option<Base>,
     pub stat: Option<Stat>,
 }
@@ -230,6 +251,7 @@ impl From<Compilation> for ExtraFeaTables {
             hhea,
             vhea,
             os2,
+            mvar,
             base,
             name,
             stat,
@@ -240,6 +262,7 @@ impl From<Compilation> for ExtraFeaTables {
             hhea,
             vhea,
             os2,
+            mvar,
             base,
             stat,
             name,
@@ -273,7 +296,7 @@ impl Persistable for ExtraFeaTables {
             Some(T::read(bytes.into()).unwrap())
         }
 
-        let [head, hhea, vhea, os2, base, stat, name]: [Option<Vec<u8>>; 7] =
+        let [head, hhea, vhea, os2, mvar, base, stat, name]: [Option<Vec<u8>>; 8] =
             bincode::deserialize_from(from).unwrap();
 
         Self {
@@ -281,6 +304,7 @@ impl Persistable for ExtraFeaTables {
             hhea: read_table(hhea.as_ref()),
             vhea: read_table(vhea.as_ref()),
             os2: read_table(os2.as_ref()),
+            mvar: read_table(mvar.as_ref()),
             base: read_table(base.as_ref()),
             stat: read_table(stat.as_ref()),
             name: read_table(name.as_ref()),
@@ -296,6 +320,7 @@ impl Persistable for ExtraFeaTables {
             hhea,
             vhea,
             os2,
+            mvar,
             base,
             stat,
             name,
@@ -306,6 +331,7 @@ impl Persistable for ExtraFeaTables {
             dump(hhea.as_ref()),
             dump(vhea.as_ref()),
             dump(os2.as_ref()),
+            dump(mvar.as_ref()),
             dump(base.as_ref()),
             dump(stat.as_ref()),
             dump(name.as_ref()),
@@ -322,6 +348,10 @@ impl Persistable for ExtraFeaTables {
 pub struct Glyph {
     pub name: GlyphName,
     pub data: RawGlyph,
+    /// Set OVERLAP_SIMPLE on the first point of a simple glyph when compiled.
+    ///
+    /// Kept alongside the data because write-fonts doesn't model the flag.
+    pub overlap_simple: bool,
 }
 
 impl Glyph {
@@ -329,9 +359,17 @@ impl Glyph {
         Self {
             name,
             data: glyph.into(),
+            overlap_simple: false,
         }
     }
 
+    pub(crate) fn with_overlap_simple(mut self, overlap_simple: bool) -> Self {
+        // an empty glyph has no point to flag
+        self.overlap_simple = overlap_simple
+            && matches!(&self.data, RawGlyph::Simple(simple) if !simple.contours.is_empty());
+        self
+    }
+
     pub fn is_simple(&self) -> bool {
         matches!(&self.data, RawGlyph::Simple(_))
     }
@@ -341,7 +379,12 @@ impl Glyph {
     }
 
     pub fn to_bytes(&self) -> Vec<u8> {
-        dump_table(&self.data).unwrap()
+        let bytes = dump_table(&self.data).unwrap();
+        if self.overlap_simple {
+            set_overlap_simple(bytes)
+        } else {
+            bytes
+        }
     }
 }
 
@@ -353,14 +396,24 @@ impl IdAware<AnyWorkId> for Glyph {
 
 impl Persistable for Glyph {
     fn read(from: &mut dyn Read) -> Self {
-        let (name, bytes): (GlyphName, Vec<u8>) = bincode::deserialize_from(from).unwrap();
-        let glyph = FontRead::read(bytes.as_slice().into()).unwrap();
-        Glyph { name, data: glyph }
+        let (name, overlap_simple, bytes): (GlyphName, bool, Vec<u8>) =
+            bincode::deserialize_from(from).unwrap();
+        // an empty glyph writes no bytes at all, which isn't something we can read back
+        let data = if bytes.is_empty() {
+            RawGlyph::Empty
+        } else {
+            FontRead::read(bytes.as_slice().into()).unwrap()
+        };
+        Glyph {
+            name,
+            data,
+            overlap_simple,
+        }
     }
 
     fn write(&self, to: &mut dyn Write) {
         let glyph_bytes = dump_table(&self.data).unwrap();
-        let to_write = (&self.name, glyph_bytes);
+        let to_write = (&self.name, self.overlap_simple, glyph_bytes);
         bincode::serialize_into(to, &to_write).unwrap();
     }
 }
@@ -864,6 +917,8 @@ pub struct Context {
 
     // Allow avar to be explicitly None to record a noop avar being generated
     pub avar: BeContextItem<PossiblyEmptyAvar>,
+    pub cff: BeContextItem<Bytes>,
+    pub cff2: BeContextItem<Bytes>,
     pub cmap: BeContextItem<Cmap>,
     pub colr: BeContextItem<Colr>,
     pub cpal: BeContextItem<Cpal>,
@@ -893,6 +948,11 @@ pub struct Context {
     pub fea_rs_marks: BeContextItem<FeaRsMarks>,
     pub extra_fea_tables: BeContextItem<ExtraFeaTables>,
     pub stat: BeContextItem<Stat>,
+    pub varc: BeContextItem<Bytes>,
+    pub vhea: BeContextItem<Vhea>,
+    pub vmtx: BeContextItem<Bytes>,
+    pub vorg: BeContextItem<Vorg>,
+    pub vvar: BeContextItem<Vvar>,
     pub font: BeContextItem<Bytes>,
 }
 
@@ -906,6 +966,8 @@ impl Context {
             glyphs: self.glyphs.clone_with_acl(acl.clone()),
             gvar_fragments: self.gvar_fragments.clone_with_acl(acl.clone()),
             avar: self.avar.clone_with_acl(acl.clone()),
+            cff: self.cff.clone_with_acl(acl.clone()),
+            cff2: self.cff2.clone_with_acl(acl.clone()),
             cmap: self.cmap.clone_with_acl(acl.clone()),
             colr: self.colr.clone_with_acl(acl.clone()),
             cpal: self.cpal.clone_with_acl(acl.clone()),
@@ -933,6 +995,11 @@ impl Context {
             fea_rs_kerns: self.fea_rs_kerns.clone_with_acl(acl.clone()),
             fea_rs_marks: self.fea_rs_marks.clone_with_acl(acl.clone()),
             stat: self.stat.clone_with_acl(acl.clone()),
+            varc: self.varc.clone_with_acl(acl.clone()),
+            vhea: self.vhea.clone_with_acl(acl.clone()),
+            vmtx: self.vmtx.clone_with_acl(acl.clone()),
+            vorg: self.vorg.clone_with_acl(acl.clone()),
+            vvar: self.vvar.clone_with_acl(acl.clone()),
             fea_ast: self.fea_ast.clone_with_acl(acl.clone()),
             extra_fea_tables: self.extra_fea_tables.clone_with_acl(acl.clone()),
             font: self.font.clone_with_acl(acl),
@@ -952,6 +1019,8 @@ impl Context {
             glyphs: ContextMap::new(acl.clone(), persistent_storage.clone()),
             gvar_fragments: ContextMap::new(acl.clone(), persistent_storage.clone()),
             avar: ContextItem::new(WorkId::Avar.into(), acl.clone(), persistent_storage.clone()),
+            cff: ContextItem::new(WorkId::Cff.into(), acl.clone(), persistent_storage.clone()),
+            cff2: ContextItem::new(WorkId::Cff2.into(), acl.clone(), persistent_storage.clone()),
             cmap: ContextItem::new(WorkId::Cmap.into(), acl.clone(), persistent_storage.clone()),
             colr: ContextItem::new(WorkId::Colr.into(), acl.clone(), persistent_storage.clone()),
             cpal: ContextItem::new(WorkId::Cpal.into(), acl.clone(), persistent_storage.clone()),
@@ -1000,6 +1069,11 @@ impl Context {
                 persistent_storage.clone(),
             ),
             stat: ContextItem::new(WorkId::Stat.into(), acl.clone(), persistent_storage.clone()),
+            varc: ContextItem::new(WorkId::Varc.into(), acl.clone(), persistent_storage.clone()),
+            vhea: ContextItem::new(WorkId::Vhea.into(), acl.clone(), persistent_storage.clone()),
+            vmtx: ContextItem::new(WorkId::Vmtx.into(), acl.clone(), persistent_storage.clone()),
+            vorg: ContextItem::new(WorkId::Vorg.into(), acl.clone(), persistent_storage.clone()),
+            vvar: ContextItem::new(WorkId::Vvar.into(), acl.clone(), persistent_storage.clone()),
             extra_fea_tables: ContextItem::new(
                 WorkId::ExtraFeaTables.into(),
                 acl.clone(),
@@ -0,0 +1,217 @@
+//! Detect whether the contours of a glyph overlap
+//!
+//! Rasterizers that don't use nonzero winding everywhere, notably on macOS and
+//! in FreeType when asked to, render overlaps with dropouts unless glyf marks them with
+//! [OVERLAP_SIMPLE or OVERLAP_COMPOUND](https://learn.microsoft.com/en-us/typography/opentype/spec/glyf).
+
+use kurbo::{BezPath, PathEl, Point, Rect};
+
+/// How closely the flattened outline follows the curves, in font units
+const FLATTEN_TOLERANCE: f64 = 0.25;
+
+/// Whether any area of path is covered more than once.
+///
+/// That happens if contours cross each other or themselves, or if a contour
+/// sits inside another contour wound the same way.
+pub fn has_overlaps(path: &BezPath) -> bool {
+    let polygons = polygons(path);
+    has_crossings(&polygons) || has_nested_same_direction(&polygons)
+}
+
+/// Flatten each closed contour of path to a list of points
+fn polygons(path: &BezPath) -> Vec<Vec<Point>> {
+    let mut polygons: Vec<Vec<Point>> = Vec::new();
+    kurbo::flatten(path, FLATTEN_TOLERANCE, |el| match el {
+        PathEl::MoveTo(p) => polygons.push(vec![p]),
+        PathEl::LineTo(p) => {
+            if let Some(polygon) = polygons.last_mut() {
+                if polygon.last() != Some(&p) {
+                    polygon.push(p);
+                }
+            }
+        }
+        PathEl::ClosePath => {
+            if let Some(polygon) = polygons.last_mut() {
+                // Closing is implied, don't keep the first point twice
+                if polygon.len() > 1 && polygon.first() == polygon.last() {
+                    polygon.pop();
+                }
+            }
+        }
+        PathEl::QuadTo(..) | PathEl::CurveTo(..) => unreachable!("flatten only emits lines"),
+    });
+    polygons.retain(|polygon| polygon.len() > 2);
+    polygons
+}
+
+/// The edges of a polygon, including the closing one
+fn edges(polygon: &[Point]) -> impl Iterator<Item = (Point, Point)> + '_ {
+    polygon
+        .iter()
+        .zip(polygon.iter().cycle().skip(1))
+        .map(|(p0, p1)| (*p0, *p1))
+}
+
+fn cross(o: Point, a: Point, b: Point) -> f64 {
+    (a - o).cross(b - o)
+}
+
+/// True if the open segments cross, touching at an end point doesn't count
+fn segments_cross((a0, a1): (Point, Point), (b0, b1): (Point, Point)) -> bool {
+    let d0 = cross(a0, a1, b0);
+    let d1 = cross(a0, a1, b1);
+    let d2 = cross(b0, b1, a0);
+    let d3 = cross(b0, b1, a1);
+    d0 * d1 < 0.0 && d2 * d3 < 0.0
+}
+
+fn has_crossings(polygons: &[Vec<Point>]) -> bool {
+    let edges: Vec<_> = polygons
+        .iter()
+        .enumerate()
+        .flat_map(|(contour, polygon)| {
+            edges(polygon).map(move |edge| (contour, edge, Rect::from_points(edge.0, edge.1)))
+        })
+        .collect();
+    for (i, (contour_i, edge_i, bbox_i)) in edges.iter().enumerate() {
+        for (contour_j, edge_j, bbox_j) in edges.iter().skip(i + 1) {
+            // Neighbours share a point, segments_cross doesn't count that
+            if contour_i == contour_j && (edge_i.1 == edge_j.0 || edge_j.1 == edge_i.0) {
+                continue;
+            }
+            if bbox_i.x1 < bbox_j.x0
+                || bbox_j.x1 < bbox_i.x0
+                || bbox_i.y1 < bbox_j.y0
+                || bbox_j.y1 < bbox_i.y0
+            {
+                continue;
+            }
+            if segments_cross(*edge_i, *edge_j) {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+fn signed_area(polygon: &[Point]) -> f64 {
+    edges(polygon)
+        .map(|(p0, p1)| p0.to_vec2().cross(p1.to_vec2()))
+        .sum::<f64>()
+        / 2.0
+}
+
+/// How many times polygon winds around pt, positive if counter-clockwise
+fn winding(polygon: &[Point], pt: Point) -> i32 {
+    edges(polygon)
+        .map(|(p0, p1)| {
+            if p0.y <= pt.y && p1.y > pt.y && cross(p0, p1, pt) > 0.0 {
+                1
+            } else if p0.y > pt.y && p1.y <= pt.y && cross(p0, p1, pt) < 0.0 {
+                -1
+            } else {
+                0
+            }
+        })
+        .sum()
+}
+
+/// Without crossings each contour is either outside or entirely inside each other one
+/// so the winding just inside a contour tells us if it is covered twice.
+fn has_nested_same_direction(polygons: &[Vec<Point>]) -> bool {
+    polygons.iter().enumerate().any(|(i, polygon)| {
+        let direction = signed_area(polygon).signum() as i32;
+        let around: i32 = polygons
+            .iter()
+            .enumerate()
+            .filter(|(j, _)| *j != i)
+            .map(|(_, other)| winding(other, polygon[0]))
+            .sum();
+        (around + direction).abs() > 1
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use kurbo::Shape;
+
+    use super::*;
+
+    fn rects(rects: &[Rect], reverse: &[bool]) -> BezPath {
+        let mut path = BezPath::new();
+        for (rect, reverse) in rects.iter().zip(reverse) {
+            let contour = rect.to_path(0.1);
+            let contour = if *reverse {
+                contour.reverse_subpaths()
+            } else {
+                contour
+            };
+            path.extend(contour);
+        }
+        path
+    }
+
+    #[test]
+    fn single_contour_does_not_overlap() {
+        assert!(!has_overlaps(&rects(
+            &[Rect::new(0.0, 0.0, 100.0, 100.0)],
+            &[false]
+        )));
+    }
+
+    #[test]
+    fn disjoint_contours_do_not_overlap() {
+        assert!(!has_overlaps(&rects(
+            &[
+                Rect::new(0.0, 0.0, 100.0, 100.0),
+                Rect::new(200.0, 0.0, 300.0, 100.0)
+            ],
+            &[false, false]
+        )));
+    }
+
+    #[test]
+    fn counter_does_not_overlap() {
+        assert!(!has_overlaps(&rects(
+            &[
+                Rect::new(0.0, 0.0, 100.0, 100.0),
+                Rect::new(25.0, 25.0, 75.0, 75.0)
+            ],
+            &[false, true]
+        )));
+    }
+
+    #[test]
+    fn crossing_contours_overlap() {
+        assert!(has_overlaps(&rects(
+            &[
+                Rect::new(0.0, 0.0, 100.0, 100.0),
+                Rect::new(50.0, 50.0, 150.0, 150.0)
+            ],
+            &[false, false]
+        )));
+    }
+
+    #[test]
+    fn nested_same_direction_overlaps() {
+        assert!(has_overlaps(&rects(
+            &[
+                Rect::new(0.0, 0.0, 100.0, 100.0),
+                Rect::new(25.0, 25.0, 75.0, 75.0)
+            ],
+            &[false, false]
+        )));
+    }
+
+    #[test]
+    fn self_intersection_overlaps() {
+        // a figure eight
+        let mut path = BezPath::new();
+        path.move_to((0.0, 0.0));
+        path.line_to((100.0, 100.0));
+        path.line_to((100.0, 0.0));
+        path.line_to((0.0, 100.0));
+        path.close_path();
+        assert!(has_overlaps(&path));
+    }
+}
@@ -81,6 +81,8 @@ impl Paths {
             WorkId::Gvar => self.build_dir.join("gvar.table"),
             WorkId::Loca => self.build_dir.join("loca.table"),
             WorkId::LocaFormat => self.build_dir.join("loca.format"),
+            WorkId::Cff => self.build_dir.join("cff.table"),
+            WorkId::Cff2 => self.build_dir.join("cff2.table"),
             WorkId::Cmap => self.build_dir.join("cmap.table"),
             WorkId::Fvar => self.build_dir.join("fvar.table"),
             WorkId::Head => self.build_dir.join("head.table"),
@@ -97,7 +99,12 @@ impl Paths {
             WorkId::Os2 => self.build_dir.join("os2.table"),
             WorkId::Post => self.build_dir.join("post.table"),
             WorkId::Stat => self.build_dir.join("stat.table"),
+            WorkId::Varc => self.build_dir.join("varc.table"),
             WorkId::Meta => self.build_dir.join("meta.table"),
+            WorkId::Vhea => self.build_dir.join("vhea.table"),
+            WorkId::Vmtx => self.build_dir.join("vmtx.table"),
+            WorkId::Vorg => self.build_dir.join("vorg.table"),
+            WorkId::Vvar => self.build_dir.join("vvar.table"),
             WorkId::ExtraFeaTables => self.build_dir.join("extra_tables.bin"),
             WorkId::Font => self
                 .output_file
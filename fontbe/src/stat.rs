@@ -2,13 +2,19 @@
 
 use std::collections::HashMap;
 
-use log::trace;
+use log::{trace, warn};
 
-use fontdrasil::orchestration::{Access, Work};
-use fontir::orchestration::WorkId as FeWorkId;
+use fontdrasil::{
+    coords::UserCoord,
+    orchestration::{Access, Work},
+};
+use fontir::{
+    ir::{AxisValueLabel, AxisValueLocation, StaticMetadata},
+    orchestration::WorkId as FeWorkId,
+};
 use write_fonts::{
-    tables::stat::{AxisRecord, Stat},
-    types::NameId,
+    tables::stat::{AxisRecord, AxisValue, AxisValueRecord, AxisValueTableFlags, Stat},
+    types::{Fixed, NameId},
 };
 
 use crate::{
@@ -35,7 +41,6 @@ impl Work<Context, AnyWorkId, Error> for StatWork {
     /// Generate [stat](https://learn.microsoft.com/en-us/typography/opentype/spec/stat)
     ///
     /// See <https://github.com/fonttools/fonttools/blob/main/Lib/fontTools/otlLib/builder.py#L2688-L2810>
-    /// Note that we support only a very simple STAT at time of writing.
     fn exec(&self, context: &Context) -> Result<(), Error> {
         let static_metadata = context.ir.static_metadata.get();
 
@@ -45,14 +50,34 @@ impl Work<Context, AnyWorkId, Error> for StatWork {
             return Ok(());
         }
 
-        let reverse_names: HashMap<_, _> = static_metadata
-            .names
+        // Several ids can share a string, e.g. "Regular"; prefer the lowest like fontTools
+        let mut reverse_names: HashMap<&str, NameId> = HashMap::new();
+        for (key, name) in static_metadata.names.iter() {
+            reverse_names
+                .entry(name.as_str())
+                .and_modify(|name_id| *name_id = key.name_id.min(*name_id))
+                .or_insert(key.name_id);
+        }
+
+        let stat_labels = &static_metadata.stat_labels;
+        // Multi-axis values go first, as in fontTools
+        let (multi_axis, single_axis): (Vec<_>, Vec<_>) = stat_labels
+            .axis_values
             .iter()
-            .map(|(key, name)| (name.as_str(), key.name_id))
+            .partition(|label| matches!(label.value, AxisValueLocation::Location(..)));
+        let axis_values = multi_axis
+            .into_iter()
+            .chain(single_axis)
+            .filter_map(|label| to_axis_value(&static_metadata, &reverse_names, label))
             .collect();
 
-        context.stat.set(Stat {
-            design_axes: static_metadata
+        let elided_fallback_name_id = match &stat_labels.elided_fallback_name {
+            Some(name) => *reverse_names.get(name.as_str()).unwrap(),
+            None => NameId::SUBFAMILY_NAME,
+        };
+
+        context.stat.set(Stat::new(
+            static_metadata
                 .axes
                 .iter()
                 .enumerate()
@@ -61,12 +86,85 @@ impl Work<Context, AnyWorkId, Error> for StatWork {
                     axis_name_id: *reverse_names.get(a.ui_label_name()).unwrap(),
                     axis_ordering: idx as u16,
                 })
-                .collect::<Vec<_>>()
-                .into(),
-            elided_fallback_name_id: Some(NameId::SUBFAMILY_NAME),
-            ..Default::default()
-        });
+                .collect(),
+            axis_values,
+            elided_fallback_name_id,
+        ));
 
         Ok(())
     }
 }
+
+/// Build an axis value, or None if it refers only to axes that don't vary
+fn to_axis_value(
+    static_metadata: &StaticMetadata,
+    reverse_names: &HashMap<&str, NameId>,
+    label: &AxisValueLabel,
+) -> Option<AxisValue> {
+    let axis_index = |tag| {
+        static_metadata
+            .axes
+            .iter()
+            .position(|axis| axis.tag == tag)
+            .map(|idx| idx as u16)
+    };
+    let fixed = |coord: UserCoord| Fixed::from_f64(coord.to_f64());
+
+    let mut flags = AxisValueTableFlags::empty();
+    if label.older_sibling {
+        flags |= AxisValueTableFlags::OLDER_SIBLING_FONT_ATTRIBUTE;
+    }
+    if label.elidable {
+        flags |= AxisValueTableFlags::ELIDABLE_AXIS_VALUE_NAME;
+    }
+    // Names were claimed when the labels were added to static metadata
+    let name_id = *reverse_names.get(label.name.as_str()).unwrap();
+
+    let axis_value = match &label.value {
+        AxisValueLocation::Point {
+            axis,
+            value,
+            linked_value,
+        } => {
+            let axis_index = axis_index(*axis)?;
+            match linked_value {
+                Some(linked_value) => AxisValue::format_3(
+                    axis_index,
+                    flags,
+                    name_id,
+                    fixed(*value),
+                    fixed(*linked_value),
+                ),
+                None => AxisValue::format_1(axis_index, flags, name_id, fixed(*value)),
+            }
+        }
+        AxisValueLocation::Range {
+            axis,
+            nominal,
+            min,
+            max,
+        } => AxisValue::format_2(
+            axis_index(*axis)?,
+            flags,
+            name_id,
+            fixed(*nominal),
+            min.map(fixed).unwrap_or(Fixed::MIN),
+            max.map(fixed).unwrap_or(Fixed::MAX),
+        ),
+        AxisValueLocation::Location(location) => {
+            let mut records: Vec<_> = location
+                .iter()
+                .filter_map(|(tag, value)| {
+                    Some(AxisValueRecord::new(axis_index(*tag)?, fixed(*value)))
+                })
+                .collect();
+            if records.is_empty() {
+                warn!("STAT label '{}' is not on any variable axis", label.name);
+                return None;
+            }
+            records.sort_by_key(|record| record.axis_index);
+            AxisValue::format_4(flags, name_id, records)
+        }
+    };
+    Some(axis_value)
+}
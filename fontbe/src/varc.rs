@@ -0,0 +1,618 @@
+//! Generates a [VARC](https://github.com/harfbuzz/boring-expansion-spec/blob/main/VARC.md) table.
+//!
+//! write-fonts doesn't know how to write VARC so we serialize it here, following fontTools
+//! [VarComponent.compile](https://github.com/fonttools/fonttools/blob/5e6b12d12fa08abafbeb7570f47707fbedf69a45/Lib/fontTools/ttLib/tables/otTables.py#L411-L471).
+//!
+//! Glyphs in VARC are also in glyf or CFF, decomposed, so anything that doesn't
+//! understand VARC still draws them.
+
+use std::collections::{BTreeSet, HashMap};
+
+use fontdrasil::{
+    coords::NormalizedLocation,
+    orchestration::{Access, AccessBuilder, Work},
+    types::Axis,
+};
+use fontir::{
+    ir::{DecomposedTransform, Glyph, GlyphOrder},
+    orchestration::WorkId as FeWorkId,
+    variations::{VariationModel, VariationRegion},
+};
+use log::{debug, trace, warn};
+use write_fonts::{types::F2Dot14, OtRound};
+
+use crate::{
+    cff::write_index,
+    error::Error,
+    orchestration::{AnyWorkId, BeWork, Context, WorkId},
+};
+
+// Variable component flags, see
+// <https://github.com/harfbuzz/boring-expansion-spec/blob/main/VARC.md#variable-component-flags>
+const HAVE_AXES: u32 = 1 << 1;
+const AXIS_VALUES_HAVE_VARIATION: u32 = 1 << 2;
+const TRANSFORM_HAS_VARIATION: u32 = 1 << 3;
+const HAVE_TRANSLATE_X: u32 = 1 << 4;
+const HAVE_TRANSLATE_Y: u32 = 1 << 5;
+const HAVE_ROTATION: u32 = 1 << 6;
+const HAVE_SCALE_X: u32 = 1 << 8;
+const HAVE_SCALE_Y: u32 = 1 << 9;
+const HAVE_TCENTER_X: u32 = 1 << 10;
+const HAVE_TCENTER_Y: u32 = 1 << 11;
+const HAVE_SKEW_X: u32 = 1 << 13;
+const HAVE_SKEW_Y: u32 = 1 << 14;
+
+/// How each part of a transform is stored, in the order they are written.
+///
+/// The flag that marks it present, its fractional bits and the scale it is divided by. Angles
+/// are stored in half turns, with skew x flipped to be counter-clockwise like the others.
+///
+/// See fontTools [VAR_TRANSFORM_MAPPING](https://github.com/fonttools/fonttools/blob/5e6b12d12fa08abafbeb7570f47707fbedf69a45/Lib/fontTools/ttLib/tables/otTables.py#L351-L361)
+const TRANSFORM_FIELDS: [(u32, u32, f64); 9] = [
+    (HAVE_TRANSLATE_X, 0, 1.0),
+    (HAVE_TRANSLATE_Y, 0, 1.0),
+    (HAVE_ROTATION, 12, 180.0),
+    (HAVE_SCALE_X, 10, 1.0),
+    (HAVE_SCALE_Y, 10, 1.0),
+    (HAVE_SKEW_X, 12, -180.0),
+    (HAVE_SKEW_Y, 12, 180.0),
+    (HAVE_TCENTER_X, 0, 1.0),
+    (HAVE_TCENTER_Y, 0, 1.0),
+];
+
+const SCALE_X: usize = 3;
+const SCALE_Y: usize = 4;
+
+#[derive(Debug)]
+struct VarcWork {}
+
+pub fn create_varc_work() -> Box<BeWork> {
+    Box::new(VarcWork {})
+}
+
+/// The parts of a transform as they are stored, see [TRANSFORM_FIELDS]
+fn transform_values(transform: &DecomposedTransform) -> [f64; 9] {
+    let values = [
+        transform.translate_x,
+        transform.translate_y,
+        transform.rotation,
+        transform.scale_x,
+        transform.scale_y,
+        transform.skew_x,
+        transform.skew_y,
+        transform.t_center_x,
+        transform.t_center_y,
+    ];
+    let mut fixed = [0.0; 9];
+    for (i, (value, (_, fractional_bits, scale))) in values.iter().zip(TRANSFORM_FIELDS).enumerate()
+    {
+        fixed[i] = (value / scale * (1 << fractional_bits) as f64).ot_round();
+    }
+    fixed
+}
+
+/// Write a uint32var, a big endian integer whose leading one bits give the number of extra bytes.
+///
+/// See <https://github.com/harfbuzz/boring-expansion-spec/blob/main/VARC.md#uint32var>
+fn write_u32_var(out: &mut Vec<u8>, value: u32) {
+    let bytes = value.to_be_bytes();
+    match value {
+        0..0x80 => out.push(value as u8),
+        0x80..0x4000 => out.extend(&(value as u16 | 0x8000).to_be_bytes()),
+        0x4000..0x200000 => out.extend(&(value | 0xC00000).to_be_bytes()[1..]),
+        0x200000..0x10000000 => out.extend((value | 0xE0000000).to_be_bytes()),
+        _ => {
+            out.push(0xF0);
+            out.extend(bytes);
+        }
+    }
+}
+
+/// Write values the way gvar packs deltas, plus runs of 32-bit values.
+///
+/// See <https://github.com/harfbuzz/boring-expansion-spec/blob/main/VARC.md#tuplevalues>
+fn write_tuple_values(out: &mut Vec<u8>, values: &[i32]) {
+    const DELTAS_ARE_ZERO: u8 = 0x80;
+    const DELTAS_ARE_WORDS: u8 = 0x40;
+    const DELTAS_ARE_LONGS: u8 = 0xC0;
+    const MAX_RUN: usize = 64;
+
+    let kind = |value: i32| match value {
+        0 => DELTAS_ARE_ZERO,
+        -128..=127 => 0,
+        -32768..=32767 => DELTAS_ARE_WORDS,
+        _ => DELTAS_ARE_LONGS,
+    };
+    let mut i = 0;
+    while i < values.len() {
+        let run_kind = kind(values[i]);
+        let run_len = values[i..]
+            .iter()
+            .take(MAX_RUN)
+            .take_while(|v| kind(**v) == run_kind)
+            .count();
+        out.push(run_kind | (run_len - 1) as u8);
+        for value in &values[i..i + run_len] {
+            match run_kind {
+                DELTAS_ARE_ZERO => (),
+                DELTAS_ARE_WORDS => out.extend((*value as i16).to_be_bytes()),
+                DELTAS_ARE_LONGS => out.extend(value.to_be_bytes()),
+                _ => out.push(*value as i8 as u8),
+            }
+        }
+        i += run_len;
+    }
+}
+
+/// A sparse variation region, (axis index, start, peak, end) for each axis it spans
+type SparseRegion = Vec<(u16, [F2Dot14; 3])>;
+
+/// Accumulates the deltas of a MultiItemVariationStore.
+///
+/// See <https://github.com/harfbuzz/boring-expansion-spec/blob/main/VARC.md#multiitemvariationstore>
+#[derive(Debug, Default)]
+struct MultiItemVariationStoreBuilder {
+    regions: Vec<SparseRegion>,
+    region_indices: HashMap<SparseRegion, u16>,
+    /// For each list of region indices the delta sets that use it
+    data: Vec<(Vec<u16>, Vec<Vec<i32>>)>,
+}
+
+impl MultiItemVariationStoreBuilder {
+    fn region_index(&mut self, axes: &[Axis], region: &VariationRegion) -> u16 {
+        let sparse: SparseRegion = axes
+            .iter()
+            .enumerate()
+            .filter_map(|(i, axis)| {
+                let tent = region.get(&axis.tag)?;
+                tent.has_non_zero().then(|| {
+                    let coords = tent.to_region_axis_coords();
+                    (
+                        i as u16,
+                        [coords.start_coord, coords.peak_coord, coords.end_coord],
+                    )
+                })
+            })
+            .collect();
+        if let Some(idx) = self.region_indices.get(&sparse) {
+            return *idx;
+        }
+        let idx = self.regions.len() as u16;
+        self.regions.push(sparse.clone());
+        self.region_indices.insert(sparse, idx);
+        idx
+    }
+
+    /// Add the deltas of a vector of values, returning its variation index.
+    ///
+    /// Returns None if nothing varies.
+    fn add(&mut self, axes: &[Axis], deltas: Vec<(VariationRegion, Vec<f64>)>) -> Option<u32> {
+        let (region_indices, deltas): (Vec<_>, Vec<_>) = deltas
+            .into_iter()
+            .filter(|(region, deltas)| !region.is_default() && deltas.iter().any(|d| *d != 0.0))
+            .map(|(region, deltas)| (self.region_index(axes, &region), deltas))
+            .unzip();
+        if region_indices.is_empty() {
+            return None;
+        }
+        // Region by region, each with the delta of every value
+        let delta_set: Vec<i32> = deltas
+            .iter()
+            .flat_map(|deltas| {
+                deltas.iter().map(|d| {
+                    let d: f64 = d.ot_round();
+                    d as i32
+                })
+            })
+            .collect();
+
+        let outer = match self.data.iter().position(|(indices, sets)| {
+            *indices == region_indices && sets.len() < u16::MAX as usize
+        }) {
+            Some(outer) => outer,
+            None => {
+                self.data.push((region_indices, Vec::new()));
+                self.data.len() - 1
+            }
+        };
+        let delta_sets = &mut self.data[outer].1;
+        delta_sets.push(delta_set);
+        Some(((outer as u32) << 16) | (delta_sets.len() - 1) as u32)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    fn write(&self, out: &mut Vec<u8>) {
+        let start = out.len();
+        // format, region list offset, data count, data offsets
+        let header_len = 2 + 4 + 2 + 4 * self.data.len();
+
+        let mut region_list = Vec::new();
+        region_list.extend((self.regions.len() as u16).to_be_bytes());
+        let mut offset = 2 + 4 * self.regions.len();
+        for region in self.regions.iter() {
+            region_list.extend((offset as u32).to_be_bytes());
+            offset += 2 + 8 * region.len();
+        }
+        for region in self.regions.iter() {
+            region_list.extend((region.len() as u16).to_be_bytes());
+            for (axis_index, coords) in region {
+                region_list.extend(axis_index.to_be_bytes());
+                for coord in coords {
+                    region_list.extend(coord.to_be_bytes());
+                }
+            }
+        }
+
+        let data: Vec<_> = self
+            .data
+            .iter()
+            .map(|(region_indices, delta_sets)| {
+                let mut data = vec![1u8];
+                data.extend((region_indices.len() as u16).to_be_bytes());
+                for idx in region_indices {
+                    data.extend(idx.to_be_bytes());
+                }
+                let delta_sets: Vec<_> = delta_sets
+                    .iter()
+                    .map(|deltas| {
+                        let mut buf = Vec::new();
+                        write_tuple_values(&mut buf, deltas);
+                        buf
+                    })
+                    .collect();
+                write_index(&mut data, &delta_sets, true);
+                data
+            })
+            .collect();
+
+        out.extend(1u16.to_be_bytes());
+        out.extend((header_len as u32).to_be_bytes());
+        out.extend((self.data.len() as u16).to_be_bytes());
+        let mut offset = header_len + region_list.len();
+        for data in data.iter() {
+            out.extend((offset as u32).to_be_bytes());
+            offset += data.len();
+        }
+        debug_assert_eq!(header_len, out.len() - start);
+        out.extend(region_list);
+        for data in data {
+            out.extend(data);
+        }
+    }
+}
+
+/// The VARC description of a glyph, the concatenated records of its components.
+///
+/// Returns None, with a message, for glyphs VARC can't describe. They are still
+/// present, decomposed, in glyf or CFF.
+fn glyph_record(
+    glyph: &Glyph,
+    axes: &[Axis],
+    glyph_order: &GlyphOrder,
+    axis_indices: &mut Vec<Vec<i32>>,
+    store: &mut MultiItemVariationStoreBuilder,
+) -> Result<Option<Vec<u8>>, Error> {
+    let default = glyph.default_instance();
+    if glyph.sources().values().any(|i| !i.contours.is_empty()) {
+        warn!(
+            "'{}' has contours as well as variable components, it is decomposed and not added to VARC",
+            glyph.name
+        );
+        return Ok(None);
+    }
+    let bases: Vec<_> = default.components.iter().map(|c| &c.base).collect();
+    if glyph.sources().values().any(|i| {
+        !i.components
+            .iter()
+            .map(|c| &c.base)
+            .eq(bases.iter().copied())
+    }) {
+        warn!(
+            "'{}' doesn't use the same components everywhere, it is decomposed and not added to VARC",
+            glyph.name
+        );
+        return Ok(None);
+    }
+    let Some(gids) = bases
+        .iter()
+        .map(|base| glyph_order.glyph_id(*base))
+        .collect::<Option<Vec<_>>>()
+    else {
+        debug!(
+            "'{}' uses components that aren't in the font, it is decomposed and not added to VARC",
+            glyph.name
+        );
+        return Ok(None);
+    };
+
+    let model = VariationModel::new(glyph.sources().keys().cloned().collect(), axes.to_vec())
+        .map_err(|e| Error::VariationModelError(glyph.name.clone(), e))?;
+    let deltas = |values: &HashMap<NormalizedLocation, Vec<f64>>| {
+        model
+            .deltas(values)
+            .map_err(|e| Error::GlyphDeltaError(glyph.name.clone(), e))
+    };
+
+    let mut record = Vec::new();
+    for (i, gid) in gids.into_iter().enumerate() {
+        let components: Vec<_> = glyph
+            .sources()
+            .iter()
+            .map(|(loc, inst)| (loc, &inst.components[i]))
+            .collect();
+
+        // Axes must be mentioned everywhere, otherwise they would only sometimes follow the glyph
+        let axis_tags: BTreeSet<_> = components
+            .iter()
+            .filter_map(|(_, c)| c.variable.as_ref())
+            .flat_map(|v| v.location.axis_tags())
+            .collect();
+        let mut axis_values = HashMap::new();
+        for (loc, component) in components.iter() {
+            let location = component.variable.as_ref().map(|v| &v.location);
+            let values: Option<Vec<f64>> = axis_tags
+                .iter()
+                .map(|tag| {
+                    location
+                        .and_then(|l| l.get(**tag))
+                        .map(|coord| (coord.to_f64() * 16384.0).ot_round())
+                })
+                .collect();
+            let Some(values) = values else {
+                warn!(
+                    "'{}' component {i} doesn't set {axis_tags:?} everywhere, it is decomposed and not added to VARC",
+                    glyph.name
+                );
+                return Ok(None);
+            };
+            axis_values.insert((*loc).clone(), values);
+        }
+        let Some(indices) = axis_tags
+            .iter()
+            .map(|tag| axes.iter().position(|a| a.tag == **tag).map(|i| i as i32))
+            .collect::<Option<Vec<_>>>()
+        else {
+            debug!(
+                "'{}' component {i} uses axes that aren't in fvar, it is decomposed and not added to VARC",
+                glyph.name
+            );
+            return Ok(None);
+        };
+
+        let transforms: HashMap<_, _> = components
+            .iter()
+            .map(|(loc, c)| ((*loc).clone(), transform_values(&c.decomposed_transform())))
+            .collect();
+        let default_transform = transform_values(&default.components[i].decomposed_transform());
+        let identity = transform_values(&DecomposedTransform::default());
+        let mut flags = 0;
+        for (j, (flag, ..)) in TRANSFORM_FIELDS.iter().enumerate() {
+            let present = if j == SCALE_Y {
+                // Absent, scale y is scale x
+                transforms.values().any(|t| t[SCALE_Y] != t[SCALE_X])
+            } else {
+                transforms.values().any(|t| t[j] != identity[j])
+            };
+            if present {
+                flags |= flag;
+            }
+        }
+        let fields: Vec<_> = (0..TRANSFORM_FIELDS.len())
+            .filter(|j| flags & TRANSFORM_FIELDS[*j].0 != 0)
+            .collect();
+        for value in fields.iter().map(|j| default_transform[*j]) {
+            if !(i16::MIN as f64..=i16::MAX as f64).contains(&value) {
+                return Err(Error::OutOfBounds {
+                    what: format!("'{}' component {i} transform", glyph.name),
+                    value: value.to_string(),
+                });
+            }
+        }
+
+        let axis_values_var_idx = store.add(axes, deltas(&axis_values)?);
+        let transform_values: HashMap<_, _> = transforms
+            .iter()
+            .map(|(loc, t)| (loc.clone(), fields.iter().map(|j| t[*j]).collect()))
+            .collect();
+        let transform_var_idx = store.add(axes, deltas(&transform_values)?);
+
+        let axis_indices_idx = (!indices.is_empty()).then(|| {
+            axis_indices
+                .iter()
+                .position(|existing| *existing == indices)
+                .unwrap_or_else(|| {
+                    axis_indices.push(indices);
+                    axis_indices.len() - 1
+                })
+        });
+        if axis_indices_idx.is_some() {
+            flags |= HAVE_AXES;
+        }
+        if axis_values_var_idx.is_some() {
+            flags |= AXIS_VALUES_HAVE_VARIATION;
+        }
+        if transform_var_idx.is_some() {
+            flags |= TRANSFORM_HAS_VARIATION;
+        }
+
+        write_u32_var(&mut record, flags);
+        record.extend(gid.to_u16().to_be_bytes());
+        if let Some(idx) = axis_indices_idx {
+            write_u32_var(&mut record, idx as u32);
+            let default_values: Vec<_> = axis_values[glyph.default_location()]
+                .iter()
+                .map(|v| *v as i32)
+                .collect();
+            write_tuple_values(&mut record, &default_values);
+        }
+        if let Some(idx) = axis_values_var_idx {
+            write_u32_var(&mut record, idx);
+        }
+        if let Some(idx) = transform_var_idx {
+            write_u32_var(&mut record, idx);
+        }
+        for j in fields {
+            record.extend((default_transform[j] as i16).to_be_bytes());
+        }
+    }
+    Ok(Some(record))
+}
+
+impl Work<Context, AnyWorkId, Error> for VarcWork {
+    fn id(&self) -> AnyWorkId {
+        WorkId::Varc.into()
+    }
+
+    fn read_access(&self) -> Access<AnyWorkId> {
+        AccessBuilder::new()
+            .variant(FeWorkId::StaticMetadata)
+            .variant(FeWorkId::GlyphOrder)
+            .variant(FeWorkId::VariableComposites)
+            .build()
+    }
+
+    /// Generate [VARC](https://github.com/harfbuzz/boring-expansion-spec/blob/main/VARC.md)
+    fn exec(&self, context: &Context) -> Result<(), Error> {
+        let variable_composites = context.ir.variable_composites.get();
+        if variable_composites.glyphs.is_empty() {
+            return Ok(());
+        }
+        let static_metadata = context.ir.static_metadata.get();
+        let glyph_order = context.ir.glyph_order.get();
+        let axes = &static_metadata.axes;
+
+        let mut glyphs: Vec<_> = variable_composites
+            .glyphs
+            .values()
+            .filter_map(|glyph| glyph_order.glyph_id(&glyph.name).map(|gid| (gid, glyph)))
+            .collect();
+        glyphs.sort_by_key(|(gid, _)| *gid);
+
+        let mut axis_indices = Vec::new();
+        let mut store = MultiItemVariationStoreBuilder::default();
+        let mut coverage = Vec::new();
+        let mut records = Vec::new();
+        for (gid, glyph) in glyphs {
+            if let Some(record) =
+                glyph_record(glyph, axes, &glyph_order, &mut axis_indices, &mut store)?
+            {
+                trace!("VARC '{}' is {} bytes", glyph.name, record.len());
+                coverage.push(gid);
+                records.push(record);
+            }
+        }
+        if records.is_empty() {
+            return Ok(());
+        }
+
+        // Coverage format 1
+        let mut coverage_bytes = Vec::new();
+        coverage_bytes.extend(1u16.to_be_bytes());
+        coverage_bytes.extend((coverage.len() as u16).to_be_bytes());
+        for gid in coverage {
+            coverage_bytes.extend(gid.to_u16().to_be_bytes());
+        }
+
+        let mut store_bytes = Vec::new();
+        if !store.is_empty() {
+            store.write(&mut store_bytes);
+        }
+
+        let mut axis_indices_bytes = Vec::new();
+        if !axis_indices.is_empty() {
+            let items: Vec<_> = axis_indices
+                .iter()
+                .map(|indices| {
+                    let mut buf = Vec::new();
+                    write_tuple_values(&mut buf, indices);
+                    buf
+                })
+                .collect();
+            write_index(&mut axis_indices_bytes, &items, true);
+        }
+
+        let mut glyph_bytes = Vec::new();
+        write_index(&mut glyph_bytes, &records, true);
+
+        // version, then offsets to coverage, store, conditions, axis indices and glyphs
+        let mut varc = Vec::new();
+        varc.extend(1u16.to_be_bytes());
+        varc.extend(0u16.to_be_bytes());
+        let mut offset = 4 + 4 * 5;
+        for subtable in [
+            &coverage_bytes,
+            &store_bytes,
+            &Vec::new(),
+            &axis_indices_bytes,
+            &glyph_bytes,
+        ] {
+            if subtable.is_empty() {
+                varc.extend(0u32.to_be_bytes());
+            } else {
+                varc.extend((offset as u32).to_be_bytes());
+                offset += subtable.len();
+            }
+        }
+        for subtable in [coverage_bytes, store_bytes, axis_indices_bytes, glyph_bytes] {
+            varc.extend(subtable);
+        }
+
+        debug!("VARC is {} bytes", varc.len());
+        context.varc.set(varc.into());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn u32_var_lengths() {
+        for (value, expected) in [
+            (0x7F, vec![0x7F]),
+            (0x80, vec![0x80, 0x80]),
+            (0x3FFF, vec![0xBF, 0xFF]),
+            (0x4000, vec![0xC0, 0x40, 0x00]),
+            (0x200000, vec![0xE0, 0x20, 0x00, 0x00]),
+            (0x10000000, vec![0xF0, 0x10, 0x00, 0x00, 0x00]),
+        ] {
+            let mut out = Vec::new();
+            write_u32_var(&mut out, value);
+            assert_eq!(expected, out, "{value:#x}");
+        }
+    }
+
+    #[test]
+    fn tuple_values_runs() {
+        let mut out = Vec::new();
+        write_tuple_values(&mut out, &[0, 0, 1, -2, 300, 16384, 32768]);
+        assert_eq!(
+            vec![
+                0x81, // two zeros
+                0x01, 0x01, 0xFE, // two bytes
+                0x41, 0x01, 0x2C, 0x40, 0x00, // two words
+                0xC0, 0x00, 0x00, 0x80, 0x00, // one long
+            ],
+            out
+        );
+    }
+
+    #[test]
+    fn transform_values_are_fixed_point() {
+        let transform = DecomposedTransform {
+            translate_x: 10.4,
+            rotation: 90.0,
+            scale_x: 0.5,
+            skew_x: 45.0,
+            ..Default::default()
+        };
+        assert_eq!(
+            [10.0, 0.0, 2048.0, 512.0, 1024.0, -1024.0, 0.0, 0.0, 0.0],
+            transform_values(&transform)
+        );
+    }
+}
@@ -0,0 +1,341 @@
+//! Generates the [vmtx](https://learn.microsoft.com/en-us/typography/opentype/spec/vmtx),
+//! [vhea](https://learn.microsoft.com/en-us/typography/opentype/spec/vhea), and, for CFF outlines,
+//! [VORG](https://learn.microsoft.com/en-us/typography/opentype/spec/vorg) tables
+
+use std::{
+    cmp::{max, min},
+    collections::HashMap,
+};
+
+use fontdrasil::{
+    coords::NormalizedLocation,
+    orchestration::{Access, AccessBuilder, Work},
+    types::{Axis, GlyphName},
+};
+use fontir::{
+    ir::{self, GlobalMetric, GlobalMetrics},
+    orchestration::{Flags, WorkId as FeWorkId},
+    variations::VariationModel,
+};
+use write_fonts::{
+    dump_table,
+    tables::{
+        glyf::Bbox,
+        vhea::Vhea,
+        vmtx::{LongMetric, Vmtx},
+    },
+    types::{FWord, GlyphId16},
+    OtRound,
+};
+
+use crate::{
+    cff::charstring_bbox,
+    error::Error,
+    metrics_and_limits::split_off_side_bearings,
+    orchestration::{AnyWorkId, BeWork, Context, WorkId},
+    vertical_tables::{VertOriginYMetrics, Vorg},
+};
+
+/// The vertical advance and origin of a glyph instance
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct VerticalMetrics {
+    pub(crate) advance: f64,
+    /// The y coordinate top side bearings are measured from
+    pub(crate) origin: f64,
+}
+
+impl VerticalMetrics {
+    /// The vertical metrics of a glyph instance.
+    ///
+    /// Instances that don't specify their own fall back to the font's ascender - descender
+    /// for the advance and, like ufo2ft, the OS/2 typo ascender for the origin.
+    /// See <https://github.com/googlefonts/ufo2ft/blob/main/Lib/ufo2ft/outlineCompiler.py>
+    pub(crate) fn new(
+        glyph_name: &GlyphName,
+        instance: &ir::GlyphInstance,
+        location: &NormalizedLocation,
+        global_metrics: &GlobalMetrics,
+        axes: &[Axis],
+    ) -> Result<Self, Error> {
+        let metric = |metric| metric_at(glyph_name, global_metrics, metric, location, axes);
+        let advance = match instance.height {
+            Some(height) => height,
+            None => metric(GlobalMetric::Ascender)? - metric(GlobalMetric::Descender)?,
+        };
+        let origin = match instance.vertical_origin {
+            Some(origin) => origin,
+            None => metric(GlobalMetric::Os2TypoAscender)?,
+        };
+        Ok(VerticalMetrics { advance, origin })
+    }
+}
+
+/// The vertical metrics of every instance of a glyph
+pub(crate) fn glyph_vertical_metrics(
+    glyph: &ir::Glyph,
+    global_metrics: &GlobalMetrics,
+    axes: &[Axis],
+) -> Result<HashMap<NormalizedLocation, VerticalMetrics>, Error> {
+    glyph
+        .sources()
+        .iter()
+        .map(|(loc, instance)| {
+            VerticalMetrics::new(&glyph.name, instance, loc, global_metrics, axes)
+                .map(|metrics| (loc.clone(), metrics))
+        })
+        .collect()
+}
+
+/// The value of a global metric at location.
+///
+/// Glyphs may have instances, such as intermediate layers, where no global metrics
+/// are defined so we interpolate.
+fn metric_at(
+    glyph_name: &GlyphName,
+    global_metrics: &GlobalMetrics,
+    metric: GlobalMetric,
+    location: &NormalizedLocation,
+    axes: &[Axis],
+) -> Result<f64, Error> {
+    let values = global_metrics.values(metric);
+    if let Some(value) = values.get(location) {
+        return Ok(value.into_inner());
+    }
+    let values: HashMap<_, _> = values
+        .iter()
+        .map(|(loc, value)| (loc.clone(), vec![value.into_inner()]))
+        .collect();
+    let model = VariationModel::new(values.keys().cloned().collect(), axes.to_vec())
+        .map_err(|e| Error::VariationModelError(glyph_name.clone(), e))?;
+    let value: Vec<f64> = model
+        .interpolate(location, &values)
+        .map_err(|e| Error::GlyphDeltaError(glyph_name.clone(), e))?;
+    Ok(value[0])
+}
+
+#[derive(Debug)]
+struct VerticalMetricsWork {}
+
+pub fn create_vertical_metrics_work() -> Box<BeWork> {
+    Box::new(VerticalMetricsWork {})
+}
+
+/// Font-wide vertical limits
+#[derive(Debug, Default)]
+struct VerticalLimits {
+    advance_height_max: u16,
+    min_top_side_bearing: Option<i16>,
+    min_bottom_side_bearing: Option<i16>,
+    y_max_extent: Option<i16>,
+}
+
+impl VerticalLimits {
+    /// Update for a glyph, returning its top side bearing
+    fn update(&mut self, advance: u16, origin: i16, bbox: Option<Bbox>) -> i16 {
+        // max advance height should consider every glyph that has a vmtx entry
+        self.advance_height_max = max(self.advance_height_max, advance);
+
+        // side bearings are only for non-empty glyphs
+        let Some(bbox) = bbox else {
+            return 0;
+        };
+        let height = bbox.y_max as i32 - bbox.y_min as i32;
+        let top_side_bearing = origin as i32 - bbox.y_max as i32;
+        let bottom_side_bearing = advance as i32 - top_side_bearing - height;
+        let clamp = |v: i32| v.clamp(i16::MIN as i32, i16::MAX as i32) as i16;
+        let (top_side_bearing, bottom_side_bearing, extent) = (
+            clamp(top_side_bearing),
+            clamp(bottom_side_bearing),
+            clamp(top_side_bearing + height),
+        );
+        self.min_top_side_bearing = self
+            .min_top_side_bearing
+            .map(|v| min(v, top_side_bearing))
+            .or(Some(top_side_bearing));
+        self.min_bottom_side_bearing = self
+            .min_bottom_side_bearing
+            .map(|v| min(v, bottom_side_bearing))
+            .or(Some(bottom_side_bearing));
+        self.y_max_extent = self.y_max_extent.map(|v| max(v, extent)).or(Some(extent));
+        top_side_bearing
+    }
+}
+
+/// The most common vertical origin, ties going to the first seen
+fn most_common_origin(origins: &[i16]) -> i16 {
+    let mut counts: HashMap<i16, usize> = HashMap::new();
+    for origin in origins {
+        *counts.entry(*origin).or_default() += 1;
+    }
+    let max_count = counts.values().copied().max().unwrap_or_default();
+    origins
+        .iter()
+        .copied()
+        .find(|origin| counts[origin] == max_count)
+        .unwrap_or_default()
+}
+
+impl Work<Context, AnyWorkId, Error> for VerticalMetricsWork {
+    fn id(&self) -> AnyWorkId {
+        WorkId::Vmtx.into()
+    }
+
+    fn read_access(&self) -> Access<AnyWorkId> {
+        AccessBuilder::new()
+            .variant(FeWorkId::StaticMetadata)
+            .variant(FeWorkId::GlobalMetrics)
+            .variant(FeWorkId::GlyphOrder)
+            .variant(FeWorkId::ALL_GLYPHS)
+            .variant(WorkId::ALL_GLYF_FRAGMENTS)
+            .build()
+    }
+
+    fn write_access(&self) -> Access<AnyWorkId> {
+        AccessBuilder::new()
+            .variant(WorkId::Vmtx)
+            .variant(WorkId::Vhea)
+            .variant(WorkId::Vorg)
+            .build()
+    }
+
+    fn also_completes(&self) -> Vec<AnyWorkId> {
+        vec![WorkId::Vhea.into(), WorkId::Vorg.into()]
+    }
+
+    /// Generate:
+    ///
+    /// * [vmtx](https://learn.microsoft.com/en-us/typography/opentype/spec/vmtx)
+    /// * [vhea](https://learn.microsoft.com/en-us/typography/opentype/spec/vhea)
+    /// * [VORG](https://learn.microsoft.com/en-us/typography/opentype/spec/vorg) if we have CFF outlines
+    fn exec(&self, context: &Context) -> Result<(), Error> {
+        let static_metadata = context.ir.static_metadata.get();
+        if !static_metadata.misc.build_vertical {
+            return Ok(());
+        }
+        let default_location = static_metadata.default_location();
+        let global_metrics = context.ir.global_metrics.get();
+        let default_metrics = global_metrics.at(default_location);
+        let glyph_order = context.ir.glyph_order.get();
+        let cff_outlines = context.flags.contains(Flags::CFF_OUTLINES);
+
+        let mut limits = VerticalLimits::default();
+        let mut origins = Vec::with_capacity(glyph_order.len());
+        let mut long_metrics = Vec::with_capacity(glyph_order.len());
+        for gn in glyph_order.names() {
+            let ir_glyph = context.ir.get_glyph(gn.clone());
+            let metrics = VerticalMetrics::new(
+                gn,
+                ir_glyph.default_instance(),
+                default_location,
+                &global_metrics,
+                &static_metadata.axes,
+            )?;
+            let advance: u16 = metrics.advance.ot_round();
+            let origin: i16 = metrics.origin.ot_round();
+            let bbox = if cff_outlines {
+                charstring_bbox(ir_glyph.default_instance())
+            } else {
+                context
+                    .glyphs
+                    .get(&WorkId::GlyfFragment(gn.clone()).into())
+                    .data
+                    .bbox()
+            };
+            let side_bearing = limits.update(advance, origin, bbox);
+            origins.push(origin);
+            long_metrics.push(LongMetric {
+                advance,
+                side_bearing,
+            });
+        }
+
+        let top_side_bearings = split_off_side_bearings(&mut long_metrics);
+
+        let vhea = Vhea {
+            ascender: FWord::new(default_metrics.vhea_ascender.into_inner().ot_round()),
+            descender: FWord::new(default_metrics.vhea_descender.into_inner().ot_round()),
+            line_gap: FWord::new(default_metrics.vhea_line_gap.into_inner().ot_round()),
+            advance_height_max: limits.advance_height_max.into(),
+            min_top_side_bearing: limits.min_top_side_bearing.unwrap_or_default().into(),
+            min_bottom_side_bearing: limits.min_bottom_side_bearing.unwrap_or_default().into(),
+            y_max_extent: limits.y_max_extent.unwrap_or_default().into(),
+            caret_slope_rise: default_metrics
+                .vhea_caret_slope_rise
+                .into_inner()
+                .ot_round(),
+            caret_slope_run: default_metrics.vhea_caret_slope_run.into_inner().ot_round(),
+            caret_offset: default_metrics.vhea_caret_offset.into_inner().ot_round(),
+            number_of_long_ver_metrics: long_metrics.len().try_into().map_err(|_| {
+                Error::OutOfBounds {
+                    what: "number_of_long_ver_metrics".into(),
+                    value: format!("{}", long_metrics.len()),
+                }
+            })?,
+        };
+        context.vhea.set(vhea);
+
+        let vmtx = Vmtx::new(long_metrics, top_side_bearings);
+        let raw_vmtx = dump_table(&vmtx)
+            .map_err(|e| Error::DumpTableError {
+                e,
+                context: "vmtx".into(),
+            })?
+            .into();
+        context.vmtx.set(raw_vmtx);
+
+        // ufo2ft writes the most common origin as the default and the rest explicitly
+        if cff_outlines {
+            let default_origin = most_common_origin(&origins);
+            let exceptions = origins
+                .into_iter()
+                .enumerate()
+                .filter(|(_, origin)| *origin != default_origin)
+                .map(|(gid, origin)| VertOriginYMetrics::new(GlyphId16::new(gid as u16), origin))
+                .collect();
+            context.vorg.set(Vorg::new(default_origin, exceptions));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn most_common_origin_prefers_first_seen() {
+        assert_eq!(880, most_common_origin(&[880, 900, 900, 880]));
+        assert_eq!(900, most_common_origin(&[880, 900, 900]));
+        assert_eq!(0, most_common_origin(&[]));
+    }
+
+    #[test]
+    fn empty_glyph_contributes_to_max_only() {
+        let mut limits = VerticalLimits::default();
+        assert_eq!(0, limits.update(1000, 880, None));
+        assert_eq!(1000, limits.advance_height_max);
+        assert_eq!(None, limits.min_top_side_bearing);
+    }
+
+    #[test]
+    fn side_bearings_from_origin() {
+        let mut limits = VerticalLimits::default();
+        let bbox = Bbox {
+            x_min: 50,
+            y_min: -100,
+            x_max: 950,
+            y_max: 800,
+        };
+        // tsb = 880 - 800, bsb = 1000 - 80 - 900
+        assert_eq!(80, limits.update(1000, 880, Some(bbox)));
+        assert_eq!(
+            (Some(80), Some(20), Some(980)),
+            (
+                limits.min_top_side_bearing,
+                limits.min_bottom_side_bearing,
+                limits.y_max_extent
+            )
+        );
+    }
+}
@@ -0,0 +1,242 @@
+//! The [VORG](https://learn.microsoft.com/en-us/typography/opentype/spec/vorg) and
+//! [VVAR](https://learn.microsoft.com/en-us/typography/opentype/spec/vvar) tables.
+//!
+//! The version of write-fonts we depend on can read but not write these, so they
+//! are defined here, following the shape of the generated write-fonts tables.
+
+use write_fonts::{
+    from_obj::{FromObjRef, FromTableRef, ToOwnedTable},
+    read::{FontData, FontRead, ReadError, TopLevelTable},
+    tables::variations::{DeltaSetIndexMap, ItemVariationStore},
+    types::{GlyphId16, MajorMinor, Tag},
+    validate::{Validate, ValidationCtx},
+    FontWrite, NullableOffsetMarker, OffsetMarker, TableWriter,
+};
+
+/// The [VORG (Vertical Origin)](https://learn.microsoft.com/en-us/typography/opentype/spec/vorg) table
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Vorg {
+    /// The y coordinate of the vertical origin of glyphs without an entry in
+    /// `vert_origin_y_metrics`.
+    pub default_vert_origin_y: i16,
+    /// Vertical origins that differ from the default, sorted by glyph id.
+    pub vert_origin_y_metrics: Vec<VertOriginYMetrics>,
+}
+
+/// A glyph's vertical origin, in the VORG table.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct VertOriginYMetrics {
+    pub glyph_index: GlyphId16,
+    pub vert_origin_y: i16,
+}
+
+impl Vorg {
+    pub fn new(default_vert_origin_y: i16, vert_origin_y_metrics: Vec<VertOriginYMetrics>) -> Self {
+        Self {
+            default_vert_origin_y,
+            vert_origin_y_metrics,
+        }
+    }
+}
+
+impl VertOriginYMetrics {
+    pub fn new(glyph_index: GlyphId16, vert_origin_y: i16) -> Self {
+        Self {
+            glyph_index,
+            vert_origin_y,
+        }
+    }
+}
+
+impl FontWrite for Vorg {
+    fn write_into(&self, writer: &mut TableWriter) {
+        MajorMinor::VERSION_1_0.write_into(writer);
+        self.default_vert_origin_y.write_into(writer);
+        (self.vert_origin_y_metrics.len() as u16).write_into(writer);
+        for metrics in self.vert_origin_y_metrics.iter() {
+            metrics.glyph_index.write_into(writer);
+            metrics.vert_origin_y.write_into(writer);
+        }
+    }
+}
+
+impl Validate for Vorg {
+    fn validate_impl(&self, ctx: &mut ValidationCtx) {
+        ctx.in_table("Vorg", |ctx| {
+            ctx.in_field("vert_origin_y_metrics", |ctx| {
+                if self.vert_origin_y_metrics.len() > u16::MAX as usize {
+                    ctx.report("array exceeds max length");
+                }
+                if !self
+                    .vert_origin_y_metrics
+                    .windows(2)
+                    .all(|pair| pair[0].glyph_index < pair[1].glyph_index)
+                {
+                    ctx.report("records must be sorted by glyph id");
+                }
+            });
+        })
+    }
+}
+
+impl TopLevelTable for Vorg {
+    const TAG: Tag = Tag::new(b"VORG");
+}
+
+impl<'a> FontRead<'a> for Vorg {
+    fn read(data: FontData<'a>) -> Result<Self, ReadError> {
+        let vorg = write_fonts::read::tables::vorg::Vorg::read(data)?;
+        Ok(Vorg {
+            default_vert_origin_y: vorg.default_vert_origin_y(),
+            vert_origin_y_metrics: vorg
+                .vert_origin_y_metrics()
+                .iter()
+                .map(|metrics| {
+                    VertOriginYMetrics::new(metrics.glyph_index(), metrics.vert_origin_y())
+                })
+                .collect(),
+        })
+    }
+}
+
+/// The [VVAR (Vertical Metrics Variations)](https://learn.microsoft.com/en-us/typography/opentype/spec/vvar) table
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Vvar {
+    pub version: MajorMinor,
+    pub item_variation_store: OffsetMarker<ItemVariationStore, 4>,
+    pub advance_height_mapping: NullableOffsetMarker<DeltaSetIndexMap, 4>,
+    pub tsb_mapping: NullableOffsetMarker<DeltaSetIndexMap, 4>,
+    pub bsb_mapping: NullableOffsetMarker<DeltaSetIndexMap, 4>,
+    pub v_org_mapping: NullableOffsetMarker<DeltaSetIndexMap, 4>,
+}
+
+impl Vvar {
+    pub fn new(
+        version: MajorMinor,
+        item_variation_store: ItemVariationStore,
+        advance_height_mapping: Option<DeltaSetIndexMap>,
+        tsb_mapping: Option<DeltaSetIndexMap>,
+        bsb_mapping: Option<DeltaSetIndexMap>,
+        v_org_mapping: Option<DeltaSetIndexMap>,
+    ) -> Self {
+        Self {
+            version,
+            item_variation_store: item_variation_store.into(),
+            advance_height_mapping: advance_height_mapping.into(),
+            tsb_mapping: tsb_mapping.into(),
+            bsb_mapping: bsb_mapping.into(),
+            v_org_mapping: v_org_mapping.into(),
+        }
+    }
+}
+
+impl FontWrite for Vvar {
+    fn write_into(&self, writer: &mut TableWriter) {
+        self.version.write_into(writer);
+        self.item_variation_store.write_into(writer);
+        self.advance_height_mapping.write_into(writer);
+        self.tsb_mapping.write_into(writer);
+        self.bsb_mapping.write_into(writer);
+        self.v_org_mapping.write_into(writer);
+    }
+}
+
+impl Validate for Vvar {
+    fn validate_impl(&self, ctx: &mut ValidationCtx) {
+        ctx.in_table("Vvar", |ctx| {
+            ctx.in_field("item_variation_store", |ctx| {
+                self.item_variation_store.validate_impl(ctx);
+            });
+            ctx.in_field("advance_height_mapping", |ctx| {
+                self.advance_height_mapping.validate_impl(ctx);
+            });
+            ctx.in_field("tsb_mapping", |ctx| {
+                self.tsb_mapping.validate_impl(ctx);
+            });
+            ctx.in_field("bsb_mapping", |ctx| {
+                self.bsb_mapping.validate_impl(ctx);
+            });
+            ctx.in_field("v_org_mapping", |ctx| {
+                self.v_org_mapping.validate_impl(ctx);
+            });
+        })
+    }
+}
+
+impl TopLevelTable for Vvar {
+    const TAG: Tag = Tag::new(b"VVAR");
+}
+
+impl<'a> FromObjRef<write_fonts::read::tables::vvar::Vvar<'a>> for Vvar {
+    fn from_obj_ref(obj: &write_fonts::read::tables::vvar::Vvar<'a>, _: FontData) -> Self {
+        Vvar {
+            version: obj.version(),
+            item_variation_store: obj.item_variation_store().to_owned_table(),
+            advance_height_mapping: obj.advance_height_mapping().to_owned_table(),
+            tsb_mapping: obj.tsb_mapping().to_owned_table(),
+            bsb_mapping: obj.bsb_mapping().to_owned_table(),
+            v_org_mapping: obj.v_org_mapping().to_owned_table(),
+        }
+    }
+}
+
+impl<'a> FromTableRef<write_fonts::read::tables::vvar::Vvar<'a>> for Vvar {}
+
+impl<'a> FontRead<'a> for Vvar {
+    fn read(data: FontData<'a>) -> Result<Self, ReadError> {
+        write_fonts::read::tables::vvar::Vvar::read(data).map(|vvar| vvar.to_owned_table())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use write_fonts::{
+        read::{tables::vorg::Vorg as ReadVorg, FontRead},
+        tables::variations::ivs_builder::VariationStoreBuilder,
+        types::{GlyphId16, MajorMinor},
+    };
+
+    use super::*;
+
+    #[test]
+    fn vorg_round_trip() {
+        let vorg = Vorg::new(
+            880,
+            vec![
+                VertOriginYMetrics::new(GlyphId16::new(2), 900),
+                VertOriginYMetrics::new(GlyphId16::new(5), 700),
+            ],
+        );
+        let bytes = write_fonts::dump_table(&vorg).unwrap();
+        let read = ReadVorg::read(bytes.as_slice().into()).unwrap();
+        assert_eq!(880, read.default_vert_origin_y());
+        assert_eq!(
+            vec![(2, 900), (5, 700)],
+            read.vert_origin_y_metrics()
+                .iter()
+                .map(|m| (m.glyph_index().to_u16(), m.vert_origin_y()))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(vorg, Vorg::read(bytes.as_slice().into()).unwrap());
+    }
+
+    #[test]
+    fn unsorted_vorg_is_invalid() {
+        let vorg = Vorg::new(
+            880,
+            vec![
+                VertOriginYMetrics::new(GlyphId16::new(5), 700),
+                VertOriginYMetrics::new(GlyphId16::new(2), 900),
+            ],
+        );
+        assert!(write_fonts::dump_table(&vorg).is_err());
+    }
+
+    #[test]
+    fn vvar_round_trip() {
+        let (varstore, _) = VariationStoreBuilder::new(1).build();
+        let vvar = Vvar::new(MajorMinor::VERSION_1_0, varstore, None, None, None, None);
+        let bytes = write_fonts::dump_table(&vvar).unwrap();
+        assert_eq!(vvar, Vvar::read(bytes.as_slice().into()).unwrap());
+    }
+}
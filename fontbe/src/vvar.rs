@@ -0,0 +1,155 @@
+//! Generates a [VVAR](https://learn.microsoft.com/en-us/typography/opentype/spec/VVAR) table.
+
+use fontdrasil::{
+    coords::NormalizedLocation,
+    orchestration::{Access, AccessBuilder, Work},
+};
+use fontir::{ir, orchestration::WorkId as FeWorkId};
+use kurbo::Rect;
+use write_fonts::{
+    tables::variations::{ivs_builder::VariationStoreBuilder, DeltaSetIndexMap},
+    types::MajorMinor,
+    OtRound,
+};
+
+use crate::{
+    error::Error,
+    hvar::{build_varstore, GlyphMetricDeltas},
+    orchestration::{AnyWorkId, BeWork, Context, WorkId},
+    vertical_metrics::glyph_vertical_metrics,
+    vertical_tables::Vvar,
+};
+
+#[derive(Debug)]
+struct VvarWork {}
+
+pub fn create_vvar_work() -> Box<BeWork> {
+    Box::new(VvarWork {})
+}
+
+/// The bounds of a glyph instance, looking through components.
+///
+/// Components without an instance at location contribute their default instance.
+fn bounds(
+    context: &Context,
+    instance: &ir::GlyphInstance,
+    location: &NormalizedLocation,
+) -> Option<Rect> {
+    let contours = instance
+        .contours
+        .iter()
+        .map(|contour| contour.control_box());
+    let components = instance.components.iter().filter_map(|component| {
+        let glyph = context.ir.get_glyph(component.base.clone());
+        let instance = glyph
+            .sources()
+            .get(location)
+            .unwrap_or_else(|| glyph.default_instance());
+        bounds(context, instance, location)
+            .map(|bounds| component.transform.transform_rect_bbox(bounds))
+    });
+    contours
+        .chain(components)
+        .reduce(|acc, bounds| acc.union(bounds))
+}
+
+impl Work<Context, AnyWorkId, Error> for VvarWork {
+    fn id(&self) -> AnyWorkId {
+        WorkId::Vvar.into()
+    }
+
+    fn read_access(&self) -> Access<AnyWorkId> {
+        AccessBuilder::new()
+            .variant(FeWorkId::StaticMetadata)
+            .variant(FeWorkId::GlobalMetrics)
+            .variant(FeWorkId::GlyphOrder)
+            .variant(FeWorkId::ALL_GLYPHS)
+            .build()
+    }
+
+    /// Generate [VVAR](https://learn.microsoft.com/en-us/typography/opentype/spec/VVAR)
+    fn exec(&self, context: &Context) -> Result<(), Error> {
+        let static_metadata = context.ir.static_metadata.get();
+        if !static_metadata.misc.build_vertical {
+            return Ok(());
+        }
+        let global_metrics = context.ir.global_metrics.get();
+        let var_model = &static_metadata.variation_model;
+        let glyph_order = context.ir.glyph_order.get();
+        let axis_count = var_model.axes().count().try_into().unwrap();
+        let glyphs: Vec<_> = glyph_order
+            .names()
+            .map(|name| context.ir.get_glyph(name.clone()))
+            .collect();
+        let glyph_locations = || glyphs.iter().flat_map(|glyph| glyph.sources().keys());
+
+        let mut advance_deltas = GlyphMetricDeltas::new(var_model.clone(), glyph_locations());
+        let mut tsb_deltas = GlyphMetricDeltas::new(var_model.clone(), glyph_locations());
+        for glyph in glyphs.iter() {
+            let vertical = glyph_vertical_metrics(glyph, &global_metrics, &static_metadata.axes)?;
+            // values must be rounded before the computing deltas to match fontmake
+            advance_deltas.add(
+                &glyph.name,
+                vertical
+                    .iter()
+                    .map(|(loc, metrics)| (loc.clone(), metrics.advance.ot_round())),
+            )?;
+            tsb_deltas.add(
+                &glyph.name,
+                glyph.sources().iter().map(|(loc, instance)| {
+                    let top_side_bearing = bounds(context, instance, loc)
+                        .map(|bounds| {
+                            let origin: f64 = vertical[loc].origin.ot_round();
+                            let y_max: f64 = bounds.max_y().ot_round();
+                            origin - y_max
+                        })
+                        .unwrap_or_default();
+                    (loc.clone(), top_side_bearing)
+                }),
+            )?;
+        }
+
+        let vvar = if tsb_deltas.is_empty() {
+            let (varstore, advance_map) = build_varstore(&advance_deltas, axis_count)?;
+            Vvar::new(
+                MajorMinor::VERSION_1_0,
+                varstore,
+                advance_map,
+                None,
+                None,
+                None,
+            )
+        } else {
+            // Both mappings index the same store so neither can use implicit indices
+            let mut builder = VariationStoreBuilder::new(axis_count);
+            let advance_idxes: Vec<_> = advance_deltas
+                .iter()
+                .map(|deltas| builder.add_deltas(deltas.clone()))
+                .collect();
+            let tsb_idxes: Vec<_> = tsb_deltas
+                .iter()
+                .map(|deltas| builder.add_deltas(deltas.clone()))
+                .collect();
+            let (varstore, varidx_map) = builder.build();
+            // unwrap since VariationStoreBuilder guarantees that any temporary index returned by
+            // add_deltas will exist in the returned map
+            let to_delta_set_index_map = |idxes: Vec<_>| -> DeltaSetIndexMap {
+                idxes
+                    .into_iter()
+                    .map(|idx| varidx_map.get(idx).unwrap())
+                    .collect()
+            };
+            Vvar::new(
+                MajorMinor::VERSION_1_0,
+                varstore,
+                Some(to_delta_set_index_map(advance_idxes)),
+                Some(to_delta_set_index_map(tsb_idxes)),
+                None,
+                None,
+            )
+        };
+        context.vvar.set(vvar);
+
+        Ok(())
+    }
+}
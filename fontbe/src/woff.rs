@@ -0,0 +1,1449 @@
+//! Encodes an sfnt as [WOFF](https://www.w3.org/TR/WOFF/) or [WOFF2](https://www.w3.org/TR/WOFF2/).
+//!
+//! WOFF compresses each table with zlib. WOFF2 applies the glyf/loca and hmtx
+//! transforms and then compresses all table data as a single Brotli stream.
+
+use std::io::Write;
+
+use brotli::enc::{backward_references::BrotliEncoderMode, BrotliEncoderParams};
+use flate2::{write::ZlibEncoder, Compression};
+use write_fonts::{
+    read::{FontRef, TableProvider, TopLevelTable},
+    tables::{glyf::Glyf, head::Head, hmtx::Hmtx, loca::Loca},
+    types::Tag,
+};
+
+use crate::error::Error;
+
+const WOFF_SIGNATURE: u32 = 0x774F4646; // 'wOFF'
+const WOFF2_SIGNATURE: u32 = 0x774F4632; // 'wOF2'
+
+const WOFF_HEADER_SIZE: usize = 44;
+const WOFF_DIRECTORY_ENTRY_SIZE: usize = 20;
+const WOFF2_HEADER_SIZE: usize = 48;
+
+/// Bit 11 of head.flags, set for fonts that have been through a lossless transform
+///
+/// See <https://learn.microsoft.com/en-us/typography/opentype/spec/head>
+const HEAD_FLAG_TRANSFORMED: u16 = 1 << 11;
+
+/// The tags a WOFF2 table directory can refer to by index rather than spelling out
+///
+/// See <https://www.w3.org/TR/WOFF2/#table_dir_format>
+const WOFF2_KNOWN_TAGS: [&[u8; 4]; 63] = [
+    b"cmap", b"head", b"hhea", b"hmtx", b"maxp", b"name", b"OS/2", b"post", b"cvt ", b"fpgm",
+    b"glyf", b"loca", b"prep", b"CFF ", b"VORG", b"EBDT", b"EBLC", b"gasp", b"hdmx", b"kern",
+    b"LTSH", b"PCLT", b"VDMX", b"vhea", b"vmtx", b"BASE", b"GDEF", b"GPOS", b"GSUB", b"EBSC",
+    b"JSTF", b"MATH", b"CBDT", b"CBLC", b"COLR", b"CPAL", b"SVG ", b"sbix", b"acnt", b"avar",
+    b"bdat", b"bloc", b"bsln", b"cvar", b"fdsc", b"feat", b"fmtx", b"fvar", b"gvar", b"hsty",
+    b"just", b"lcar", b"mort", b"morx", b"opbd", b"prop", b"trak", b"Zapf", b"Silf", b"Glat",
+    b"Gloc", b"Feat", b"Sill",
+];
+
+// Simple glyph flags, <https://learn.microsoft.com/en-us/typography/opentype/spec/glyf#simple-glyph-description>
+const ON_CURVE_POINT: u8 = 0x01;
+const X_SHORT_VECTOR: u8 = 0x02;
+const Y_SHORT_VECTOR: u8 = 0x04;
+const REPEAT_FLAG: u8 = 0x08;
+const X_IS_SAME_OR_POSITIVE: u8 = 0x10;
+const Y_IS_SAME_OR_POSITIVE: u8 = 0x20;
+const OVERLAP_SIMPLE: u8 = 0x40;
+
+// Composite glyph flags, <https://learn.microsoft.com/en-us/typography/opentype/spec/glyf#composite-glyph-description>
+const ARG_1_AND_2_ARE_WORDS: u16 = 0x0001;
+const WE_HAVE_A_SCALE: u16 = 0x0008;
+const MORE_COMPONENTS: u16 = 0x0020;
+const WE_HAVE_AN_X_AND_Y_SCALE: u16 = 0x0040;
+const WE_HAVE_A_TWO_BY_TWO: u16 = 0x0080;
+const WE_HAVE_INSTRUCTIONS: u16 = 0x0100;
+
+/// The web font container to write
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WebFontFormat {
+    Woff,
+    Woff2,
+}
+
+impl WebFontFormat {
+    /// Wrap the sfnt in this format
+    pub fn encode(&self, sfnt: &[u8]) -> Result<Vec<u8>, Error> {
+        match self {
+            WebFontFormat::Woff => to_woff(sfnt),
+            WebFontFormat::Woff2 => to_woff2(sfnt),
+        }
+    }
+}
+
+/// A table from the input sfnt
+struct SfntTable<'a> {
+    tag: Tag,
+    checksum: u32,
+    data: &'a [u8],
+}
+
+/// The pieces of an sfnt we need to build a web font from it
+struct Sfnt<'a> {
+    font: FontRef<'a>,
+    flavor: u32,
+    /// Sorted by tag
+    tables: Vec<SfntTable<'a>>,
+}
+
+impl<'a> Sfnt<'a> {
+    fn new(data: &'a [u8]) -> Result<Self, Error> {
+        let font = FontRef::new(data)?;
+        let flavor = font.table_directory.sfnt_version();
+        let mut tables = font
+            .table_directory
+            .table_records()
+            .iter()
+            .map(|record| {
+                let tag = record.tag();
+                let data = font
+                    .table_data(tag)
+                    .ok_or(Error::MissingTable(tag))?
+                    .as_bytes();
+                let checksum = checksum(data);
+                if checksum != record.checksum() && tag != Head::TAG {
+                    return Err(Error::ChecksumMismatch {
+                        tag,
+                        expected: record.checksum(),
+                        actual: checksum,
+                    });
+                }
+                Ok(SfntTable {
+                    tag,
+                    checksum: record.checksum(),
+                    data,
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        tables.sort_by_key(|t| t.tag);
+        Ok(Sfnt {
+            font,
+            flavor,
+            tables,
+        })
+    }
+
+    /// The size of the sfnt rebuilt from these tables, including padding
+    fn total_size(&self) -> u32 {
+        (12 + 16 * self.tables.len()
+            + self
+                .tables
+                .iter()
+                .map(|t| pad4(t.data.len()))
+                .sum::<usize>()) as u32
+    }
+
+    /// head.fontRevision as (major, minor)
+    fn version(&self) -> (u16, u16) {
+        self.font
+            .head()
+            .map(|head| {
+                let revision = head.font_revision().to_bits() as u32;
+                ((revision >> 16) as u16, revision as u16)
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// Wrap an sfnt in a WOFF container, zlib compressing each table.
+///
+/// Tables that don't get smaller are stored uncompressed. The checksum of each
+/// table is carried over from the sfnt table directory.
+///
+/// See <https://www.w3.org/TR/WOFF/>
+pub fn to_woff(sfnt: &[u8]) -> Result<Vec<u8>, Error> {
+    let sfnt = Sfnt::new(sfnt)?;
+
+    let mut directory = Vec::with_capacity(WOFF_DIRECTORY_ENTRY_SIZE * sfnt.tables.len());
+    let mut table_data = Vec::new();
+    let data_start = WOFF_HEADER_SIZE + WOFF_DIRECTORY_ENTRY_SIZE * sfnt.tables.len();
+    for table in sfnt.tables.iter() {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(table.data)?;
+        let compressed = encoder.finish()?;
+        let stored = if compressed.len() < table.data.len() {
+            compressed.as_slice()
+        } else {
+            table.data
+        };
+
+        directory.extend(table.tag.to_be_bytes());
+        directory.extend(((data_start + table_data.len()) as u32).to_be_bytes());
+        directory.extend((stored.len() as u32).to_be_bytes());
+        directory.extend((table.data.len() as u32).to_be_bytes());
+        directory.extend(table.checksum.to_be_bytes());
+
+        table_data.extend_from_slice(stored);
+        table_data.resize(pad4(table_data.len()), 0);
+    }
+
+    let (major_version, minor_version) = sfnt.version();
+    let length = data_start + table_data.len();
+    let mut woff = Vec::with_capacity(length);
+    woff.extend(WOFF_SIGNATURE.to_be_bytes());
+    woff.extend(sfnt.flavor.to_be_bytes());
+    woff.extend((length as u32).to_be_bytes());
+    woff.extend((sfnt.tables.len() as u16).to_be_bytes());
+    woff.extend(0u16.to_be_bytes()); // reserved
+    woff.extend(sfnt.total_size().to_be_bytes());
+    woff.extend(major_version.to_be_bytes());
+    woff.extend(minor_version.to_be_bytes());
+    // No metadata or private data: offset, length, original length of metadata,
+    // then offset, length of private data
+    woff.extend([0u8; 20]);
+    woff.extend(directory);
+    woff.extend(table_data);
+    debug_assert_eq!(length, woff.len());
+    Ok(woff)
+}
+
+/// Wrap an sfnt in a WOFF2 container.
+///
+/// TrueType fonts get the glyf/loca transform, and the hmtx transform when
+/// side bearings can be recovered from the glyph bounding boxes. All table data
+/// is then compressed as a single Brotli stream.
+///
+/// WOFF2 doesn't store checksums; a decoder recomputes them for the sfnt it
+/// rebuilds. As the spec requires, head.flags bit 11 is set to indicate the
+/// font has been transformed.
+///
+/// See <https://www.w3.org/TR/WOFF2/>
+pub fn to_woff2(sfnt: &[u8]) -> Result<Vec<u8>, Error> {
+    let sfnt = Sfnt::new(sfnt)?;
+
+    let transformed_glyf = transform_glyf(&sfnt)?;
+    let transformed_hmtx = transformed_glyf
+        .as_ref()
+        .map(|glyf| transform_hmtx(&sfnt, &glyf.x_mins))
+        .transpose()?
+        .flatten();
+
+    let mut directory = Vec::new();
+    let mut table_data = Vec::new();
+    for table in sfnt.tables.iter() {
+        let known_tag = WOFF2_KNOWN_TAGS
+            .iter()
+            .position(|known| Tag::new(known) == table.tag);
+        // (transform version, transformed data); transformed tables list their transformed length
+        let (transform_version, transformed): (u8, Option<&[u8]>) = match table.tag {
+            Glyf::TAG | Loca::TAG => match &transformed_glyf {
+                Some(glyf) if table.tag == Glyf::TAG => (0, Some(&glyf.data)),
+                Some(_) => (0, Some(&[])),
+                None => (3, None),
+            },
+            Hmtx::TAG => match &transformed_hmtx {
+                Some(hmtx) => (1, Some(hmtx)),
+                None => (0, None),
+            },
+            _ => (0, None),
+        };
+
+        directory.push(known_tag.unwrap_or(0x3F) as u8 | (transform_version << 6));
+        if known_tag.is_none() {
+            directory.extend(table.tag.to_be_bytes());
+        }
+        write_uint_base128(&mut directory, table.data.len() as u32);
+        if let Some(transformed) = transformed {
+            write_uint_base128(&mut directory, transformed.len() as u32);
+            table_data.extend_from_slice(transformed);
+        } else if table.tag == Head::TAG {
+            let mut head = table.data.to_vec();
+            set_head_transformed_flag(&mut head)?;
+            table_data.extend(head);
+        } else {
+            table_data.extend_from_slice(table.data);
+        }
+    }
+
+    let params = BrotliEncoderParams {
+        quality: 11,
+        lgwin: 22,
+        mode: BrotliEncoderMode::BROTLI_MODE_FONT,
+        size_hint: table_data.len(),
+        ..Default::default()
+    };
+    let mut compressed = Vec::new();
+    brotli::BrotliCompress(&mut table_data.as_slice(), &mut compressed, &params)?;
+
+    let (major_version, minor_version) = sfnt.version();
+    let length = pad4(WOFF2_HEADER_SIZE + directory.len() + compressed.len());
+    let mut woff2 = Vec::with_capacity(length);
+    woff2.extend(WOFF2_SIGNATURE.to_be_bytes());
+    woff2.extend(sfnt.flavor.to_be_bytes());
+    woff2.extend((length as u32).to_be_bytes());
+    woff2.extend((sfnt.tables.len() as u16).to_be_bytes());
+    woff2.extend(0u16.to_be_bytes()); // reserved
+    woff2.extend(sfnt.total_size().to_be_bytes());
+    woff2.extend((compressed.len() as u32).to_be_bytes());
+    woff2.extend(major_version.to_be_bytes());
+    woff2.extend(minor_version.to_be_bytes());
+    // No metadata or private data: offset, length, original length of metadata,
+    // then offset, length of private data
+    woff2.extend([0u8; 20]);
+    woff2.extend(directory);
+    woff2.extend(compressed);
+    woff2.resize(length, 0);
+    Ok(woff2)
+}
+
+fn set_head_transformed_flag(head: &mut [u8]) -> Result<(), Error> {
+    // flags is the u16 at offset 16
+    let Some(flags) = head.get_mut(16..18) else {
+        return Err(Error::InvalidTableBytes(Head::TAG));
+    };
+    let value = u16::from_be_bytes([flags[0], flags[1]]) | HEAD_FLAG_TRANSFORMED;
+    flags.copy_from_slice(&value.to_be_bytes());
+    Ok(())
+}
+
+/// The result of the WOFF2 glyf transform
+struct TransformedGlyf {
+    data: Vec<u8>,
+    /// xMin of each glyph, 0 for empty glyphs, for the hmtx transform
+    x_mins: Vec<i16>,
+}
+
+/// The streams that make up a transformed glyf table
+#[derive(Default)]
+struct GlyfStreams {
+    n_contour: Vec<u8>,
+    n_points: Vec<u8>,
+    flag: Vec<u8>,
+    glyph: Vec<u8>,
+    composite: Vec<u8>,
+    bbox_bitmap: Vec<u8>,
+    bbox: Vec<u8>,
+    instruction: Vec<u8>,
+    overlap_bitmap: Vec<u8>,
+}
+
+/// Apply the WOFF2 glyf/loca transform.
+///
+/// Returns None if the font has no glyf table, or if the glyf table, once a
+/// decoder pads every glyph to 4 bytes, could no longer be addressed by a short loca.
+///
+/// See <https://www.w3.org/TR/WOFF2/#glyf_table_format>
+fn transform_glyf(sfnt: &Sfnt) -> Result<Option<TransformedGlyf>, Error> {
+    let (Some(glyf), Some(_)) = (
+        sfnt.font.table_data(Glyf::TAG),
+        sfnt.font.table_data(Loca::TAG),
+    ) else {
+        return Ok(None);
+    };
+    let glyf = glyf.as_bytes();
+    let head = sfnt.font.head()?;
+    let num_glyphs = sfnt.font.maxp()?.num_glyphs();
+    let index_format = head.index_to_loc_format();
+    let loca = sfnt.font.loca(index_format == 1)?;
+
+    let mut offsets = Vec::with_capacity(num_glyphs as usize + 1);
+    for gid in 0..=num_glyphs as usize {
+        let offset = loca
+            .get_raw(gid)
+            .ok_or(Error::InvalidTableBytes(Loca::TAG))?;
+        offsets.push(offset as usize);
+    }
+    let padded_size: usize = offsets.windows(2).map(|w| pad4(w[1] - w[0])).sum();
+    if index_format == 0 && padded_size > 2 * u16::MAX as usize {
+        return Ok(None);
+    }
+
+    let mut streams = GlyfStreams {
+        bbox_bitmap: vec![0; 4 * (num_glyphs as usize).div_ceil(32)],
+        overlap_bitmap: vec![0; (num_glyphs as usize).div_ceil(8)],
+        ..Default::default()
+    };
+    let mut x_mins = Vec::with_capacity(num_glyphs as usize);
+    let mut has_overlap_bits = false;
+    for (gid, range) in offsets.windows(2).enumerate() {
+        let data = glyf
+            .get(range[0]..range[1])
+            .ok_or(Error::InvalidTableBytes(Glyf::TAG))?;
+        let glyph = RawGlyph::parse(data)?;
+        x_mins.push(glyph.bbox().map(|bbox| bbox[0]).unwrap_or_default());
+        let bit = 0x80u8 >> (gid % 8);
+        match glyph {
+            RawGlyph::Empty => streams.n_contour.extend(0i16.to_be_bytes()),
+            RawGlyph::Simple(simple) => {
+                streams
+                    .n_contour
+                    .extend((simple.end_points.len() as i16).to_be_bytes());
+                let mut start = 0;
+                for end in simple.end_points.iter() {
+                    let end = *end as usize + 1;
+                    if end < start {
+                        return Err(Error::InvalidTableBytes(Glyf::TAG));
+                    }
+                    write_255_u16(&mut streams.n_points, (end - start) as u16);
+                    start = end;
+                }
+                for point in simple.points.iter() {
+                    write_triplet(&mut streams.flag, &mut streams.glyph, point);
+                }
+                write_255_u16(&mut streams.glyph, simple.instructions.len() as u16);
+                streams.instruction.extend_from_slice(simple.instructions);
+                if simple.computed_bbox() != Some(simple.bbox) {
+                    streams.bbox_bitmap[gid / 8] |= bit;
+                    streams
+                        .bbox
+                        .extend(simple.bbox.iter().flat_map(|v| v.to_be_bytes()));
+                }
+                if simple.overlap {
+                    streams.overlap_bitmap[gid / 8] |= bit;
+                    has_overlap_bits = true;
+                }
+            }
+            RawGlyph::Composite(composite) => {
+                streams.n_contour.extend((-1i16).to_be_bytes());
+                streams.composite.extend_from_slice(composite.components);
+                if let Some(instructions) = composite.instructions {
+                    write_255_u16(&mut streams.glyph, instructions.len() as u16);
+                    streams.instruction.extend_from_slice(instructions);
+                }
+                // composites always have an explicit bbox
+                streams.bbox_bitmap[gid / 8] |= bit;
+                streams
+                    .bbox
+                    .extend(composite.bbox.iter().flat_map(|v| v.to_be_bytes()));
+            }
+        }
+    }
+
+    let option_flags: u16 = if has_overlap_bits { 1 } else { 0 };
+    let bbox_stream_size = streams.bbox_bitmap.len() + streams.bbox.len();
+    let mut data = Vec::new();
+    data.extend(0u16.to_be_bytes()); // reserved
+    data.extend(option_flags.to_be_bytes());
+    data.extend(num_glyphs.to_be_bytes());
+    data.extend((index_format as u16).to_be_bytes());
+    for size in [
+        streams.n_contour.len(),
+        streams.n_points.len(),
+        streams.flag.len(),
+        streams.glyph.len(),
+        streams.composite.len(),
+        bbox_stream_size,
+        streams.instruction.len(),
+    ] {
+        data.extend((size as u32).to_be_bytes());
+    }
+    data.extend(streams.n_contour);
+    data.extend(streams.n_points);
+    data.extend(streams.flag);
+    data.extend(streams.glyph);
+    data.extend(streams.composite);
+    data.extend(streams.bbox_bitmap);
+    data.extend(streams.bbox);
+    data.extend(streams.instruction);
+    if has_overlap_bits {
+        data.extend(streams.overlap_bitmap);
+    }
+    Ok(Some(TransformedGlyf { data, x_mins }))
+}
+
+/// Apply the WOFF2 hmtx transform, omitting side bearings that match glyph xMin.
+///
+/// Returns None if no side bearings can be omitted, in which case hmtx should
+/// be stored as-is.
+///
+/// See <https://www.w3.org/TR/WOFF2/#hmtx_table_format>
+fn transform_hmtx(sfnt: &Sfnt, x_mins: &[i16]) -> Result<Option<Vec<u8>>, Error> {
+    let Some(hmtx) = sfnt.font.table_data(Hmtx::TAG) else {
+        return Ok(None);
+    };
+    let hmtx = hmtx.as_bytes();
+    let num_h_metrics = sfnt.font.hhea()?.number_of_h_metrics() as usize;
+    let num_glyphs = x_mins.len();
+    if num_h_metrics == 0
+        || num_h_metrics > num_glyphs
+        || hmtx.len() < 4 * num_h_metrics + 2 * (num_glyphs - num_h_metrics)
+    {
+        return Err(Error::InvalidTableBytes(Hmtx::TAG));
+    }
+    let read_i16 = |offset: usize| i16::from_be_bytes([hmtx[offset], hmtx[offset + 1]]);
+
+    let advances: Vec<_> = (0..num_h_metrics)
+        .map(|gid| read_i16(4 * gid) as u16)
+        .collect();
+    let lsbs: Vec<_> = (0..num_h_metrics)
+        .map(|gid| read_i16(4 * gid + 2))
+        .collect();
+    let left_side_bearings: Vec<_> = (num_h_metrics..num_glyphs)
+        .map(|gid| read_i16(4 * num_h_metrics + 2 * (gid - num_h_metrics)))
+        .collect();
+
+    let omit_lsbs = lsbs.iter().zip(x_mins).all(|(lsb, x_min)| lsb == x_min);
+    let omit_left_side_bearings = left_side_bearings
+        .iter()
+        .zip(&x_mins[num_h_metrics..])
+        .all(|(lsb, x_min)| lsb == x_min);
+    if !omit_lsbs && !omit_left_side_bearings {
+        return Ok(None);
+    }
+
+    let mut data = Vec::new();
+    data.push(omit_lsbs as u8 | ((omit_left_side_bearings as u8) << 1));
+    data.extend(advances.iter().flat_map(|v| v.to_be_bytes()));
+    if !omit_lsbs {
+        data.extend(lsbs.iter().flat_map(|v| v.to_be_bytes()));
+    }
+    if !omit_left_side_bearings {
+        data.extend(left_side_bearings.iter().flat_map(|v| v.to_be_bytes()));
+    }
+    Ok(Some(data))
+}
+
+/// A glyph from the glyf table, parsed just enough to transform it
+enum RawGlyph<'a> {
+    Empty,
+    Simple(RawSimpleGlyph<'a>),
+    Composite(RawCompositeGlyph<'a>),
+}
+
+/// A point of a simple glyph, relative to the previous point
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct RawPoint {
+    dx: i16,
+    dy: i16,
+    on_curve: bool,
+}
+
+struct RawSimpleGlyph<'a> {
+    bbox: [i16; 4],
+    end_points: Vec<u16>,
+    instructions: &'a [u8],
+    points: Vec<RawPoint>,
+    overlap: bool,
+}
+
+struct RawCompositeGlyph<'a> {
+    bbox: [i16; 4],
+    /// The component records, exactly as they appear in glyf
+    components: &'a [u8],
+    instructions: Option<&'a [u8]>,
+}
+
+/// Reads big-endian values from a glyph, failing on truncated data
+struct GlyphCursor<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> GlyphCursor<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + len)
+            .ok_or(Error::InvalidTableBytes(Glyf::TAG))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, Error> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn i16(&mut self) -> Result<i16, Error> {
+        self.u16().map(|v| v as i16)
+    }
+}
+
+impl<'a> RawGlyph<'a> {
+    fn parse(data: &'a [u8]) -> Result<Self, Error> {
+        if data.is_empty() {
+            return Ok(RawGlyph::Empty);
+        }
+        let mut cursor = GlyphCursor { data, pos: 0 };
+        let num_contours = cursor.i16()?;
+        let bbox = [cursor.i16()?, cursor.i16()?, cursor.i16()?, cursor.i16()?];
+        if num_contours < 0 {
+            return Self::parse_composite(cursor, bbox);
+        }
+
+        let end_points = (0..num_contours)
+            .map(|_| cursor.u16())
+            .collect::<Result<Vec<_>, _>>()?;
+        let instruction_len = cursor.u16()? as usize;
+        let instructions = cursor.take(instruction_len)?;
+        let num_points = end_points.last().map(|e| *e as usize + 1).unwrap_or(0);
+
+        let mut flags = Vec::with_capacity(num_points);
+        while flags.len() < num_points {
+            let flag = cursor.u8()?;
+            let repeat = if flag & REPEAT_FLAG != 0 {
+                cursor.u8()? as usize
+            } else {
+                0
+            };
+            flags.extend(std::iter::repeat_n(flag, repeat + 1));
+        }
+        if flags.len() != num_points {
+            return Err(Error::InvalidTableBytes(Glyf::TAG));
+        }
+        let mut read_deltas = |short: u8, same_or_positive: u8| {
+            flags
+                .iter()
+                .map(
+                    |flag| match (flag & short != 0, flag & same_or_positive != 0) {
+                        (true, true) => cursor.u8().map(|v| v as i16),
+                        (true, false) => cursor.u8().map(|v| -(v as i16)),
+                        (false, true) => Ok(0),
+                        (false, false) => cursor.i16(),
+                    },
+                )
+                .collect::<Result<Vec<_>, _>>()
+        };
+        let dxs = read_deltas(X_SHORT_VECTOR, X_IS_SAME_OR_POSITIVE)?;
+        let dys = read_deltas(Y_SHORT_VECTOR, Y_IS_SAME_OR_POSITIVE)?;
+        let points = flags
+            .iter()
+            .zip(dxs.into_iter().zip(dys))
+            .map(|(flag, (dx, dy))| RawPoint {
+                dx,
+                dy,
+                on_curve: flag & ON_CURVE_POINT != 0,
+            })
+            .collect();
+
+        Ok(RawGlyph::Simple(RawSimpleGlyph {
+            bbox,
+            end_points,
+            instructions,
+            points,
+            overlap: flags.first().is_some_and(|f| f & OVERLAP_SIMPLE != 0),
+        }))
+    }
+
+    fn parse_composite(mut cursor: GlyphCursor<'a>, bbox: [i16; 4]) -> Result<Self, Error> {
+        let start = cursor.pos;
+        let mut have_instructions = false;
+        loop {
+            let flags = cursor.u16()?;
+            cursor.u16()?; // glyph index
+            let mut len = if flags & ARG_1_AND_2_ARE_WORDS != 0 {
+                4
+            } else {
+                2
+            };
+            if flags & WE_HAVE_A_SCALE != 0 {
+                len += 2;
+            } else if flags & WE_HAVE_AN_X_AND_Y_SCALE != 0 {
+                len += 4;
+            } else if flags & WE_HAVE_A_TWO_BY_TWO != 0 {
+                len += 8;
+            }
+            cursor.take(len)?;
+            have_instructions |= flags & WE_HAVE_INSTRUCTIONS != 0;
+            if flags & MORE_COMPONENTS == 0 {
+                break;
+            }
+        }
+        let components = &cursor.data[start..cursor.pos];
+        let instructions = if have_instructions {
+            let len = cursor.u16()? as usize;
+            Some(cursor.take(len)?)
+        } else {
+            None
+        };
+        Ok(RawGlyph::Composite(RawCompositeGlyph {
+            bbox,
+            components,
+            instructions,
+        }))
+    }
+
+    /// The bbox as stored in the glyph, [xMin, yMin, xMax, yMax]
+    fn bbox(&self) -> Option<[i16; 4]> {
+        match self {
+            RawGlyph::Empty => None,
+            RawGlyph::Simple(simple) => Some(simple.bbox),
+            RawGlyph::Composite(composite) => Some(composite.bbox),
+        }
+    }
+}
+
+impl RawSimpleGlyph<'_> {
+    /// The bbox a WOFF2 decoder would compute from the points
+    fn computed_bbox(&self) -> Option<[i16; 4]> {
+        let mut x = 0i16;
+        let mut y = 0i16;
+        let mut bbox: Option<[i16; 4]> = None;
+        for point in self.points.iter() {
+            x = x.wrapping_add(point.dx);
+            y = y.wrapping_add(point.dy);
+            bbox = Some(match bbox {
+                None => [x, y, x, y],
+                Some([x_min, y_min, x_max, y_max]) => {
+                    [x_min.min(x), y_min.min(y), x_max.max(x), y_max.max(y)]
+                }
+            });
+        }
+        bbox
+    }
+}
+
+/// Pad to a 4-byte boundary
+fn pad4(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+/// The OpenType table checksum, the sum of the data as zero-padded u32s
+///
+/// See <https://learn.microsoft.com/en-us/typography/opentype/spec/otff#calculating-checksums>
+fn checksum(data: &[u8]) -> u32 {
+    data.chunks(4)
+        .map(|chunk| {
+            let mut word = [0u8; 4];
+            word[..chunk.len()].copy_from_slice(chunk);
+            u32::from_be_bytes(word)
+        })
+        .fold(0u32, |sum, word| sum.wrapping_add(word))
+}
+
+/// See <https://www.w3.org/TR/WOFF2/#DataTypes>
+fn write_uint_base128(out: &mut Vec<u8>, value: u32) {
+    let num_bytes = (1..5).find(|n| value >> (7 * n) == 0).unwrap_or(5);
+    for i in (0..num_bytes).rev() {
+        let byte = ((value >> (7 * i)) & 0x7F) as u8;
+        out.push(if i > 0 { byte | 0x80 } else { byte });
+    }
+}
+
+/// See <https://www.w3.org/TR/WOFF2/#DataTypes>
+fn write_255_u16(out: &mut Vec<u8>, value: u16) {
+    const ONE_MORE_BYTE_CODE_1: u8 = 255;
+    const ONE_MORE_BYTE_CODE_2: u8 = 254;
+    const WORD_CODE: u8 = 253;
+    const LOWEST_U_CODE: u16 = 253;
+    match value {
+        0..253 => out.push(value as u8),
+        253..506 => out.extend([ONE_MORE_BYTE_CODE_1, (value - LOWEST_U_CODE) as u8]),
+        506..762 => out.extend([ONE_MORE_BYTE_CODE_2, (value - 2 * LOWEST_U_CODE) as u8]),
+        _ => {
+            out.push(WORD_CODE);
+            out.extend(value.to_be_bytes());
+        }
+    }
+}
+
+/// Write a point of a simple glyph using the WOFF2 triplet encoding.
+///
+/// See <https://www.w3.org/TR/WOFF2/#triplet_decoding>
+fn write_triplet(flags: &mut Vec<u8>, glyph: &mut Vec<u8>, point: &RawPoint) {
+    let on_curve_bit = if point.on_curve { 0 } else { 0x80 };
+    let x = point.dx as i32;
+    let y = point.dy as i32;
+    let abs_x = x.unsigned_abs();
+    let abs_y = y.unsigned_abs();
+    let x_sign_bit = if x < 0 { 0 } else { 1 };
+    let y_sign_bit = if y < 0 { 0 } else { 1 };
+    let xy_sign_bits = x_sign_bit + 2 * y_sign_bit;
+
+    let flag = if x == 0 && abs_y < 1280 {
+        glyph.push(abs_y as u8);
+        ((abs_y & 0xF00) >> 7) + y_sign_bit
+    } else if y == 0 && abs_x < 1280 {
+        glyph.push(abs_x as u8);
+        10 + ((abs_x & 0xF00) >> 7) + x_sign_bit
+    } else if abs_x < 65 && abs_y < 65 {
+        glyph.push(((((abs_x - 1) & 0xF) << 4) | ((abs_y - 1) & 0xF)) as u8);
+        20 + ((abs_x - 1) & 0x30) + (((abs_y - 1) & 0x30) >> 2) + xy_sign_bits
+    } else if abs_x < 769 && abs_y < 769 {
+        glyph.extend([(abs_x - 1) as u8, (abs_y - 1) as u8]);
+        84 + 12 * (((abs_x - 1) & 0x300) >> 8) + (((abs_y - 1) & 0x300) >> 6) + xy_sign_bits
+    } else if abs_x < 4096 && abs_y < 4096 {
+        glyph.extend([
+            (abs_x >> 4) as u8,
+            (((abs_x & 0xF) << 4) | (abs_y >> 8)) as u8,
+            abs_y as u8,
+        ]);
+        120 + xy_sign_bits
+    } else {
+        glyph.extend((abs_x as u16).to_be_bytes());
+        glyph.extend((abs_y as u16).to_be_bytes());
+        124 + xy_sign_bits
+    };
+    flags.push(on_curve_bit | flag as u8);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use flate2::read::ZlibDecoder;
+    use kurbo::BezPath;
+    use write_fonts::{
+        read::{FontRef, TableProvider, TopLevelTable},
+        tables::{
+            glyf::{
+                Anchor, Component, ComponentFlags, CompositeGlyph, GlyfLocaBuilder, Glyph,
+                SimpleGlyph, Transform,
+            },
+            head::Head,
+            hhea::Hhea,
+            hmtx::{Hmtx, LongMetric},
+            loca::{Loca, LocaFormat},
+            maxp::Maxp,
+            post::Post,
+        },
+        types::{F2Dot14, Fixed, GlyphId16, Tag},
+        FontBuilder,
+    };
+
+    use super::*;
+
+    fn simple_glyph(svg: &str, instructions: Vec<u8>) -> SimpleGlyph {
+        let mut glyph = SimpleGlyph::from_bezpath(&BezPath::from_svg(svg).unwrap()).unwrap();
+        glyph.instructions = instructions;
+        glyph
+    }
+
+    /// A TrueType font whose glyphs exercise every triplet encoding, instructions,
+    /// composites and empty glyphs.
+    ///
+    /// Each glyph's lsb is its xMin plus the matching entry of lsb_deltas.
+    fn test_font(lsb_deltas: [i16; 4]) -> Vec<u8> {
+        let glyphs = [
+            Glyph::Empty,
+            Glyph::Simple(simple_glyph(
+                "M10,0 L10,1000 L1200,1000 L1200,940 L1260,1000 L1900,1700 L5000,1700 Q-6000,-3000 10,0 Z",
+                vec![0xB0, 0x01, 0x2C],
+            )),
+            Glyph::Simple(simple_glyph("M-100,-5 L-90,5 L-80,-5 Z M0,0 L0,-1279 Z", vec![])),
+            Glyph::Composite(
+                CompositeGlyph::try_from_iter([
+                    (
+                        Component::new(
+                            GlyphId16::new(1),
+                            Anchor::Offset { x: 300, y: -20 },
+                            Transform::default(),
+                            ComponentFlags::default(),
+                        ),
+                        write_fonts::tables::glyf::Bbox {
+                            x_min: 300,
+                            y_min: -20,
+                            x_max: 5300,
+                            y_max: 1680,
+                        },
+                    ),
+                    (
+                        Component::new(
+                            GlyphId16::new(2),
+                            Anchor::Offset { x: 1000, y: 0 },
+                            Transform {
+                                xx: F2Dot14::from_f32(0.5),
+                                yy: F2Dot14::from_f32(0.5),
+                                ..Default::default()
+                            },
+                            ComponentFlags::default(),
+                        ),
+                        write_fonts::tables::glyf::Bbox {
+                            x_min: 950,
+                            y_min: -640,
+                            x_max: 1000,
+                            y_max: 3,
+                        },
+                    ),
+                ])
+                .unwrap(),
+            ),
+        ];
+
+        let mut builder = GlyfLocaBuilder::new();
+        let mut x_mins = Vec::new();
+        for glyph in glyphs.iter() {
+            builder.add_glyph(glyph).unwrap();
+            x_mins.push(glyph.bbox().map(|b| b.x_min).unwrap_or_default());
+        }
+        let (glyf, loca, loca_format) = builder.build();
+
+        let lsbs: Vec<_> = x_mins.iter().zip(lsb_deltas).map(|(x, d)| x + d).collect();
+        // Two long metrics so both the lsb and leftSideBearing arrays are present
+        let hmtx = Hmtx::new(
+            vec![LongMetric::new(500, lsbs[0]), LongMetric::new(600, lsbs[1])],
+            lsbs[2..].to_vec(),
+        );
+        let head = Head {
+            font_revision: Fixed::from_f64(2.5),
+            units_per_em: 1000,
+            index_to_loc_format: if loca_format == LocaFormat::Long {
+                1
+            } else {
+                0
+            },
+            ..Default::default()
+        };
+        let hhea = Hhea {
+            number_of_h_metrics: 2,
+            ..Default::default()
+        };
+
+        let mut builder = FontBuilder::new();
+        builder
+            .add_table(&head)
+            .unwrap()
+            .add_table(&hhea)
+            .unwrap()
+            .add_table(&Maxp::new(glyphs.len() as u16))
+            .unwrap()
+            .add_table(&hmtx)
+            .unwrap()
+            .add_table(&glyf)
+            .unwrap()
+            .add_table(&loca)
+            .unwrap()
+            .add_table(&Post::default())
+            .unwrap();
+        builder.build()
+    }
+
+    fn read_u16(data: &[u8], offset: usize) -> u16 {
+        u16::from_be_bytes(data[offset..offset + 2].try_into().unwrap())
+    }
+
+    fn read_u32(data: &[u8], offset: usize) -> u32 {
+        u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap())
+    }
+
+    /// Decode a WOFF file to (tag, checksum, data) for each table
+    fn decode_woff(woff: &[u8]) -> Vec<(Tag, u32, Vec<u8>)> {
+        assert_eq!(WOFF_SIGNATURE, read_u32(woff, 0));
+        assert_eq!(woff.len(), read_u32(woff, 8) as usize);
+        let num_tables = read_u16(woff, 12) as usize;
+        (0..num_tables)
+            .map(|i| {
+                let entry = WOFF_HEADER_SIZE + i * WOFF_DIRECTORY_ENTRY_SIZE;
+                let tag = Tag::from_u32(read_u32(woff, entry));
+                let offset = read_u32(woff, entry + 4) as usize;
+                let comp_length = read_u32(woff, entry + 8) as usize;
+                let orig_length = read_u32(woff, entry + 12) as usize;
+                let checksum = read_u32(woff, entry + 16);
+                assert_eq!(0, offset % 4, "{tag} isn't 4-byte aligned");
+                let stored = &woff[offset..offset + comp_length];
+                let data = if comp_length < orig_length {
+                    let mut data = Vec::new();
+                    ZlibDecoder::new(stored).read_to_end(&mut data).unwrap();
+                    data
+                } else {
+                    stored.to_vec()
+                };
+                assert_eq!(orig_length, data.len(), "{tag}");
+                (tag, checksum, data)
+            })
+            .collect()
+    }
+
+    fn read_uint_base128(data: &[u8], pos: &mut usize) -> u32 {
+        let mut value = 0u32;
+        loop {
+            let byte = data[*pos];
+            *pos += 1;
+            value = (value << 7) | (byte & 0x7F) as u32;
+            if byte & 0x80 == 0 {
+                return value;
+            }
+        }
+    }
+
+    fn read_255_u16(data: &[u8], pos: &mut usize) -> u16 {
+        let code = data[*pos];
+        *pos += 1;
+        let value = match code {
+            253 => {
+                *pos += 2;
+                return read_u16(data, *pos - 2);
+            }
+            254 => data[*pos] as u16 + 506,
+            255 => data[*pos] as u16 + 253,
+            _ => return code as u16,
+        };
+        *pos += 1;
+        value
+    }
+
+    struct Woff2Table {
+        tag: Tag,
+        transform_version: u8,
+        orig_length: usize,
+        data: Vec<u8>,
+    }
+
+    /// Decode a WOFF2 file to its tables, leaving any transforms in place
+    fn decode_woff2(woff2: &[u8]) -> (u32, Vec<Woff2Table>) {
+        assert_eq!(WOFF2_SIGNATURE, read_u32(woff2, 0));
+        assert_eq!(woff2.len(), read_u32(woff2, 8) as usize);
+        assert_eq!(0, woff2.len() % 4);
+        let num_tables = read_u16(woff2, 12) as usize;
+        let compressed_size = read_u32(woff2, 20) as usize;
+
+        let mut pos = WOFF2_HEADER_SIZE;
+        let mut entries = Vec::new();
+        for _ in 0..num_tables {
+            let flags = woff2[pos];
+            pos += 1;
+            let tag = match flags & 0x3F {
+                0x3F => {
+                    pos += 4;
+                    Tag::from_u32(read_u32(woff2, pos - 4))
+                }
+                index => Tag::new(WOFF2_KNOWN_TAGS[index as usize]),
+            };
+            let transform_version = flags >> 6;
+            let orig_length = read_uint_base128(woff2, &mut pos) as usize;
+            let transformed = match tag {
+                Glyf::TAG | Loca::TAG => transform_version == 0,
+                _ => transform_version != 0,
+            };
+            let length = if transformed {
+                read_uint_base128(woff2, &mut pos) as usize
+            } else {
+                orig_length
+            };
+            entries.push((tag, transform_version, orig_length, length));
+        }
+
+        let mut stream = Vec::new();
+        brotli::BrotliDecompress(&mut &woff2[pos..pos + compressed_size], &mut stream).unwrap();
+        let mut offset = 0;
+        let tables = entries
+            .into_iter()
+            .map(|(tag, transform_version, orig_length, length)| {
+                let data = stream[offset..offset + length].to_vec();
+                offset += length;
+                Woff2Table {
+                    tag,
+                    transform_version,
+                    orig_length,
+                    data,
+                }
+            })
+            .collect();
+        assert_eq!(stream.len(), offset);
+        (read_u32(woff2, 4), tables)
+    }
+
+    /// A glyph as a decoder would rebuild it: bbox, end points, absolute points,
+    /// instructions, and for composites the raw component records.
+    #[derive(Debug, PartialEq)]
+    struct DecodedGlyph {
+        num_contours: i16,
+        bbox: Option<[i16; 4]>,
+        end_points: Vec<u16>,
+        points: Vec<(i32, i32, bool)>,
+        instructions: Vec<u8>,
+        components: Vec<u8>,
+    }
+
+    fn with_sign(flag: u8, value: i32) -> i32 {
+        if flag & 1 != 0 {
+            value
+        } else {
+            -value
+        }
+    }
+
+    /// Reverse <https://www.w3.org/TR/WOFF2/#triplet_decoding>
+    fn read_triplet(flag: u8, data: &[u8], pos: &mut usize) -> (i32, i32, bool) {
+        let on_curve = flag & 0x80 == 0;
+        let flag = flag & 0x7F;
+        let mut next = || {
+            *pos += 1;
+            data[*pos - 1] as i32
+        };
+        let (dx, dy) = match flag {
+            0..10 => (0, with_sign(flag, (((flag & 14) as i32) << 7) + next())),
+            10..20 => (
+                with_sign(flag, ((((flag - 10) & 14) as i32) << 7) + next()),
+                0,
+            ),
+            20..84 => {
+                let b0 = (flag - 20) as i32;
+                let b1 = next();
+                (
+                    with_sign(flag, 1 + (b0 & 0x30) + (b1 >> 4)),
+                    with_sign(flag >> 1, 1 + ((b0 & 0x0C) << 2) + (b1 & 0x0F)),
+                )
+            }
+            84..120 => {
+                let b0 = (flag - 84) as i32;
+                (
+                    with_sign(flag, 1 + ((b0 / 12) << 8) + next()),
+                    with_sign(flag >> 1, 1 + (((b0 % 12) >> 2) << 8) + next()),
+                )
+            }
+            120..124 => {
+                let (b0, b1, b2) = (next(), next(), next());
+                (
+                    with_sign(flag, (b0 << 4) + (b1 >> 4)),
+                    with_sign(flag >> 1, ((b1 & 0x0F) << 8) + b2),
+                )
+            }
+            _ => {
+                let (b0, b1, b2, b3) = (next(), next(), next(), next());
+                (
+                    with_sign(flag, (b0 << 8) + b1),
+                    with_sign(flag >> 1, (b2 << 8) + b3),
+                )
+            }
+        };
+        (dx, dy, on_curve)
+    }
+
+    /// Reverse <https://www.w3.org/TR/WOFF2/#glyf_table_format>
+    fn decode_transformed_glyf(data: &[u8]) -> (u16, Vec<DecodedGlyph>) {
+        let num_glyphs = read_u16(data, 4) as usize;
+        let index_format = read_u16(data, 6);
+        let mut start = 36;
+        let mut streams = Vec::new();
+        for i in 0..7 {
+            let size = read_u32(data, 8 + 4 * i) as usize;
+            streams.push(&data[start..start + size]);
+            start += size;
+        }
+        let [n_contour, n_points, flags, glyph, composite, bbox, instruction] =
+            streams.try_into().unwrap();
+        let bbox_bitmap_len = 4 * num_glyphs.div_ceil(32);
+        let (bbox_bitmap, bbox) = bbox.split_at(bbox_bitmap_len);
+
+        let (mut n_points_pos, mut flags_pos, mut glyph_pos, mut composite_pos) = (0, 0, 0, 0);
+        let (mut bbox_pos, mut instruction_pos) = (0, 0);
+        let mut glyphs = Vec::new();
+        for gid in 0..num_glyphs {
+            let num_contours = read_u16(n_contour, 2 * gid) as i16;
+            let has_bbox = bbox_bitmap[gid / 8] & (0x80 >> (gid % 8)) != 0;
+            let mut decoded = DecodedGlyph {
+                num_contours,
+                bbox: None,
+                end_points: Vec::new(),
+                points: Vec::new(),
+                instructions: Vec::new(),
+                components: Vec::new(),
+            };
+            let mut has_instructions = num_contours > 0;
+            if num_contours > 0 {
+                let mut end = 0;
+                for _ in 0..num_contours {
+                    end += read_255_u16(n_points, &mut n_points_pos);
+                    decoded.end_points.push(end - 1);
+                }
+                let (mut x, mut y) = (0, 0);
+                for _ in 0..end {
+                    let (dx, dy, on_curve) = read_triplet(flags[flags_pos], glyph, &mut glyph_pos);
+                    flags_pos += 1;
+                    x += dx;
+                    y += dy;
+                    decoded.points.push((x, y, on_curve));
+                }
+            } else if num_contours < 0 {
+                let start = composite_pos;
+                loop {
+                    let flags = read_u16(composite, composite_pos);
+                    let mut len = 4 + if flags & ARG_1_AND_2_ARE_WORDS != 0 {
+                        4
+                    } else {
+                        2
+                    };
+                    if flags & WE_HAVE_A_SCALE != 0 {
+                        len += 2;
+                    } else if flags & WE_HAVE_AN_X_AND_Y_SCALE != 0 {
+                        len += 4;
+                    } else if flags & WE_HAVE_A_TWO_BY_TWO != 0 {
+                        len += 8;
+                    }
+                    composite_pos += len;
+                    has_instructions |= flags & WE_HAVE_INSTRUCTIONS != 0;
+                    if flags & MORE_COMPONENTS == 0 {
+                        break;
+                    }
+                }
+                decoded.components = composite[start..composite_pos].to_vec();
+            }
+            if has_instructions {
+                let len = read_255_u16(glyph, &mut glyph_pos) as usize;
+                decoded.instructions = instruction[instruction_pos..instruction_pos + len].to_vec();
+                instruction_pos += len;
+            }
+            if has_bbox {
+                decoded.bbox = Some(std::array::from_fn(|i| {
+                    read_u16(bbox, bbox_pos + 2 * i) as i16
+                }));
+                bbox_pos += 8;
+            } else if !decoded.points.is_empty() {
+                let xs = decoded.points.iter().map(|p| p.0 as i16);
+                let ys = decoded.points.iter().map(|p| p.1 as i16);
+                decoded.bbox = Some([
+                    xs.clone().min().unwrap(),
+                    ys.clone().min().unwrap(),
+                    xs.max().unwrap(),
+                    ys.max().unwrap(),
+                ]);
+            }
+            glyphs.push(decoded);
+        }
+        assert_eq!(
+            (n_points.len(), flags.len(), glyph.len(), composite.len()),
+            (n_points_pos, flags_pos, glyph_pos, composite_pos)
+        );
+        assert_eq!((bbox.len(), instruction.len()), (bbox_pos, instruction_pos));
+        (index_format, glyphs)
+    }
+
+    /// The glyphs of an sfnt in the same form as [decode_transformed_glyf]
+    fn sfnt_glyphs(sfnt: &[u8]) -> Vec<DecodedGlyph> {
+        let font = FontRef::new(sfnt).unwrap();
+        let glyf = font.table_data(Glyf::TAG).unwrap();
+        let loca = font.loca(None).unwrap();
+        (0..font.maxp().unwrap().num_glyphs() as usize)
+            .map(|gid| {
+                let range =
+                    loca.get_raw(gid).unwrap() as usize..loca.get_raw(gid + 1).unwrap() as usize;
+                let glyph = RawGlyph::parse(&glyf.as_bytes()[range]).unwrap();
+                let mut decoded = DecodedGlyph {
+                    num_contours: 0,
+                    bbox: glyph.bbox(),
+                    end_points: Vec::new(),
+                    points: Vec::new(),
+                    instructions: Vec::new(),
+                    components: Vec::new(),
+                };
+                match glyph {
+                    RawGlyph::Empty => (),
+                    RawGlyph::Simple(simple) => {
+                        decoded.num_contours = simple.end_points.len() as i16;
+                        decoded.end_points = simple.end_points.clone();
+                        decoded.instructions = simple.instructions.to_vec();
+                        let (mut x, mut y) = (0, 0);
+                        for point in simple.points.iter() {
+                            x += point.dx as i32;
+                            y += point.dy as i32;
+                            decoded.points.push((x, y, point.on_curve));
+                        }
+                    }
+                    RawGlyph::Composite(composite) => {
+                        decoded.num_contours = -1;
+                        decoded.components = composite.components.to_vec();
+                        decoded.instructions = composite.instructions.unwrap_or_default().to_vec();
+                    }
+                }
+                decoded
+            })
+            .collect()
+    }
+
+    fn encoded_uint_base128(value: u32) -> Vec<u8> {
+        let mut buf = Vec::new();
+        write_uint_base128(&mut buf, value);
+        buf
+    }
+
+    fn encoded_255_u16(value: u16) -> Vec<u8> {
+        let mut buf = Vec::new();
+        write_255_u16(&mut buf, value);
+        buf
+    }
+
+    #[test]
+    fn uint_base128() {
+        assert_eq!(vec![0x00], encoded_uint_base128(0));
+        assert_eq!(vec![0x3F], encoded_uint_base128(63));
+        assert_eq!(vec![0x81, 0x00], encoded_uint_base128(128));
+        assert_eq!(
+            vec![0x8F, 0xFF, 0xFF, 0xFF, 0x7F],
+            encoded_uint_base128(u32::MAX)
+        );
+    }
+
+    #[test]
+    fn uint_255_u16() {
+        for (value, expected) in [
+            (252, vec![252]),
+            (253, vec![255, 0]),
+            (505, vec![255, 252]),
+            (506, vec![254, 0]),
+            (761, vec![254, 255]),
+            (762, vec![253, 0x02, 0xFA]),
+            (u16::MAX, vec![253, 0xFF, 0xFF]),
+        ] {
+            let encoded = encoded_255_u16(value);
+            assert_eq!(expected, encoded, "{value}");
+            assert_eq!(value, read_255_u16(&encoded, &mut 0));
+        }
+    }
+
+    #[test]
+    fn triplets_round_trip() {
+        let mut deltas = Vec::new();
+        for v in [
+            0, 1, 63, 64, 65, 255, 256, 767, 768, 769, 1279, 1280, 4095, 4096, 32767,
+        ] {
+            for (x, y) in [
+                (0, v),
+                (v, 0),
+                (v, v),
+                (v, 1),
+                (1, v),
+                (v, 4095 - v.min(4095)),
+            ] {
+                for (sx, sy) in [(1, 1), (-1, 1), (1, -1), (-1, -1)] {
+                    deltas.push((x * sx, y * sy));
+                }
+            }
+        }
+        deltas.push((i16::MIN, i16::MIN));
+
+        let mut flags = Vec::new();
+        let mut glyph = Vec::new();
+        for (i, (dx, dy)) in deltas.iter().enumerate() {
+            let point = RawPoint {
+                dx: *dx,
+                dy: *dy,
+                on_curve: i.is_multiple_of(2),
+            };
+            write_triplet(&mut flags, &mut glyph, &point);
+        }
+        let mut pos = 0;
+        for (i, (flag, (dx, dy))) in flags.iter().zip(deltas.iter()).enumerate() {
+            assert_eq!(
+                (*dx as i32, *dy as i32, i.is_multiple_of(2)),
+                read_triplet(*flag, &glyph, &mut pos),
+                "point {i}"
+            );
+        }
+        assert_eq!(glyph.len(), pos);
+    }
+
+    #[test]
+    fn woff_round_trips_tables_and_checksums() {
+        let sfnt = test_font([0; 4]);
+        let woff = to_woff(&sfnt).unwrap();
+        let font = FontRef::new(&sfnt).unwrap();
+
+        assert_eq!(
+            font.table_directory.sfnt_version(),
+            read_u32(&woff, 4),
+            "flavor"
+        );
+        assert_eq!((2, 0x8000), (read_u16(&woff, 20), read_u16(&woff, 22)));
+        let tables = decode_woff(&woff);
+        assert_eq!(font.table_directory.table_records().len(), tables.len());
+        for (tag, checksum, data) in tables {
+            let record = font
+                .table_directory
+                .table_records()
+                .iter()
+                .find(|r| r.tag() == tag)
+                .unwrap();
+            assert_eq!(record.checksum(), checksum, "{tag}");
+            assert_eq!(font.table_data(tag).unwrap().as_bytes(), data, "{tag}");
+            if tag != Head::TAG {
+                assert_eq!(super::checksum(&data), checksum, "{tag}");
+            }
+        }
+    }
+
+    #[test]
+    fn woff2_round_trips_glyf_and_hmtx() {
+        let sfnt = test_font([0; 4]);
+        let font = FontRef::new(&sfnt).unwrap();
+        let (flavor, tables) = decode_woff2(&to_woff2(&sfnt).unwrap());
+        assert_eq!(font.table_directory.sfnt_version(), flavor);
+        assert_eq!(
+            font.table_directory
+                .table_records()
+                .iter()
+                .map(|r| r.tag())
+                .collect::<Vec<_>>(),
+            tables.iter().map(|t| t.tag).collect::<Vec<_>>()
+        );
+
+        for table in tables.iter() {
+            let original = font.table_data(table.tag).unwrap().as_bytes();
+            assert_eq!(original.len(), table.orig_length, "{}", table.tag);
+            match table.tag {
+                Glyf::TAG => {
+                    assert_eq!(0, table.transform_version);
+                    let (index_format, glyphs) = decode_transformed_glyf(&table.data);
+                    assert_eq!(
+                        font.head().unwrap().index_to_loc_format() as u16,
+                        index_format
+                    );
+                    assert_eq!(sfnt_glyphs(&sfnt), glyphs);
+                }
+                Loca::TAG => {
+                    assert_eq!(0, table.transform_version);
+                    assert!(table.data.is_empty());
+                }
+                Hmtx::TAG => {
+                    assert_eq!(1, table.transform_version);
+                    // both lsb arrays omitted, leaving flags and advances
+                    assert_eq!(vec![0b11, 0x01, 0xF4, 0x02, 0x58], table.data);
+                }
+                Head::TAG => {
+                    let flags = read_u16(&table.data, 16);
+                    assert_eq!(HEAD_FLAG_TRANSFORMED, flags & HEAD_FLAG_TRANSFORMED);
+                    assert_eq!(original[..16], table.data[..16]);
+                    assert_eq!(original[18..], table.data[18..]);
+                }
+                _ => assert_eq!(original, table.data, "{}", table.tag),
+            }
+        }
+    }
+
+    #[test]
+    fn woff2_hmtx_keeps_lsbs_that_are_not_xmin() {
+        let sfnt = test_font([0, 1, 0, 0]);
+        let (_, tables) = decode_woff2(&to_woff2(&sfnt).unwrap());
+        let hmtx = tables.iter().find(|t| t.tag == Hmtx::TAG).unwrap();
+        assert_eq!(1, hmtx.transform_version);
+        // only the leftSideBearing array is omitted; lsbs are 0 and xMin + 1 = -5999
+        assert_eq!(
+            vec![0b10, 0x01, 0xF4, 0x02, 0x58, 0x00, 0x00, 0xE8, 0x91],
+            hmtx.data
+        );
+    }
+
+    #[test]
+    fn woff2_keeps_hmtx_if_no_lsbs_are_xmin() {
+        let sfnt = test_font([0, 1, 1, 0]);
+        let font = FontRef::new(&sfnt).unwrap();
+        let (_, tables) = decode_woff2(&to_woff2(&sfnt).unwrap());
+        let hmtx = tables.iter().find(|t| t.tag == Hmtx::TAG).unwrap();
+        assert_eq!(0, hmtx.transform_version);
+        assert_eq!(font.table_data(Hmtx::TAG).unwrap().as_bytes(), hmtx.data);
+    }
+
+    #[test]
+    fn woff2_without_glyf_is_untransformed() {
+        let hhea = Hhea {
+            number_of_h_metrics: 1,
+            ..Default::default()
+        };
+        let mut builder = FontBuilder::new();
+        builder
+            .add_table(&Head::default())
+            .unwrap()
+            .add_table(&hhea)
+            .unwrap()
+            .add_table(&Maxp::new(1))
+            .unwrap()
+            .add_table(&Hmtx::new(vec![LongMetric::new(500, 0)], vec![]))
+            .unwrap()
+            .add_raw(Tag::new(b"ZZZZ"), vec![1, 2, 3]);
+        let sfnt = builder.build();
+        let font = FontRef::new(&sfnt).unwrap();
+        let (_, tables) = decode_woff2(&to_woff2(&sfnt).unwrap());
+        for table in tables.iter().filter(|t| t.tag != Head::TAG) {
+            assert_eq!(0, table.transform_version, "{}", table.tag);
+            assert_eq!(font.table_data(table.tag).unwrap().as_bytes(), table.data);
+        }
+        assert!(tables.iter().any(|t| t.tag == Tag::new(b"ZZZZ")));
+    }
+}
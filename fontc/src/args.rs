@@ -1,11 +1,17 @@
 //! Command line arguments
 
-use std::path::{Path, PathBuf};
-
-use clap::{ArgAction, Parser};
-use fontir::orchestration::Flags;
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use clap::{ArgAction, Parser, ValueEnum};
+use fontbe::woff::WebFontFormat;
+use fontdrasil::coords::UserCoord;
+use fontir::{instancer::AxisLimit, orchestration::Flags};
 use regex::Regex;
 use serde::{Deserialize, Deserializer, Serialize};
+use write_fonts::types::Tag;
 
 /// What font can we build for you today?
 #[derive(Serialize, Deserialize, Parser, Debug, Clone, PartialEq)]
@@ -24,13 +30,47 @@ pub struct Args {
     source: Option<PathBuf>,
 
     /// Whether to write IR to disk.
+    ///
+    /// A later compilation into the same build directory reuses IR whose inputs didn't change.
     #[arg(short, long, default_value = "false")]
     pub emit_ir: bool,
 
-    /// Output file name (default: build/font.ttf)
+    /// Output file name (default: build/font.ttf, or build/font.otf for otf output)
+    ///
+    /// A .woff or .woff2 extension writes a web font, as if --flavor had been passed.
+    ///
+    /// A designspace that describes several variable fonts, with `<variable-fonts>` or
+    /// discrete axes, writes each to the directory of the output file (default: build/)
+    /// named for the variable font.
     #[arg(short, long)]
     pub output_file: Option<PathBuf>,
 
+    /// The flavor of outlines to write; TrueType (glyf/gvar) or CFF (CFF/CFF2).
+    ///
+    /// otf-default writes a static CFF font of the default master of variable sources,
+    /// pinning any axis limited with --axis-limit as close to its default as it allows.
+    #[arg(long, value_enum, default_value_t = OutputFormat::Ttf)]
+    pub output_format: OutputFormat,
+
+    /// Wrap the font in a web font container.
+    ///
+    /// If not set, an output file ending in .woff or .woff2 selects the flavor.
+    #[arg(long, value_enum)]
+    pub flavor: Option<Flavor>,
+
+    /// Whether to also write a static font for each named instance of a variable font.
+    ///
+    /// Written to build/instances/, named for the postscript name of the instance.
+    #[arg(long, default_value = "false")]
+    pub static_instances: bool,
+
+    /// Restrict an axis to a range, or pin it at a value, in user space; e.g. wght=400:700 or wdth=100.
+    ///
+    /// May be repeated. The font is built for just that part of the variation space,
+    /// sources outside it are interpolated at its edges and pinned axes are removed.
+    #[arg(long = "axis-limit", value_parser = parse_axis_limit)]
+    pub axis_limits: Vec<AxisLimit>,
+
     /// Whether to write additional debug files to disk.
     #[arg(long, default_value = "false")]
     pub emit_debug: bool,
@@ -50,6 +90,20 @@ pub struct Args {
     #[arg(long, default_value = "false")]
     pub decompose_transformed_components: bool,
 
+    /// Whether anchors of components are copied to the composites using them.
+    ///
+    /// Sources can also ask for this with the ufo2ft propagateAnchors filter.
+    /// Glyphs sources do it too, unless the 'Propagate Anchors' custom parameter is off.
+    #[arg(long, default_value = "false")]
+    pub propagate_anchors: bool,
+
+    /// Whether to set the glyf overlap flags only on glyphs whose outline overlaps.
+    ///
+    /// Variable fonts otherwise flag every glyph, as fontmake does, so rasterizers
+    /// that need the hint render overlaps without dropouts.
+    #[arg(long, default_value = "false")]
+    pub detect_overlaps: bool,
+
     /// Whether to out timing data, notably a visualization of threadpool execution of tasks.
     ///
     /// See <https://github.com/googlefonts/fontc/pull/443>
@@ -97,6 +151,60 @@ pub struct Args {
     pub log: Option<String>,
 }
 
+/// The kind of outlines a font is built with
+#[derive(Serialize, Deserialize, ValueEnum, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// TrueType outlines, glyf and gvar
+    #[default]
+    Ttf,
+    /// PostScript outlines, CFF for static fonts and CFF2 for variable fonts
+    Otf,
+    /// PostScript outlines in a static font with CFF; variable sources are built at their default
+    OtfDefault,
+}
+
+impl OutputFormat {
+    /// Whether outlines are CFF or CFF2, rather than glyf
+    pub fn is_cff(&self) -> bool {
+        matches!(self, OutputFormat::Otf | OutputFormat::OtfDefault)
+    }
+}
+
+/// The web font container a font is written in
+#[derive(Serialize, Deserialize, ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flavor {
+    /// WOFF, each table zlib compressed
+    Woff,
+    /// WOFF2, glyf/loca and hmtx transformed then Brotli compressed
+    Woff2,
+}
+
+impl Flavor {
+    /// The conventional file extension for this flavor
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Flavor::Woff => "woff",
+            Flavor::Woff2 => "woff2",
+        }
+    }
+
+    fn from_extension(path: &Path) -> Option<Flavor> {
+        let extension = path.extension()?.to_str()?;
+        [Flavor::Woff, Flavor::Woff2]
+            .into_iter()
+            .find(|flavor| extension.eq_ignore_ascii_case(flavor.extension()))
+    }
+}
+
+impl From<Flavor> for WebFontFormat {
+    fn from(value: Flavor) -> Self {
+        match value {
+            Flavor::Woff => WebFontFormat::Woff,
+            Flavor::Woff2 => WebFontFormat::Woff2,
+        }
+    }
+}
+
 /// A wrapper around a validated regex string
 ///
 /// This is a wrapper because the Regex type itself does not implement PartialEq or
@@ -117,9 +225,12 @@ impl Args {
             Flags::DECOMPOSE_TRANSFORMED_COMPONENTS,
             self.decompose_transformed_components,
         );
+        flags.set(Flags::PROPAGATE_ANCHORS, self.propagate_anchors);
+        flags.set(Flags::DETECT_OVERLAPS, self.detect_overlaps);
         flags.set(Flags::EMIT_TIMING, self.emit_timing);
         flags.set(Flags::KEEP_DIRECTION, self.keep_direction);
         flags.set(Flags::PRODUCTION_NAMES, !self.no_production_names);
+        flags.set(Flags::CFF_OUTLINES, self.output_format.is_cff());
 
         flags
     }
@@ -131,6 +242,10 @@ impl Args {
             source: None,
             emit_ir: false,
             output_file: None,
+            output_format: OutputFormat::Ttf,
+            flavor: None,
+            static_instances: false,
+            axis_limits: Vec::new(),
             emit_debug: false, // they get destroyed by test cleanup
             emit_timing: false,
             build_dir: build_dir.to_path_buf(),
@@ -138,6 +253,8 @@ impl Args {
             flatten_components: Flags::default().contains(Flags::FLATTEN_COMPONENTS),
             decompose_transformed_components: Flags::default()
                 .contains(Flags::DECOMPOSE_TRANSFORMED_COMPONENTS),
+            propagate_anchors: Flags::default().contains(Flags::PROPAGATE_ANCHORS),
+            detect_overlaps: Flags::default().contains(Flags::DETECT_OVERLAPS),
             skip_features: false,
             keep_direction: false,
             no_production_names: false,
@@ -157,6 +274,31 @@ impl Args {
         result
     }
 
+    /// The web font flavor to write, if any.
+    ///
+    /// Set explicitly with --flavor, otherwise inferred from the output file extension.
+    pub fn flavor(&self) -> Option<Flavor> {
+        self.flavor
+            .or_else(|| self.output_file.as_deref().and_then(Flavor::from_extension))
+    }
+
+    /// Where the web font is written, if we're writing one.
+    ///
+    /// The output file if one was given, otherwise build/font.woff or build/font.woff2.
+    pub fn web_font_file(&self) -> Option<PathBuf> {
+        let flavor = self.flavor()?;
+        Some(
+            self.output_file
+                .clone()
+                .unwrap_or_else(|| self.build_dir.join(format!("font.{}", flavor.extension()))),
+        )
+    }
+
+    /// The directory static instances are written to.
+    pub fn instance_dir(&self) -> PathBuf {
+        self.build_dir.join("instances")
+    }
+
     /// The input source to compile.
     pub fn source(&self) -> &Path {
         // safe to unwrap because clap ensures that the input_source is
@@ -167,6 +309,35 @@ impl Args {
     }
 }
 
+/// Parse TAG=MIN:MAX or TAG=VALUE into an [AxisLimit].
+///
+/// We use a string as the error type because this is mainly
+/// designed to be used with clap.
+pub fn parse_axis_limit(s: &str) -> Result<AxisLimit, String> {
+    let (tag, range) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected TAG=MIN:MAX or TAG=VALUE, got '{s}'"))?;
+    let tag = Tag::from_str(tag.trim()).map_err(|e| format!("invalid tag '{tag}': {e}"))?;
+    let coord = |value: &str| {
+        value
+            .trim()
+            .parse::<f64>()
+            .map(UserCoord::new)
+            .map_err(|e| format!("invalid value '{value}' for '{tag}': {e}"))
+    };
+    let (min, max) = match range.split_once(':') {
+        Some((min, max)) => (coord(min)?, coord(max)?),
+        None => {
+            let value = coord(range)?;
+            (value, value)
+        }
+    };
+    if min > max {
+        return Err(format!("'{tag}' has minimum {min:?} > maximum {max:?}"));
+    }
+    Ok(AxisLimit { tag, min, max })
+}
+
 impl ValidatedRegex {
     /// Create a new regex from a raw string.
     ///
@@ -213,7 +384,11 @@ mod tests {
     use clap::Parser;
     use fontir::orchestration::Flags;
 
-    use crate::Args;
+    use fontdrasil::coords::UserCoord;
+    use fontir::instancer::AxisLimit;
+    use write_fonts::types::Tag;
+
+    use crate::{args::parse_axis_limit, Args};
 
     // It's awkward to get the Flags::default values into #[arg] so test for consistency
     #[test]
@@ -228,4 +403,39 @@ mod tests {
             arg_default.bits(),
         );
     }
+
+    #[test]
+    fn parse_axis_limits() {
+        let args = Args::parse_from(vec![
+            "program",
+            "--source",
+            "dont.care",
+            "--axis-limit",
+            "wght=400:600",
+            "--axis-limit",
+            "wdth=87.5",
+        ]);
+        assert_eq!(
+            vec![
+                AxisLimit {
+                    tag: Tag::new(b"wght"),
+                    min: UserCoord::new(400.0),
+                    max: UserCoord::new(600.0),
+                },
+                AxisLimit {
+                    tag: Tag::new(b"wdth"),
+                    min: UserCoord::new(87.5),
+                    max: UserCoord::new(87.5),
+                },
+            ],
+            args.axis_limits
+        );
+    }
+
+    #[test]
+    fn reject_bad_axis_limits() {
+        for bad in ["wght", "wght=bold", "wght=700:400", "toolong=1"] {
+            assert!(parse_axis_limit(bad).is_err(), "{bad}");
+        }
+    }
 }
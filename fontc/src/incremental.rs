@@ -0,0 +1,151 @@
+//! Reuse of the output of the prior compilation into the build directory
+//!
+//! See [fontir::incremental] for how we decide what can be reused.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::Arc,
+};
+
+use fontbe::{glyphs::create_restore_glyf_work, orchestration::BeWork};
+use fontdrasil::types::GlyphName;
+use fontir::{
+    incremental::{BuildState, Fingerprints, Reuse, ReusePlan, ReuseSummary},
+    ir::Glyph,
+    orchestration::{Context as FeContext, IrWork, WorkId as FeWorkIdentifier},
+    paths::Paths as IrPaths,
+    source::Source,
+};
+
+use crate::{Args, Error};
+
+/// Identifies the compiler that produced the IR in a build directory
+const COMPILER: &str = concat!(
+    env!("CARGO_PKG_VERSION"),
+    " ",
+    env!("VERGEN_GIT_SHA"),
+    " rustc ",
+    env!("VERGEN_RUSTC_SEMVER")
+);
+
+pub(crate) struct Incremental {
+    paths: IrPaths,
+    flags: u32,
+    fingerprints: Fingerprints,
+    plan: ReusePlan,
+    reuse: Arc<Reuse>,
+    /// The IR of each glyph as produced from source, whether computed or restored
+    source_glyphs: HashMap<GlyphName, Arc<Glyph>>,
+    kerning_instances: usize,
+    glyph_binaries: usize,
+}
+
+impl Incremental {
+    /// Plan reuse of the prior compilation, None if we can't compile incrementally.
+    ///
+    /// IR must be emitted for there to be anything to reuse next time.
+    pub(crate) fn new(args: &Args, source: &dyn Source) -> Result<Option<Incremental>, Error> {
+        if !args.emit_ir {
+            return Ok(None);
+        }
+        let Some(fingerprints) = source.fingerprint()? else {
+            return Ok(None);
+        };
+        let paths = IrPaths::new(&args.build_dir);
+        let plan = ReusePlan::load(&paths, COMPILER, args.flags(), &fingerprints)?;
+        Ok(Some(Incremental {
+            paths,
+            flags: args.flags().bits(),
+            fingerprints,
+            plan,
+            reuse: Default::default(),
+            source_glyphs: Default::default(),
+            kerning_instances: 0,
+            glyph_binaries: 0,
+        }))
+    }
+
+    pub(crate) fn glyph_ir_work(&self, work: Box<IrWork>) -> Box<IrWork> {
+        match work.id() {
+            FeWorkIdentifier::Glyph(glyph_name) if self.plan.reuses_glyph(&glyph_name) => {
+                self.plan.restore_glyph_work(work, self.reuse.clone())
+            }
+            _ => work,
+        }
+    }
+
+    pub(crate) fn kerning_instance_work(&self, work: Box<IrWork>) -> Box<IrWork> {
+        if !self.plan.reuses_kerning() {
+            return work;
+        }
+        self.plan
+            .restore_kerning_instance_work(work, self.reuse.clone())
+    }
+
+    /// None if there is no chance of reusing the binary glyph
+    pub(crate) fn glyf_work(&self, glyph_name: &GlyphName) -> Option<Box<BeWork>> {
+        self.plan.reuses_glyph(glyph_name).then(|| {
+            create_restore_glyf_work(
+                glyph_name.clone(),
+                self.plan.glyph_order(),
+                self.reuse.clone(),
+            )
+        })
+    }
+
+    /// Note the completion of IR for a glyph from source
+    pub(crate) fn glyph_completed(&mut self, fe_root: &FeContext, glyph_name: GlyphName) {
+        let glyph = fe_root
+            .glyphs
+            .get(&FeWorkIdentifier::Glyph(glyph_name.clone()));
+        self.source_glyphs.insert(glyph_name, glyph);
+    }
+
+    pub(crate) fn kerning_instance_completed(&mut self) {
+        self.kerning_instances += 1;
+    }
+
+    pub(crate) fn glyph_binary_completed(&mut self) {
+        self.glyph_binaries += 1;
+    }
+
+    pub(crate) fn summary(&self) -> ReuseSummary {
+        self.reuse.summary(
+            self.source_glyphs.len(),
+            self.kerning_instances,
+            self.glyph_binaries,
+        )
+    }
+
+    /// Write down what the next compilation needs to know about this one.
+    ///
+    /// Only call once compilation has succeeded.
+    pub(crate) fn save(&self, fe_root: &FeContext) -> Result<(), Error> {
+        let mut state = BuildState {
+            compiler: COMPILER.to_string(),
+            flags: self.flags,
+            fingerprints: self.fingerprints.clone(),
+            ..Default::default()
+        };
+        for (glyph_name, glyph) in self.source_glyphs.iter() {
+            let components: BTreeSet<_> = glyph
+                .sources()
+                .values()
+                .flat_map(|inst| inst.components.iter().map(|c| c.base.clone()))
+                .collect();
+            if !components.is_empty() {
+                state.components.insert(glyph_name.clone(), components);
+            }
+
+            // Anything finalizing glyph order changed was written down again, replacing source IR
+            let current = fe_root
+                .glyphs
+                .get(&FeWorkIdentifier::Glyph(glyph_name.clone()));
+            if !Arc::ptr_eq(glyph, &current) {
+                state.rewritten.insert(glyph_name.clone());
+            }
+        }
+        state.save(&self.paths)?;
+        Ok(())
+    }
+}
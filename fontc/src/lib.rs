@@ -2,11 +2,12 @@
 
 mod args;
 mod error;
+mod incremental;
 mod timing;
 pub mod work;
 mod workload;
 
-pub use args::Args;
+pub use args::{Args, Flavor, OutputFormat};
 pub use error::Error;
 
 use fontra2fontir::source::FontraIrSource;
@@ -21,18 +22,23 @@ use std::{
     fs::{self, OpenOptions},
     io::BufWriter,
     path::Path,
+    sync::Arc,
+    time::Instant,
 };
 
 use fontir::{
+    incremental::ReuseSummary,
+    instancer::{InstanceSource, VariableIr},
     orchestration::{Context as FeContext, Flags},
     source::Source,
 };
 
 use fontbe::orchestration::Context as BeContext;
 use fontbe::paths::Paths as BePaths;
+use fontbe::woff::WebFontFormat;
 use fontir::paths::Paths as IrPaths;
 
-use log::debug;
+use log::{debug, warn};
 
 /// Creates the implementation of [`Source`] that should be used for the provided path
 fn create_source(source: &Path) -> Result<Box<dyn Source>, Error> {
@@ -54,18 +60,74 @@ fn create_source(source: &Path) -> Result<Box<dyn Source>, Error> {
 }
 
 /// Run the compiler with the provided arguments
-pub fn run(args: Args, mut timer: JobTimer) -> Result<(), Error> {
+///
+/// Returns what each font compiled incrementally reused from the prior compilation.
+pub fn run(args: Args, timer: JobTimer) -> Result<Vec<ReuseSummary>, Error> {
+    let variable_fonts = variable_font_sources(args.source())?;
+    if variable_fonts.is_empty() {
+        return Ok(compile(args, None, timer)?.into_iter().collect());
+    }
+
+    // Each variable font is a compilation of its own, written beside where the font would go
+    let output_dir = args
+        .output_file
+        .as_ref()
+        .and_then(|file| file.parent())
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| args.build_dir.clone());
+    let flavor = args.flavor();
+    let extension = output_extension(&args);
+    let mut timer = Some(timer);
+    let mut reuse_summaries = Vec::new();
+    for (file_stem, source) in variable_fonts {
+        debug!("Compiling variable font {file_stem}");
+        let mut vf_args = args.clone();
+        vf_args.flavor = flavor;
+        vf_args.build_dir = args.build_dir.join(&file_stem);
+        vf_args.output_file = Some(output_dir.join(format!("{file_stem}.{extension}")));
+        let timer = timer
+            .take()
+            .unwrap_or_else(|| JobTimer::new(Instant::now()));
+        reuse_summaries.extend(compile(vf_args, Some(Box::new(source)), timer)?);
+    }
+    Ok(reuse_summaries)
+}
+
+/// The variable fonts a designspace describes, if it describes more than the one font.
+fn variable_font_sources(source: &Path) -> Result<Vec<(String, DesignSpaceIrSource)>, Error> {
+    if source.extension().and_then(OsStr::to_str) != Some("designspace") || !source.exists() {
+        return Ok(Vec::new());
+    }
+    Ok(DesignSpaceIrSource::variable_fonts(source)?)
+}
+
+/// Compile one font, from the source in args unless one is provided.
+///
+/// Returns what was reused from the prior compilation, if compilation is incremental.
+fn compile(
+    args: Args,
+    source: Option<Box<dyn Source>>,
+    mut timer: JobTimer,
+) -> Result<Option<ReuseSummary>, Error> {
     let time = create_timer(AnyWorkId::InternalTiming("Init config"), 0)
         .queued()
         .run();
     let (ir_paths, be_paths) = init_paths(&args)?;
     timer.add(time.complete());
 
-    let workload = Workload::new(args.clone(), timer)?;
+    // The default or partial instance is compiled from the IR of the font, the font itself
+    // isn't needed
+    let ir_only = args.output_format == OutputFormat::OtfDefault || !args.axis_limits.is_empty();
+    let workload = match source {
+        Some(source) if ir_only => Workload::ir_only(args.clone(), source, timer)?,
+        Some(source) => Workload::with_source(args.clone(), source, timer)?,
+        None if ir_only => Workload::ir_only(args.clone(), create_source(args.source())?, timer)?,
+        None => Workload::new(args.clone(), timer)?,
+    };
 
     let fe_root = FeContext::new_root(args.flags(), ir_paths);
     let be_root = BeContext::new_root(args.flags(), be_paths, &fe_root);
-    let mut timing = workload.exec(&fe_root, &be_root)?;
+    let (mut timing, reuse_summary) = workload.exec(&fe_root, &be_root)?;
 
     if args.flags().contains(Flags::EMIT_TIMING) {
         let path = args.build_dir.join("threads.svg");
@@ -85,7 +147,116 @@ pub fn run(args: Args, mut timer: JobTimer) -> Result<(), Error> {
     }
 
     // At long last!
-    write_font_file(&args, &be_root)
+    let fe_root = if args.output_format == OutputFormat::OtfDefault {
+        write_default_instance(&args, &fe_root)?;
+        fe_root
+    } else if args.axis_limits.is_empty() {
+        write_font_file(&args, &be_root)?;
+        fe_root
+    } else {
+        write_partial_instance(&args, &fe_root)?
+    };
+
+    if args.static_instances {
+        write_static_instances(&args, &fe_root)?;
+    }
+    Ok(reuse_summary)
+}
+
+/// The extension of the font files we write
+fn output_extension(args: &Args) -> &'static str {
+    match (args.flavor(), args.output_format) {
+        (Some(flavor), _) => flavor.extension(),
+        (None, OutputFormat::Ttf) => "ttf",
+        (None, OutputFormat::Otf | OutputFormat::OtfDefault) => "otf",
+    }
+}
+
+/// Compile a static font for each named instance of the variable font in fe_root.
+///
+/// Each instance is a complete compilation of IR interpolated from the variable font.
+fn write_static_instances(args: &Args, fe_root: &FeContext) -> Result<(), Error> {
+    let variable = Arc::new(VariableIr::new(fe_root));
+    let named_instances = &variable.static_metadata().named_instances;
+    if named_instances.is_empty() {
+        warn!("Static instances requested but the font has no named instances");
+        return Ok(());
+    }
+    let instance_dir = args.instance_dir();
+    require_dir(&instance_dir)?;
+
+    let flavor = args.flavor();
+    let extension = output_extension(args);
+    for named_instance in named_instances.iter() {
+        let source = InstanceSource::new(variable.clone(), named_instance)?;
+        let file_stem = source
+            .postscript_name()
+            .unwrap_or(&named_instance.name)
+            .to_string();
+        debug!("Compiling instance {file_stem}");
+
+        let mut instance_args = args.clone();
+        instance_args.static_instances = false;
+        instance_args.flavor = flavor;
+        instance_args.build_dir = instance_dir.join(&file_stem);
+        instance_args.output_file = Some(instance_dir.join(format!("{file_stem}.{extension}")));
+        compile_instance(&instance_args, source)?;
+    }
+    Ok(())
+}
+
+/// Compile the default master of the font in fe_root as a static font.
+///
+/// The static font is written where the variable font would have been.
+fn write_default_instance(args: &Args, fe_root: &FeContext) -> Result<(), Error> {
+    let variable = Arc::new(VariableIr::new(fe_root));
+    let source = InstanceSource::default_instance(variable, &args.axis_limits)?;
+    debug!("Compiling the default instance");
+
+    let mut default_args = args.clone();
+    default_args.axis_limits.clear();
+    default_args.static_instances = false;
+    default_args.flavor = args.flavor();
+    default_args.build_dir = args.build_dir.join("default");
+    default_args.output_file = Some(args.output_file.clone().unwrap_or_else(|| {
+        args.build_dir
+            .join(format!("font.{}", output_extension(args)))
+    }));
+    compile_instance(&default_args, source)?;
+    Ok(())
+}
+
+/// Compile the variable font in fe_root restricted to the axis limits in args.
+///
+/// The restricted font is written where the variable font would have been. Returns its
+/// IR so static instances can be made from it.
+fn write_partial_instance(args: &Args, fe_root: &FeContext) -> Result<FeContext, Error> {
+    let variable = Arc::new(VariableIr::new(fe_root));
+    let source = InstanceSource::partial(variable, &args.axis_limits)?;
+    debug!("Compiling partial instance for {:?}", args.axis_limits);
+
+    let mut partial_args = args.clone();
+    partial_args.axis_limits.clear();
+    partial_args.static_instances = false;
+    partial_args.flavor = args.flavor();
+    partial_args.build_dir = args.build_dir.join("partial");
+    partial_args.output_file = Some(args.output_file.clone().unwrap_or_else(|| {
+        args.build_dir
+            .join(format!("font.{}", output_extension(args)))
+    }));
+    compile_instance(&partial_args, source)
+}
+
+/// Compile the font an [InstanceSource] produces, returning its IR.
+fn compile_instance(args: &Args, source: InstanceSource) -> Result<FeContext, Error> {
+    let (ir_paths, be_paths) = init_paths(args)?;
+    let timer = JobTimer::new(Instant::now());
+    let workload = Workload::with_source(args.clone(), Box::new(source), timer)?;
+    let fe_root = FeContext::new_root(args.flags(), ir_paths);
+    let be_root = BeContext::new_root(args.flags(), be_paths, &fe_root);
+    workload.exec(&fe_root, &be_root)?;
+    write_font_file(args, &be_root)?;
+    Ok(fe_root)
 }
 
 pub fn require_dir(dir: &Path) -> Result<(), Error> {
@@ -108,8 +279,11 @@ pub fn require_dir(dir: &Path) -> Result<(), Error> {
 
 pub fn init_paths(args: &Args) -> Result<(IrPaths, BePaths), Error> {
     let ir_paths = IrPaths::new(&args.build_dir);
-    let be_paths = if let Some(output_file) = &args.output_file {
+    // a web font is encoded from the sfnt, which stays in the build dir
+    let be_paths = if let (Some(output_file), None) = (&args.output_file, args.flavor()) {
         BePaths::with_output_file(&args.build_dir, output_file)
+    } else if args.output_format.is_cff() {
+        BePaths::with_output_file(&args.build_dir, &args.build_dir.join("font.otf"))
     } else {
         BePaths::new(&args.build_dir)
     };
@@ -121,7 +295,7 @@ pub fn init_paths(args: &Args) -> Result<(IrPaths, BePaths), Error> {
     }
 
     // the build dir stores the IR (for incremental builds) and the default output
-    // file ('font.ttf') so we don't need to create one unless we're writing to it
+    // file ('font.ttf' or 'font.otf') so we don't need to create one unless we're writing to it
     if args.output_file.is_none() || args.emit_ir {
         require_dir(&args.build_dir)?;
     }
@@ -144,6 +318,14 @@ pub fn init_paths(args: &Args) -> Result<(IrPaths, BePaths), Error> {
 }
 
 pub fn write_font_file(args: &Args, be_context: &BeContext) -> Result<(), Error> {
+    if let (Some(flavor), Some(web_font_file)) = (args.flavor(), args.web_font_file()) {
+        let web_font = WebFontFormat::from(flavor).encode(be_context.font.get().get())?;
+        return fs::write(&web_font_file, web_font).map_err(|source| Error::FileIo {
+            path: web_font_file,
+            source,
+        });
+    }
+
     // if IR is off the font didn't get written yet (nothing did), otherwise it's done already
     let font_file = be_context.font_file();
     if !args.emit_ir {
@@ -194,6 +376,7 @@ mod tests {
         types::{GlyphName, WidthClass},
     };
     use fontir::{
+        incremental::ReuseSummary,
         ir::{self, GlobalMetric, GlyphOrder, KernGroup, KernPair, KernSide},
         orchestration::{Context as FeContext, Persistable, WorkId as FeWorkIdentifier},
     };
@@ -201,22 +384,33 @@ mod tests {
     use log::info;
     use pretty_assertions::assert_eq;
 
-    use skrifa::{charmap::Charmap, instance::Size, outline::DrawSettings, MetadataProvider};
+    use skrifa::{
+        charmap::{Charmap, MapVariant},
+        instance::Size,
+        outline::DrawSettings,
+        MetadataProvider,
+    };
     use tempfile::{tempdir, TempDir};
     use write_fonts::{
         dump_table,
+        from_obj::ToOwnedTable,
         read::{
             tables::{
                 cmap::{Cmap, CmapSubtable},
+                colr::Paint,
                 cpal::ColorRecord,
                 gasp::GaspRangeBehavior,
                 glyf::{self, CompositeGlyph, CurvePoint, Glyf},
                 gpos::{AnchorTable, Gpos, MarkBasePosFormat1Marker, PositionLookup},
                 hmtx::Hmtx,
-                layout::FeatureParams,
+                layout::{CoverageTable, FeatureParams},
                 loca::Loca,
                 name::Name,
                 os2::SelectionFlags,
+                postscript::{
+                    dict::{self, Entry},
+                    Index2,
+                },
                 variations::{DeltaSetIndexMap, ItemVariationData},
             },
             FontData, FontRead, FontReadWithArgs, FontRef, TableProvider, TableRef,
@@ -227,7 +421,10 @@ mod tests {
             loca::LocaFormat,
             meta::{DataMapRecord, Metadata, ScriptLangTag},
         },
-        types::{F2Dot14, GlyphId, GlyphId16, NameId, Tag},
+        types::{
+            F2Dot14, Fixed, GlyphId, GlyphId16, MajorMinor, NameId, Tag, Version16Dot16,
+            CFF_SFNT_VERSION,
+        },
     };
 
     use super::*;
@@ -280,7 +477,7 @@ mod tests {
 
             write_font_file(&args, &result.be_context).unwrap();
 
-            result.raw_font = fs::read(result.build_dir.join("font.ttf")).unwrap();
+            result.raw_font = fs::read(result.be_context.font_file()).unwrap();
 
             result
         }
@@ -351,7 +548,9 @@ mod tests {
             FeWorkIdentifier::PaintGraph.into(),
             FeWorkIdentifier::PreliminaryGlyphOrder.into(),
             FeWorkIdentifier::GlyphOrder.into(),
+            FeWorkIdentifier::VariableComposites.into(),
             FeWorkIdentifier::Features.into(),
+            FeWorkIdentifier::FeatureVariations.into(),
             FeWorkIdentifier::KerningGroups.into(),
             FeWorkIdentifier::KernInstance(NormalizedLocation::for_pos(&[("wght", 0.0)])).into(),
             FeWorkIdentifier::KernInstance(NormalizedLocation::for_pos(&[("wght", 1.0)])).into(),
@@ -387,6 +586,11 @@ mod tests {
             BeWorkIdentifier::Os2.into(),
             BeWorkIdentifier::Post.into(),
             BeWorkIdentifier::Stat.into(),
+            BeWorkIdentifier::Varc.into(),
+            BeWorkIdentifier::Vhea.into(),
+            BeWorkIdentifier::Vmtx.into(),
+            BeWorkIdentifier::Vorg.into(),
+            BeWorkIdentifier::Vvar.into(),
         ];
 
         expected.extend(
@@ -848,6 +1052,215 @@ mod tests {
         };
     }
 
+    #[test]
+    fn applies_glyph_filters() {
+        let result = TestCompile::compile_source("Filters.ufo");
+        let glyph = |name: &str| result.fe_context.get_glyph(name);
+
+        // the pre filter decomposed C before the post filter transformed A
+        let c = glyph("C");
+        assert!(c.default_instance().components.is_empty());
+        let bbox = c.default_instance().contours[0].control_box();
+        assert_eq!((0.0, 700.0), (bbox.y0, bbox.y1));
+
+        // A is squashed towards the x-height
+        let bbox = glyph("A").default_instance().contours[0].control_box();
+        assert_eq!((250.0, 600.0), (bbox.y0, bbox.y1));
+
+        // B is excluded from sorting so keeps its original contour order
+        assert_eq!(
+            vec![300.0, 100.0],
+            glyph("B")
+                .default_instance()
+                .contours
+                .iter()
+                .map(|contour| contour.control_box().x0)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn propagates_anchors_on_request() {
+        fn anchors(result: &TestCompile, glyph_name: &str) -> Vec<(String, Point)> {
+            let mut anchors: Vec<_> = result
+                .fe_context
+                .anchors
+                .get(&FeWorkIdentifier::Anchor(glyph_name.into()))
+                .anchors
+                .iter()
+                .map(|anchor| (anchor.kind.name().to_string(), anchor.default_pos()))
+                .collect();
+            anchors.sort_by(|a, b| a.0.cmp(&b.0));
+            anchors
+        }
+
+        let result = TestCompile::compile_source("PropagateAnchors.ufo");
+        assert!(anchors(&result, "aacute").is_empty());
+
+        let result = TestCompile::compile("PropagateAnchors.ufo", |mut args| {
+            args.propagate_anchors = true;
+            args
+        });
+        assert_eq!(
+            vec![("top".to_string(), Point::new(250.0, 700.0))],
+            anchors(&result, "aacute")
+        );
+        assert_eq!(
+            vec![
+                ("top_1".to_string(), Point::new(250.0, 500.0)),
+                ("top_2".to_string(), Point::new(750.0, 500.0))
+            ],
+            anchors(&result, "a_a")
+        );
+    }
+
+    fn overlap_flags(result: &TestCompile) -> Vec<(&'static str, bool)> {
+        let glyphs = result.glyphs();
+        let glyphs = glyphs.read();
+        ["bar", "cross", "bars", "barbar"]
+            .into_iter()
+            .map(|name| {
+                let gid = result.get_glyph_index(name).unwrap() as usize;
+                let overlaps = match &glyphs[gid] {
+                    Some(glyf::Glyph::Simple(glyph)) => glyph.has_overlapping_contours(),
+                    Some(glyf::Glyph::Composite(glyph)) => glyph
+                        .components()
+                        .next()
+                        .unwrap()
+                        .flags
+                        .contains(glyf::CompositeGlyphFlags::OVERLAP_COMPOUND),
+                    None => panic!("{name} is empty"),
+                };
+                (name, overlaps)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn variable_glyphs_flag_overlaps() {
+        let result = TestCompile::compile_source("Overlap.designspace");
+        assert_eq!(
+            vec![
+                ("bar", true),
+                ("cross", true),
+                ("bars", true),
+                ("barbar", true)
+            ],
+            overlap_flags(&result)
+        );
+    }
+
+    #[test]
+    fn variable_glyphs_flag_detected_overlaps() {
+        let result = TestCompile::compile("Overlap.designspace", |mut args| {
+            args.detect_overlaps = true;
+            args
+        });
+        assert_eq!(
+            vec![
+                ("bar", false),
+                ("cross", true),
+                ("bars", false),
+                ("barbar", true)
+            ],
+            overlap_flags(&result)
+        );
+    }
+
+    #[test]
+    fn static_glyphs_do_not_flag_overlaps() {
+        let result = TestCompile::compile_source("Overlap-Regular.ufo");
+        assert_eq!(
+            vec![
+                ("bar", false),
+                ("cross", false),
+                ("bars", false),
+                ("barbar", false)
+            ],
+            overlap_flags(&result)
+        );
+    }
+
+    #[test]
+    fn propagates_glyphs_anchors() {
+        let result = TestCompile::compile_source("glyphs3/PropagateAnchorsTest.glyphs");
+        let expected = glyphs_reader::Font::load(
+            &testdata_dir().join("glyphs3/PropagateAnchorsTest-propagated.glyphs"),
+        )
+        .unwrap();
+        let default_master_id = &expected.default_master().id;
+
+        for glyph in expected.glyphs.values() {
+            let ir_anchors = result
+                .fe_context
+                .anchors
+                .get(&FeWorkIdentifier::Anchor(glyph.name.as_str().into()));
+            for layer in glyph.layers.iter() {
+                let is_default = &layer.layer_id == default_master_id;
+                let mut expected_anchors: Vec<_> = layer
+                    .anchors
+                    .iter()
+                    .map(|anchor| (anchor.name.to_string(), anchor.pos))
+                    .collect();
+                expected_anchors.sort_by(|a, b| a.0.cmp(&b.0));
+                let mut anchors: Vec<_> = ir_anchors
+                    .anchors
+                    .iter()
+                    .map(|anchor| {
+                        let (_, pos) = anchor
+                            .positions
+                            .iter()
+                            .find(|(loc, _)| loc.is_default() == is_default)
+                            .unwrap();
+                        (anchor.kind.name().to_string(), *pos)
+                    })
+                    .collect();
+                anchors.sort_by(|a, b| a.0.cmp(&b.0));
+                assert_eq!(expected_anchors, anchors, "{}", glyph.name);
+            }
+        }
+    }
+
+    #[test]
+    fn dont_propagate_glyphs_anchors() {
+        let result = TestCompile::compile_source("glyphs2/DontPropagateAnchors.glyphs");
+        assert!(result
+            .fe_context
+            .anchors
+            .get(&FeWorkIdentifier::Anchor("Aacute".into()))
+            .anchors
+            .is_empty());
+    }
+
+    #[test]
+    fn writes_cmap_variation_sequences() {
+        let result = TestCompile::compile_source("UnicodeVariationSequences.ufo");
+        let font = result.font();
+        let glyph_order = result.fe_context.glyph_order.get();
+        let gid = |name: &str| GlyphId::from(glyph_order.glyph_id(&GlyphName::new(name)).unwrap());
+
+        let charmap = font.charmap();
+        assert_eq!(
+            vec![
+                Some(MapVariant::UseDefault),
+                Some(MapVariant::Variant(gid("heart.emoji"))),
+                Some(MapVariant::UseDefault),
+                Some(MapVariant::Variant(gid("uni82A6.jp"))),
+                None,
+            ],
+            [
+                (0x2764u32, 0xFE0Eu32),
+                (0x2764, 0xFE0F),
+                (0x82A6, 0xE0100),
+                (0x82A6, 0xE0101),
+                (0x0020, 0xFE0F),
+            ]
+            .into_iter()
+            .map(|(base, selector)| charmap.map_variant(base, selector))
+            .collect::<Vec<_>>()
+        );
+    }
+
     #[test]
     fn writes_cmap() {
         let result = TestCompile::compile_source("glyphs2/Component.glyphs");
@@ -986,6 +1399,542 @@ mod tests {
         );
     }
 
+    #[test]
+    fn compile_static_otf() {
+        let ttf = TestCompile::compile_source("static.designspace");
+        let otf = TestCompile::compile("static.designspace", |mut args| {
+            args.output_format = OutputFormat::Otf;
+            args
+        });
+        let font = otf.font();
+
+        assert_eq!(CFF_SFNT_VERSION, font.table_directory.sfnt_version());
+        assert!(font.table_data(Tag::new(b"CFF ")).is_some());
+        assert!(font.table_data(Tag::new(b"CFF2")).is_none());
+        assert!(font.table_data(Tag::new(b"glyf")).is_none());
+        assert!(font.table_data(Tag::new(b"loca")).is_none());
+        assert_eq!(Version16Dot16::VERSION_0_5, font.maxp().unwrap().version());
+        assert_eq!(
+            ttf.font().maxp().unwrap().num_glyphs(),
+            font.maxp().unwrap().num_glyphs()
+        );
+
+        // Outlines should draw the same as the TrueType ones
+        for ch in ['|', '+'] {
+            assert_eq!(
+                cbox_of_char(ch as u32, &ttf.font(), Vec::new()),
+                cbox_of_char(ch as u32, &font, Vec::new()),
+                "{ch}"
+            );
+        }
+        let bounds = |font: &FontRef| {
+            let head = font.head().unwrap();
+            (head.x_min(), head.y_min(), head.x_max(), head.y_max())
+        };
+        assert_eq!(bounds(&ttf.font()), bounds(&font));
+    }
+
+    #[test]
+    fn compile_variable_otf() {
+        let ttf = TestCompile::compile_source("wght_var.designspace");
+        let otf = TestCompile::compile("wght_var.designspace", |mut args| {
+            args.output_format = OutputFormat::Otf;
+            args
+        });
+        let font = otf.font();
+
+        assert_eq!(CFF_SFNT_VERSION, font.table_directory.sfnt_version());
+        assert!(font.table_data(Tag::new(b"CFF2")).is_some());
+        assert!(font.table_data(Tag::new(b"CFF ")).is_none());
+        assert!(font.table_data(Tag::new(b"glyf")).is_none());
+        assert!(font.table_data(Tag::new(b"gvar")).is_none());
+
+        // Outlines should draw, and vary, the same as the TrueType ones
+        for ch in ['|', '+'] {
+            for coords in [vec![0.0], vec![1.0]] {
+                assert_eq!(
+                    cbox_of_char(ch as u32, &ttf.font(), coords.clone()),
+                    cbox_of_char(ch as u32, &font, coords.clone()),
+                    "{ch} at {coords:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn compile_otf_with_vertical_metrics() {
+        let temp_dir = tempdir().unwrap();
+        let source = editable_wght_var(temp_dir.path());
+        for ufo in ["WghtVar-Regular.ufo", "WghtVar-Bold.ufo"] {
+            let fontinfo = source.parent().unwrap().join(ufo).join("fontinfo.plist");
+            let plist = fs::read_to_string(&fontinfo).unwrap();
+            fs::write(
+                &fontinfo,
+                plist.replace(
+                    "<key>unitsPerEm</key>",
+                    "<key>openTypeVheaVertTypoAscender</key><integer>500</integer>\
+                     <key>openTypeVheaVertTypoDescender</key><integer>-500</integer>\
+                     <key>openTypeVheaVertTypoLineGap</key><integer>0</integer>\
+                     <key>unitsPerEm</key>",
+                ),
+            )
+            .unwrap();
+        }
+        let result = TestCompile::compile(source.to_str().unwrap(), |mut args| {
+            args.output_format = OutputFormat::Otf;
+            args
+        });
+        let font = result.font();
+
+        // No glyph sets its own vertical origin so they all use the typo ascender
+        let vorg = font.vorg().unwrap();
+        assert_eq!(
+            font.os2().unwrap().s_typo_ascender(),
+            vorg.default_vert_origin_y()
+        );
+        assert!(vorg.vert_origin_y_metrics().is_empty());
+        assert_eq!(
+            font.maxp().unwrap().num_glyphs(),
+            font.vmtx().unwrap().v_metrics().len() as u16
+                + font.vmtx().unwrap().top_side_bearings().len() as u16
+        );
+    }
+
+    #[test]
+    fn compile_variable_fonts_of_designspace() {
+        let temp_dir = tempdir().unwrap();
+        let args = Args::for_test(temp_dir.path(), "wght_ital_vfs.designspace");
+        run(args, JobTimer::new(Instant::now())).unwrap();
+
+        // A font per <variable-font>, nothing for the designspace as a whole
+        assert!(!temp_dir.path().join("font.ttf").exists());
+        for (file, wght_max, num_feature_variations) in [
+            ("WghtItal-Roman.ttf", 700.0, 0),
+            ("WghtItal-Italic.ttf", 700.0, 1),
+            ("WghtItal-RomanLight.ttf", 550.0, 0),
+        ] {
+            let raw_font = fs::read(temp_dir.path().join(file)).unwrap();
+            let font = FontRef::new(&raw_font).unwrap();
+            // ital is pinned so only wght varies
+            assert_eq!(
+                vec![(Tag::new(b"wght"), 400.0, 400.0, wght_max)],
+                axes(&font),
+                "{file}"
+            );
+            // The rule to substitute only applies to italics
+            let feature_variations = font
+                .gsub()
+                .ok()
+                .and_then(|gsub| gsub.feature_variations())
+                .map(|fv| fv.unwrap().feature_variation_records().len())
+                .unwrap_or_default();
+            assert_eq!(num_feature_variations, feature_variations, "{file}");
+        }
+    }
+
+    #[test]
+    fn compile_static_instances() {
+        let temp_dir = tempdir().unwrap();
+        let mut args = Args::for_test(temp_dir.path(), "wght_var_instances.designspace");
+        args.static_instances = true;
+        run(args.clone(), JobTimer::new(Instant::now())).unwrap();
+
+        let var_font = fs::read(temp_dir.path().join("font.ttf")).unwrap();
+        let var_font = FontRef::new(&var_font).unwrap();
+
+        for (file, wght, weight_class, names, fs_selection) in [
+            (
+                "WghtVar-Regular.ttf",
+                0.0,
+                400,
+                ["Wght Var", "Regular", "Wght Var Regular"],
+                SelectionFlags::REGULAR,
+            ),
+            (
+                "WghtVar-Medium.ttf",
+                1.0 / 3.0,
+                500,
+                ["Wght Var Medium", "Regular", "Wght Var Medium"],
+                SelectionFlags::REGULAR,
+            ),
+            (
+                "WghtVar-Bold.ttf",
+                1.0,
+                700,
+                ["Wght Var", "Bold", "Wght Var Bold"],
+                SelectionFlags::BOLD,
+            ),
+        ] {
+            let raw_font = fs::read(args.instance_dir().join(file)).unwrap();
+            let font = FontRef::new(&raw_font).unwrap();
+
+            for tag in [b"fvar", b"gvar", b"HVAR", b"MVAR", b"STAT", b"avar"] {
+                assert!(
+                    font.table_data(Tag::new(tag)).is_none(),
+                    "{file} has {}",
+                    Tag::new(tag)
+                );
+            }
+            let name = font.name().unwrap();
+            assert_eq!(
+                names.map(|n| Some(n.to_string())),
+                [
+                    NameId::FAMILY_NAME,
+                    NameId::SUBFAMILY_NAME,
+                    NameId::FULL_NAME
+                ]
+                .map(|id| resolve_name(&name, id)),
+                "{file}"
+            );
+            let os2 = font.os2().unwrap();
+            assert_eq!(weight_class, os2.us_weight_class(), "{file}");
+            assert_eq!(
+                fs_selection,
+                os2.fs_selection() & (SelectionFlags::REGULAR | SelectionFlags::BOLD),
+                "{file}"
+            );
+
+            // Outlines should match the variable font at the instance location,
+            // allowing for the variable font rounding its deltas
+            for ch in ['|', '+'] {
+                let expected = cbox_of_char(ch as u32, &var_font, vec![wght]);
+                let actual = cbox_of_char(ch as u32, &font, Vec::new());
+                for (e, a) in [
+                    (expected.x0, actual.x0),
+                    (expected.y0, actual.y0),
+                    (expected.x1, actual.x1),
+                    (expected.y1, actual.y1),
+                ] {
+                    assert!((e - a).abs() <= 1.0, "{file} {ch} {expected:?} {actual:?}");
+                }
+            }
+        }
+    }
+
+    /// Compile wght_var_instances.designspace with axis limits, returning the variable font
+    /// and the limited font.
+    fn compile_with_axis_limits(limits: &[&str]) -> (Vec<u8>, Vec<u8>) {
+        let var_font = TestCompile::compile_source("wght_var_instances.designspace").raw_font;
+
+        let temp_dir = tempdir().unwrap();
+        let mut args = Args::for_test(temp_dir.path(), "wght_var_instances.designspace");
+        args.axis_limits = limits
+            .iter()
+            .map(|limit| args::parse_axis_limit(limit).unwrap())
+            .collect();
+        run(args, JobTimer::new(Instant::now())).unwrap();
+        (
+            var_font,
+            fs::read(temp_dir.path().join("font.ttf")).unwrap(),
+        )
+    }
+
+    fn assert_same_cbox(expected: Rect, actual: Rect) {
+        // allow for both fonts rounding their deltas
+        for (e, a) in [
+            (expected.x0, actual.x0),
+            (expected.y0, actual.y0),
+            (expected.x1, actual.x1),
+            (expected.y1, actual.y1),
+        ] {
+            assert!((e - a).abs() <= 1.0, "{expected:?} {actual:?}");
+        }
+    }
+
+    #[test]
+    fn compile_partial_instance() {
+        let (var_font, partial_font) = compile_with_axis_limits(&["wght=400:500"]);
+        let var_font = FontRef::new(&var_font).unwrap();
+        let font = FontRef::new(&partial_font).unwrap();
+
+        assert_eq!(vec![(Tag::new(b"wght"), 400.0, 400.0, 500.0)], axes(&font));
+        // Bold is beyond the limit
+        let name = font.name().unwrap();
+        assert_eq!(
+            vec![Some("Regular".to_string()), Some("Medium".to_string())],
+            font.fvar()
+                .unwrap()
+                .instances()
+                .unwrap()
+                .iter()
+                .map(|instance| resolve_name(&name, instance.unwrap().subfamily_name_id))
+                .collect::<Vec<_>>()
+        );
+
+        // 500 is a third of the way from 400 to 700 in the variable font
+        for ch in ['|', '+'] {
+            for (var_coords, coords) in [(0.0, 0.0), (1.0 / 6.0, 0.5), (1.0 / 3.0, 1.0)] {
+                assert_same_cbox(
+                    cbox_of_char(ch as u32, &var_font, vec![var_coords]),
+                    cbox_of_char(ch as u32, &font, vec![coords]),
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn compile_pinned_instance() {
+        let (var_font, pinned_font) = compile_with_axis_limits(&["wght=550"]);
+        let var_font = FontRef::new(&var_font).unwrap();
+        let font = FontRef::new(&pinned_font).unwrap();
+
+        // The only axis is pinned so the font is static
+        for tag in [b"fvar", b"gvar", b"STAT"] {
+            assert!(
+                font.table_data(Tag::new(tag)).is_none(),
+                "{}",
+                Tag::new(tag)
+            );
+        }
+        assert_eq!(550, font.os2().unwrap().us_weight_class());
+        for ch in ['|', '+'] {
+            assert_same_cbox(
+                cbox_of_char(ch as u32, &var_font, vec![0.5]),
+                cbox_of_char(ch as u32, &font, Vec::new()),
+            );
+        }
+    }
+
+    fn compile_otf_default(limits: &[&str]) -> Vec<u8> {
+        let temp_dir = tempdir().unwrap();
+        let mut args = Args::for_test(temp_dir.path(), "wght_var_instances.designspace");
+        args.output_format = OutputFormat::OtfDefault;
+        args.axis_limits = limits
+            .iter()
+            .map(|limit| args::parse_axis_limit(limit).unwrap())
+            .collect();
+        run(args, JobTimer::new(Instant::now())).unwrap();
+        fs::read(temp_dir.path().join("font.otf")).unwrap()
+    }
+
+    #[test]
+    fn compile_otf_default_of_variable_font() {
+        let var_font = TestCompile::compile_source("wght_var_instances.designspace").raw_font;
+        let var_font = FontRef::new(&var_font).unwrap();
+        let font = compile_otf_default(&[]);
+        let font = FontRef::new(&font).unwrap();
+
+        assert_eq!(CFF_SFNT_VERSION, font.table_directory.sfnt_version());
+        assert!(font.table_data(Tag::new(b"CFF ")).is_some());
+        for tag in [b"CFF2", b"fvar", b"HVAR", b"STAT"] {
+            assert!(
+                font.table_data(Tag::new(tag)).is_none(),
+                "{}",
+                Tag::new(tag)
+            );
+        }
+        assert_eq!(400, font.os2().unwrap().us_weight_class());
+        for ch in ['|', '+'] {
+            assert_same_cbox(
+                cbox_of_char(ch as u32, &var_font, vec![0.0]),
+                cbox_of_char(ch as u32, &font, Vec::new()),
+            );
+        }
+    }
+
+    #[test]
+    fn compile_otf_default_within_axis_limit() {
+        let var_font = TestCompile::compile_source("wght_var_instances.designspace").raw_font;
+        let var_font = FontRef::new(&var_font).unwrap();
+        // The default, 400, is outside the limit so the closest value it allows is used
+        let font = compile_otf_default(&["wght=550:700"]);
+        let font = FontRef::new(&font).unwrap();
+
+        assert!(font.table_data(Tag::new(b"fvar")).is_none());
+        assert_eq!(550, font.os2().unwrap().us_weight_class());
+        for ch in ['|', '+'] {
+            assert_same_cbox(
+                cbox_of_char(ch as u32, &var_font, vec![0.5]),
+                cbox_of_char(ch as u32, &font, Vec::new()),
+            );
+        }
+    }
+
+    #[test]
+    fn compile_woff_keeps_table_checksums() {
+        let temp_dir = tempdir().unwrap();
+        let mut args = Args::for_test(temp_dir.path(), "wght_var.designspace");
+        args.flavor = Some(Flavor::Woff);
+        run(args, JobTimer::new(Instant::now())).unwrap();
+
+        // With IR on the sfnt is still written to the build dir
+        let sfnt = fs::read(temp_dir.path().join("font.ttf")).unwrap();
+        let sfnt = FontRef::new(&sfnt).unwrap();
+        let woff = fs::read(temp_dir.path().join("font.woff")).unwrap();
+
+        let read_u32 = |pos: usize| u32::from_be_bytes(woff[pos..pos + 4].try_into().unwrap());
+        assert_eq!(Tag::new(b"wOFF"), Tag::from_u32(read_u32(0)));
+        assert_eq!(sfnt.table_directory.sfnt_version(), read_u32(4));
+        assert_eq!(woff.len(), read_u32(8) as usize);
+        let records = sfnt.table_directory.table_records();
+        let woff_checksums: Vec<_> = (0..records.len())
+            .map(|i| {
+                let entry = 44 + 20 * i;
+                (Tag::from_u32(read_u32(entry)), read_u32(entry + 16))
+            })
+            .collect();
+        assert_eq!(
+            records
+                .iter()
+                .map(|r| (r.tag(), r.checksum()))
+                .collect::<Vec<_>>(),
+            woff_checksums
+        );
+    }
+
+    #[test]
+    fn compile_woff2_from_output_extension() {
+        let temp_dir = tempdir().unwrap();
+        let mut args = Args::for_test(temp_dir.path(), "wght_var.designspace");
+        args.output_format = OutputFormat::Otf;
+        args.output_file = Some(temp_dir.path().join("web").join("Font.WOFF2"));
+        assert_eq!(Some(Flavor::Woff2), args.flavor());
+        run(args, JobTimer::new(Instant::now())).unwrap();
+
+        let woff2 = fs::read(temp_dir.path().join("web").join("Font.WOFF2")).unwrap();
+        assert_eq!(b"wOF2", &woff2[..4]);
+        assert_eq!(b"OTTO", &woff2[4..8]);
+        // the sfnt the web font is made from stays in the build dir
+        assert!(temp_dir.path().join("font.otf").is_file());
+    }
+
+    fn copy_dir(from: &Path, to: &Path) {
+        fs::create_dir_all(to).unwrap();
+        for entry in fs::read_dir(from).unwrap() {
+            let path = entry.unwrap().path();
+            let dest = to.join(path.file_name().unwrap());
+            if path.is_dir() {
+                copy_dir(&path, &dest);
+            } else {
+                fs::copy(&path, &dest).unwrap();
+            }
+        }
+    }
+
+    /// Compile source into build_dir, reusing whatever a prior compilation there left behind
+    fn compile_incrementally(build_dir: &Path, source: &Path) -> (ReuseSummary, Vec<u8>) {
+        let mut args = Args::new(build_dir, source.to_path_buf());
+        args.emit_ir = true;
+        let (ir_paths, be_paths) = init_paths(&args).unwrap();
+        let fe_context = FeContext::new_root(args.flags(), ir_paths);
+        let be_context = BeContext::new_root(args.flags(), be_paths, &fe_context.read_only());
+
+        let mut workload = Workload::new(args.clone(), JobTimer::new(Instant::now())).unwrap();
+        workload.run_for_test(&fe_context, &be_context);
+        write_font_file(&args, &be_context).unwrap();
+        (
+            workload.reuse_summary().unwrap(),
+            without_build_time(fs::read(be_context.font_file()).unwrap()),
+        )
+    }
+
+    /// Zero the parts of head that depend on when the font was built, so builds
+    /// that straddle a second can be compared
+    fn without_build_time(mut font: Vec<u8>) -> Vec<u8> {
+        let head = FontRef::new(&font)
+            .unwrap()
+            .table_directory
+            .table_records()
+            .iter()
+            .find(|record| record.tag() == Tag::new(b"head"))
+            .unwrap()
+            .offset() as usize;
+        // checksumAdjustment
+        font[head + 8..head + 12].fill(0);
+        // created, which is also the build time when the source doesn't say, and modified
+        font[head + 20..head + 36].fill(0);
+        font
+    }
+
+    /// Copy of designspace_from_glyphs/WghtVar we can safely edit
+    fn editable_wght_var(temp_dir: &Path) -> PathBuf {
+        let src = testdata_dir().join("designspace_from_glyphs");
+        let dest = temp_dir.join("src");
+        fs::create_dir_all(&dest).unwrap();
+        fs::copy(
+            src.join("WghtVar.designspace"),
+            dest.join("WghtVar.designspace"),
+        )
+        .unwrap();
+        for ufo in ["WghtVar-Regular.ufo", "WghtVar-Bold.ufo"] {
+            copy_dir(&src.join(ufo), &dest.join(ufo));
+        }
+        dest.join("WghtVar.designspace")
+    }
+
+    #[test]
+    fn incremental_compile_reuses_unchanged_work() {
+        let temp_dir = tempdir().unwrap();
+        let source = editable_wght_var(temp_dir.path());
+        let build_dir = temp_dir.path().join("build");
+
+        let (first, clean_font) = compile_incrementally(&build_dir, &source);
+        assert_eq!((0, 6), first.glyphs);
+        assert_eq!((0, 2), first.kerning_instances);
+        assert_eq!(0, first.glyph_binaries.0);
+
+        // manual-component is decomposed when glyph order is finalized so what was written
+        // down isn't what source produced; it always reruns. The .notdef we add does too.
+        let (second, font) = compile_incrementally(&build_dir, &source);
+        assert_eq!((5, 6), second.glyphs);
+        assert_eq!((2, 2), second.kerning_instances);
+        assert_eq!((5, 7), second.glyph_binaries);
+        assert_eq!(clean_font, font);
+    }
+
+    #[test]
+    fn incremental_compile_reruns_changed_glyph_and_dependents() {
+        let temp_dir = tempdir().unwrap();
+        let source = editable_wght_var(temp_dir.path());
+        let build_dir = temp_dir.path().join("build");
+        compile_incrementally(&build_dir, &source);
+
+        // manual-component uses hyphen so both must rerun
+        let hyphen = source
+            .parent()
+            .unwrap()
+            .join("WghtVar-Bold.ufo/glyphs/hyphen.glif");
+        let glif = fs::read_to_string(&hyphen).unwrap();
+        fs::write(
+            &hyphen,
+            glif.replace("advance width=\"600\"", "advance width=\"640\""),
+        )
+        .unwrap();
+
+        let (summary, font) = compile_incrementally(&build_dir, &source);
+        assert_eq!((4, 6), summary.glyphs);
+        assert_eq!((2, 2), summary.kerning_instances);
+
+        let (_, clean_font) = compile_incrementally(&temp_dir.path().join("clean"), &source);
+        assert_eq!(clean_font, font);
+    }
+
+    #[test]
+    fn incremental_compile_reuses_nothing_after_global_change() {
+        let temp_dir = tempdir().unwrap();
+        let source = editable_wght_var(temp_dir.path());
+        let build_dir = temp_dir.path().join("build");
+        compile_incrementally(&build_dir, &source);
+
+        let designspace = fs::read_to_string(&source).unwrap();
+        fs::write(
+            &source,
+            designspace.replace("maximum=\"700\"", "maximum=\"800\""),
+        )
+        .unwrap();
+        let designspace = fs::read_to_string(&source).unwrap();
+        fs::write(
+            &source,
+            designspace.replace("xvalue=\"700\"", "xvalue=\"800\""),
+        )
+        .unwrap();
+
+        let (summary, _) = compile_incrementally(&build_dir, &source);
+        assert_eq!((0, 6), summary.glyphs);
+        assert_eq!((0, 2), summary.kerning_instances);
+        assert_eq!(0, summary.glyph_binaries.0);
+    }
+
     #[test]
     fn compile_mov_xy_and_move_around() {
         let result = TestCompile::compile_source("mov_xy.designspace");
@@ -1189,6 +2138,78 @@ mod tests {
         );
     }
 
+    #[test]
+    fn compile_variable_otf_with_hints() {
+        let result =
+            TestCompile::compile("designspace_from_glyphs/WghtVar.designspace", |mut args| {
+                args.output_format = OutputFormat::Otf;
+                args
+            });
+        let font = result.font();
+        let cff2 = font.cff2().unwrap();
+
+        // The Private DICT is found through the one Font DICT in the FDArray
+        let fd_array = dict::entries(cff2.top_dict_data(), None)
+            .find_map(|entry| match entry.unwrap() {
+                Entry::FdArrayOffset(offset) => Some(offset),
+                _ => None,
+            })
+            .unwrap();
+        let fd_array = Index2::read(cff2.offset_data().split_off(fd_array).unwrap()).unwrap();
+        let private = dict::entries(fd_array.get(0).unwrap(), None)
+            .find_map(|entry| match entry.unwrap() {
+                Entry::PrivateDictRange(range) => Some(range),
+                _ => None,
+            })
+            .unwrap();
+        let private = &cff2.offset_data().as_bytes()[private];
+
+        let to_i32 = |(bottom, top): &(Fixed, Fixed)| (bottom.to_i32(), top.to_i32());
+        assert_eq!(
+            vec![
+                ("BlueValues", vec![(-16, 0), (737, 753)]),
+                ("OtherBlues", vec![(-58, -42)]),
+            ],
+            dict::entries(private, None)
+                .map(|entry| match entry.unwrap() {
+                    Entry::BlueValues(blues) =>
+                        ("BlueValues", blues.values().iter().map(to_i32).collect()),
+                    Entry::OtherBlues(blues) =>
+                        ("OtherBlues", blues.values().iter().map(to_i32).collect()),
+                    other => panic!("unexpected {other:?}"),
+                })
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn compile_avar2_from_axis_mappings() {
+        let result = TestCompile::compile_source("wght_var_avar2.designspace");
+        let font = result.font();
+        let avar = font.avar().unwrap();
+        assert_eq!(MajorMinor::VERSION_2_0, avar.version());
+
+        let axis_index_map = avar.axis_index_map().unwrap().unwrap();
+        let var_store = avar.var_store().unwrap().unwrap();
+        // How far each of wght, XOPQ moves at a few input weights
+        let actual: Vec<_> = [0.0, 0.5, 0.75, 1.0]
+            .into_iter()
+            .map(|wght| {
+                let coords = [F2Dot14::from_f32(wght), F2Dot14::ZERO];
+                (0..2)
+                    .map(|axis| {
+                        let idx = axis_index_map.get(axis).unwrap();
+                        var_store.compute_delta(idx, &coords).unwrap()
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        assert_eq!(
+            vec![vec![0, 0], vec![4096, 0], vec![2048, 8192], vec![0, 16384]],
+            actual
+        );
+    }
+
     #[test]
     fn compile_without_ir() {
         let result = TestCompile::compile("glyphs2/WghtVar.glyphs", |mut args| {
@@ -1519,6 +2540,45 @@ mod tests {
         );
     }
 
+    #[test]
+    fn generates_stat_axis_values() {
+        let result = TestCompile::compile_source("wght_var_stat.designspace");
+        let font = result.font();
+
+        let name = font.name().unwrap();
+        let stat = font.stat().unwrap();
+        let axis_values: Vec<_> = stat
+            .offset_to_axis_values()
+            .unwrap()
+            .unwrap()
+            .axis_values()
+            .iter()
+            .map(|av| {
+                let av = av.unwrap();
+                (
+                    av.format(),
+                    resolve_name(&name, av.value_name_id()).unwrap(),
+                    av.flags().bits(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            (
+                "Regular".to_string(),
+                vec![
+                    (4, "Bold Special".to_string(), 0),
+                    (3, "Regular".to_string(), 2),
+                    (2, "Semibold".to_string(), 0),
+                    (1, "Bold".to_string(), 0),
+                ]
+            ),
+            (
+                resolve_name(&name, stat.elided_fallback_name_id().unwrap()).unwrap(),
+                axis_values
+            )
+        );
+    }
+
     fn assert_simple_kerning(source: &str) {
         let result = TestCompile::compile_source(source);
 
@@ -1642,6 +2702,111 @@ mod tests {
         assert_simple_kerning("designspace_from_glyphs/WghtVar.designspace");
     }
 
+    #[test]
+    fn compile_fontra() {
+        let result = TestCompile::compile_source("fontra/wght_var.fontra");
+        let font = result.font();
+
+        assert_eq!(
+            vec![(Tag::new(b"wght"), 400.0, 400.0, 700.0)],
+            font.fvar()
+                .unwrap()
+                .axes()
+                .unwrap()
+                .iter()
+                .map(|a| (
+                    a.axis_tag(),
+                    a.min_value().to_f64(),
+                    a.default_value().to_f64(),
+                    a.max_value().to_f64()
+                ))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            Some("Wght Var".to_string()),
+            resolve_name(&font.name().unwrap(), NameId::FAMILY_NAME)
+        );
+        assert_eq!(800, font.os2().unwrap().s_typo_ascender());
+
+        // The intermediate source is kept, the inactive one is not
+        let v = result.fe_context.get_glyph("V");
+        let mut wght: Vec<_> = v
+            .sources()
+            .keys()
+            .map(|loc| loc.get(Tag::new(b"wght")).unwrap().to_f64())
+            .collect();
+        wght.sort_by(|a, b| a.total_cmp(b));
+        assert_eq!(vec![0.0, 0.5, 1.0], wght);
+
+        let gsub = font.gsub().unwrap();
+        let feature_list = gsub.feature_list().unwrap();
+        assert_eq!(
+            vec![Tag::new(b"ss01")],
+            feature_list
+                .feature_records()
+                .iter()
+                .map(|f| f.feature_tag())
+                .collect::<Vec<_>>()
+        );
+
+        let kerning_groups = result.fe_context.kerning_groups.get();
+        let mut kerns = Vec::new();
+        for kern_loc in kerning_groups.locations.iter() {
+            let wght = kern_loc.get(Tag::new(b"wght")).unwrap().to_f64();
+            let kerns_at = result
+                .fe_context
+                .kerning_at
+                .get(&FeWorkIdentifier::KernInstance(kern_loc.clone()));
+            for ((left, right), adjustment) in kerns_at.kerns.iter() {
+                kerns.push((format!("{left} {right}"), wght, adjustment.0));
+            }
+        }
+        kerns.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(
+            vec![
+                ("@side1.A @side2.V".to_string(), 0.0, -40.0),
+                ("@side1.A @side2.V".to_string(), 1.0, -60.0),
+                ("V A".to_string(), 0.0, -30.0),
+            ],
+            kerns
+        );
+        assert!(font.gpos().is_ok());
+    }
+
+    #[test]
+    fn compile_fontra_variable_components() {
+        let result = TestCompile::compile_source("fontra/varc.fontra");
+        let font = result.font();
+
+        // VARC covers the glyph with variable components
+        let varc = font.table_data(Tag::new(b"VARC")).unwrap();
+        let varc = varc.as_bytes();
+        assert_eq!(&[0u8, 1, 0, 0], &varc[..4]);
+        let coverage_offset = u32::from_be_bytes(varc[4..8].try_into().unwrap()) as usize;
+        let coverage = CoverageTable::read(FontData::new(&varc[coverage_offset..])).unwrap();
+        assert_eq!(
+            vec![result.get_gid("bars")],
+            coverage.iter().collect::<Vec<_>>()
+        );
+        assert!(result
+            .fe_context
+            .variable_composites
+            .get()
+            .glyphs
+            .contains_key("bars"));
+
+        // glyf gets the decomposed outline, which varies along with bar
+        let bars = result.fe_context.get_glyph("bars");
+        assert_eq!(2, bars.sources().len());
+        let glyphs = result.glyphs();
+        let glyphs = glyphs.read();
+        let Some(glyf::Glyph::Simple(glyph)) = &glyphs[result.get_gid("bars").to_u16() as usize]
+        else {
+            panic!("Expected a simple glyph");
+        };
+        assert_eq!(2, glyph.number_of_contours());
+    }
+
     fn assert_intermediate_layer(src: &str) {
         let result = TestCompile::compile_source(src);
         let font = result.font();
@@ -2819,6 +3984,145 @@ mod tests {
         assert!(ss02.feature_params().is_none());
     }
 
+    #[test]
+    fn compile_designspace_rules_to_feature_variations() {
+        let result = TestCompile::compile_source("wght_var_rules.designspace");
+        let font = result.font();
+        let gsub: write_fonts::tables::gsub::Gsub = font.gsub().unwrap().to_owned_table();
+
+        // bar=>plus sorts before plus=>bar so it gets the first lookup
+        let bar = result.get_glyph_index("bar").unwrap();
+        let plus = result.get_glyph_index("plus").unwrap();
+        let substitutions = gsub
+            .lookup_list
+            .lookups
+            .iter()
+            .map(|lookup| {
+                let write_fonts::tables::gsub::SubstitutionLookup::Single(lookup) = &**lookup
+                else {
+                    panic!("Expected single substitution, got {lookup:?}");
+                };
+                match &*lookup.subtables[0] {
+                    write_fonts::tables::gsub::SingleSubst::Format1(table) => table
+                        .coverage
+                        .iter()
+                        .map(|gid| {
+                            let to = gid.to_u16() as i32 + table.delta_glyph_id as i32;
+                            (gid.to_u16() as u32, to as u32)
+                        })
+                        .collect::<Vec<_>>(),
+                    write_fonts::tables::gsub::SingleSubst::Format2(table) => table
+                        .coverage
+                        .iter()
+                        .zip(table.substitute_glyph_ids.iter())
+                        .map(|(from, to)| (from.to_u16() as u32, to.to_u16() as u32))
+                        .collect::<Vec<_>>(),
+                }
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(vec![vec![(bar, plus)], vec![(plus, bar)]], substitutions);
+
+        // rvrn exists but only does anything through feature variations
+        let features = gsub
+            .feature_list
+            .feature_records
+            .iter()
+            .map(|r| (r.feature_tag, r.feature.lookup_list_indices.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(vec![(Tag::new(b"rvrn"), vec![])], features);
+
+        // Where both rules apply both lookups run, heavy first
+        let records = gsub
+            .feature_variations
+            .as_ref()
+            .unwrap()
+            .feature_variation_records
+            .iter()
+            .map(|record| {
+                let conditions = record
+                    .condition_set
+                    .as_ref()
+                    .unwrap()
+                    .conditions
+                    .iter()
+                    .map(|condition| {
+                        let write_fonts::tables::layout::Condition::Format1AxisRange(condition) =
+                            &**condition
+                        else {
+                            panic!("Expected axis range, got {condition:?}");
+                        };
+                        (
+                            condition.axis_index,
+                            condition.filter_range_min_value.to_f32(),
+                            condition.filter_range_max_value.to_f32(),
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                let substitutions = record
+                    .feature_table_substitution
+                    .as_ref()
+                    .unwrap()
+                    .substitutions
+                    .iter()
+                    .map(|s| {
+                        (
+                            s.feature_index,
+                            s.alternate_feature.lookup_list_indices.clone(),
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                (conditions, substitutions)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                (vec![(0, 0.75, 1.0)], vec![(0, vec![0, 1])]),
+                (vec![(0, 0.5, 0.75)], vec![(0, vec![0])]),
+            ],
+            records
+        );
+    }
+
+    #[test]
+    fn compile_bracket_layers_to_feature_variations() {
+        let result = TestCompile::compile_source("glyphs3/WghtVar_Bracket.glyphs");
+        let font = result.font();
+        let gsub: write_fonts::tables::gsub::Gsub = font.gsub().unwrap().to_owned_table();
+
+        // The alternates exist, without codepoints
+        let a = result.get_glyph_index("A").unwrap();
+        let a_alt = result.get_glyph_index("A.BRACKET.varAlt01").unwrap();
+        assert!(result.get_glyph_index("Aacute.BRACKET.varAlt01").is_some());
+        let charmap = Charmap::new(&font);
+        assert_eq!(Some(GlyphId::new(a)), charmap.map('A'));
+
+        // Bracket substitutions run last, under rclt
+        let features = gsub
+            .feature_list
+            .feature_records
+            .iter()
+            .map(|r| r.feature_tag)
+            .collect::<Vec<_>>();
+        assert_eq!(vec![Tag::new(b"rclt")], features);
+        let feature_variations = gsub.feature_variations.as_ref().unwrap();
+        assert_eq!(1, feature_variations.feature_variation_records.len());
+        let write_fonts::tables::gsub::SubstitutionLookup::Single(lookup) =
+            &*gsub.lookup_list.lookups[0]
+        else {
+            panic!("Expected a single substitution");
+        };
+        let covered = match &*lookup.subtables[0] {
+            write_fonts::tables::gsub::SingleSubst::Format1(table) => {
+                table.coverage.iter().collect::<Vec<_>>()
+            }
+            write_fonts::tables::gsub::SingleSubst::Format2(table) => {
+                table.coverage.iter().collect::<Vec<_>>()
+            }
+        };
+        assert!(covered.contains(&GlyphId16::new(a as u16)), "{covered:?}");
+        assert_ne!(a, a_alt);
+    }
+
     #[test]
     fn merge_name_table_from_fea() {
         let result = TestCompile::compile_source("CustomNameTableInFea.ufo");
@@ -2954,4 +4258,183 @@ mod tests {
         let result = TestCompile::compile_source("glyphs3/COLRv1-grayscale.glyphs");
         result.font().colr().unwrap(); // for now just check the table exists
     }
+
+    #[test]
+    fn colr_gradients() {
+        let result = TestCompile::compile_source("glyphs3/COLRv1-simple.glyphs");
+        let font = result.font();
+        let colr = font.colr().unwrap();
+        assert_eq!(0, colr.num_base_glyph_records());
+
+        let base_glyphs = colr.base_glyph_list().unwrap().unwrap();
+        assert_eq!(
+            ["A", "B", "C", "D", "K", "L", "M", "N"]
+                .map(|n| result.get_gid(n))
+                .as_slice(),
+            base_glyphs
+                .base_glyph_paint_records()
+                .iter()
+                .map(|r| r.glyph_id())
+                .collect::<Vec<_>>()
+        );
+
+        // Every color glyph paints its own outline, the gradient relative to the outline bbox
+        let paints = base_glyphs
+            .base_glyph_paint_records()
+            .iter()
+            .map(|r| {
+                let Paint::Glyph(glyph) = r.paint(base_glyphs.offset_data()).unwrap() else {
+                    panic!("Expected PaintGlyph");
+                };
+                assert_eq!(r.glyph_id(), glyph.glyph_id());
+                glyph.paint().unwrap()
+            })
+            .collect::<Vec<_>>();
+        let Paint::LinearGradient(linear) = &paints[0] else {
+            panic!("Expected PaintLinearGradient, got {:?}", paints[0]);
+        };
+        assert_eq!(
+            (111, 50, 494, 450, -289, 433),
+            (
+                linear.x0().to_i16(),
+                linear.y0().to_i16(),
+                linear.x1().to_i16(),
+                linear.y1().to_i16(),
+                linear.x2().to_i16(),
+                linear.y2().to_i16()
+            )
+        );
+        let color_line = linear.color_line().unwrap();
+        assert_eq!(
+            vec![(0.0, 1), (1.0, 0)],
+            color_line
+                .color_stops()
+                .iter()
+                .map(|s| (s.stop_offset().to_f32(), s.palette_index()))
+                .collect::<Vec<_>>()
+        );
+        let Paint::RadialGradient(radial) = &paints[4] else {
+            panic!("Expected PaintRadialGradient, got {:?}", paints[4]);
+        };
+        assert_eq!(
+            (303, 250, 0, 303, 250, 346),
+            (
+                radial.x0().to_i16(),
+                radial.y0().to_i16(),
+                radial.radius0().to_u16(),
+                radial.x1().to_i16(),
+                radial.y1().to_i16(),
+                radial.radius1().to_u16()
+            )
+        );
+    }
+
+    #[test]
+    fn colr_layers() {
+        let result = TestCompile::compile_source("glyphs3/COLRv1-layers.glyphs");
+        let font = result.font();
+        let colr = font.colr().unwrap();
+        let base_glyphs = colr.base_glyph_list().unwrap().unwrap();
+        let layer_list = colr.layer_list().unwrap().unwrap();
+        let paints = base_glyphs
+            .base_glyph_paint_records()
+            .iter()
+            .map(|r| {
+                let Paint::ColrLayers(layers) = r.paint(base_glyphs.offset_data()).unwrap() else {
+                    panic!("Expected PaintColrLayers");
+                };
+                let first = layers.first_layer_index() as usize;
+                (first..first + layers.num_layers() as usize)
+                    .map(|i| layer_list.paints().get(i).unwrap())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        // Each path of A is painted through a glyph of its own
+        let [Paint::Glyph(first), Paint::Glyph(second)] = paints[0].as_slice() else {
+            panic!("Expected two PaintGlyph, got {:?}", paints[0]);
+        };
+        assert_eq!(
+            (result.get_gid("A.color0"), result.get_gid("A.color1")),
+            (first.glyph_id(), second.glyph_id())
+        );
+        assert!(matches!(first.paint(), Ok(Paint::LinearGradient(..))));
+        assert!(matches!(second.paint(), Ok(Paint::Solid(..))));
+
+        // The component of B reuses the paint of A, moved
+        let [Paint::Glyph(path), Paint::Transform(component)] = paints[1].as_slice() else {
+            panic!(
+                "Expected PaintGlyph and PaintTransform, got {:?}",
+                paints[1]
+            );
+        };
+        assert_eq!(result.get_gid("B.color0"), path.glyph_id());
+        assert_eq!(50.0, component.transform().unwrap().dx().to_f64());
+        let Ok(Paint::ColrGlyph(reuse)) = component.paint() else {
+            panic!("Expected PaintColrGlyph");
+        };
+        assert_eq!(result.get_gid("A"), reuse.glyph_id());
+    }
+
+    #[test]
+    fn colr_from_ufo_lib() {
+        let temp_dir = tempdir().unwrap();
+        let source = editable_wght_var(temp_dir.path());
+        for (ufo, alpha) in [("WghtVar-Regular.ufo", "1.0"), ("WghtVar-Bold.ufo", "0.5")] {
+            let lib = source.parent().unwrap().join(ufo).join("lib.plist");
+            let mut plist = fs::read_to_string(&lib).unwrap();
+            let end = plist.rfind("</dict>").unwrap();
+            plist.insert_str(
+                end,
+                &format!(
+                    "<key>com.github.googlei18n.ufo2ft.colorPalettes</key>\
+                     <array><array>\
+                     <array><real>1</real><real>0</real><real>0</real><real>1</real></array>\
+                     <array><real>0</real><real>0</real><real>1</real><real>1</real></array>\
+                     </array></array>\
+                     <key>com.github.googlei18n.ufo2ft.colorGlyphs</key>\
+                     <dict><key>exclam</key><dict>\
+                     <key>Format</key><integer>10</integer>\
+                     <key>Glyph</key><string>hyphen</string>\
+                     <key>Paint</key><dict>\
+                     <key>Format</key><integer>3</integer>\
+                     <key>PaletteIndex</key><integer>1</integer>\
+                     <key>Alpha</key><real>{alpha}</real>\
+                     </dict></dict></dict>"
+                ),
+            );
+            fs::write(&lib, plist).unwrap();
+        }
+        let result = TestCompile::compile(source.to_str().unwrap(), |args| args);
+        let font = result.font();
+
+        // The palette is sorted so blue, index 1 in the source, comes first
+        let cpal = font.cpal().unwrap();
+        assert_eq!(
+            vec![(0, 0, 255), (255, 0, 0)],
+            cpal.color_records_array()
+                .unwrap()
+                .unwrap()
+                .iter()
+                .map(|c| (c.red, c.green, c.blue))
+                .collect::<Vec<_>>()
+        );
+
+        let colr = font.colr().unwrap();
+        let base_glyphs = colr.base_glyph_list().unwrap().unwrap();
+        let [record] = base_glyphs.base_glyph_paint_records() else {
+            panic!("Expected one color glyph");
+        };
+        assert_eq!(result.get_gid("exclam"), record.glyph_id());
+        let Paint::Glyph(glyph) = record.paint(base_glyphs.offset_data()).unwrap() else {
+            panic!("Expected PaintGlyph");
+        };
+        assert_eq!(result.get_gid("hyphen"), glyph.glyph_id());
+        // Alpha differs between masters
+        let Ok(Paint::VarSolid(solid)) = glyph.paint() else {
+            panic!("Expected PaintVarSolid");
+        };
+        assert_eq!(0, solid.palette_index());
+        assert!(colr.item_variation_store().is_some());
+    }
 }
@@ -61,7 +61,10 @@ fn run(args: Args) -> Result<(), Error> {
     log_cfg.init();
     timer.add(time.complete());
 
-    fontc::run(args, timer)
+    for reuse_summary in fontc::run(args, timer)? {
+        println!("{reuse_summary}");
+    }
+    Ok(())
 }
 
 fn print_verbose_version() -> Result<(), std::io::Error> {
@@ -125,6 +125,7 @@ fn short_name(id: &AnyWorkId) -> &'static str {
         AnyWorkId::Fe(FeWorkIdentifier::Anchor(..)) => "anchor",
         AnyWorkId::Fe(FeWorkIdentifier::ColorPalettes) => "cpal",
         AnyWorkId::Fe(FeWorkIdentifier::Features) => "fea",
+        AnyWorkId::Fe(FeWorkIdentifier::FeatureVariations) => "featvars",
         AnyWorkId::Fe(FeWorkIdentifier::GlobalMetrics) => "metrics",
         AnyWorkId::Fe(FeWorkIdentifier::Glyph(..)) => "glyph",
         AnyWorkId::Fe(FeWorkIdentifier::GlyphOrder) => "glyphorder",
@@ -133,7 +134,10 @@ fn short_name(id: &AnyWorkId) -> &'static str {
         AnyWorkId::Fe(FeWorkIdentifier::PaintGraph) => "colr",
         AnyWorkId::Fe(FeWorkIdentifier::PreliminaryGlyphOrder) => "pre-go",
         AnyWorkId::Fe(FeWorkIdentifier::StaticMetadata) => "static-meta",
+        AnyWorkId::Fe(FeWorkIdentifier::VariableComposites) => "varcomposites",
         AnyWorkId::Be(BeWorkIdentifier::Avar) => "avar",
+        AnyWorkId::Be(BeWorkIdentifier::Cff) => "cff",
+        AnyWorkId::Be(BeWorkIdentifier::Cff2) => "cff2",
         AnyWorkId::Be(BeWorkIdentifier::Cmap) => "cmap",
         AnyWorkId::Be(BeWorkIdentifier::Colr) => "colr-be",
         AnyWorkId::Be(BeWorkIdentifier::Cpal) => "cpal-be",
@@ -166,6 +170,11 @@ fn short_name(id: &AnyWorkId) -> &'static str {
         AnyWorkId::Be(BeWorkIdentifier::Post) => "post",
         AnyWorkId::Be(BeWorkIdentifier::Meta) => "meta",
         AnyWorkId::Be(BeWorkIdentifier::Stat) => "STAT",
+        AnyWorkId::Be(BeWorkIdentifier::Varc) => "VARC",
+        AnyWorkId::Be(BeWorkIdentifier::Vhea) => "vhea",
+        AnyWorkId::Be(BeWorkIdentifier::Vmtx) => "vmtx",
+        AnyWorkId::Be(BeWorkIdentifier::Vorg) => "VORG",
+        AnyWorkId::Be(BeWorkIdentifier::Vvar) => "VVAR",
         AnyWorkId::Be(BeWorkIdentifier::ExtraFeaTables) => "ExtraFeaTables",
         AnyWorkId::InternalTiming(name) => name,
     }
@@ -12,6 +12,7 @@ use std::{
 use crossbeam_channel::{Receiver, TryRecvError};
 use fontbe::{
     avar::create_avar_work,
+    cff::create_cff_work,
     cmap::create_cmap_work,
     colr::create_colr_work,
     cpal::create_cpal_work,
@@ -34,6 +35,9 @@ use fontbe::{
     os2::create_os2_work,
     post::create_post_work,
     stat::create_stat_work,
+    varc::create_varc_work,
+    vertical_metrics::create_vertical_metrics_work,
+    vvar::create_vvar_work,
 };
 use fontdrasil::{
     coords::NormalizedLocation,
@@ -42,13 +46,15 @@ use fontdrasil::{
 };
 use fontir::{
     glyph::create_glyph_order_work,
-    orchestration::{Context as FeContext, WorkId as FeWorkIdentifier},
+    incremental::ReuseSummary,
+    orchestration::{Context as FeContext, Flags, WorkId as FeWorkIdentifier},
     source::Source,
 };
 use log::{debug, trace, warn};
 
 use crate::{
     create_source,
+    incremental::Incremental,
     timing::{create_timer, JobTime, JobTimeQueued, JobTimer},
     work::{AnyAccess, AnyContext, AnyWork},
     Args, Error,
@@ -58,12 +64,17 @@ use crate::{
 pub struct Workload {
     args: Args,
     source: Box<dyn Source>,
+    // Set if only IR is wanted, there's no backend work
+    ir_only: bool,
     job_count: usize,
     success: HashSet<AnyWorkId>,
     error: Option<Error>,
     // we count the number of errors encountered but only store the first we see
     n_failures: usize,
 
+    // Set if we can reuse the output of the prior compilation into the build dir
+    incremental: Option<Incremental>,
+
     // When K completes also mark all entries in V complete
     also_completes: HashMap<AnyWorkId, Vec<AnyWorkId>>,
     pub(crate) jobs_pending: HashMap<AnyWorkId, Job>,
@@ -124,82 +135,148 @@ impl Workload {
         let source = create_source(args.source())?;
 
         timer.add(time.complete());
+        Self::with_source(args, source, timer)
+    }
+
+    /// Create a workload that compiles the provided source.
+    pub fn with_source(
+        args: Args,
+        source: Box<dyn Source>,
+        timer: JobTimer,
+    ) -> Result<Self, Error> {
         let time = create_timer(AnyWorkId::InternalTiming("Create workload"), 0)
             .queued()
             .run();
 
-        let mut workload = Self {
+        let incremental = Incremental::new(&args, source.as_ref())?;
+        let mut workload = Self::empty(args, source, incremental, false, timer);
+
+        // Create work roughly in the order it would typically occur
+        // Work is eligible to run as soon as all dependencies are complete
+        // so this is NOT the definitive execution order
+        workload.add_ir_work()?;
+        workload.add_binary_work();
+
+        workload.timer.add(time.complete());
+
+        Ok(workload)
+    }
+
+    /// Create a workload that compiles the provided source to IR and goes no further.
+    ///
+    /// For when the font to write is made from the IR rather than the source.
+    pub fn ir_only(args: Args, source: Box<dyn Source>, timer: JobTimer) -> Result<Self, Error> {
+        let time = create_timer(AnyWorkId::InternalTiming("Create workload"), 0)
+            .queued()
+            .run();
+
+        let mut workload = Self::empty(args, source, None, true, timer);
+        workload.add_ir_work()?;
+
+        workload.timer.add(time.complete());
+
+        Ok(workload)
+    }
+
+    fn empty(
+        args: Args,
+        source: Box<dyn Source>,
+        incremental: Option<Incremental>,
+        ir_only: bool,
+        timer: JobTimer,
+    ) -> Self {
+        Self {
             args,
             source,
+            ir_only,
             job_count: 0,
             success: Default::default(),
             error: Default::default(),
             n_failures: 0,
+            incremental,
             also_completes: Default::default(),
             jobs_pending: Default::default(),
             count_pending: Default::default(),
             timer,
-        };
+        }
+    }
 
-        // Create work roughly in the order it would typically occur
-        // Work is eligible to run as soon as all dependencies are complete
-        // so this is NOT the definitive execution order
+    /// FE: f(source) => IR
+    fn add_ir_work(&mut self) -> Result<(), Error> {
+        self.add(self.source.create_static_metadata_work()?);
+        self.add(self.source.create_global_metric_work()?);
+        self.add(self.source.create_feature_ir_work()?);
+        self.add_skippable_feature_work(self.source.create_feature_variations_work()?);
+        self.add_skippable_feature_work(self.source.create_kerning_group_ir_work()?);
+        for work in self.source.create_glyph_ir_work()? {
+            let work = match &self.incremental {
+                Some(incremental) => incremental.glyph_ir_work(work),
+                None => work,
+            };
+            self.add(work);
+        }
+        self.add(create_glyph_order_work());
+        self.add(self.source.create_color_palette_work()?);
+        self.add(self.source.create_paint_graph_work()?);
+        Ok(())
+    }
 
-        // FE: f(source) => IR
-        workload.add(workload.source.create_static_metadata_work()?);
-        workload.add(workload.source.create_global_metric_work()?);
-        workload.add(workload.source.create_feature_ir_work()?);
-        workload.add_skippable_feature_work(workload.source.create_kerning_group_ir_work()?);
-        workload
-            .source
-            .create_glyph_ir_work()?
-            .into_iter()
-            .for_each(|w| workload.add(w));
-        workload.add(create_glyph_order_work());
-        workload.add(workload.source.create_color_palette_work()?);
-        workload.add(workload.source.create_paint_graph_work()?);
-
-        // BE: f(IR, maybe other BE work) => binary
-        workload.add_skippable_feature_work(FeatureFirstPassWork::create());
-        workload.add_skippable_feature_work(FeatureCompilationWork::create());
-        workload.add(create_gasp_work());
-        let ir_glyphs = workload
-            .jobs_pending
-            .keys()
-            .filter_map(|id| match id {
-                AnyWorkId::Fe(FeWorkIdentifier::Glyph(name)) => Some(name.clone()),
-                _ => None,
-            })
-            .collect::<Vec<_>>();
-        for glyph_name in ir_glyphs {
-            workload.add(create_glyf_work(glyph_name))
+    /// BE: f(IR, maybe other BE work) => binary
+    fn add_binary_work(&mut self) {
+        self.add_skippable_feature_work(FeatureFirstPassWork::create());
+        self.add_skippable_feature_work(FeatureCompilationWork::create());
+        self.add(create_gasp_work());
+        if self.args.flags().contains(Flags::CFF_OUTLINES) {
+            self.add(create_cff_work());
+        } else {
+            let ir_glyphs = self
+                .jobs_pending
+                .keys()
+                .filter_map(|id| match id {
+                    AnyWorkId::Fe(FeWorkIdentifier::Glyph(name)) => Some(name.clone()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            for glyph_name in ir_glyphs {
+                let restore = self
+                    .incremental
+                    .as_ref()
+                    .and_then(|incremental| incremental.glyf_work(&glyph_name));
+                self.add(restore.unwrap_or_else(|| create_glyf_work(glyph_name)))
+            }
+            self.add(create_glyf_loca_work());
+            self.add(create_gvar_work());
         }
-        workload.add(create_glyf_loca_work());
-        workload.add(create_avar_work());
-        workload.add(create_stat_work());
-        workload.add(create_meta_work());
-        workload.add(create_cmap_work());
-        workload.add(create_colr_work());
-        workload.add(create_cpal_work());
-        workload.add(create_fvar_work());
-        workload.add(create_gvar_work());
-        workload.add(create_head_work());
-        workload.add_skippable_feature_work(create_gather_ir_kerning_work());
-        workload.add_skippable_feature_work(create_kerns_work());
-        workload.add_skippable_feature_work(create_mark_work());
-        workload.add(create_metric_and_limit_work());
-        workload.add(create_hvar_work());
-        workload.add(create_mvar_work());
-        workload.add(create_name_work());
-        workload.add(create_os2_work());
-        workload.add(create_post_work());
+        self.add(create_avar_work());
+        self.add(create_stat_work());
+        self.add(create_meta_work());
+        self.add(create_cmap_work());
+        self.add(create_colr_work());
+        self.add(create_cpal_work());
+        self.add(create_fvar_work());
+        self.add(create_head_work());
+        self.add_skippable_feature_work(create_gather_ir_kerning_work());
+        self.add_skippable_feature_work(create_kerns_work());
+        self.add_skippable_feature_work(create_mark_work());
+        self.add(create_metric_and_limit_work());
+        self.add(create_hvar_work());
+        self.add(create_mvar_work());
+        self.add(create_name_work());
+        self.add(create_os2_work());
+        self.add(create_post_work());
+        self.add(create_vertical_metrics_work());
+        self.add(create_vvar_work());
+        self.add(create_varc_work());
 
         // Make a damn font
-        workload.add(create_font_work());
-
-        workload.timer.add(time.complete());
+        self.add(create_font_work());
+    }
 
-        Ok(workload)
+    /// What was reused from the prior compilation, None if compilation isn't incremental
+    pub(crate) fn reuse_summary(&self) -> Option<ReuseSummary> {
+        self.incremental
+            .as_ref()
+            .map(|incremental| incremental.summary())
     }
 
     fn add_skippable_feature_work(&mut self, work: impl Into<AnyWork>) {
@@ -340,7 +417,10 @@ impl Workload {
             return;
         }
 
-        let mut deps = AccessBuilder::<AnyWorkId>::new().variant(FeWorkIdentifier::StaticMetadata);
+        // Global metrics are needed for vertical phantom points
+        let mut deps = AccessBuilder::<AnyWorkId>::new()
+            .variant(FeWorkIdentifier::StaticMetadata)
+            .variant(FeWorkIdentifier::GlobalMetrics);
 
         let mut has_components = false;
         for inst in glyph.sources().values() {
@@ -353,6 +433,10 @@ impl Workload {
         // We don't *have* to wait on glyph order, but if we don't it delays the critical path
         if has_components {
             deps = deps.variant(FeWorkIdentifier::GlyphOrder);
+            // Overlap detection decomposes nested components too
+            if self.args.flags().contains(Flags::DETECT_OVERLAPS) {
+                deps = deps.variant(FeWorkIdentifier::ALL_GLYPHS);
+            }
         }
 
         let deps = deps.build().into();
@@ -378,7 +462,11 @@ impl Workload {
         self.mark_also_completed(&success);
 
         // When glyph order finalizes, add BE work for any new glyphs
-        if let AnyWorkId::Fe(FeWorkIdentifier::GlyphOrder) = success {
+        // CFF is built in a single job that reads every glyph so there's nothing to add
+        if let (AnyWorkId::Fe(FeWorkIdentifier::GlyphOrder), false) = (
+            &success,
+            self.ir_only || self.args.flags().contains(Flags::CFF_OUTLINES),
+        ) {
             let preliminary_glyph_order = fe_root.preliminary_glyph_order.get();
             for glyph_name in fe_root
                 .glyph_order
@@ -396,23 +484,29 @@ impl Workload {
         if let AnyWorkId::Fe(FeWorkIdentifier::KerningGroups) = success {
             if let Some(groups) = fe_root.kerning_groups.try_get() {
                 for location in groups.locations.iter() {
-                    self.add(
-                        self.source
-                            .create_kerning_instance_ir_work(location.clone())?,
-                    );
+                    let work = self
+                        .source
+                        .create_kerning_instance_ir_work(location.clone())?;
+                    let work = match &self.incremental {
+                        Some(incremental) => incremental.kerning_instance_work(work),
+                        None => work,
+                    };
+                    self.add(work);
                 }
             }
 
             // https://github.com/googlefonts/fontc/pull/655: don't set read access on GatherIrKerning until we spawn kern instance tasks
-            self.jobs_pending
-                .get_mut(&AnyWorkId::Be(BeWorkIdentifier::GatherIrKerning))
-                .expect("Gather IR Kerning has to be pending")
-                .read_access = AccessBuilder::<AnyWorkId>::new()
-                .variant(FeWorkIdentifier::GlyphOrder)
-                .variant(FeWorkIdentifier::KerningGroups)
-                .variant(FeWorkIdentifier::KernInstance(NormalizedLocation::default()))
-                .build()
-                .into();
+            if !self.ir_only {
+                self.jobs_pending
+                    .get_mut(&AnyWorkId::Be(BeWorkIdentifier::GatherIrKerning))
+                    .expect("Gather IR Kerning has to be pending")
+                    .read_access = AccessBuilder::<AnyWorkId>::new()
+                    .variant(FeWorkIdentifier::GlyphOrder)
+                    .variant(FeWorkIdentifier::KerningGroups)
+                    .variant(FeWorkIdentifier::KernInstance(NormalizedLocation::default()))
+                    .build()
+                    .into();
+            }
         }
 
         if let AnyWorkId::Be(BeWorkIdentifier::GatherIrKerning) = success {
@@ -435,6 +529,21 @@ impl Workload {
                 .into();
         }
 
+        if let Some(incremental) = self.incremental.as_mut() {
+            match &success {
+                AnyWorkId::Fe(FeWorkIdentifier::Glyph(glyph_name)) => {
+                    incremental.glyph_completed(fe_root, glyph_name.clone())
+                }
+                AnyWorkId::Fe(FeWorkIdentifier::KernInstance(..)) => {
+                    incremental.kerning_instance_completed()
+                }
+                AnyWorkId::Be(BeWorkIdentifier::GlyfFragment(..)) => {
+                    incremental.glyph_binary_completed()
+                }
+                _ => (),
+            }
+        }
+
         if let AnyWorkId::Fe(FeWorkIdentifier::Glyph(glyph_name)) = success {
             self.update_be_glyph_work(fe_root, glyph_name);
         }
@@ -545,7 +654,13 @@ impl Workload {
         counters
     }
 
-    pub fn exec(mut self, fe_root: &FeContext, be_root: &BeContext) -> Result<JobTimer, Error> {
+    /// Run every job, returning the timing of the jobs and what was reused from the
+    /// prior compilation, if compilation is incremental.
+    pub fn exec(
+        mut self,
+        fe_root: &FeContext,
+        be_root: &BeContext,
+    ) -> Result<(JobTimer, Option<ReuseSummary>), Error> {
         // Async work will send us it's ID on completion
         let (send, recv) =
             crossbeam_channel::unbounded::<(AnyWorkId, Result<(), Error>, JobTime)>();
@@ -737,7 +852,12 @@ impl Workload {
             }
         }
 
-        Ok(self.timer)
+        if let Some(incremental) = &self.incremental {
+            incremental.save(fe_root)?;
+        }
+        let reuse_summary = self.reuse_summary();
+
+        Ok((self.timer, reuse_summary))
     }
 
     fn read_completions(
@@ -891,6 +1011,11 @@ impl Workload {
             self.handle_success(fe_root, be_root, id.clone(), timing)
                 .unwrap_or_else(|e| panic!("Failed to handle success for {id:?}: {e}"));
         }
+        if let Some(incremental) = &self.incremental {
+            incremental
+                .save(fe_root)
+                .unwrap_or_else(|e| panic!("Unable to save build state: {e}"));
+        }
         self.success.difference(&pre_success).cloned().collect()
     }
 }
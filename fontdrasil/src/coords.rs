@@ -328,6 +328,10 @@ impl<Space> Location<Space> {
         self.0.get(&tag).copied()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
     pub fn retain(&mut self, pred: impl Fn(&Tag, &mut Coord<Space>) -> bool) {
         self.0.retain(pred);
     }
@@ -9,6 +9,8 @@ use smol_str::SmolStr;
 use thiserror::Error;
 use write_fonts::types::{InvalidTag, Tag};
 
+use crate::{ir::Color, variations::DeltaError};
+
 #[derive(Debug, Error)]
 pub enum Error {
     /// A source file was not understood
@@ -26,6 +28,12 @@ pub enum Error {
         #[source]
         source: io::Error,
     },
+    #[error("Failed to write file {path}: '{source}'")]
+    WriteFailed {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
     #[error("UPEM value {0} outside valid range 16..=16384")]
     InvalidUpem(f64),
     #[error("Inconsistent UPEM values: {0:?}")]
@@ -81,6 +89,27 @@ pub enum Error {
         n: usize,
         size_n: usize,
     },
+    #[error("'{0}' uses {1:?} which is not in the color palette")]
+    MissingPaletteColor(GlyphName, Color),
+    #[error("Unable to interpolate {what}: '{source}'")]
+    InterpolationFailed {
+        what: String,
+        #[source]
+        source: DeltaError,
+    },
+    #[error("The sources of {0} are not compatible, unable to interpolate")]
+    IncompatibleSources(String),
+    #[error("Rule '{rule}' has a condition on '{axis}', which is not an axis")]
+    NoAxisForCondition { rule: String, axis: String },
+    #[error("Rule '{rule}' has a condition on '{axis}' with minimum {min} > maximum {max}")]
+    InvalidCondition {
+        rule: String,
+        axis: String,
+        min: f64,
+        max: f64,
+    },
+    #[error("Unable to limit '{0}', it is not a variable axis of the font")]
+    NoAxisToLimit(Tag),
 }
 
 /// An error related to loading source input files
@@ -0,0 +1,424 @@
+//! Glyph filters a source asks for, see [GlyphFilter].
+//!
+//! Equivalents of the [ufo2ft filters](https://github.com/googlefonts/ufo2ft/tree/main/Lib/ufo2ft/filters)
+//! sources can declare. Each filter runs over every glyph it selects before the next one starts.
+
+use std::{cmp::Ordering, sync::Arc};
+
+use fontdrasil::{coords::NormalizedLocation, types::GlyphName};
+use kurbo::{Affine, BezPath};
+use log::{debug, warn};
+use write_fonts::OtRound;
+
+use crate::{
+    error::BadGlyph,
+    glyph::{convert_components_to_contours, flatten_glyph},
+    ir::{
+        GlobalMetric, Glyph, GlyphAnchors, GlyphFilter, GlyphFilterKind, GlyphOrder,
+        TransformationOrigin, Transformations,
+    },
+    orchestration::{Context, WorkId},
+    propagate_anchors::propagate_anchors,
+};
+
+/// Run the filters for which `pre` matches on the glyphs in glyph_order, in order.
+pub(crate) fn apply_glyph_filters(
+    context: &Context,
+    glyph_order: &GlyphOrder,
+    pre: bool,
+) -> Result<(), BadGlyph> {
+    let static_metadata = context.static_metadata.get();
+    for filter in static_metadata
+        .glyph_filters
+        .iter()
+        .filter(|filter| filter.pre == pre)
+    {
+        debug!("Apply {:?} filter", filter.kind);
+        match &filter.kind {
+            GlyphFilterKind::DecomposeComponents => {
+                for glyph in selected_glyphs(context, glyph_order, filter) {
+                    if !glyph.default_instance().components.is_empty() {
+                        convert_components_to_contours(context, &glyph)?;
+                    }
+                }
+            }
+            GlyphFilterKind::DecomposeTransformedComponents => {
+                for glyph in selected_glyphs(context, glyph_order, filter) {
+                    if glyph.has_nonidentity_2x2() {
+                        convert_components_to_contours(context, &glyph)?;
+                    }
+                }
+            }
+            GlyphFilterKind::FlattenComponents => {
+                for glyph in selected_glyphs(context, glyph_order, filter) {
+                    flatten_glyph(context, &glyph)?;
+                }
+            }
+            GlyphFilterKind::SortContours => {
+                for glyph in selected_glyphs(context, glyph_order, filter) {
+                    sort_contours(context, &glyph);
+                }
+            }
+            GlyphFilterKind::Transformations(transformations) => {
+                apply_transformations(context, glyph_order, filter, transformations)
+            }
+            GlyphFilterKind::PropagateAnchors => {
+                propagate_anchors(context, glyph_order, &filter.glyphs)?
+            }
+        }
+    }
+    Ok(())
+}
+
+fn selected_glyphs<'a>(
+    context: &'a Context,
+    glyph_order: &'a GlyphOrder,
+    filter: &'a GlyphFilter,
+) -> impl Iterator<Item = Arc<Glyph>> + 'a {
+    glyph_order
+        .names()
+        .filter(|glyph_name| filter.glyphs.contains(glyph_name))
+        .map(|glyph_name| context.get_glyph(glyph_name.clone()))
+}
+
+/// Order contours by their control bounds, as ufo2ft's sortContours does.
+///
+/// The order is taken from the default instance and used for all of them so
+/// the glyph still interpolates. Glyphs with components are left alone.
+fn sort_contours(context: &Context, glyph: &Glyph) {
+    let default = glyph.default_instance();
+    if default.contours.len() < 2 {
+        return;
+    }
+    if !default.components.is_empty() {
+        warn!(
+            "Not sorting the contours of '{}' because it also has components",
+            glyph.name
+        );
+        return;
+    }
+    let bounds: Vec<_> = default
+        .contours
+        .iter()
+        .map(|contour| {
+            let bbox = contour.control_box();
+            [bbox.x0, bbox.y0, bbox.x1, bbox.y1]
+        })
+        .collect();
+    let mut order: Vec<_> = (0..bounds.len()).collect();
+    order.sort_by(|a, b| {
+        bounds[*a]
+            .iter()
+            .zip(bounds[*b].iter())
+            .map(|(a, b)| a.total_cmp(b))
+            .find(|ordering| *ordering != Ordering::Equal)
+            .unwrap_or(Ordering::Equal)
+    });
+    if order.iter().enumerate().all(|(i, j)| i == *j) {
+        return;
+    }
+
+    let mut glyph = glyph.clone();
+    for (_, inst) in glyph.sources_mut() {
+        let contours: Vec<BezPath> = std::mem::take(&mut inst.contours);
+        inst.contours = order.iter().map(|i| contours[*i].clone()).collect();
+    }
+    context.glyphs.set(glyph);
+}
+
+/// The transform ufo2ft's transformations filter applies at location.
+fn transform_at(
+    context: &Context,
+    transformations: &Transformations,
+    location: &NormalizedLocation,
+) -> Affine {
+    let metric_at = |metric: GlobalMetric| {
+        // Metrics are only defined at masters, glyphs elsewhere use those at the default
+        let metrics = context.global_metrics.get();
+        let values = metrics.values(metric);
+        values
+            .get(location)
+            .or_else(|| values.get(context.static_metadata.get().default_location()))
+            .map(|value| value.0)
+            .unwrap_or_default()
+    };
+    let origin_height: f64 = match transformations.origin {
+        TransformationOrigin::CapHeight => metric_at(GlobalMetric::CapHeight),
+        TransformationOrigin::HalfCapHeight => {
+            (metric_at(GlobalMetric::CapHeight) / 2.0).ot_round()
+        }
+        TransformationOrigin::XHeight => metric_at(GlobalMetric::XHeight),
+        TransformationOrigin::HalfXHeight => (metric_at(GlobalMetric::XHeight) / 2.0).ot_round(),
+        TransformationOrigin::Baseline => 0.0,
+    };
+    // Slant and scale about the origin height, then offset
+    Affine::translate((transformations.offset_x.0, transformations.offset_y.0))
+        * Affine::translate((0.0, origin_height))
+        * Affine::scale_non_uniform(transformations.scale_x.0, transformations.scale_y.0)
+        * Affine::skew(transformations.slant.0.to_radians().tan(), 0.0)
+        * Affine::translate((0.0, -origin_height))
+}
+
+fn apply_transformations(
+    context: &Context,
+    glyph_order: &GlyphOrder,
+    filter: &GlyphFilter,
+    transformations: &Transformations,
+) {
+    let selected = |glyph_name: &GlyphName| {
+        glyph_order.contains(glyph_name) && filter.glyphs.contains(glyph_name)
+    };
+    for glyph in selected_glyphs(context, glyph_order, filter) {
+        let mut glyph = (*glyph).clone();
+        for (location, inst) in glyph.sources_mut() {
+            let transform = transform_at(context, transformations, location);
+            for contour in inst.contours.iter_mut() {
+                contour.apply_affine(transform);
+                if transform.determinant() < 0.0 {
+                    *contour = contour.reverse_subpaths();
+                }
+            }
+            for component in inst.components.iter_mut() {
+                // A base that is itself transformed already carries the transform
+                component.transform = if selected(&component.base) && transform.determinant() != 0.0
+                {
+                    transform * component.transform * transform.inverse()
+                } else {
+                    transform * component.transform
+                };
+            }
+        }
+        let Some(anchors) = context.anchors.try_get(&WorkId::Anchor(glyph.name.clone())) else {
+            context.glyphs.set(glyph);
+            continue;
+        };
+        let mut anchors: GlyphAnchors = (*anchors).clone();
+        for anchor in anchors.anchors.iter_mut() {
+            for (location, pos) in anchor.positions.iter_mut() {
+                *pos = transform_at(context, transformations, location) * *pos;
+            }
+        }
+        context.glyphs.set(glyph);
+        context.anchors.set(anchors);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::{BTreeSet, HashMap, HashSet},
+        path::Path,
+    };
+
+    use fontdrasil::orchestration::Access;
+    use kurbo::{Point, Rect, Shape};
+
+    use crate::{
+        ir::{
+            Anchor, AnchorKind, Component, GlobalMetrics, GlyphBuilder, GlyphInstance,
+            GlyphSelection, StaticMetadata,
+        },
+        orchestration::Flags,
+        paths::Paths,
+    };
+
+    use super::*;
+
+    fn test_context(glyph_filters: Vec<GlyphFilter>) -> Context {
+        let mut flags = Flags::default();
+        flags.set(Flags::EMIT_IR, false);
+        let context = Context::new_root(flags, Paths::new(Path::new("/fake/path")))
+            .copy_for_work(Access::All, Access::All);
+        let mut static_metadata = StaticMetadata::new(
+            1000,
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            HashSet::from([NormalizedLocation::new()]),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            None,
+        )
+        .unwrap();
+        static_metadata.glyph_filters = glyph_filters;
+        context.static_metadata.set(static_metadata);
+        context
+    }
+
+    fn add_glyph(context: &Context, name: &str, inst: GlyphInstance) {
+        let mut glyph = GlyphBuilder::new(name.into());
+        glyph
+            .try_add_source(&NormalizedLocation::new(), inst)
+            .unwrap();
+        context.glyphs.set(glyph.build().unwrap());
+    }
+
+    fn square(x: f64) -> BezPath {
+        Rect::new(x, 0.0, x + 100.0, 100.0).to_path(0.0)
+    }
+
+    fn component(base: &str, transform: Affine) -> Component {
+        Component {
+            base: base.into(),
+            transform,
+            variable: None,
+            anchor: None,
+        }
+    }
+
+    fn glyph_order(names: &[&str]) -> GlyphOrder {
+        names.iter().map(|name| GlyphName::new(*name)).collect()
+    }
+
+    fn default_instance(context: &Context, name: &str) -> GlyphInstance {
+        context.get_glyph(name).default_instance().clone()
+    }
+
+    #[test]
+    fn transformations_apply_to_contours_components_and_anchors() {
+        let context = test_context(vec![GlyphFilter {
+            kind: GlyphFilterKind::Transformations(Transformations {
+                offset_x: 10.0.into(),
+                scale_x: 0.5.into(),
+                scale_y: 0.5.into(),
+                ..Default::default()
+            }),
+            pre: false,
+            glyphs: GlyphSelection::Exclude(BTreeSet::from([GlyphName::new("c")])),
+        }]);
+        add_glyph(
+            &context,
+            "a",
+            GlyphInstance {
+                contours: vec![square(0.0)],
+                ..Default::default()
+            },
+        );
+        for name in ["b", "c"] {
+            add_glyph(
+                &context,
+                name,
+                GlyphInstance {
+                    components: vec![component("a", Affine::translate((100.0, 0.0)))],
+                    ..Default::default()
+                },
+            );
+        }
+        context.anchors.set(GlyphAnchors::new(
+            "a".into(),
+            vec![Anchor {
+                kind: AnchorKind::Base("top".into()),
+                positions: HashMap::from([(NormalizedLocation::new(), Point::new(50.0, 100.0))]),
+            }],
+        ));
+
+        apply_glyph_filters(&context, &glyph_order(&["a", "b", "c"]), false).unwrap();
+
+        assert_eq!(
+            Rect::new(10.0, 0.0, 60.0, 50.0),
+            default_instance(&context, "a").contours[0].bounding_box()
+        );
+        // a is transformed itself, so b only needs its offset transformed to match
+        assert_eq!(
+            Affine::translate((50.0, 0.0)),
+            default_instance(&context, "b").components[0].transform
+        );
+        // c is excluded
+        assert_eq!(
+            Affine::translate((100.0, 0.0)),
+            default_instance(&context, "c").components[0].transform
+        );
+        assert_eq!(
+            Point::new(35.0, 50.0),
+            context.anchors.get(&WorkId::Anchor("a".into())).anchors[0].default_pos()
+        );
+    }
+
+    #[test]
+    fn slant_about_half_x_height() {
+        let transformations = Transformations {
+            slant: 45.0.into(),
+            origin: TransformationOrigin::HalfXHeight,
+            ..Default::default()
+        };
+        let context = test_context(Vec::new());
+        let mut metrics = GlobalMetrics::new();
+        metrics.set(GlobalMetric::XHeight, NormalizedLocation::new(), 501.0);
+        context.global_metrics.set(metrics);
+
+        // half the x-height rounds to 251, which stays put
+        let transform = transform_at(&context, &transformations, &NormalizedLocation::new());
+        let moved = transform * Point::new(0.0, 251.0);
+        assert!((moved - Point::new(0.0, 251.0)).hypot() < 1e-9, "{moved:?}");
+        let moved = transform * Point::new(0.0, 351.0);
+        assert!(
+            (moved - Point::new(100.0, 351.0)).hypot() < 1e-9,
+            "{moved:?}"
+        );
+    }
+
+    #[test]
+    fn sort_contours_by_bounds() {
+        let context = test_context(vec![GlyphFilter {
+            kind: GlyphFilterKind::SortContours,
+            pre: true,
+            glyphs: GlyphSelection::All,
+        }]);
+        add_glyph(
+            &context,
+            "a",
+            GlyphInstance {
+                contours: vec![square(200.0), square(0.0), square(100.0)],
+                ..Default::default()
+            },
+        );
+
+        apply_glyph_filters(&context, &glyph_order(&["a"]), true).unwrap();
+
+        assert_eq!(
+            vec![0.0, 100.0, 200.0],
+            default_instance(&context, "a")
+                .contours
+                .iter()
+                .map(|c| c.control_box().x0)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn only_run_filters_for_the_stage() {
+        let context = test_context(vec![GlyphFilter {
+            kind: GlyphFilterKind::DecomposeComponents,
+            pre: true,
+            glyphs: GlyphSelection::Include(BTreeSet::from([GlyphName::new("b")])),
+        }]);
+        add_glyph(
+            &context,
+            "a",
+            GlyphInstance {
+                contours: vec![square(0.0)],
+                ..Default::default()
+            },
+        );
+        for name in ["b", "c"] {
+            add_glyph(
+                &context,
+                name,
+                GlyphInstance {
+                    components: vec![component("a", Affine::IDENTITY)],
+                    ..Default::default()
+                },
+            );
+        }
+        let glyph_order = glyph_order(&["a", "b", "c"]);
+
+        apply_glyph_filters(&context, &glyph_order, false).unwrap();
+        assert_eq!(1, default_instance(&context, "b").components.len());
+
+        apply_glyph_filters(&context, &glyph_order, true).unwrap();
+        let b = default_instance(&context, "b");
+        assert_eq!((1, 0), (b.contours.len(), b.components.len()));
+        assert_eq!(1, default_instance(&context, "c").components.len());
+    }
+}
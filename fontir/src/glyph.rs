@@ -2,6 +2,8 @@
 //!
 //! Notably includes splitting glyphs with contours and components into one new glyph with
 //! the contours and one updated glyph with no contours that references the new gyph as a component.
+//! Glyphs that use variable components are decomposed into contours, see [VariableComposites].
+//! Filters the source declares, see [GlyphFilter](crate::ir::GlyphFilter), run here too.
 
 use std::{
     collections::{HashMap, HashSet, VecDeque},
@@ -11,17 +13,24 @@ use std::{
 use fontdrasil::{
     coords::NormalizedLocation,
     orchestration::{Access, AccessBuilder, Work},
-    types::GlyphName,
+    types::{Axis, GlyphName},
 };
-use kurbo::Affine;
+use kurbo::{Affine, BezPath};
 use log::{debug, log_enabled, trace};
 use ordered_float::OrderedFloat;
 use write_fonts::types::GlyphId16;
 
 use crate::{
     error::{BadGlyph, BadGlyphKind, Error},
-    ir::{Component, Glyph, GlyphBuilder, GlyphOrder},
+    filters::apply_glyph_filters,
+    instancer::{check_compatible_sources, glyph_from_values, glyph_values},
+    ir::{
+        Component, Glyph, GlyphBuilder, GlyphFilterKind, GlyphInstance, GlyphOrder, GlyphSelection,
+        VariableComposites,
+    },
     orchestration::{Context, Flags, IrWork, WorkId},
+    propagate_anchors::propagate_anchors,
+    variations::VariationModel,
 };
 
 pub fn create_glyph_order_work() -> Box<IrWork> {
@@ -78,6 +87,8 @@ fn split_glyph(glyph_order: &GlyphOrder, original: &Glyph) -> Result<(Glyph, Gly
         inst.components.push(Component {
             base: simple_glyph_name.clone(),
             transform: Affine::IDENTITY,
+            variable: None,
+            anchor: None,
         });
     });
 
@@ -224,7 +235,10 @@ fn resolve_inconsistencies(
 /// At time of writing we only support this if every instance uses the same set of components.
 ///
 /// <https://github.com/googlefonts/ufo2ft/blob/dd738cdcddf61cce2a744d1cafab5c9b33e92dd4/Lib/ufo2ft/util.py#L165>
-fn convert_components_to_contours(context: &Context, original: &Glyph) -> Result<(), BadGlyph> {
+pub(crate) fn convert_components_to_contours(
+    context: &Context,
+    original: &Glyph,
+) -> Result<(), BadGlyph> {
     let mut simple = GlyphBuilder::from(original.clone());
     simple
         .sources
@@ -321,7 +335,7 @@ fn move_contours_to_new_component(
 /// that no mixed contour+component glyphs exist.
 ///
 /// See <https://github.com/googlefonts/ufo2ft/blob/main/Lib/ufo2ft/filters/flattenComponents.py>
-fn flatten_glyph(context: &Context, glyph: &Glyph) -> Result<(), BadGlyph> {
+pub(crate) fn flatten_glyph(context: &Context, glyph: &Glyph) -> Result<(), BadGlyph> {
     // Guard: nothing to see here folks
     if glyph.default_instance().components.is_empty() {
         return Ok(());
@@ -351,6 +365,8 @@ fn flatten_glyph(context: &Context, glyph: &Glyph) -> Result<(), BadGlyph> {
                     frontier.push_front(Component {
                         base: ref_component.base.clone(),
                         transform: component.transform * ref_component.transform,
+                        variable: None,
+                        anchor: None,
                     });
                 }
             }
@@ -370,7 +386,8 @@ fn flatten_glyph(context: &Context, glyph: &Glyph) -> Result<(), BadGlyph> {
 ///
 /// This includes decomposing components with non-identity 2x2 transforms
 /// and flattening nested composite glyphs so that they all have depth 1
-/// (no components that reference components).
+/// (no components that reference components). When building CFF outlines
+/// every component is decomposed.
 fn apply_optional_transformations(
     context: &Context,
     glyph_order: &GlyphOrder,
@@ -392,6 +409,17 @@ fn apply_optional_transformations(
         }
     }
 
+    // CFF has no notion of components, every glyph must be simple
+    if context.flags.contains(Flags::CFF_OUTLINES) {
+        for glyph_name in glyph_order.names() {
+            let glyph = context.get_glyph(glyph_name.clone());
+            if !glyph.default_instance().components.is_empty() {
+                convert_components_to_contours(context, &glyph)?;
+            }
+        }
+        return Ok(());
+    }
+
     if context.flags.contains(Flags::FLATTEN_COMPONENTS) {
         for glyph_name in glyph_order.names() {
             let glyph = context.get_glyph(glyph_name.clone());
@@ -402,6 +430,151 @@ fn apply_optional_transformations(
     Ok(())
 }
 
+fn has_variable_components(glyph: &Glyph) -> bool {
+    glyph
+        .sources()
+        .values()
+        .flat_map(|inst| inst.components.iter())
+        .any(|component| component.variable.is_some())
+}
+
+/// The instance of glyph at location, interpolated if glyph has no source there.
+fn instance_at(
+    axes: &[Axis],
+    glyph: &Glyph,
+    location: &NormalizedLocation,
+) -> Result<GlyphInstance, Error> {
+    if let Some(instance) = glyph.sources().get(location) {
+        return Ok(instance.clone());
+    }
+    check_compatible_sources(glyph)?;
+    let default = glyph.default_instance();
+    let default_height = default.height.unwrap_or_default();
+    let default_vertical_origin = default.vertical_origin.unwrap_or_default();
+    let values: HashMap<_, _> = glyph
+        .sources()
+        .iter()
+        .map(|(loc, instance)| {
+            (
+                loc.clone(),
+                glyph_values(instance, default_height, default_vertical_origin),
+            )
+        })
+        .collect();
+    let model = VariationModel::new(values.keys().cloned().collect(), axes.to_vec())?;
+    let values =
+        model
+            .interpolate(location, &values)
+            .map_err(|source| Error::InterpolationFailed {
+                what: format!("glyph '{}'", glyph.name),
+                source,
+            })?;
+    Ok(glyph_from_values(default, &values))
+}
+
+/// The contours of an instance with every component, variable or not, decomposed.
+///
+/// A variable component places its base at its own location along the axes it
+/// mentions, the other axes follow location.
+fn decomposed_instance(
+    context: &Context,
+    axes: &[Axis],
+    instance: &GlyphInstance,
+    location: &NormalizedLocation,
+) -> Result<Vec<BezPath>, Error> {
+    let mut contours = instance.contours.clone();
+    for component in instance.components.iter() {
+        let base = context.get_glyph(component.base.clone());
+        let mut base_location = location.clone();
+        if let Some(variable) = &component.variable {
+            for (tag, coord) in variable.location.iter() {
+                base_location.insert(*tag, *coord);
+            }
+        }
+        let base_instance = instance_at(axes, &base, &base_location)?;
+        for mut contour in decomposed_instance(context, axes, &base_instance, &base_location)? {
+            contour.apply_affine(component.transform);
+            if component.transform.determinant() < 0.0 {
+                contour = contour.reverse_subpaths();
+            }
+            contours.push(contour);
+        }
+    }
+    Ok(contours)
+}
+
+/// Every location where glyph, or any glyph it uses as a component, has a source.
+///
+/// The decomposed glyph needs all of them to vary as its components do.
+fn decomposed_locations(
+    context: &Context,
+    glyph: &Glyph,
+    locations: &mut HashSet<NormalizedLocation>,
+) {
+    locations.extend(glyph.sources().keys().cloned());
+    let bases: HashSet<_> = glyph
+        .sources()
+        .values()
+        .flat_map(|instance| instance.components.iter())
+        .map(|component| component.base.clone())
+        .collect();
+    for base in bases {
+        decomposed_locations(context, &context.get_glyph(base), locations);
+    }
+}
+
+/// Decompose glyphs that use variable components, returning them as they were.
+///
+/// glyf and CFF have no way to express a variable component so they get the
+/// decomposed glyph. The originals remain available to build VARC.
+fn decompose_variable_components(
+    context: &Context,
+    glyph_order: &GlyphOrder,
+) -> Result<VariableComposites, Error> {
+    let originals: Vec<_> = glyph_order
+        .names()
+        .map(|name| context.get_glyph(name.clone()))
+        .filter(|glyph| has_variable_components(glyph))
+        .collect();
+    if originals.is_empty() {
+        return Ok(Default::default());
+    }
+
+    // Decompose everything before updating anything so each glyph sees the originals
+    let axes = &context.static_metadata.get().axes;
+    let mut decomposed = Vec::with_capacity(originals.len());
+    for glyph in originals.iter() {
+        debug!("Decompose variable components of '{}'", glyph.name);
+        let mut locations = HashSet::new();
+        decomposed_locations(context, glyph, &mut locations);
+        let mut simple = GlyphBuilder::from((**glyph).clone());
+        simple.sources.clear();
+        for location in locations {
+            let instance = instance_at(axes, glyph, &location)?;
+            let contours = decomposed_instance(context, axes, &instance, &location)?;
+            simple.sources.insert(
+                location,
+                GlyphInstance {
+                    contours,
+                    components: Vec::new(),
+                    ..instance
+                },
+            );
+        }
+        decomposed.push(simple.build()?);
+    }
+    for glyph in decomposed {
+        context.glyphs.set(glyph);
+    }
+
+    Ok(VariableComposites {
+        glyphs: originals
+            .into_iter()
+            .map(|glyph| (glyph.name.clone(), (*glyph).clone()))
+            .collect(),
+    })
+}
+
 fn ensure_notdef_exists_and_is_gid_0(
     context: &Context,
     glyph_order: &mut GlyphOrder,
@@ -444,6 +617,7 @@ impl Work<Context, WorkId, Error> for GlyphOrderWork {
             .variant(WorkId::PreliminaryGlyphOrder)
             .variant(WorkId::GlobalMetrics)
             .variant(WorkId::ALL_GLYPHS)
+            .variant(WorkId::ALL_ANCHORS)
             .build()
     }
 
@@ -451,15 +625,42 @@ impl Work<Context, WorkId, Error> for GlyphOrderWork {
         AccessBuilder::new()
             .variant(WorkId::GlyphOrder)
             .variant(WorkId::ALL_GLYPHS)
+            .variant(WorkId::ALL_ANCHORS)
+            .variant(WorkId::VariableComposites)
             .build()
     }
 
+    fn also_completes(&self) -> Vec<WorkId> {
+        vec![WorkId::VariableComposites]
+    }
+
     fn exec(&self, context: &Context) -> Result<(), Error> {
         // We should now have access to *all* the glyph IR
         // Some of it may need to be massaged to produce BE glyphs
         // In particular, glyphs with both paths and components need to push the path into a component
         let arc_current = context.preliminary_glyph_order.get();
         let current_glyph_order = &*arc_current;
+
+        // Variable components are only for VARC, everything else sees their outlines
+        context
+            .variable_composites
+            .set(decompose_variable_components(context, current_glyph_order)?);
+
+        // Sources that declare propagation decide when it runs, otherwise it goes first
+        if context.flags.contains(Flags::PROPAGATE_ANCHORS)
+            && !context
+                .static_metadata
+                .get()
+                .glyph_filters
+                .iter()
+                .any(|filter| filter.kind == GlyphFilterKind::PropagateAnchors)
+        {
+            propagate_anchors(context, current_glyph_order, &GlyphSelection::All)?;
+        }
+
+        // Filters the source asks to run before anything else touches the glyphs
+        apply_glyph_filters(context, current_glyph_order, true)?;
+
         let original_glyphs: HashMap<_, _> = current_glyph_order
             .names()
             .map(|gn| (gn, context.get_glyph(gn.clone())))
@@ -515,6 +716,7 @@ impl Work<Context, WorkId, Error> for GlyphOrderWork {
         drop(original_glyphs); // lets not accidentally use that from here on
 
         apply_optional_transformations(context, &new_glyph_order)?;
+        apply_glyph_filters(context, &new_glyph_order, false)?;
 
         // Resolve component references to glyphs that are not retained by conversion to contours
         // Glyphs have to have consistent components at this point so it's safe to just check the default
@@ -556,11 +758,19 @@ impl Work<Context, WorkId, Error> for GlyphOrderWork {
 mod tests {
     use std::{collections::HashSet, path::Path};
 
-    use fontdrasil::{orchestration::Access, types::GlyphName};
+    use fontdrasil::{
+        coords::{CoordConverter, UserCoord},
+        orchestration::Access,
+        types::GlyphName,
+    };
     use kurbo::{Affine, BezPath};
+    use write_fonts::types::Tag;
 
     use crate::{
-        ir::{Component, Glyph, GlyphBuilder, GlyphInstance, GlyphOrder},
+        ir::{
+            Component, DecomposedTransform, Glyph, GlyphBuilder, GlyphInstance, GlyphOrder,
+            StaticMetadata, VariableComponent,
+        },
         orchestration::{Context, Flags, WorkId},
         paths::Paths,
     };
@@ -572,6 +782,8 @@ mod tests {
             components: vec![Component {
                 base: "component".into(),
                 transform: Affine::translate((3.0, 3.0)),
+                variable: None,
+                anchor: None,
             }],
             ..Default::default()
         }
@@ -721,7 +933,12 @@ mod tests {
 
     fn component_glyph(name: &str, base: GlyphName, transform: Affine) -> Glyph {
         let component = GlyphInstance {
-            components: vec![Component { base, transform }],
+            components: vec![Component {
+                base,
+                transform,
+                variable: None,
+                anchor: None,
+            }],
             ..Default::default()
         };
         let mut glyph = GlyphBuilder::new(name.into());
@@ -825,14 +1042,20 @@ mod tests {
                         Component {
                             base: test_data.shallow_component.name.clone(),
                             transform: Affine::IDENTITY,
+                            variable: None,
+                            anchor: None,
                         },
                         Component {
                             base: test_data.shallow_component.name,
                             transform: Affine::translate((0.0, 2.0)),
+                            variable: None,
+                            anchor: None,
                         },
                         Component {
                             base: test_data.deep_component.name,
                             transform: Affine::translate((0.0, 5.0)),
+                            variable: None,
+                            anchor: None,
                         },
                     ],
                     contours: vec![contour()],
@@ -884,6 +1107,8 @@ mod tests {
                     components: vec![Component {
                         base: reuse_me.name.clone(),
                         transform: the_neg,
+                        variable: None,
+                        anchor: None,
                     }],
                     ..Default::default()
                 },
@@ -1045,6 +1270,19 @@ mod tests {
         assert_is_flattened_component(&context, test_data.deep_component.name);
     }
 
+    #[test]
+    fn cff_outlines_decompose_all_components() {
+        let test_data = deep_component();
+        let mut context = test_context();
+        context.flags.set(Flags::CFF_OUTLINES, true);
+        test_data.write_to(&context);
+
+        apply_optional_transformations(&context, &test_data.glyph_order()).unwrap();
+
+        assert_is_simple_glyph(&context, test_data.shallow_component.name);
+        assert_is_simple_glyph(&context, test_data.deep_component.name);
+    }
+
     #[derive(Default)]
     struct GlyphOrderBuilder(Vec<Arc<Glyph>>);
 
@@ -1056,6 +1294,8 @@ mod tests {
                     .map(|name| Component {
                         base: name.into(),
                         transform: Default::default(),
+                        variable: None,
+                        anchor: None,
                     })
                     .collect(),
                 ..Default::default()
@@ -1109,4 +1349,128 @@ mod tests {
 
         assert_eq!(fix_order, ["b", "d", "e", "c", "a"]);
     }
+
+    fn wght(value: f64) -> NormalizedLocation {
+        NormalizedLocation::for_pos(&[("wght", value)])
+    }
+
+    fn bar(width: f64) -> GlyphInstance {
+        let mut path = BezPath::new();
+        path.move_to((0.0, 0.0));
+        path.line_to((width, 0.0));
+        path.line_to((width, 100.0));
+        path.line_to((0.0, 100.0));
+        path.close_path();
+        GlyphInstance {
+            contours: vec![path],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn decompose_variable_components_to_contours() {
+        let context = test_context();
+        let (min, default, max) = (
+            UserCoord::new(400.0),
+            UserCoord::new(400.0),
+            UserCoord::new(700.0),
+        );
+        let axis = Axis {
+            name: "Weight".to_string(),
+            tag: Tag::new(b"wght"),
+            hidden: false,
+            min,
+            default,
+            max,
+            converter: CoordConverter::unmapped(min, default, max),
+        };
+        context.static_metadata.set(
+            StaticMetadata::new(
+                1000,
+                Default::default(),
+                vec![axis],
+                Default::default(),
+                HashSet::from([wght(0.0), wght(1.0)]),
+                Default::default(),
+                Default::default(),
+                Default::default(),
+                None,
+            )
+            .unwrap(),
+        );
+
+        // a bar that gets wider as weight increases
+        let mut base = GlyphBuilder::new("bar".into());
+        base.try_add_source(&wght(0.0), bar(10.0)).unwrap();
+        base.try_add_source(&wght(1.0), bar(30.0)).unwrap();
+        context.glyphs.set(base.build().unwrap());
+
+        // one bar pinned at half weight, one that follows the weight of the composite
+        let pinned = DecomposedTransform {
+            translate_x: 100.0,
+            ..Default::default()
+        };
+        let mut composite = GlyphBuilder::new("bars".into());
+        composite
+            .try_add_source(
+                &wght(0.0),
+                GlyphInstance {
+                    components: vec![
+                        Component {
+                            base: "bar".into(),
+                            transform: pinned.to_affine(),
+                            variable: Some(VariableComponent::new(wght(0.5), pinned)),
+                            anchor: None,
+                        },
+                        Component {
+                            base: "bar".into(),
+                            transform: Affine::IDENTITY,
+                            variable: None,
+                            anchor: None,
+                        },
+                    ],
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        let composite = composite.build().unwrap();
+        context.glyphs.set(composite.clone());
+
+        let mut glyph_order = GlyphOrder::new();
+        glyph_order.insert("bar".into());
+        glyph_order.insert("bars".into());
+        let originals = decompose_variable_components(&context, &glyph_order).unwrap();
+        assert_eq!(
+            vec![&composite],
+            originals.glyphs.values().collect::<Vec<_>>()
+        );
+
+        // The decomposed glyph varies where the bar does even though the composite doesn't
+        let decomposed = context.get_glyph("bars");
+        assert_simple(&decomposed);
+        let svg = |location: &NormalizedLocation| {
+            decomposed.sources()[location]
+                .contours
+                .iter()
+                .map(|c| c.to_svg())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            vec![
+                "M100,0 L120,0 L120,100 L100,100 Z",
+                "M0,0 L10,0 L10,100 L0,100 Z"
+            ],
+            svg(&wght(0.0))
+        );
+        assert_eq!(
+            vec![
+                "M100,0 L120,0 L120,100 L100,100 Z",
+                "M0,0 L30,0 L30,100 L0,100 Z"
+            ],
+            svg(&wght(1.0))
+        );
+
+        // Glyphs without variable components are untouched
+        assert_eq!(2, context.get_glyph("bar").sources().len());
+    }
 }
@@ -0,0 +1,590 @@
+//! Reuse of IR written down by a prior compilation.
+//!
+//! When IR is emitted the build directory holds the output of the last compilation.
+//! We record a [Fingerprint] of each source input alongside it so the next compilation
+//! into the same directory can restore whatever has unchanged inputs instead of recomputing it.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fmt::Display,
+    fs,
+    hash::{DefaultHasher, Hash, Hasher},
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use fontdrasil::{
+    coords::NormalizedLocation,
+    orchestration::{Access, Work},
+    types::GlyphName,
+};
+use log::{debug, warn};
+use parking_lot::RwLock;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    error::{BadSource, Error},
+    ir::{GlyphOrder, KerningGroups},
+    orchestration::{Context, Flags, IrWork, WorkId},
+    paths::Paths,
+};
+
+/// A hash of the inputs to some part of a compilation.
+///
+/// Only meaningful when compared to a fingerprint produced by the same compiler.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Fingerprint(u64);
+
+impl Fingerprint {
+    pub fn of(value: &impl Hash) -> Fingerprint {
+        let mut fingerprinter = Fingerprinter::default();
+        fingerprinter.add(value);
+        fingerprinter.finish()
+    }
+}
+
+/// Accumulates the inputs that make up a [Fingerprint]
+#[derive(Default)]
+pub struct Fingerprinter(DefaultHasher);
+
+impl Fingerprinter {
+    pub fn add(&mut self, value: &impl Hash) -> &mut Self {
+        value.hash(&mut self.0);
+        self
+    }
+
+    /// Add the path and content of a file
+    pub fn add_file(&mut self, path: &Path) -> Result<&mut Self, Error> {
+        let content = fs::read(path).map_err(|e| BadSource::new(path, e))?;
+        path.hash(&mut self.0);
+        content.hash(&mut self.0);
+        Ok(self)
+    }
+
+    pub fn finish(&self) -> Fingerprint {
+        Fingerprint(self.0.finish())
+    }
+}
+
+/// Fingerprints of the inputs to a compilation, grouped by what they feed.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct Fingerprints {
+    /// Inputs that can affect any glyph or kerning, such as axes and masters.
+    ///
+    /// Inputs that only feed work that always reruns, such as features, can be left out.
+    pub global: Fingerprint,
+    /// Inputs that only affect kerning
+    pub kerning: Fingerprint,
+    /// The inputs for each glyph, keyed by glyph name
+    pub glyphs: BTreeMap<GlyphName, Fingerprint>,
+}
+
+/// What we know about the compilation that produced the IR in a build directory
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct BuildState {
+    /// Identifies the compiler, nothing is reused across compilers
+    pub compiler: String,
+    /// The bits of the [Flags] the compilation ran with
+    pub flags: u32,
+    pub fingerprints: Fingerprints,
+    /// The glyphs each glyph used as a component, as read from source
+    pub components: BTreeMap<GlyphName, BTreeSet<GlyphName>>,
+    /// Glyphs whose IR was rewritten when the glyph order was finalized
+    ///
+    /// What was written down for them isn't what source produced so it can't be reused.
+    pub rewritten: BTreeSet<GlyphName>,
+}
+
+impl BuildState {
+    /// Remove and return the state of the prior compilation, if there was one.
+    ///
+    /// The state is only valid for the IR it was written with so it's removed before
+    /// a compilation starts to overwrite that IR. Unreadable state is treated as absent.
+    pub fn take(paths: &Paths) -> Result<Option<BuildState>, Error> {
+        let file = paths.build_state_file();
+        if !file.is_file() {
+            return Ok(None);
+        }
+        let state = read_yaml(file);
+        fs::remove_file(file).map_err(|source| Error::DeleteFailed {
+            path: file.to_path_buf(),
+            source,
+        })?;
+        Ok(state)
+    }
+
+    pub fn save(&self, paths: &Paths) -> Result<(), Error> {
+        let file = paths.build_state_file();
+        let yaml = serde_yaml::to_string(self).expect("build state is always serializable");
+        fs::write(file, yaml).map_err(|source| Error::WriteFailed {
+            path: file.to_path_buf(),
+            source,
+        })
+    }
+}
+
+fn read_yaml<T: DeserializeOwned>(file: &Path) -> Option<T> {
+    let raw = match fs::read(file) {
+        Ok(raw) => raw,
+        Err(e) => {
+            warn!("Unable to read {file:?}, it will not be reused: {e}");
+            return None;
+        }
+    };
+    match serde_yaml::from_slice(&raw) {
+        Ok(value) => Some(value),
+        Err(e) => {
+            warn!("Unable to parse {file:?}, it will not be reused: {e}");
+            None
+        }
+    }
+}
+
+/// What a compilation can take from the prior compilation into the same build directory.
+#[derive(Debug, Default)]
+pub struct ReusePlan {
+    glyphs: HashSet<GlyphName>,
+    kerning: bool,
+    glyph_order: Option<Arc<GlyphOrder>>,
+    kerning_groups: Option<Arc<KerningGroups>>,
+}
+
+impl ReusePlan {
+    /// Plan reuse of the IR written to paths by the prior compilation.
+    ///
+    /// Takes the record of the prior compilation, see [BuildState::take].
+    pub fn load(
+        paths: &Paths,
+        compiler: &str,
+        flags: Flags,
+        fingerprints: &Fingerprints,
+    ) -> Result<ReusePlan, Error> {
+        let Some(prior) = BuildState::take(paths)? else {
+            return Ok(ReusePlan::default());
+        };
+        let mut plan = ReusePlan::new(&prior, compiler, flags, fingerprints);
+        if plan.is_empty() {
+            return Ok(plan);
+        }
+
+        // Whatever a rerun reads from these must match what the prior compilation read,
+        // grab them before this compilation overwrites them
+        plan.glyph_order = read_yaml(&paths.target_file(&WorkId::GlyphOrder)).map(Arc::new);
+        plan.kerning_groups = read_yaml(&paths.target_file(&WorkId::KerningGroups)).map(Arc::new);
+        plan.kerning &= plan.glyph_order.is_some() && plan.kerning_groups.is_some();
+        Ok(plan)
+    }
+
+    /// Plan reuse given the state of the prior compilation and the inputs to this one.
+    ///
+    /// A glyph can be reused if its inputs are unchanged, as are those of every glyph
+    /// it uses as a component. Kerning can be reused if its inputs are unchanged.
+    /// Nothing is reused if anything global changed.
+    pub fn new(
+        prior: &BuildState,
+        compiler: &str,
+        flags: Flags,
+        fingerprints: &Fingerprints,
+    ) -> ReusePlan {
+        if prior.compiler != compiler
+            || prior.flags != flags.bits()
+            || prior.fingerprints.global != fingerprints.global
+        {
+            debug!("Global inputs changed, nothing can be reused");
+            return ReusePlan::default();
+        }
+
+        let mut reusable = HashMap::new();
+        for glyph_name in fingerprints.glyphs.keys() {
+            is_reusable(
+                prior,
+                fingerprints,
+                glyph_name,
+                &mut Vec::new(),
+                &mut reusable,
+            );
+        }
+
+        ReusePlan {
+            glyphs: reusable
+                .into_iter()
+                .filter_map(|(glyph_name, reusable)| reusable.then_some(glyph_name))
+                .collect(),
+            kerning: prior.fingerprints.kerning == fingerprints.kerning
+                && prior.fingerprints.glyphs.len() == fingerprints.glyphs.len()
+                && prior
+                    .fingerprints
+                    .glyphs
+                    .keys()
+                    .all(|glyph_name| fingerprints.glyphs.contains_key(glyph_name)),
+            ..Default::default()
+        }
+    }
+
+    /// True if nothing can be reused
+    pub fn is_empty(&self) -> bool {
+        self.glyphs.is_empty() && !self.kerning
+    }
+
+    pub fn reuses_glyph(&self, glyph_name: &GlyphName) -> bool {
+        self.glyphs.contains(glyph_name)
+    }
+
+    pub fn reuses_kerning(&self) -> bool {
+        self.kerning
+    }
+
+    /// The final glyph order of the prior compilation
+    pub fn glyph_order(&self) -> Option<Arc<GlyphOrder>> {
+        self.glyph_order.clone()
+    }
+
+    /// Wrap glyph IR work such that it restores the prior IR for the glyph if it can.
+    pub fn restore_glyph_work(&self, work: Box<IrWork>, reuse: Arc<Reuse>) -> Box<IrWork> {
+        Box::new(RestoreGlyphWork { work, reuse })
+    }
+
+    /// Wrap kerning instance work such that it restores the prior IR for the location if it can.
+    pub fn restore_kerning_instance_work(
+        &self,
+        work: Box<IrWork>,
+        reuse: Arc<Reuse>,
+    ) -> Box<IrWork> {
+        Box::new(RestoreKerningInstanceWork {
+            work,
+            glyph_order: self.glyph_order.clone().unwrap_or_default(),
+            kerning_groups: self.kerning_groups.clone().unwrap_or_default(),
+            reuse,
+        })
+    }
+}
+
+fn is_reusable(
+    prior: &BuildState,
+    fingerprints: &Fingerprints,
+    glyph_name: &GlyphName,
+    visiting: &mut Vec<GlyphName>,
+    reusable: &mut HashMap<GlyphName, bool>,
+) -> bool {
+    if let Some(result) = reusable.get(glyph_name) {
+        return *result;
+    }
+    // A cycle of components is an error we want to see reported
+    if visiting.contains(glyph_name) {
+        return false;
+    }
+
+    let unchanged = fingerprints.glyphs.contains_key(glyph_name)
+        && prior.fingerprints.glyphs.get(glyph_name) == fingerprints.glyphs.get(glyph_name)
+        && !prior.rewritten.contains(glyph_name);
+    let result = unchanged && {
+        visiting.push(glyph_name.clone());
+        let components_reusable = prior
+            .components
+            .get(glyph_name)
+            .into_iter()
+            .flatten()
+            .all(|component| is_reusable(prior, fingerprints, component, visiting, reusable));
+        visiting.pop();
+        components_reusable
+    };
+    reusable.insert(glyph_name.clone(), result);
+    result
+}
+
+/// Tracks what a compilation restored from the prior compilation
+#[derive(Debug, Default)]
+pub struct Reuse {
+    glyphs: RwLock<HashSet<GlyphName>>,
+    kerning_instances: AtomicUsize,
+    glyph_binaries: AtomicUsize,
+}
+
+impl Reuse {
+    /// True if the IR for glyph_name was restored rather than computed
+    pub fn restored_glyph(&self, glyph_name: &GlyphName) -> bool {
+        self.glyphs.read().contains(glyph_name)
+    }
+
+    pub fn glyph_binary_restored(&self) {
+        self.glyph_binaries.fetch_add(1, Ordering::AcqRel);
+    }
+
+    /// Describe what was reused out of how much work of each kind there was
+    pub fn summary(
+        &self,
+        glyphs: usize,
+        kerning_instances: usize,
+        glyph_binaries: usize,
+    ) -> ReuseSummary {
+        ReuseSummary {
+            glyphs: (self.glyphs.read().len(), glyphs),
+            kerning_instances: (
+                self.kerning_instances.load(Ordering::Acquire),
+                kerning_instances,
+            ),
+            glyph_binaries: (self.glyph_binaries.load(Ordering::Acquire), glyph_binaries),
+        }
+    }
+}
+
+/// How much of each kind of work was reused, as (reused, total)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReuseSummary {
+    pub glyphs: (usize, usize),
+    pub kerning_instances: (usize, usize),
+    pub glyph_binaries: (usize, usize),
+}
+
+impl Display for ReuseSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Reused {}/{} glyphs, {}/{} kerning instances, {}/{} binary glyphs",
+            self.glyphs.0,
+            self.glyphs.1,
+            self.kerning_instances.0,
+            self.kerning_instances.1,
+            self.glyph_binaries.0,
+            self.glyph_binaries.1
+        )
+    }
+}
+
+/// Restores [crate::ir::Glyph] and [crate::ir::GlyphAnchors], falling back to running work
+#[derive(Debug)]
+struct RestoreGlyphWork {
+    work: Box<IrWork>,
+    reuse: Arc<Reuse>,
+}
+
+impl Work<Context, WorkId, Error> for RestoreGlyphWork {
+    fn id(&self) -> WorkId {
+        self.work.id()
+    }
+
+    fn read_access(&self) -> Access<WorkId> {
+        self.work.read_access()
+    }
+
+    fn write_access(&self) -> Access<WorkId> {
+        self.work.write_access()
+    }
+
+    fn also_completes(&self) -> Vec<WorkId> {
+        self.work.also_completes()
+    }
+
+    fn exec(&self, context: &Context) -> Result<(), Error> {
+        let WorkId::Glyph(glyph_name) = self.work.id() else {
+            return self.work.exec(context);
+        };
+        if context
+            .glyphs
+            .restore(&WorkId::Glyph(glyph_name.clone()))
+            .is_some()
+            && context
+                .anchors
+                .restore(&WorkId::Anchor(glyph_name.clone()))
+                .is_some()
+        {
+            self.reuse.glyphs.write().insert(glyph_name);
+            return Ok(());
+        }
+        self.work.exec(context)
+    }
+}
+
+/// Restores [crate::ir::KerningInstance], falling back to running work
+#[derive(Debug)]
+struct RestoreKerningInstanceWork {
+    work: Box<IrWork>,
+    glyph_order: Arc<GlyphOrder>,
+    kerning_groups: Arc<KerningGroups>,
+    reuse: Arc<Reuse>,
+}
+
+impl RestoreKerningInstanceWork {
+    fn restore(&self, context: &Context, location: &NormalizedLocation) -> bool {
+        // Pairs are filtered by glyph order and grouped by kerning groups so both must match
+        let glyph_order = context.glyph_order.get();
+        if !glyph_order.names().eq(self.glyph_order.names())
+            || *context.kerning_groups.get() != *self.kerning_groups
+        {
+            return false;
+        }
+        context
+            .kerning_at
+            .restore(&WorkId::KernInstance(location.clone()))
+            .is_some()
+    }
+}
+
+impl Work<Context, WorkId, Error> for RestoreKerningInstanceWork {
+    fn id(&self) -> WorkId {
+        self.work.id()
+    }
+
+    fn read_access(&self) -> Access<WorkId> {
+        self.work.read_access()
+    }
+
+    fn write_access(&self) -> Access<WorkId> {
+        self.work.write_access()
+    }
+
+    fn also_completes(&self) -> Vec<WorkId> {
+        self.work.also_completes()
+    }
+
+    fn exec(&self, context: &Context) -> Result<(), Error> {
+        if let WorkId::KernInstance(location) = self.work.id() {
+            if self.restore(context, &location) {
+                self.reuse.kerning_instances.fetch_add(1, Ordering::AcqRel);
+                return Ok(());
+            }
+        }
+        self.work.exec(context)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn glyph_fingerprints(glyphs: &[(&str, u64)]) -> Fingerprints {
+        Fingerprints {
+            global: Fingerprint(1),
+            kerning: Fingerprint(2),
+            glyphs: glyphs
+                .iter()
+                .map(|(name, fp)| (GlyphName::new(name), Fingerprint(*fp)))
+                .collect(),
+        }
+    }
+
+    fn prior_state(glyphs: &[(&str, u64)], components: &[(&str, &str)]) -> BuildState {
+        let mut state = BuildState {
+            compiler: "test".to_string(),
+            flags: Flags::default().bits(),
+            fingerprints: glyph_fingerprints(glyphs),
+            ..Default::default()
+        };
+        for (glyph, component) in components {
+            state
+                .components
+                .entry(GlyphName::new(glyph))
+                .or_default()
+                .insert(GlyphName::new(component));
+        }
+        state
+    }
+
+    fn reused_glyphs(plan: &ReusePlan) -> Vec<&str> {
+        let mut glyphs: Vec<_> = plan.glyphs.iter().map(|g| g.as_str()).collect();
+        glyphs.sort();
+        glyphs
+    }
+
+    #[test]
+    fn fingerprint_is_stable() {
+        assert_eq!(Fingerprint::of(&"abc"), Fingerprint::of(&"abc"));
+        assert_ne!(Fingerprint::of(&"abc"), Fingerprint::of(&"abd"));
+    }
+
+    #[test]
+    fn reuse_unchanged_glyphs() {
+        let prior = prior_state(&[("a", 1), ("b", 2), ("c", 3)], &[]);
+        let plan = ReusePlan::new(
+            &prior,
+            "test",
+            Flags::default(),
+            &glyph_fingerprints(&[("a", 1), ("b", 20), ("c", 3)]),
+        );
+        assert_eq!(vec!["a", "c"], reused_glyphs(&plan));
+        assert!(plan.reuses_kerning());
+    }
+
+    #[test]
+    fn component_change_invalidates_composite() {
+        // aacute uses acutecomb which uses dot; dot changes
+        let prior = prior_state(
+            &[("a", 1), ("aacute", 2), ("acutecomb", 3), ("dot", 4)],
+            &[
+                ("aacute", "a"),
+                ("aacute", "acutecomb"),
+                ("acutecomb", "dot"),
+            ],
+        );
+        let plan = ReusePlan::new(
+            &prior,
+            "test",
+            Flags::default(),
+            &glyph_fingerprints(&[("a", 1), ("aacute", 2), ("acutecomb", 3), ("dot", 40)]),
+        );
+        assert_eq!(vec!["a"], reused_glyphs(&plan));
+    }
+
+    #[test]
+    fn rewritten_glyphs_are_not_reused() {
+        let mut prior = prior_state(&[("a", 1), ("b", 2)], &[]);
+        prior.rewritten.insert(GlyphName::new("b"));
+        let plan = ReusePlan::new(
+            &prior,
+            "test",
+            Flags::default(),
+            &glyph_fingerprints(&[("a", 1), ("b", 2)]),
+        );
+        assert_eq!(vec!["a"], reused_glyphs(&plan));
+    }
+
+    #[test]
+    fn component_cycle_is_not_reused() {
+        let prior = prior_state(&[("a", 1), ("b", 2)], &[("a", "b"), ("b", "a")]);
+        let plan = ReusePlan::new(
+            &prior,
+            "test",
+            Flags::default(),
+            &glyph_fingerprints(&[("a", 1), ("b", 2)]),
+        );
+        assert!(reused_glyphs(&plan).is_empty());
+    }
+
+    #[test]
+    fn glyph_set_change_invalidates_kerning() {
+        let prior = prior_state(&[("a", 1), ("b", 2)], &[]);
+        let plan = ReusePlan::new(
+            &prior,
+            "test",
+            Flags::default(),
+            &glyph_fingerprints(&[("a", 1), ("b", 2), ("c", 3)]),
+        );
+        assert_eq!(vec!["a", "b"], reused_glyphs(&plan));
+        assert!(!plan.reuses_kerning());
+    }
+
+    #[test]
+    fn global_change_reuses_nothing() {
+        let prior = prior_state(&[("a", 1)], &[]);
+        let mut fingerprints = glyph_fingerprints(&[("a", 1)]);
+        fingerprints.global = Fingerprint(99);
+        assert!(ReusePlan::new(&prior, "test", Flags::default(), &fingerprints).is_empty());
+    }
+
+    #[test]
+    fn compiler_or_flag_change_reuses_nothing() {
+        let prior = prior_state(&[("a", 1)], &[]);
+        let fingerprints = glyph_fingerprints(&[("a", 1)]);
+        assert!(ReusePlan::new(&prior, "other", Flags::default(), &fingerprints).is_empty());
+        assert!(ReusePlan::new(
+            &prior,
+            "test",
+            Flags::default() | Flags::KEEP_DIRECTION,
+            &fingerprints
+        )
+        .is_empty());
+    }
+}
@@ -0,0 +1,1630 @@
+//! Produce the IR for an instance of a variable font.
+//!
+//! The IR of a completed variable font compile is interpolated at the location of a
+//! [NamedInstance], much as fontmake does when asked for static instances, or restricted
+//! to a part of its variation space, as the fontTools
+//! [instancer](https://github.com/fonttools/fonttools/blob/main/Lib/fontTools/varLib/instancer/__init__.py)
+//! does for partial instances. The result is exposed as a [Source] so the usual workload
+//! can compile it.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fmt::Display,
+    iter,
+    sync::Arc,
+};
+
+use fontdrasil::{
+    coords::{CoordConverter, NormalizedCoord, NormalizedLocation, UserCoord, UserLocation},
+    orchestration::{Access, AccessBuilder, Work},
+    types::{Axis, GlyphName, WidthClass},
+};
+use kurbo::{Affine, BezPath, PathEl, Point};
+use log::{debug, trace};
+use ordered_float::OrderedFloat;
+use serde::{Deserialize, Serialize};
+use smol_str::SmolStr;
+use write_fonts::{
+    tables::os2::SelectionFlags,
+    types::{NameId, Tag},
+    OtRound,
+};
+
+use crate::{
+    error::Error,
+    incremental::Fingerprints,
+    ir::{
+        self, Anchor, AxisValueLabel, AxisValueLocation, ColorGlyph, ColorPalettes, Condition,
+        ConditionSet, ConditionalSubstitution, FeatureVariations, FeaturesSource, GlobalMetrics,
+        Glyph, GlyphAnchors, GlyphInstance, GlyphOrder, KerningGroups, KerningInstance,
+        MiscMetadata, NameBuilder, NameKey, NamedInstance, Paint, PaintGraph, StatLabels,
+        StaticMetadata,
+    },
+    orchestration::{Context, IrWork, WorkId},
+    source::Source,
+    variations::VariationModel,
+};
+
+/// The IR of a variable font that has finished compiling.
+///
+/// Captured from a [Context] so it can be instanced any number of times.
+#[derive(Debug)]
+pub struct VariableIr {
+    static_metadata: Arc<StaticMetadata>,
+    glyph_order: Arc<GlyphOrder>,
+    global_metrics: Arc<GlobalMetrics>,
+    glyphs: HashMap<GlyphName, Arc<Glyph>>,
+    anchors: HashMap<GlyphName, Arc<GlyphAnchors>>,
+    features: Option<Arc<FeaturesSource>>,
+    feature_variations: Option<Arc<FeatureVariations>>,
+    kerning_groups: Option<Arc<KerningGroups>>,
+    kerning: Vec<Arc<KerningInstance>>,
+    colors: Option<Arc<ColorPalettes>>,
+    paint_graph: Option<Arc<PaintGraph>>,
+}
+
+impl VariableIr {
+    /// Capture the IR in a context that has completed the front end.
+    ///
+    /// Features and kerning are optional as they are not produced when features are skipped.
+    pub fn new(context: &Context) -> Self {
+        let ir_name = |id: WorkId| match id {
+            WorkId::Glyph(name) | WorkId::Anchor(name) => name,
+            _ => unreachable!("only glyphs and anchors are keyed by name"),
+        };
+        VariableIr {
+            static_metadata: context.static_metadata.get(),
+            glyph_order: context.glyph_order.get(),
+            global_metrics: context.global_metrics.get(),
+            glyphs: context
+                .glyphs
+                .all()
+                .into_iter()
+                .map(|(id, glyph)| (ir_name(id), glyph))
+                .collect(),
+            anchors: context
+                .anchors
+                .all()
+                .into_iter()
+                .map(|(id, anchors)| (ir_name(id), anchors))
+                .collect(),
+            features: context.features.try_get(),
+            feature_variations: context.feature_variations.try_get(),
+            kerning_groups: context.kerning_groups.try_get(),
+            kerning: context
+                .kerning_at
+                .all()
+                .into_iter()
+                .map(|(_, kerning)| kerning)
+                .collect(),
+            colors: context.colors.try_get(),
+            paint_graph: context.paint_graph.try_get(),
+        }
+    }
+
+    pub fn static_metadata(&self) -> &StaticMetadata {
+        &self.static_metadata
+    }
+}
+
+/// How much of an axis a partial instance keeps, in user space.
+///
+/// The axis is pinned, and thus removed, if min and max are the same.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AxisLimit {
+    pub tag: Tag,
+    pub min: UserCoord,
+    pub max: UserCoord,
+}
+
+/// Where an axis of the variable font may be in an instance, in its normalized space.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct NormalizedLimit {
+    min: NormalizedCoord,
+    default: NormalizedCoord,
+    max: NormalizedCoord,
+}
+
+impl NormalizedLimit {
+    fn pinned(at: NormalizedCoord) -> Self {
+        NormalizedLimit {
+            min: at,
+            default: at,
+            max: at,
+        }
+    }
+
+    fn is_pinned(&self) -> bool {
+        self.min == self.max
+    }
+
+    fn clamp(&self, coord: NormalizedCoord) -> NormalizedCoord {
+        coord.max(self.min).min(self.max)
+    }
+
+    /// Where coord, which must be within our limits, is in the normalized space of the instance.
+    ///
+    /// The default of the instance is the variable font default clamped to our limits so
+    /// the latter is never strictly between the default and either limit. Normalization is
+    /// linear on either side of the default, hence so is the mapping from one to the other.
+    fn renormalize(&self, coord: NormalizedCoord) -> NormalizedCoord {
+        let (coord, default) = (coord.to_f64(), self.default.to_f64());
+        let value = if coord > default {
+            (coord - default) / (self.max.to_f64() - default)
+        } else if coord < default {
+            (coord - default) / (default - self.min.to_f64())
+        } else {
+            0.0
+        };
+        NormalizedCoord::new(value)
+    }
+}
+
+/// The limits of every variable axis of the variable font, by tag.
+#[derive(Debug)]
+struct Limits(BTreeMap<Tag, NormalizedLimit>);
+
+impl Limits {
+    /// Axes we know nothing of are taken to be pinned at their default
+    fn get(&self, tag: Tag) -> NormalizedLimit {
+        self.0
+            .get(&tag)
+            .copied()
+            .unwrap_or_else(|| NormalizedLimit::pinned(NormalizedCoord::new(0.0)))
+    }
+
+    /// Where a location of the variable font, which must be within our limits, is in the instance.
+    fn instance_location(&self, location: &NormalizedLocation) -> NormalizedLocation {
+        self.0
+            .iter()
+            .filter(|(_, limit)| !limit.is_pinned())
+            .map(|(tag, limit)| {
+                (
+                    *tag,
+                    limit.renormalize(location.get(*tag).unwrap_or_default()),
+                )
+            })
+            .collect()
+    }
+
+    /// The locations of the instance that values at locations of the variable font become.
+    ///
+    /// Each is mapped to the location of the variable font to interpolate at. Locations
+    /// outside our limits are clamped to them and the default of the instance is always present.
+    fn locations<'a>(
+        &self,
+        variable_locations: impl IntoIterator<Item = &'a NormalizedLocation>,
+    ) -> BTreeMap<NormalizedLocation, NormalizedLocation> {
+        let default: NormalizedLocation = self
+            .0
+            .iter()
+            .map(|(tag, limit)| (*tag, limit.default))
+            .collect();
+        variable_locations
+            .into_iter()
+            .map(|location| self.clamp(location))
+            .chain(iter::once(default))
+            .map(|location| (self.instance_location(&location), location))
+            .collect()
+    }
+
+    /// The nearest location to location within our limits.
+    fn clamp(&self, location: &NormalizedLocation) -> NormalizedLocation {
+        self.0
+            .iter()
+            .map(|(tag, limit)| {
+                let coord = location.get(*tag).unwrap_or_default();
+                (*tag, limit.clamp(coord))
+            })
+            .collect()
+    }
+
+    /// The part of a condition set within our limits, None if it can't be met.
+    fn condition_set(&self, condition_set: &ConditionSet) -> Option<ConditionSet> {
+        let mut conditions = Vec::new();
+        for condition in condition_set.iter() {
+            let limit = self.get(condition.axis);
+            let min = condition.min.max(limit.min);
+            let max = condition.max.min(limit.max);
+            if min > max {
+                return None;
+            }
+            // A condition on a pinned axis is met everywhere or nowhere
+            if !limit.is_pinned() {
+                conditions.push(Condition {
+                    axis: condition.axis,
+                    min: limit.renormalize(min),
+                    max: limit.renormalize(max),
+                });
+            }
+        }
+        Some(ConditionSet::new(conditions))
+    }
+}
+
+/// Everything the works of an [InstanceSource] share.
+#[derive(Debug)]
+struct Instance {
+    variable: Arc<VariableIr>,
+    limits: Limits,
+    static_metadata: StaticMetadata,
+}
+
+/// A [Source] that produces the IR for an instance of a variable font.
+pub struct InstanceSource {
+    instance: Arc<Instance>,
+}
+
+impl InstanceSource {
+    /// Create a source for the static font at the location of named_instance.
+    pub fn new(variable: Arc<VariableIr>, named_instance: &NamedInstance) -> Result<Self, Error> {
+        let var_metadata = variable.static_metadata();
+        let axes_by_tag: HashMap<_, _> = var_metadata.axes.iter().map(|a| (a.tag, a)).collect();
+
+        // Fill in the default for any axis the instance doesn't specify
+        let mut user_location = named_instance.location.clone();
+        user_location.retain(|tag, _| axes_by_tag.contains_key(tag));
+        for axis in var_metadata.axes.iter() {
+            if !user_location.contains(axis.tag) {
+                user_location.insert(axis.tag, axis.default);
+            }
+        }
+        // Sources are located in the space axis mappings produce, as are we
+        let location =
+            var_metadata.apply_axis_mappings(&user_location.to_normalized(&axes_by_tag))?;
+        let limits = Limits(
+            var_metadata
+                .axes
+                .iter()
+                .map(|axis| {
+                    let at = location.get(axis.tag).unwrap_or_default();
+                    (axis.tag, NormalizedLimit::pinned(at))
+                })
+                .collect(),
+        );
+
+        let names = instance_names(var_metadata, &named_instance.name);
+
+        let mut misc = var_metadata.misc.clone();
+        misc.selection_flags =
+            instance_selection_flags(var_metadata.misc.selection_flags, &named_instance.name);
+        set_weight_and_width_class(&mut misc, &user_location);
+
+        let mut static_metadata = StaticMetadata::new(
+            var_metadata.units_per_em,
+            names,
+            Vec::new(),
+            Vec::new(),
+            HashSet::from([NormalizedLocation::new()]),
+            var_metadata.postscript_names.clone(),
+            var_metadata.italic_angle.into_inner(),
+            var_metadata.gdef_categories.clone(),
+            None,
+        )?;
+        static_metadata.misc = misc;
+        static_metadata.variation_sequences = var_metadata.variation_sequences.clone();
+        debug!("Instance '{}' at {location:?}", named_instance.name);
+
+        InstanceSource::from_instance(Instance {
+            variable: variable.clone(),
+            limits,
+            static_metadata,
+        })
+    }
+
+    /// Create a source for the static font at the default location of the variable font.
+    ///
+    /// An axis in axis_limits is pinned at the value its limit allows that is closest to
+    /// the default. The static font is named for the style of the variable font.
+    pub fn default_instance(
+        variable: Arc<VariableIr>,
+        axis_limits: &[AxisLimit],
+    ) -> Result<Self, Error> {
+        let var_metadata = variable.static_metadata();
+        if let Some(limit) = axis_limits
+            .iter()
+            .find(|limit| var_metadata.axis(&limit.tag).is_none())
+        {
+            return Err(Error::NoAxisToLimit(limit.tag));
+        }
+        let location = var_metadata
+            .axes
+            .iter()
+            .map(|axis| {
+                // The last limit for an axis wins, as on a command line
+                let at = axis_limits
+                    .iter()
+                    .rev()
+                    .find(|limit| limit.tag == axis.tag)
+                    .map(|limit| axis.default.clamp(limit.min, limit.max))
+                    .unwrap_or(axis.default);
+                (axis.tag, at)
+            })
+            .collect();
+        let name = [NameId::TYPOGRAPHIC_SUBFAMILY_NAME, NameId::SUBFAMILY_NAME]
+            .into_iter()
+            .find_map(|name_id| {
+                var_metadata
+                    .names
+                    .iter()
+                    .find(|(key, _)| key.name_id == name_id)
+                    .map(|(_, name)| name.clone())
+            })
+            .unwrap_or_else(|| "Regular".to_string());
+        InstanceSource::new(variable, &NamedInstance { name, location })
+    }
+
+    /// Create a source for the variable font restricted to a range, or pinned at a value,
+    /// on the axes of axis_limits.
+    ///
+    /// Axes without a limit keep their full range. Rather than adjusting deltas, as fontTools
+    /// does, the variable font is interpolated at its master locations clamped to the limits.
+    /// Named instances and STAT labels outside the limits are dropped.
+    pub fn partial(variable: Arc<VariableIr>, axis_limits: &[AxisLimit]) -> Result<Self, Error> {
+        let var_metadata = variable.static_metadata();
+        if let Some(limit) = axis_limits
+            .iter()
+            .find(|limit| var_metadata.axis(&limit.tag).is_none())
+        {
+            return Err(Error::NoAxisToLimit(limit.tag));
+        }
+        if !var_metadata.axis_mappings.is_empty() {
+            return Err(Error::UnsupportedConstruct(
+                "limiting the axes of a font with axis mappings".to_string(),
+            ));
+        }
+
+        let mut limits = BTreeMap::new();
+        let mut axes = Vec::with_capacity(var_metadata.axes.len());
+        for axis in var_metadata.axes.iter() {
+            // The last limit for an axis wins, as on a command line
+            let (min, max) = axis_limits
+                .iter()
+                .rev()
+                .find(|limit| limit.tag == axis.tag)
+                .map(|limit| (limit.min, limit.max))
+                .unwrap_or((axis.min, axis.max));
+            let (axis, limit) = limit_axis(axis, min, max);
+            limits.insert(axis.tag, limit);
+            axes.push(axis);
+        }
+        let limits = Limits(limits);
+
+        // Pinned axes are gone from the user locations of named instances and STAT
+        let pinned: HashMap<_, _> = axes
+            .iter()
+            .filter(|axis| {
+                limits
+                    .0
+                    .get(&axis.tag)
+                    .is_some_and(NormalizedLimit::is_pinned)
+            })
+            .map(|axis| (axis.tag, axis.default))
+            .collect();
+        let within = |tag: &Tag, value: UserCoord| {
+            axes.iter()
+                .find(|axis| axis.tag == *tag)
+                .is_none_or(|axis| (axis.min..=axis.max).contains(&value))
+        };
+        let named_instances = var_metadata
+            .named_instances
+            .iter()
+            .filter(|ni| {
+                var_metadata.axes.iter().all(|axis| {
+                    within(&axis.tag, ni.location.get(axis.tag).unwrap_or(axis.default))
+                })
+            })
+            .map(|ni| {
+                let mut location = ni.location.clone();
+                location.retain(|tag, _| !pinned.contains_key(tag));
+                NamedInstance {
+                    name: ni.name.clone(),
+                    location,
+                }
+            })
+            .collect();
+        let stat_labels = StatLabels {
+            axis_values: var_metadata
+                .stat_labels
+                .axis_values
+                .iter()
+                .filter(|label| label_within(label, within))
+                .cloned()
+                .collect(),
+            elided_fallback_name: var_metadata.stat_labels.elided_fallback_name.clone(),
+        };
+
+        // Names for axes, named instances and labels are claimed afresh as some are gone
+        let names = var_metadata
+            .names
+            .iter()
+            .filter(|(key, _)| key.name_id.to_u16() < 256)
+            .map(|(key, name)| (*key, name.clone()))
+            .collect();
+
+        let mut misc = var_metadata.misc.clone();
+        set_weight_and_width_class(&mut misc, &pinned.into_iter().collect());
+
+        // Locations of the instance only name the axes that still vary, so its default
+        // location must too; pinned and point axes are no longer of any use.
+        axes.retain(|axis| !axis.is_point());
+
+        let mut static_metadata = StaticMetadata::new(
+            var_metadata.units_per_em,
+            names,
+            axes,
+            named_instances,
+            limits
+                .locations(var_metadata.variation_model.locations())
+                .into_keys()
+                .collect(),
+            var_metadata.postscript_names.clone(),
+            var_metadata.italic_angle.into_inner(),
+            var_metadata.gdef_categories.clone(),
+            None,
+        )?;
+        static_metadata.misc = misc;
+        static_metadata.variation_sequences = var_metadata.variation_sequences.clone();
+        static_metadata.set_stat_labels(stat_labels);
+        debug!("Partial instance limited to {:?}", limits.0);
+
+        InstanceSource::from_instance(Instance {
+            variable: variable.clone(),
+            limits,
+            static_metadata,
+        })
+    }
+
+    fn from_instance(mut instance: Instance) -> Result<Self, Error> {
+        instance.static_metadata.number_values = instance.number_values()?;
+        Ok(InstanceSource {
+            instance: Arc::new(instance),
+        })
+    }
+
+    /// The [StaticMetadata] of the font this source produces.
+    pub fn static_metadata(&self) -> &StaticMetadata {
+        &self.instance.static_metadata
+    }
+
+    /// The postscript name of the static font, handy for naming the output file.
+    pub fn postscript_name(&self) -> Option<&str> {
+        self.static_metadata()
+            .names
+            .iter()
+            .find(|(key, _)| key.name_id == NameId::POSTSCRIPT_NAME)
+            .map(|(_, name)| name.as_str())
+    }
+}
+
+/// The axis restricted to min..=max, and where that is in the normalized space of the axis.
+///
+/// The default is clamped to the range, as in fontTools. The mapping from user to design
+/// space is kept for the part of the axis that remains.
+fn limit_axis(axis: &Axis, min: UserCoord, max: UserCoord) -> (Axis, NormalizedLimit) {
+    let min = min.max(axis.min).min(axis.max);
+    let max = max.max(min).min(axis.max);
+    let default = axis.default.max(min).min(max);
+
+    let mut mappings: Vec<_> = axis
+        .converter
+        .iter()
+        .map(|(user, design, _)| (user, design))
+        .filter(|(user, _)| (min..=max).contains(user))
+        .chain([min, default, max].map(|user| (user, user.to_design(&axis.converter))))
+        .collect();
+    mappings.sort_by_key(|(user, _)| *user);
+    mappings.dedup_by_key(|(user, _)| *user);
+    let default_idx = mappings
+        .iter()
+        .position(|(user, _)| *user == default)
+        .unwrap();
+
+    let limit = NormalizedLimit {
+        min: min.to_normalized(&axis.converter),
+        default: default.to_normalized(&axis.converter),
+        max: max.to_normalized(&axis.converter),
+    };
+    let axis = Axis {
+        min,
+        default,
+        max,
+        converter: CoordConverter::new(mappings, default_idx),
+        ..axis.clone()
+    };
+    (axis, limit)
+}
+
+/// Whether every position a STAT label names is within the limits, as in fontTools.
+fn label_within(label: &AxisValueLabel, within: impl Fn(&Tag, UserCoord) -> bool) -> bool {
+    match &label.value {
+        AxisValueLocation::Point { axis, value, .. } => within(axis, *value),
+        AxisValueLocation::Range { axis, nominal, .. } => within(axis, *nominal),
+        AxisValueLocation::Location(location) => {
+            location.iter().all(|(tag, value)| within(tag, *value))
+        }
+    }
+}
+
+/// The OS/2 weight and width classes of a font pinned on the wght or wdth axes.
+fn set_weight_and_width_class(misc: &mut MiscMetadata, pinned: &UserLocation) {
+    if let Some(wght) = pinned.get(Tag::new(b"wght")) {
+        misc.us_weight_class = Some(wght.to_f64().clamp(1.0, 1000.0).ot_round());
+    }
+    if let Some(wdth) = pinned.get(Tag::new(b"wdth")) {
+        misc.us_width_class = Some(WidthClass::nearest(wdth.to_f64()) as u16);
+    }
+}
+
+/// Names that are specific to a style and thus rebuilt for an instance
+const INSTANCE_NAME_IDS: [NameId; 8] = [
+    NameId::FAMILY_NAME,
+    NameId::SUBFAMILY_NAME,
+    NameId::UNIQUE_ID,
+    NameId::FULL_NAME,
+    NameId::POSTSCRIPT_NAME,
+    NameId::TYPOGRAPHIC_FAMILY_NAME,
+    NameId::TYPOGRAPHIC_SUBFAMILY_NAME,
+    NameId::VARIATIONS_POSTSCRIPT_NAME_PREFIX,
+];
+
+/// The trailing words of a style name that make up the legacy (RIBBI) subfamily.
+///
+/// Matches how [NameBuilder::make_family_name] strips them from the legacy family name.
+fn ribbi_words(style_name: &str) -> (bool, bool) {
+    let mut bold = false;
+    let mut italic = false;
+    for word in style_name.split_ascii_whitespace().rev() {
+        match word {
+            "Bold" => bold = true,
+            "Italic" => italic = true,
+            "Regular" => (),
+            _ => break,
+        }
+    }
+    (bold, italic)
+}
+
+/// Build the name table for an instance, as ufo2ft would for a static font.
+///
+/// Names that don't depend on the style, such as copyright, are kept. Names for
+/// axes and named instances (ID 256+) are dropped as they are only used by fvar and STAT.
+fn instance_names(var_metadata: &StaticMetadata, style_name: &str) -> HashMap<NameKey, String> {
+    let get = |name_id: NameId| {
+        var_metadata
+            .names
+            .iter()
+            .find(|(key, _)| key.name_id == name_id)
+            .map(|(_, name)| name.as_str())
+    };
+    let family = get(NameId::TYPOGRAPHIC_FAMILY_NAME)
+        .or_else(|| get(NameId::FAMILY_NAME))
+        .or_else(|| ir::default_value(NameId::FAMILY_NAME))
+        .unwrap_or_default()
+        .to_string();
+
+    let mut builder = NameBuilder::default();
+    builder.set_version(
+        var_metadata.misc.version_major,
+        var_metadata.misc.version_minor,
+    );
+    for (key, name) in var_metadata.names.iter() {
+        if key.name_id.to_u16() < 256 && !INSTANCE_NAME_IDS.contains(&key.name_id) {
+            builder.add(key.name_id, name.clone());
+        }
+    }
+
+    let subfamily = match ribbi_words(style_name) {
+        (true, true) => "Bold Italic",
+        (true, false) => "Bold",
+        (false, true) => "Italic",
+        (false, false) => "Regular",
+    };
+    builder.add(
+        NameId::FAMILY_NAME,
+        NameBuilder::make_family_name(&family, style_name, true),
+    );
+    builder.add(NameId::SUBFAMILY_NAME, subfamily.to_string());
+    builder.add(NameId::TYPOGRAPHIC_FAMILY_NAME, family);
+    builder.add(NameId::TYPOGRAPHIC_SUBFAMILY_NAME, style_name.to_string());
+
+    let vendor_id = var_metadata.misc.vendor_id.to_string();
+    builder.apply_default_fallbacks(vendor_id.trim_end());
+    builder.into_inner()
+}
+
+/// The OS/2 fsSelection for an instance.
+///
+/// Bold and italic follow the style name, the latter is also retained if the variable
+/// font is italic. Flags unrelated to the style, such as USE_TYPO_METRICS, are kept.
+fn instance_selection_flags(var_flags: SelectionFlags, style_name: &str) -> SelectionFlags {
+    let (bold, italic) = ribbi_words(style_name);
+    let italic = italic || var_flags.contains(SelectionFlags::ITALIC);
+
+    let mut flags =
+        var_flags - (SelectionFlags::BOLD | SelectionFlags::ITALIC | SelectionFlags::REGULAR);
+    if bold {
+        flags |= SelectionFlags::BOLD;
+    }
+    if italic {
+        flags |= SelectionFlags::ITALIC;
+    }
+    if !bold && !italic {
+        flags |= SelectionFlags::REGULAR;
+    }
+    flags
+}
+
+impl Instance {
+    /// Compute values for the instance from values defined at locations in the variable font.
+    fn interpolate(
+        &self,
+        what: impl Display,
+        values: &HashMap<NormalizedLocation, Vec<f64>>,
+    ) -> Result<HashMap<NormalizedLocation, Vec<f64>>, Error> {
+        self.interpolate_at(what, values, self.limits.locations(values.keys()))
+    }
+
+    /// Compute values at locations of the instance, each mapped to the location of the
+    /// variable font it corresponds to, from values defined at locations in the variable font.
+    ///
+    /// Uses the global model if values are defined at every master location,
+    /// otherwise a model of just the locations that have values.
+    fn interpolate_at(
+        &self,
+        what: impl Display,
+        values: &HashMap<NormalizedLocation, Vec<f64>>,
+        locations: BTreeMap<NormalizedLocation, NormalizedLocation>,
+    ) -> Result<HashMap<NormalizedLocation, Vec<f64>>, Error> {
+        // Nothing to interpolate if we only have the default
+        if let (1, Some((location, values))) = (values.len(), values.iter().next()) {
+            if !location.has_any_non_zero() {
+                return Ok(locations
+                    .into_keys()
+                    .map(|location| (location, values.clone()))
+                    .collect());
+            }
+        }
+
+        let var_metadata = self.variable.static_metadata();
+        let global_model = &var_metadata.variation_model;
+        let sub_model;
+        let model = if values.len() == global_model.num_locations()
+            && values.keys().all(|loc| global_model.supports(loc))
+        {
+            global_model
+        } else {
+            sub_model =
+                VariationModel::new(values.keys().cloned().collect(), var_metadata.axes.clone())?;
+            &sub_model
+        };
+        locations
+            .into_iter()
+            .map(|(location, variable_location)| {
+                model
+                    .interpolate(&variable_location, values)
+                    .map(|values| (location, values))
+                    .map_err(|source| Error::InterpolationFailed {
+                        what: what.to_string(),
+                        source,
+                    })
+            })
+            .collect()
+    }
+
+    /// Glyphs number values, which are defined per master, for the instance.
+    fn number_values(
+        &self,
+    ) -> Result<HashMap<NormalizedLocation, BTreeMap<SmolStr, OrderedFloat<f64>>>, Error> {
+        let number_values = &self.variable.static_metadata().number_values;
+        let names: BTreeSet<_> = number_values.values().flat_map(|v| v.keys()).collect();
+        let mut result: HashMap<_, BTreeMap<_, _>> = HashMap::new();
+        for name in names {
+            let values = number_values
+                .iter()
+                .filter_map(|(loc, values)| {
+                    values
+                        .get(name)
+                        .map(|v| (loc.clone(), vec![v.into_inner()]))
+                })
+                .collect();
+            for (loc, value) in self.interpolate(format_args!("number value '{name}'"), &values)? {
+                result
+                    .entry(loc)
+                    .or_default()
+                    .insert(name.clone(), value[0].into());
+            }
+        }
+        Ok(result)
+    }
+}
+
+/// Rebuild a path, replacing each point with the output of f.
+fn map_points(path: &BezPath, mut f: impl FnMut(Point) -> Point) -> BezPath {
+    path.elements()
+        .iter()
+        .map(|el| match *el {
+            PathEl::MoveTo(p) => PathEl::MoveTo(f(p)),
+            PathEl::LineTo(p) => PathEl::LineTo(f(p)),
+            PathEl::QuadTo(p0, p1) => {
+                let p0 = f(p0);
+                PathEl::QuadTo(p0, f(p1))
+            }
+            PathEl::CurveTo(p0, p1, p2) => {
+                let p0 = f(p0);
+                let p1 = f(p1);
+                PathEl::CurveTo(p0, p1, f(p2))
+            }
+            PathEl::ClosePath => PathEl::ClosePath,
+        })
+        .collect()
+}
+
+/// Fails unless every source of glyph has the same structure, meaning their
+/// [glyph_values] correspond one to one.
+pub(crate) fn check_compatible_sources(glyph: &Glyph) -> Result<(), Error> {
+    let structure = |instance: &GlyphInstance| {
+        (
+            instance.path_elements(),
+            instance
+                .components
+                .iter()
+                .map(|c| {
+                    let axes = c
+                        .variable
+                        .as_ref()
+                        .map(|v| v.location.axis_tags().copied().collect::<Vec<_>>());
+                    (c.base.clone(), axes)
+                })
+                .collect::<Vec<_>>(),
+        )
+    };
+    let default_structure = structure(glyph.default_instance());
+    if glyph
+        .sources()
+        .values()
+        .any(|instance| structure(instance) != default_structure)
+    {
+        return Err(Error::IncompatibleSources(format!(
+            "glyph '{}'",
+            glyph.name
+        )));
+    }
+    Ok(())
+}
+
+/// The numbers that vary in a glyph instance, in a stable order.
+///
+/// Instances that pass [check_compatible_sources] produce values that correspond one to one.
+/// Variable components contribute their decomposed transform and location rather than
+/// the affine, those are what interpolate.
+pub(crate) fn glyph_values(
+    instance: &GlyphInstance,
+    default_height: f64,
+    default_vertical_origin: f64,
+) -> Vec<f64> {
+    let mut values = vec![
+        instance.width,
+        instance.height.unwrap_or(default_height),
+        instance.vertical_origin.unwrap_or(default_vertical_origin),
+    ];
+    for contour in instance.contours.iter() {
+        map_points(contour, |p| {
+            values.extend([p.x, p.y]);
+            p
+        });
+    }
+    for component in instance.components.iter() {
+        match &component.variable {
+            Some(variable) => {
+                let t = &variable.transform;
+                values.extend([
+                    t.translate_x,
+                    t.translate_y,
+                    t.rotation,
+                    t.scale_x,
+                    t.scale_y,
+                    t.skew_x,
+                    t.skew_y,
+                    t.t_center_x,
+                    t.t_center_y,
+                ]);
+                values.extend(variable.location.iter().map(|(_, coord)| coord.to_f64()));
+            }
+            None => values.extend(component.transform.as_coeffs()),
+        }
+    }
+    values
+}
+
+/// The inverse of [glyph_values], using template for structure.
+pub(crate) fn glyph_from_values(template: &GlyphInstance, values: &[f64]) -> GlyphInstance {
+    let mut values = values.iter().copied();
+    let mut next = || values.next().unwrap();
+    let width = next();
+    let height = next();
+    let vertical_origin = next();
+    let contours = template
+        .contours
+        .iter()
+        .map(|contour| map_points(contour, |_| Point::new(next(), next())))
+        .collect();
+    let components = template
+        .components
+        .iter()
+        .map(|component| match &component.variable {
+            Some(variable) => {
+                let transform = ir::DecomposedTransform {
+                    translate_x: next(),
+                    translate_y: next(),
+                    rotation: next(),
+                    scale_x: next(),
+                    scale_y: next(),
+                    skew_x: next(),
+                    skew_y: next(),
+                    t_center_x: next(),
+                    t_center_y: next(),
+                };
+                let location = variable
+                    .location
+                    .axis_tags()
+                    .map(|tag| (*tag, NormalizedCoord::new(next())))
+                    .collect();
+                ir::Component {
+                    base: component.base.clone(),
+                    transform: transform.to_affine(),
+                    variable: Some(ir::VariableComponent::new(location, transform)),
+                    anchor: component.anchor.clone(),
+                }
+            }
+            None => ir::Component {
+                base: component.base.clone(),
+                transform: Affine::new([next(), next(), next(), next(), next(), next()]),
+                variable: None,
+                anchor: component.anchor.clone(),
+            },
+        })
+        .collect();
+    GlyphInstance {
+        width,
+        height: template.height.map(|_| height),
+        vertical_origin: template.vertical_origin.map(|_| vertical_origin),
+        contours,
+        components,
+    }
+}
+
+/// Rebuild a paint, replacing each number that may vary with the output of f.
+fn map_paint_values(paint: &Paint, f: &mut impl FnMut(f64) -> f64) -> Paint {
+    let point = |p: Point, f: &mut dyn FnMut(f64) -> f64| {
+        let x = f(p.x);
+        Point::new(x, f(p.y))
+    };
+    let color_line = |color_line: &ir::ColorLine, f: &mut dyn FnMut(f64) -> f64| ir::ColorLine {
+        extend: color_line.extend,
+        stops: color_line
+            .stops
+            .iter()
+            .map(|stop| {
+                let offset = f(stop.offset);
+                ir::ColorStop {
+                    offset,
+                    palette_index: stop.palette_index,
+                    alpha: f(stop.alpha),
+                }
+            })
+            .collect(),
+    };
+    match paint {
+        Paint::Layers(layers) => Paint::Layers(
+            layers
+                .iter()
+                .map(|layer| map_paint_values(layer, f))
+                .collect(),
+        ),
+        Paint::Solid {
+            palette_index,
+            alpha,
+        } => Paint::Solid {
+            palette_index: *palette_index,
+            alpha: f(*alpha),
+        },
+        Paint::LinearGradient {
+            color_line: cl,
+            p0,
+            p1,
+            p2,
+        } => Paint::LinearGradient {
+            color_line: color_line(cl, f),
+            p0: point(*p0, f),
+            p1: point(*p1, f),
+            p2: point(*p2, f),
+        },
+        Paint::RadialGradient {
+            color_line: cl,
+            c0,
+            r0,
+            c1,
+            r1,
+        } => Paint::RadialGradient {
+            color_line: color_line(cl, f),
+            c0: point(*c0, f),
+            r0: f(*r0),
+            c1: point(*c1, f),
+            r1: f(*r1),
+        },
+        Paint::SweepGradient {
+            color_line: cl,
+            center,
+            start_angle,
+            end_angle,
+        } => Paint::SweepGradient {
+            color_line: color_line(cl, f),
+            center: point(*center, f),
+            start_angle: f(*start_angle),
+            end_angle: f(*end_angle),
+        },
+        Paint::Glyph { name, paint } => Paint::Glyph {
+            name: name.clone(),
+            paint: Box::new(map_paint_values(paint, f)),
+        },
+        Paint::ColrGlyph(name) => Paint::ColrGlyph(name.clone()),
+        Paint::Transform { transform, paint } => {
+            let [xx, yx, xy, yy, dx, dy] = transform.as_coeffs().map(&mut *f);
+            Paint::Transform {
+                transform: Affine::new([xx, yx, xy, yy, dx, dy]),
+                paint: Box::new(map_paint_values(paint, f)),
+            }
+        }
+        Paint::Translate { dx, dy, paint } => Paint::Translate {
+            dx: f(*dx),
+            dy: f(*dy),
+            paint: Box::new(map_paint_values(paint, f)),
+        },
+        Paint::Scale {
+            scale_x,
+            scale_y,
+            center,
+            paint,
+        } => Paint::Scale {
+            scale_x: f(*scale_x),
+            scale_y: f(*scale_y),
+            center: center.map(|c| point(c, f)),
+            paint: Box::new(map_paint_values(paint, f)),
+        },
+        Paint::Rotate {
+            angle,
+            center,
+            paint,
+        } => Paint::Rotate {
+            angle: f(*angle),
+            center: center.map(|c| point(c, f)),
+            paint: Box::new(map_paint_values(paint, f)),
+        },
+        Paint::Skew {
+            x_skew_angle,
+            y_skew_angle,
+            center,
+            paint,
+        } => Paint::Skew {
+            x_skew_angle: f(*x_skew_angle),
+            y_skew_angle: f(*y_skew_angle),
+            center: center.map(|c| point(c, f)),
+            paint: Box::new(map_paint_values(paint, f)),
+        },
+        Paint::Composite {
+            source,
+            mode,
+            backdrop,
+        } => Paint::Composite {
+            source: Box::new(map_paint_values(source, f)),
+            mode: *mode,
+            backdrop: Box::new(map_paint_values(backdrop, f)),
+        },
+    }
+}
+
+impl Source for InstanceSource {
+    fn create_static_metadata_work(&self) -> Result<Box<IrWork>, Error> {
+        Ok(Box::new(StaticMetadataWork(self.instance.clone())))
+    }
+
+    fn create_global_metric_work(&self) -> Result<Box<IrWork>, Error> {
+        Ok(Box::new(GlobalMetricWork(self.instance.clone())))
+    }
+
+    fn create_glyph_ir_work(&self) -> Result<Vec<Box<IrWork>>, Error> {
+        Ok(self
+            .instance
+            .variable
+            .glyph_order
+            .names()
+            .map(|glyph_name| {
+                Box::new(GlyphIrWork {
+                    instance: self.instance.clone(),
+                    glyph_name: glyph_name.clone(),
+                }) as Box<IrWork>
+            })
+            .collect())
+    }
+
+    fn create_feature_ir_work(&self) -> Result<Box<IrWork>, Error> {
+        Ok(Box::new(FeatureWork(self.instance.clone())))
+    }
+
+    fn create_feature_variations_work(&self) -> Result<Box<IrWork>, Error> {
+        Ok(Box::new(FeatureVariationsWork(self.instance.clone())))
+    }
+
+    fn create_kerning_group_ir_work(&self) -> Result<Box<IrWork>, Error> {
+        Ok(Box::new(KerningGroupWork(self.instance.clone())))
+    }
+
+    fn create_kerning_instance_ir_work(
+        &self,
+        at: NormalizedLocation,
+    ) -> Result<Box<IrWork>, Error> {
+        Ok(Box::new(KerningInstanceWork {
+            instance: self.instance.clone(),
+            location: at,
+        }))
+    }
+
+    fn create_color_palette_work(&self) -> Result<Box<IrWork>, Error> {
+        Ok(Box::new(ColorPaletteWork(self.instance.clone())))
+    }
+
+    fn create_paint_graph_work(&self) -> Result<Box<IrWork>, Error> {
+        Ok(Box::new(PaintGraphWork(self.instance.clone())))
+    }
+
+    /// Instances are recompiled from the variable IR every time
+    fn fingerprint(&self) -> Result<Option<Fingerprints>, Error> {
+        Ok(None)
+    }
+}
+
+#[derive(Debug)]
+struct StaticMetadataWork(Arc<Instance>);
+
+impl Work<Context, WorkId, Error> for StaticMetadataWork {
+    fn id(&self) -> WorkId {
+        WorkId::StaticMetadata
+    }
+
+    fn also_completes(&self) -> Vec<WorkId> {
+        vec![WorkId::PreliminaryGlyphOrder]
+    }
+
+    fn exec(&self, context: &Context) -> Result<(), Error> {
+        context.static_metadata.set(self.0.static_metadata.clone());
+        context
+            .preliminary_glyph_order
+            .set((*self.0.variable.glyph_order).clone());
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct GlobalMetricWork(Arc<Instance>);
+
+impl Work<Context, WorkId, Error> for GlobalMetricWork {
+    fn id(&self) -> WorkId {
+        WorkId::GlobalMetrics
+    }
+
+    fn exec(&self, context: &Context) -> Result<(), Error> {
+        let mut metrics = GlobalMetrics::new();
+        for (metric, values) in self.0.variable.global_metrics.iter() {
+            let values = values
+                .iter()
+                .map(|(loc, value)| (loc.clone(), vec![value.into_inner()]))
+                .collect();
+            for (loc, value) in self.0.interpolate(format_args!("{metric:?}"), &values)? {
+                metrics.set(*metric, loc, value[0]);
+            }
+        }
+        context.global_metrics.set(metrics);
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct GlyphIrWork {
+    instance: Arc<Instance>,
+    glyph_name: GlyphName,
+}
+
+impl GlyphIrWork {
+    fn instance_glyph(&self, glyph: &Glyph) -> Result<Glyph, Error> {
+        check_compatible_sources(glyph)?;
+        let default = glyph.default_instance();
+        let default_height = default.height.unwrap_or_default();
+        let default_vertical_origin = default.vertical_origin.unwrap_or_default();
+        let values = glyph
+            .sources()
+            .iter()
+            .map(|(loc, instance)| {
+                (
+                    loc.clone(),
+                    glyph_values(instance, default_height, default_vertical_origin),
+                )
+            })
+            .collect();
+        let sources = self
+            .instance
+            .interpolate(&glyph.name, &values)?
+            .into_iter()
+            .map(|(loc, values)| (loc, glyph_from_values(default, &values)))
+            .collect();
+
+        Ok(Glyph::new(
+            glyph.name.clone(),
+            glyph.emit_to_binary,
+            glyph.codepoints.clone(),
+            sources,
+        )?)
+    }
+
+    fn instance_anchor(&self, anchor: &Anchor) -> Result<Anchor, Error> {
+        let values = anchor
+            .positions
+            .iter()
+            .map(|(loc, pos)| (loc.clone(), vec![pos.x, pos.y]))
+            .collect();
+        let positions = self
+            .instance
+            .interpolate(
+                format_args!("anchor {:?} of '{}'", anchor.kind, self.glyph_name),
+                &values,
+            )?
+            .into_iter()
+            .map(|(loc, values)| (loc, Point::new(values[0], values[1])))
+            .collect();
+        Ok(Anchor {
+            kind: anchor.kind.clone(),
+            positions,
+        })
+    }
+}
+
+impl Work<Context, WorkId, Error> for GlyphIrWork {
+    fn id(&self) -> WorkId {
+        WorkId::Glyph(self.glyph_name.clone())
+    }
+
+    fn write_access(&self) -> Access<WorkId> {
+        AccessBuilder::new()
+            .specific_instance(WorkId::Glyph(self.glyph_name.clone()))
+            .specific_instance(WorkId::Anchor(self.glyph_name.clone()))
+            .build()
+    }
+
+    fn also_completes(&self) -> Vec<WorkId> {
+        vec![WorkId::Anchor(self.glyph_name.clone())]
+    }
+
+    fn exec(&self, context: &Context) -> Result<(), Error> {
+        trace!("Instance glyph '{}'", self.glyph_name);
+        let variable = &self.instance.variable;
+        let glyph = variable
+            .glyphs
+            .get(&self.glyph_name)
+            .ok_or_else(|| Error::NoGlyphForName(self.glyph_name.clone()))?;
+        context.glyphs.set(self.instance_glyph(glyph)?);
+
+        let anchors = variable
+            .anchors
+            .get(&self.glyph_name)
+            .map(|anchors| {
+                anchors
+                    .anchors
+                    .iter()
+                    .map(|anchor| self.instance_anchor(anchor))
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .transpose()?
+            .unwrap_or_default();
+        context
+            .anchors
+            .set(GlyphAnchors::new(self.glyph_name.clone(), anchors));
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct FeatureWork(Arc<Instance>);
+
+impl Work<Context, WorkId, Error> for FeatureWork {
+    fn id(&self) -> WorkId {
+        WorkId::Features
+    }
+
+    fn exec(&self, context: &Context) -> Result<(), Error> {
+        let features = match &self.0.variable.features {
+            Some(features) => (**features).clone(),
+            None => FeaturesSource::empty(),
+        };
+        context.features.set(features);
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct FeatureVariationsWork(Arc<Instance>);
+
+impl Work<Context, WorkId, Error> for FeatureVariationsWork {
+    fn id(&self) -> WorkId {
+        WorkId::FeatureVariations
+    }
+
+    fn exec(&self, context: &Context) -> Result<(), Error> {
+        let Some(feature_variations) = &self.0.variable.feature_variations else {
+            return Ok(());
+        };
+        // Rules keep the part of their region within our limits, those on pinned axes
+        // either apply everywhere or not at all
+        let rules = feature_variations
+            .rules
+            .iter()
+            .filter_map(|rule| {
+                let condition_sets: Vec<_> = rule
+                    .condition_sets
+                    .iter()
+                    .filter_map(|condition_set| self.0.limits.condition_set(condition_set))
+                    .collect();
+                (!condition_sets.is_empty()).then(|| ConditionalSubstitution {
+                    condition_sets,
+                    substitutions: rule.substitutions.clone(),
+                })
+            })
+            .collect::<Vec<_>>();
+        debug!(
+            "{} of {} rules apply within {:?}",
+            rules.len(),
+            feature_variations.rules.len(),
+            self.0.limits.0
+        );
+        if !rules.is_empty() {
+            context.feature_variations.set(FeatureVariations::new(
+                feature_variations.features.clone(),
+                rules,
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct KerningGroupWork(Arc<Instance>);
+
+impl Work<Context, WorkId, Error> for KerningGroupWork {
+    fn id(&self) -> WorkId {
+        WorkId::KerningGroups
+    }
+
+    fn exec(&self, context: &Context) -> Result<(), Error> {
+        // The variable kerning already uses the new group names so there is nothing to rename
+        let variable_groups = self.0.variable.kerning_groups.as_ref();
+        let groups = variable_groups
+            .map(|groups| groups.groups.clone())
+            .unwrap_or_default();
+        let locations = self
+            .0
+            .limits
+            .locations(
+                variable_groups
+                    .iter()
+                    .flat_map(|groups| groups.locations.iter()),
+            )
+            .into_keys()
+            .collect();
+        context.kerning_groups.set(KerningGroups {
+            groups,
+            locations,
+            old_to_new_group_names: Default::default(),
+        });
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct KerningInstanceWork {
+    instance: Arc<Instance>,
+    location: NormalizedLocation,
+}
+
+impl Work<Context, WorkId, Error> for KerningInstanceWork {
+    fn id(&self) -> WorkId {
+        WorkId::KernInstance(self.location.clone())
+    }
+
+    fn read_access(&self) -> Access<WorkId> {
+        Access::Variant(WorkId::KerningGroups)
+    }
+
+    fn exec(&self, context: &Context) -> Result<(), Error> {
+        let variable = &self.instance.variable;
+        let mut kerning = KerningInstance {
+            location: self.location.clone(),
+            ..Default::default()
+        };
+
+        if let Some(groups) = &variable.kerning_groups {
+            // Every pair must have a value at every location before we can interpolate
+            let mut kern_by_pos: HashMap<_, _> = variable
+                .kerning
+                .iter()
+                .filter(|ki| groups.locations.contains(&ki.location))
+                .map(|ki| (ki.location.clone(), (**ki).clone()))
+                .collect();
+            ir::align_kerning(groups, &mut kern_by_pos);
+
+            let mut values: HashMap<NormalizedLocation, Vec<f64>> = HashMap::new();
+            let pairs: BTreeSet<_> = kern_by_pos
+                .values()
+                .flat_map(|ki| ki.kerns.keys())
+                .cloned()
+                .collect();
+            for (loc, ki) in kern_by_pos.iter() {
+                values.insert(
+                    loc.clone(),
+                    pairs
+                        .iter()
+                        .map(|pair| ki.kerns.get(pair).copied().unwrap_or_default().into_inner())
+                        .collect(),
+                );
+            }
+            // We are one of the locations the kerning locations of the variable font become
+            let locations = self
+                .instance
+                .limits
+                .locations(groups.locations.iter())
+                .into_iter()
+                .filter(|(location, _)| *location == self.location)
+                .collect();
+            let values = self
+                .instance
+                .interpolate_at("kerning", &values, locations)?
+                .remove(&self.location)
+                .unwrap_or_default();
+            kerning.kerns = pairs
+                .into_iter()
+                .zip(values)
+                .map(|(pair, value)| (pair, value.into()))
+                .collect();
+        }
+
+        context.kerning_at.set(kerning);
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct ColorPaletteWork(Arc<Instance>);
+
+impl Work<Context, WorkId, Error> for ColorPaletteWork {
+    fn id(&self) -> WorkId {
+        WorkId::ColorPalettes
+    }
+
+    fn write_access(&self) -> Access<WorkId> {
+        Access::Variant(WorkId::ColorPalettes)
+    }
+
+    fn exec(&self, context: &Context) -> Result<(), Error> {
+        if let Some(colors) = &self.0.variable.colors {
+            context.colors.set((**colors).clone());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct PaintGraphWork(Arc<Instance>);
+
+impl Work<Context, WorkId, Error> for PaintGraphWork {
+    fn id(&self) -> WorkId {
+        WorkId::PaintGraph
+    }
+
+    fn read_access(&self) -> Access<WorkId> {
+        Access::Variant(WorkId::ColorPalettes)
+    }
+
+    fn write_access(&self) -> Access<WorkId> {
+        Access::Variant(WorkId::PaintGraph)
+    }
+
+    fn exec(&self, context: &Context) -> Result<(), Error> {
+        let Some(paint_graph) = &self.0.variable.paint_graph else {
+            return Ok(());
+        };
+        let mut base_glyphs = BTreeMap::new();
+        for (glyph_name, color_glyph) in paint_graph.base_glyphs.iter() {
+            let values = color_glyph
+                .sources
+                .iter()
+                .map(|(loc, paint)| {
+                    let mut values = Vec::new();
+                    map_paint_values(paint, &mut |v| {
+                        values.push(v);
+                        v
+                    });
+                    (loc.clone(), values)
+                })
+                .collect();
+            let values = self
+                .0
+                .interpolate(format_args!("paint of '{glyph_name}'"), &values)?;
+
+            // Any source will do as a template, they all have the same structure
+            let template = color_glyph
+                .sources
+                .iter()
+                .find(|(loc, _)| !loc.has_any_non_zero())
+                .or_else(|| color_glyph.sources.iter().next())
+                .map(|(_, paint)| paint)
+                .unwrap();
+            let sources = values
+                .into_iter()
+                .map(|(loc, values)| {
+                    let mut values = values.into_iter();
+                    let paint = map_paint_values(template, &mut |_| values.next().unwrap());
+                    (loc, paint)
+                })
+                .collect();
+            base_glyphs.insert(glyph_name.clone(), ColorGlyph { sources });
+        }
+        context.paint_graph.set(PaintGraph { base_glyphs });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use write_fonts::tables::os2::SelectionFlags;
+
+    use super::*;
+
+    #[test]
+    fn ribbi_words_from_style_name() {
+        assert_eq!((false, false), ribbi_words("Regular"));
+        assert_eq!((true, false), ribbi_words("Bold"));
+        assert_eq!((false, true), ribbi_words("Light Italic"));
+        assert_eq!((true, true), ribbi_words("Bold Italic"));
+        assert_eq!((false, false), ribbi_words("Bold Condensed"));
+    }
+
+    #[test]
+    fn selection_flags_follow_style() {
+        let flags = SelectionFlags::REGULAR | SelectionFlags::USE_TYPO_METRICS;
+        assert_eq!(
+            SelectionFlags::BOLD | SelectionFlags::USE_TYPO_METRICS,
+            instance_selection_flags(flags, "Bold")
+        );
+        assert_eq!(
+            SelectionFlags::REGULAR | SelectionFlags::USE_TYPO_METRICS,
+            instance_selection_flags(flags, "Light")
+        );
+        assert_eq!(
+            SelectionFlags::ITALIC,
+            instance_selection_flags(SelectionFlags::ITALIC, "Light")
+        );
+    }
+
+    fn weight_axis() -> Axis {
+        let [min, default, max] = [400.0, 400.0, 700.0].map(UserCoord::new);
+        Axis {
+            name: "Weight".to_string(),
+            tag: Tag::new(b"wght"),
+            min,
+            default,
+            max,
+            hidden: false,
+            converter: CoordConverter::unmapped(min, default, max),
+        }
+    }
+
+    fn wght_condition(min: f64, max: f64) -> ConditionSet {
+        ConditionSet::new([Condition {
+            axis: Tag::new(b"wght"),
+            min: NormalizedCoord::new(min),
+            max: NormalizedCoord::new(max),
+        }])
+    }
+
+    #[test]
+    fn limit_axis_keeps_default() {
+        let (axis, limit) =
+            limit_axis(&weight_axis(), UserCoord::new(400.0), UserCoord::new(550.0));
+        assert_eq!(
+            (400.0, 400.0, 550.0),
+            (axis.min.to_f64(), axis.default.to_f64(), axis.max.to_f64())
+        );
+        assert_eq!(
+            (0.0, 0.0, 0.5),
+            (
+                limit.min.to_f64(),
+                limit.default.to_f64(),
+                limit.max.to_f64()
+            )
+        );
+        assert_eq!(
+            NormalizedCoord::new(0.5),
+            limit.renormalize(NormalizedCoord::new(0.25))
+        );
+    }
+
+    #[test]
+    fn limit_axis_clamps_default() {
+        let (axis, limit) =
+            limit_axis(&weight_axis(), UserCoord::new(550.0), UserCoord::new(900.0));
+        assert_eq!(
+            (550.0, 550.0, 700.0),
+            (axis.min.to_f64(), axis.default.to_f64(), axis.max.to_f64())
+        );
+        assert_eq!(
+            (0.5, 0.5, 1.0),
+            (
+                limit.min.to_f64(),
+                limit.default.to_f64(),
+                limit.max.to_f64()
+            )
+        );
+        assert_eq!(
+            NormalizedCoord::new(0.5),
+            limit.renormalize(NormalizedCoord::new(0.75))
+        );
+        // The new default is where the old one is clamped to
+        assert_eq!(
+            NormalizedCoord::new(0.0),
+            limit.renormalize(limit.clamp(NormalizedCoord::new(0.0)))
+        );
+    }
+
+    #[test]
+    fn conditions_are_limited() {
+        let (_, limit) = limit_axis(&weight_axis(), UserCoord::new(400.0), UserCoord::new(550.0));
+        let limits = Limits(BTreeMap::from([(Tag::new(b"wght"), limit)]));
+        assert_eq!(None, limits.condition_set(&wght_condition(0.75, 1.0)));
+        assert_eq!(
+            Some(wght_condition(0.5, 1.0)),
+            limits.condition_set(&wght_condition(0.25, 1.0))
+        );
+
+        let pinned = Limits(BTreeMap::from([(
+            Tag::new(b"wght"),
+            NormalizedLimit::pinned(NormalizedCoord::new(0.5)),
+        )]));
+        assert_eq!(
+            Some(ConditionSet::default()),
+            pinned.condition_set(&wght_condition(0.25, 1.0))
+        );
+        assert_eq!(None, pinned.condition_set(&wght_condition(0.75, 1.0)));
+    }
+
+    #[test]
+    fn glyph_values_round_trip() {
+        let mut path = BezPath::new();
+        path.move_to((1.0, 2.0));
+        path.quad_to((3.0, 4.0), (5.0, 6.0));
+        path.curve_to((7.0, 8.0), (9.0, 10.0), (11.0, 12.0));
+        path.close_path();
+        let instance = GlyphInstance {
+            width: 600.0,
+            height: None,
+            vertical_origin: Some(880.0),
+            contours: vec![path],
+            components: vec![ir::Component {
+                base: "a".into(),
+                transform: Affine::new([1.0, 0.0, 0.25, 1.0, 10.0, 20.0]),
+                variable: None,
+                anchor: None,
+            }],
+        };
+        let values = glyph_values(&instance, 0.0, 0.0);
+        assert_eq!(3 + 12 + 6, values.len());
+        assert_eq!(instance, glyph_from_values(&instance, &values));
+    }
+}
@@ -12,7 +12,7 @@ use kurbo::{Affine, BezPath, PathEl, Point};
 use log::{log_enabled, trace, warn};
 use ordered_float::OrderedFloat;
 use serde::{de::Error as _, Deserialize, Serialize};
-use smol_str::SmolStr;
+use smol_str::{format_smolstr, SmolStr};
 use write_fonts::{
     tables::{gasp::GaspRange, gdef::GlyphClassDef, os2::SelectionFlags},
     types::{GlyphId16, NameId, Tag},
@@ -20,7 +20,7 @@ use write_fonts::{
 };
 
 use fontdrasil::{
-    coords::{NormalizedCoord, NormalizedLocation, UserLocation},
+    coords::{NormalizedCoord, NormalizedLocation, UserCoord, UserLocation},
     types::{Axis, GlyphName},
 };
 
@@ -85,6 +85,186 @@ pub struct StaticMetadata {
     /// Miscellaneous font-wide data that didn't seem worthy of top billing
     pub misc: MiscMetadata,
     pub gdef_categories: GdefCategories,
+
+    /// Names for positions on the axes, see [StatLabels]
+    pub stat_labels: StatLabels,
+
+    /// Unicode variation sequences, variation selector => base codepoint => glyph.
+    ///
+    /// See <https://learn.microsoft.com/en-us/typography/opentype/spec/cmap#format-14-unicode-variation-sequences>
+    pub variation_sequences: BTreeMap<u32, BTreeMap<u32, GlyphName>>,
+
+    /// Mappings between locations that may span several axes, see [AxisMapping].
+    ///
+    /// Sources are located in the space these map to.
+    pub axis_mappings: Vec<AxisMapping>,
+
+    /// Transformations to apply to glyphs before compilation, in order, see [GlyphFilter].
+    pub glyph_filters: Vec<GlyphFilter>,
+}
+
+/// Maps an input location to an output location, possibly across several axes.
+///
+/// Captures a designspace 5.1 [mapping](https://fonttools.readthedocs.io/en/latest/designspaceLib/xml.html#mappings-element),
+/// which becomes [avar 2](https://github.com/harfbuzz/boring-expansion-spec/blob/main/avar2.md).
+/// Both locations are normalized, after the per-axis mapping of avar 1. Axes missing
+/// from the input are at their default, axes missing from the output are left unchanged.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AxisMapping {
+    pub input: NormalizedLocation,
+    pub output: NormalizedLocation,
+}
+
+/// A variation model of the input locations of the [AxisMapping]s, and how far
+/// each input location moves on each axis.
+pub type AxisMappingSources = (VariationModel, HashMap<NormalizedLocation, Vec<f64>>);
+
+/// The style attributes of [STAT](https://learn.microsoft.com/en-us/typography/opentype/spec/stat)
+/// beyond the axes themselves.
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq, Eq)]
+pub struct StatLabels {
+    pub axis_values: Vec<AxisValueLabel>,
+    /// The name to use when every axis value name is elided.
+    ///
+    /// If None the subfamily name is used, as in fontTools.
+    pub elided_fallback_name: Option<String>,
+}
+
+/// A name for a position on, or range of, one or more axes.
+///
+/// Becomes a [STAT axis value](https://learn.microsoft.com/en-us/typography/opentype/spec/stat#axis-value-tables).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AxisValueLabel {
+    pub name: String,
+    /// The name may be omitted when composing a style name, e.g. Regular
+    pub elidable: bool,
+    /// Superseded by a more recent font with the same name, see
+    /// <https://learn.microsoft.com/en-us/typography/opentype/spec/stat#flags>
+    pub older_sibling: bool,
+    pub value: AxisValueLocation,
+}
+
+/// Where an [AxisValueLabel] applies, which determines the format of the axis value.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum AxisValueLocation {
+    /// Format 1, or format 3 if linked to another value, e.g. Regular to Bold
+    Point {
+        axis: Tag,
+        value: UserCoord,
+        linked_value: Option<UserCoord>,
+    },
+    /// Format 2, the range is unbounded on sides that are None
+    Range {
+        axis: Tag,
+        nominal: UserCoord,
+        min: Option<UserCoord>,
+        max: Option<UserCoord>,
+    },
+    /// Format 4, a position on several axes at once
+    Location(UserLocation),
+}
+
+/// A transformation of glyphs a source asks for, such as a
+/// [ufo2ft filter](https://github.com/googlefonts/ufo2ft/tree/main/Lib/ufo2ft/filters).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct GlyphFilter {
+    pub kind: GlyphFilterKind,
+    /// Pre filters run before glyphs are made consistent and before optional
+    /// transformations such as flattening, others run after.
+    pub pre: bool,
+    pub glyphs: GlyphSelection,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum GlyphFilterKind {
+    /// Replace all components with the outlines they reference
+    DecomposeComponents,
+    /// Replace components whose transform scales, skews or rotates with outlines
+    DecomposeTransformedComponents,
+    /// Replace components of components with components of the glyph they reference
+    FlattenComponents,
+    /// Copy anchors of components onto the glyph using them
+    PropagateAnchors,
+    /// Order contours by their bounds
+    SortContours,
+    Transformations(Transformations),
+}
+
+/// The glyphs a [GlyphFilter] applies to
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq, Eq)]
+pub enum GlyphSelection {
+    #[default]
+    All,
+    Include(BTreeSet<GlyphName>),
+    Exclude(BTreeSet<GlyphName>),
+}
+
+impl GlyphSelection {
+    pub fn contains(&self, glyph_name: &GlyphName) -> bool {
+        match self {
+            GlyphSelection::All => true,
+            GlyphSelection::Include(names) => names.contains(glyph_name),
+            GlyphSelection::Exclude(names) => !names.contains(glyph_name),
+        }
+    }
+}
+
+/// Parameters of the ufo2ft
+/// [transformations](https://github.com/googlefonts/ufo2ft/blob/main/Lib/ufo2ft/filters/transformations.py)
+/// filter.
+///
+/// Glyphs are scaled and slanted about a height given by origin, then offset.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Transformations {
+    pub offset_x: OrderedFloat<f64>,
+    pub offset_y: OrderedFloat<f64>,
+    /// 1.0 leaves the size unchanged
+    pub scale_x: OrderedFloat<f64>,
+    pub scale_y: OrderedFloat<f64>,
+    /// Counter-clockwise degrees from the vertical, positive leans right
+    pub slant: OrderedFloat<f64>,
+    pub origin: TransformationOrigin,
+}
+
+impl Default for Transformations {
+    fn default() -> Self {
+        Transformations {
+            offset_x: 0.0.into(),
+            offset_y: 0.0.into(),
+            scale_x: 1.0.into(),
+            scale_y: 1.0.into(),
+            slant: 0.0.into(),
+            origin: Default::default(),
+        }
+    }
+}
+
+/// The height [Transformations] scale and slant about.
+///
+/// Values match those of ufo2ft's `OriginHeight`.
+#[derive(Serialize, Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransformationOrigin {
+    CapHeight = 0,
+    HalfCapHeight = 1,
+    XHeight = 2,
+    HalfXHeight = 3,
+    #[default]
+    Baseline = 4,
+}
+
+impl TryFrom<i64> for TransformationOrigin {
+    type Error = i64;
+
+    fn try_from(value: i64) -> Result<Self, Self::Error> {
+        Ok(match value {
+            0 => TransformationOrigin::CapHeight,
+            1 => TransformationOrigin::HalfCapHeight,
+            2 => TransformationOrigin::XHeight,
+            3 => TransformationOrigin::HalfXHeight,
+            4 => TransformationOrigin::Baseline,
+            _ => return Err(value),
+        })
+    }
 }
 
 #[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq, Eq)]
@@ -152,6 +332,31 @@ pub struct MiscMetadata {
 
     // <https://learn.microsoft.com/en-us/typography/opentype/spec/gasp>
     pub gasp: Vec<GaspRange>,
+
+    /// Whether to emit vertical metrics, vhea, vmtx and friends.
+    ///
+    /// Set when the source defines vertical metrics, as for CJK or Mongolian fonts.
+    pub build_vertical: bool,
+
+    /// Hinting values for the Private DICT of a CFF or CFF2 table
+    pub postscript_hints: PostscriptHints,
+}
+
+/// The alignment zones and stem widths PostScript hinting uses, from the default master.
+///
+/// Values are rounded, as ufo2ft does. Each is empty if the source doesn't have it.
+///
+/// See <https://unifiedfontobject.org/versions/ufo3/fontinfo.plist/#postscript-specific-data>
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq, Eq)]
+pub struct PostscriptHints {
+    /// Pairs of bottom and top of alignment zones, the first of which is the baseline zone
+    pub blue_values: Vec<i16>,
+    /// Pairs of bottom and top of alignment zones below the baseline
+    pub other_blues: Vec<i16>,
+    /// Horizontal stem widths, the first is the dominant one (StdHW)
+    pub stem_snap_h: Vec<i16>,
+    /// Vertical stem widths, the first is the dominant one (StdVW)
+    pub stem_snap_v: Vec<i16>,
 }
 
 /// PANOSE bytes
@@ -359,6 +564,113 @@ pub struct KerningInstance {
     pub kerns: BTreeMap<KernPair, OrderedFloat<f64>>,
 }
 
+/// 'align' the kerning, ensuring each pair is defined for each location.
+///
+/// missing pairs are filled in via the UFO kerning value lookup algorithm:
+///
+/// <https://unifiedfontobject.org/versions/ufo3/kerning.plist/#kerning-value-lookup-algorithm>
+///
+/// in pythonland this happens in ufo2ft, here:
+/// <https://github.com/googlefonts/ufo2ft/blob/5fd168e65a0b0a/Lib/ufo2ft/featureWriters/kernFeatureWriter.py#L442>
+pub fn align_kerning(
+    groups: &KerningGroups,
+    instances: &mut HashMap<NormalizedLocation, KerningInstance>,
+) {
+    // all pairs defined in at least one instance
+    let all_known_pairs = instances
+        .values()
+        .flat_map(|instance| instance.kerns.keys())
+        .cloned()
+        .collect::<HashSet<_>>();
+
+    let side1_glyph_to_group_map = groups
+        .groups
+        .iter()
+        .filter(|(group, _)| matches!(group, KernGroup::Side1(_)))
+        .flat_map(|(group, glyphs)| glyphs.iter().map(move |glyph| (glyph, group)))
+        .collect::<HashMap<_, _>>();
+    let side2_glyph_to_group_map = groups
+        .groups
+        .iter()
+        .filter(|(group, _)| matches!(group, KernGroup::Side2(_)))
+        .flat_map(|(group, glyphs)| glyphs.iter().map(move |glyph| (glyph, group)))
+        .collect::<HashMap<_, _>>();
+
+    for instance in instances.values_mut() {
+        align_instance(
+            &all_known_pairs,
+            &mut instance.kerns,
+            &side1_glyph_to_group_map,
+            &side2_glyph_to_group_map,
+        )
+    }
+}
+
+fn align_instance(
+    all_pairs: &HashSet<KernPair>,
+    instance: &mut BTreeMap<KernPair, OrderedFloat<f64>>,
+    side1_glyphs: &HashMap<&GlyphName, &KernGroup>,
+    side2_glyphs: &HashMap<&GlyphName, &KernGroup>,
+) {
+    let mut buf = Vec::new();
+    // iterate the pairs that are not present in this instance
+    for pair in all_pairs.iter().filter(|pair| !instance.contains_key(pair)) {
+        let value = lookup_kerning_value(pair, instance, side1_glyphs, side2_glyphs);
+
+        // accumulate any additions and add at the end, otherwise newly added
+        // additions could influence the calculation of subsequent values
+        buf.push((pair, value));
+    }
+    // when done all pairs, add them to the instance
+    for (pair, value) in buf {
+        instance.insert(pair.to_owned(), value);
+    }
+}
+
+// <https://github.com/fonttools/fonttools/blob/a3b9eddcafca/Lib/fontTools/ufoLib/kerning.py#L1>
+fn lookup_kerning_value(
+    pair: &KernPair,
+    kerning: &BTreeMap<KernPair, OrderedFloat<f64>>,
+    side1_glyphs: &HashMap<&GlyphName, &KernGroup>,
+    side2_glyphs: &HashMap<&GlyphName, &KernGroup>,
+) -> OrderedFloat<f64> {
+    // if already a group, return it, else look for group for glyph
+    fn get_group_if_glyph(
+        side: &KernSide,
+        map: &HashMap<&GlyphName, &KernGroup>,
+    ) -> Option<KernSide> {
+        match side {
+            KernSide::Glyph(glyph) => map
+                .get(&glyph)
+                .map(|group| KernSide::Group((*group).clone())),
+            KernSide::Group(_) => Some(side.to_owned()),
+        }
+    }
+
+    let (first, second) = pair;
+    // for each side: if it's a group, we only check the group.
+    // if it's a glyph, we check both the glyph as well as the group containing that glyph.
+    let first_group = get_group_if_glyph(first, side1_glyphs);
+    let second_group = get_group_if_glyph(second, side2_glyphs);
+    let first = Some(first).filter(|side| side.is_glyph());
+    let second = Some(second).filter(|side| side.is_glyph());
+
+    for (first, second) in [
+        (first.cloned(), second_group.clone()),
+        (first_group.clone(), second.cloned()),
+        (first_group.clone(), second_group.clone()),
+    ] {
+        if let Some(pair) = first.zip(second) {
+            if let Some(value) = kerning.get(&pair) {
+                return *value;
+            }
+        }
+    }
+
+    // then fallback to zero
+    0.0.into()
+}
+
 /// A named set of glyphs with common kerning behaviour
 ///
 /// Identical sets can have different behaviour depending on whether or not they
@@ -473,21 +785,14 @@ impl StaticMetadata {
         };
 
         // Claim names for axes and named instances
-        let mut name_id_gen = 255;
         let mut key_to_name = names;
-        let mut visited = key_to_name.values().cloned().collect::<HashSet<_>>();
-
-        variable_axes
-            .iter()
-            .map(|axis| axis.ui_label_name())
-            .chain(named_instances.iter().map(|ni| ni.name.as_ref()))
-            .for_each(|name| {
-                if !visited.insert(name.to_string()) {
-                    return;
-                }
-                name_id_gen += 1;
-                key_to_name.insert(NameKey::new(name_id_gen.into(), name), name.to_string());
-            });
+        claim_names(
+            &mut key_to_name,
+            variable_axes
+                .iter()
+                .map(|axis| axis.ui_label_name())
+                .chain(named_instances.iter().map(|ni| ni.name.as_ref())),
+        );
 
         let variation_model = VariationModel::new(global_locations, variable_axes.clone())?;
 
@@ -507,6 +812,10 @@ impl StaticMetadata {
             postscript_names,
             italic_angle: italic_angle.into(),
             gdef_categories,
+            stat_labels: Default::default(),
+            variation_sequences: Default::default(),
+            axis_mappings: Default::default(),
+            glyph_filters: Default::default(),
             number_values: glyphsapp_number_values.unwrap_or_default(),
             misc: MiscMetadata {
                 fs_type: None, // default is, sigh, inconsistent across source formats
@@ -529,6 +838,8 @@ impl StaticMetadata {
                 us_weight_class: None,
                 us_width_class: None,
                 gasp: Vec::new(),
+                build_vertical: false,
+                postscript_hints: Default::default(),
             },
         })
     }
@@ -541,6 +852,103 @@ impl StaticMetadata {
     pub fn axis(&self, tag: &Tag) -> Option<&Axis> {
         self.axes.iter().find(|a| &a.tag == tag)
     }
+
+    /// Set the STAT labels, claiming names for any not already in the name table
+    pub fn set_stat_labels(&mut self, stat_labels: StatLabels) {
+        claim_names(
+            &mut self.names,
+            stat_labels
+                .axis_values
+                .iter()
+                .map(|label| label.name.as_str())
+                .chain(stat_labels.elided_fallback_name.as_deref()),
+        );
+        self.stat_labels = stat_labels;
+    }
+
+    /// A model of the [AxisMapping]s and how far each input location moves on each axis.
+    ///
+    /// The movement is in the order of [StaticMetadata::axes]. Like fontTools
+    /// [_add_avar](https://github.com/fonttools/fonttools/blob/main/Lib/fontTools/varLib/__init__.py)
+    /// the default maps to itself unless a mapping says otherwise. None if there are no mappings.
+    pub fn axis_mapping_sources(&self) -> Result<Option<AxisMappingSources>, VariationModelError> {
+        if self.axis_mappings.is_empty() {
+            return Ok(None);
+        }
+        let fill = |location: &NormalizedLocation| -> NormalizedLocation {
+            self.axes
+                .iter()
+                .map(|axis| {
+                    let pos = location.get(axis.tag).unwrap_or_default();
+                    (axis.tag, pos)
+                })
+                .collect()
+        };
+        let mut sources = HashMap::new();
+        for mapping in self.axis_mappings.iter() {
+            let input = fill(&mapping.input);
+            let movement = self
+                .axes
+                .iter()
+                .map(|axis| match mapping.output.get(axis.tag) {
+                    Some(output) => output.to_f64() - input.get(axis.tag).unwrap().to_f64(),
+                    None => 0.0,
+                })
+                .collect();
+            sources.insert(input, movement);
+        }
+        sources
+            .entry(fill(&NormalizedLocation::new()))
+            .or_insert_with(|| vec![0.0; self.axes.len()]);
+        let model = VariationModel::new(sources.keys().cloned().collect(), self.axes.clone())?;
+        Ok(Some((model, sources)))
+    }
+
+    /// Map a normalized location through the [AxisMapping]s, as avar 2 would.
+    ///
+    /// The result is where sources are located.
+    pub fn apply_axis_mappings(
+        &self,
+        location: &NormalizedLocation,
+    ) -> Result<NormalizedLocation, Error> {
+        let Some((model, sources)) = self.axis_mapping_sources()? else {
+            return Ok(location.clone());
+        };
+        let movement: Vec<f64> =
+            model
+                .interpolate(location, &sources)
+                .map_err(|source| Error::InterpolationFailed {
+                    what: "axis mappings".to_string(),
+                    source,
+                })?;
+        let mut mapped = location.clone();
+        for (axis, movement) in self.axes.iter().zip(movement) {
+            let pos = location.get(axis.tag).unwrap_or_default().to_f64();
+            mapped.insert(
+                axis.tag,
+                NormalizedCoord::new((pos + movement).clamp(-1.0, 1.0)),
+            );
+        }
+        Ok(mapped)
+    }
+}
+
+/// Add names that don't already exist, with ids from 256 up.
+fn claim_names<'a>(names: &mut HashMap<NameKey, String>, new_names: impl Iterator<Item = &'a str>) {
+    let mut name_id_gen = names
+        .keys()
+        .map(|key| key.name_id.to_u16())
+        .filter(|id| *id > 255)
+        .max()
+        .unwrap_or(255);
+    let mut visited = names.values().cloned().collect::<HashSet<_>>();
+    for name in new_names {
+        if !visited.insert(name.to_string()) {
+            continue;
+        }
+        name_id_gen += 1;
+        names.insert(NameKey::new(name_id_gen.into(), name), name.to_string());
+    }
 }
 
 /// Global metrics. Ascender/descender, cap height, etc.
@@ -559,6 +967,12 @@ pub enum GlobalMetric {
     HheaAscender,
     HheaDescender,
     HheaLineGap,
+    VheaAscender,
+    VheaDescender,
+    VheaLineGap,
+    VheaCaretSlopeRise,
+    VheaCaretSlopeRun,
+    VheaCaretOffset,
     Os2TypoAscender,
     Os2TypoDescender,
     Os2TypoLineGap,
@@ -584,15 +998,28 @@ pub enum GlobalMetric {
 }
 
 impl GlobalMetric {
+    /// True for metrics that only matter for vertical typesetting
+    pub fn is_vertical(&self) -> bool {
+        matches!(
+            self,
+            GlobalMetric::VheaAscender
+                | GlobalMetric::VheaDescender
+                | GlobalMetric::VheaLineGap
+                | GlobalMetric::VheaCaretSlopeRise
+                | GlobalMetric::VheaCaretSlopeRun
+                | GlobalMetric::VheaCaretOffset
+        )
+    }
+
     /// Return the 4-byte tag used to represent a global metric in the `MVAR` table.
     ///
     /// `None` if this metric is not associated with an `MVAR` value tag, or if
-    /// we don't support it yet (e.g. vertical typesetting metrics).
+    /// we don't support it yet.
     ///
     /// <https://learn.microsoft.com/en-us/typography/opentype/spec/mvar#value-tags>
     pub fn mvar_tag(&self) -> Option<Tag> {
         // We support the same subset of the metrics defined in the spec
-        // as fonttools does (except vertical ones which we don't support yet):
+        // as fonttools does:
         // https://github.com/fonttools/fonttools/blob/0c5cb3b/Lib/fontTools/varLib/mvar.py
         match self {
             GlobalMetric::Os2TypoAscender => Some(Tag::new(b"hasc")),
@@ -600,17 +1027,15 @@ impl GlobalMetric {
             GlobalMetric::Os2TypoLineGap => Some(Tag::new(b"hlgp")),
             GlobalMetric::Os2WinAscent => Some(Tag::new(b"hcla")),
             GlobalMetric::Os2WinDescent => Some(Tag::new(b"hcld")),
-            // TODO: support vertical global metrics
-            // https://github.com/googlefonts/fontc/issues/668
-            // GlobalMetric::VheaAscender => Some(Tag::new(b"vasc")),
-            // GlobalMetric::VheaDescender => Some(Tag::new(b"vdsc")),
-            // GlobalMetric::VheaLineGap => Some(Tag::new(b"vlgp")),
+            GlobalMetric::VheaAscender => Some(Tag::new(b"vasc")),
+            GlobalMetric::VheaDescender => Some(Tag::new(b"vdsc")),
+            GlobalMetric::VheaLineGap => Some(Tag::new(b"vlgp")),
             GlobalMetric::CaretSlopeRise => Some(Tag::new(b"hcrs")),
             GlobalMetric::CaretSlopeRun => Some(Tag::new(b"hcrn")),
             GlobalMetric::CaretOffset => Some(Tag::new(b"hcof")),
-            // GlobalMetric::VheaCaretSlopeRise => Some(Tag::new(b"vcrs")),
-            // GlobalMetric::VheaCaretSlopeRun => Some(Tag::new(b"vcrn")),
-            // GlobalMetric::VheaCaretOffset => Some(Tag::new(b"vcof")),
+            GlobalMetric::VheaCaretSlopeRise => Some(Tag::new(b"vcrs")),
+            GlobalMetric::VheaCaretSlopeRun => Some(Tag::new(b"vcrn")),
+            GlobalMetric::VheaCaretOffset => Some(Tag::new(b"vcof")),
             GlobalMetric::XHeight => Some(Tag::new(b"xhgt")),
             GlobalMetric::CapHeight => Some(Tag::new(b"cpht")),
             GlobalMetric::SubscriptXSize => Some(Tag::new(b"sbxs")),
@@ -696,6 +1121,15 @@ impl GlobalMetrics {
         // https://github.com/googlefonts/ufo2ft/blob/0d2688cd847d003b41104534d16973f72ef26c40/Lib/ufo2ft/fontInfoData.py#L366
         set_if_absent(GlobalMetric::HheaLineGap, 0.0);
 
+        // https://github.com/googlefonts/ufo2ft/blob/0d2688cd847d003b41104534d16973f72ef26c40/Lib/ufo2ft/fontInfoData.py#L165-L178
+        set_if_absent(GlobalMetric::VheaAscender, units_per_em * 0.5);
+        set_if_absent(GlobalMetric::VheaDescender, -units_per_em * 0.5);
+        set_if_absent(GlobalMetric::VheaLineGap, units_per_em);
+        // https://github.com/googlefonts/ufo2ft/blob/0d2688cd847d003b41104534d16973f72ef26c40/Lib/ufo2ft/fontInfoData.py#L368-L370
+        set_if_absent(GlobalMetric::VheaCaretSlopeRise, 0.0);
+        set_if_absent(GlobalMetric::VheaCaretSlopeRun, 1.0);
+        set_if_absent(GlobalMetric::VheaCaretOffset, 0.0);
+
         // https://github.com/googlefonts/ufo2ft/blob/0d2688cd847d003b41104534d16973f72ef26c40/Lib/ufo2ft/fontInfoData.py#L241-L254
         set_if_absent(GlobalMetric::Os2WinAscent, ascender + typo_line_gap);
         set_if_absent(GlobalMetric::Os2WinDescent, descender.abs());
@@ -758,7 +1192,8 @@ impl GlobalMetrics {
         );
     }
 
-    fn values(&self, metric: GlobalMetric) -> &GlobalMetricValues {
+    /// The values of a metric at every location that defines it
+    pub fn values(&self, metric: GlobalMetric) -> &GlobalMetricValues {
         // We presume that ctor initializes for every GlobalMetric
         self.0.get(&metric).unwrap()
     }
@@ -831,6 +1266,12 @@ impl GlobalMetrics {
             hhea_ascender: self.get(GlobalMetric::HheaAscender, pos),
             hhea_descender: self.get(GlobalMetric::HheaDescender, pos),
             hhea_line_gap: self.get(GlobalMetric::HheaLineGap, pos),
+            vhea_ascender: self.get(GlobalMetric::VheaAscender, pos),
+            vhea_descender: self.get(GlobalMetric::VheaDescender, pos),
+            vhea_line_gap: self.get(GlobalMetric::VheaLineGap, pos),
+            vhea_caret_slope_rise: self.get(GlobalMetric::VheaCaretSlopeRise, pos),
+            vhea_caret_slope_run: self.get(GlobalMetric::VheaCaretSlopeRun, pos),
+            vhea_caret_offset: self.get(GlobalMetric::VheaCaretOffset, pos),
             underline_thickness: self.get(GlobalMetric::UnderlineThickness, pos),
             underline_position: self.get(GlobalMetric::UnderlinePosition, pos),
         }
@@ -860,6 +1301,12 @@ pub struct GlobalMetricsInstance {
     pub hhea_ascender: OrderedFloat<f64>,
     pub hhea_descender: OrderedFloat<f64>,
     pub hhea_line_gap: OrderedFloat<f64>,
+    pub vhea_ascender: OrderedFloat<f64>,
+    pub vhea_descender: OrderedFloat<f64>,
+    pub vhea_line_gap: OrderedFloat<f64>,
+    pub vhea_caret_slope_rise: OrderedFloat<f64>,
+    pub vhea_caret_slope_run: OrderedFloat<f64>,
+    pub vhea_caret_offset: OrderedFloat<f64>,
     pub strikeout_position: OrderedFloat<f64>,
     pub strikeout_size: OrderedFloat<f64>,
     pub subscript_x_offset: OrderedFloat<f64>,
@@ -916,6 +1363,12 @@ pub mod test_helpers {
                 hhea_ascender: self.hhea_ascender.round2(),
                 hhea_descender: self.hhea_descender.round2(),
                 hhea_line_gap: self.hhea_line_gap.round2(),
+                vhea_ascender: self.vhea_ascender.round2(),
+                vhea_descender: self.vhea_descender.round2(),
+                vhea_line_gap: self.vhea_line_gap.round2(),
+                vhea_caret_slope_rise: self.vhea_caret_slope_rise.round2(),
+                vhea_caret_slope_run: self.vhea_caret_slope_run.round2(),
+                vhea_caret_offset: self.vhea_caret_offset.round2(),
                 underline_thickness: self.underline_thickness.round2(),
                 underline_position: self.underline_position.round2(),
                 caret_slope_run: self.caret_slope_run.round2(),
@@ -1260,6 +1713,85 @@ impl FeaturesSource {
     }
 }
 
+/// Glyph substitutions that only apply in parts of the design space.
+///
+/// For example, designspace `<rules>`. Compiled into GSUB
+/// [FeatureVariations](https://learn.microsoft.com/en-us/typography/opentype/spec/chapter2#featurevariations-table).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct FeatureVariations {
+    /// The features the substitutions are registered under
+    pub features: Vec<Tag>,
+    /// In source order; where regions overlap and substitutions conflict the earlier one wins
+    pub rules: Vec<ConditionalSubstitution>,
+}
+
+impl FeatureVariations {
+    /// The feature used when substitutions should happen before any other
+    pub const PROCESS_FIRST_FEATURE: Tag = Tag::new(b"rvrn");
+    /// The feature used when substitutions should happen after other substitutions
+    pub const PROCESS_LAST_FEATURE: Tag = Tag::new(b"rclt");
+
+    pub fn new(features: Vec<Tag>, rules: Vec<ConditionalSubstitution>) -> Self {
+        FeatureVariations { features, rules }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.features.is_empty() || self.rules.iter().all(|r| r.substitutions.is_empty())
+    }
+
+    /// Whether the lookups for our substitutions go ahead of all other lookups.
+    ///
+    /// Matches fontTools, only the case if we are registered solely under `rvrn`.
+    pub fn process_first(&self) -> bool {
+        self.features == [Self::PROCESS_FIRST_FEATURE]
+    }
+}
+
+/// Substitutions that apply wherever any of the condition sets are met.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ConditionalSubstitution {
+    pub condition_sets: Vec<ConditionSet>,
+    pub substitutions: BTreeMap<GlyphName, GlyphName>,
+}
+
+/// A box in normalized space, met if every condition is met.
+///
+/// Axes without a condition are unconstrained so an empty set is met everywhere.
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ConditionSet(Vec<Condition>);
+
+/// Met if the position on axis is within min..=max.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Condition {
+    pub axis: Tag,
+    pub min: NormalizedCoord,
+    pub max: NormalizedCoord,
+}
+
+impl ConditionSet {
+    pub fn new(conditions: impl IntoIterator<Item = Condition>) -> Self {
+        let mut conditions: Vec<_> = conditions.into_iter().collect();
+        conditions.sort_by_key(|c| c.axis);
+        ConditionSet(conditions)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Condition> {
+        self.0.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Axes we have no condition for are taken to be at their default
+    pub fn contains(&self, location: &NormalizedLocation) -> bool {
+        self.0.iter().all(|c| {
+            let pos = location.get(c.axis).unwrap_or_default();
+            c.min <= pos && pos <= c.max
+        })
+    }
+}
+
 /// The anchors for a [Glyph]
 ///
 /// Not having any is fine.
@@ -1404,6 +1936,20 @@ impl AnchorKind {
         Ok(AnchorKind::Base(name.into()))
     }
 
+    /// The name of an anchor of this kind, such that `AnchorKind::new(kind.name())` is kind
+    pub fn name(&self) -> SmolStr {
+        match self {
+            AnchorKind::Base(group_name) => group_name.clone(),
+            AnchorKind::Mark(group_name) => format_smolstr!("_{group_name}"),
+            AnchorKind::Ligature { group_name, index } => format_smolstr!("{group_name}_{index}"),
+            AnchorKind::ComponentMarker(index) => format_smolstr!("_{index}"),
+            AnchorKind::Caret(index) => format_smolstr!("caret_{index}"),
+            AnchorKind::VCaret(index) => format_smolstr!("vcaret_{index}"),
+            AnchorKind::CursiveEntry => SmolStr::new_inline("entry"),
+            AnchorKind::CursiveExit => SmolStr::new_inline("exit"),
+        }
+    }
+
     /// Returns `true` If this is a base or ligature base anchor
     ///
     /// (i.e, if it is an anchor that marks attach to)
@@ -1607,6 +2153,10 @@ impl Glyph {
         })
     }
 
+    pub fn default_location(&self) -> &NormalizedLocation {
+        &self.default_location
+    }
+
     pub fn default_instance(&self) -> &GlyphInstance {
         self.sources.get(&self.default_location).unwrap()
     }
@@ -1712,6 +2262,26 @@ impl Persistable for FeaturesSource {
     }
 }
 
+impl Persistable for VariableComposites {
+    fn read(from: &mut dyn Read) -> Self {
+        serde_yaml::from_reader(from).unwrap()
+    }
+
+    fn write(&self, to: &mut dyn std::io::Write) {
+        serde_yaml::to_writer(to, self).unwrap();
+    }
+}
+
+impl Persistable for FeatureVariations {
+    fn read(from: &mut dyn Read) -> Self {
+        serde_yaml::from_reader(from).unwrap()
+    }
+
+    fn write(&self, to: &mut dyn std::io::Write) {
+        serde_yaml::to_writer(to, self).unwrap();
+    }
+}
+
 impl Persistable for KerningGroups {
     fn read(from: &mut dyn Read) -> Self {
         serde_yaml::from_reader(from).unwrap()
@@ -1893,6 +2463,9 @@ pub struct GlyphInstance {
     pub width: f64,
     /// Advance height; if None, assumed to equal font's ascender - descender.
     pub height: Option<f64>,
+    /// Y of the vertical origin; if None, assumed to equal font's OS/2 typo ascender.
+    #[serde(default)]
+    pub vertical_origin: Option<f64>,
     /// List of glyph contours.
     pub contours: Vec<BezPath>,
     /// List of glyph components.
@@ -1928,6 +2501,162 @@ pub struct Component {
     pub base: GlyphName,
     /// Affine transformation to apply to the referenced glyph.
     pub transform: Affine,
+    /// Set for a variable component, one that picks its own location in designspace.
+    ///
+    /// If set `transform` is the affine form of the variable component transform.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variable: Option<VariableComponent>,
+    /// The anchor the component attaches to, if the source names one.
+    ///
+    /// Glyphs sources name the anchor of a ligature a mark sits on, such as 'top_2',
+    /// so propagated anchors can be numbered for it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub anchor: Option<SmolStr>,
+}
+
+impl Component {
+    /// The transform of the component, by parts.
+    pub fn decomposed_transform(&self) -> DecomposedTransform {
+        match &self.variable {
+            Some(variable) => variable.transform,
+            None => DecomposedTransform::from_affine(self.transform),
+        }
+    }
+}
+
+/// What a variable component has that a plain component does not.
+///
+/// See the [VARC](https://github.com/harfbuzz/boring-expansion-spec/blob/main/VARC.md) table.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct VariableComponent {
+    /// Where in designspace to use the referenced glyph.
+    ///
+    /// Axes not mentioned are wherever the glyph using the component is.
+    pub location: NormalizedLocation,
+    /// The transform by parts, so it interpolates part by part
+    pub transform: DecomposedTransform,
+}
+
+impl VariableComponent {
+    pub fn new(location: NormalizedLocation, transform: DecomposedTransform) -> Self {
+        VariableComponent {
+            location,
+            transform,
+        }
+    }
+}
+
+/// A transform expressed as translation, rotation, scale and skew around a center.
+///
+/// Angles are in degrees. Rotation and skew_y are counter-clockwise, skew_x is clockwise.
+///
+/// See fontTools [DecomposedTransform](https://github.com/fonttools/fonttools/blob/0572f7871823bdef3ceceaf41dedd0a6bd100995/Lib/fontTools/misc/transform.py#L410-L424)
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct DecomposedTransform {
+    pub translate_x: f64,
+    pub translate_y: f64,
+    pub rotation: f64,
+    pub scale_x: f64,
+    pub scale_y: f64,
+    pub skew_x: f64,
+    pub skew_y: f64,
+    pub t_center_x: f64,
+    pub t_center_y: f64,
+}
+
+impl Default for DecomposedTransform {
+    fn default() -> Self {
+        DecomposedTransform {
+            translate_x: 0.0,
+            translate_y: 0.0,
+            rotation: 0.0,
+            scale_x: 1.0,
+            scale_y: 1.0,
+            skew_x: 0.0,
+            skew_y: 0.0,
+            t_center_x: 0.0,
+            t_center_y: 0.0,
+        }
+    }
+}
+
+impl DecomposedTransform {
+    /// <https://github.com/fonttools/fonttools/blob/0572f7871823bdef3ceceaf41dedd0a6bd100995/Lib/fontTools/misc/transform.py#L463-L481>
+    pub fn to_affine(&self) -> Affine {
+        let skew = Affine::new([
+            1.0,
+            self.skew_y.to_radians().tan(),
+            (-self.skew_x).to_radians().tan(),
+            1.0,
+            0.0,
+            0.0,
+        ]);
+        Affine::translate((
+            self.translate_x + self.t_center_x,
+            self.translate_y + self.t_center_y,
+        )) * Affine::rotate(self.rotation.to_radians())
+            * Affine::scale_non_uniform(self.scale_x, self.scale_y)
+            * skew
+            * Affine::translate((-self.t_center_x, -self.t_center_y))
+    }
+
+    /// The parts of an affine, with no center and no skew_y.
+    ///
+    /// <https://github.com/fonttools/fonttools/blob/0572f7871823bdef3ceceaf41dedd0a6bd100995/Lib/fontTools/misc/transform.py#L426-L461>
+    pub fn from_affine(affine: Affine) -> Self {
+        let [mut a, mut b, c, d, x, y] = affine.as_coeffs();
+        let sign = 1f64.copysign(a);
+        a *= sign;
+        b *= sign;
+        let determinant = a * d - b * c;
+
+        let mut rotation = 0.0;
+        let (mut scale_x, mut scale_y) = (0.0, 0.0);
+        let (mut skew_x, mut skew_y) = (0.0, 0.0);
+        if a != 0.0 || b != 0.0 {
+            let r = (a * a + b * b).sqrt();
+            rotation = if b >= 0.0 {
+                (a / r).acos()
+            } else {
+                -(a / r).acos()
+            };
+            (scale_x, scale_y) = (r, determinant / r);
+            skew_x = ((a * c + b * d) / (r * r)).atan();
+        } else if c != 0.0 || d != 0.0 {
+            let s = (c * c + d * d).sqrt();
+            rotation = std::f64::consts::FRAC_PI_2
+                - if d >= 0.0 {
+                    (-c / s).acos()
+                } else {
+                    -(c / s).acos()
+                };
+            (scale_x, scale_y) = (determinant / s, s);
+            skew_y = ((a * c + b * d) / (s * s)).atan();
+        }
+
+        DecomposedTransform {
+            translate_x: x,
+            translate_y: y,
+            rotation: rotation.to_degrees(),
+            scale_x: scale_x * sign,
+            scale_y,
+            // skew_x is clockwise
+            skew_x: -skew_x.to_degrees() * sign,
+            skew_y: skew_y.to_degrees(),
+            t_center_x: 0.0,
+            t_center_y: 0.0,
+        }
+    }
+}
+
+/// Glyphs made of variable components, as they were before being decomposed.
+///
+/// Variable components can't go in glyf or CFF so the glyphs that use them are
+/// decomposed into contours. The originals are kept here to build a
+/// [VARC](https://github.com/harfbuzz/boring-expansion-spec/blob/main/VARC.md) table.
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+pub struct VariableComposites {
+    pub glyphs: BTreeMap<GlyphName, Glyph>,
 }
 
 /// Data to inform construction of [CPAL](https://learn.microsoft.com/en-us/typography/opentype/spec/cpal#palette-table-header)
@@ -1953,15 +2682,12 @@ impl ColorPalettes {
             });
         }
 
-        // Trivial optimization: if there is only one palette we can deduplicate it
+        // Trivial optimization: if there is only one palette we can deduplicate it,
+        // sorting for stability in output. Many palettes share indices so keep their order.
         if let [one_palette] = palettes.as_mut_slice() {
             one_palette.sort();
             one_palette.dedup();
         }
-        // Sort for stability in output
-        for p in palettes.iter_mut() {
-            p.sort();
-        }
 
         Ok(Some(Self { palettes }))
     }
@@ -1976,9 +2702,175 @@ pub struct Color {
     pub a: u8,
 }
 
+/// The palette index that means "use the text foreground color"
+///
+/// See <https://learn.microsoft.com/en-us/typography/opentype/spec/colr#color-references-colorstop-and-colorline>
+pub const FOREGROUND_PALETTE_INDEX: u16 = 0xFFFF;
+
 /// Data to inform construction of [COLR](https://learn.microsoft.com/en-us/typography/opentype/spec/colr)
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
-pub struct PaintGraph {}
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+pub struct PaintGraph {
+    /// The paint for every color glyph, keyed by the name of the base glyph
+    pub base_glyphs: BTreeMap<GlyphName, ColorGlyph>,
+}
+
+impl PaintGraph {
+    pub fn is_empty(&self) -> bool {
+        self.base_glyphs.is_empty()
+    }
+}
+
+/// The paint for a single color glyph.
+///
+/// If defined in many locations, presumed to vary continuously
+/// between positions and required to have variation compatible structure.
+/// That is, only the numeric values of the paint may differ between locations.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ColorGlyph {
+    pub sources: HashMap<NormalizedLocation, Paint>,
+}
+
+impl ColorGlyph {
+    /// A color glyph that doesn't vary
+    pub fn new(location: NormalizedLocation, paint: Paint) -> Self {
+        ColorGlyph {
+            sources: HashMap::from([(location, paint)]),
+        }
+    }
+}
+
+/// A node in a paint graph.
+///
+/// Closely follows the [COLRv1 paint tables](https://learn.microsoft.com/en-us/typography/opentype/spec/colr#paint-tables)
+/// but without the distinction between static and variable paints; whether a paint
+/// varies is determined by comparing the paint at each location.
+///
+/// Angles are in degrees, counter-clockwise. Coordinates are in font units.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Paint {
+    /// Paint each layer in turn, bottom to top
+    Layers(Vec<Paint>),
+    Solid {
+        palette_index: u16,
+        alpha: f64,
+    },
+    LinearGradient {
+        color_line: ColorLine,
+        p0: Point,
+        p1: Point,
+        /// Rotation point, see <https://learn.microsoft.com/en-us/typography/opentype/spec/colr#linear-gradients>
+        p2: Point,
+    },
+    RadialGradient {
+        color_line: ColorLine,
+        c0: Point,
+        r0: f64,
+        c1: Point,
+        r1: f64,
+    },
+    SweepGradient {
+        color_line: ColorLine,
+        center: Point,
+        start_angle: f64,
+        end_angle: f64,
+    },
+    /// Fill the outline of the named glyph using paint
+    Glyph {
+        name: GlyphName,
+        paint: Box<Paint>,
+    },
+    /// Reuse the paint of another color glyph
+    ColrGlyph(GlyphName),
+    Transform {
+        transform: Affine,
+        paint: Box<Paint>,
+    },
+    Translate {
+        dx: f64,
+        dy: f64,
+        paint: Box<Paint>,
+    },
+    Scale {
+        scale_x: f64,
+        scale_y: f64,
+        center: Option<Point>,
+        paint: Box<Paint>,
+    },
+    Rotate {
+        angle: f64,
+        center: Option<Point>,
+        paint: Box<Paint>,
+    },
+    Skew {
+        x_skew_angle: f64,
+        y_skew_angle: f64,
+        center: Option<Point>,
+        paint: Box<Paint>,
+    },
+    Composite {
+        source: Box<Paint>,
+        mode: CompositeMode,
+        backdrop: Box<Paint>,
+    },
+}
+
+/// See <https://learn.microsoft.com/en-us/typography/opentype/spec/colr#color-references-colorstop-and-colorline>
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+pub struct ColorLine {
+    pub extend: ExtendMode,
+    pub stops: Vec<ColorStop>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ColorStop {
+    pub offset: f64,
+    /// Index into the palettes of [ColorPalettes] or [FOREGROUND_PALETTE_INDEX]
+    pub palette_index: u16,
+    pub alpha: f64,
+}
+
+/// How a color line extends beyond its first and last stop
+#[derive(Serialize, Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ExtendMode {
+    #[default]
+    Pad,
+    Repeat,
+    Reflect,
+}
+
+/// See <https://learn.microsoft.com/en-us/typography/opentype/spec/colr#format-32-paintcomposite>
+#[derive(Serialize, Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CompositeMode {
+    Clear,
+    Src,
+    Dest,
+    #[default]
+    SrcOver,
+    DestOver,
+    SrcIn,
+    DestIn,
+    SrcOut,
+    DestOut,
+    SrcAtop,
+    DestAtop,
+    Xor,
+    Plus,
+    Screen,
+    Overlay,
+    Darken,
+    Lighten,
+    ColorDodge,
+    ColorBurn,
+    HardLight,
+    SoftLight,
+    Difference,
+    Exclusion,
+    Multiply,
+    HslHue,
+    HslSaturation,
+    HslColor,
+    HslLuminosity,
+}
 
 #[cfg(test)]
 mod tests {
@@ -2068,6 +2960,36 @@ mod tests {
                 .collect(),
                 prefer_gdef_categories_in_fea: false,
             },
+            stat_labels: StatLabels {
+                axis_values: vec![AxisValueLabel {
+                    name: "Nobody".to_string(),
+                    elidable: true,
+                    older_sibling: false,
+                    value: AxisValueLocation::Point {
+                        axis: WGHT,
+                        value: UserCoord::new(100.0),
+                        linked_value: None,
+                    },
+                }],
+                elided_fallback_name: None,
+            },
+            variation_sequences: BTreeMap::from([(
+                0xFE0F,
+                BTreeMap::from([(0x2764, GlyphName::new("heart.emoji"))]),
+            )]),
+            axis_mappings: vec![AxisMapping {
+                input: vec![(WGHT, NormalizedCoord::new(-0.5))].into(),
+                output: vec![(WGHT, NormalizedCoord::new(-0.25))].into(),
+            }],
+            glyph_filters: vec![GlyphFilter {
+                kind: GlyphFilterKind::Transformations(Transformations {
+                    slant: 10.0.into(),
+                    origin: TransformationOrigin::HalfXHeight,
+                    ..Default::default()
+                }),
+                pre: true,
+                glyphs: GlyphSelection::Exclude(BTreeSet::from([GlyphName::new("A")])),
+            }],
             misc: MiscMetadata {
                 fs_type: None,
                 is_fixed_pitch: None,
@@ -2086,6 +3008,8 @@ mod tests {
                 us_weight_class: None,
                 us_width_class: None,
                 gasp: Vec::new(),
+                build_vertical: false,
+                postscript_hints: Default::default(),
             },
             number_values: Default::default(),
         }
@@ -2127,6 +3051,138 @@ mod tests {
         assert_bincode_round_trip(test_static_metadata());
     }
 
+    #[test]
+    fn apply_axis_mappings_interpolates_between_inputs() {
+        let mut static_metadata = test_static_metadata();
+        static_metadata.axis_mappings = vec![AxisMapping {
+            input: vec![(WGHT, NormalizedCoord::new(0.5))].into(),
+            output: vec![(WGHT, NormalizedCoord::new(0.75))].into(),
+        }];
+        let actual: Vec<_> = [-0.5, 0.0, 0.25, 0.5, 0.75, 1.0]
+            .into_iter()
+            .map(|pos| {
+                let location = vec![(WGHT, NormalizedCoord::new(pos))].into();
+                static_metadata
+                    .apply_axis_mappings(&location)
+                    .unwrap()
+                    .get(WGHT)
+                    .unwrap()
+                    .to_f64()
+            })
+            .collect();
+        // as in avar 2, the mapping's influence ends at the furthest input on each side
+        assert_eq!(vec![-0.5, 0.0, 0.375, 0.75, 0.75, 1.0], actual);
+    }
+
+    #[test]
+    fn no_axis_mappings_is_identity() {
+        let mut static_metadata = test_static_metadata();
+        static_metadata.axis_mappings.clear();
+        let location: NormalizedLocation = vec![(WGHT, NormalizedCoord::new(0.3))].into();
+        assert_eq!(
+            location,
+            static_metadata.apply_axis_mappings(&location).unwrap()
+        );
+    }
+
+    #[test]
+    fn stat_labels_claim_missing_names() {
+        let mut static_metadata = test_static_metadata();
+        let mut stat_labels = static_metadata.stat_labels.clone();
+        let mut somebody = stat_labels.axis_values[0].clone();
+        somebody.name = "Somebody".to_string();
+        stat_labels.axis_values.push(somebody);
+        stat_labels.elided_fallback_name = Some("Fam".to_string());
+        static_metadata.set_stat_labels(stat_labels);
+
+        let mut names: Vec<_> = static_metadata
+            .names
+            .iter()
+            .map(|(key, name)| (key.name_id.to_u16(), name.as_str()))
+            .collect();
+        names.sort();
+        assert_eq!(
+            vec![
+                (1, "Fam"),
+                (256, "Weight"),
+                (257, "Nobody"),
+                (258, "Somebody")
+            ],
+            names
+        );
+    }
+
+    #[test]
+    fn paint_graph_yaml() {
+        let color_line = ColorLine {
+            extend: ExtendMode::Reflect,
+            stops: vec![
+                ColorStop {
+                    offset: 0.0,
+                    palette_index: 0,
+                    alpha: 1.0,
+                },
+                ColorStop {
+                    offset: 1.0,
+                    palette_index: FOREGROUND_PALETTE_INDEX,
+                    alpha: 0.5,
+                },
+            ],
+        };
+        let paint = Paint::Composite {
+            source: Box::new(Paint::Glyph {
+                name: "A".into(),
+                paint: Box::new(Paint::LinearGradient {
+                    color_line,
+                    p0: Point::new(0.0, 0.0),
+                    p1: Point::new(100.0, 0.0),
+                    p2: Point::new(0.0, 100.0),
+                }),
+            }),
+            mode: CompositeMode::Multiply,
+            backdrop: Box::new(Paint::Rotate {
+                angle: 45.0,
+                center: Some(Point::new(50.0, 50.0)),
+                paint: Box::new(Paint::ColrGlyph("B".into())),
+            }),
+        };
+        assert_yml_round_trip(PaintGraph {
+            base_glyphs: BTreeMap::from([(
+                "C".into(),
+                ColorGlyph::new(NormalizedLocation::for_pos(&[("wght", 0.0)]), paint),
+            )]),
+        });
+    }
+
+    #[test]
+    fn feature_variations_yaml() {
+        assert_yml_round_trip(FeatureVariations::new(
+            vec![FeatureVariations::PROCESS_FIRST_FEATURE],
+            vec![ConditionalSubstitution {
+                condition_sets: vec![ConditionSet::new([Condition {
+                    axis: WGHT,
+                    min: NormalizedCoord::new(0.5),
+                    max: NormalizedCoord::new(1.0),
+                }])],
+                substitutions: BTreeMap::from([("dollar".into(), "dollar.heavy".into())]),
+            }],
+        ));
+    }
+
+    #[test]
+    fn condition_set_contains() {
+        let condition_set = ConditionSet::new([Condition {
+            axis: WGHT,
+            min: NormalizedCoord::new(0.5),
+            max: NormalizedCoord::new(1.0),
+        }]);
+        assert!(condition_set.contains(&NormalizedLocation::for_pos(&[("wght", 0.5)])));
+        assert!(condition_set.contains(&NormalizedLocation::for_pos(&[("wght", 1.0)])));
+        assert!(!condition_set.contains(&NormalizedLocation::for_pos(&[("wght", 0.0)])));
+        assert!(!condition_set.contains(&NormalizedLocation::for_pos(&[("wdth", 1.0)])));
+        assert!(ConditionSet::default().contains(&NormalizedLocation::for_pos(&[("wght", 0.0)])));
+    }
+
     // from
     // <https://github.com/googlefonts/ufo2ft/blob/6787e37e6/tests/featureWriters/markFeatureWriter_test.py#L34>
     #[test]
@@ -2154,6 +3210,22 @@ mod tests {
         );
     }
 
+    #[test]
+    fn anchor_kind_names_round_trip() {
+        for name in [
+            "top",
+            "_top",
+            "top_right_2",
+            "_3",
+            "caret_1",
+            "vcaret_2",
+            "entry",
+            "exit",
+        ] {
+            assert_eq!(name, AnchorKind::new(name).unwrap().name());
+        }
+    }
+
     #[test]
     fn caret_anchor_names() {
         assert_eq!(AnchorKind::new("caret_1"), Ok(AnchorKind::Caret(1)));
@@ -2357,4 +3429,92 @@ mod tests {
             .ot_round();
         assert_eq!(451, rounded);
     }
+
+    // we had a bug where we were updating the kerning values in place, which
+    // meant the order in which we handled pairs could influence the results
+    #[test]
+    fn alignment_determinism() {
+        let g1 = GlyphName::new("a");
+        let g2 = GlyphName::new("b");
+        let side1 = KernGroup::Side1("aa".into());
+        let side2 = KernGroup::Side2("bb".into());
+        let side1_glyphs = HashMap::from([(&g1, &side1)]);
+        let side2_glyphs = HashMap::from([(&g2, &side2)]);
+
+        let glyph_glyph: KernPair = (g1.clone().into(), g2.clone().into());
+        let glyph_group: KernPair = (g1.clone().into(), side2.clone().into());
+        let group_glyph: KernPair = (side1.clone().into(), g2.clone().into());
+        let group_group: KernPair = (side1.clone().into(), side2.clone().into());
+
+        let all_pairs = HashSet::from([
+            glyph_glyph.clone(),
+            glyph_group.clone(),
+            group_glyph.clone(),
+            group_group.clone(),
+        ]);
+        let mut kerns = BTreeMap::new();
+        kerns.insert(group_group.clone(), OrderedFloat::from(-70.));
+        kerns.insert(group_glyph.clone(), 10.0.into());
+        // explanation:
+        // we need to align glyph_glyph and glyph_group.
+        // - if we do glyph_group first, we will use the group_group value of
+        //   -70, and then when we do glyph_glyph we will use this value, since
+        //   glyph_group is preferred to group_glyph
+        // - but if we do glyph_glyph first, we will use the value from
+        //   group_glyph, which is set.
+
+        // run a few times because triggering depended on hashmap iteration order
+        for _ in 0..20 {
+            align_instance(&all_pairs, &mut kerns, &side1_glyphs, &side2_glyphs);
+            assert_eq!(kerns.get(&glyph_glyph).map(|x| x.0), Some(10.0f64));
+        }
+    }
+
+    fn assert_same_affine(expected: Affine, actual: Affine) {
+        let close = expected
+            .as_coeffs()
+            .iter()
+            .zip(actual.as_coeffs())
+            .all(|(e, a)| (e - a).abs() < 1e-9);
+        assert!(close, "{expected:?} != {actual:?}");
+    }
+
+    #[test]
+    fn decomposed_transform_round_trip() {
+        for affine in [
+            Affine::IDENTITY,
+            Affine::translate((10.0, -20.0)),
+            Affine::rotate(0.5) * Affine::scale_non_uniform(2.0, 0.5),
+            Affine::new([1.0, 0.0, 0.25, 1.0, 5.0, 0.0]),
+            Affine::scale_non_uniform(-1.0, 1.0),
+            Affine::new([0.0, -1.0, 1.0, 0.0, 3.0, 4.0]),
+        ] {
+            assert_same_affine(affine, DecomposedTransform::from_affine(affine).to_affine());
+        }
+    }
+
+    #[test]
+    fn decomposed_transform_skew_x_is_negated() {
+        let transform = DecomposedTransform {
+            skew_x: 45.0,
+            ..Default::default()
+        };
+        // as in fontTools the x skew angle is negated before use
+        assert_same_affine(
+            Affine::new([1.0, 0.0, -1.0, 1.0, 0.0, 0.0]),
+            transform.to_affine(),
+        );
+    }
+
+    #[test]
+    fn decomposed_transform_rotates_around_center() {
+        let transform = DecomposedTransform {
+            rotation: 90.0,
+            t_center_x: 100.0,
+            t_center_y: 0.0,
+            ..Default::default()
+        };
+        let center = transform.to_affine() * Point::new(100.0, 0.0);
+        assert!((center - Point::new(100.0, 0.0)).hypot() < 1e-9);
+    }
 }
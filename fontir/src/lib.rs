@@ -1,9 +1,13 @@
 //! Intermediate Representation (IR) types for font compilation
 
 pub mod error;
+mod filters;
 pub mod glyph;
+pub mod incremental;
+pub mod instancer;
 pub mod ir;
 pub mod orchestration;
 pub mod paths;
+mod propagate_anchors;
 pub mod source;
 pub mod variations;
@@ -38,6 +38,12 @@ bitflags! {
         const KEEP_DIRECTION = 0b01000000;
         // If set, production names are read & used
         const PRODUCTION_NAMES = 0b10000000;
+        // If set, outlines are emitted as CFF (static) or CFF2 (variable) rather than glyf/gvar
+        const CFF_OUTLINES = 0b100000000;
+        // If set, anchors of components are copied to the composites using them
+        const PROPAGATE_ANCHORS = 0b1000000000;
+        // If set, variable glyf only flags glyphs whose outline actually overlaps, not every glyph
+        const DETECT_OVERLAPS = 0b10000000000;
     }
 }
 
@@ -224,6 +230,20 @@ where
         self.try_get(id)
             .unwrap_or_else(|| panic!("{:?} is not available", id))
     }
+
+    /// Take the value written down by a prior execution instead of computing it.
+    ///
+    /// None if persistent storage is inactive or has nothing for id.
+    pub fn restore(&self, id: &I) -> Option<Arc<T>> {
+        self.acl.assert_write_access(id);
+        if !self.persistent_storage.active() {
+            return None;
+        }
+        let mut reader = self.persistent_storage.reader(id)?;
+        let restored = Arc::from(T::read(&mut reader));
+        self.value.write().insert(id.clone(), restored.clone());
+        Some(restored)
+    }
 }
 
 impl<I, T, Ir> ContextMap<I, T, Ir>
@@ -311,6 +331,8 @@ pub enum WorkId {
     /// The final glyph order. Most things that need glyph order should rely on this.
     GlyphOrder,
     Features,
+    /// Substitutions that apply in parts of the design space, e.g. designspace rules
+    FeatureVariations,
     KerningGroups,
     KernInstance(NormalizedLocation),
     Anchor(GlyphName),
@@ -318,6 +340,8 @@ pub enum WorkId {
     ColorPalettes,
     /// COLR data
     PaintGraph,
+    /// Glyphs that use variable components, as they were before decomposition
+    VariableComposites,
 }
 
 impl WorkId {
@@ -336,11 +360,13 @@ impl Identifier for WorkId {
             WorkId::PreliminaryGlyphOrder => "IrPreliminaryGlyphOrder",
             WorkId::GlyphOrder => "IrGlyphOrder",
             WorkId::Features => "IrFeatures",
+            WorkId::FeatureVariations => "IrFeatureVariations",
             WorkId::KerningGroups => "IrKerningGroups",
             WorkId::KernInstance(..) => "IrKernInstance",
             WorkId::Anchor(..) => "IrAnchor",
             WorkId::ColorPalettes => "IrPalettes",
             WorkId::PaintGraph => "IrPaints",
+            WorkId::VariableComposites => "IrVariableComposites",
         }
     }
 }
@@ -414,11 +440,13 @@ pub struct Context {
     pub global_metrics: FeContextItem<ir::GlobalMetrics>,
     pub glyphs: FeContextMap<ir::Glyph>,
     pub features: FeContextItem<ir::FeaturesSource>,
+    pub feature_variations: FeContextItem<ir::FeatureVariations>,
     pub kerning_groups: FeContextItem<ir::KerningGroups>,
     pub kerning_at: FeContextMap<ir::KerningInstance>,
     pub anchors: FeContextMap<ir::GlyphAnchors>,
     pub colors: FeContextItem<ir::ColorPalettes>,
     pub paint_graph: FeContextItem<ir::PaintGraph>,
+    pub variable_composites: FeContextItem<ir::VariableComposites>,
 }
 
 pub fn set_cached<T>(lock: &Arc<RwLock<Option<Arc<T>>>>, value: T) {
@@ -438,11 +466,13 @@ impl Context {
             global_metrics: self.global_metrics.clone_with_acl(acl.clone()),
             glyphs: self.glyphs.clone_with_acl(acl.clone()),
             features: self.features.clone_with_acl(acl.clone()),
+            feature_variations: self.feature_variations.clone_with_acl(acl.clone()),
             kerning_groups: self.kerning_groups.clone_with_acl(acl.clone()),
             kerning_at: self.kerning_at.clone_with_acl(acl.clone()),
             anchors: self.anchors.clone_with_acl(acl.clone()),
             colors: self.colors.clone_with_acl(acl.clone()),
-            paint_graph: self.paint_graph.clone_with_acl(acl),
+            paint_graph: self.paint_graph.clone_with_acl(acl.clone()),
+            variable_composites: self.variable_composites.clone_with_acl(acl),
         }
     }
 
@@ -477,6 +507,11 @@ impl Context {
             ),
             glyphs: ContextMap::new(acl.clone(), persistent_storage.clone()),
             features: ContextItem::new(WorkId::Features, acl.clone(), persistent_storage.clone()),
+            feature_variations: ContextItem::new(
+                WorkId::FeatureVariations,
+                acl.clone(),
+                persistent_storage.clone(),
+            ),
             kerning_groups: ContextItem::new(
                 WorkId::KerningGroups,
                 acl.clone(),
@@ -489,7 +524,16 @@ impl Context {
                 acl.clone(),
                 persistent_storage.clone(),
             ),
-            paint_graph: ContextItem::new(WorkId::PaintGraph, acl, persistent_storage),
+            paint_graph: ContextItem::new(
+                WorkId::PaintGraph,
+                acl.clone(),
+                persistent_storage.clone(),
+            ),
+            variable_composites: ContextItem::new(
+                WorkId::VariableComposites,
+                acl,
+                persistent_storage,
+            ),
         }
     }
 
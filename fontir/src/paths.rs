@@ -12,6 +12,7 @@ pub struct Paths {
     anchor_ir_dir: PathBuf,
     glyph_ir_dir: PathBuf,
     ir_input_file: PathBuf,
+    build_state_file: PathBuf,
 }
 
 impl Paths {
@@ -20,11 +21,13 @@ impl Paths {
         let anchor_ir_dir = build_dir.join("anchor_ir");
         let glyph_ir_dir = build_dir.join("glyph_ir");
         let ir_input_file = build_dir.join("irinput.yml");
+        let build_state_file = build_dir.join("build_state.yml");
         Paths {
             build_dir,
             anchor_ir_dir,
             glyph_ir_dir,
             ir_input_file,
+            build_state_file,
         }
     }
 
@@ -44,6 +47,11 @@ impl Paths {
         &self.ir_input_file
     }
 
+    /// Describes the compilation that produced the IR in the build dir, see [crate::incremental]
+    pub fn build_state_file(&self) -> &Path {
+        &self.build_state_file
+    }
+
     fn anchor_ir_file(&self, name: &str) -> PathBuf {
         self.anchor_ir_dir.join(string_to_filename(name, ".yml"))
     }
@@ -72,10 +80,12 @@ impl Paths {
             WorkId::GlobalMetrics => self.build_dir.join("global_metrics.yml"),
             WorkId::Glyph(name) => self.glyph_ir_file(name.as_str()),
             WorkId::Features => self.build_dir.join("features.yml"),
+            WorkId::FeatureVariations => self.build_dir.join("feature_variations.yml"),
             WorkId::KerningGroups => self.build_dir.join("kern_groups.yml"),
             WorkId::KernInstance(location) => self.kern_ir_file(location),
             WorkId::ColorPalettes => self.build_dir.join("colors.yml"),
             WorkId::PaintGraph => self.build_dir.join("paint_graph.yml"),
+            WorkId::VariableComposites => self.build_dir.join("variable_composites.yml"),
         }
     }
 }
@@ -0,0 +1,1001 @@
+//! Propagating anchors from components to their composites
+//!
+//! Glyphs.app has a nice feature where anchors defined in the components
+//! of composite glyphs are copied into the composites themselves. This feature
+//! is not very extensively documented, and the code here is based off the
+//! Objective-C implementation, which was shared with us privately.
+//!
+//! It runs over IR so it works for any source. Glyphs sources get it unless they
+//! turn it off with the 'Propagate Anchors' custom parameter, other sources ask for
+//! it with the ufo2ft propagateAnchors filter, or it can be requested with
+//! [Flags::PROPAGATE_ANCHORS](crate::orchestration::Flags).
+//!
+//! Glyph categories come from [GdefCategories](crate::ir::GdefCategories). When a glyph
+//! has none a glyph with mark anchors is taken to be a mark and a glyph whose name
+//! joins others with an underscore, such as 'f_i', to be a ligature.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
+
+use fontdrasil::{coords::NormalizedLocation, types::GlyphName};
+use indexmap::IndexMap;
+use kurbo::{Affine, Point, Vec2};
+use log::{trace, warn};
+use smol_str::{format_smolstr, SmolStr};
+use write_fonts::tables::gdef::GlyphClassDef;
+
+use crate::{
+    error::BadGlyph,
+    ir::{AnchorBuilder, Glyph, GlyphAnchors, GlyphOrder, GlyphSelection},
+    orchestration::{Context, WorkId},
+};
+
+/// The name of the anchor that moves the origin of the other anchors of a glyph
+const ORIGIN: &str = "*origin";
+
+/// Anchor positions by name, in the order they were added
+type Anchors = IndexMap<SmolStr, Point>;
+
+/// Copy anchors from components into the selected composites of glyph_order.
+pub(crate) fn propagate_anchors(
+    context: &Context,
+    glyph_order: &GlyphOrder,
+    selection: &GlyphSelection,
+) -> Result<(), BadGlyph> {
+    let static_metadata = context.static_metadata.get();
+    let categories = &static_metadata.gdef_categories.categories;
+    let glyphs: BTreeMap<_, _> = glyph_order
+        .names()
+        .map(|name| (name.clone(), context.get_glyph(name.clone())))
+        .collect();
+    let own_anchors: HashMap<_, _> = glyphs
+        .keys()
+        .filter_map(|name| {
+            context
+                .anchors
+                .try_get(&WorkId::Anchor(name.clone()))
+                .map(|anchors| (name.clone(), anchors_by_location(&anchors)))
+        })
+        .collect();
+
+    // Components are always done before the glyphs using them
+    let mut done: HashMap<GlyphName, HashMap<NormalizedLocation, Anchors>> = HashMap::new();
+    let mut base_glyph_counts = HashMap::new();
+    for name in depth_sorted_composite_glyphs(&glyphs) {
+        let glyph = glyphs.get(&name).unwrap();
+        let own = own_anchors.get(&name);
+        let category = categories.get(&name);
+        let is_mark = match category {
+            Some(category) => *category == GlyphClassDef::Mark,
+            None => own.is_some_and(|own| {
+                own.values()
+                    .flat_map(|anchors| anchors.keys())
+                    .any(|anchor_name| anchor_name.starts_with('_'))
+            }),
+        };
+        let is_ligature = match category {
+            Some(category) => *category == GlyphClassDef::Ligature,
+            None => name
+                .as_str()
+                .split('.')
+                .next()
+                .is_some_and(|base_name| base_name.trim_start_matches('_').contains('_')),
+        };
+        for location in glyph.sources().keys() {
+            let empty = Anchors::new();
+            let own = own.and_then(|own| own.get(location)).unwrap_or(&empty);
+            let anchors = anchors_traversing_components(
+                glyph,
+                location,
+                own,
+                is_mark,
+                is_ligature,
+                &done,
+                &mut base_glyph_counts,
+            );
+            done.entry(name.clone())
+                .or_default()
+                .insert(location.clone(), anchors);
+        }
+    }
+
+    // Only composites get new anchors
+    let default_location = static_metadata.default_location();
+    for (name, by_location) in done {
+        let glyph = glyphs.get(&name).unwrap();
+        if !selection.contains(&name)
+            || glyph
+                .sources()
+                .values()
+                .all(|inst| inst.components.is_empty())
+        {
+            continue;
+        }
+        let Some(default_anchors) = by_location.get(default_location) else {
+            continue;
+        };
+        if default_anchors.is_empty() && !own_anchors.contains_key(&name) {
+            continue;
+        }
+        let mut anchors = AnchorBuilder::new(name.clone());
+        for (location, location_anchors) in by_location.iter() {
+            for (anchor_name, pos) in location_anchors {
+                // A mark can't attach to an anchor that isn't at the default
+                if !default_anchors.contains_key(anchor_name) {
+                    warn!("'{name}' has no default position for propagated anchor '{anchor_name}'");
+                    continue;
+                }
+                anchors.add(anchor_name.clone(), location.clone(), *pos)?;
+            }
+        }
+        let anchors = anchors.build()?;
+        trace!(
+            "propagated anchors for '{name}': {:?}",
+            anchors
+                .anchors
+                .iter()
+                .map(|anchor| anchor.kind.name())
+                .collect::<Vec<_>>()
+        );
+        context.anchors.set(anchors);
+    }
+    Ok(())
+}
+
+fn anchors_by_location(anchors: &GlyphAnchors) -> HashMap<NormalizedLocation, Anchors> {
+    let mut by_location: HashMap<NormalizedLocation, Anchors> = HashMap::new();
+    for anchor in anchors.anchors.iter() {
+        for (location, pos) in anchor.positions.iter() {
+            by_location
+                .entry(location.clone())
+                .or_default()
+                .insert(anchor.kind.name(), *pos);
+        }
+    }
+    for anchors in by_location.values_mut() {
+        // IR doesn't keep the source order of anchors, use a stable one
+        anchors.sort_keys();
+        // The origin anchor moves the others and goes no further
+        if let Some(origin) = anchors.shift_remove(ORIGIN) {
+            anchors
+                .values_mut()
+                .for_each(|pos| *pos -= origin.to_vec2());
+        }
+    }
+    by_location
+}
+
+/// Return the anchors for glyph at location, including anchors from components
+///
+/// This function is a reimplementation of a similarly named function in glyphs.app.
+///
+/// The logic for copying anchors from components into their containing composites
+/// is tricky. Anchors need to be adjusted in various ways:
+///
+/// - a special "*origin" anchor may exist, which modifies the position of other anchors
+/// - if a component is flipped on the x or y axes, we rename "top" to "bottom"
+///   and/or "left" to "right"
+/// - we need to apply the transform from the component
+/// - we may need to rename an anchor when the component is part of a ligature glyph
+fn anchors_traversing_components(
+    glyph: &Glyph,
+    location: &NormalizedLocation,
+    own: &Anchors,
+    is_mark: bool,
+    is_ligature: bool,
+    done: &HashMap<GlyphName, HashMap<NormalizedLocation, Anchors>>,
+    // each (glyph, location) writes its number of base glyphs into this map during traversal
+    base_glyph_counts: &mut HashMap<(GlyphName, NormalizedLocation), usize>,
+) -> Anchors {
+    let components = &glyph.sources().get(location).unwrap().components;
+    if own.is_empty() && components.is_empty() {
+        return Anchors::new();
+    }
+
+    // if this is a mark and it has anchors, just return them
+    if !own.is_empty() && is_mark {
+        return own.clone();
+    }
+
+    let mut has_underscore = own.keys().any(|name| name.starts_with('_'));
+    let mut number_of_base_glyphs = 0usize;
+    let mut all_anchors = Anchors::new();
+    for (component_idx, component) in components.iter().enumerate() {
+        let Some(anchors) = done
+            .get(&component.base)
+            .and_then(|by_location| by_location.get(location))
+        else {
+            warn!(
+                "could not get anchors at {location:?} for component '{}' of glyph '{}'",
+                component.base, glyph.name
+            );
+            continue;
+        };
+        // if this component has an explicitly set attachment anchor, use it
+        let renamed;
+        let anchors = match component.anchor.as_ref().filter(|_| component_idx > 0) {
+            Some(component_anchor) => {
+                renamed = renamed_component_anchor(component_anchor, anchors);
+                &renamed
+            }
+            None => anchors,
+        };
+        let component_number_of_base_glyphs = base_glyph_counts
+            .get(&(component.base.clone(), location.clone()))
+            .copied()
+            .unwrap_or(0);
+
+        let comb_has_underscore = anchors
+            .keys()
+            .any(|name| name.len() >= 2 && name.starts_with('_'));
+        let comb_has_exit = anchors.keys().any(|name| name.ends_with("exit"));
+        if !(comb_has_underscore | comb_has_exit) {
+            // delete exit anchors we may have taken from earlier components
+            all_anchors.retain(|name, _| !name.ends_with("exit"));
+        }
+
+        let scale = get_xy_rotation(component.transform);
+        for (name, pos) in anchors {
+            let new_has_underscore = name.starts_with('_');
+            if (component_idx > 0 || has_underscore) && new_has_underscore {
+                continue;
+            }
+            // skip entry anchors on non-first glyphs
+            if component_idx > 0 && name.ends_with("entry") {
+                continue;
+            }
+
+            let mut new_name = rename_anchor_for_scale(name, scale);
+            if is_ligature
+                && component_number_of_base_glyphs > 0
+                && !new_has_underscore
+                && !(new_name.ends_with("exit") || new_name.ends_with("entry"))
+            {
+                // dealing with marks like top_1 on a ligature
+                new_name = make_liga_anchor_name(new_name, number_of_base_glyphs);
+            }
+            all_anchors.insert(new_name, transform_anchor(*pos, component.transform));
+            has_underscore |= new_has_underscore;
+        }
+        number_of_base_glyphs += component_number_of_base_glyphs;
+    }
+
+    // anchors defined on the glyph itself win
+    all_anchors.extend(own.iter().map(|(name, pos)| (name.clone(), *pos)));
+
+    // count how many components we have, based on our anchors
+    let number_of_base_glyphs =
+        count_base_glyphs(all_anchors.keys(), is_ligature, number_of_base_glyphs);
+
+    if own.contains_key("_bottom") {
+        all_anchors.shift_remove("top");
+        all_anchors.shift_remove("_top");
+    }
+    if own.contains_key("_top") {
+        all_anchors.shift_remove("bottom");
+        all_anchors.shift_remove("_bottom");
+    }
+    base_glyph_counts.insert(
+        (glyph.name.clone(), location.clone()),
+        number_of_base_glyphs,
+    );
+    all_anchors
+}
+
+/// The anchors of a component that attaches to a named anchor, e.g. 'top_2'.
+///
+/// A component that is a mark, with both 'top' and '_top', offers its 'top' as 'top_2'.
+fn renamed_component_anchor(component_anchor: &SmolStr, anchors: &Anchors) -> Anchors {
+    let Some((sub_name, _)) = component_anchor.split_once('_') else {
+        return anchors.clone();
+    };
+    let mark_name = format_smolstr!("_{sub_name}");
+    if !(anchors.contains_key(sub_name) && anchors.contains_key(&mark_name)) {
+        return anchors.clone();
+    }
+    anchors
+        .iter()
+        .map(|(name, pos)| {
+            let name = if name == sub_name {
+                component_anchor
+            } else {
+                name
+            };
+            (name.clone(), *pos)
+        })
+        .collect()
+}
+
+/// How many base glyphs a glyph is made of, given the names of all its anchors
+/// and the number of base glyphs its components contribute.
+fn count_base_glyphs<'a>(
+    anchor_names: impl IntoIterator<Item = &'a SmolStr>,
+    is_ligature: bool,
+    from_components: usize,
+) -> usize {
+    let mut has_underscore_anchor = false;
+    let mut has_mark_anchor = false;
+    let mut component_count_from_anchors = 0;
+    for name in anchor_names {
+        has_underscore_anchor |= name.starts_with('_');
+        has_mark_anchor |= name.chars().next().unwrap_or('\0').is_ascii_alphabetic();
+        if is_ligature
+            || from_components > 0
+            || name.starts_with('_')
+            || name.ends_with("entry")
+            || name.ends_with("exit")
+        {
+            continue;
+        }
+        let Some((_, suffix)) = name.split_once('_') else {
+            continue;
+        };
+        // carets count space between components, so the last caret
+        // is n_components - 1
+        let anchor_index = suffix.parse::<usize>().unwrap_or(0);
+        let anchor_index = if name.starts_with("caret") {
+            anchor_index + 1
+        } else {
+            anchor_index
+        };
+        component_count_from_anchors = component_count_from_anchors.max(anchor_index);
+    }
+    let mut number_of_base_glyphs = from_components;
+    if !has_underscore_anchor && number_of_base_glyphs == 0 && has_mark_anchor {
+        number_of_base_glyphs += 1;
+    }
+    number_of_base_glyphs.max(component_count_from_anchors)
+}
+
+/// Returns a vec2 where for each axis a negative value indicates that axis is considered flipped
+fn get_xy_rotation(xform: Affine) -> Vec2 {
+    // this is based on examining the behaviour of glyphs via the macro panel
+    // and careful testing.
+    let [xx, xy, ..] = xform.as_coeffs();
+    // first take the rotation
+    let angle = xy.atan2(xx);
+    // then remove the rotation, and take the scale
+    let rotated = xform.pre_rotate(-angle).as_coeffs();
+    let mut scale = Vec2::new(rotated[0], rotated[3]);
+    // then invert the scale if the rotation was >= 180°
+    if (angle.to_degrees() - 180.0).abs() < 0.001 {
+        scale *= -1.0;
+    }
+
+    scale
+}
+
+/// Apply the transform but also do some rounding, so we don't have anchors
+/// with points like (512, 302.000000006)
+fn transform_anchor(pos: Point, transform: Affine) -> Point {
+    // how many zeros do we care about? not this many
+    const ROUND_TO: f64 = 1e6;
+    let mut pos = (transform * pos).to_vec2();
+    pos *= ROUND_TO;
+    pos = pos.round();
+    pos /= ROUND_TO;
+    pos.to_point()
+}
+
+/// The name of an anchor taken from the component after base_number base glyphs
+/// of a ligature, e.g. 'top' from the second component becomes 'top_2'
+fn make_liga_anchor_name(name: SmolStr, base_number: usize) -> SmolStr {
+    match name.split_once('_') {
+        // if this anchor already has a number (like 'top_2') we want to consider that
+        Some((name, suffix)) => {
+            let suffix = base_number + suffix.parse::<usize>().ok().unwrap_or(1);
+            format_smolstr!("{name}_{suffix}")
+        }
+        // otherwise we're turning 'top' into 'top_N'
+        None => format_smolstr!("{name}_{}", base_number + 1),
+    }
+}
+
+/// If a component is rotated, flip bottom/top, left/right, entry/exit
+fn rename_anchor_for_scale(name: &SmolStr, scale: Vec2) -> SmolStr {
+    // swap the two words in the target, if they're present
+    fn swap_pair(s: &mut String, one: &str, two: &str) {
+        fn replace(s: &mut String, target: &str, by: &str) -> bool {
+            if let Some(idx) = s.find(target) {
+                s.replace_range(idx..idx + target.len(), by);
+                return true;
+            }
+            false
+        }
+        // once we swap 'left' for 'right' we don't want to then check for 'right'!
+        if !replace(s, one, two) {
+            replace(s, two, one);
+        }
+    }
+
+    if scale.x >= 0. && scale.y >= 0. {
+        return name.to_owned();
+    }
+
+    let mut name = name.to_string();
+    if scale.y < 0. {
+        swap_pair(&mut name, "bottom", "top");
+    }
+    if scale.x < 0. {
+        swap_pair(&mut name, "left", "right");
+        swap_pair(&mut name, "exit", "entry");
+    }
+
+    SmolStr::from(name)
+}
+
+/// Returns the names of glyphs, sorted such that components come before the glyphs using them.
+///
+/// Glyphs whose components form a cycle or reference missing glyphs are left out.
+fn depth_sorted_composite_glyphs(glyphs: &BTreeMap<GlyphName, Arc<Glyph>>) -> Vec<GlyphName> {
+    let component_names = |glyph: &Glyph| -> Vec<GlyphName> {
+        glyph
+            .sources()
+            .values()
+            .flat_map(|inst| inst.components.iter().map(|c| c.base.clone()))
+            .collect()
+    };
+    let mut indeterminate_depth = Vec::new();
+    let mut depths: HashMap<GlyphName, usize> = HashMap::new();
+    for (name, glyph) in glyphs {
+        let components = component_names(glyph);
+        if components.is_empty() {
+            depths.insert(name.clone(), 0);
+        } else {
+            indeterminate_depth.push((name, components));
+        }
+    }
+
+    // We know the depth once every component we rely on has a depth
+    let mut progress = true;
+    while progress {
+        let before = indeterminate_depth.len();
+        indeterminate_depth.retain(|(name, components)| {
+            let max_component_depth = components
+                .iter()
+                .map(|component| depths.get(component).copied())
+                .try_fold(0, |acc, depth| depth.map(|depth| acc.max(depth)));
+            if let Some(max_component_depth) = max_component_depth {
+                depths.insert((*name).clone(), max_component_depth + 1);
+            }
+            max_component_depth.is_none()
+        });
+        progress = indeterminate_depth.len() < before;
+    }
+
+    if !indeterminate_depth.is_empty() {
+        let mut names: Vec<_> = indeterminate_depth.iter().map(|(name, _)| *name).collect();
+        names.sort();
+        warn!(
+            "Invalid component graph (cycles or bad refs) for {} glyphs: {names:?}",
+            names.len(),
+        );
+    }
+
+    let mut by_depth: Vec<_> = depths
+        .into_iter()
+        .map(|(name, depth)| (depth, name))
+        .collect();
+    by_depth.sort();
+    by_depth.into_iter().map(|(_, name)| name).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, path::Path};
+
+    use fontdrasil::orchestration::Access;
+
+    use crate::{
+        ir::{Component, GdefCategories, GlyphBuilder, GlyphInstance, StaticMetadata},
+        orchestration::Flags,
+        paths::Paths,
+    };
+
+    use super::*;
+
+    /// The anchors of each glyph, sorted by name
+    type Propagated = HashMap<String, Vec<(String, (f64, f64))>>;
+
+    #[derive(Default)]
+    struct GlyphSetBuilder {
+        glyphs: Vec<(Glyph, Option<GlyphAnchors>)>,
+        categories: BTreeMap<GlyphName, GlyphClassDef>,
+    }
+
+    impl GlyphSetBuilder {
+        fn add_glyph(&mut self, name: &str, anchors: &[(&str, (f64, f64))]) -> &mut Self {
+            self.add_composite(name, &[], anchors)
+        }
+
+        fn add_composite(
+            &mut self,
+            name: &str,
+            components: &[(&str, Affine)],
+            anchors: &[(&str, (f64, f64))],
+        ) -> &mut Self {
+            let mut glyph = GlyphBuilder::new(name.into());
+            glyph
+                .try_add_source(
+                    &NormalizedLocation::new(),
+                    GlyphInstance {
+                        components: components
+                            .iter()
+                            .map(|(base, transform)| Component {
+                                base: (*base).into(),
+                                transform: *transform,
+                                variable: None,
+                                anchor: None,
+                            })
+                            .collect(),
+                        ..Default::default()
+                    },
+                )
+                .unwrap();
+            let glyph_anchors = (!anchors.is_empty()).then(|| {
+                let mut builder = AnchorBuilder::new(name.into());
+                for (anchor_name, pos) in anchors {
+                    builder
+                        .add(
+                            (*anchor_name).into(),
+                            NormalizedLocation::new(),
+                            (*pos).into(),
+                        )
+                        .unwrap();
+                }
+                builder.build().unwrap()
+            });
+            self.glyphs.push((glyph.build().unwrap(), glyph_anchors));
+            self
+        }
+
+        /// Name the anchor the component at component_idx of the glyph attaches to
+        fn set_component_anchor(
+            &mut self,
+            name: &str,
+            component_idx: usize,
+            anchor: &str,
+        ) -> &mut Self {
+            let (glyph, _) = self
+                .glyphs
+                .iter_mut()
+                .find(|(glyph, _)| glyph.name == name)
+                .unwrap();
+            for (_, inst) in glyph.sources_mut() {
+                inst.components[component_idx].anchor = Some(anchor.into());
+            }
+            self
+        }
+
+        fn set_category(&mut self, name: &str, category: GlyphClassDef) -> &mut Self {
+            self.categories.insert(name.into(), category);
+            self
+        }
+
+        /// Propagate, returning the anchors of each glyph sorted by name
+        fn propagate(&self) -> Propagated {
+            let mut flags = Flags::default();
+            flags.set(Flags::EMIT_IR, false);
+            let context = Context::new_root(flags, Paths::new(Path::new("/fake/path")))
+                .copy_for_work(Access::All, Access::All);
+            context.static_metadata.set(
+                StaticMetadata::new(
+                    1000,
+                    Default::default(),
+                    Default::default(),
+                    Default::default(),
+                    HashSet::from([NormalizedLocation::new()]),
+                    Default::default(),
+                    Default::default(),
+                    GdefCategories {
+                        categories: self.categories.clone(),
+                        prefer_gdef_categories_in_fea: false,
+                    },
+                    None,
+                )
+                .unwrap(),
+            );
+            let mut glyph_order = GlyphOrder::new();
+            for (glyph, anchors) in self.glyphs.iter() {
+                glyph_order.insert(glyph.name.clone());
+                context.glyphs.set(glyph.clone());
+                if let Some(anchors) = anchors {
+                    context.anchors.set(anchors.clone());
+                }
+            }
+
+            propagate_anchors(&context, &glyph_order, &GlyphSelection::All).unwrap();
+
+            glyph_order
+                .names()
+                .filter_map(|name| context.anchors.try_get(&WorkId::Anchor(name.clone())))
+                .map(|anchors| {
+                    let mut positions: Vec<_> = anchors
+                        .anchors
+                        .iter()
+                        .map(|anchor| {
+                            let pos = anchor.default_pos();
+                            (anchor.kind.name().to_string(), (pos.x, pos.y))
+                        })
+                        .collect();
+                    positions.sort_by(|a, b| a.0.cmp(&b.0));
+                    (anchors.glyph_name.to_string(), positions)
+                })
+                .collect()
+        }
+    }
+
+    fn anchors<'a>(propagated: &'a Propagated, glyph_name: &str) -> Vec<(&'a str, (f64, f64))> {
+        propagated
+            .get(glyph_name)
+            .unwrap()
+            .iter()
+            .map(|(name, pos)| (name.as_str(), *pos))
+            .collect()
+    }
+
+    #[test]
+    fn components_by_depth() {
+        let mut builder = GlyphSetBuilder::default();
+        for (name, components) in [
+            ("A", &[][..]),
+            ("E", &[]),
+            ("acutecomb", &[]),
+            ("brevecomb", &[]),
+            ("brevecomb_acutecomb", &["acutecomb", "brevecomb"]),
+            ("AE", &["A", "E"]),
+            ("Aacute", &["A", "acutecomb"]),
+            ("Aacutebreve", &["A", "brevecomb_acutecomb"]),
+            ("AEacutebreve", &["AE", "brevecomb_acutecomb"]),
+        ] {
+            let components: Vec<_> = components
+                .iter()
+                .map(|name| (*name, Affine::IDENTITY))
+                .collect();
+            builder.add_composite(name, &components, &[]);
+        }
+        let glyphs = builder
+            .glyphs
+            .iter()
+            .map(|(glyph, _)| (glyph.name.clone(), Arc::new(glyph.clone())))
+            .collect();
+
+        assert_eq!(
+            vec![
+                "A",
+                "E",
+                "acutecomb",
+                "brevecomb",
+                "AE",
+                "Aacute",
+                "brevecomb_acutecomb",
+                "AEacutebreve",
+                "Aacutebreve",
+            ],
+            depth_sorted_composite_glyphs(&glyphs)
+                .iter()
+                .map(|name| name.as_str())
+                .collect::<Vec<_>>()
+        );
+    }
+
+    fn depth_sorted(builder: &GlyphSetBuilder) -> Vec<String> {
+        let glyphs = builder
+            .glyphs
+            .iter()
+            .map(|(glyph, _)| (glyph.name.clone(), Arc::new(glyph.clone())))
+            .collect();
+        depth_sorted_composite_glyphs(&glyphs)
+            .into_iter()
+            .map(|name| name.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn composite_cycle() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let mut builder = GlyphSetBuilder::default();
+        builder
+            .add_composite("A", &[("B", Affine::IDENTITY)], &[])
+            .add_composite("B", &[("A", Affine::IDENTITY)], &[]);
+        // cycles should be dropped
+        assert!(depth_sorted(&builder).is_empty());
+    }
+
+    #[test]
+    fn composite_not_a_cycle() {
+        let mut builder = GlyphSetBuilder::default();
+        builder
+            .add_glyph("A", &[])
+            .add_composite(
+                "B",
+                &[("A", Affine::IDENTITY), ("D", Affine::IDENTITY)],
+                &[],
+            )
+            .add_glyph("C", &[])
+            .add_composite("D", &[("E", Affine::IDENTITY)], &[])
+            .add_composite("E", &[("C", Affine::translate((0.0, -180.0)))], &[]);
+        assert_eq!(vec!["A", "C", "E", "D", "B"], depth_sorted(&builder));
+    }
+
+    #[test]
+    fn basic_composite_anchor() {
+        let propagated = GlyphSetBuilder::default()
+            .add_glyph(
+                "A",
+                &[
+                    ("bottom", (234.0, 0.0)),
+                    ("ogonek", (411.0, 0.0)),
+                    ("top", (234.0, 810.0)),
+                ],
+            )
+            .add_glyph(
+                "acutecomb",
+                &[("_top", (0.0, 578.0)), ("top", (0.0, 810.0))],
+            )
+            .add_composite(
+                "Aacute",
+                &[
+                    ("A", Affine::IDENTITY),
+                    ("acutecomb", Affine::translate((234.0, 232.0))),
+                ],
+                &[],
+            )
+            .propagate();
+
+        assert_eq!(
+            vec![
+                ("bottom", (234.0, 0.0)),
+                ("ogonek", (411.0, 0.0)),
+                ("top", (234.0, 1042.0))
+            ],
+            anchors(&propagated, "Aacute")
+        );
+        // the mark keeps its own anchors
+        assert_eq!(
+            vec![("_top", (0.0, 578.0)), ("top", (0.0, 810.0))],
+            anchors(&propagated, "acutecomb")
+        );
+    }
+
+    #[test]
+    fn propagate_ligature_anchors() {
+        // the IJ glyph in Oswald (ExtraLight), as propagated by glyphs.app
+        let glyph_set = || {
+            let mut builder = GlyphSetBuilder::default();
+            builder
+                .add_glyph(
+                    "I",
+                    &[
+                        ("bottom", (103.0, 0.0)),
+                        ("ogonek", (103.0, 0.0)),
+                        ("top", (103.0, 810.0)),
+                        ("topleft", (20.0, 810.0)),
+                    ],
+                )
+                .add_glyph("J", &[("bottom", (133.0, 0.0)), ("top", (163.0, 810.0))])
+                .add_composite(
+                    "IJ",
+                    &[
+                        ("I", Affine::IDENTITY),
+                        ("J", Affine::translate((206.0, 0.0))),
+                    ],
+                    &[],
+                );
+            builder
+        };
+        let expected = vec![
+            ("bottom_1", (103.0, 0.0)),
+            ("bottom_2", (339.0, 0.0)),
+            ("ogonek_1", (103.0, 0.0)),
+            ("top_1", (103.0, 810.0)),
+            ("top_2", (369.0, 810.0)),
+            ("topleft_1", (20.0, 810.0)),
+        ];
+
+        let propagated = glyph_set()
+            .set_category("IJ", GlyphClassDef::Ligature)
+            .propagate();
+        assert_eq!(expected, anchors(&propagated, "IJ"));
+
+        // without a category IJ isn't a ligature, so J's anchors win
+        let propagated = glyph_set().propagate();
+        assert_eq!(
+            vec![
+                ("bottom", (339.0, 0.0)),
+                ("ogonek", (103.0, 0.0)),
+                ("top", (369.0, 810.0)),
+                ("topleft", (20.0, 810.0)),
+            ],
+            anchors(&propagated, "IJ")
+        );
+    }
+
+    #[test]
+    fn ligature_by_name() {
+        let propagated = GlyphSetBuilder::default()
+            .add_glyph("f", &[("top", (100.0, 700.0))])
+            .add_composite(
+                "f_f",
+                &[
+                    ("f", Affine::IDENTITY),
+                    ("f", Affine::translate((300.0, 0.0))),
+                ],
+                &[],
+            )
+            .propagate();
+        assert_eq!(
+            vec![("top_1", (100.0, 700.0)), ("top_2", (400.0, 700.0))],
+            anchors(&propagated, "f_f")
+        );
+    }
+
+    #[test]
+    fn invert_names_on_rotation() {
+        let propagated = GlyphSetBuilder::default()
+            .add_glyph("comma", &[])
+            .add_composite(
+                "commaaccentcomb",
+                &[("comma", Affine::translate((9.0, -164.0)))],
+                &[("_bottom", (289.0, 0.0)), ("mybottom", (277.0, -308.0))],
+            )
+            .add_composite(
+                "commaturnedabovecomb",
+                &[(
+                    "commaaccentcomb",
+                    Affine::rotate(180.0f64.to_radians()).then_translate((589.0, 502.0).into()),
+                )],
+                &[],
+            )
+            .propagate();
+
+        assert_eq!(
+            vec![("_top", (300.0, 502.0)), ("mytop", (312.0, 810.0))],
+            anchors(&propagated, "commaturnedabovecomb")
+        );
+    }
+
+    #[test]
+    fn remove_exit_anchor_on_component() {
+        // derived from the observed behaviour of glyphs 3.2.2 (3259)
+        let propagated = GlyphSetBuilder::default()
+            .add_glyph("comma", &[])
+            .add_glyph(
+                "ain-ar.init",
+                &[("top", (294.0, 514.0)), ("exit", (0.0, 0.0))],
+            )
+            .add_composite(
+                "ain-ar.init.alt",
+                &[
+                    ("ain-ar.init", Affine::IDENTITY),
+                    ("comma", Affine::IDENTITY),
+                ],
+                &[],
+            )
+            .propagate();
+        assert_eq!(
+            vec![("top", (294.0, 514.0))],
+            anchors(&propagated, "ain-ar.init.alt")
+        );
+    }
+
+    #[test]
+    fn component_anchor() {
+        // derived from the observed behaviour of glyphs 3.2.2 (3259)
+        let propagated = GlyphSetBuilder::default()
+            .add_glyph(
+                "acutecomb",
+                &[("_top", (150.0, 580.0)), ("top", (170.0, 792.0))],
+            )
+            .add_glyph(
+                "aa",
+                &[
+                    ("bottom_1", (218.0, 8.0)),
+                    ("bottom_2", (742.0, 7.0)),
+                    ("ogonek_1", (398.0, 9.0)),
+                    ("ogonek_2", (902.0, 9.0)),
+                    ("top_1", (227.0, 548.0)),
+                    ("top_2", (746.0, 548.0)),
+                ],
+            )
+            .add_composite("a_a", &[("aa", Affine::IDENTITY)], &[])
+            .add_composite(
+                "a_aacute",
+                &[
+                    ("a_a", Affine::IDENTITY),
+                    ("acutecomb", Affine::translate((596.0, -32.0))),
+                ],
+                &[],
+            )
+            .set_component_anchor("a_aacute", 1, "top_2")
+            .propagate();
+        assert_eq!(
+            vec![
+                ("bottom_1", (218.0, 8.0)),
+                ("bottom_2", (742.0, 7.0)),
+                ("ogonek_1", (398.0, 9.0)),
+                ("ogonek_2", (902.0, 9.0)),
+                ("top_1", (227.0, 548.0)),
+                ("top_2", (766.0, 760.0)),
+            ],
+            anchors(&propagated, "a_aacute")
+        );
+    }
+
+    #[test]
+    fn origin_anchor() {
+        // derived from the observed behaviour of glyphs 3.2.2 (3259)
+        let propagated = GlyphSetBuilder::default()
+            .add_glyph(
+                "a",
+                &[
+                    ("*origin", (-20.0, 0.0)),
+                    ("bottom", (242.0, 7.0)),
+                    ("ogonek", (402.0, 9.0)),
+                    ("top", (246.0, 548.0)),
+                ],
+            )
+            .add_glyph(
+                "acutecomb",
+                &[("_top", (150.0, 580.0)), ("top", (170.0, 792.0))],
+            )
+            .add_composite(
+                "aacute",
+                &[
+                    ("a", Affine::IDENTITY),
+                    ("acutecomb", Affine::translate((116.0, -32.0))),
+                ],
+                &[],
+            )
+            .propagate();
+        assert_eq!(
+            vec![
+                ("bottom", (262.0, 7.0)),
+                ("ogonek", (422.0, 9.0)),
+                ("top", (286.0, 760.0)),
+            ],
+            anchors(&propagated, "aacute")
+        );
+    }
+
+    #[test]
+    fn affine_scale() {
+        let affine = Affine::rotate((180.0f64).to_radians()).then_translate((589., 502.).into());
+        let delta = get_xy_rotation(affine);
+        assert!(delta.x.is_sign_negative() && delta.y.is_sign_negative());
+
+        let affine = Affine::translate((10., 10.));
+        let delta = get_xy_rotation(affine);
+        assert!(delta.x.is_sign_positive() && delta.y.is_sign_positive());
+        let flip_y = get_xy_rotation(Affine::FLIP_Y);
+        assert!(flip_y.y.is_sign_negative());
+        assert!(flip_y.x.is_sign_positive());
+        let flip_x = get_xy_rotation(Affine::FLIP_X);
+        assert!(flip_x.y.is_sign_positive());
+        assert!(flip_x.x.is_sign_negative());
+
+        let rotate_flip = Affine::rotate((180.0f64).to_radians())
+            .then_translate((589., 502.).into())
+            * Affine::FLIP_X;
+        let rotate_flip = get_xy_rotation(rotate_flip);
+        assert!(rotate_flip.x.is_sign_positive());
+        assert!(rotate_flip.y.is_sign_negative());
+    }
+
+    #[test]
+    fn base_glyphs_from_anchor_names() {
+        let names = |names: &[&str]| names.iter().map(SmolStr::new).collect::<Vec<_>>();
+        // a base with mark anchors is one base glyph
+        assert_eq!(1, count_base_glyphs(&names(&["top", "bottom"]), false, 0));
+        // a mark isn't a base
+        assert_eq!(0, count_base_glyphs(&names(&["_top", "top"]), false, 0));
+        // numbered anchors tell us how many components a ligature has
+        assert_eq!(3, count_base_glyphs(&names(&["top_1", "top_3"]), false, 0));
+        // the last caret sits between the last two components
+        assert_eq!(3, count_base_glyphs(&names(&["caret_2"]), false, 0));
+        // components that already counted win
+        assert_eq!(2, count_base_glyphs(&names(&["top_5"]), false, 2));
+    }
+}
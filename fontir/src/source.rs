@@ -1,20 +1,15 @@
 //! Generic model of font sources.
 
-use std::path::Path;
-
 use fontdrasil::coords::NormalizedLocation;
 
-use crate::{error::Error, orchestration::IrWork};
+use crate::{error::Error, incremental::Fingerprints, orchestration::IrWork};
 
 /// A source of data from which one could compile a font.
 ///
 /// Expected to be implemented once per font format, e.g. one for .glyphs, one for ufo+ds, etc.
+/// Implementations that read files typically offer a constructor that takes the path to the
+/// root entry, e.g. .glyphs file, .designspace, etc.
 pub trait Source {
-    /// path is to the root entry, e.g. .glyphs file, .designspace, etc
-    fn new(root: &Path) -> Result<Self, Error>
-    where
-        Self: Sized;
-
     /// Create a function that could be called to generate [crate::ir::StaticMetadata].
     ///
     /// When run work should update [crate::orchestration::Context] with new [crate::ir::StaticMetadata].
@@ -40,6 +35,12 @@ pub trait Source {
     /// When run work should update [crate::orchestration::Context] with [crate::ir::FeaturesSource].
     fn create_feature_ir_work(&self) -> Result<Box<IrWork>, Error>;
 
+    /// Create a function that could be called to generate [crate::ir::FeatureVariations].
+    ///
+    /// When run work should update [crate::orchestration::Context] with new [crate::ir::FeatureVariations]
+    /// if the source has any substitutions that apply only in part of the design space.
+    fn create_feature_variations_work(&self) -> Result<Box<IrWork>, Error>;
+
     /// Create a function that could be called to produce kerning groups.
     ///
     /// When run work should update [crate::orchestration::Context] with [crate::ir::KerningGroups].
@@ -60,4 +61,9 @@ pub trait Source {
     ///
     /// When run work should update [crate::orchestration::Context] with new [crate::ir::PaintGraph].
     fn create_paint_graph_work(&self) -> Result<Box<IrWork>, Error>;
+
+    /// Fingerprint the inputs so a later compilation can tell what changed.
+    ///
+    /// None if the source doesn't support reuse of prior output, see [crate::incremental].
+    fn fingerprint(&self) -> Result<Option<Fingerprints>, Error>;
 }
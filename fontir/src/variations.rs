@@ -205,6 +205,39 @@ impl VariationModel {
         &self,
         point_seqs: &HashMap<NormalizedLocation, Vec<P>>,
     ) -> Result<Vec<(VariationRegion, Vec<V>)>, DeltaError>
+    where
+        P: Copy + Default + Sub<P, Output = V>,
+        V: Copy + Mul<f64, Output = V> + Sub<V, Output = V> + RoundTiesEven,
+    {
+        self.compute_deltas(point_seqs, true)
+    }
+
+    /// Compute the value of every point at location from absolute positions at master locations.
+    ///
+    /// The same constraints on point_seqs as for [VariationModel::deltas] apply. Unlike
+    /// [VariationModel::deltas] intermediate deltas are not rounded so the result is
+    /// suitable for producing static instances.
+    ///
+    /// Returns a value, as the vector type, for every input point. For example, for
+    /// [kurbo::Point] input each result is the offset of the interpolated point from the origin.
+    pub fn interpolate<P, V>(
+        &self,
+        location: &NormalizedLocation,
+        point_seqs: &HashMap<NormalizedLocation, Vec<P>>,
+    ) -> Result<Vec<V>, DeltaError>
+    where
+        P: Copy + Default + Sub<P, Output = V>,
+        V: Copy + Mul<f64, Output = V> + Add<V, Output = V> + Sub<V, Output = V> + RoundTiesEven,
+    {
+        let deltas = self.compute_deltas(point_seqs, false)?;
+        Ok(Self::interpolate_from_deltas(location, &deltas))
+    }
+
+    fn compute_deltas<P, V>(
+        &self,
+        point_seqs: &HashMap<NormalizedLocation, Vec<P>>,
+        round: bool,
+    ) -> Result<Vec<(VariationRegion, Vec<V>)>, DeltaError>
     where
         P: Copy + Default + Sub<P, Output = V>,
         V: Copy + Mul<f64, Output = V> + Sub<V, Output = V> + RoundTiesEven,
@@ -253,32 +286,34 @@ impl VariationModel {
 
             for (idx, point) in points.iter().enumerate() {
                 let initial_vector: V = *point - Default::default();
-                deltas.push(
-                    // Find other masters that are active (have influence)
-                    // Any master with influence on us was processed already so we can get that masters
-                    // deltas from the results so far. If we subtract away all such influences what's
-                    // left is the delta to take us to point.
-                    master_influences
-                        .iter()
-                        .filter_map(|(master_idx, master_weight)| {
-                            let result_idx = model_idx_to_result_idx.get(master_idx)?;
-                            let master_deltas: &Vec<V> = result
-                                .get(*result_idx)
-                                .map(|(_, master_deltas)| master_deltas)?;
-                            let delta = master_deltas.get(idx)?;
-                            Some((delta, master_weight.into_inner()))
-                        })
-                        .fold(initial_vector, |acc, (other, other_weight)| {
-                            acc - *other * other_weight
-                        })
-                        // deltas will be stored as integers in the VarStore hence must be rounded at
-                        // some point; this is the correct place to round them, instead of at the end,
-                        // otherwise rounding errors can compound especially where master influences
-                        // overlap. This also matches FontTools behavior, see:
-                        // https://github.com/fonttools/fonttools/issues/2213
-                        // https://github.com/fonttools/fonttools/pull/2214
-                        .round_ties_even(),
-                );
+                // Find other masters that are active (have influence)
+                // Any master with influence on us was processed already so we can get that masters
+                // deltas from the results so far. If we subtract away all such influences what's
+                // left is the delta to take us to point.
+                let delta = master_influences
+                    .iter()
+                    .filter_map(|(master_idx, master_weight)| {
+                        let result_idx = model_idx_to_result_idx.get(master_idx)?;
+                        let master_deltas: &Vec<V> = result
+                            .get(*result_idx)
+                            .map(|(_, master_deltas)| master_deltas)?;
+                        let delta = master_deltas.get(idx)?;
+                        Some((delta, master_weight.into_inner()))
+                    })
+                    .fold(initial_vector, |acc, (other, other_weight)| {
+                        acc - *other * other_weight
+                    });
+                // deltas will be stored as integers in the VarStore hence must be rounded at
+                // some point; this is the correct place to round them, instead of at the end,
+                // otherwise rounding errors can compound especially where master influences
+                // overlap. This also matches FontTools behavior, see:
+                // https://github.com/fonttools/fonttools/issues/2213
+                // https://github.com/fonttools/fonttools/pull/2214
+                deltas.push(if round {
+                    delta.round_ties_even()
+                } else {
+                    delta
+                });
             }
             model_idx_to_result_idx.insert(model_idx, result.len());
             result.push((region.clone(), deltas));
@@ -291,12 +326,9 @@ impl VariationModel {
     ///
     /// Rust version of <https://github.com/fonttools/fonttools/blob/4ad6b0db/Lib/fontTools/varLib/models.py#L514-L545>
     ///
-    /// Not `pub` yet as it's currently only used for self-tests.
-    ///
     /// TODO: document invariants and what we are returning. Perhaps allow a different
     /// type parameter for the return value so that e.g. absolute Points are returned
     /// when the deltas are Vec2?
-    #[allow(dead_code)]
     fn interpolate_from_deltas<V>(
         location: &NormalizedLocation,
         deltasets: &[(VariationRegion, Vec<V>)],
@@ -1421,6 +1453,33 @@ mod tests {
         );
     }
 
+    #[test]
+    fn interpolate_does_not_round() {
+        let origin = NormalizedLocation::for_pos(&[("wght", 0.0)]);
+        let mid_wght = NormalizedLocation::for_pos(&[("wght", 0.5)]);
+        let max_wght = NormalizedLocation::for_pos(&[("wght", 1.0)]);
+        let locations = HashSet::from([origin.clone(), mid_wght.clone(), max_wght.clone()]);
+        let model = VariationModel::new(locations, vec![axis("wght")]).unwrap();
+
+        // The delta for mid_wght is 0.25, which would round to 0
+        let point_seqs = HashMap::from([
+            (origin, vec![Point::new(0.0, 10.0)]),
+            (mid_wght, vec![Point::new(0.75, 10.0)]),
+            (max_wght, vec![Point::new(1.0, 11.0)]),
+        ]);
+
+        let loc = NormalizedLocation::for_pos(&[("wght", 0.5)]);
+        assert_eq!(
+            vec![Vec2::new(0.75, 10.0)],
+            model.interpolate(&loc, &point_seqs).unwrap()
+        );
+        let loc = NormalizedLocation::for_pos(&[("wght", 0.25)]);
+        assert_eq!(
+            vec![Vec2::new(0.375, 10.0)],
+            model.interpolate(&loc, &point_seqs).unwrap()
+        );
+    }
+
     #[derive(Debug, Default, Copy, Clone, PartialEq)]
     struct NoRoundF64(f64);
 
@@ -9,12 +9,16 @@ use std::{
 };
 
 use fontdrasil::{paths::string_to_filename, types::GlyphName};
-use fontir::error::{BadSource, PathConversionError};
-use serde::Deserialize;
+use fontir::{
+    error::{BadSource, PathConversionError},
+    ir::DecomposedTransform,
+};
+use serde::{Deserialize, Deserializer};
 use write_fonts::types::Tag;
 
 pub(crate) type AxisName = String;
 pub(crate) type LayerName = String;
+pub(crate) type SourceIdentifier = String;
 
 pub(crate) fn glyph_file(glyph_dir: &Path, glyph: GlyphName) -> PathBuf {
     glyph_dir.join(string_to_filename(glyph.as_str(), ".json"))
@@ -33,8 +37,12 @@ where
 pub(crate) struct FontraFontData {
     #[serde(rename = "unitsPerEm")]
     pub(crate) units_per_em: u16,
+    #[serde(rename = "fontInfo", default)]
+    pub(crate) font_info: FontraFontInfo,
     #[serde(default)]
-    pub(crate) axes: Vec<FontraAxis>,
+    pub(crate) axes: FontraAxes,
+    #[serde(default, deserialize_with = "deserialize_sources")]
+    pub(crate) sources: BTreeMap<SourceIdentifier, FontraFontSource>,
 }
 
 impl FontraFontData {
@@ -43,6 +51,91 @@ impl FontraFontData {
     }
 }
 
+/// Sources used to be a list, they are now keyed by identifier
+///
+/// Sources from a list are identified by their position.
+fn deserialize_sources<'de, D>(
+    deserializer: D,
+) -> Result<BTreeMap<SourceIdentifier, FontraFontSource>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum RawSources {
+        List(Vec<FontraFontSource>),
+        Map(BTreeMap<SourceIdentifier, FontraFontSource>),
+    }
+    Ok(match RawSources::deserialize(deserializer)? {
+        RawSources::List(sources) => sources
+            .into_iter()
+            .enumerate()
+            .map(|(i, source)| (i.to_string(), source))
+            .collect(),
+        RawSources::Map(sources) => sources,
+    })
+}
+
+/// <https://github.com/googlefonts/fontra/blob/ed5d5ee7cd9ec7a2c1ef1bd3cf1aebc7ad2a6e55/src/fontra/core/classes.py#L18-L37>
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct FontraFontInfo {
+    pub(crate) family_name: Option<String>,
+    pub(crate) version_major: Option<i32>,
+    pub(crate) version_minor: Option<u32>,
+    pub(crate) copyright: Option<String>,
+    pub(crate) trademark: Option<String>,
+    pub(crate) description: Option<String>,
+    pub(crate) sample_text: Option<String>,
+    pub(crate) designer: Option<String>,
+    #[serde(rename = "designerURL")]
+    pub(crate) designer_url: Option<String>,
+    pub(crate) manufacturer: Option<String>,
+    #[serde(rename = "manufacturerURL")]
+    pub(crate) manufacturer_url: Option<String>,
+    pub(crate) license_description: Option<String>,
+    #[serde(rename = "licenseInfoURL")]
+    pub(crate) license_info_url: Option<String>,
+    #[serde(rename = "vendorID")]
+    pub(crate) vendor_id: Option<String>,
+}
+
+/// The global axes, plus any cross-axis mappings
+///
+/// Older files have only a list of axes.
+///
+/// <https://github.com/googlefonts/fontra/blob/ed5d5ee7cd9ec7a2c1ef1bd3cf1aebc7ad2a6e55/src/fontra/core/classes.py#L120-L125>
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(from = "RawAxes")]
+pub(crate) struct FontraAxes {
+    pub(crate) axes: Vec<FontraAxis>,
+    pub(crate) mappings: Vec<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawAxes {
+    List(Vec<FontraAxis>),
+    Axes {
+        #[serde(default)]
+        axes: Vec<FontraAxis>,
+        #[serde(default)]
+        mappings: Vec<serde_json::Value>,
+    },
+}
+
+impl From<RawAxes> for FontraAxes {
+    fn from(raw: RawAxes) -> Self {
+        match raw {
+            RawAxes::List(axes) => FontraAxes {
+                axes,
+                mappings: Vec::new(),
+            },
+            RawAxes::Axes { axes, mappings } => FontraAxes { axes, mappings },
+        }
+    }
+}
+
 #[derive(Debug, Clone, Deserialize)]
 #[serde(untagged)]
 pub(crate) enum FontraAxis {
@@ -124,11 +217,171 @@ pub(crate) struct FontraDiscreteAxis {
     values: Vec<f64>,
 }
 
+/// A font-wide source, what other formats call a master
+///
+/// <https://github.com/googlefonts/fontra/blob/ed5d5ee7cd9ec7a2c1ef1bd3cf1aebc7ad2a6e55/src/fontra/core/classes.py#L60-L70>
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct FontraFontSource {
+    pub(crate) name: String,
+    /// Sparse sources only exist to hold glyph or kerning data
+    #[serde(rename = "isSparse", default)]
+    pub(crate) is_sparse: bool,
+    /// In design coordinates
+    #[serde(default)]
+    pub(crate) location: HashMap<AxisName, f64>,
+    #[serde(rename = "lineMetricsHorizontalLayout", default)]
+    pub(crate) line_metrics_horizontal_layout: HashMap<String, FontraLineMetric>,
+    /// In degrees counter-clockwise
+    #[serde(rename = "italicAngle", default)]
+    pub(crate) italic_angle: f64,
+}
+
+impl FontraFontSource {
+    pub(crate) fn line_metric(&self, name: &str) -> Option<f64> {
+        self.line_metrics_horizontal_layout
+            .get(name)
+            .map(|metric| metric.value)
+    }
+}
+
+/// <https://github.com/googlefonts/fontra/blob/ed5d5ee7cd9ec7a2c1ef1bd3cf1aebc7ad2a6e55/src/fontra/core/classes.py#L54-L57>
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct FontraLineMetric {
+    pub(crate) value: f64,
+}
+
+/// The kerning of one type, e.g. "kern" or "vkrn", read from kerning.csv
+///
+/// <https://github.com/googlefonts/fontra/blob/ed5d5ee7cd9ec7a2c1ef1bd3cf1aebc7ad2a6e55/src/fontra/core/classes.py#L73-L79>
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct FontraKerning {
+    pub(crate) groups_side1: BTreeMap<String, Vec<GlyphName>>,
+    pub(crate) groups_side2: BTreeMap<String, Vec<GlyphName>>,
+    pub(crate) source_identifiers: Vec<SourceIdentifier>,
+    /// side1 => side2 => a value per source identifier, groups are prefixed with '@'
+    pub(crate) values: BTreeMap<String, BTreeMap<String, Vec<Option<f64>>>>,
+}
+
+impl FontraKerning {
+    /// Read every type of kerning in a kerning.csv file
+    ///
+    /// The file is a series of sections separated by blank lines, each introduced by a
+    /// line holding only TYPE, GROUPS1, GROUPS2 or VALUES. See
+    /// <https://github.com/googlefonts/fontra/blob/ed5d5ee7cd9ec7a2c1ef1bd3cf1aebc7ad2a6e55/src/fontra/backends/fontra.py#L268-L302>
+    pub(crate) fn from_file(p: &Path) -> Result<BTreeMap<String, FontraKerning>, BadSource> {
+        let raw = fs::read_to_string(p).map_err(|e| BadSource::new(p, e))?;
+        Self::parse(&raw).map_err(|e| BadSource::custom(p, e))
+    }
+
+    fn parse(raw: &str) -> Result<BTreeMap<String, FontraKerning>, String> {
+        #[derive(PartialEq)]
+        enum Section {
+            Type,
+            Groups1,
+            Groups2,
+            ValuesHeader,
+            Values,
+        }
+
+        let mut kerning = BTreeMap::new();
+        let mut current: Option<(String, FontraKerning)> = None;
+        let mut section = None;
+        for (i, line) in raw.lines().enumerate() {
+            let line_num = i + 1;
+            let parts: Vec<_> = line.split(';').map(str::trim).collect();
+            match parts.as_slice() {
+                [""] => continue,
+                ["TYPE"] => section = Some(Section::Type),
+                ["GROUPS1"] => section = Some(Section::Groups1),
+                ["GROUPS2"] => section = Some(Section::Groups2),
+                ["VALUES"] => section = Some(Section::ValuesHeader),
+                _ => {
+                    if section == Some(Section::Type) {
+                        let [kern_type] = parts.as_slice() else {
+                            return Err(format!("Expected a kerning type at line {line_num}"));
+                        };
+                        if let Some((kern_type, table)) = current.take() {
+                            kerning.insert(kern_type, table);
+                        }
+                        current = Some((kern_type.to_string(), FontraKerning::default()));
+                        section = None;
+                        continue;
+                    }
+                    let Some((_, table)) = current.as_mut() else {
+                        return Err(format!("Expected TYPE before line {line_num}"));
+                    };
+                    match section {
+                        Some(Section::Groups1) | Some(Section::Groups2) => {
+                            let groups = if section == Some(Section::Groups1) {
+                                &mut table.groups_side1
+                            } else {
+                                &mut table.groups_side2
+                            };
+                            groups.insert(
+                                parts[0].to_string(),
+                                parts[1..]
+                                    .iter()
+                                    .filter(|g| !g.is_empty())
+                                    .map(|g| GlyphName::new(*g))
+                                    .collect(),
+                            );
+                        }
+                        Some(Section::ValuesHeader) => {
+                            let ["side1", "side2", source_identifiers @ ..] = parts.as_slice()
+                            else {
+                                return Err(format!(
+                                    "Expected side1;side2;<sources> at line {line_num}"
+                                ));
+                            };
+                            table.source_identifiers =
+                                source_identifiers.iter().map(|s| s.to_string()).collect();
+                            section = Some(Section::Values);
+                        }
+                        Some(Section::Values) => {
+                            let [side1, side2, values @ ..] = parts.as_slice() else {
+                                return Err(format!("Expected a kerning pair at line {line_num}"));
+                            };
+                            if values.len() != table.source_identifiers.len() {
+                                return Err(format!(
+                                    "Expected {} values at line {line_num}",
+                                    table.source_identifiers.len()
+                                ));
+                            }
+                            let values = values
+                                .iter()
+                                .map(|v| {
+                                    if v.is_empty() {
+                                        return Ok(None);
+                                    }
+                                    v.parse::<f64>().map(Some).map_err(|e| {
+                                        format!("Bad kerning value {v:?} at line {line_num}: {e}")
+                                    })
+                                })
+                                .collect::<Result<_, _>>()?;
+                            table
+                                .values
+                                .entry(side1.to_string())
+                                .or_default()
+                                .insert(side2.to_string(), values);
+                        }
+                        Some(Section::Type) | None => {
+                            return Err(format!("Expected a section name at line {line_num}"))
+                        }
+                    }
+                }
+            }
+        }
+        if let Some((kern_type, table)) = current.take() {
+            kerning.insert(kern_type, table);
+        }
+        Ok(kerning)
+    }
+}
+
 /// serde type used to load .fontra/glyphs/namelike.json files
 ///
 /// <https://github.com/googlefonts/fontra/blob/a4edd06837118e583804fd963c22ed806a315b04/src/fontra/core/classes.py#L104-L116>
 #[derive(Debug, Clone, Deserialize)]
-#[allow(dead_code)] // TEMPORARY
 pub(crate) struct FontraGlyph {
     pub(crate) name: GlyphName,
     /// Variable component, or glyph-local, axes
@@ -155,56 +408,139 @@ pub(crate) struct FontraGlyphAxis {
 
 /// <https://github.com/googlefonts/fontra/blob/a4edd06837118e583804fd963c22ed806a315b04/src/fontra/core/classes.py#L119-L126>
 #[derive(Debug, Clone, Deserialize)]
-#[allow(dead_code)] // TEMPORARY
 pub(crate) struct FontraSource {
     pub(crate) name: String,
     #[serde(rename = "layerName")]
     pub(crate) layer_name: LayerName,
+    /// In design coordinates for global axes, which may be mixed with glyph-local axes
     #[serde(default)]
     pub(crate) location: HashMap<AxisName, f64>,
-    // TODO: locationBase
+    /// The font source whose location this source is at, [Self::location] only adds to it
+    #[serde(rename = "locationBase", default)]
+    pub(crate) location_base: Option<SourceIdentifier>,
     #[serde(default)]
     pub(crate) inactive: bool,
 }
 
 /// <https://github.com/googlefonts/fontra/blob/a4edd06837118e583804fd963c22ed806a315b04/src/fontra/core/classes.py#L129-L132>
 #[derive(Debug, Clone, Deserialize)]
-#[allow(dead_code)] // TEMPORARY
 pub(crate) struct FontraLayer {
     pub(crate) glyph: FontraGlyphInstance,
 }
 
 /// <https://github.com/googlefonts/fontra/blob/a4edd06837118e583804fd963c22ed806a315b04/src/fontra/core/classes.py#L135-L151>
 #[derive(Debug, Clone, Deserialize)]
-#[allow(dead_code)] // TEMPORARY
 pub(crate) struct FontraGlyphInstance {
-    #[serde(rename = "xAdvance")]
+    #[serde(rename = "xAdvance", default)]
     pub(crate) x_advance: f64,
-    // TODO: Fontra has two representations, packed and unpacked. This only covers one.
+    #[serde(rename = "yAdvance", default)]
+    pub(crate) y_advance: Option<f64>,
+    #[serde(rename = "verticalOrigin", default)]
+    pub(crate) vertical_origin: Option<f64>,
     #[serde(default)]
     pub(crate) path: FontraPath,
     #[serde(default)]
     pub(crate) components: Vec<FontraComponent>,
+    #[serde(default)]
+    pub(crate) anchors: Vec<FontraAnchor>,
 }
 
 impl FontraGlyph {
-    #[allow(dead_code)] // TEMPORARY
     pub(crate) fn from_file(p: &Path) -> Result<Self, BadSource> {
         from_file(p)
     }
 }
 
+/// Fontra stores paths either as a list of contours or packed into flat lists
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub(crate) enum FontraPath {
+    Packed(FontraPackedPath),
+    Unpacked(FontraUnpackedPath),
+}
+
+impl Default for FontraPath {
+    fn default() -> Self {
+        FontraPath::Unpacked(Default::default())
+    }
+}
+
+impl FontraPath {
+    /// The contours of the path, unpacking if necessary
+    pub(crate) fn contours(&self) -> Result<Vec<FontraContour>, PathConversionError> {
+        match self {
+            FontraPath::Packed(packed) => packed.unpack(),
+            FontraPath::Unpacked(unpacked) => Ok(unpacked.contours.clone()),
+        }
+    }
+}
+
 /// <https://github.com/googlefonts/fontra/blob/a4edd06837118e583804fd963c22ed806a315b04/src/fontra/core/path.py#L34-L53>
 #[derive(Default, Debug, Clone, Deserialize)]
-#[allow(dead_code)] // TEMPORARY
-pub(crate) struct FontraPath {
+pub(crate) struct FontraUnpackedPath {
     #[serde(default)]
     pub(crate) contours: Vec<FontraContour>,
 }
 
+/// <https://github.com/googlefonts/fontra/blob/a4edd06837118e583804fd963c22ed806a315b04/src/fontra/core/path.py#L72-L80>
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct FontraPackedPath {
+    /// x, y for each point
+    pub(crate) coordinates: Vec<f64>,
+    #[serde(rename = "pointTypes")]
+    pub(crate) point_types: Vec<u8>,
+    #[serde(rename = "contourInfo")]
+    pub(crate) contour_info: Vec<FontraContourInfo>,
+}
+
+/// <https://github.com/googlefonts/fontra/blob/a4edd06837118e583804fd963c22ed806a315b04/src/fontra/core/path.py#L28-L31>
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct FontraContourInfo {
+    /// Index of the last point of the contour
+    #[serde(rename = "endPoint")]
+    pub(crate) end_point: usize,
+    #[serde(rename = "isClosed", default)]
+    pub(crate) is_closed: bool,
+}
+
+impl FontraPackedPath {
+    fn unpack(&self) -> Result<Vec<FontraContour>, PathConversionError> {
+        if self.coordinates.len() != 2 * self.point_types.len() {
+            return Err(PathConversionError::Parse(format!(
+                "{} coordinates for {} points",
+                self.coordinates.len(),
+                self.point_types.len()
+            )));
+        }
+        let mut contours = Vec::with_capacity(self.contour_info.len());
+        let mut start = 0;
+        for info in self.contour_info.iter() {
+            if info.end_point < start || info.end_point >= self.point_types.len() {
+                return Err(PathConversionError::Parse(format!(
+                    "Bad contour end point {}",
+                    info.end_point
+                )));
+            }
+            let mut points = Vec::with_capacity(info.end_point + 1 - start);
+            for i in start..=info.end_point {
+                points.push(FontraPoint::new(
+                    self.coordinates[2 * i],
+                    self.coordinates[2 * i + 1],
+                    PointType::try_from(self.point_types[i])?,
+                ));
+            }
+            contours.push(FontraContour {
+                points,
+                is_closed: info.is_closed,
+            });
+            start = info.end_point + 1;
+        }
+        Ok(contours)
+    }
+}
+
 /// <https://github.com/googlefonts/fontra/blob/a4edd06837118e583804fd963c22ed806a315b04/src/fontra/core/path.py#L28-L31>
 #[derive(Debug, Clone, Deserialize)]
-#[allow(dead_code)] // TEMPORARY
 pub(crate) struct FontraContour {
     pub(crate) points: Vec<FontraPoint>,
     #[serde(rename = "isClosed", default)]
@@ -212,7 +548,6 @@ pub(crate) struct FontraContour {
 }
 
 #[derive(Debug, Clone, Deserialize)]
-#[allow(dead_code)] // TEMPORARY
 pub(crate) struct FontraPoint {
     pub(crate) x: f64,
     pub(crate) y: f64,
@@ -223,8 +558,22 @@ pub(crate) struct FontraPoint {
 }
 
 impl FontraPoint {
+    fn new(x: f64, y: f64, point_type: PointType) -> Self {
+        let (smooth, raw_type) = match point_type {
+            PointType::OnCurve => (false, None),
+            PointType::OnCurveSmooth => (true, None),
+            PointType::OffCurveQuad => (false, Some("quad")),
+            PointType::OffCurveCubic => (false, Some("cubic")),
+        };
+        FontraPoint {
+            x,
+            y,
+            smooth,
+            raw_type: raw_type.map(String::from),
+        }
+    }
+
     /// <https://github.com/googlefonts/fontra/blob/a4edd06837118e583804fd963c22ed806a315b04/src/fontra/core/path.py#L396-L406>
-    #[allow(dead_code)] // TEMPORARY
     pub(crate) fn point_type(&self) -> Result<PointType, PathConversionError> {
         match (self.smooth, self.raw_type.as_deref()) {
             (false, Some("cubic")) => Ok(PointType::OffCurveCubic),
@@ -242,7 +591,6 @@ impl FontraPoint {
 
 /// <https://github.com/googlefonts/fontra/blob/a4edd06837118e583804fd963c22ed806a315b04/src/fontra/core/path.py#L65-L69>
 #[derive(Debug, Clone, Copy, Default, PartialEq)]
-#[allow(dead_code)] // TEMPORARY
 pub(crate) enum PointType {
     #[default]
     OnCurve,
@@ -260,9 +608,25 @@ impl PointType {
     }
 }
 
+/// The values used by packed paths
+impl TryFrom<u8> for PointType {
+    type Error = PathConversionError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x00 => Ok(PointType::OnCurve),
+            0x01 => Ok(PointType::OffCurveQuad),
+            0x02 => Ok(PointType::OffCurveCubic),
+            0x08 => Ok(PointType::OnCurveSmooth),
+            _ => Err(PathConversionError::Parse(format!(
+                "Unrecognized point type {value}"
+            ))),
+        }
+    }
+}
+
 /// <https://github.com/googlefonts/fontra/blob/a4edd06837118e583804fd963c22ed806a315b04/src/fontra/core/classes.py#L154-L158>
 #[derive(Debug, Clone, Deserialize)]
-#[allow(dead_code)] // TEMPORARY
 pub(crate) struct FontraComponent {
     pub(crate) name: GlyphName,
     #[serde(default)]
@@ -272,11 +636,19 @@ pub(crate) struct FontraComponent {
     pub(crate) location: HashMap<String, f64>,
 }
 
+/// <https://github.com/googlefonts/fontra/blob/ed5d5ee7cd9ec7a2c1ef1bd3cf1aebc7ad2a6e55/src/fontra/core/classes.py#L161-L165>
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct FontraAnchor {
+    #[serde(default)]
+    pub(crate) name: Option<String>,
+    pub(crate) x: f64,
+    pub(crate) y: f64,
+}
+
 /// What FontTools calls a DecomposedTransform
 ///
 /// <https://github.com/fonttools/fonttools/blob/0572f7871823bdef3ceceaf41dedd0a6bd100995/Lib/fontTools/misc/transform.py#L410-L424>
 #[derive(Default, Debug, Clone, Deserialize)]
-#[allow(dead_code)] // TEMPORARY
 pub(crate) struct FontraTransform {
     #[serde(rename = "translateX", default)]
     translate_x: f64,
@@ -301,6 +673,22 @@ pub(crate) struct FontraTransform {
     t_center_y: f64,
 }
 
+impl FontraTransform {
+    pub(crate) fn to_decomposed(&self) -> DecomposedTransform {
+        DecomposedTransform {
+            translate_x: self.translate_x,
+            translate_y: self.translate_y,
+            rotation: self.rotation,
+            scale_x: self.scale_x,
+            scale_y: self.scale_y,
+            skew_x: self.skew_x,
+            skew_y: self.skew_y,
+            t_center_x: self.t_center_x,
+            t_center_y: self.t_center_y,
+        }
+    }
+}
+
 fn float_one() -> f64 {
     1.0
 }
@@ -318,6 +706,7 @@ mod tests {
 
     fn axis_tuples(font_data: &FontraFontData) -> Vec<(&str, Tag, f64, f64, f64)> {
         font_data
+            .axes
             .axes
             .iter()
             .map(|a| match a {
@@ -378,6 +767,7 @@ mod tests {
             axis_tuples(&font_data)
         );
         let wght = font_data
+            .axes
             .axes
             .iter()
             .find(|a| a.tag() == Tag::new(b"wght"))
@@ -388,6 +778,106 @@ mod tests {
         );
     }
 
+    #[test]
+    fn fontdata_of_wght_var() {
+        let font_data =
+            FontraFontData::from_file(&testdata_dir().join("wght_var.fontra/font-data.json"))
+                .unwrap();
+        assert_eq!(
+            vec![("Weight", Tag::new(b"wght"), 400.0, 400.0, 700.0)],
+            axis_tuples(&font_data)
+        );
+        assert!(font_data.axes.mappings.is_empty());
+        assert_eq!(Some("Wght Var"), font_data.font_info.family_name.as_deref());
+        assert_eq!(
+            (Some(1), Some(2)),
+            (
+                font_data.font_info.version_major,
+                font_data.font_info.version_minor
+            )
+        );
+        assert_eq!(
+            vec![
+                ("bold", "Bold", Some(820.0), 100.0),
+                ("regular", "Regular", Some(800.0), 0.0)
+            ],
+            font_data
+                .sources
+                .iter()
+                .map(|(id, s)| (
+                    id.as_str(),
+                    s.name.as_str(),
+                    s.line_metric("ascender"),
+                    s.location["Weight"]
+                ))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn read_packed_path() {
+        let glyph = read_test_glyph("wght_var.fontra", "V");
+        let contours = glyph.layers["regular"].glyph.path.contours().unwrap();
+        assert_eq!(1, contours.len());
+        assert!(contours[0].is_closed);
+        assert_eq!(
+            vec![
+                (20.0, 700.0, PointType::OnCurve),
+                (280.0, 0.0, PointType::OnCurve),
+                (300.0, 0.0, PointType::OnCurveSmooth),
+                (300.0, 350.0, PointType::OffCurveCubic),
+                (540.0, 700.0, PointType::OffCurveCubic),
+            ],
+            contours[0]
+                .points
+                .iter()
+                .map(|p| (p.x, p.y, p.point_type().unwrap()))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn read_location_base() {
+        let glyph = read_test_glyph("wght_var.fontra", "V");
+        assert_eq!(
+            vec![
+                ("Regular", Some("regular"), false),
+                ("Semibold", None, false),
+                ("Bold", Some("bold"), false),
+                ("Experiment", None, true),
+            ],
+            glyph
+                .sources
+                .iter()
+                .map(|s| (s.name.as_str(), s.location_base.as_deref(), s.inactive))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn read_kerning() {
+        let kerning =
+            FontraKerning::from_file(&testdata_dir().join("wght_var.fontra/kerning.csv")).unwrap();
+        assert_eq!(vec!["kern"], kerning.keys().collect::<Vec<_>>());
+        let kern = &kerning["kern"];
+        assert_eq!(
+            BTreeMap::from([("A".to_string(), vec![GlyphName::new("A")])]),
+            kern.groups_side1
+        );
+        assert_eq!(
+            BTreeMap::from([("V".to_string(), vec![GlyphName::new("V")])]),
+            kern.groups_side2
+        );
+        assert_eq!(vec!["regular", "bold"], kern.source_identifiers);
+        assert_eq!(vec![Some(-40.0), Some(-60.0)], kern.values["@A"]["@V"]);
+        assert_eq!(vec![Some(-30.0), None], kern.values["V"]["A"]);
+    }
+
+    #[test]
+    fn reject_kerning_without_type() {
+        assert!(FontraKerning::parse("GROUPS1\nA;A\n").is_err());
+    }
+
     #[test]
     fn read_notdef() {
         let glyph = read_test_glyph("minimal.fontra", ".notdef");
@@ -426,16 +916,13 @@ mod tests {
             glyph
                 .layers
                 .values()
-                .flat_map(|l| l.glyph.path.contours.iter().map(|c| c.points.len()))
+                .flat_map(|l| l.glyph.path.contours().unwrap())
+                .map(|c| c.points.len())
                 .collect::<HashSet<_>>(),
             "{glyph:#?}"
         );
-        let contour = glyph.layers["foreground"]
-            .glyph
-            .path
-            .contours
-            .first()
-            .unwrap();
+        let contours = glyph.layers["foreground"].glyph.path.contours().unwrap();
+        let contour = contours.first().unwrap();
         assert_eq!(PointType::OnCurve, contour.points[0].point_type().unwrap());
         assert_eq!(
             PointType::OffCurveCubic,
@@ -1,27 +1,45 @@
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, BTreeSet, HashSet},
     fs::File,
     io::{BufRead, BufReader},
     path::{Path, PathBuf},
     sync::Arc,
 };
 
-use fontdrasil::{orchestration::Work, types::GlyphName};
+use fontdrasil::{
+    coords::NormalizedLocation,
+    orchestration::{Access, AccessBuilder, Work},
+    types::GlyphName,
+};
 use fontir::{
     error::{BadSource, BadSourceKind, Error},
-    ir::StaticMetadata,
-    orchestration::{Context, WorkId},
+    incremental::{Fingerprinter, Fingerprints},
+    ir::{
+        FeaturesSource, GlobalMetric, GlobalMetrics, GlyphOrder, KernGroup, KernSide,
+        KerningGroups, KerningInstance, StaticMetadata,
+    },
+    orchestration::{Context, IrWork, WorkId},
     source::Source,
 };
-use log::debug;
+use log::{debug, trace, warn};
 
 use crate::{
-    fontra::{self, FontraFontData},
-    toir::to_ir_static_metadata,
+    fontra::{self, FontraFontData, FontraGlyph, FontraKerning},
+    toir::{
+        font_source_locations, glyph_source_locations, to_ir_axes, to_ir_glyph,
+        to_ir_static_metadata, to_normalized_location,
+    },
 };
 
+/// The type of kerning we compile, Fontra also has "vkrn"
+const KERN: &str = "kern";
+
 pub struct FontraIrSource {
     fontdata_file: PathBuf,
+    kerning_file: Option<PathBuf>,
+    features_file: Option<PathBuf>,
+    font_data: Arc<FontraFontData>,
+    kerning: Arc<FontraKerning>,
     glyph_info: Arc<BTreeMap<GlyphName, (PathBuf, Vec<u32>)>>,
 }
 
@@ -94,85 +112,155 @@ fn parse_glyph_info(fontra_dir: &Path) -> Result<BTreeMap<GlyphName, (PathBuf, V
     Ok(glyph_info)
 }
 
-impl Source for FontraIrSource {
-    fn new(fontra_dir: &Path) -> Result<Self, Error> {
+impl FontraIrSource {
+    /// Path is to a .fontra directory
+    pub fn new(fontra_dir: &Path) -> Result<Self, Error> {
         let fontdata_file = fontra_dir.join("font-data.json");
         if !fontdata_file.is_file() {
             return Err(BadSource::new(fontdata_file, BadSourceKind::ExpectedFile).into());
         }
+        let font_data = FontraFontData::from_file(&fontdata_file)?;
+
+        // Kerning and features are optional
+        let kerning_file = Some(fontra_dir.join("kerning.csv")).filter(|f| f.is_file());
+        let kerning = match &kerning_file {
+            Some(kerning_file) => FontraKerning::from_file(kerning_file)?
+                .remove(KERN)
+                .unwrap_or_default(),
+            None => Default::default(),
+        };
+        let features_file = Some(fontra_dir.join("features.fea")).filter(|f| f.is_file());
 
         let glyph_info = parse_glyph_info(fontra_dir)?;
 
         Ok(FontraIrSource {
             fontdata_file,
+            kerning_file,
+            features_file,
+            font_data: Arc::new(font_data),
+            kerning: Arc::new(kerning),
             glyph_info: Arc::new(glyph_info),
         })
     }
+}
 
-    fn create_static_metadata_work(
-        &self,
-    ) -> Result<Box<fontir::orchestration::IrWork>, fontir::error::Error> {
-        FontraFontData::from_file(&self.fontdata_file)?;
+impl Source for FontraIrSource {
+    fn create_static_metadata_work(&self) -> Result<Box<IrWork>, Error> {
         Ok(Box::new(StaticMetadataWork {
             fontdata_file: self.fontdata_file.clone(),
+            font_data: self.font_data.clone(),
             glyph_info: self.glyph_info.clone(),
         }))
     }
 
-    fn create_global_metric_work(
-        &self,
-    ) -> Result<Box<fontir::orchestration::IrWork>, fontir::error::Error> {
-        todo!()
+    fn create_global_metric_work(&self) -> Result<Box<IrWork>, Error> {
+        Ok(Box::new(GlobalMetricWork(self.font_data.clone())))
     }
 
-    fn create_glyph_ir_work(
-        &self,
-    ) -> Result<Vec<Box<fontir::orchestration::IrWork>>, fontir::error::Error> {
-        todo!()
+    fn create_glyph_ir_work(&self) -> Result<Vec<Box<IrWork>>, Error> {
+        Ok(self
+            .glyph_info
+            .keys()
+            .map(|glyph_name| {
+                Box::new(GlyphIrWork {
+                    glyph_name: glyph_name.clone(),
+                    font_data: self.font_data.clone(),
+                    glyph_info: self.glyph_info.clone(),
+                }) as Box<IrWork>
+            })
+            .collect())
     }
 
-    fn create_feature_ir_work(
-        &self,
-    ) -> Result<Box<fontir::orchestration::IrWork>, fontir::error::Error> {
-        todo!()
+    fn create_feature_ir_work(&self) -> Result<Box<IrWork>, Error> {
+        Ok(Box::new(FeatureWork {
+            features_file: self.features_file.clone(),
+        }))
     }
 
-    fn create_kerning_group_ir_work(
-        &self,
-    ) -> Result<Box<fontir::orchestration::IrWork>, fontir::error::Error> {
-        todo!()
+    fn create_feature_variations_work(&self) -> Result<Box<IrWork>, Error> {
+        Ok(Box::new(FeatureVariationsWork))
+    }
+
+    fn create_kerning_group_ir_work(&self) -> Result<Box<IrWork>, Error> {
+        Ok(Box::new(KerningGroupWork {
+            font_data: self.font_data.clone(),
+            kerning: self.kerning.clone(),
+        }))
     }
 
     fn create_kerning_instance_ir_work(
         &self,
-        _at: fontdrasil::coords::NormalizedLocation,
-    ) -> Result<Box<fontir::orchestration::IrWork>, fontir::error::Error> {
-        todo!()
+        at: NormalizedLocation,
+    ) -> Result<Box<IrWork>, Error> {
+        Ok(Box::new(KerningInstanceWork {
+            font_data: self.font_data.clone(),
+            kerning: self.kerning.clone(),
+            location: at,
+        }))
     }
 
-    fn create_color_palette_work(
-        &self,
-    ) -> Result<Box<fontir::orchestration::IrWork>, fontir::error::Error> {
-        todo!()
+    fn create_color_palette_work(&self) -> Result<Box<IrWork>, Error> {
+        Ok(Box::new(ColorPaletteWork))
     }
 
-    fn create_paint_graph_work(
-        &self,
-    ) -> Result<Box<fontir::orchestration::IrWork>, fontir::error::Error> {
-        todo!()
+    fn create_paint_graph_work(&self) -> Result<Box<IrWork>, Error> {
+        Ok(Box::new(PaintGraphWork))
+    }
+
+    fn fingerprint(&self) -> Result<Option<Fingerprints>, Error> {
+        let mut global = Fingerprinter::default();
+        global.add_file(&self.fontdata_file)?;
+        if let Some(features_file) = &self.features_file {
+            global.add_file(features_file)?;
+        }
+        let global = global.finish();
+        let kerning = match &self.kerning_file {
+            Some(kerning_file) => Fingerprinter::default().add_file(kerning_file)?.finish(),
+            None => Default::default(),
+        };
+        let glyphs = self
+            .glyph_info
+            .iter()
+            .map(|(glyph_name, (glyph_file, codepoints))| {
+                let fingerprint = Fingerprinter::default()
+                    .add_file(glyph_file)?
+                    .add(codepoints)
+                    .finish();
+                Ok((glyph_name.clone(), fingerprint))
+            })
+            .collect::<Result<_, Error>>()?;
+        Ok(Some(Fingerprints {
+            global,
+            kerning,
+            glyphs,
+        }))
     }
 }
 
 #[derive(Debug)]
 struct StaticMetadataWork {
     fontdata_file: PathBuf,
+    font_data: Arc<FontraFontData>,
     glyph_info: Arc<BTreeMap<GlyphName, (PathBuf, Vec<u32>)>>,
 }
 
-fn create_static_metadata(fontdata_file: &Path) -> Result<StaticMetadata, Error> {
-    debug!("Static metadata for {:#?}", fontdata_file);
-    let font_data = FontraFontData::from_file(fontdata_file)?;
-    to_ir_static_metadata(&font_data)
+impl StaticMetadataWork {
+    /// Fontra glyphs are sparse, any glyph may have sources that aren't at a font source
+    fn global_locations(&self) -> Result<HashSet<NormalizedLocation>, Error> {
+        let axes = to_ir_axes(&self.font_data)?;
+        let mut locations: HashSet<_> = font_source_locations(&axes, &self.font_data)
+            .into_values()
+            .collect();
+        for (glyph_file, _) in self.glyph_info.values() {
+            let glyph = FontraGlyph::from_file(glyph_file)?;
+            locations.extend(
+                glyph_source_locations(&axes, &self.font_data.sources, &glyph)?
+                    .into_iter()
+                    .map(|(_, location)| location),
+            );
+        }
+        Ok(locations)
+    }
 }
 
 impl Work<Context, WorkId, Error> for StaticMetadataWork {
@@ -186,12 +274,367 @@ impl Work<Context, WorkId, Error> for StaticMetadataWork {
 
     fn exec(&self, context: &Context) -> Result<(), Error> {
         debug!("Static metadata for {:#?}", self.fontdata_file);
+        let static_metadata = to_ir_static_metadata(&self.font_data, self.global_locations()?)?;
         context
             .preliminary_glyph_order
             .set(self.glyph_info.keys().cloned().collect());
-        context
-            .static_metadata
-            .set(create_static_metadata(&self.fontdata_file)?);
+        context.static_metadata.set(static_metadata);
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct GlobalMetricWork(Arc<FontraFontData>);
+
+impl Work<Context, WorkId, Error> for GlobalMetricWork {
+    fn id(&self) -> WorkId {
+        WorkId::GlobalMetrics
+    }
+
+    fn read_access(&self) -> Access<WorkId> {
+        Access::Variant(WorkId::StaticMetadata)
+    }
+
+    fn exec(&self, context: &Context) -> Result<(), Error> {
+        let font_data = self.0.as_ref();
+        let static_metadata = context.static_metadata.get();
+        let axes = &static_metadata.all_source_axes;
+        let mut metrics = GlobalMetrics::new();
+
+        // Sparse sources only hold glyphs or kerning
+        let mut has_metrics = false;
+        for source in font_data.sources.values().filter(|s| !s.is_sparse) {
+            has_metrics = true;
+            let pos = to_normalized_location(axes, &source.location);
+            trace!("Global metrics of source '{}' at {pos:?}", source.name);
+            metrics.set_if_some(
+                GlobalMetric::CapHeight,
+                pos.clone(),
+                source.line_metric("capHeight"),
+            );
+            metrics.populate_defaults(
+                &pos,
+                static_metadata.units_per_em,
+                source.line_metric("xHeight"),
+                source.line_metric("ascender"),
+                source.line_metric("descender"),
+                Some(source.italic_angle),
+            );
+        }
+        if !has_metrics {
+            metrics.populate_defaults(
+                static_metadata.default_location(),
+                static_metadata.units_per_em,
+                None,
+                None,
+                None,
+                None,
+            );
+        }
+
+        trace!("{:#?}", metrics);
+        context.global_metrics.set(metrics);
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct GlyphIrWork {
+    glyph_name: GlyphName,
+    font_data: Arc<FontraFontData>,
+    glyph_info: Arc<BTreeMap<GlyphName, (PathBuf, Vec<u32>)>>,
+}
+
+impl Work<Context, WorkId, Error> for GlyphIrWork {
+    fn id(&self) -> WorkId {
+        WorkId::Glyph(self.glyph_name.clone())
+    }
+
+    fn read_access(&self) -> Access<WorkId> {
+        Access::Variant(WorkId::StaticMetadata)
+    }
+
+    fn write_access(&self) -> Access<WorkId> {
+        AccessBuilder::new()
+            .specific_instance(WorkId::Glyph(self.glyph_name.clone()))
+            .specific_instance(WorkId::Anchor(self.glyph_name.clone()))
+            .build()
+    }
+
+    fn also_completes(&self) -> Vec<WorkId> {
+        vec![WorkId::Anchor(self.glyph_name.clone())]
+    }
+
+    fn exec(&self, context: &Context) -> Result<(), Error> {
+        trace!("Generate IR for '{}'", self.glyph_name.as_str());
+        let static_metadata = context.static_metadata.get();
+        let (glyph_file, codepoints) = self
+            .glyph_info
+            .get(&self.glyph_name)
+            .ok_or_else(|| Error::NoGlyphForName(self.glyph_name.clone()))?;
+        let fontra_glyph = FontraGlyph::from_file(glyph_file)?;
+        let (glyph, anchors) = to_ir_glyph(
+            &static_metadata.all_source_axes,
+            &self.font_data.sources,
+            codepoints.iter().copied().collect(),
+            &fontra_glyph,
+        )?;
+        context.anchors.set(anchors);
+        context.glyphs.set(glyph);
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct FeatureWork {
+    features_file: Option<PathBuf>,
+}
+
+impl Work<Context, WorkId, Error> for FeatureWork {
+    fn id(&self) -> WorkId {
+        WorkId::Features
+    }
+
+    fn exec(&self, context: &Context) -> Result<(), Error> {
+        trace!("Generate features");
+        // Includes resolve relative to the .fontra directory
+        let features = match &self.features_file {
+            Some(fea_file) => {
+                let include_dir = fea_file.parent().map(Path::to_path_buf);
+                FeaturesSource::from_file(fea_file.clone(), include_dir)
+            }
+            None => FeaturesSource::empty(),
+        };
+        context.features.set(features);
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct FeatureVariationsWork;
+
+impl Work<Context, WorkId, Error> for FeatureVariationsWork {
+    fn id(&self) -> WorkId {
+        WorkId::FeatureVariations
+    }
+
+    fn exec(&self, _context: &Context) -> Result<(), Error> {
+        debug!("Feature variations not implemented for Fontra");
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct KerningGroupWork {
+    font_data: Arc<FontraFontData>,
+    kerning: Arc<FontraKerning>,
+}
+
+#[derive(Debug)]
+struct KerningInstanceWork {
+    font_data: Arc<FontraFontData>,
+    kerning: Arc<FontraKerning>,
+    location: NormalizedLocation,
+}
+
+/// The location of each kerning source, None if it isn't a font source
+fn kerning_source_locations(
+    static_metadata: &StaticMetadata,
+    font_data: &FontraFontData,
+    kerning: &FontraKerning,
+) -> Vec<Option<NormalizedLocation>> {
+    let mut locations = font_source_locations(&static_metadata.all_source_axes, font_data);
+    kerning
+        .source_identifiers
+        .iter()
+        .map(|id| {
+            let location = locations.remove(id);
+            if location.is_none() {
+                warn!("Kerning is present for non-existent source {id}");
+            }
+            location
+        })
+        .collect()
+}
+
+/// Groups are prefixed with '@' in kerning values
+fn kern_participant(
+    glyph_order: &GlyphOrder,
+    groups: &BTreeMap<KernGroup, BTreeSet<GlyphName>>,
+    to_group: fn(&str) -> KernGroup,
+    raw_side: &str,
+) -> Option<KernSide> {
+    if let Some(group_name) = raw_side.strip_prefix('@') {
+        let group = to_group(group_name);
+        if groups.contains_key(&group) {
+            Some(KernSide::Group(group))
+        } else {
+            warn!("Invalid kern side: {raw_side}, no group {group:?}");
+            None
+        }
+    } else {
+        let name = GlyphName::from(raw_side);
+        if glyph_order.contains(&name) {
+            Some(KernSide::Glyph(name))
+        } else {
+            warn!("Invalid kern side: {raw_side}, no such glyph");
+            None
+        }
+    }
+}
+
+impl Work<Context, WorkId, Error> for KerningGroupWork {
+    fn id(&self) -> WorkId {
+        WorkId::KerningGroups
+    }
+
+    fn read_access(&self) -> Access<WorkId> {
+        Access::Variant(WorkId::StaticMetadata)
+    }
+
+    fn exec(&self, context: &Context) -> Result<(), Error> {
+        trace!("Generate IR for kerning");
+        let static_metadata = context.static_metadata.get();
+        let kerning = self.kerning.as_ref();
+
+        let mut groups = KerningGroups::default();
+        let side1 = kerning
+            .groups_side1
+            .iter()
+            .map(|(name, glyphs)| (KernGroup::Side1(name.as_str().into()), glyphs));
+        let side2 = kerning
+            .groups_side2
+            .iter()
+            .map(|(name, glyphs)| (KernGroup::Side2(name.as_str().into()), glyphs));
+        for (group, glyphs) in side1.chain(side2) {
+            groups
+                .groups
+                .insert(group, glyphs.iter().cloned().collect());
+        }
+
+        // Only sources that actually have a value kern
+        groups.locations = kerning_source_locations(&static_metadata, &self.font_data, kerning)
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| {
+                kerning
+                    .values
+                    .values()
+                    .flat_map(|side2| side2.values())
+                    .any(|values| values[*i].is_some())
+            })
+            .filter_map(|(_, location)| location)
+            .collect();
+
+        context.kerning_groups.set(groups);
+        Ok(())
+    }
+}
+
+impl Work<Context, WorkId, Error> for KerningInstanceWork {
+    fn id(&self) -> WorkId {
+        WorkId::KernInstance(self.location.clone())
+    }
+
+    fn read_access(&self) -> Access<WorkId> {
+        AccessBuilder::new()
+            .variant(WorkId::StaticMetadata)
+            .variant(WorkId::GlyphOrder)
+            .variant(WorkId::KerningGroups)
+            .build()
+    }
+
+    fn exec(&self, context: &Context) -> Result<(), Error> {
+        trace!("Generate IR for kerning at {:?}", self.location);
+        let static_metadata = context.static_metadata.get();
+        let kerning_groups = context.kerning_groups.get();
+        let groups = &kerning_groups.groups;
+        let glyph_order = context.glyph_order.get();
+        let kerning = self.kerning.as_ref();
+
+        let mut kerns = KerningInstance {
+            location: self.location.clone(),
+            ..Default::default()
+        };
+
+        // The sources at our location
+        let source_idxs: Vec<_> =
+            kerning_source_locations(&static_metadata, &self.font_data, kerning)
+                .into_iter()
+                .enumerate()
+                .filter(|(_, location)| location.as_ref() == Some(&self.location))
+                .map(|(i, _)| i)
+                .collect();
+
+        for (side1, side2_values) in kerning.values.iter() {
+            for (side2, values) in side2_values.iter() {
+                let Some(value) = source_idxs.iter().find_map(|i| values[*i]) else {
+                    continue;
+                };
+                let side1 = kern_participant(
+                    &glyph_order,
+                    groups,
+                    |name| KernGroup::Side1(name.into()),
+                    side1,
+                );
+                let side2 = kern_participant(
+                    &glyph_order,
+                    groups,
+                    |name| KernGroup::Side2(name.into()),
+                    side2,
+                );
+                let (Some(side1), Some(side2)) = (side1, side2) else {
+                    continue;
+                };
+                kerns.kerns.insert((side1, side2), value.into());
+            }
+        }
+
+        context.kerning_at.set(kerns);
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct ColorPaletteWork;
+
+impl Work<Context, WorkId, Error> for ColorPaletteWork {
+    fn id(&self) -> WorkId {
+        WorkId::ColorPalettes
+    }
+
+    fn read_access(&self) -> Access<WorkId> {
+        Access::None
+    }
+
+    fn write_access(&self) -> Access<WorkId> {
+        Access::Variant(WorkId::ColorPalettes)
+    }
+
+    fn exec(&self, _context: &Context) -> Result<(), Error> {
+        debug!("Color palettes not implemented for Fontra");
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct PaintGraphWork;
+
+impl Work<Context, WorkId, Error> for PaintGraphWork {
+    fn id(&self) -> WorkId {
+        WorkId::PaintGraph
+    }
+
+    fn read_access(&self) -> Access<WorkId> {
+        Access::None
+    }
+
+    fn write_access(&self) -> Access<WorkId> {
+        Access::Variant(WorkId::PaintGraph)
+    }
+
+    fn exec(&self, _context: &Context) -> Result<(), Error> {
+        debug!("Paint graph not implemented for Fontra");
         Ok(())
     }
 }
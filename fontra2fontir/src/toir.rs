@@ -1,6 +1,9 @@
 //! Functions to convert fontra things to fontc IR things
 
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    str::FromStr,
+};
 
 use fontdrasil::{
     coords::{CoordConverter, DesignCoord, NormalizedCoord, NormalizedLocation, UserCoord},
@@ -8,16 +11,29 @@ use fontdrasil::{
 };
 use fontir::{
     error::{BadGlyph, BadGlyphKind, Error, PathConversionError},
-    ir::{Glyph, GlyphInstance, GlyphPathBuilder, StaticMetadata},
+    ir::{
+        AnchorBuilder, Component, Glyph, GlyphAnchors, GlyphBuilder, GlyphInstance,
+        GlyphPathBuilder, NameBuilder, NameKey, StaticMetadata, VariableComponent,
+        DEFAULT_VENDOR_ID,
+    },
 };
-use kurbo::BezPath;
-use log::trace;
-use write_fonts::types::Tag;
+use kurbo::{BezPath, Point};
+use log::{trace, warn};
+use write_fonts::types::{NameId, Tag};
 
-use crate::fontra::{AxisName, FontraContour, FontraFontData, FontraGlyph, FontraPoint, PointType};
+use crate::fontra::{
+    AxisName, FontraComponent, FontraContour, FontraFontData, FontraFontInfo, FontraFontSource,
+    FontraGlyph, FontraPoint, FontraSource, PointType, SourceIdentifier,
+};
 
-pub(crate) fn to_ir_static_metadata(font_data: &FontraFontData) -> Result<StaticMetadata, Error> {
-    let axes = font_data
+pub(crate) fn to_ir_axes(font_data: &FontraFontData) -> Result<Vec<Axis>, Error> {
+    if !font_data.axes.mappings.is_empty() {
+        return Err(Error::UnsupportedConstruct(
+            "cross-axis mappings".to_string(),
+        ));
+    }
+    font_data
+        .axes
         .axes
         .iter()
         .map(|a| match a {
@@ -66,102 +82,255 @@ pub(crate) fn to_ir_static_metadata(font_data: &FontraFontData) -> Result<Static
                 converter,
             })
         })
-        .collect::<Result<_, _>>()?;
+        .collect()
+}
+
+/// Normalize a location in design coordinates keyed by axis name.
+///
+/// Axes the location doesn't mention are at their default.
+pub(crate) fn to_normalized_location(
+    axes: &[Axis],
+    location: &HashMap<AxisName, f64>,
+) -> NormalizedLocation {
+    axes.iter()
+        .map(|axis| {
+            let coord = location
+                .get(&axis.name)
+                .map(|value| DesignCoord::new(*value).to_normalized(&axis.converter))
+                .unwrap_or(NormalizedCoord::new(0.0));
+            (axis.tag, coord)
+        })
+        .collect()
+}
+
+/// The normalized location of each font source, by identifier
+pub(crate) fn font_source_locations(
+    axes: &[Axis],
+    font_data: &FontraFontData,
+) -> BTreeMap<SourceIdentifier, NormalizedLocation> {
+    font_data
+        .sources
+        .iter()
+        .map(|(id, source)| (id.clone(), to_normalized_location(axes, &source.location)))
+        .collect()
+}
+
+/// The normalized location of each active source of a glyph.
+///
+/// A source with a `locationBase` starts from the location of that font source. IR has
+/// no glyph-local axes so only sources at the default of every glyph-local axis are used.
+pub(crate) fn glyph_source_locations<'a>(
+    axes: &[Axis],
+    font_sources: &BTreeMap<SourceIdentifier, FontraFontSource>,
+    fontra_glyph: &'a FontraGlyph,
+) -> Result<Vec<(&'a FontraSource, NormalizedLocation)>, BadGlyph> {
+    let local_axes: HashMap<_, _> = fontra_glyph
+        .axes
+        .iter()
+        .map(|a| (a.name.as_str(), a.default_value))
+        .collect();
+
+    let mut locations = Vec::new();
+    for source in fontra_glyph.sources.iter().filter(|s| !s.inactive) {
+        let mut design_location = HashMap::new();
+        if let Some(base) = &source.location_base {
+            let Some(font_source) = font_sources.get(base) else {
+                return Err(BadGlyph::new(
+                    fontra_glyph.name.clone(),
+                    BadGlyphKind::MissingMaster(base.clone()),
+                ));
+            };
+            design_location.extend(font_source.location.clone());
+        }
+        design_location.extend(source.location.clone());
+
+        let at_local_default = local_axes.iter().all(|(name, default)| {
+            design_location
+                .get(*name)
+                .is_none_or(|value| value == default)
+        });
+        if !at_local_default {
+            trace!(
+                "Skip '{}' source '{}', it varies along glyph-local axes",
+                fontra_glyph.name,
+                source.name
+            );
+            continue;
+        }
+        // Glyph-local axes shadow global axes of the same name
+        design_location.retain(|name, _| !local_axes.contains_key(name.as_str()));
+        locations.push((source, to_normalized_location(axes, &design_location)));
+    }
+    Ok(locations)
+}
 
-    StaticMetadata::new(
+pub(crate) fn to_ir_static_metadata(
+    font_data: &FontraFontData,
+    global_locations: HashSet<NormalizedLocation>,
+) -> Result<StaticMetadata, Error> {
+    let axes = to_ir_axes(font_data)?;
+    let font_info = &font_data.font_info;
+    let mut static_metadata = StaticMetadata::new(
         font_data.units_per_em,
-        Default::default(),
+        names(font_info),
         axes,
         Default::default(),
-        Default::default(), // TODO: glyph locations we really do need
+        global_locations,
         Default::default(),
         Default::default(),
         Default::default(),
         None,
     )
-    .map_err(Error::VariationModelError)
+    .map_err(Error::VariationModelError)?;
+
+    if let Some(vendor_id) = font_info.vendor_id.as_ref().filter(|id| !id.is_empty()) {
+        static_metadata.misc.vendor_id =
+            Tag::from_str(vendor_id).map_err(|cause| Error::InvalidTag {
+                raw_tag: vendor_id.to_owned(),
+                cause,
+            })?;
+    }
+    static_metadata.misc.version_major = font_info
+        .version_major
+        .unwrap_or(static_metadata.misc.version_major);
+    static_metadata.misc.version_minor = font_info
+        .version_minor
+        .unwrap_or(static_metadata.misc.version_minor);
+    Ok(static_metadata)
 }
 
-#[allow(dead_code)] // TEMPORARY
-fn to_ir_glyph(
-    global_axes: HashMap<AxisName, Tag>,
-    codepoints: HashSet<u32>,
-    fontra_glyph: &FontraGlyph,
-) -> Result<Glyph, BadGlyph> {
-    let _local_axes: HashMap<_, _> = fontra_glyph
-        .axes
-        .iter()
-        .map(|a| (a.name.as_str(), a))
-        .collect();
+fn names(font_info: &FontraFontInfo) -> HashMap<NameKey, String> {
+    let mut builder = NameBuilder::default();
+    builder.set_version(
+        font_info.version_major.unwrap_or_default(),
+        font_info.version_minor.unwrap_or_default(),
+    );
 
-    let layer_locations: HashMap<_, _> = fontra_glyph
-        .sources
-        .iter()
-        .map(|s| (&s.layer_name, &s.location))
-        .collect();
+    builder.add_if_present(NameId::COPYRIGHT_NOTICE, &font_info.copyright);
+    builder.add_if_present(NameId::FAMILY_NAME, &font_info.family_name);
+    builder.add_if_present(NameId::TRADEMARK, &font_info.trademark);
+    builder.add_if_present(NameId::MANUFACTURER, &font_info.manufacturer);
+    builder.add_if_present(NameId::DESIGNER, &font_info.designer);
+    builder.add_if_present(NameId::DESCRIPTION, &font_info.description);
+    builder.add_if_present(NameId::VENDOR_URL, &font_info.manufacturer_url);
+    builder.add_if_present(NameId::DESIGNER_URL, &font_info.designer_url);
+    builder.add_if_present(NameId::LICENSE_DESCRIPTION, &font_info.license_description);
+    builder.add_if_present(NameId::LICENSE_URL, &font_info.license_info_url);
+    builder.add_if_present(NameId::SAMPLE_TEXT, &font_info.sample_text);
 
-    let mut instances = HashMap::new();
-    for (layer_name, layer) in fontra_glyph.layers.iter() {
-        // TODO: we need IR VARC support to proceed
-        if !fontra_glyph.axes.is_empty() {
-            todo!("Support local axes");
-        }
+    let vendor = font_info
+        .vendor_id
+        .as_deref()
+        .filter(|id| !id.is_empty())
+        .unwrap_or(DEFAULT_VENDOR_ID);
+    builder.apply_default_fallbacks(vendor);
+
+    builder.into_inner()
+}
 
-        let Some(location) = layer_locations.get(layer_name) else {
+pub(crate) fn to_ir_glyph(
+    axes: &[Axis],
+    font_sources: &BTreeMap<SourceIdentifier, FontraFontSource>,
+    codepoints: HashSet<u32>,
+    fontra_glyph: &FontraGlyph,
+) -> Result<(Glyph, GlyphAnchors), BadGlyph> {
+    let glyph_name = &fontra_glyph.name;
+    let mut glyph = GlyphBuilder::new(glyph_name.clone());
+    glyph.codepoints = codepoints;
+    let mut anchors = AnchorBuilder::new(glyph_name.clone());
+
+    for (source, location) in glyph_source_locations(axes, font_sources, fontra_glyph)? {
+        let Some(layer) = fontra_glyph.layers.get(&source.layer_name) else {
             return Err(BadGlyph::new(
-                fontra_glyph.name.clone(),
-                BadGlyphKind::MissingLayer(layer_name.clone()),
+                glyph_name.clone(),
+                BadGlyphKind::MissingLayer(source.layer_name.clone()),
             ));
         };
-        let global_location: NormalizedLocation = global_axes
-            .iter()
-            .map(|(name, tag)| {
-                (
-                    *tag,
-                    NormalizedCoord::new(location.get(name).copied().unwrap_or_default()),
-                )
-            })
-            .collect();
+        let layer = &layer.glyph;
 
         let contours: Vec<_> = layer
-            .glyph
             .path
-            .contours
+            .contours()
+            .map_err(|e| BadGlyph::new(glyph_name.clone(), e))?
             .iter()
-            .map(|c| to_ir_path(fontra_glyph.name.clone(), c))
+            .map(|c| to_ir_path(glyph_name.clone(), c))
             .collect::<Result<_, _>>()?;
-        if instances
-            .insert(
-                global_location.clone(),
-                GlyphInstance {
-                    width: layer.glyph.x_advance,
-                    contours,
-                    ..Default::default()
-                },
-            )
-            .is_some()
-        {
-            return Err(BadGlyph::new(
-                fontra_glyph.name.clone(),
-                BadGlyphKind::DuplicateLocation(global_location),
-            ));
-        };
+        let components: Vec<_> = layer
+            .components
+            .iter()
+            .map(|c| to_ir_component(axes, glyph_name, c))
+            .collect();
+        glyph.try_add_source(
+            &location,
+            GlyphInstance {
+                width: layer.x_advance,
+                height: layer.y_advance,
+                vertical_origin: layer.vertical_origin,
+                contours,
+                components,
+            },
+        )?;
+
+        for anchor in layer.anchors.iter() {
+            let Some(name) = &anchor.name else {
+                continue;
+            };
+            anchors.add(
+                name.as_str().into(),
+                location.clone(),
+                Point::new(anchor.x, anchor.y),
+            )?;
+        }
     }
 
-    Glyph::new(fontra_glyph.name.clone(), true, codepoints, instances)
+    Ok((glyph.build()?, anchors.build()?))
+}
+
+/// A component, variable if it has a location along any font axis.
+///
+/// Axes the location doesn't mention follow the location of the glyph using the component.
+fn to_ir_component(
+    axes: &[Axis],
+    glyph_name: &GlyphName,
+    component: &FontraComponent,
+) -> Component {
+    let mut location = NormalizedLocation::new();
+    for (name, value) in component.location.iter() {
+        let Some(axis) = axes.iter().find(|a| &a.name == name) else {
+            warn!(
+                "'{glyph_name}' component '{}' uses glyph-local axis '{name}', it is ignored",
+                component.name
+            );
+            continue;
+        };
+        location.insert(
+            axis.tag,
+            DesignCoord::new(*value).to_normalized(&axis.converter),
+        );
+    }
+    let transform = component.transformation.to_decomposed();
+    Component {
+        base: component.name.clone(),
+        transform: transform.to_affine(),
+        variable: (!location.is_empty()).then(|| VariableComponent::new(location, transform)),
+        anchor: None,
+    }
 }
 
-#[allow(dead_code)] // TEMPORARY
 fn add_to_path<'a>(
     path_builder: &'a mut GlyphPathBuilder,
     points: impl Iterator<Item = &'a FontraPoint>,
 ) -> Result<(), PathConversionError> {
     // Walk through the remaining points, accumulating off-curve points until we see an on-curve
     // https://github.com/googlefonts/glyphsLib/blob/24b4d340e4c82948ba121dcfe563c1450a8e69c9/Lib/glyphsLib/pens.py#L92
+    let mut quadratic = false;
     for point in points {
         let point_type = point.point_type()?;
         // Smooth is only relevant to editors so ignore here
         match point_type {
+            PointType::OnCurve | PointType::OnCurveSmooth if quadratic => {
+                path_builder.qcurve_to((point.x, point.y))?
+            }
             PointType::OnCurve | PointType::OnCurveSmooth => {
                 path_builder.curve_to((point.x, point.y))?
             }
@@ -169,6 +338,7 @@ fn add_to_path<'a>(
                 path_builder.offcurve((point.x, point.y))?
             }
         }
+        quadratic = point_type == PointType::OffCurveQuad;
     }
     Ok(())
 }
@@ -216,20 +386,40 @@ fn to_ir_path(glyph_name: GlyphName, contour: &FontraContour) -> Result<BezPath,
 
 #[cfg(test)]
 mod tests {
-    use std::collections::{HashMap, HashSet};
+    use std::collections::HashSet;
 
-    use fontdrasil::types::Axis;
+    use fontdrasil::{coords::NormalizedCoord, types::Axis};
     use fontir::ir::Glyph;
     use kurbo::{BezPath, PathEl};
-    use write_fonts::types::Tag;
+    use write_fonts::types::{NameId, Tag};
 
     use crate::{
         fontra::{FontraFontData, FontraGlyph},
         test::testdata_dir,
-        toir::to_ir_static_metadata,
     };
 
-    use super::to_ir_glyph;
+    use super::*;
+
+    fn load(fontra_dir: &str, glyph_name: &str) -> (FontraFontData, FontraGlyph) {
+        let fontra_dir = testdata_dir().join(fontra_dir);
+        let font_data = FontraFontData::from_file(&fontra_dir.join("font-data.json")).unwrap();
+        let glyph_file = crate::fontra::glyph_file(&fontra_dir.join("glyphs"), glyph_name.into());
+        (font_data, FontraGlyph::from_file(&glyph_file).unwrap())
+    }
+
+    fn wght_positions(font_data: &FontraFontData, glyph: &FontraGlyph) -> Vec<(String, f64)> {
+        let axes = to_ir_axes(font_data).unwrap();
+        glyph_source_locations(&axes, &font_data.sources, glyph)
+            .unwrap()
+            .into_iter()
+            .map(|(source, loc)| {
+                (
+                    source.name.clone(),
+                    loc.get(Tag::new(b"wght")).unwrap().to_f64(),
+                )
+            })
+            .collect()
+    }
 
     fn axis_tuples(axes: &[Axis]) -> Vec<(&str, Tag, f64, f64, f64)> {
         axes.iter()
@@ -278,7 +468,7 @@ mod tests {
     fn static_metadata_of_2glyphs() {
         let fontdata_file = testdata_dir().join("2glyphs.fontra/font-data.json");
         let font_data = FontraFontData::from_file(&fontdata_file).unwrap();
-        let static_metadata = to_ir_static_metadata(&font_data).unwrap();
+        let static_metadata = to_ir_static_metadata(&font_data, Default::default()).unwrap();
         assert_eq!(1000, static_metadata.units_per_em);
         assert_eq!(
             vec![
@@ -289,16 +479,40 @@ mod tests {
         );
     }
 
+    #[test]
+    fn static_metadata_of_wght_var() {
+        let fontdata_file = testdata_dir().join("wght_var.fontra/font-data.json");
+        let font_data = FontraFontData::from_file(&fontdata_file).unwrap();
+        let static_metadata = to_ir_static_metadata(&font_data, Default::default()).unwrap();
+        assert_eq!(
+            (Some("Wght Var"), Some("Version 1.002")),
+            (
+                static_metadata
+                    .names
+                    .get(&NameKey::new_bmp_only(NameId::FAMILY_NAME))
+                    .map(String::as_str),
+                static_metadata
+                    .names
+                    .get(&NameKey::new_bmp_only(NameId::VERSION_STRING))
+                    .map(String::as_str)
+            )
+        );
+        assert_eq!(Tag::new(b"FNTR"), static_metadata.misc.vendor_id);
+        assert_eq!(
+            (1, 2),
+            (
+                static_metadata.misc.version_major,
+                static_metadata.misc.version_minor
+            )
+        );
+    }
+
     #[test]
     fn ir_of_glyph_u20089() {
-        let glyph_file = testdata_dir().join("2glyphs.fontra/glyphs/u20089.json");
-        let fontra_glyph = FontraGlyph::from_file(&glyph_file).unwrap();
-        let glyph = to_ir_glyph(
-            HashMap::from([("Weight".to_string(), Tag::new(b"wght"))]),
-            Default::default(),
-            &fontra_glyph,
-        )
-        .unwrap();
+        let (font_data, fontra_glyph) = load("2glyphs.fontra", "u20089");
+        let axes = to_ir_axes(&font_data).unwrap();
+        let (glyph, _) =
+            to_ir_glyph(&axes, &font_data.sources, Default::default(), &fontra_glyph).unwrap();
         assert_eq!(
             vec![(2, 0), (2, 0)],
             glyph
@@ -309,4 +523,84 @@ mod tests {
         );
         assert_contour_compatibility(&glyph);
     }
+
+    #[test]
+    fn glyph_locations_are_normalized_design_locations() {
+        let (font_data, fontra_glyph) = load("2glyphs.fontra", "u20089");
+        let axes = to_ir_axes(&font_data).unwrap();
+        let (glyph, _) =
+            to_ir_glyph(&axes, &font_data.sources, Default::default(), &fontra_glyph).unwrap();
+        let mut wght: Vec<_> = glyph
+            .sources()
+            .keys()
+            .map(|loc| loc.get(Tag::new(b"wght")).unwrap())
+            .collect();
+        wght.sort();
+        assert_eq!(
+            vec![NormalizedCoord::new(0.0), NormalizedCoord::new(1.0)],
+            wght
+        );
+    }
+
+    #[test]
+    fn glyph_locations_use_location_base() {
+        let (font_data, fontra_glyph) = load("wght_var.fontra", "V");
+        // The inactive source is skipped
+        assert_eq!(
+            vec![
+                ("Regular".to_string(), 0.0),
+                ("Semibold".to_string(), 0.5),
+                ("Bold".to_string(), 1.0)
+            ],
+            wght_positions(&font_data, &fontra_glyph)
+        );
+    }
+
+    #[test]
+    fn glyph_locations_skip_local_axis_variation() {
+        let (font_data, fontra_glyph) = load("component.fontra", "VG_4E00_00.alt");
+        assert_eq!(
+            vec![("<default>".to_string(), 0.0), ("wght=1".to_string(), 1.0)],
+            wght_positions(&font_data, &fontra_glyph)
+        );
+    }
+
+    #[test]
+    fn ir_of_packed_glyph_with_anchors() {
+        let (font_data, fontra_glyph) = load("wght_var.fontra", "A");
+        let axes = to_ir_axes(&font_data).unwrap();
+        let (glyph, anchors) =
+            to_ir_glyph(&axes, &font_data.sources, Default::default(), &fontra_glyph).unwrap();
+        assert_eq!(2, glyph.sources().len());
+        assert_contour_compatibility(&glyph);
+        assert_eq!(vec![(280.0, 700.0), (300.0, 720.0)], {
+            let mut positions: Vec<_> = anchors.anchors[0]
+                .positions
+                .values()
+                .map(|p| (p.x, p.y))
+                .collect();
+            positions.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            positions
+        });
+    }
+
+    #[test]
+    fn component_transform() {
+        let (font_data, fontra_glyph) = load("component.fontra", "uni4E00");
+        let axes = to_ir_axes(&font_data).unwrap();
+        let (glyph, _) =
+            to_ir_glyph(&axes, &font_data.sources, Default::default(), &fontra_glyph).unwrap();
+        let default = glyph.default_instance();
+        assert_eq!(
+            vec![(
+                GlyphName::new("VG_4E00_00"),
+                [1.0, 0.0, 0.0, 1.0, 53.0, 0.0]
+            )],
+            default
+                .components
+                .iter()
+                .map(|c| (c.base.clone(), c.transform.as_coeffs()))
+                .collect::<Vec<_>>()
+        );
+    }
 }
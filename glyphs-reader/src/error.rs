@@ -1,5 +1,6 @@
 use std::{io, num::TryFromIntError, path::PathBuf};
 
+use smol_str::SmolStr;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -22,4 +23,6 @@ pub enum Error {
     WorstPlistEver(#[from] crate::plist::Error),
     #[error("Invalid code page {0}")]
     InvalidCodePage(u32),
+    #[error("Unable to use smart glyph '{0}': {1}")]
+    BadSmartGlyph(SmolStr, String),
 }
@@ -104,7 +104,11 @@ pub struct CustomParameters {
     pub vhea_caret_slope_run: Option<i64>,
     pub vhea_caret_slope_rise: Option<i64>,
     pub vhea_caret_offset: Option<i64>,
+    pub vhea_ascender: Option<i64>,
+    pub vhea_descender: Option<i64>,
+    pub vhea_line_gap: Option<i64>,
     pub meta_table: Option<MetaTableValues>,
+    pub feature_for_feature_variations: Option<SmolStr>,
     // these fields are parsed via the config, but are stored
     // in the top-level `Font` struct
     pub virtual_masters: Option<Vec<BTreeMap<String, OrderedFloat<f64>>>>,
@@ -262,6 +266,8 @@ pub struct Glyph {
     pub right_kern: Option<SmolStr>,
     pub category: Option<Category>,
     pub sub_category: Option<Subcategory>,
+    /// The axes of a smart glyph, its components pick a location along them
+    pub smart_component_axes: Vec<SmartComponentAxis>,
 }
 
 impl Glyph {
@@ -271,14 +277,6 @@ impl Glyph {
             (Some(Category::Mark), Some(Subcategory::Nonspacing))
         )
     }
-
-    pub(crate) fn has_components(&self) -> bool {
-        self.layers
-            .iter()
-            .flat_map(Layer::components)
-            .next()
-            .is_some()
-    }
 }
 
 #[derive(Debug, Default, Clone, PartialEq, Hash)]
@@ -286,9 +284,17 @@ pub struct Layer {
     pub layer_id: String,
     pub associated_master_id: Option<String>,
     pub width: OrderedFloat<f64>,
+    /// Vertical advance, if set
+    pub vert_width: Option<OrderedFloat<f64>>,
+    /// Distance from the ascender down to the vertical origin, if set
+    pub vert_origin: Option<OrderedFloat<f64>>,
     pub shapes: Vec<Shape>,
     pub anchors: Vec<Anchor>,
     pub attributes: LayerAttributes,
+    /// For layers of a smart glyph, the end of each smart component axis the layer is at
+    pub smart_component_poles: BTreeMap<SmolStr, SmartComponentPole>,
+    /// Corner, cap and segment components attached to the paths of the layer
+    pub hint_components: Vec<HintComponent>,
 }
 
 impl Layer {
@@ -300,6 +306,11 @@ impl Layer {
         self.associated_master_id.is_some() && !self.attributes.coordinates.is_empty()
     }
 
+    /// True if this is an alternate (aka 'bracket') layer, used in part of the designspace
+    pub fn is_alternate(&self) -> bool {
+        self.associated_master_id.is_some() && !self.attributes.axis_rules.is_empty()
+    }
+
     pub(crate) fn components(&self) -> impl Iterator<Item = &Component> + '_ {
         self.shapes.iter().filter_map(|shape| match shape {
             Shape::Path(_) => None,
@@ -307,14 +318,175 @@ impl Layer {
         })
     }
 
-    // TODO add is_alternate, is_color, etc.
+    // TODO add is_color, etc.
 }
 
 #[derive(Clone, Default, Debug, PartialEq, Hash)]
 pub struct LayerAttributes {
     pub coordinates: Vec<OrderedFloat<f64>>,
     pub color: bool,
-    // TODO: add axisRules, etc.
+    /// For alternate (aka 'bracket') layers, the range of each axis, in axis order,
+    /// where the layer is used
+    pub axis_rules: Vec<AxisRule>,
+}
+
+/// An axis of a smart glyph, what Glyphs calls a part setting.
+///
+/// <https://github.com/schriftgestalt/GlyphsSDK/blob/Glyphs3/GlyphsFileFormat/GlyphsFileFormatv3.md#spec-glyphs-3-glyph>
+#[derive(Clone, Default, Debug, PartialEq, Hash, FromPlist)]
+pub struct SmartComponentAxis {
+    pub name: SmolStr,
+    pub bottom_value: OrderedFloat<f64>,
+    pub top_value: OrderedFloat<f64>,
+}
+
+/// The end of a smart component axis a layer of a smart glyph is at
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SmartComponentPole {
+    Bottom,
+    Top,
+}
+
+impl TryFrom<i64> for SmartComponentPole {
+    type Error = Error;
+
+    fn try_from(value: i64) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(SmartComponentPole::Bottom),
+            2 => Ok(SmartComponentPole::Top),
+            _ => Err(Error::StructuralError(format!(
+                "Bad smart component pole {value}"
+            ))),
+        }
+    }
+}
+
+/// What a [HintComponent] does to the path it is attached to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum HintComponentKind {
+    /// Replaces the corner at a node, a `_corner.*` glyph
+    Corner,
+    /// Replaces the segment that follows a node, a `_cap.*` glyph
+    Cap,
+    /// Follows the segment that follows a node, a `_segment.*` glyph
+    Segment,
+}
+
+impl HintComponentKind {
+    /// The kind of a hint, if it attaches a component
+    ///
+    /// Glyphs 3 names the type, Glyphs 2 numbers it.
+    fn from_hint_type(hint_type: &Plist) -> Option<Self> {
+        match hint_type {
+            Plist::String(name) => match name.to_ascii_lowercase().as_str() {
+                "corner" => Some(HintComponentKind::Corner),
+                "cap" => Some(HintComponentKind::Cap),
+                "segment" => Some(HintComponentKind::Segment),
+                _ => None,
+            },
+            Plist::Integer(16) => Some(HintComponentKind::Corner),
+            Plist::Integer(17) => Some(HintComponentKind::Cap),
+            _ => None,
+        }
+    }
+}
+
+/// A corner, cap or segment component
+///
+/// Glyphs stores these with the hints of a layer as they are attached to a node of a
+/// path rather than placed freely.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HintComponent {
+    pub kind: HintComponentKind,
+    /// The glyph this component references
+    pub name: SmolStr,
+    /// Index into the paths of the layer, not counting components
+    pub path_index: usize,
+    /// Index into the nodes of the path
+    pub node_index: usize,
+    /// Applied to the component's outline before it's fit to the path
+    pub scale: Vec2,
+    /// For corners, how the component is aligned to the strokes meeting at the node
+    pub alignment: CornerAlignment,
+}
+
+impl Hash for HintComponent {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.kind.hash(state);
+        self.name.hash(state);
+        self.path_index.hash(state);
+        self.node_index.hash(state);
+        PointForEqAndHash::new(self.scale.to_point()).hash(state);
+        self.alignment.hash(state);
+    }
+}
+
+/// Which stroke a corner component follows, stored in the options of its hint
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, Hash)]
+pub enum CornerAlignment {
+    /// The x axis of the component runs along the stroke leaving the corner
+    #[default]
+    Outstroke,
+    /// The y axis of the component runs back along the stroke entering the corner
+    Instroke,
+    /// The component is rotated halfway between the other two alignments
+    Middle,
+    /// The component keeps its orientation
+    Unaligned,
+}
+
+impl From<i64> for CornerAlignment {
+    fn from(options: i64) -> Self {
+        match options & 0x3 {
+            1 => CornerAlignment::Instroke,
+            2 => CornerAlignment::Middle,
+            3 => CornerAlignment::Unaligned,
+            _ => CornerAlignment::Outstroke,
+        }
+    }
+}
+
+/// The range of an axis, in design coordinates, where an alternate layer applies.
+///
+/// A missing min or max means the range extends to the end of the axis.
+///
+/// <https://github.com/schriftgestalt/GlyphsSDK/blob/Glyphs3/GlyphsFileFormat/GlyphsFileFormatv3.md#spec-glyphs-3-layer>
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, FromPlist)]
+pub struct AxisRule {
+    pub min: Option<OrderedFloat<f64>>,
+    pub max: Option<OrderedFloat<f64>>,
+}
+
+impl AxisRule {
+    /// Parse the Glyphs 2 bracket syntax from a layer name.
+    ///
+    /// 'Bold [300]' applies from 300 up, 'Bold ]300]' below 300, both on the first axis.
+    /// Matches glyphsLib's `.*([\[\]])\s*(\d+)\s*\].*`, so the last match in the name wins.
+    fn from_v2_layer_name(name: &str) -> Option<AxisRule> {
+        name.char_indices()
+            .rev()
+            .filter(|(_, c)| *c == '[' || *c == ']')
+            .find_map(|(idx, c)| {
+                let rest = name[idx + 1..].trim_start();
+                let digits = rest.find(|c: char| !c.is_ascii_digit())?;
+                if digits == 0 || !rest[digits..].trim_start().starts_with(']') {
+                    return None;
+                }
+                let value: f64 = rest[..digits].parse().ok()?;
+                let value = Some(OrderedFloat(value));
+                Some(if c == '[' {
+                    AxisRule {
+                        min: value,
+                        max: None,
+                    }
+                } else {
+                    AxisRule {
+                        min: None,
+                        max: value,
+                    }
+                })
+            })
+    }
 }
 
 // hand-parse because they can take multiple shapes
@@ -322,6 +494,7 @@ impl FromPlist for LayerAttributes {
     fn parse(tokenizer: &mut Tokenizer<'_>) -> Result<Self, crate::plist::Error> {
         let mut coordinates = Vec::new();
         let mut color = false;
+        let mut axis_rules = Vec::new();
 
         tokenizer.eat(b'{')?;
 
@@ -335,6 +508,7 @@ impl FromPlist for LayerAttributes {
             match key.as_str() {
                 "coordinates" => coordinates = tokenizer.parse()?,
                 "color" => color = tokenizer.parse()?,
+                "axisRules" => axis_rules = tokenizer.parse()?,
                 // skip unsupported attributes for now
                 // TODO: match the others
                 _ => tokenizer.skip_rec()?,
@@ -342,7 +516,11 @@ impl FromPlist for LayerAttributes {
             tokenizer.eat(b';')?;
         }
 
-        Ok(LayerAttributes { coordinates, color })
+        Ok(LayerAttributes {
+            coordinates,
+            color,
+            axis_rules,
+        })
     }
 }
 
@@ -679,6 +857,9 @@ impl RawCustomParameters {
                 "hheaAscender" => add_and_report_issues!(hhea_ascender, Plist::as_i64),
                 "hheaDescender" => add_and_report_issues!(hhea_descender, Plist::as_i64),
                 "hheaLineGap" => add_and_report_issues!(hhea_line_gap, Plist::as_i64),
+                "vheaVertAscender" => add_and_report_issues!(vhea_ascender, Plist::as_i64),
+                "vheaVertDescender" => add_and_report_issues!(vhea_descender, Plist::as_i64),
+                "vheaVertLineGap" => add_and_report_issues!(vhea_line_gap, Plist::as_i64),
                 "underlineThickness" => {
                     add_and_report_issues!(underline_thickness, Plist::as_ordered_f64)
                 }
@@ -729,6 +910,12 @@ impl RawCustomParameters {
                 "meta Table" => {
                     add_and_report_issues!(meta_table, MetaTableValues::from_plist)
                 }
+                "Feature for Feature Variations" => {
+                    add_and_report_issues!(
+                        feature_for_feature_variations,
+                        value.as_str().map(SmolStr::new)
+                    )
+                }
                 // these might need to be handled? they're in the same list as
                 // the items above:
                 // https://github.com/googlefonts/glyphsLib/blob/74c63244fdb/Lib/glyphsLib/builder/custom_params.py#L429
@@ -851,6 +1038,7 @@ struct RawGlyph {
     unicode: Option<String>,
     category: Option<SmolStr>,
     sub_category: Option<SmolStr>,
+    parts_settings: Vec<SmartComponentAxis>,
     #[fromplist(ignore)]
     other_stuff: BTreeMap<String, Plist>,
 }
@@ -861,24 +1049,99 @@ struct RawLayer {
     layer_id: String,
     associated_master_id: Option<String>,
     width: Option<OrderedFloat<f64>>,
+    vert_width: Option<OrderedFloat<f64>>,
+    vert_origin: Option<OrderedFloat<f64>>,
     shapes: Vec<RawShape>,
     paths: Vec<Path>,
     components: Vec<Component>,
     anchors: Vec<RawAnchor>,
     #[fromplist(alt_name = "attr")]
     attributes: LayerAttributes,
+    part_selection: BTreeMap<SmolStr, i64>,
+    hints: Vec<RawHint>,
     #[fromplist(ignore)]
     other_stuff: BTreeMap<String, Plist>,
 }
 
+/// A hint, of which we only keep those that attach components
+#[derive(Default, Clone, Debug, PartialEq, FromPlist)]
+struct RawHint {
+    name: Option<SmolStr>,
+    #[fromplist(key = "type")]
+    type_: Option<Plist>,
+    origin: Option<Plist>,
+    scale: Option<Plist>,
+    options: Option<i64>,
+    #[fromplist(ignore)]
+    other_stuff: BTreeMap<String, Plist>,
+}
+
+impl RawHint {
+    fn to_hint_component(&self) -> Result<Option<HintComponent>, Error> {
+        let Some(kind) = self
+            .type_
+            .as_ref()
+            .and_then(HintComponentKind::from_hint_type)
+        else {
+            return Ok(None);
+        };
+        let Some(name) = self.name.clone() else {
+            warn!("Ignoring a {kind:?} component without a name");
+            return Ok(None);
+        };
+        let origin = self
+            .origin
+            .as_ref()
+            .and_then(plist_to_point)
+            .ok_or_else(|| {
+                Error::StructuralError(format!("Bad origin for {kind:?} component '{name}'"))
+            })?;
+        if origin.x < 0.0 || origin.y < 0.0 {
+            return Err(Error::StructuralError(format!(
+                "Bad origin {origin:?} for {kind:?} component '{name}'"
+            )));
+        }
+        let scale = match self.scale.as_ref() {
+            Some(scale) => plist_to_point(scale).ok_or_else(|| {
+                Error::StructuralError(format!("Bad scale for {kind:?} component '{name}'"))
+            })?,
+            None => Point::new(1.0, 1.0),
+        };
+        Ok(Some(HintComponent {
+            kind,
+            name,
+            path_index: origin.x as usize,
+            node_index: origin.y as usize,
+            scale: scale.to_vec2(),
+            alignment: self.options.unwrap_or_default().into(),
+        }))
+    }
+}
+
+/// A pair of numbers, `(x,y)` in Glyphs 3 or `"{x, y}"` in Glyphs 2
+fn plist_to_point(plist: &Plist) -> Option<Point> {
+    match plist {
+        Plist::String(raw) => Point::parse_plist(raw).ok(),
+        Plist::Array(values) => match values.as_slice() {
+            [x, y] => Some(Point::new(x.as_f64()?, y.as_f64()?)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
 impl RawLayer {
     /// Return true if the layer is a draft that is not meant to be compiled.
     ///
     /// The presence of an associated master indicates this is not a simple 'master' instance.
     /// Without 'attributes' that specify whether it's a special intermediate, alternate or
     /// color layer, we can assume the non-master layer is a draft.
+    ///
+    /// Layers of a smart glyph are told apart by where they are on its axes instead.
     fn is_draft(&self) -> bool {
-        self.associated_master_id.is_some() && self.attributes == Default::default()
+        self.associated_master_id.is_some()
+            && self.attributes == Default::default()
+            && self.part_selection.is_empty()
     }
 
     fn v2_to_v3_attributes(&mut self) {
@@ -897,7 +1160,11 @@ impl RawLayer {
         if !brace_coordinates.is_empty() {
             self.attributes.coordinates = brace_coordinates;
         }
-        // TODO: handle 'bracket' layers and other attributes
+        // In Glyphs v2, 'bracket' or alternate layers put the crossover in the layer name
+        if let Some(axis_rule) = AxisRule::from_v2_layer_name(&self.name) {
+            self.attributes.axis_rules = vec![axis_rule];
+        }
+        // TODO: handle other attributes
     }
 }
 
@@ -925,6 +1192,8 @@ struct RawShape {
     pos: Vec<f64>,             // v3
     angle: Option<f64>,        // v3
     scale: Vec<f64>,           // v3
+    // for components of smart glyphs, the location along the smart glyph's axes
+    piece: BTreeMap<SmolStr, OrderedFloat<f64>>,
 
     #[fromplist(alt_name = "attr")]
     attributes: ShapeAttributes,
@@ -951,12 +1220,16 @@ pub struct Component {
     /// we might rename its 'top' anchor to 'top_2'
     pub anchor: Option<SmolStr>,
     pub attributes: ShapeAttributes,
+    /// For a component of a smart glyph, the location along its axes by axis name
+    #[fromplist(key = "piece")]
+    pub smart_component_location: BTreeMap<SmolStr, OrderedFloat<f64>>,
 }
 
 impl PartialEq for Component {
     fn eq(&self, other: &Self) -> bool {
         self.name == other.name
             && Into::<AffineForEqAndHash>::into(self.transform) == other.transform.into()
+            && self.smart_component_location == other.smart_component_location
     }
 }
 
@@ -966,6 +1239,7 @@ impl Hash for Component {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
         self.name.hash(state);
         Into::<AffineForEqAndHash>::into(self.transform).hash(state);
+        self.smart_component_location.hash(state);
     }
 }
 
@@ -1021,16 +1295,6 @@ pub struct Anchor {
     pub pos: Point,
 }
 
-impl Anchor {
-    pub(crate) fn is_origin(&self) -> bool {
-        self.name == "*origin"
-    }
-
-    pub(crate) fn origin_delta(&self) -> Option<Vec2> {
-        self.is_origin().then_some(self.pos.to_vec2())
-    }
-}
-
 impl Hash for Anchor {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
         self.name.hash(state);
@@ -1075,6 +1339,25 @@ impl FontMaster {
     pub fn italic_angle(&self) -> Option<f64> {
         self.read_metric("italic angle")
     }
+
+    /// The position and overshoot of each metric with an overshoot, ordered by position.
+    ///
+    /// These are what Glyphs 2 called alignment zones.
+    pub fn alignment_zones(&self) -> Vec<(f64, f64)> {
+        let mut zones: Vec<_> = self
+            .metric_values
+            .values()
+            .filter_map(|metric| {
+                let over = metric.over.filter(|over| *over != 0.)?;
+                Some((metric.pos.unwrap_or_default(), over))
+            })
+            .collect();
+        zones.sort();
+        zones
+            .into_iter()
+            .map(|(pos, over)| (pos.into_inner(), over.into_inner()))
+            .collect()
+    }
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Eq, Hash, FromPlist)]
@@ -2080,6 +2363,7 @@ impl TryFrom<RawShape> for Shape {
                 transform,
                 anchor: from.anchor,
                 attributes: from.attributes,
+                smart_component_location: from.piece,
             })
         } else {
             // no ref; presume it's a path
@@ -2158,13 +2442,28 @@ impl TryFrom<RawLayer> for Layer {
             })
             .collect();
 
+        let smart_component_poles = from
+            .part_selection
+            .into_iter()
+            .map(|(axis, pole)| pole.try_into().map(|pole| (axis, pole)))
+            .collect::<Result<_, _>>()?;
+
+        let mut hint_components = Vec::new();
+        for hint in from.hints.iter() {
+            hint_components.extend(hint.to_hint_component()?);
+        }
+
         Ok(Layer {
             layer_id: from.layer_id,
             associated_master_id: from.associated_master_id,
             width: from.width.unwrap_or(DEFAULT_LAYER_WIDTH.into()),
+            vert_width: from.vert_width,
+            vert_origin: from.vert_origin,
             shapes,
             anchors,
             attributes: from.attributes,
+            smart_component_poles,
+            hint_components,
         })
     }
 }
@@ -2222,6 +2521,7 @@ impl RawGlyph {
             unicode: codepoints,
             category,
             sub_category,
+            smart_component_axes: self.parts_settings,
         })
     }
 }
@@ -2707,15 +3007,11 @@ fn variable_instance_for<'a>(instances: &'a [Instance], name: &str) -> Option<&'
 impl Font {
     pub fn load(glyphs_file: &path::Path) -> Result<Font, Error> {
         let mut font = Self::load_raw(glyphs_file)?;
-
-        // propagate anchors by default unless explicitly set to false
-        if font.custom_parameters.propagate_anchors.unwrap_or(true) {
-            font.propagate_all_anchors();
-        }
+        font.decompose_smart_components()?;
         Ok(font)
     }
 
-    // load without propagating anchors
+    // load without decomposing smart components
     pub(crate) fn load_raw(glyphs_file: impl AsRef<path::Path>) -> Result<Font, Error> {
         RawFont::load(glyphs_file.as_ref()).and_then(Font::try_from)
     }
@@ -2770,8 +3066,8 @@ impl From<Affine> for AffineForEqAndHash {
 mod tests {
     use crate::{
         font::{
-            default_master_idx, normalized_rotation, AxisUserToDesignMap, Color, Gradient,
-            RawFeature, RawFont, RawFontMaster, UserToDesignMapping,
+            default_master_idx, normalized_rotation, AxisRule, AxisUserToDesignMap, Color,
+            Gradient, RawFeature, RawFont, RawFontMaster, UserToDesignMapping,
         },
         glyphdata::{Category, GlyphData},
         plist::FromPlist,
@@ -3480,6 +3776,55 @@ mod tests {
         assert_eq!(v3.names, v2.names);
     }
 
+    #[test]
+    fn v2_to_v3_bracket_layers() {
+        let v2 = Font::load(&glyphs2_dir().join("WghtVar_Bracket.glyphs")).unwrap();
+        let v3 = Font::load(&glyphs3_dir().join("WghtVar_Bracket.glyphs")).unwrap();
+        let axis_rules = |font: &Font| {
+            font.glyphs
+                .get("A")
+                .unwrap()
+                .layers
+                .iter()
+                .filter(|l| l.is_alternate())
+                .map(|l| (l.layer_id.clone(), l.attributes.axis_rules.clone()))
+                .collect::<Vec<_>>()
+        };
+        let expected_rules = vec![AxisRule {
+            min: Some(OrderedFloat(600.0)),
+            max: None,
+        }];
+        assert_eq!(
+            vec![
+                ("A-m01-bracket".to_string(), expected_rules.clone()),
+                ("A-m02-bracket".to_string(), expected_rules),
+            ],
+            axis_rules(&v3)
+        );
+        assert_eq!(axis_rules(&v3), axis_rules(&v2));
+    }
+
+    #[test]
+    fn v2_bracket_layer_names() {
+        let min = |v: f64| AxisRule {
+            min: Some(OrderedFloat(v)),
+            max: None,
+        };
+        let max = |v: f64| AxisRule {
+            min: None,
+            max: Some(OrderedFloat(v)),
+        };
+        assert_eq!(Some(min(300.0)), AxisRule::from_v2_layer_name("Bold [300]"));
+        assert_eq!(Some(max(300.0)), AxisRule::from_v2_layer_name("Bold ]300]"));
+        assert_eq!(
+            Some(min(300.0)),
+            AxisRule::from_v2_layer_name("[ 300 ] alt")
+        );
+        assert_eq!(None, AxisRule::from_v2_layer_name("Bold"));
+        assert_eq!(None, AxisRule::from_v2_layer_name("Bold {300}"));
+        assert_eq!(None, AxisRule::from_v2_layer_name("Bold [abc]"));
+    }
+
     #[test]
     fn v2_style_names_in_a_v3_file() {
         let v3_mixed_with_v2 =
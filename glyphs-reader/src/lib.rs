@@ -6,10 +6,11 @@ pub mod glyphdata;
 mod glyphdata_bundled;
 mod glyphslib_enums;
 mod plist;
-mod propagate_anchors;
+mod smart_components;
 
 pub use font::{
-    Axis, Component, CustomParameters, FeatureSnippet, Font, FontMaster, Glyph, InstanceType,
-    Layer, Node, NodeType, Path, Shape,
+    Axis, AxisRule, Component, CornerAlignment, CustomParameters, FeatureSnippet, Font, FontMaster,
+    Glyph, HintComponent, HintComponentKind, InstanceType, Layer, Node, NodeType, Path, Shape,
+    SmartComponentAxis, SmartComponentPole,
 };
 pub use plist::Plist;
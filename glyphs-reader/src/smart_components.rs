@@ -0,0 +1,334 @@
+//! Decomposing smart components
+//!
+//! A smart glyph has axes of its own, its `partsSettings`, and layers at either end
+//! of them, picked by their `partSelection`. A component of a smart glyph sets a
+//! location along those axes, its `piece`, and contributes the smart glyph's layers
+//! interpolated, or extrapolated, there. Port of glyphsLib's
+//! [smart_components](https://github.com/googlefonts/glyphsLib/blob/main/Lib/glyphsLib/builder/smart_components.py).
+
+use std::collections::BTreeMap;
+
+use kurbo::{Affine, Point};
+use log::{debug, warn};
+use ordered_float::OrderedFloat;
+use smol_str::SmolStr;
+
+use crate::{error::Error, font::SmartComponentPole, Component, Font, Glyph, Layer, Path, Shape};
+
+impl Font {
+    /// Replace components of smart glyphs with the outlines they stand for.
+    ///
+    /// Each component is interpolated from the smart glyph's layers for the same master
+    /// as the layer using it. The smart glyph's extra layers are dropped once done.
+    pub fn decompose_smart_components(&mut self) -> Result<(), Error> {
+        let mut decomposed = Vec::new();
+        for glyph in self.glyphs.values() {
+            for (i, layer) in glyph.layers.iter().enumerate() {
+                if !layer
+                    .components()
+                    .any(|component| is_smart(&self.glyphs, component))
+                {
+                    continue;
+                }
+                debug!(
+                    "Decompose smart components of '{}' layer '{}'",
+                    glyph.name, layer.layer_id
+                );
+                let master_id = layer
+                    .associated_master_id
+                    .as_deref()
+                    .unwrap_or(&layer.layer_id);
+                let mut shapes = Vec::with_capacity(layer.shapes.len());
+                for shape in layer.shapes.iter() {
+                    match shape {
+                        Shape::Component(component) => {
+                            push_component_shapes(&self.glyphs, master_id, component, &mut shapes)?
+                        }
+                        Shape::Path(..) => shapes.push(shape.clone()),
+                    }
+                }
+                decomposed.push((glyph.name.clone(), i, shapes));
+            }
+        }
+        for (glyph_name, i, shapes) in decomposed {
+            self.glyphs.get_mut(&glyph_name).unwrap().layers[i].shapes = shapes;
+        }
+
+        for glyph in self.glyphs.values_mut() {
+            glyph
+                .layers
+                .retain(|layer| layer.is_master() || layer.smart_component_poles.is_empty());
+        }
+        Ok(())
+    }
+}
+
+fn is_smart(glyphs: &BTreeMap<SmolStr, Glyph>, component: &Component) -> bool {
+    glyphs
+        .get(&component.name)
+        .is_some_and(|glyph| !glyph.smart_component_axes.is_empty())
+}
+
+/// Add the shapes component contributes to shapes, decomposing it if it is smart.
+///
+/// Components within a smart glyph are kept unless they are themselves smart.
+fn push_component_shapes(
+    glyphs: &BTreeMap<SmolStr, Glyph>,
+    master_id: &str,
+    component: &Component,
+    shapes: &mut Vec<Shape>,
+) -> Result<(), Error> {
+    let Some(smart_glyph) = glyphs
+        .get(&component.name)
+        .filter(|glyph| !glyph.smart_component_axes.is_empty())
+    else {
+        shapes.push(Shape::Component(component.clone()));
+        return Ok(());
+    };
+    let transform = component.transform;
+    for shape in interpolate(smart_glyph, master_id, &component.smart_component_location)? {
+        match shape {
+            Shape::Path(mut path) => {
+                for node in path.nodes.iter_mut() {
+                    node.pt = transform * node.pt;
+                }
+                shapes.push(Shape::Path(path));
+            }
+            Shape::Component(mut nested) => {
+                nested.transform = transform * nested.transform;
+                push_component_shapes(glyphs, master_id, &nested, shapes)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The shapes of a smart glyph at location, in its axes' own units.
+///
+/// Axes the location doesn't mention are at their bottom value. Locations beyond
+/// the ends of an axis extrapolate.
+fn interpolate(
+    glyph: &Glyph,
+    master_id: &str,
+    location: &BTreeMap<SmolStr, OrderedFloat<f64>>,
+) -> Result<Vec<Shape>, Error> {
+    let bad_smart_glyph = |reason: String| Error::BadSmartGlyph(glyph.name.clone(), reason);
+    let axes = &glyph.smart_component_axes;
+    if let Some(axis) = axes.iter().find(|a| a.bottom_value == a.top_value) {
+        return Err(bad_smart_glyph(format!(
+            "axis '{}' has the same bottom and top value",
+            axis.name
+        )));
+    }
+
+    // The layers for the master, each identified by the axes it is at the top of
+    let mut poles: Vec<(Vec<bool>, &Layer)> = Vec::new();
+    for layer in glyph.layers.iter().filter(|layer| {
+        layer.layer_id == master_id
+            || (layer.associated_master_id.as_deref() == Some(master_id)
+                && !layer.smart_component_poles.is_empty())
+    }) {
+        let at_top: Vec<_> = axes
+            .iter()
+            .map(|axis| {
+                layer.smart_component_poles.get(&axis.name) == Some(&SmartComponentPole::Top)
+            })
+            .collect();
+        if poles.iter().any(|(existing, _)| *existing == at_top) {
+            warn!(
+                "'{}' has more than one layer at {at_top:?} for master '{master_id}', using the first",
+                glyph.name
+            );
+            continue;
+        }
+        poles.push((at_top, layer));
+    }
+    // Each layer's delta depends on those at the top of fewer axes
+    poles.sort_by_key(|(at_top, _)| at_top.iter().filter(|top| **top).count());
+    let Some((_, default)) = poles
+        .first()
+        .filter(|(at_top, _)| at_top.iter().all(|top| !top))
+    else {
+        return Err(bad_smart_glyph(format!(
+            "no layer for master '{master_id}' is at the bottom of every axis"
+        )));
+    };
+    if let Some((_, layer)) = poles
+        .iter()
+        .find(|(_, layer)| !compatible(&default.shapes, &layer.shapes))
+    {
+        return Err(bad_smart_glyph(format!(
+            "layer '{}' is incompatible with the master layer",
+            layer.layer_id
+        )));
+    }
+
+    // A location of 0 is the bottom of an axis and 1 the top
+    let normalized: Vec<_> = axes
+        .iter()
+        .map(|axis| {
+            let value = location.get(&axis.name).unwrap_or(&axis.bottom_value);
+            (value.0 - axis.bottom_value.0) / (axis.top_value.0 - axis.bottom_value.0)
+        })
+        .collect();
+
+    let mut deltas: Vec<Vec<f64>> = Vec::with_capacity(poles.len());
+    for (i, (at_top, layer)) in poles.iter().enumerate() {
+        let mut delta = shape_values(&layer.shapes);
+        for ((other, _), other_delta) in poles[..i].iter().zip(deltas.iter()) {
+            if other.iter().zip(at_top).all(|(o, t)| !o || *t) {
+                delta.iter_mut().zip(other_delta).for_each(|(d, o)| *d -= o);
+            }
+        }
+        deltas.push(delta);
+    }
+    let mut values = vec![0.0; deltas[0].len()];
+    for ((at_top, _), delta) in poles.iter().zip(deltas.iter()) {
+        let scalar: f64 = normalized
+            .iter()
+            .zip(at_top)
+            .filter(|(_, top)| **top)
+            .map(|(value, _)| value)
+            .product();
+        values
+            .iter_mut()
+            .zip(delta)
+            .for_each(|(v, d)| *v += scalar * d);
+    }
+
+    Ok(shapes_from_values(&default.shapes, &values))
+}
+
+/// True if the shapes have the same structure, so their values correspond.
+fn compatible(shapes: &[Shape], other: &[Shape]) -> bool {
+    shapes.len() == other.len()
+        && shapes.iter().zip(other).all(|pair| match pair {
+            (Shape::Path(p1), Shape::Path(p2)) => {
+                p1.closed == p2.closed
+                    && p1.nodes.len() == p2.nodes.len()
+                    && p1
+                        .nodes
+                        .iter()
+                        .zip(p2.nodes.iter())
+                        .all(|(n1, n2)| n1.node_type == n2.node_type)
+            }
+            (Shape::Component(c1), Shape::Component(c2)) => c1.name == c2.name,
+            _ => false,
+        })
+}
+
+/// The numbers that interpolate, in a stable order
+fn shape_values(shapes: &[Shape]) -> Vec<f64> {
+    let mut values = Vec::new();
+    for shape in shapes {
+        match shape {
+            Shape::Path(path) => {
+                values.extend(path.nodes.iter().flat_map(|node| [node.pt.x, node.pt.y]))
+            }
+            Shape::Component(component) => values.extend(component.transform.as_coeffs()),
+        }
+    }
+    values
+}
+
+/// The inverse of [shape_values], using template for structure.
+fn shapes_from_values(template: &[Shape], values: &[f64]) -> Vec<Shape> {
+    let mut values = values.iter().copied();
+    let mut next = || values.next().unwrap();
+    template
+        .iter()
+        .map(|shape| match shape {
+            Shape::Path(path) => {
+                let mut path: Path = path.clone();
+                for node in path.nodes.iter_mut() {
+                    node.pt = Point::new(next(), next());
+                }
+                Shape::Path(path)
+            }
+            Shape::Component(component) => Shape::Component(Component {
+                transform: Affine::new([next(), next(), next(), next(), next(), next()]),
+                ..component.clone()
+            }),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    fn rect_corners(font: &Font, glyph_name: &str) -> Vec<(f64, f64)> {
+        let glyph = font.glyphs.get(glyph_name).unwrap();
+        assert_eq!(1, glyph.layers.len());
+        let [Shape::Path(path)] = glyph.layers[0].shapes.as_slice() else {
+            panic!("Expected a single path, got {:?}", glyph.layers[0].shapes);
+        };
+        path.nodes.iter().map(|n| (n.pt.x, n.pt.y)).collect()
+    }
+
+    #[test]
+    fn decompose_smart_components() {
+        let font = Font::load(Path::new(
+            "../resources/testdata/glyphs3/SmartComponent.glyphs",
+        ))
+        .unwrap();
+
+        // halfway along width, at the top of height, and moved over
+        assert_eq!(
+            vec![(10.0, 0.0), (110.0, 0.0), (110.0, 300.0), (10.0, 300.0)],
+            rect_corners(&font, "stem")
+        );
+        // beyond the top of width, height is at its bottom as it isn't set
+        assert_eq!(
+            vec![(0.0, 0.0), (200.0, 0.0), (200.0, 100.0), (0.0, 100.0)],
+            rect_corners(&font, "stem.wide")
+        );
+        // the smart glyph keeps only its master layer
+        assert_eq!(
+            vec![(0.0, 0.0), (50.0, 0.0), (50.0, 100.0), (0.0, 100.0)],
+            rect_corners(&font, "_part.stem")
+        );
+    }
+
+    #[test]
+    fn parse_smart_glyph() {
+        let font = Font::load_raw("../resources/testdata/glyphs3/SmartComponent.glyphs").unwrap();
+        let smart_glyph = font.glyphs.get("_part.stem").unwrap();
+        assert_eq!(
+            vec![("Width", 0.0, 100.0), ("Height", 0.0, 100.0)],
+            smart_glyph
+                .smart_component_axes
+                .iter()
+                .map(|a| (a.name.as_str(), a.bottom_value.0, a.top_value.0))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![
+                (SmartComponentPole::Bottom, SmartComponentPole::Bottom),
+                (SmartComponentPole::Bottom, SmartComponentPole::Top),
+                (SmartComponentPole::Top, SmartComponentPole::Bottom),
+            ],
+            smart_glyph
+                .layers
+                .iter()
+                .map(|l| (
+                    l.smart_component_poles["Height"],
+                    l.smart_component_poles["Width"]
+                ))
+                .collect::<Vec<_>>()
+        );
+        let Shape::Component(component) = &font.glyphs.get("stem").unwrap().layers[0].shapes[0]
+        else {
+            panic!("Expected a component");
+        };
+        assert_eq!(
+            BTreeMap::from([
+                (SmolStr::new("Height"), OrderedFloat(100.0)),
+                (SmolStr::new("Width"), OrderedFloat(50.0)),
+            ]),
+            component.smart_component_location
+        );
+    }
+}
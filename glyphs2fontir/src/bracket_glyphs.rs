@@ -0,0 +1,292 @@
+//! Alternate (aka 'bracket') layers become glyphs of their own.
+//!
+//! Each distinct region used by a glyph's alternate layers becomes a '.BRACKET.' glyph
+//! which feature variations substitute in across that region. Port of glyphsLib's
+//! [bracket_layers](https://github.com/googlefonts/glyphsLib/blob/main/Lib/glyphsLib/builder/bracket_layers.py).
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use glyphs_reader::{AxisRule, Font, Glyph, Layer, Shape};
+use log::{debug, warn};
+use smol_str::{format_smolstr, SmolStr};
+
+/// The range of every axis, in axis order, where some alternates apply
+pub(crate) type BracketBox = Vec<AxisRule>;
+
+/// For each region, glyph name => the name of the alternate glyph to use there
+pub(crate) type BracketSubstitutions = BTreeMap<BracketBox, BTreeMap<SmolStr, SmolStr>>;
+
+/// Move alternate layers out into new glyphs, returning the substitutions that bring them back.
+///
+/// Composites whose components have alternates get alternates of their own so
+/// the substitution is visible through them.
+pub(crate) fn synthesize_bracket_glyphs(font: &mut Font) -> BracketSubstitutions {
+    let num_axes = font.axes.len();
+
+    // glyph name => region => alternate layers
+    let mut alternates: BTreeMap<SmolStr, BTreeMap<BracketBox, Vec<Layer>>> = BTreeMap::new();
+    for glyph in font.glyphs.values_mut() {
+        if !glyph.layers.iter().any(Layer::is_alternate) {
+            continue;
+        }
+        let (alternate_layers, layers): (Vec<_>, Vec<_>) = std::mem::take(&mut glyph.layers)
+            .into_iter()
+            .partition(Layer::is_alternate);
+        glyph.layers = layers;
+        for layer in alternate_layers {
+            let mut bracket_box = layer.attributes.axis_rules.clone();
+            bracket_box.resize(num_axes, AxisRule::default());
+            alternates
+                .entry(glyph.name.clone())
+                .or_default()
+                .entry(bracket_box)
+                .or_default()
+                .push(layer);
+        }
+    }
+    if alternates.is_empty() {
+        return Default::default();
+    }
+
+    add_composites_of_alternates(font, &mut alternates);
+
+    // Name the alternates before building them so components can refer to each other
+    let mut substitutions = BracketSubstitutions::new();
+    for (glyph_name, boxes) in alternates.iter() {
+        for (i, bracket_box) in boxes.keys().enumerate() {
+            substitutions
+                .entry(bracket_box.clone())
+                .or_default()
+                .insert(
+                    glyph_name.clone(),
+                    format_smolstr!("{glyph_name}.BRACKET.varAlt{:02}", i + 1),
+                );
+        }
+    }
+
+    for (glyph_name, boxes) in alternates {
+        let glyph = font.glyphs.get(&glyph_name).unwrap();
+        let mut new_glyphs = Vec::with_capacity(boxes.len());
+        for (bracket_box, layers) in boxes {
+            let renames = &substitutions[&bracket_box];
+            let alternate_name = renames[&glyph_name].clone();
+            let layers = alternate_glyph_layers(font, glyph, &alternate_name, layers, renames);
+            new_glyphs.push(Glyph {
+                name: alternate_name,
+                export: glyph.export,
+                layers,
+                unicode: Default::default(),
+                left_kern: glyph.left_kern.clone(),
+                right_kern: glyph.right_kern.clone(),
+                category: glyph.category,
+                sub_category: glyph.sub_category,
+                smart_component_axes: glyph.smart_component_axes.clone(),
+            });
+        }
+        for new_glyph in new_glyphs {
+            debug!("Alternate '{}' for '{glyph_name}'", new_glyph.name);
+            font.glyph_order.push(new_glyph.name.clone());
+            font.glyphs.insert(new_glyph.name.clone(), new_glyph);
+        }
+    }
+
+    substitutions
+}
+
+/// A composite that uses a glyph with alternates must itself have alternates in the same
+/// regions, otherwise the substitution would be invisible through it.
+///
+/// The composite alternates are copies of its master layers, components are swapped
+/// for their alternates when the glyph is built.
+fn add_composites_of_alternates(
+    font: &Font,
+    alternates: &mut BTreeMap<SmolStr, BTreeMap<BracketBox, Vec<Layer>>>,
+) {
+    // Repeat until nothing changes to reach composites of composites
+    loop {
+        let mut added = false;
+        for glyph in font.glyphs.values() {
+            let component_boxes: BTreeSet<_> = glyph
+                .layers
+                .iter()
+                .filter(|layer| layer.is_master())
+                .flat_map(|layer| layer.shapes.iter())
+                .filter_map(|shape| match shape {
+                    Shape::Component(component) => alternates.get(&component.name),
+                    Shape::Path(..) => None,
+                })
+                .flat_map(|boxes| boxes.keys())
+                .cloned()
+                .collect();
+            for bracket_box in component_boxes {
+                let boxes = alternates.entry(glyph.name.clone()).or_default();
+                if boxes.contains_key(&bracket_box) {
+                    continue;
+                }
+                debug!(
+                    "'{}' uses components with alternates, it needs alternates too",
+                    glyph.name
+                );
+                boxes.insert(bracket_box, Vec::new());
+                added = true;
+            }
+        }
+        if !added {
+            break;
+        }
+    }
+}
+
+/// The layers of an alternate glyph, one for each master plus any intermediates.
+///
+/// Masters without an alternate layer use the default master layer of the glyph.
+fn alternate_glyph_layers(
+    font: &Font,
+    glyph: &Glyph,
+    alternate_name: &SmolStr,
+    alternate_layers: Vec<Layer>,
+    renames: &BTreeMap<SmolStr, SmolStr>,
+) -> Vec<Layer> {
+    let mut layers = Vec::with_capacity(font.masters.len());
+    for master in font.masters.iter() {
+        let alternate = alternate_layers.iter().find(|layer| {
+            layer.associated_master_id.as_ref() == Some(&master.id)
+                && layer.attributes.coordinates.is_empty()
+        });
+        let layer = match alternate {
+            Some(layer) => layer,
+            None => {
+                let Some(layer) = glyph.layers.iter().find(|l| l.layer_id == master.id) else {
+                    // The glyph itself is missing this master, it will fail as a glyph
+                    continue;
+                };
+                // Composites we added alternates for are expected to copy master layers
+                if !alternate_layers.is_empty() {
+                    warn!(
+                        "'{alternate_name}' has no alternate layer for master '{}', using the '{}' master layer",
+                        master.name, glyph.name
+                    );
+                }
+                layer
+            }
+        };
+        let mut layer = layer.clone();
+        layer.layer_id = master.id.clone();
+        layer.associated_master_id = None;
+        layers.push(layer);
+    }
+
+    // Alternate intermediates stay intermediates
+    layers.extend(
+        alternate_layers
+            .into_iter()
+            .filter(|layer| !layer.attributes.coordinates.is_empty()),
+    );
+
+    for layer in layers.iter_mut() {
+        layer.attributes.axis_rules.clear();
+        for shape in layer.shapes.iter_mut() {
+            if let Shape::Component(component) = shape {
+                if let Some(alternate) = renames.get(&component.name) {
+                    component.name = alternate.clone();
+                }
+            }
+        }
+    }
+    layers
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use glyphs_reader::{Font, Shape};
+    use ordered_float::OrderedFloat;
+
+    use super::*;
+
+    fn testdata_dir() -> &'static Path {
+        Path::new("../resources/testdata")
+    }
+
+    fn load(name: &str) -> (Font, BracketSubstitutions) {
+        let mut font = Font::load(&testdata_dir().join(name)).unwrap();
+        let substitutions = synthesize_bracket_glyphs(&mut font);
+        (font, substitutions)
+    }
+
+    fn expected_box() -> BracketBox {
+        vec![AxisRule {
+            min: Some(OrderedFloat(600.0)),
+            max: None,
+        }]
+    }
+
+    fn assert_bracket_glyphs(name: &str) {
+        let (font, substitutions) = load(name);
+        assert_eq!(
+            BracketSubstitutions::from([(
+                expected_box(),
+                BTreeMap::from([
+                    ("A".into(), "A.BRACKET.varAlt01".into()),
+                    ("Aacute".into(), "Aacute.BRACKET.varAlt01".into()),
+                ])
+            )]),
+            substitutions
+        );
+
+        // The originals lose their alternate layers
+        let a = font.glyphs.get("A").unwrap();
+        assert!(a.layers.iter().all(|l| !l.is_alternate()), "{a:?}");
+
+        // The alternate has a layer per master
+        let alternate = font.glyphs.get("A.BRACKET.varAlt01").unwrap();
+        assert_eq!(
+            font.masters
+                .iter()
+                .map(|m| m.id.clone())
+                .collect::<Vec<_>>(),
+            alternate
+                .layers
+                .iter()
+                .map(|l| l.layer_id.clone())
+                .collect::<Vec<_>>()
+        );
+        assert!(alternate.unicode.is_empty());
+        assert!(font
+            .glyph_order
+            .contains(&SmolStr::new("A.BRACKET.varAlt01")));
+
+        // The composite points to the alternate
+        let aacute = font.glyphs.get("Aacute.BRACKET.varAlt01").unwrap();
+        let components: BTreeSet<_> = aacute
+            .layers
+            .iter()
+            .flat_map(|l| l.shapes.iter())
+            .filter_map(|s| match s {
+                Shape::Component(c) => Some(c.name.as_str()),
+                Shape::Path(..) => None,
+            })
+            .collect();
+        assert_eq!(
+            BTreeSet::from(["A.BRACKET.varAlt01", "acutecomb"]),
+            components
+        );
+    }
+
+    #[test]
+    fn bracket_glyphs_v2() {
+        assert_bracket_glyphs("glyphs2/WghtVar_Bracket.glyphs");
+    }
+
+    #[test]
+    fn bracket_glyphs_v3() {
+        assert_bracket_glyphs("glyphs3/WghtVar_Bracket.glyphs");
+    }
+
+    #[test]
+    fn no_brackets_no_substitutions() {
+        let (_, substitutions) = load("glyphs3/WghtVar.glyphs");
+        assert!(substitutions.is_empty());
+    }
+}
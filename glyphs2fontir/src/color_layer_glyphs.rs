@@ -0,0 +1,125 @@
+//! The paths of color layers become glyphs of their own.
+//!
+//! A COLR PaintGlyph fills the whole outline of a glyph with one paint, so a color
+//! layer with many shapes needs a glyph per path to give each its own paint. Port of
+//! glyphsLib's [color_layers](https://github.com/googlefonts/glyphsLib/blob/main/Lib/glyphsLib/builder/color_layers.py).
+
+use glyphs_reader::{Font, Glyph, Layer, Shape};
+use log::{debug, warn};
+use smol_str::{format_smolstr, SmolStr};
+
+/// The name of the glyph made from the path at shape_idx of a color layer of glyph_name
+pub(crate) fn color_layer_glyph_name(glyph_name: &str, shape_idx: usize) -> SmolStr {
+    format_smolstr!("{glyph_name}.color{shape_idx}")
+}
+
+/// The master layers of a glyph that are color layers
+pub(crate) fn color_layers(glyph: &Glyph) -> impl Iterator<Item = &Layer> {
+    glyph
+        .layers
+        .iter()
+        .filter(|layer| layer.attributes.color && layer.is_master())
+}
+
+/// Add a glyph for every path of color layers with more than one shape.
+///
+/// A color layer with a single path is the outline of its glyph so it needs
+/// nothing extra.
+pub(crate) fn synthesize_color_layer_glyphs(font: &mut Font) {
+    let mut new_glyphs = Vec::new();
+    for glyph_name in font.glyph_order.iter() {
+        let Some(glyph) = font.glyphs.get(glyph_name) else {
+            continue;
+        };
+        // Shapes are matched up by index between masters, as they are to build the paint
+        let Some(num_shapes) = color_layers(glyph).map(|layer| layer.shapes.len()).max() else {
+            continue;
+        };
+        if num_shapes < 2 {
+            continue;
+        }
+        for shape_idx in 0..num_shapes {
+            let layers: Vec<_> = color_layers(glyph)
+                .filter_map(|layer| match layer.shapes.get(shape_idx) {
+                    Some(path @ Shape::Path(_)) => {
+                        // A plain outline, not a color glyph in turn
+                        let mut layer = Layer {
+                            shapes: vec![path.clone()],
+                            anchors: Vec::new(),
+                            hint_components: Vec::new(),
+                            ..layer.clone()
+                        };
+                        layer.attributes.color = false;
+                        Some(layer)
+                    }
+                    _ => None,
+                })
+                .collect();
+            if layers.is_empty() {
+                continue;
+            }
+            let name = color_layer_glyph_name(glyph_name, shape_idx);
+            if font.glyphs.contains_key(&name) {
+                warn!("'{name}' already exists, it can't hold a path of the '{glyph_name}' color layer");
+                continue;
+            }
+            new_glyphs.push(Glyph {
+                name,
+                export: true,
+                layers,
+                unicode: Default::default(),
+                left_kern: None,
+                right_kern: None,
+                category: None,
+                sub_category: None,
+                smart_component_axes: Vec::new(),
+            });
+        }
+    }
+
+    for new_glyph in new_glyphs {
+        debug!("Color layer glyph '{}'", new_glyph.name);
+        font.glyph_order.push(new_glyph.name.clone());
+        font.glyphs.insert(new_glyph.name.clone(), new_glyph);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    #[test]
+    fn a_glyph_per_path() {
+        let mut font =
+            Font::load(&Path::new("../resources/testdata/glyphs3").join("COLRv1-layers.glyphs"))
+                .unwrap();
+        synthesize_color_layer_glyphs(&mut font);
+
+        // The component of B stays a component
+        assert_eq!(
+            ["A", "B", "A.color0", "A.color1", "B.color0"]
+                .map(SmolStr::new)
+                .as_slice(),
+            font.glyph_order.as_slice()
+        );
+        let color1 = font.glyphs.get("A.color1").unwrap();
+        assert!(color1.unicode.is_empty());
+        assert!(color_layers(color1).next().is_none());
+        assert_eq!(
+            vec![font.glyphs["A"].layers[0].shapes[1].clone()],
+            color1.layers[0].shapes
+        );
+    }
+
+    #[test]
+    fn single_path_needs_no_glyph() {
+        let mut font =
+            Font::load(&Path::new("../resources/testdata/glyphs3").join("COLRv1-simple.glyphs"))
+                .unwrap();
+        let glyph_order = font.glyph_order.clone();
+        synthesize_color_layer_glyphs(&mut font);
+        assert_eq!(glyph_order, font.glyph_order);
+    }
+}
@@ -0,0 +1,475 @@
+//! Inserting corner and cap components into the paths of a layer
+//!
+//! Glyphs attaches these components to a node of a path, storing them with the hints
+//! of the layer. The glyph of a corner component has an open path drawn around a
+//! corner at the origin, the stroke coming into the corner running down the y axis
+//! and the stroke leaving it running along the x axis. Its ends are joined to the two
+//! strokes meeting at the node, replacing the corner. The glyph of a cap component has
+//! an open path that replaces the segment following the node, typically the flat end
+//! of a stroke; it's stretched so its ends meet those of the segment.
+//!
+//! Port of the corner and cap handling of glyphsLib's `corner_components`.
+
+use std::collections::BTreeMap;
+
+use fontdrasil::types::GlyphName;
+use fontir::error::BadGlyph;
+use glyphs_reader::{
+    CornerAlignment, Font, HintComponent, HintComponentKind, Layer, Node, NodeType, Path, Shape,
+};
+use kurbo::{Affine, BezPath, Line, ParamCurve, ParamCurveNearest, PathEl, PathSeg, Point, Vec2};
+use log::{debug, warn};
+
+use crate::toir::to_ir_path;
+
+/// Distances below this are treated as zero, as when deciding if two points coincide
+const EPSILON: f64 = 1e-6;
+
+/// Insert the corner and cap components of layer into contours, the layer's paths in order
+pub(crate) fn insert_hint_components(
+    font: &Font,
+    glyph_name: &GlyphName,
+    layer: &Layer,
+    contours: &mut [BezPath],
+) -> Result<(), BadGlyph> {
+    let paths: Vec<_> = layer
+        .shapes
+        .iter()
+        .filter_map(|shape| match shape {
+            Shape::Path(path) => Some(path),
+            Shape::Component(..) => None,
+        })
+        .collect();
+
+    let mut by_path: BTreeMap<usize, Vec<&HintComponent>> = BTreeMap::new();
+    for hint in layer.hint_components.iter() {
+        if hint.kind == HintComponentKind::Segment {
+            warn!(
+                "'{glyph_name}' uses segment component '{}', segment components are not yet supported",
+                hint.name
+            );
+            continue;
+        }
+        if hint.path_index >= paths.len() || hint.node_index >= paths[hint.path_index].nodes.len() {
+            warn!(
+                "'{glyph_name}' layer '{}' attaches '{}' to missing node {} of path {}",
+                layer.layer_id, hint.name, hint.node_index, hint.path_index
+            );
+            continue;
+        }
+        by_path.entry(hint.path_index).or_default().push(hint);
+    }
+
+    for (path_index, hints) in by_path {
+        let path = paths[path_index];
+        let Some(mut contour) = Contour::new(path, &contours[path_index]) else {
+            warn!(
+                "'{glyph_name}' layer '{}' path {path_index} can't take corner or cap components",
+                layer.layer_id
+            );
+            continue;
+        };
+        // Caps replace whole segments so go first, corners then trim whatever is there
+        let (caps, corners): (Vec<_>, Vec<_>) = hints
+            .into_iter()
+            .partition(|hint| hint.kind == HintComponentKind::Cap);
+        for hint in caps.into_iter().chain(corners) {
+            let Some(component_path) = component_path(font, glyph_name, layer, hint)? else {
+                continue;
+            };
+            let ordinal = contour.ordinals[hint.node_index];
+            let inserted = match (hint.kind, ordinal) {
+                (HintComponentKind::Cap, Some(ordinal)) => {
+                    contour.insert_cap(ordinal, &component_path, hint)
+                }
+                (HintComponentKind::Corner, Some(ordinal)) => {
+                    contour.insert_corner(ordinal, &component_path, hint)
+                }
+                _ => false,
+            };
+            if inserted {
+                debug!(
+                    "'{glyph_name}' layer '{}' inserted '{}' at node {} of path {path_index}",
+                    layer.layer_id, hint.name, hint.node_index
+                );
+            } else {
+                warn!(
+                    "'{glyph_name}' layer '{}' can't fit '{}' at node {} of path {path_index}",
+                    layer.layer_id, hint.name, hint.node_index
+                );
+            }
+        }
+        contours[path_index] = contour.into_bez_path();
+    }
+    Ok(())
+}
+
+/// The open path of the component's glyph, from the layer matching the one using it
+fn component_path(
+    font: &Font,
+    glyph_name: &GlyphName,
+    layer: &Layer,
+    hint: &HintComponent,
+) -> Result<Option<BezPath>, BadGlyph> {
+    let Some(glyph) = font.glyphs.get(&hint.name) else {
+        warn!("'{glyph_name}' uses '{}', which doesn't exist", hint.name);
+        return Ok(None);
+    };
+    let master_id = layer
+        .associated_master_id
+        .as_deref()
+        .unwrap_or(&layer.layer_id);
+    // An intermediate layer prefers a layer of the component at the same location
+    let component_layer = glyph
+        .layers
+        .iter()
+        .find(|l| {
+            !layer.attributes.coordinates.is_empty()
+                && l.associated_master_id.as_deref() == Some(master_id)
+                && l.attributes.coordinates == layer.attributes.coordinates
+        })
+        .or_else(|| glyph.layers.iter().find(|l| l.layer_id == master_id));
+    let Some(path) = component_layer.and_then(|l| {
+        l.shapes.iter().find_map(|shape| match shape {
+            Shape::Path(path) if !path.closed => Some(path),
+            _ => None,
+        })
+    }) else {
+        warn!(
+            "'{glyph_name}' uses '{}', which has no open path for master '{master_id}'",
+            hint.name
+        );
+        return Ok(None);
+    };
+    to_ir_path(path)
+        .map(Some)
+        .map_err(|e| BadGlyph::new(hint.name.as_str(), e))
+}
+
+/// The segments of a contour, grouped by the node of the source path they end at
+///
+/// Keeping the groups lets us find the segments around a node after earlier insertions
+/// have changed the number of segments.
+struct Contour {
+    closed: bool,
+    /// For each node of the source path, its index among the on-curve nodes
+    ordinals: Vec<Option<usize>>,
+    /// The segments ending at each on-curve node, empty for the start of an open path
+    ///
+    /// A component inserted at a node is added to the segments ending there.
+    segments: Vec<Vec<PathSeg>>,
+}
+
+impl Contour {
+    /// Group the segments of contour, which was converted from path
+    fn new(path: &Path, contour: &BezPath) -> Option<Contour> {
+        // We rely on the start of a closed path being its last node, as Glyphs writes it
+        if path.nodes.last().is_none_or(|node| !node.is_on_curve()) {
+            return None;
+        }
+        let mut ordinals = Vec::with_capacity(path.nodes.len());
+        let mut segment_counts = Vec::new();
+        let mut off_curves = 0;
+        for (i, node) in path.nodes.iter().enumerate() {
+            if !node.is_on_curve() {
+                ordinals.push(None);
+                off_curves += 1;
+                continue;
+            }
+            ordinals.push(Some(segment_counts.len()));
+            segment_counts.push(match node {
+                _ if i == 0 && !path.closed => 0,
+                // several off-curves imply several quadratic segments
+                Node {
+                    node_type: NodeType::QCurve | NodeType::QCurveSmooth,
+                    ..
+                } if off_curves > 1 => off_curves,
+                _ => 1,
+            });
+            off_curves = 0;
+        }
+
+        let mut all_segments = contour.segments();
+        let segments: Vec<Vec<_>> = segment_counts
+            .iter()
+            .map(|count| all_segments.by_ref().take(*count).collect())
+            .collect();
+        if all_segments.next().is_some()
+            || segments
+                .iter()
+                .zip(segment_counts.iter())
+                .any(|(segments, count)| segments.len() != *count)
+        {
+            return None;
+        }
+        Some(Contour {
+            closed: path.closed,
+            ordinals,
+            segments,
+        })
+    }
+
+    /// The on-curve node after the one at ordinal, if any
+    fn next(&self, ordinal: usize) -> Option<usize> {
+        if ordinal + 1 < self.segments.len() {
+            Some(ordinal + 1)
+        } else if self.closed && self.segments.len() > 1 {
+            Some(0)
+        } else {
+            None
+        }
+    }
+
+    /// Replace the corner at the on-curve node at ordinal with corner
+    ///
+    /// The strokes on either side are cut where the ends of the corner path, once
+    /// aligned, come closest to them, joined by lines if the ends aren't on the strokes.
+    fn insert_corner(&mut self, ordinal: usize, corner: &BezPath, hint: &HintComponent) -> bool {
+        let Some(next) = self.next(ordinal) else {
+            return false;
+        };
+        let (Some(&incoming), Some(&outgoing)) =
+            (self.segments[ordinal].last(), self.segments[next].first())
+        else {
+            return false;
+        };
+        let (Some(in_dir), Some(out_dir)) = (end_direction(incoming), start_direction(outgoing))
+        else {
+            return false;
+        };
+        let corner_pt = incoming.end();
+
+        // The corner is drawn for a left turn, mirror it for a right turn
+        let turn = if in_dir.cross(out_dir) < 0.0 {
+            -1.0
+        } else {
+            1.0
+        };
+        let perpendicular = |v: Vec2| Vec2::new(-v.y, v.x) * turn;
+        let x_axis = match hint.alignment {
+            CornerAlignment::Outstroke => out_dir,
+            CornerAlignment::Instroke => -perpendicular(-in_dir),
+            CornerAlignment::Middle => {
+                let middle = out_dir - perpendicular(-in_dir);
+                if middle.length() > EPSILON {
+                    middle.normalize()
+                } else {
+                    out_dir
+                }
+            }
+            CornerAlignment::Unaligned => Vec2::new(1.0, 0.0),
+        };
+        let y_axis = match hint.alignment {
+            CornerAlignment::Unaligned => Vec2::new(0.0, 1.0),
+            _ => perpendicular(x_axis),
+        };
+        let transform = Affine::new([
+            x_axis.x,
+            x_axis.y,
+            y_axis.x,
+            y_axis.y,
+            corner_pt.x,
+            corner_pt.y,
+        ]) * Affine::scale_non_uniform(hint.scale.x, hint.scale.y);
+        let mut corner_segments: Vec<_> = corner.segments().map(|seg| transform * seg).collect();
+        let (Some(first), Some(last)) = (corner_segments.first(), corner_segments.last()) else {
+            return false;
+        };
+
+        // The path should run from the incoming stroke to the outgoing one, if it's
+        // drawn the other way around turn it around
+        let in_line = Line::new(corner_pt, corner_pt - in_dir);
+        let out_line = Line::new(corner_pt, corner_pt + out_dir);
+        let (start, end) = (first.start(), last.end());
+        if distance_to_line(start, out_line) + distance_to_line(end, in_line)
+            < distance_to_line(start, in_line) + distance_to_line(end, out_line)
+        {
+            corner_segments = corner_segments.iter().rev().map(PathSeg::reverse).collect();
+        }
+        let start = corner_segments[0].start();
+        let end = corner_segments[corner_segments.len() - 1].end();
+
+        let in_t = incoming.nearest(start, EPSILON).t;
+        let out_t = outgoing.nearest(end, EPSILON).t;
+        let incoming = incoming.subsegment(0.0..in_t);
+        let outgoing = outgoing.subsegment(out_t..1.0);
+
+        let at_corner = &mut self.segments[ordinal];
+        *at_corner.last_mut().unwrap() = incoming;
+        push_joined(at_corner, incoming.end(), corner_segments);
+        if end.distance(outgoing.start()) > EPSILON {
+            at_corner.push(Line::new(end, outgoing.start()).into());
+        }
+        *self.segments[next].first_mut().unwrap() = outgoing;
+        true
+    }
+
+    /// Replace the segment following the on-curve node at ordinal with cap
+    ///
+    /// The cap is rotated and stretched along the line between its ends so they meet
+    /// the ends of the segment; across that line the vertical scale of the hint applies.
+    fn insert_cap(&mut self, ordinal: usize, cap: &BezPath, hint: &HintComponent) -> bool {
+        let Some(next) = self.next(ordinal) else {
+            return false;
+        };
+        let (Some(first), Some(last)) = (self.segments[next].first(), self.segments[next].last())
+        else {
+            return false;
+        };
+        let (seg_start, seg_end) = (first.start(), last.end());
+
+        let cap_segments: Vec<_> = cap.segments().collect();
+        let (Some(cap_first), Some(cap_last)) = (cap_segments.first(), cap_segments.last()) else {
+            return false;
+        };
+        let (cap_start, cap_end) = (cap_first.start(), cap_last.end());
+        let (cap_span, seg_span) = (cap_end - cap_start, seg_end - seg_start);
+        if cap_span.length() < EPSILON || seg_span.length() < EPSILON {
+            return false;
+        }
+        // Map the line between the cap's ends onto the segment, working in the frame of
+        // each line rather than with angles to keep axis aligned results exact
+        let frame = |along: Vec2| {
+            let along = along.normalize();
+            Affine::new([along.x, along.y, -along.y, along.x, 0.0, 0.0])
+        };
+        let transform = Affine::translate(seg_start.to_vec2())
+            * frame(seg_span)
+            * Affine::scale_non_uniform(seg_span.length() / cap_span.length(), hint.scale.y)
+            * frame(cap_span).inverse()
+            * Affine::translate(-cap_start.to_vec2());
+        let mut replacement = Vec::with_capacity(cap_segments.len());
+        push_joined(
+            &mut replacement,
+            seg_start,
+            cap_segments.into_iter().map(|seg| transform * seg),
+        );
+        self.segments[next] = replacement;
+        true
+    }
+
+    fn into_bez_path(self) -> BezPath {
+        let mut segments = self.segments.into_iter().flatten().filter(|seg| {
+            // trimming at the very start or end of a stroke leaves nothing of it
+            !matches!(seg, PathSeg::Line(line) if line.p0.distance(line.p1) < EPSILON)
+        });
+        let mut path = BezPath::new();
+        let Some(first) = segments.next() else {
+            return path;
+        };
+        path.move_to(first.start());
+        path.push(first.as_path_el());
+        segments.for_each(|seg| path.push(seg.as_path_el()));
+        if self.closed {
+            path.push(PathEl::ClosePath);
+        }
+        path
+    }
+}
+
+/// Add segments to those ending at start, joining them with a line if needed
+fn push_joined(dest: &mut Vec<PathSeg>, start: Point, segments: impl IntoIterator<Item = PathSeg>) {
+    let mut segments = segments.into_iter().peekable();
+    if let Some(first) = segments.peek() {
+        if start.distance(first.start()) > EPSILON {
+            dest.push(Line::new(start, first.start()).into());
+        }
+    }
+    dest.extend(segments);
+}
+
+fn distance_to_line(pt: Point, line: Line) -> f64 {
+    let direction = line.p1 - line.p0;
+    (pt - line.p0).cross(direction).abs() / direction.length()
+}
+
+fn control_points(seg: PathSeg) -> Vec<Point> {
+    match seg {
+        PathSeg::Line(line) => vec![line.p0, line.p1],
+        PathSeg::Quad(quad) => vec![quad.p0, quad.p1, quad.p2],
+        PathSeg::Cubic(cubic) => vec![cubic.p0, cubic.p1, cubic.p2, cubic.p3],
+    }
+}
+
+/// The unit direction the segment leaves its start in
+fn start_direction(seg: PathSeg) -> Option<Vec2> {
+    let points = control_points(seg);
+    points[1..]
+        .iter()
+        .map(|pt| *pt - points[0])
+        .find(|v| v.length() > EPSILON)
+        .map(Vec2::normalize)
+}
+
+/// The unit direction the segment arrives at its end in
+fn end_direction(seg: PathSeg) -> Option<Vec2> {
+    start_direction(seg.reverse()).map(|v| -v)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    fn contours(font: &Font, glyph_name: &str) -> Vec<String> {
+        let glyph = font.glyphs.get(glyph_name).unwrap();
+        let layer = &glyph.layers[0];
+        let (contours, _) =
+            crate::toir::to_ir_contours_and_components(glyph_name.into(), font, layer).unwrap();
+        contours.iter().map(|c| c.to_svg()).collect()
+    }
+
+    fn load() -> Font {
+        Font::load(Path::new(
+            "../resources/testdata/glyphs3/CornerComponents.glyphs",
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn parse_hint_components() {
+        let font = load();
+        // stem hints are not components
+        let hints = &font.glyphs.get("I").unwrap().layers[0].hint_components;
+        assert_eq!(
+            vec![(HintComponentKind::Corner, "_corner.serif", 0, 3)],
+            hints
+                .iter()
+                .map(|h| (h.kind, h.name.as_str(), h.path_index, h.node_index))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(Vec2::new(1.0, 1.0), hints[0].scale);
+        let hints = &font.glyphs.get("I.wide").unwrap().layers[0].hint_components;
+        assert_eq!(Vec2::new(2.0, 1.0), hints[0].scale);
+        let hints = &font.glyphs.get("I.cap").unwrap().layers[0].hint_components;
+        assert_eq!(HintComponentKind::Cap, hints[0].kind);
+    }
+
+    #[test]
+    fn insert_corner_aligned_to_outstroke() {
+        // The bottom left corner of the stem gains a serif sticking out to the left
+        assert_eq!(
+            vec!["M100,0 L200,0 L200,500 L100,500 L100,50 L50,0 L100,0 Z"],
+            contours(&load(), "I")
+        );
+    }
+
+    #[test]
+    fn insert_scaled_corner() {
+        // scale = (2,1) makes the serif twice as wide
+        assert_eq!(
+            vec!["M100,0 L200,0 L200,500 L100,500 L100,50 L0,0 L100,0 Z"],
+            contours(&load(), "I.wide")
+        );
+    }
+
+    #[test]
+    fn insert_cap_fit_to_stroke_end() {
+        // The cap drawn 50 wide is stretched over the 100 wide top of the stem
+        assert_eq!(
+            vec!["M100,0 L200,0 L200,500 L150,550 L100,500 L100,0 Z"],
+            contours(&load(), "I.cap")
+        );
+    }
+}
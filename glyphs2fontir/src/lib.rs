@@ -1,4 +1,7 @@
 //! Converts glyphs.app sources into IR for font compilation.
+mod bracket_glyphs;
+mod color_layer_glyphs;
+mod corner_components;
 mod erase_open_corners;
 pub mod source;
 mod toir;
@@ -5,19 +5,24 @@ use std::{
 };
 
 use chrono::DateTime;
+use kurbo::{Affine, Point, Rect, Shape as _, Vec2};
 use log::{debug, trace, warn};
 
 use fontdrasil::{
-    coords::{NormalizedCoord, NormalizedLocation},
+    coords::{DesignCoord, NormalizedCoord, NormalizedLocation},
     orchestration::{Access, AccessBuilder, Work},
     types::GlyphName,
 };
 use fontir::{
     error::{BadGlyph, BadGlyphKind, BadSource, Error},
+    incremental::{Fingerprint, Fingerprinter, Fingerprints},
     ir::{
-        self, AnchorBuilder, Color, ColorPalettes, GdefCategories, GlobalMetric, GlobalMetrics,
-        GlyphInstance, GlyphOrder, KernGroup, KernSide, KerningGroups, KerningInstance,
-        MetaTableValues, NameBuilder, NameKey, NamedInstance, StaticMetadata, DEFAULT_VENDOR_ID,
+        self, AnchorBuilder, AxisValueLabel, AxisValueLocation, Color, ColorGlyph, ColorLine,
+        ColorPalettes, ColorStop, Condition, ConditionSet, ConditionalSubstitution, ExtendMode,
+        FeatureVariations, GdefCategories, GlobalMetric, GlobalMetrics, GlyphFilter,
+        GlyphFilterKind, GlyphInstance, GlyphOrder, GlyphSelection, KernGroup, KernSide,
+        KerningGroups, KerningInstance, MetaTableValues, NameBuilder, NameKey, NamedInstance,
+        Paint, PaintGraph, StatLabels, StaticMetadata, DEFAULT_VENDOR_ID, FOREGROUND_PALETTE_INDEX,
     },
     orchestration::{Context, IrWork, WorkId},
     source::Source,
@@ -35,9 +40,13 @@ use write_fonts::{
         os2::SelectionFlags,
     },
     types::{NameId, Tag},
+    OtRound,
 };
 
-use crate::toir::{design_location, to_ir_contours_and_components, to_ir_features, FontInfo};
+use crate::{
+    color_layer_glyphs::{color_layer_glyph_name, color_layers},
+    toir::{design_location, to_ir_contours_and_components, to_ir_features, to_ir_path, FontInfo},
+};
 
 #[derive(Debug, Clone)]
 pub struct GlyphsIrSource {
@@ -46,20 +55,8 @@ pub struct GlyphsIrSource {
 }
 
 impl GlyphsIrSource {
-    fn create_work_for_one_glyph(
-        &self,
-        glyph_name: GlyphName,
-        font_info: Arc<FontInfo>,
-    ) -> Result<GlyphIrWork, Error> {
-        Ok(GlyphIrWork {
-            glyph_name,
-            font_info,
-        })
-    }
-}
-
-impl Source for GlyphsIrSource {
-    fn new(glyphs_file: &Path) -> Result<Self, Error> {
+    /// Path is to a .glyphs file or .glyphspackage directory
+    pub fn new(glyphs_file: &Path) -> Result<Self, Error> {
         // We have to read the glyphs file then shred it to figure out if anything changed
         let font_info = FontInfo::try_from(Font::load(glyphs_file).map_err(|e| {
             BadSource::custom(
@@ -81,6 +78,19 @@ impl Source for GlyphsIrSource {
         })
     }
 
+    fn create_work_for_one_glyph(
+        &self,
+        glyph_name: GlyphName,
+        font_info: Arc<FontInfo>,
+    ) -> Result<GlyphIrWork, Error> {
+        Ok(GlyphIrWork {
+            glyph_name,
+            font_info,
+        })
+    }
+}
+
+impl Source for GlyphsIrSource {
     fn create_static_metadata_work(&self) -> Result<Box<IrWork>, Error> {
         Ok(Box::new(StaticMetadataWork(self.clone())))
     }
@@ -104,6 +114,10 @@ impl Source for GlyphsIrSource {
         Ok(Box::new(FeatureWork(self.font_info.clone())))
     }
 
+    fn create_feature_variations_work(&self) -> Result<Box<IrWork>, Error> {
+        Ok(Box::new(FeatureVariationsWork(self.font_info.clone())))
+    }
+
     fn create_kerning_group_ir_work(&self) -> Result<Box<IrWork>, Error> {
         Ok(Box::new(KerningGroupWork(self.font_info.clone())))
     }
@@ -130,7 +144,50 @@ impl Source for GlyphsIrSource {
         &self,
     ) -> Result<Box<fontir::orchestration::IrWork>, fontir::error::Error> {
         Ok(Box::new(PaintGraphWork {
-            _font_info: self.font_info.clone(),
+            font_info: self.font_info.clone(),
+        }))
+    }
+
+    fn fingerprint(&self) -> Result<Option<Fingerprints>, Error> {
+        // Exhaustive so a new field forces a decision about what it feeds.
+        // Names, instances, versioning and features only feed work that always reruns.
+        // The date changes on every save so including it would defeat reuse entirely.
+        let Font {
+            units_per_em,
+            axes,
+            masters,
+            default_master_idx,
+            glyphs,
+            glyph_order,
+            axis_mappings,
+            virtual_masters,
+            features: _,
+            names: _,
+            instances: _,
+            version_major: _,
+            version_minor: _,
+            date: _,
+            kerning_ltr,
+            custom_parameters,
+        } = &self.font_info.font;
+
+        let global = Fingerprinter::default()
+            .add(units_per_em)
+            .add(axes)
+            .add(masters)
+            .add(default_master_idx)
+            .add(glyph_order)
+            .add(axis_mappings)
+            .add(virtual_masters)
+            .add(custom_parameters)
+            .finish();
+        Ok(Some(Fingerprints {
+            global,
+            kerning: Fingerprint::of(kerning_ltr),
+            glyphs: glyphs
+                .iter()
+                .map(|(name, glyph)| (name.as_str().into(), Fingerprint::of(glyph)))
+                .collect(),
         }))
     }
 }
@@ -225,6 +282,81 @@ fn names(font: &Font, flags: SelectionFlags) -> HashMap<NameKey, String> {
     builder.into_inner()
 }
 
+/// Parse a variation sequence, (base, selector), from a name like uni82A6.uvsE0101.
+///
+/// The base is named as for a glyph with that codepoint and the selector is in hex.
+fn parse_variation_sequence(glyph_name: &str) -> Option<(u32, u32)> {
+    let (base, selector) = glyph_name.split_once(".uvs")?;
+    let base = base
+        .strip_prefix("uni")
+        .filter(|hex| hex.len() == 4)
+        .or_else(|| {
+            base.strip_prefix('u')
+                .filter(|hex| (4..=6).contains(&hex.len()))
+        })?;
+    let parse_hex = |hex: &str| {
+        hex.chars()
+            .all(|c| c.is_ascii_hexdigit())
+            .then(|| u32::from_str_radix(hex, 16).ok())
+            .flatten()
+    };
+    let selector = parse_hex(selector)
+        .filter(|cp| (0xFE00..=0xFE0F).contains(cp) || (0xE0100..=0xE01EF).contains(cp))?;
+    Some((parse_hex(base)?, selector))
+}
+
+/// Name the positions of instances that are at the default of all but one axis.
+///
+/// For example, a Bold instance at the default width labels weight 700 as Bold.
+/// Labels at an axis default are elidable, and the instance at the default location
+/// provides the elided fallback name.
+fn stat_labels(static_metadata: &StaticMetadata) -> StatLabels {
+    let axes = &static_metadata.axes;
+    let is_default = |instance: &NamedInstance, axis: &fontdrasil::types::Axis| {
+        instance.location.get(axis.tag).unwrap_or(axis.default) == axis.default
+    };
+
+    let mut axis_values = Vec::new();
+    for axis in axes.iter() {
+        let mut seen = HashSet::new();
+        for instance in static_metadata.named_instances.iter() {
+            if !axes
+                .iter()
+                .filter(|other| other.tag != axis.tag)
+                .all(|other| is_default(instance, other))
+            {
+                continue;
+            }
+            let value = instance.location.get(axis.tag).unwrap_or(axis.default);
+            // First instance at a value wins
+            if !seen.insert(value) {
+                continue;
+            }
+            axis_values.push(AxisValueLabel {
+                name: instance.name.clone(),
+                elidable: value == axis.default,
+                older_sibling: false,
+                value: AxisValueLocation::Point {
+                    axis: axis.tag,
+                    value,
+                    linked_value: None,
+                },
+            });
+        }
+    }
+
+    let elided_fallback_name = static_metadata
+        .named_instances
+        .iter()
+        .find(|instance| axes.iter().all(|axis| is_default(instance, axis)))
+        .map(|instance| instance.name.clone());
+
+    StatLabels {
+        axis_values,
+        elided_fallback_name,
+    }
+}
+
 #[derive(Debug)]
 struct StaticMetadataWork(GlyphsIrSource);
 
@@ -411,6 +543,67 @@ impl Work<Context, WorkId, Error> for StaticMetadataWork {
             }
         }
 
+        // Like glyphsLib, zones at the baseline or that overshoot upwards are BlueValues,
+        // the rest OtherBlues. Glyphs doesn't give us stems.
+        // <https://github.com/googlefonts/glyphsLib/blob/main/Lib/glyphsLib/builder/blue_values.py>
+        let hints = &mut static_metadata.misc.postscript_hints;
+        for (pos, over) in default_master.alignment_zones() {
+            let blues = if pos == 0. || over >= 0. {
+                &mut hints.blue_values
+            } else {
+                &mut hints.other_blues
+            };
+            let (bottom, top) = if over < 0. {
+                (pos + over, pos)
+            } else {
+                (pos, pos + over)
+            };
+            blues.extend::<[i16; 2]>([bottom.ot_round(), top.ot_round()]);
+        }
+
+        // Vertical metrics are wanted if they are given for the font or any glyph
+        static_metadata.misc.build_vertical = font
+            .masters
+            .iter()
+            .map(|m| &m.custom_parameters)
+            .chain(std::iter::once(&font.custom_parameters))
+            .any(|params| {
+                params.vhea_ascender.is_some()
+                    || params.vhea_descender.is_some()
+                    || params.vhea_line_gap.is_some()
+            })
+            || font
+                .glyphs
+                .values()
+                .flat_map(|glyph| glyph.layers.iter())
+                .any(|layer| layer.vert_width.is_some() || layer.vert_origin.is_some());
+
+        // Glyphs propagates anchors unless explicitly told not to
+        if font.custom_parameters.propagate_anchors.unwrap_or(true) {
+            static_metadata.glyph_filters = vec![GlyphFilter {
+                kind: GlyphFilterKind::PropagateAnchors,
+                pre: true,
+                glyphs: GlyphSelection::All,
+            }];
+        }
+
+        let stat_labels = stat_labels(&static_metadata);
+        static_metadata.set_stat_labels(stat_labels);
+
+        for glyph_name in font
+            .glyph_order
+            .iter()
+            .filter(|name| self.0.glyph_names.contains(&GlyphName::new(name.as_str())))
+        {
+            if let Some((base, selector)) = parse_variation_sequence(glyph_name) {
+                static_metadata
+                    .variation_sequences
+                    .entry(selector)
+                    .or_default()
+                    .insert(base, GlyphName::new(glyph_name.as_str()));
+            }
+        }
+
         context.static_metadata.set(static_metadata);
 
         let glyph_order = font
@@ -429,7 +622,7 @@ fn make_glyph_categories(font: &Font) -> GdefCategories {
         .glyphs
         .iter()
         .filter_map(|(name, glyph)| {
-            category_for_glyph(glyph).map(|cat| (GlyphName::new(name), cat))
+            category_for_glyph(font, glyph).map(|cat| (GlyphName::new(name), cat))
         })
         .collect();
     GdefCategories {
@@ -459,14 +652,11 @@ fn get_number_values(
 /// determine the GDEF category for this glyph, if appropriate
 // see
 // <https://github.com/googlefonts/glyphsLib/blob/e2ebf5b517/Lib/glyphsLib/builder/features.py#L205>
-fn category_for_glyph(glyph: &glyphs_reader::Glyph) -> Option<GlyphClassDef> {
-    let has_attaching_anchor = glyph
-        .layers
-        .iter()
-        .flat_map(|layer| layer.anchors.iter())
-        // glyphsLib considers any anchor that does not start with '_' as an
-        // 'attaching anchor'; see https://github.com/googlefonts/glyphsLib/issues/1024
-        .any(|anchor| !anchor.name.starts_with('_'));
+fn category_for_glyph(font: &Font, glyph: &glyphs_reader::Glyph) -> Option<GlyphClassDef> {
+    // glyphsLib categorizes after anchors are propagated, we propagate later, in IR
+    let propagates_anchors = font.custom_parameters.propagate_anchors.unwrap_or(true);
+    let has_attaching_anchor =
+        has_attaching_anchor(font, glyph, propagates_anchors, &mut HashSet::new());
     match (glyph.category, glyph.sub_category) {
         (_, Some(Subcategory::Ligature)) if has_attaching_anchor => Some(GlyphClassDef::Ligature),
         (
@@ -478,6 +668,38 @@ fn category_for_glyph(glyph: &glyphs_reader::Glyph) -> Option<GlyphClassDef> {
     }
 }
 
+/// Whether the glyph has, or will get by anchor propagation, an attaching anchor
+fn has_attaching_anchor<'a>(
+    font: &'a Font,
+    glyph: &'a glyphs_reader::Glyph,
+    propagates_anchors: bool,
+    visited: &mut HashSet<&'a str>,
+) -> bool {
+    if !visited.insert(glyph.name.as_str()) {
+        return false;
+    }
+    // glyphsLib considers any anchor that does not start with '_' as an
+    // 'attaching anchor'; see https://github.com/googlefonts/glyphsLib/issues/1024
+    if glyph
+        .layers
+        .iter()
+        .flat_map(|layer| layer.anchors.iter())
+        .any(|anchor| !anchor.name.starts_with('_'))
+    {
+        return true;
+    }
+    propagates_anchors
+        && glyph
+            .layers
+            .iter()
+            .flat_map(|layer| layer.shapes.iter())
+            .filter_map(|shape| match shape {
+                glyphs_reader::Shape::Component(component) => font.glyphs.get(&component.name),
+                _ => None,
+            })
+            .any(|component| has_attaching_anchor(font, component, propagates_anchors, visited))
+}
+
 #[derive(Debug)]
 struct GlobalMetricWork(Arc<FontInfo>);
 
@@ -576,6 +798,12 @@ impl Work<Context, WorkId, Error> for GlobalMetricWork {
             set_metric!(CaretSlopeRun, hhea_caret_slope_run);
             set_metric!(CaretSlopeRise, hhea_caret_slope_rise);
             set_metric!(CaretOffset, hhea_caret_offset);
+            set_metric!(VheaAscender, vhea_ascender);
+            set_metric!(VheaDescender, vhea_descender);
+            set_metric!(VheaLineGap, vhea_line_gap);
+            set_metric!(VheaCaretSlopeRun, vhea_caret_slope_run);
+            set_metric!(VheaCaretSlopeRise, vhea_caret_slope_rise);
+            set_metric!(VheaCaretOffset, vhea_caret_offset);
             // 50.0 is the Glyphs default <https://github.com/googlefonts/glyphsLib/blob/9d5828d874110c42dfc5f542db8eb84f88641eb5/Lib/glyphsLib/builder/custom_params.py#L1136-L1156>
             set_metric!(UnderlineThickness, underline_thickness, 50.0);
             // -100.0 is the Glyphs default <https://github.com/googlefonts/glyphsLib/blob/9d5828d874110c42dfc5f542db8eb84f88641eb5/Lib/glyphsLib/builder/custom_params.py#L1136-L1156>
@@ -615,6 +843,84 @@ impl Work<Context, WorkId, Error> for FeatureWork {
     }
 }
 
+#[derive(Debug)]
+struct FeatureVariationsWork(Arc<FontInfo>);
+
+impl Work<Context, WorkId, Error> for FeatureVariationsWork {
+    fn id(&self) -> WorkId {
+        WorkId::FeatureVariations
+    }
+
+    fn exec(&self, context: &Context) -> Result<(), Error> {
+        let font_info = self.0.as_ref();
+        if font_info.bracket_substitutions.is_empty() {
+            return Ok(());
+        }
+        let rules = font_info
+            .bracket_substitutions
+            .iter()
+            .map(|(bracket_box, substitutions)| {
+                let conditions = bracket_box
+                    .iter()
+                    .zip(font_info.axes.iter())
+                    .filter(|(rule, _)| rule.min.is_some() || rule.max.is_some())
+                    .map(|(rule, axis)| {
+                        let normalize = |design: Option<OrderedFloat<f64>>, unbounded: f64| {
+                            design
+                                .map(|v| {
+                                    DesignCoord::new(v)
+                                        .to_normalized(&axis.converter)
+                                        .to_f64()
+                                        .clamp(-1.0, 1.0)
+                                })
+                                .unwrap_or(unbounded)
+                        };
+                        Condition {
+                            axis: axis.tag,
+                            min: NormalizedCoord::new(normalize(rule.min, -1.0)),
+                            max: NormalizedCoord::new(normalize(rule.max, 1.0)),
+                        }
+                    });
+                ConditionalSubstitution {
+                    condition_sets: vec![ConditionSet::new(conditions)],
+                    substitutions: substitutions
+                        .iter()
+                        .map(|(glyph, alternate)| {
+                            (glyph.as_str().into(), alternate.as_str().into())
+                        })
+                        .collect(),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        // Like glyphsLib, bracket substitutions run after other substitutions
+        let features = match &font_info
+            .font
+            .custom_parameters
+            .feature_for_feature_variations
+        {
+            Some(raw_tags) => raw_tags
+                .split(',')
+                .map(|raw_tag| {
+                    Tag::new_checked(raw_tag.trim().as_bytes()).map_err(|cause| Error::InvalidTag {
+                        raw_tag: raw_tag.to_string(),
+                        cause,
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+            None => vec![FeatureVariations::PROCESS_LAST_FEATURE],
+        };
+        debug!(
+            "{} bracket layer rules registered under {features:?}",
+            rules.len()
+        );
+        context
+            .feature_variations
+            .set(FeatureVariations::new(features, rules));
+        Ok(())
+    }
+}
+
 fn parse_kern_group(name: &str) -> Option<KernGroup> {
     name.strip_prefix(SIDE1_PREFIX)
         .map(|name| KernGroup::Side1(name.into()))
@@ -888,16 +1194,31 @@ impl Work<Context, WorkId, Error> for GlyphIrWork {
                 axis_positions.entry(*tag).or_default().insert(*coord);
             }
 
-            // TODO populate width and height properly
+            // glyphsLib measures vertical metrics from the master ascender
+            // <https://github.com/googlefonts/glyphsLib/blob/main/Lib/glyphsLib/builder/glyph.py>
+            let ascender = master.ascender().unwrap_or(800.0);
+            let descender = master.descender().unwrap_or(-200.0);
             let (contours, components) =
-                to_ir_contours_and_components(self.glyph_name.clone(), &instance.shapes)?;
+                to_ir_contours_and_components(self.glyph_name.clone(), font, instance)?;
             let glyph_instance = GlyphInstance {
                 width: if !zero_width {
                     instance.width.into_inner()
                 } else {
                     0.0
                 },
-                height: None,
+                height: Some(
+                    instance
+                        .vert_width
+                        .map(OrderedFloat::into_inner)
+                        .unwrap_or(ascender - descender),
+                ),
+                vertical_origin: Some(
+                    ascender
+                        - instance
+                            .vert_origin
+                            .map(OrderedFloat::into_inner)
+                            .unwrap_or(0.0),
+                ),
                 contours,
                 components,
             };
@@ -980,7 +1301,7 @@ impl Work<Context, WorkId, Error> for ColorPaletteWork {
 
 #[derive(Debug)]
 struct PaintGraphWork {
-    _font_info: Arc<FontInfo>,
+    font_info: Arc<FontInfo>,
 }
 
 impl Work<Context, WorkId, Error> for PaintGraphWork {
@@ -989,19 +1310,197 @@ impl Work<Context, WorkId, Error> for PaintGraphWork {
     }
 
     fn read_access(&self) -> Access<WorkId> {
-        Access::None
+        Access::Variant(WorkId::ColorPalettes)
     }
 
     fn write_access(&self) -> Access<WorkId> {
         Access::Variant(WorkId::PaintGraph)
     }
 
-    fn exec(&self, _context: &Context) -> Result<(), Error> {
-        debug!("TODO: actually create paint graph");
+    fn exec(&self, context: &Context) -> Result<(), Error> {
+        let font_info = self.font_info.as_ref();
+        let palettes = context.colors.try_get();
+        let palette = palettes.as_ref().map(|p| p.palettes[0].as_slice());
+
+        let mut paint_graph = PaintGraph::default();
+        for glyph in font_info.font.glyphs.values() {
+            let glyph_name: GlyphName = glyph.name.as_str().into();
+            let mut sources = HashMap::new();
+            for layer in color_layers(glyph) {
+                let Some(location) = font_info.master_positions.get(&layer.layer_id) else {
+                    return Err(BadGlyph::new(
+                        glyph_name.clone(),
+                        BadGlyphKind::MissingMaster(layer.layer_id.clone()),
+                    )
+                    .into());
+                };
+                let Some(paint) = to_ir_paint(&font_info.font, &glyph_name, layer, palette)? else {
+                    continue;
+                };
+                sources.insert(location.clone(), paint);
+            }
+            if !sources.is_empty() {
+                paint_graph
+                    .base_glyphs
+                    .insert(glyph_name, ColorGlyph { sources });
+            }
+        }
+        debug!("{} color glyphs", paint_graph.base_glyphs.len());
+        if !paint_graph.is_empty() {
+            context.paint_graph.set(paint_graph);
+        }
         Ok(())
     }
 }
 
+/// Paint a color layer, shapes bottom to top.
+///
+/// A layer with a single path is the glyph outline so it's painted directly. Otherwise
+/// each path is painted through a glyph of its own, see [crate::color_layer_glyphs],
+/// and each component paints the glyph it refers to.
+fn to_ir_paint(
+    font: &Font,
+    glyph_name: &GlyphName,
+    layer: &glyphs_reader::Layer,
+    palette: Option<&[Color]>,
+) -> Result<Option<Paint>, Error> {
+    if let [shape @ glyphs_reader::Shape::Path(_)] = layer.shapes.as_slice() {
+        // Gradient start/end are relative to the bounding box of the shape
+        let (contours, _) = to_ir_contours_and_components(glyph_name.clone(), font, layer)?;
+        let bbox = contours
+            .iter()
+            .map(|c| c.bounding_box())
+            .reduce(|acc, b| acc.union(b))
+            .unwrap_or_default();
+        return Ok(
+            to_ir_fill(glyph_name, shape, bbox, palette)?.map(|paint| Paint::Glyph {
+                name: glyph_name.clone(),
+                paint: Box::new(paint),
+            }),
+        );
+    }
+
+    let mut layers = Vec::with_capacity(layer.shapes.len());
+    for (shape_idx, shape) in layer.shapes.iter().enumerate() {
+        match shape {
+            glyphs_reader::Shape::Path(path) => {
+                let bbox = to_ir_path(path)
+                    .map_err(|e| BadGlyph::new(glyph_name.clone(), e))?
+                    .bounding_box();
+                let Some(paint) = to_ir_fill(glyph_name, shape, bbox, palette)? else {
+                    continue;
+                };
+                layers.push(Paint::Glyph {
+                    name: color_layer_glyph_name(glyph_name.as_str(), shape_idx).into(),
+                    paint: Box::new(paint),
+                });
+            }
+            glyphs_reader::Shape::Component(component) => {
+                let base: GlyphName = component.name.as_str().into();
+                let is_color_glyph = font
+                    .glyphs
+                    .get(&component.name)
+                    .is_some_and(|glyph| color_layers(glyph).next().is_some());
+                let paint = if is_color_glyph {
+                    Paint::ColrGlyph(base)
+                } else {
+                    Paint::Glyph {
+                        name: base,
+                        paint: Box::new(FOREGROUND),
+                    }
+                };
+                layers.push(if component.transform == Affine::IDENTITY {
+                    paint
+                } else {
+                    Paint::Transform {
+                        transform: component.transform,
+                        paint: Box::new(paint),
+                    }
+                });
+            }
+        }
+    }
+    Ok(match layers.len() {
+        0 | 1 => layers.pop(),
+        _ => Some(Paint::Layers(layers)),
+    })
+}
+
+const FOREGROUND: Paint = Paint::Solid {
+    palette_index: FOREGROUND_PALETTE_INDEX,
+    alpha: 1.0,
+};
+
+/// The paint that fills a shape whose bounding box is bbox
+fn to_ir_fill(
+    glyph_name: &GlyphName,
+    shape: &glyphs_reader::Shape,
+    bbox: Rect,
+    palette: Option<&[Color]>,
+) -> Result<Option<Paint>, Error> {
+    let gradient = &shape.attributes().gradient;
+    if gradient.colors.is_empty() {
+        return Ok(Some(FOREGROUND));
+    }
+
+    let stops = gradient
+        .colors
+        .iter()
+        .map(|c| {
+            let color = Color {
+                r: c.r as u8,
+                g: c.g as u8,
+                b: c.b as u8,
+                a: c.a as u8,
+            };
+            let palette_index = palette
+                .and_then(|p| p.iter().position(|pc| *pc == color))
+                .ok_or_else(|| Error::MissingPaletteColor(glyph_name.clone(), color))?;
+            Ok(ColorStop {
+                offset: c.stop_offset.into_inner(),
+                palette_index: palette_index as u16,
+                alpha: 1.0,
+            })
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    let color_line = ColorLine {
+        extend: ExtendMode::Pad,
+        stops,
+    };
+
+    let to_point = |rel: &[OrderedFloat<f64>]| match rel {
+        [x, y] => Some(Point::new(
+            bbox.x0 + x.into_inner() * bbox.width(),
+            bbox.y0 + y.into_inner() * bbox.height(),
+        )),
+        _ => None,
+    };
+    let (Some(start), Some(end)) = (to_point(&gradient.start), to_point(&gradient.end)) else {
+        warn!("'{glyph_name}' has a gradient without a start and end, ignoring it");
+        return Ok(None);
+    };
+
+    let paint = if gradient.style == "circle" {
+        Paint::RadialGradient {
+            color_line,
+            c0: start,
+            r0: 0.0,
+            c1: start,
+            r1: start.distance(end),
+        }
+    } else {
+        // The rotation point is perpendicular to start => end so color lines are too
+        let d = end - start;
+        Paint::LinearGradient {
+            color_line,
+            p0: start,
+            p1: end,
+            p2: start + Vec2::new(-d.y, d.x),
+        }
+    };
+    Ok(Some(paint))
+}
+
 #[cfg(test)]
 mod tests {
     use std::{
@@ -1077,6 +1576,22 @@ mod tests {
         );
     }
 
+    #[test]
+    fn fingerprint_ignores_serialization() {
+        let glyphs = GlyphsIrSource::new(&glyphs3_dir().join("WghtVar.glyphs"))
+            .unwrap()
+            .fingerprint()
+            .unwrap()
+            .unwrap();
+        let glyphspackage = GlyphsIrSource::new(&glyphs3_dir().join("WghtVar.glyphspackage"))
+            .unwrap()
+            .fingerprint()
+            .unwrap()
+            .unwrap();
+        assert_eq!(glyphs, glyphspackage);
+        assert_eq!(6, glyphs.glyphs.len());
+    }
+
     fn context_for(glyphs_file: &Path) -> (impl Source, Context) {
         let source = GlyphsIrSource::new(glyphs_file).unwrap();
         let mut flags = Flags::default();
@@ -1376,6 +1891,64 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parses_variation_sequences_from_names() {
+        assert_eq!(
+            vec![
+                Some((0x82A6, 0xE0101)),
+                Some((0x1F600, 0xFE0F)),
+                None,
+                None,
+                None,
+            ],
+            [
+                "uni82A6.uvsE0101",
+                "u1F600.uvsFE0F",
+                "uni82A6.uvs0041",
+                "uni82A6.uvsE0101.ss01",
+                "uni82A6.jp",
+            ]
+            .into_iter()
+            .map(parse_variation_sequence)
+            .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn stat_labels_from_instances() {
+        let (_, context) = build_static_metadata(glyphs3_dir().join("WghtVar_Instances.glyphs"));
+        let static_metadata = context.static_metadata.get();
+        let wght = Tag::new(b"wght");
+        assert_eq!(
+            StatLabels {
+                axis_values: vec![
+                    AxisValueLabel {
+                        name: "Regular".to_string(),
+                        elidable: true,
+                        older_sibling: false,
+                        value: AxisValueLocation::Point {
+                            axis: wght,
+                            value: UserCoord::new(400.0),
+                            linked_value: None,
+                        },
+                    },
+                    AxisValueLabel {
+                        name: "Bold".to_string(),
+                        elidable: false,
+                        older_sibling: false,
+                        value: AxisValueLocation::Point {
+                            axis: wght,
+                            value: UserCoord::new(700.0),
+                            linked_value: None,
+                        },
+                    },
+                ],
+                elided_fallback_name: Some("Regular".to_string()),
+            },
+            static_metadata.stat_labels
+        );
+    }
+
     #[test]
     fn captures_single_codepoints() {
         let (source, context) = build_static_metadata(glyphs2_dir().join("WghtVar.glyphs"));
@@ -1417,7 +1990,7 @@ mod tests {
 
         // even though glyphs does not assign this a category, we still determine
         // it is a base based on the presence of base anchors.
-        assert_eq!(category_for_glyph(glyph), Some(GlyphClassDef::Base));
+        assert_eq!(category_for_glyph(&font, glyph), Some(GlyphClassDef::Base));
     }
 
     // It's so minimal it's a good test
@@ -1622,6 +2195,15 @@ mod tests {
         );
     }
 
+    // The same zones glyphsLib puts in designspace_from_glyphs/WghtVar-Regular.ufo
+    #[test]
+    fn alignment_zones_become_blue_values() {
+        let (_, context) = build_static_metadata(glyphs3_dir().join("WghtVar.glyphs"));
+        let hints = &context.static_metadata.get().misc.postscript_hints;
+        assert_eq!(vec![-16, 0, 737, 753], hints.blue_values);
+        assert_eq!(vec![-58, -42], hints.other_blues);
+    }
+
     #[test]
     fn version_default() {
         let font = Font::load(&glyphs3_dir().join("infinity.glyphs")).unwrap();
@@ -1665,6 +2247,10 @@ mod tests {
                 hhea_ascender: 1158.0.into(),
                 hhea_descender: (-42.0).into(),
                 hhea_line_gap: 0.0.into(),
+                vhea_ascender: 500.0.into(),
+                vhea_descender: (-500.0).into(),
+                vhea_line_gap: 1000.0.into(),
+                vhea_caret_slope_run: 1.0.into(),
                 underline_thickness: 50.0.into(),
                 underline_position: (-100.0).into(),
                 ..Default::default()
@@ -1821,6 +2407,10 @@ mod tests {
                     hhea_ascender: 950.0.into(),
                     hhea_descender: (-350.0).into(),
                     hhea_line_gap: 0.0.into(),
+                    vhea_ascender: 500.0.into(),
+                    vhea_descender: (-500.0).into(),
+                    vhea_line_gap: 1000.0.into(),
+                    vhea_caret_slope_run: 1.0.into(),
                     underline_thickness: 40.0.into(), // overridden from global value
                     underline_position: (-300.0).into(),
                     ..Default::default()
@@ -1854,6 +2444,10 @@ mod tests {
                 hhea_ascender: 1000.0.into(),
                 hhea_descender: (-400.0).into(),
                 hhea_line_gap: 0.0.into(),
+                vhea_ascender: 500.0.into(),
+                vhea_descender: (-500.0).into(),
+                vhea_line_gap: 1000.0.into(),
+                vhea_caret_slope_run: 1.0.into(),
                 underline_thickness: 42.0.into(), // global value
                 underline_position: (-300.0).into(),
                 ..Default::default()
@@ -2052,4 +2646,88 @@ mod tests {
         // this is a spacing-combining mark, so we shouldn't zero it's width
         assert!(glyph.default_instance().width != 0.0);
     }
+
+    fn build_feature_variations(glyphs_file: PathBuf) -> Option<Arc<FeatureVariations>> {
+        let (source, context) = context_for(&glyphs_file);
+        let work = source.create_feature_variations_work().unwrap();
+        work.exec(&context.copy_for_work(work.read_access(), work.write_access()))
+            .unwrap();
+        context.feature_variations.try_get()
+    }
+
+    fn assert_bracket_feature_variations(glyphs_file: PathBuf) {
+        let feature_variations = build_feature_variations(glyphs_file).unwrap();
+        assert_eq!(
+            vec![FeatureVariations::PROCESS_LAST_FEATURE],
+            feature_variations.features
+        );
+        let rules = feature_variations
+            .rules
+            .iter()
+            .map(|rule| {
+                let conditions = rule
+                    .condition_sets
+                    .iter()
+                    .flat_map(|cs| cs.iter())
+                    .map(|c| {
+                        (
+                            c.axis,
+                            (c.min.to_f64() * 1000.0).round() / 1000.0,
+                            c.max.to_f64(),
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                let substitutions = rule
+                    .substitutions
+                    .iter()
+                    .map(|(from, to)| (from.as_str(), to.as_str()))
+                    .collect::<Vec<_>>();
+                (conditions, substitutions)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![(
+                vec![(Tag::new(b"wght"), 0.667, 1.0)],
+                vec![
+                    ("A", "A.BRACKET.varAlt01"),
+                    ("Aacute", "Aacute.BRACKET.varAlt01")
+                ]
+            )],
+            rules
+        );
+    }
+
+    #[test]
+    fn bracket_layers_to_feature_variations_v2() {
+        assert_bracket_feature_variations(glyphs2_dir().join("WghtVar_Bracket.glyphs"));
+    }
+
+    #[test]
+    fn bracket_layers_to_feature_variations_v3() {
+        assert_bracket_feature_variations(glyphs3_dir().join("WghtVar_Bracket.glyphs"));
+    }
+
+    #[test]
+    fn no_bracket_layers_no_feature_variations() {
+        assert!(build_feature_variations(glyphs3_dir().join("WghtVar.glyphs")).is_none());
+    }
+
+    #[test]
+    fn bracket_glyphs_have_ir() {
+        let (source, context) = build_static_metadata(glyphs3_dir().join("WghtVar_Bracket.glyphs"));
+        build_glyphs(&source, &context).unwrap();
+        let glyph = context.get_glyph("A.BRACKET.varAlt01");
+        assert!(glyph.codepoints.is_empty());
+        assert_eq!(2, glyph.sources().len());
+        let composite = context.get_glyph("Aacute.BRACKET.varAlt01");
+        assert_eq!(
+            vec!["A.BRACKET.varAlt01", "acutecomb"],
+            composite
+                .default_instance()
+                .components
+                .iter()
+                .map(|c| c.base.as_str())
+                .collect::<Vec<_>>()
+        );
+    }
 }
@@ -17,28 +17,54 @@ use fontir::{
     error::{BadGlyph, Error, PathConversionError},
     ir::{self, GlyphPathBuilder},
 };
-use glyphs_reader::{Component, FeatureSnippet, Font, NodeType, Path, Shape};
+use glyphs_reader::{Component, FeatureSnippet, Font, Layer, NodeType, Path, Shape};
 
+use crate::{
+    bracket_glyphs::{synthesize_bracket_glyphs, BracketSubstitutions},
+    color_layer_glyphs::synthesize_color_layer_glyphs,
+    corner_components::insert_hint_components,
+};
+
+/// The contours and components of a layer.
+///
+/// Corner and cap components attached to the layer's paths are inserted into the contours.
 pub(crate) fn to_ir_contours_and_components(
     glyph_name: GlyphName,
-    shapes: &[Shape],
+    font: &Font,
+    layer: &Layer,
 ) -> Result<(Vec<BezPath>, Vec<ir::Component>), BadGlyph> {
     // For most glyphs in most fonts all the shapes are contours so it's a good guess
-    let mut contours = Vec::with_capacity(shapes.len());
+    let mut contours = Vec::with_capacity(layer.shapes.len());
     let mut components = Vec::new();
 
-    for shape in shapes.iter() {
+    for shape in layer.shapes.iter() {
         match shape {
             Shape::Component(component) => {
                 components.push(to_ir_component(glyph_name.clone(), component))
             }
-            Shape::Path(path) => contours.push(
-                to_ir_path(glyph_name.clone(), path)
-                    .map_err(|e| BadGlyph::new(glyph_name.clone(), e))?,
-            ),
+            Shape::Path(path) => {
+                contours.push(to_ir_path(path).map_err(|e| BadGlyph::new(glyph_name.clone(), e))?)
+            }
         }
     }
 
+    if !layer.hint_components.is_empty() {
+        insert_hint_components(font, &glyph_name, layer, &mut contours)?;
+    }
+
+    // Open corners are erased from the final outline, as glyphsLib does in a filter
+    for contour in contours.iter_mut() {
+        if let Some(changes) = crate::erase_open_corners::erase_open_corners(contour) {
+            log::debug!("erased open contours for {glyph_name}");
+            *contour = changes;
+        }
+        trace!(
+            "Built a {} entry path for {}",
+            contour.elements().len(),
+            glyph_name
+        );
+    }
+
     Ok((contours, components))
 }
 
@@ -52,6 +78,8 @@ fn to_ir_component(glyph_name: GlyphName, component: &Component) -> ir::Componen
     ir::Component {
         base: component.name.as_str().into(),
         transform: component.transform,
+        variable: None,
+        anchor: component.anchor.clone(),
     }
 }
 
@@ -77,7 +105,7 @@ fn add_to_path<'a>(
     Ok(())
 }
 
-fn to_ir_path(glyph_name: GlyphName, src_path: &Path) -> Result<BezPath, PathConversionError> {
+pub(crate) fn to_ir_path(src_path: &Path) -> Result<BezPath, PathConversionError> {
     // Based on https://github.com/googlefonts/glyphsLib/blob/24b4d340e4c82948ba121dcfe563c1450a8e69c9/Lib/glyphsLib/builder/paths.py#L20
     // See also https://github.com/fonttools/ufoLib2/blob/4d8a9600148b670b0840120658d9aab0b38a9465/src/ufoLib2/pointPens/glyphPointPen.py#L16
     if src_path.nodes.is_empty() {
@@ -112,21 +140,7 @@ fn to_ir_path(glyph_name: GlyphName, src_path: &Path) -> Result<BezPath, PathCon
         add_to_path(&mut path_builder, src_path.nodes.iter())?;
     };
 
-    let path = path_builder.build()?;
-
-    let path = match crate::erase_open_corners::erase_open_corners(&path) {
-        Some(changes) => {
-            log::debug!("erased open contours for {glyph_name}");
-            changes
-        }
-        None => path,
-    };
-    trace!(
-        "Built a {} entry path for {}",
-        path.elements().len(),
-        glyph_name
-    );
-    Ok(path)
+    path_builder.build()
 }
 
 pub(crate) fn to_ir_features(features: &[FeatureSnippet]) -> Result<ir::FeaturesSource, Error> {
@@ -269,12 +283,17 @@ pub(crate) struct FontInfo {
     /// Axes values => location for every instance and master
     pub locations: HashMap<Vec<OrderedFloat<f64>>, NormalizedLocation>,
     pub axes: Vec<fontdrasil::types::Axis>,
+    /// Alternate glyphs synthesized from bracket layers, by the region they apply in
+    pub bracket_substitutions: BracketSubstitutions,
 }
 
 impl TryFrom<Font> for FontInfo {
     type Error = Error;
 
-    fn try_from(font: Font) -> Result<Self, Self::Error> {
+    fn try_from(mut font: Font) -> Result<Self, Self::Error> {
+        let bracket_substitutions = synthesize_bracket_glyphs(&mut font);
+        synthesize_color_layer_glyphs(&mut font);
+
         let master_indices: HashMap<_, _> = font
             .masters
             .iter()
@@ -324,6 +343,7 @@ impl TryFrom<Font> for FontInfo {
             master_positions,
             locations,
             axes,
+            bracket_substitutions,
         })
     }
 }
@@ -353,7 +373,7 @@ mod tests {
             pt: (32.0, 32.0).into(),
             node_type: glyphs_reader::NodeType::Curve,
         });
-        let bez = to_ir_path("test".into(), &path).unwrap();
+        let bez = to_ir_path(&path).unwrap();
         assert_eq!("M32,32 C64,64 64,0 32,32 Z", bez.to_svg());
     }
 
@@ -374,7 +394,7 @@ mod tests {
             ..Default::default()
         };
 
-        let bez = to_ir_path("hello".into(), &path).unwrap();
+        let bez = to_ir_path(&path).unwrap();
         assert_eq!(
             bez.elements().first(),
             Some(&kurbo::PathEl::MoveTo((5., 0.).into()))
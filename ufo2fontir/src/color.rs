@@ -0,0 +1,533 @@
+//! Color palettes and color glyphs from the lib keys ufo2ft reads.
+//!
+//! Palettes are `com.github.googlei18n.ufo2ft.colorPalettes`, lists of RGBA colors with
+//! components from 0 to 1. Color glyphs are either COLRv0 layers in
+//! `com.github.googlei18n.ufo2ft.colorLayers` or paints in
+//! `com.github.googlei18n.ufo2ft.colorGlyphs`, in the form fontTools'
+//! [buildCOLR](https://github.com/fonttools/fonttools/blob/main/Lib/fontTools/colorLib/builder.py)
+//! takes. Palette indices are those of the source palettes.
+
+use std::collections::BTreeMap;
+
+use fontdrasil::types::GlyphName;
+use fontir::{
+    error::BadSource,
+    ir::{Color, ColorLine, ColorStop, CompositeMode, ExtendMode, Paint},
+};
+use kurbo::{Affine, Point};
+use plist::{Dictionary, Value};
+
+const COLOR_PALETTES: &str = "com.github.googlei18n.ufo2ft.colorPalettes";
+const COLOR_LAYERS: &str = "com.github.googlei18n.ufo2ft.colorLayers";
+const COLOR_GLYPHS: &str = "com.github.googlei18n.ufo2ft.colorGlyphs";
+
+fn bad_lib(message: impl Into<String>) -> BadSource {
+    BadSource::custom("lib.plist", message.into())
+}
+
+fn number(value: &Value) -> Option<f64> {
+    value
+        .as_real()
+        .or_else(|| value.as_signed_integer().map(|v| v as f64))
+}
+
+/// The color palettes of a lib, empty if it has none
+pub(crate) fn color_palettes(lib: &Dictionary) -> Result<Vec<Vec<Color>>, BadSource> {
+    let Some(raw_palettes) = lib.get(COLOR_PALETTES) else {
+        return Ok(Vec::new());
+    };
+    let not_palettes = || bad_lib(format!("'{COLOR_PALETTES}' is not a list of palettes"));
+    raw_palettes
+        .as_array()
+        .ok_or_else(not_palettes)?
+        .iter()
+        .map(|palette| {
+            palette
+                .as_array()
+                .ok_or_else(not_palettes)?
+                .iter()
+                .map(|color| {
+                    let rgba = color
+                        .as_array()
+                        .map(|c| c.iter().map(number).collect::<Option<Vec<_>>>())
+                        .unwrap_or_default();
+                    let Some([r, g, b, a]) = rgba.as_deref() else {
+                        return Err(bad_lib(format!(
+                            "'{COLOR_PALETTES}' has a color that isn't [r, g, b, a]"
+                        )));
+                    };
+                    let channel = |v: &f64| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+                    Ok(Color {
+                        r: channel(r),
+                        g: channel(g),
+                        b: channel(b),
+                        a: channel(a),
+                    })
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// The paint of every color glyph of a lib, by base glyph name
+pub(crate) fn color_glyphs(lib: &Dictionary) -> Result<BTreeMap<GlyphName, Paint>, BadSource> {
+    let mut color_glyphs = BTreeMap::new();
+    // A glyph with both layers and a paint takes the paint
+    for key in [COLOR_LAYERS, COLOR_GLYPHS] {
+        let Some(raw_glyphs) = lib.get(key) else {
+            continue;
+        };
+        let raw_glyphs = raw_glyphs
+            .as_dictionary()
+            .ok_or_else(|| bad_lib(format!("'{key}' is not a dictionary")))?;
+        for (glyph_name, raw_paint) in raw_glyphs {
+            let paint = match raw_paint {
+                Value::Array(layers) => to_ir_layers(glyph_name, layers)?,
+                Value::Dictionary(paint) => PaintDict::new(glyph_name, paint).to_ir_paint()?,
+                _ => {
+                    return Err(bad_lib(format!(
+                        "'{key}' has neither layers nor a paint for '{glyph_name}'"
+                    )))
+                }
+            };
+            color_glyphs.insert(GlyphName::new(glyph_name), paint);
+        }
+    }
+    Ok(color_glyphs)
+}
+
+/// Layers are COLRv0 (glyph name, palette index) pairs or paints
+fn to_ir_layers(glyph_name: &str, layers: &[Value]) -> Result<Paint, BadSource> {
+    layers
+        .iter()
+        .map(|layer| match layer {
+            Value::Dictionary(paint) => PaintDict::new(glyph_name, paint).to_ir_paint(),
+            Value::Array(pair) => match pair.as_slice() {
+                [Value::String(name), palette_index] => Ok(Paint::Glyph {
+                    name: GlyphName::new(name),
+                    paint: Box::new(Paint::Solid {
+                        palette_index: palette_index
+                            .as_unsigned_integer()
+                            .and_then(|i| u16::try_from(i).ok())
+                            .ok_or_else(|| {
+                                bad_lib(format!("'{glyph_name}' has a bad palette index"))
+                            })?,
+                        alpha: 1.0,
+                    }),
+                }),
+                _ => Err(bad_lib(format!(
+                    "'{glyph_name}' has a layer that isn't a glyph name and palette index"
+                ))),
+            },
+            _ => Err(bad_lib(format!(
+                "'{glyph_name}' has a layer that isn't a paint"
+            ))),
+        })
+        .collect::<Result<_, _>>()
+        .map(Paint::Layers)
+}
+
+/// A paint as a dictionary keyed by the field names of the COLR paint tables
+struct PaintDict<'a> {
+    glyph_name: &'a str,
+    dict: &'a Dictionary,
+}
+
+impl<'a> PaintDict<'a> {
+    fn new(glyph_name: &'a str, dict: &'a Dictionary) -> Self {
+        PaintDict { glyph_name, dict }
+    }
+
+    fn error(&self, message: impl std::fmt::Display) -> BadSource {
+        bad_lib(format!("color glyph '{}': {message}", self.glyph_name))
+    }
+
+    fn number(&self, key: &str) -> Result<f64, BadSource> {
+        self.dict
+            .get(key)
+            .and_then(number)
+            .ok_or_else(|| self.error(format_args!("'{key}' must be a number")))
+    }
+
+    fn number_or(&self, key: &str, default: f64) -> Result<f64, BadSource> {
+        match self.dict.get(key) {
+            Some(_) => self.number(key),
+            None => Ok(default),
+        }
+    }
+
+    fn point(&self, x: &str, y: &str) -> Result<Point, BadSource> {
+        Ok(Point::new(self.number(x)?, self.number(y)?))
+    }
+
+    fn string(&self, key: &str) -> Result<&'a str, BadSource> {
+        self.dict
+            .get(key)
+            .and_then(Value::as_string)
+            .ok_or_else(|| self.error(format_args!("'{key}' must be a string")))
+    }
+
+    fn dict(&self, key: &str) -> Result<PaintDict<'a>, BadSource> {
+        self.dict
+            .get(key)
+            .and_then(Value::as_dictionary)
+            .map(|dict| PaintDict::new(self.glyph_name, dict))
+            .ok_or_else(|| self.error(format_args!("'{key}' must be a dictionary")))
+    }
+
+    fn palette_index(&self) -> Result<u16, BadSource> {
+        self.dict
+            .get("PaletteIndex")
+            .and_then(Value::as_unsigned_integer)
+            .and_then(|i| u16::try_from(i).ok())
+            .ok_or_else(|| self.error("'PaletteIndex' must be a palette index"))
+    }
+
+    fn paint(&self, key: &str) -> Result<Box<Paint>, BadSource> {
+        self.dict(key)?.to_ir_paint().map(Box::new)
+    }
+
+    fn color_line(&self) -> Result<ColorLine, BadSource> {
+        let color_line = self.dict("ColorLine")?;
+        let extend = match color_line.dict.get("Extend").map(Value::as_string) {
+            None => ExtendMode::Pad,
+            Some(Some(extend)) if extend.eq_ignore_ascii_case("pad") => ExtendMode::Pad,
+            Some(Some(extend)) if extend.eq_ignore_ascii_case("repeat") => ExtendMode::Repeat,
+            Some(Some(extend)) if extend.eq_ignore_ascii_case("reflect") => ExtendMode::Reflect,
+            Some(_) => return Err(self.error("'Extend' must be pad, repeat or reflect")),
+        };
+        let stops = color_line
+            .dict
+            .get("ColorStop")
+            .and_then(Value::as_array)
+            .ok_or_else(|| self.error("'ColorStop' must be a list"))?
+            .iter()
+            .map(|stop| {
+                let stop = stop
+                    .as_dictionary()
+                    .map(|dict| PaintDict::new(self.glyph_name, dict))
+                    .ok_or_else(|| self.error("color stops must be dictionaries"))?;
+                Ok(ColorStop {
+                    offset: stop.number("StopOffset")?,
+                    palette_index: stop.palette_index()?,
+                    alpha: stop.number_or("Alpha", 1.0)?,
+                })
+            })
+            .collect::<Result<_, BadSource>>()?;
+        Ok(ColorLine { extend, stops })
+    }
+
+    fn composite_mode(&self) -> Result<CompositeMode, BadSource> {
+        let mode = self.string("CompositeMode")?;
+        Ok(match mode.to_ascii_lowercase().as_str() {
+            "clear" => CompositeMode::Clear,
+            "src" => CompositeMode::Src,
+            "dest" => CompositeMode::Dest,
+            "src_over" => CompositeMode::SrcOver,
+            "dest_over" => CompositeMode::DestOver,
+            "src_in" => CompositeMode::SrcIn,
+            "dest_in" => CompositeMode::DestIn,
+            "src_out" => CompositeMode::SrcOut,
+            "dest_out" => CompositeMode::DestOut,
+            "src_atop" => CompositeMode::SrcAtop,
+            "dest_atop" => CompositeMode::DestAtop,
+            "xor" => CompositeMode::Xor,
+            "plus" => CompositeMode::Plus,
+            "screen" => CompositeMode::Screen,
+            "overlay" => CompositeMode::Overlay,
+            "darken" => CompositeMode::Darken,
+            "lighten" => CompositeMode::Lighten,
+            "color_dodge" => CompositeMode::ColorDodge,
+            "color_burn" => CompositeMode::ColorBurn,
+            "hard_light" => CompositeMode::HardLight,
+            "soft_light" => CompositeMode::SoftLight,
+            "difference" => CompositeMode::Difference,
+            "exclusion" => CompositeMode::Exclusion,
+            "multiply" => CompositeMode::Multiply,
+            "hsl_hue" => CompositeMode::HslHue,
+            "hsl_saturation" => CompositeMode::HslSaturation,
+            "hsl_color" => CompositeMode::HslColor,
+            "hsl_luminosity" => CompositeMode::HslLuminosity,
+            _ => return Err(self.error(format_args!("unknown composite mode '{mode}'"))),
+        })
+    }
+
+    /// Variable formats take the same fields as their static counterparts, the
+    /// variation comes from the paints of each source.
+    fn to_ir_paint(&self) -> Result<Paint, BadSource> {
+        let format = self
+            .dict
+            .get("Format")
+            .and_then(Value::as_unsigned_integer)
+            .ok_or_else(|| self.error("a paint must have a 'Format'"))?;
+        let paint = match format {
+            1 => to_ir_layers(
+                self.glyph_name,
+                self.dict
+                    .get("Layers")
+                    .and_then(Value::as_array)
+                    .ok_or_else(|| self.error("'Layers' must be a list"))?,
+            )?,
+            2 | 3 => Paint::Solid {
+                palette_index: self.palette_index()?,
+                alpha: self.number_or("Alpha", 1.0)?,
+            },
+            4 | 5 => Paint::LinearGradient {
+                color_line: self.color_line()?,
+                p0: self.point("x0", "y0")?,
+                p1: self.point("x1", "y1")?,
+                p2: self.point("x2", "y2")?,
+            },
+            6 | 7 => Paint::RadialGradient {
+                color_line: self.color_line()?,
+                c0: self.point("x0", "y0")?,
+                r0: self.number("r0")?,
+                c1: self.point("x1", "y1")?,
+                r1: self.number("r1")?,
+            },
+            8 | 9 => Paint::SweepGradient {
+                color_line: self.color_line()?,
+                center: self.point("centerX", "centerY")?,
+                start_angle: self.number("startAngle")?,
+                end_angle: self.number("endAngle")?,
+            },
+            10 => Paint::Glyph {
+                name: GlyphName::new(self.string("Glyph")?),
+                paint: self.paint("Paint")?,
+            },
+            11 => Paint::ColrGlyph(GlyphName::new(self.string("Glyph")?)),
+            12 | 13 => {
+                let transform = self.dict("Transform")?;
+                Paint::Transform {
+                    transform: Affine::new(
+                        ["xx", "yx", "xy", "yy", "dx", "dy"]
+                            .map(|key| transform.number(key))
+                            .into_iter()
+                            .collect::<Result<Vec<_>, _>>()?
+                            .try_into()
+                            .unwrap(),
+                    ),
+                    paint: self.paint("Paint")?,
+                }
+            }
+            14 | 15 => Paint::Translate {
+                dx: self.number("dx")?,
+                dy: self.number("dy")?,
+                paint: self.paint("Paint")?,
+            },
+            16..=23 => {
+                let (scale_x, scale_y) = if format >= 20 {
+                    let scale = self.number("scale")?;
+                    (scale, scale)
+                } else {
+                    (self.number("scaleX")?, self.number("scaleY")?)
+                };
+                Paint::Scale {
+                    scale_x,
+                    scale_y,
+                    center: self.center(matches!(format, 18 | 19 | 22 | 23))?,
+                    paint: self.paint("Paint")?,
+                }
+            }
+            24..=27 => Paint::Rotate {
+                angle: self.number("angle")?,
+                center: self.center(format >= 26)?,
+                paint: self.paint("Paint")?,
+            },
+            28..=31 => Paint::Skew {
+                x_skew_angle: self.number("xSkewAngle")?,
+                y_skew_angle: self.number("ySkewAngle")?,
+                center: self.center(format >= 30)?,
+                paint: self.paint("Paint")?,
+            },
+            32 => Paint::Composite {
+                source: self.paint("SourcePaint")?,
+                mode: self.composite_mode()?,
+                backdrop: self.paint("BackdropPaint")?,
+            },
+            _ => return Err(self.error(format_args!("unknown paint format {format}"))),
+        };
+        Ok(paint)
+    }
+
+    fn center(&self, around_center: bool) -> Result<Option<Point>, BadSource> {
+        around_center
+            .then(|| self.point("centerX", "centerY"))
+            .transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lib(xml: &str) -> Dictionary {
+        let xml = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0"><dict>{xml}</dict></plist>"#
+        );
+        Value::from_reader_xml(xml.as_bytes())
+            .unwrap()
+            .into_dictionary()
+            .unwrap()
+    }
+
+    #[test]
+    fn palettes() {
+        let lib = lib(r#"
+            <key>com.github.googlei18n.ufo2ft.colorPalettes</key>
+            <array>
+                <array>
+                    <array><real>1</real><real>0</real><real>0</real><real>1</real></array>
+                    <array><integer>0</integer><real>0.5</real><integer>1</integer><real>0.25</real></array>
+                </array>
+            </array>"#);
+        assert_eq!(
+            vec![vec![
+                Color {
+                    r: 255,
+                    g: 0,
+                    b: 0,
+                    a: 255
+                },
+                Color {
+                    r: 0,
+                    g: 128,
+                    b: 255,
+                    a: 64
+                }
+            ]],
+            color_palettes(&lib).unwrap()
+        );
+    }
+
+    #[test]
+    fn colrv0_layers() {
+        let lib = lib(r#"
+            <key>com.github.googlei18n.ufo2ft.colorLayers</key>
+            <dict>
+                <key>A</key>
+                <array>
+                    <array><string>A.0</string><integer>1</integer></array>
+                    <array><string>A.1</string><integer>65535</integer></array>
+                </array>
+            </dict>"#);
+        let solid = |name: &str, palette_index| Paint::Glyph {
+            name: name.into(),
+            paint: Box::new(Paint::Solid {
+                palette_index,
+                alpha: 1.0,
+            }),
+        };
+        assert_eq!(
+            BTreeMap::from([(
+                GlyphName::new("A"),
+                Paint::Layers(vec![solid("A.0", 1), solid("A.1", 0xFFFF)])
+            )]),
+            color_glyphs(&lib).unwrap()
+        );
+    }
+
+    #[test]
+    fn colrv1_paints() {
+        let lib = lib(r#"
+            <key>com.github.googlei18n.ufo2ft.colorGlyphs</key>
+            <dict>
+                <key>A</key>
+                <dict>
+                    <key>Format</key><integer>1</integer>
+                    <key>Layers</key>
+                    <array>
+                        <dict>
+                            <key>Format</key><integer>10</integer>
+                            <key>Glyph</key><string>A.0</string>
+                            <key>Paint</key>
+                            <dict>
+                                <key>Format</key><integer>4</integer>
+                                <key>ColorLine</key>
+                                <dict>
+                                    <key>Extend</key><string>reflect</string>
+                                    <key>ColorStop</key>
+                                    <array>
+                                        <dict>
+                                            <key>StopOffset</key><real>0</real>
+                                            <key>PaletteIndex</key><integer>0</integer>
+                                        </dict>
+                                        <dict>
+                                            <key>StopOffset</key><real>1</real>
+                                            <key>PaletteIndex</key><integer>1</integer>
+                                            <key>Alpha</key><real>0.5</real>
+                                        </dict>
+                                    </array>
+                                </dict>
+                                <key>x0</key><integer>0</integer>
+                                <key>y0</key><integer>0</integer>
+                                <key>x1</key><integer>100</integer>
+                                <key>y1</key><integer>0</integer>
+                                <key>x2</key><integer>0</integer>
+                                <key>y2</key><integer>100</integer>
+                            </dict>
+                        </dict>
+                        <dict>
+                            <key>Format</key><integer>26</integer>
+                            <key>angle</key><real>45</real>
+                            <key>centerX</key><integer>50</integer>
+                            <key>centerY</key><integer>60</integer>
+                            <key>Paint</key>
+                            <dict>
+                                <key>Format</key><integer>11</integer>
+                                <key>Glyph</key><string>B</string>
+                            </dict>
+                        </dict>
+                    </array>
+                </dict>
+            </dict>"#);
+        assert_eq!(
+            BTreeMap::from([(
+                GlyphName::new("A"),
+                Paint::Layers(vec![
+                    Paint::Glyph {
+                        name: "A.0".into(),
+                        paint: Box::new(Paint::LinearGradient {
+                            color_line: ColorLine {
+                                extend: ExtendMode::Reflect,
+                                stops: vec![
+                                    ColorStop {
+                                        offset: 0.0,
+                                        palette_index: 0,
+                                        alpha: 1.0
+                                    },
+                                    ColorStop {
+                                        offset: 1.0,
+                                        palette_index: 1,
+                                        alpha: 0.5
+                                    }
+                                ]
+                            },
+                            p0: Point::new(0.0, 0.0),
+                            p1: Point::new(100.0, 0.0),
+                            p2: Point::new(0.0, 100.0),
+                        })
+                    },
+                    Paint::Rotate {
+                        angle: 45.0,
+                        center: Some(Point::new(50.0, 60.0)),
+                        paint: Box::new(Paint::ColrGlyph("B".into())),
+                    }
+                ])
+            )]),
+            color_glyphs(&lib).unwrap()
+        );
+    }
+
+    #[test]
+    fn unknown_format() {
+        let lib = lib(r#"
+            <key>com.github.googlei18n.ufo2ft.colorGlyphs</key>
+            <dict>
+                <key>A</key>
+                <dict><key>Format</key><integer>99</integer></dict>
+            </dict>"#);
+        assert!(color_glyphs(&lib).is_err());
+    }
+}
@@ -0,0 +1,570 @@
+//! Designspace 5 elements norad doesn't read yet, so we pick them out of the designspace ourselves.
+//!
+//! These are the [labels](https://fonttools.readthedocs.io/en/latest/designspaceLib/xml.html#labels-element-axis),
+//! which name positions in the designspace for the STAT table, the 5.1
+//! [mappings](https://fonttools.readthedocs.io/en/latest/designspaceLib/xml.html#mappings-element)
+//! between locations that become avar 2, and the
+//! [variable fonts](https://fonttools.readthedocs.io/en/latest/designspaceLib/xml.html#variable-fonts-element)
+//! built from parts of the designspace.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::Path,
+};
+
+use fontdrasil::{
+    coords::{DesignCoord, NormalizedLocation, UserCoord},
+    types::Axis,
+};
+use fontir::{
+    error::{BadSource, BadSourceKind, Error},
+    ir::{AxisMapping, AxisValueLabel, AxisValueLocation, StatLabels},
+};
+use serde::Deserialize;
+
+#[derive(Debug, Default, Deserialize)]
+struct DesignSpace5 {
+    #[serde(default)]
+    axes: Axes,
+    #[serde(default)]
+    labels: LocationLabels,
+    #[serde(rename = "variable-fonts", default)]
+    variable_fonts: VariableFonts,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Axes {
+    #[serde(rename = "@elidedfallbackname")]
+    elided_fallback_name: Option<String>,
+    #[serde(default)]
+    mappings: Mappings,
+    #[serde(rename = "axis", default)]
+    axes: Vec<LabeledAxis>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Mappings {
+    #[serde(rename = "mapping", default)]
+    mappings: Vec<Mapping>,
+}
+
+/// <https://fonttools.readthedocs.io/en/latest/designspaceLib/xml.html#mapping-element>
+#[derive(Debug, Deserialize)]
+struct Mapping {
+    input: DesignLocation,
+    output: DesignLocation,
+}
+
+#[derive(Debug, Deserialize)]
+struct DesignLocation {
+    #[serde(rename = "dimension", default)]
+    dimensions: Vec<DesignDimension>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DesignDimension {
+    #[serde(rename = "@name")]
+    name: String,
+    #[serde(rename = "@xvalue")]
+    value: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct LabeledAxis {
+    #[serde(rename = "@name")]
+    name: String,
+    #[serde(rename = "@tag")]
+    tag: String,
+    /// Space separated, only present for discrete axes
+    #[serde(rename = "@values")]
+    values: Option<String>,
+    #[serde(default)]
+    labels: AxisLabels,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct VariableFonts {
+    #[serde(rename = "variable-font", default)]
+    variable_fonts: Vec<RawVariableFont>,
+}
+
+/// <https://fonttools.readthedocs.io/en/latest/designspaceLib/xml.html#variable-font-element>
+#[derive(Debug, Deserialize)]
+struct RawVariableFont {
+    #[serde(rename = "@name")]
+    name: String,
+    #[serde(rename = "@filename")]
+    filename: Option<String>,
+    #[serde(rename = "axis-subsets", default)]
+    axis_subsets: AxisSubsets,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct AxisSubsets {
+    #[serde(rename = "axis-subset", default)]
+    axis_subsets: Vec<RawAxisSubset>,
+}
+
+/// <https://fonttools.readthedocs.io/en/latest/designspaceLib/xml.html#axis-subset-element>
+#[derive(Debug, Deserialize)]
+struct RawAxisSubset {
+    #[serde(rename = "@name")]
+    name: String,
+    #[serde(rename = "@userminimum")]
+    user_minimum: Option<f64>,
+    #[serde(rename = "@userdefault")]
+    user_default: Option<f64>,
+    #[serde(rename = "@usermaximum")]
+    user_maximum: Option<f64>,
+    #[serde(rename = "@uservalue")]
+    user_value: Option<f64>,
+}
+
+/// A variable font built from part of a designspace.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct VariableFontDescriptor {
+    pub(crate) name: String,
+    pub(crate) filename: Option<String>,
+    /// Axis name => the part of it the font covers, axes not present are pinned at their default
+    pub(crate) axis_subsets: HashMap<String, AxisSubset>,
+}
+
+impl VariableFontDescriptor {
+    /// The name of the font file without extension, as fontmake chooses it
+    pub(crate) fn file_stem(&self) -> &str {
+        self.filename
+            .as_deref()
+            .and_then(|filename| Path::new(filename).file_stem())
+            .and_then(|stem| stem.to_str())
+            .unwrap_or(&self.name)
+    }
+}
+
+/// The part of an axis a variable font covers, in user coordinates
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum AxisSubset {
+    /// Unbounded ends extend to the ends of the axis, no default means the axis default
+    Range {
+        min: Option<UserCoord>,
+        default: Option<UserCoord>,
+        max: Option<UserCoord>,
+    },
+    /// The axis is pinned at a single value
+    Value(UserCoord),
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct AxisLabels {
+    #[serde(rename = "label", default)]
+    labels: Vec<AxisLabel>,
+}
+
+/// <https://fonttools.readthedocs.io/en/latest/designspaceLib/xml.html#label-element-axis-labels-label>
+#[derive(Debug, Deserialize)]
+struct AxisLabel {
+    #[serde(rename = "@name")]
+    name: String,
+    #[serde(rename = "@uservalue")]
+    user_value: f64,
+    #[serde(rename = "@userminimum")]
+    user_minimum: Option<f64>,
+    #[serde(rename = "@usermaximum")]
+    user_maximum: Option<f64>,
+    #[serde(rename = "@linkeduservalue")]
+    linked_user_value: Option<f64>,
+    #[serde(rename = "@elidable", default)]
+    elidable: bool,
+    #[serde(rename = "@oldersibling", default)]
+    older_sibling: bool,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct LocationLabels {
+    #[serde(rename = "label", default)]
+    labels: Vec<LocationLabel>,
+}
+
+/// <https://fonttools.readthedocs.io/en/latest/designspaceLib/xml.html#label-element-top-level-labels-label>
+#[derive(Debug, Deserialize)]
+struct LocationLabel {
+    #[serde(rename = "@name")]
+    name: String,
+    #[serde(rename = "@elidable", default)]
+    elidable: bool,
+    #[serde(rename = "@oldersibling", default)]
+    older_sibling: bool,
+    location: LabelLocation,
+}
+
+#[derive(Debug, Deserialize)]
+struct LabelLocation {
+    #[serde(rename = "dimension", default)]
+    dimensions: Vec<Dimension>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Dimension {
+    #[serde(rename = "@name")]
+    name: String,
+    #[serde(rename = "@uservalue")]
+    user_value: f64,
+}
+
+fn bad_source(path: &Path, message: String) -> Error {
+    Error::BadSource(BadSource::new(
+        path.to_path_buf(),
+        BadSourceKind::Custom(message),
+    ))
+}
+
+fn read(path: &Path) -> Result<DesignSpace5, Error> {
+    let xml = fs::read_to_string(path)
+        .map_err(|e| Error::BadSource(BadSource::new(path.to_path_buf(), BadSourceKind::Io(e))))?;
+    quick_xml::de::from_str(&xml).map_err(|e| bad_source(path, e.to_string()))
+}
+
+/// Read the STAT labels of the designspace at `path`.
+///
+/// Matches fontTools
+/// [getStatAxes and getStatLocations](https://github.com/fonttools/fonttools/blob/main/Lib/fontTools/designspaceLib/statNames.py),
+/// axes missing from a location label are at their default.
+pub(crate) fn stat_labels(path: &Path, axes: &[Axis]) -> Result<StatLabels, Error> {
+    let raw = read(path)?;
+    let axis_named = |name: &str| {
+        axes.iter()
+            .find(|axis| axis.name == name)
+            .ok_or_else(|| bad_source(path, format!("label refers to undefined axis '{name}'")))
+    };
+
+    let mut axis_values = Vec::new();
+    for raw_axis in raw.axes.axes.iter() {
+        let axis = axis_named(&raw_axis.name)?;
+        for label in raw_axis.labels.labels.iter() {
+            let value = UserCoord::new(label.user_value);
+            let value = if label.linked_user_value.is_some() {
+                AxisValueLocation::Point {
+                    axis: axis.tag,
+                    value,
+                    linked_value: label.linked_user_value.map(UserCoord::new),
+                }
+            } else if label.user_minimum.is_some() || label.user_maximum.is_some() {
+                AxisValueLocation::Range {
+                    axis: axis.tag,
+                    nominal: value,
+                    min: label.user_minimum.map(UserCoord::new),
+                    max: label.user_maximum.map(UserCoord::new),
+                }
+            } else {
+                AxisValueLocation::Point {
+                    axis: axis.tag,
+                    value,
+                    linked_value: None,
+                }
+            };
+            axis_values.push(AxisValueLabel {
+                name: label.name.clone(),
+                elidable: label.elidable,
+                older_sibling: label.older_sibling,
+                value,
+            });
+        }
+    }
+
+    for label in raw.labels.labels.iter() {
+        let mut location: Vec<_> = axes.iter().map(|axis| (axis.tag, axis.default)).collect();
+        for dimension in label.location.dimensions.iter() {
+            let tag = axis_named(&dimension.name)?.tag;
+            let (_, value) = location.iter_mut().find(|(t, _)| *t == tag).unwrap();
+            *value = UserCoord::new(dimension.user_value);
+        }
+        axis_values.push(AxisValueLabel {
+            name: label.name.clone(),
+            elidable: label.elidable,
+            older_sibling: label.older_sibling,
+            value: AxisValueLocation::Location(location.into_iter().collect()),
+        });
+    }
+
+    Ok(StatLabels {
+        axis_values,
+        elided_fallback_name: raw.axes.elided_fallback_name,
+    })
+}
+
+/// Read the axis mappings of the designspace at `path`, normalized on `axes`.
+///
+/// Like fontTools, mapping locations are normalized with the per-axis mapping
+/// so they sit in the space avar 1 produces. Point axes, such as those pinned for one
+/// of several [variable fonts](variable_fonts), don't vary so mappings whose input is
+/// elsewhere on them never apply and are dropped.
+pub(crate) fn axis_mappings(path: &Path, axes: &[Axis]) -> Result<Vec<AxisMapping>, Error> {
+    let raw = read(path)?;
+    // None if the location is off a point axis, which only matters for inputs
+    let normalize = |location: &DesignLocation, is_input: bool| {
+        let mut normalized = NormalizedLocation::new();
+        for dimension in location.dimensions.iter() {
+            let axis = axes
+                .iter()
+                .find(|axis| axis.name == dimension.name)
+                .ok_or_else(|| {
+                    bad_source(
+                        path,
+                        format!("mapping refers to undefined axis '{}'", dimension.name),
+                    )
+                })?;
+            let value = DesignCoord::new(dimension.value);
+            if axis.is_point() {
+                if is_input && value != axis.default.to_design(&axis.converter) {
+                    return Ok(None);
+                }
+                continue;
+            }
+            normalized.insert(axis.tag, value.to_normalized(&axis.converter));
+        }
+        Ok::<_, Error>(Some(normalized))
+    };
+    let mut mappings = Vec::new();
+    for mapping in raw.axes.mappings.mappings.iter() {
+        let Some(input) = normalize(&mapping.input, true)? else {
+            continue;
+        };
+        // the output on a point axis can only be where it already is
+        let output = normalize(&mapping.output, false)?.unwrap_or_default();
+        mappings.push(AxisMapping { input, output });
+    }
+    Ok(mappings)
+}
+
+/// The values of the discrete axes of the designspace at `path`, by axis name.
+pub(crate) fn discrete_axes(path: &Path) -> Result<BTreeMap<String, Vec<UserCoord>>, Error> {
+    let raw = read(path)?;
+    raw.axes
+        .axes
+        .iter()
+        .filter_map(|axis| axis.values.as_ref().map(|values| (axis, values)))
+        .map(|(axis, values)| {
+            let values = values
+                .split_ascii_whitespace()
+                .map(|value| {
+                    value.parse::<f64>().map(UserCoord::new).map_err(|e| {
+                        bad_source(path, format!("bad value for axis '{}': {e}", axis.name))
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            if values.is_empty() {
+                return Err(bad_source(
+                    path,
+                    format!("discrete axis '{}' has no values", axis.name),
+                ));
+            }
+            Ok((axis.name.clone(), values))
+        })
+        .collect()
+}
+
+/// The variable fonts of the designspace at `path`.
+///
+/// Without a `<variable-fonts>` element there is a variable font for each combination
+/// of discrete axis values, as in fontTools
+/// [getVariableFonts](https://github.com/fonttools/fonttools/blob/main/Lib/fontTools/designspaceLib/__init__.py).
+/// Empty if the designspace has neither, it is a single font.
+pub(crate) fn variable_fonts(path: &Path) -> Result<Vec<VariableFontDescriptor>, Error> {
+    let raw = read(path)?;
+    if !raw.variable_fonts.variable_fonts.is_empty() {
+        return Ok(raw
+            .variable_fonts
+            .variable_fonts
+            .into_iter()
+            .map(|vf| {
+                let axis_subsets = vf
+                    .axis_subsets
+                    .axis_subsets
+                    .into_iter()
+                    .map(|subset| {
+                        let value = match subset.user_value {
+                            Some(value) => AxisSubset::Value(UserCoord::new(value)),
+                            None => AxisSubset::Range {
+                                min: subset.user_minimum.map(UserCoord::new),
+                                default: subset.user_default.map(UserCoord::new),
+                                max: subset.user_maximum.map(UserCoord::new),
+                            },
+                        };
+                        (subset.name, value)
+                    })
+                    .collect();
+                VariableFontDescriptor {
+                    name: vf.name,
+                    filename: vf.filename,
+                    axis_subsets,
+                }
+            })
+            .collect());
+    }
+
+    let discrete_axes = discrete_axes(path)?;
+    if discrete_axes.is_empty() {
+        return Ok(Vec::new());
+    }
+    let basename = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("VF");
+    // every combination of discrete values, in axis order
+    let mut combinations: Vec<Vec<(&LabeledAxis, UserCoord)>> = vec![Vec::new()];
+    for axis in raw.axes.axes.iter() {
+        let Some(values) = discrete_axes.get(&axis.name) else {
+            continue;
+        };
+        combinations = combinations
+            .into_iter()
+            .flat_map(|combination| {
+                values.iter().map(move |value| {
+                    let mut combination = combination.clone();
+                    combination.push((axis, *value));
+                    combination
+                })
+            })
+            .collect();
+    }
+    Ok(combinations
+        .into_iter()
+        .map(|combination| {
+            let suffix: String = combination
+                .iter()
+                .map(|(axis, value)| format!("-{}{}", axis.tag, value.to_f64()))
+                .collect();
+            let mut axis_subsets: HashMap<_, _> = raw
+                .axes
+                .axes
+                .iter()
+                .filter(|axis| !discrete_axes.contains_key(&axis.name))
+                .map(|axis| {
+                    (
+                        axis.name.clone(),
+                        AxisSubset::Range {
+                            min: None,
+                            default: None,
+                            max: None,
+                        },
+                    )
+                })
+                .collect();
+            axis_subsets.extend(
+                combination
+                    .iter()
+                    .map(|(axis, value)| (axis.name.clone(), AxisSubset::Value(*value))),
+            );
+            VariableFontDescriptor {
+                name: format!("{basename}-VF{suffix}"),
+                filename: None,
+                axis_subsets,
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use fontdrasil::coords::{NormalizedLocation, UserCoord};
+    use fontir::ir::{AxisMapping, AxisValueLocation, StatLabels};
+    use write_fonts::types::Tag;
+
+    use crate::toir::to_ir_axes;
+
+    use super::{axis_mappings, stat_labels};
+
+    fn load(name: &str) -> StatLabels {
+        let path = Path::new("../resources/testdata").join(name);
+        let designspace = norad::designspace::DesignSpaceDocument::load(&path).unwrap();
+        let axes = to_ir_axes(&designspace.axes).unwrap();
+        stat_labels(&path, &axes).unwrap()
+    }
+
+    fn load_mappings(name: &str) -> Vec<AxisMapping> {
+        let path = Path::new("../resources/testdata").join(name);
+        let designspace = norad::designspace::DesignSpaceDocument::load(&path).unwrap();
+        let axes = to_ir_axes(&designspace.axes).unwrap();
+        axis_mappings(&path, &axes).unwrap()
+    }
+
+    #[test]
+    fn reads_axis_and_location_labels() {
+        let labels = load("wght_var_stat.designspace");
+        assert_eq!(Some("Regular"), labels.elided_fallback_name.as_deref());
+
+        let wght = Tag::new(b"wght");
+        let actual: Vec<_> = labels
+            .axis_values
+            .iter()
+            .map(|label| (label.name.as_str(), label.elidable, label.value.clone()))
+            .collect();
+        assert_eq!(
+            vec![
+                (
+                    "Regular",
+                    true,
+                    AxisValueLocation::Point {
+                        axis: wght,
+                        value: UserCoord::new(400.0),
+                        linked_value: Some(UserCoord::new(700.0)),
+                    }
+                ),
+                (
+                    "Semibold",
+                    false,
+                    AxisValueLocation::Range {
+                        axis: wght,
+                        nominal: UserCoord::new(600.0),
+                        min: Some(UserCoord::new(550.0)),
+                        max: None,
+                    }
+                ),
+                (
+                    "Bold",
+                    false,
+                    AxisValueLocation::Point {
+                        axis: wght,
+                        value: UserCoord::new(700.0),
+                        linked_value: None,
+                    }
+                ),
+                (
+                    "Bold Special",
+                    false,
+                    AxisValueLocation::Location(vec![(wght, UserCoord::new(650.0))].into()),
+                ),
+            ],
+            actual
+        );
+    }
+
+    #[test]
+    fn no_labels() {
+        assert_eq!(StatLabels::default(), load("wght_var.designspace"));
+    }
+
+    #[test]
+    fn reads_normalized_axis_mappings() {
+        assert_eq!(
+            vec![
+                AxisMapping {
+                    input: NormalizedLocation::for_pos(&[("wght", 0.5)]),
+                    output: NormalizedLocation::for_pos(&[("wght", 0.75)]),
+                },
+                AxisMapping {
+                    input: NormalizedLocation::for_pos(&[("wght", 1.0)]),
+                    output: NormalizedLocation::for_pos(&[("wght", 1.0), ("XOPQ", 1.0)]),
+                },
+            ],
+            load_mappings("wght_var_avar2.designspace")
+        );
+    }
+
+    #[test]
+    fn no_mappings() {
+        assert!(load_mappings("wght_var.designspace").is_empty());
+    }
+}
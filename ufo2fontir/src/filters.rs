@@ -0,0 +1,207 @@
+//! Parse the [ufo2ft filters](https://github.com/googlefonts/ufo2ft/tree/main/Lib/ufo2ft/filters)
+//! a UFO declares in its lib.
+
+use std::collections::BTreeSet;
+
+use fontdrasil::types::GlyphName;
+use fontir::{
+    error::BadSource,
+    ir::{GlyphFilter, GlyphFilterKind, GlyphSelection, TransformationOrigin, Transformations},
+};
+use log::warn;
+use ordered_float::OrderedFloat;
+
+const UFO2FT_FILTERS: &str = "com.github.googlei18n.ufo2ft.filters";
+
+fn bad_filters(message: impl Into<String>) -> BadSource {
+    BadSource::custom(
+        "lib.plist",
+        format!("bad '{UFO2FT_FILTERS}': {}", message.into()),
+    )
+}
+
+/// The filters in lib_plist, in the order they run in.
+///
+/// Filters we don't know how to run are skipped with a warning.
+pub(crate) fn glyph_filters(lib_plist: &plist::Dictionary) -> Result<Vec<GlyphFilter>, BadSource> {
+    let Some(raw_filters) = lib_plist.get(UFO2FT_FILTERS) else {
+        return Ok(Vec::new());
+    };
+    let raw_filters = raw_filters
+        .as_array()
+        .ok_or_else(|| bad_filters("not an array"))?;
+
+    let mut filters = Vec::new();
+    for raw_filter in raw_filters {
+        let raw_filter = raw_filter
+            .as_dictionary()
+            .ok_or_else(|| bad_filters("filter is not a dictionary"))?;
+        let name = raw_filter
+            .get("name")
+            .and_then(|name| name.as_string())
+            .ok_or_else(|| bad_filters("filter has no name"))?;
+        if let Some(namespace) = raw_filter
+            .get("namespace")
+            .and_then(|namespace| namespace.as_string())
+            .filter(|namespace| *namespace != "ufo2ft.filters")
+        {
+            warn!("Ignoring filter '{name}' from unsupported namespace '{namespace}'");
+            continue;
+        }
+        let kwargs = match raw_filter.get("kwargs") {
+            Some(kwargs) => kwargs
+                .as_dictionary()
+                .ok_or_else(|| bad_filters(format!("kwargs of '{name}' is not a dictionary")))?
+                .clone(),
+            None => Default::default(),
+        };
+        // ufo2ft accepts names like "decomposeComponents" or "DecomposeComponentsFilter"
+        let normalized_name = name.replace(' ', "").to_ascii_lowercase();
+        let kind = match normalized_name
+            .strip_suffix("filter")
+            .unwrap_or(&normalized_name)
+        {
+            "decomposecomponents" => GlyphFilterKind::DecomposeComponents,
+            "decomposetransformedcomponents" => GlyphFilterKind::DecomposeTransformedComponents,
+            "flattencomponents" => GlyphFilterKind::FlattenComponents,
+            "propagateanchors" => GlyphFilterKind::PropagateAnchors,
+            "sortcontours" => GlyphFilterKind::SortContours,
+            "transformations" => GlyphFilterKind::Transformations(transformations(&kwargs)?),
+            _ => {
+                warn!("Ignoring unsupported filter '{name}'");
+                continue;
+            }
+        };
+        let pre = match raw_filter.get("pre") {
+            Some(pre) => pre
+                .as_boolean()
+                .ok_or_else(|| bad_filters(format!("pre of '{name}' is not a boolean")))?,
+            None => false,
+        };
+        // include and exclude may be given alongside the name or, like any argument, in kwargs
+        let glyph_list = |key: &str| -> Result<Option<BTreeSet<GlyphName>>, BadSource> {
+            let Some(raw) = raw_filter.get(key).or_else(|| kwargs.get(key)) else {
+                return Ok(None);
+            };
+            raw.as_array()
+                .and_then(|names| {
+                    names
+                        .iter()
+                        .map(|name| name.as_string().map(GlyphName::new))
+                        .collect::<Option<_>>()
+                })
+                .map(Some)
+                .ok_or_else(|| bad_filters(format!("{key} of '{name}' is not a list of names")))
+        };
+        let glyphs = match (glyph_list("include")?, glyph_list("exclude")?) {
+            (None, None) => GlyphSelection::All,
+            (Some(include), None) => GlyphSelection::Include(include),
+            (None, Some(exclude)) => GlyphSelection::Exclude(exclude),
+            (Some(_), Some(_)) => {
+                return Err(bad_filters(format!(
+                    "'{name}' has both include and exclude"
+                )));
+            }
+        };
+        filters.push(GlyphFilter { kind, pre, glyphs });
+    }
+    Ok(filters)
+}
+
+fn transformations(kwargs: &plist::Dictionary) -> Result<Transformations, BadSource> {
+    let number = |key: &str, default: f64| -> Result<OrderedFloat<f64>, BadSource> {
+        match kwargs.get(key) {
+            Some(value) => value
+                .as_real()
+                .or_else(|| value.as_signed_integer().map(|v| v as f64))
+                .map(OrderedFloat)
+                .ok_or_else(|| bad_filters(format!("transformations {key} is not a number"))),
+            None => Ok(default.into()),
+        }
+    };
+    let origin = match kwargs.get("Origin") {
+        Some(value) => value
+            .as_signed_integer()
+            .and_then(|v| TransformationOrigin::try_from(v).ok())
+            .ok_or_else(|| bad_filters(format!("transformations Origin {value:?} is invalid")))?,
+        None => Default::default(),
+    };
+    for key in kwargs.keys() {
+        if !matches!(
+            key.as_str(),
+            "OffsetX"
+                | "OffsetY"
+                | "ScaleX"
+                | "ScaleY"
+                | "Slant"
+                | "Origin"
+                | "include"
+                | "exclude"
+        ) {
+            warn!("Ignoring unsupported transformations argument '{key}'");
+        }
+    }
+    Ok(Transformations {
+        offset_x: number("OffsetX", 0.0)?,
+        offset_y: number("OffsetY", 0.0)?,
+        // ufo2ft scales are percentages
+        scale_x: number("ScaleX", 100.0)? / 100.0,
+        scale_y: number("ScaleY", 100.0)? / 100.0,
+        slant: number("Slant", 0.0)?,
+        origin,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    fn filters_in(ufo: &str) -> Vec<GlyphFilter> {
+        let lib_plist = plist::Value::from_file(
+            Path::new("../resources/testdata")
+                .join(ufo)
+                .join("lib.plist"),
+        )
+        .unwrap()
+        .into_dictionary()
+        .unwrap();
+        glyph_filters(&lib_plist).unwrap()
+    }
+
+    #[test]
+    fn parse_declared_filters() {
+        assert_eq!(
+            vec![
+                GlyphFilter {
+                    kind: GlyphFilterKind::DecomposeTransformedComponents,
+                    pre: true,
+                    glyphs: GlyphSelection::All,
+                },
+                GlyphFilter {
+                    kind: GlyphFilterKind::Transformations(Transformations {
+                        offset_x: 10.0.into(),
+                        scale_y: 0.5.into(),
+                        slant: 12.5.into(),
+                        origin: TransformationOrigin::XHeight,
+                        ..Default::default()
+                    }),
+                    pre: false,
+                    glyphs: GlyphSelection::Include(BTreeSet::from([GlyphName::new("A")])),
+                },
+                GlyphFilter {
+                    kind: GlyphFilterKind::SortContours,
+                    pre: false,
+                    glyphs: GlyphSelection::Exclude(BTreeSet::from([GlyphName::new("B")])),
+                },
+            ],
+            filters_in("Filters.ufo")
+        );
+    }
+
+    #[test]
+    fn no_filters() {
+        assert!(glyph_filters(&Default::default()).unwrap().is_empty());
+    }
+}
@@ -3,5 +3,9 @@
 //! [UFO]: http://unifiedfontobject.org
 //! [font IR]: https://docs.rs/fontir
 
+mod color;
+mod designspace5;
+mod filters;
 pub mod source;
 pub mod toir;
+mod variable_fonts;
@@ -1,5 +1,6 @@
 use std::{
     collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fs,
     path::{Path, PathBuf},
     str::FromStr,
     sync::Arc,
@@ -7,16 +8,20 @@ use std::{
 
 use chrono::{DateTime, NaiveDateTime, Utc};
 use fontdrasil::{
-    coords::{DesignLocation, NormalizedLocation, UserCoord},
+    coords::{DesignCoord, DesignLocation, NormalizedCoord, NormalizedLocation, UserCoord},
     orchestration::{Access, AccessBuilder, Work},
     types::GlyphName,
 };
 use fontir::{
     error::{BadSource, BadSourceKind, Error},
+    incremental::{Fingerprinter, Fingerprints},
     ir::{
-        AnchorBuilder, FeaturesSource, GdefCategories, GlobalMetric, GlobalMetrics, GlyphOrder,
-        KernGroup, KernSide, KerningGroups, KerningInstance, MetaTableValues, NameBuilder, NameKey,
-        NamedInstance, Panose, PostscriptNames, StaticMetadata, DEFAULT_VENDOR_ID,
+        AnchorBuilder, Color, ColorGlyph, ColorPalettes, Condition, ConditionSet,
+        ConditionalSubstitution, FeatureVariations, FeaturesSource, GdefCategories, GlobalMetric,
+        GlobalMetrics, GlyphOrder, KernGroup, KernSide, KerningGroups, KerningInstance,
+        MetaTableValues, NameBuilder, NameKey, NamedInstance, Paint, PaintGraph, Panose,
+        PostscriptHints, PostscriptNames, StaticMetadata, DEFAULT_VENDOR_ID,
+        FOREGROUND_PALETTE_INDEX,
     },
     orchestration::{Context, Flags, IrWork, WorkId},
     source::Source,
@@ -33,10 +38,17 @@ use write_fonts::{
     OtRound,
 };
 
-use crate::toir::{master_locations, to_design_location, to_ir_axes, to_ir_glyph};
+use crate::{
+    color::{color_glyphs, color_palettes},
+    designspace5::{axis_mappings, discrete_axes, stat_labels, variable_fonts},
+    filters::glyph_filters,
+    toir::{master_locations, to_design_location, to_ir_axes, to_ir_axis, to_ir_glyph},
+    variable_fonts::subset_designspace,
+};
 
 const UFO_KERN1_PREFIX: &str = "public.kern1.";
 const UFO_KERN2_PREFIX: &str = "public.kern2.";
+const FEATURE_VARS_FEATURE_TAG: &str = "com.github.fonttools.varLib.featureVarsFeatureTag";
 
 #[derive(Clone, Debug)]
 pub struct DesignSpaceIrSource {
@@ -111,72 +123,56 @@ pub(crate) fn layer_dir<'a>(
 }
 
 impl DesignSpaceIrSource {
-    fn create_work_for_one_glyph(
-        &self,
-        glyph_name: &GlyphName,
-        export: bool,
-    ) -> Result<GlyphIrWork, Error> {
-        // A single glif could be used by many source blocks that use the same layer
-        // *gasp*
-        // So resolve each file to 1..N locations in designspace
-        let Some(glif_files) = self.glyphs.get(glyph_name) else {
-            return Err(Error::NoLocationsForGlyph(glyph_name.clone()));
-        };
-
-        Ok(GlyphIrWork {
-            glyph_name: glyph_name.clone(),
-            export,
-            glif_files: glif_files.clone(),
-        })
-    }
-}
-
-fn load_designspace(
-    designspace_or_ufo: &Path,
-) -> Result<(PathBuf, DesignSpaceDocument), BadSourceKind> {
-    let Some(designspace_dir) = designspace_or_ufo.parent().map(|d| d.to_path_buf()) else {
-        return Err(BadSourceKind::ExpectedParent);
-    };
-
-    let Some(ext) = designspace_or_ufo
-        .extension()
-        .map(|s| s.to_ascii_lowercase())
-    else {
-        return Err(BadSourceKind::UnrecognizedExtension);
-    };
-    let designspace = match ext.to_str() {
-        Some("designspace") => {
-            DesignSpaceDocument::load(designspace_or_ufo).map_err(|e| match e {
-                norad::error::DesignSpaceLoadError::Io(e) => BadSourceKind::Io(e),
-                other => BadSourceKind::Custom(other.to_string()),
-            })?
-        }
-        Some("ufo") => {
-            let Some(filename) = designspace_or_ufo.file_name().and_then(|s| s.to_str()) else {
-                return Err(BadSourceKind::ExpectedDirectory);
-            };
-            DesignSpaceDocument {
-                format: 4.1,
-                sources: vec![norad::designspace::Source {
-                    filename: filename.to_owned(),
-                    ..Default::default()
-                }],
-                ..Default::default()
-            }
-        }
-        _ => return Err(BadSourceKind::UnrecognizedExtension),
-    };
-    debug!("Loaded {ext:?} from {designspace_or_ufo:?}");
-    Ok((designspace_dir, designspace))
-}
-
-impl Source for DesignSpaceIrSource {
-    fn new(designspace_or_ufo_file: &Path) -> Result<Self, Error> {
-        let (designspace_dir, mut designspace) = load_designspace(designspace_or_ufo_file)
-            .map_err(|kind| {
+    /// Path is to a .designspace or .ufo file
+    pub fn new(designspace_or_ufo_file: &Path) -> Result<Self, Error> {
+        let (designspace_dir, designspace) =
+            load_designspace(designspace_or_ufo_file).map_err(|kind| {
                 Error::BadSource(BadSource::new(designspace_or_ufo_file.to_path_buf(), kind))
             })?;
+        Self::from_designspace(designspace_or_ufo_file, designspace_dir, designspace)
+    }
+
+    /// The variable fonts of a designspace 5 file, each a source of its own named for its file.
+    ///
+    /// Each covers only its part of the designspace: axes outside the font are pinned
+    /// and sources and instances elsewhere are dropped. Empty if the designspace is
+    /// a single font, with neither `<variable-fonts>` nor discrete axes.
+    pub fn variable_fonts(designspace_file: &Path) -> Result<Vec<(String, Self)>, Error> {
+        let variable_fonts = variable_fonts(designspace_file)?;
+        if variable_fonts.is_empty() {
+            return Ok(Vec::new());
+        }
+        let (designspace_dir, designspace) =
+            load_designspace(designspace_file).map_err(|kind| {
+                Error::BadSource(BadSource::new(designspace_file.to_path_buf(), kind))
+            })?;
+        let discrete_axes = discrete_axes(designspace_file)?;
+        variable_fonts
+            .iter()
+            .map(|variable_font| {
+                let subset = subset_designspace(
+                    designspace_file,
+                    &designspace,
+                    &discrete_axes,
+                    variable_font,
+                )?;
+                debug!(
+                    "Variable font '{}' has {} sources",
+                    variable_font.name,
+                    subset.sources.len()
+                );
+                let source =
+                    Self::from_designspace(designspace_file, designspace_dir.clone(), subset)?;
+                Ok((variable_font.file_stem().to_string(), source))
+            })
+            .collect()
+    }
 
+    fn from_designspace(
+        designspace_or_ufo_file: &Path,
+        designspace_dir: PathBuf,
+        mut designspace: DesignSpaceDocument,
+    ) -> Result<Self, Error> {
         for (i, source) in designspace.sources.iter_mut().enumerate() {
             if source.name.is_none() {
                 source.name = Some(format!("unnamed_source_{i}"));
@@ -268,6 +264,66 @@ impl Source for DesignSpaceIrSource {
         })
     }
 
+    fn create_work_for_one_glyph(
+        &self,
+        glyph_name: &GlyphName,
+        export: bool,
+    ) -> Result<GlyphIrWork, Error> {
+        // A single glif could be used by many source blocks that use the same layer
+        // *gasp*
+        // So resolve each file to 1..N locations in designspace
+        let Some(glif_files) = self.glyphs.get(glyph_name) else {
+            return Err(Error::NoLocationsForGlyph(glyph_name.clone()));
+        };
+
+        Ok(GlyphIrWork {
+            glyph_name: glyph_name.clone(),
+            export,
+            glif_files: glif_files.clone(),
+        })
+    }
+}
+
+fn load_designspace(
+    designspace_or_ufo: &Path,
+) -> Result<(PathBuf, DesignSpaceDocument), BadSourceKind> {
+    let Some(designspace_dir) = designspace_or_ufo.parent().map(|d| d.to_path_buf()) else {
+        return Err(BadSourceKind::ExpectedParent);
+    };
+
+    let Some(ext) = designspace_or_ufo
+        .extension()
+        .map(|s| s.to_ascii_lowercase())
+    else {
+        return Err(BadSourceKind::UnrecognizedExtension);
+    };
+    let designspace = match ext.to_str() {
+        Some("designspace") => {
+            DesignSpaceDocument::load(designspace_or_ufo).map_err(|e| match e {
+                norad::error::DesignSpaceLoadError::Io(e) => BadSourceKind::Io(e),
+                other => BadSourceKind::Custom(other.to_string()),
+            })?
+        }
+        Some("ufo") => {
+            let Some(filename) = designspace_or_ufo.file_name().and_then(|s| s.to_str()) else {
+                return Err(BadSourceKind::ExpectedDirectory);
+            };
+            DesignSpaceDocument {
+                format: 4.1,
+                sources: vec![norad::designspace::Source {
+                    filename: filename.to_owned(),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }
+        }
+        _ => return Err(BadSourceKind::UnrecognizedExtension),
+    };
+    debug!("Loaded {ext:?} from {designspace_or_ufo:?}");
+    Ok((designspace_dir, designspace))
+}
+
+impl Source for DesignSpaceIrSource {
     fn create_static_metadata_work(&self) -> Result<Box<IrWork>, Error> {
         Ok(Box::new(StaticMetadataWork {
             designspace_or_ufo: self.designspace_or_ufo.clone(),
@@ -292,6 +348,12 @@ impl Source for DesignSpaceIrSource {
         }))
     }
 
+    fn create_feature_variations_work(&self) -> Result<Box<IrWork>, Error> {
+        Ok(Box::new(FeatureVariationsWork {
+            designspace: self.designspace.clone(),
+        }))
+    }
+
     fn create_kerning_group_ir_work(&self) -> Result<Box<IrWork>, Error> {
         Ok(Box::new(KerningGroupWork {
             designspace_or_ufo: self.designspace_or_ufo.clone(),
@@ -343,14 +405,98 @@ impl Source for DesignSpaceIrSource {
     fn create_color_palette_work(
         &self,
     ) -> Result<Box<fontir::orchestration::IrWork>, fontir::error::Error> {
-        Ok(Box::new(ColorPaletteWork {}))
+        Ok(Box::new(ColorPaletteWork {
+            designspace_or_ufo: self.designspace_or_ufo.clone(),
+            designspace_dir: self.designspace_dir.clone(),
+            designspace: self.designspace.clone(),
+        }))
     }
 
     fn create_paint_graph_work(
         &self,
     ) -> Result<Box<fontir::orchestration::IrWork>, fontir::error::Error> {
-        Ok(Box::new(PaintGraphWork {}))
+        Ok(Box::new(PaintGraphWork {
+            designspace_or_ufo: self.designspace_or_ufo.clone(),
+            designspace_dir: self.designspace_dir.clone(),
+            designspace: self.designspace.clone(),
+        }))
     }
+
+    fn fingerprint(&self) -> Result<Option<Fingerprints>, Error> {
+        let mut global = Fingerprinter::default();
+        let mut kerning = Fingerprinter::default();
+        if self.designspace_or_ufo.is_file() {
+            global.add_file(&self.designspace_or_ufo)?;
+        }
+
+        let ufo_dirs: BTreeSet<_> = self
+            .designspace
+            .sources
+            .iter()
+            .map(|source| self.designspace_dir.join(&source.filename))
+            .collect();
+        for ufo_dir in ufo_dirs {
+            let mut files = Vec::new();
+            files_in(&ufo_dir, &mut files)?;
+            for file in files {
+                let in_ufo_root = file.parent() == Some(ufo_dir.as_path());
+                match file.file_name().and_then(|name| name.to_str()) {
+                    Some("kerning.plist" | "groups.plist") if in_ufo_root => {
+                        kerning.add_file(&file)?;
+                    }
+                    // Features always recompile
+                    Some("features.fea") if in_ufo_root => (),
+                    // Glyphs are fingerprinted individually; a layer's glyph list only
+                    // maps names to glifs, which the glyph fingerprints already cover
+                    Some("contents.plist") if !in_ufo_root => (),
+                    Some(name) if name.ends_with(".glif") => (),
+                    _ => {
+                        global.add_file(&file)?;
+                    }
+                }
+            }
+        }
+
+        let glyphs = self
+            .glyphs
+            .iter()
+            .map(|(glyph_name, glif_files)| {
+                let mut glif_files: Vec<_> = glif_files.iter().collect();
+                glif_files.sort_by_key(|(glif_file, _)| *glif_file);
+                let mut fingerprint = Fingerprinter::default();
+                for (glif_file, locations) in glif_files {
+                    fingerprint.add_file(glif_file)?.add(locations);
+                }
+                Ok((glyph_name.clone(), fingerprint.finish()))
+            })
+            .collect::<Result<_, Error>>()?;
+
+        Ok(Some(Fingerprints {
+            global: global.finish(),
+            kerning: kerning.finish(),
+            glyphs,
+        }))
+    }
+}
+
+/// Every file under dir, recursively, in a stable order
+fn files_in(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), BadSource> {
+    let mut entries = fs::read_dir(dir)
+        .and_then(|entries| {
+            entries
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .map_err(|e| BadSource::new(dir, e))?;
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            files_in(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
 }
 
 #[derive(Debug)]
@@ -374,6 +520,11 @@ struct FeatureWork {
     fea_files: Arc<Vec<PathBuf>>,
 }
 
+#[derive(Debug)]
+struct FeatureVariationsWork {
+    designspace: Arc<DesignSpaceDocument>,
+}
+
 #[derive(Debug)]
 struct KerningGroupWork {
     designspace_or_ufo: Arc<PathBuf>,
@@ -390,10 +541,18 @@ struct KerningInstanceWork {
 }
 
 #[derive(Debug)]
-struct ColorPaletteWork {}
+struct ColorPaletteWork {
+    designspace_or_ufo: Arc<PathBuf>,
+    designspace_dir: Arc<PathBuf>,
+    designspace: Arc<DesignSpaceDocument>,
+}
 
 #[derive(Debug)]
-struct PaintGraphWork {}
+struct PaintGraphWork {
+    designspace_or_ufo: Arc<PathBuf>,
+    designspace_dir: Arc<PathBuf>,
+    designspace: Arc<DesignSpaceDocument>,
+}
 
 fn default_master(designspace: &DesignSpaceDocument) -> Option<(usize, &designspace::Source)> {
     let ds_axes = to_ir_axes(&designspace.axes).ok()?;
@@ -427,6 +586,17 @@ fn load_plist(ufo_dir: &Path, name: &str) -> Result<plist::Dictionary, BadSource
         .ok_or_else(|| BadSource::custom(lib_plist_file, "not a dictionary"))
 }
 
+/// The lib of a UFO, empty if it has none
+fn load_lib_plist(ufo_dir: &Path) -> Result<plist::Dictionary, BadSource> {
+    match load_plist(ufo_dir, "lib.plist") {
+        Err(BadSource {
+            kind: BadSourceKind::ExpectedFile,
+            ..
+        }) => Ok(Default::default()),
+        result => result,
+    }
+}
+
 // Per https://github.com/googlefonts/fontmake-rs/pull/43/files#r1044596662
 fn glyph_order(
     lib_plist: &plist::Dictionary,
@@ -501,6 +671,52 @@ fn glyph_categories(
     Ok(categories)
 }
 
+/// See <https://unifiedfontobject.org/versions/ufo3/lib.plist/#publicunicodevariationsequences>
+fn variation_sequences(
+    lib_plist: &plist::Dictionary,
+) -> Result<BTreeMap<u32, BTreeMap<u32, GlyphName>>, BadSource> {
+    const UNICODE_VARIATION_SEQUENCES: &str = "public.unicodeVariationSequences";
+
+    let Some(raw_sequences) = lib_plist.get(UNICODE_VARIATION_SEQUENCES) else {
+        return Ok(Default::default());
+    };
+    let not_a_dictionary = || {
+        BadSource::custom(
+            "lib.plist",
+            format!(
+                "value for '{UNICODE_VARIATION_SEQUENCES}' is not a dictionary of dictionaries"
+            ),
+        )
+    };
+    let parse_codepoint = |raw: &str| {
+        u32::from_str_radix(raw, 16).map_err(|_| {
+            BadSource::custom(
+                "lib.plist",
+                format!("'{raw}' in '{UNICODE_VARIATION_SEQUENCES}' is not a hex codepoint"),
+            )
+        })
+    };
+
+    let mut sequences = BTreeMap::new();
+    for (selector, mappings) in raw_sequences.as_dictionary().ok_or_else(not_a_dictionary)? {
+        let selector = parse_codepoint(selector)?;
+        let mappings = mappings.as_dictionary().ok_or_else(not_a_dictionary)?;
+        let entry: &mut BTreeMap<_, _> = sequences.entry(selector).or_default();
+        for (base, glyph_name) in mappings {
+            let Some(glyph_name) = glyph_name.as_string() else {
+                return Err(BadSource::custom(
+                    "lib.plist",
+                    format!(
+                        "glyph for '{base}' in '{UNICODE_VARIATION_SEQUENCES}' is not a string"
+                    ),
+                ));
+            };
+            entry.insert(parse_codepoint(base)?, GlyphName::new(glyph_name));
+        }
+    }
+    Ok(sequences)
+}
+
 fn postscript_names(lib_plist: &plist::Dictionary) -> Result<PostscriptNames, BadSource> {
     let postscript_names = match lib_plist.get("public.postscriptNames") {
         Some(value) => {
@@ -769,15 +985,7 @@ impl Work<Context, WorkId, Error> for StaticMetadataWork {
         .into_values()
         .collect();
 
-        let lib_plist =
-            match load_plist(&designspace_dir.join(&default_master.filename), "lib.plist") {
-                Ok(lib_plist) => lib_plist,
-                Err(BadSource {
-                    kind: BadSourceKind::ExpectedFile,
-                    ..
-                }) => Default::default(),
-                Err(e) => return Err(e)?,
-            };
+        let lib_plist = load_lib_plist(&designspace_dir.join(&default_master.filename))?;
         let glyph_order = glyph_order(&lib_plist, &self.glyph_names)?;
         let glyph_categories = glyph_categories(&lib_plist).map(|categories| GdefCategories {
             categories,
@@ -807,6 +1015,8 @@ impl Work<Context, WorkId, Error> for StaticMetadataWork {
                 StyleMapStyle::BoldItalic => SelectionFlags::BOLD | SelectionFlags::ITALIC,
             };
 
+        let variation_sequences = variation_sequences(&lib_plist)?;
+
         let postscript_names = if context.flags.contains(Flags::PRODUCTION_NAMES) {
             postscript_names(&lib_plist)?
         } else {
@@ -829,6 +1039,8 @@ impl Work<Context, WorkId, Error> for StaticMetadataWork {
         )
         .map_err(Error::VariationModelError)?;
         static_metadata.misc.selection_flags = selection_flags;
+        static_metadata.variation_sequences = variation_sequences;
+        static_metadata.glyph_filters = glyph_filters(&lib_plist)?;
         if let Some(vendor_id) = font_info_at_default
             .open_type_os2_vendor_id
             .as_ref()
@@ -929,6 +1141,44 @@ impl Work<Context, WorkId, Error> for StaticMetadataWork {
                 .collect();
         }
 
+        // Like ufo2ft, PostScript hinting values are rounded and the first stems are the StdHW/StdVW
+        let rounded = |values: &Option<Vec<f64>>| {
+            values
+                .iter()
+                .flatten()
+                .map(|v| v.ot_round())
+                .collect::<Vec<i16>>()
+        };
+        static_metadata.misc.postscript_hints = PostscriptHints {
+            blue_values: rounded(&font_info_at_default.postscript_blue_values),
+            other_blues: rounded(&font_info_at_default.postscript_other_blues),
+            stem_snap_h: rounded(&font_info_at_default.postscript_stem_snap_h),
+            stem_snap_v: rounded(&font_info_at_default.postscript_stem_snap_v),
+        };
+
+        // Like ufo2ft, only build vertical tables if the vhea metrics are defined
+        static_metadata.misc.build_vertical = font_info_at_default
+            .open_type_vhea_vert_typo_ascender
+            .is_some()
+            && font_info_at_default
+                .open_type_vhea_vert_typo_descender
+                .is_some()
+            && font_info_at_default
+                .open_type_vhea_vert_typo_line_gap
+                .is_some();
+
+        if self
+            .designspace_or_ufo
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("designspace"))
+        {
+            let stat_labels =
+                stat_labels(&self.designspace_or_ufo, &static_metadata.all_source_axes)?;
+            static_metadata.set_stat_labels(stat_labels);
+            static_metadata.axis_mappings =
+                axis_mappings(&self.designspace_or_ufo, &static_metadata.all_source_axes)?;
+        }
+
         context.preliminary_glyph_order.set(glyph_order);
         context.static_metadata.set(static_metadata);
         Ok(())
@@ -1153,6 +1403,42 @@ impl Work<Context, WorkId, Error> for GlobalMetricsWork {
                 pos.clone(),
                 font_info.open_type_hhea_caret_offset.map(|v| v as f64),
             );
+            metrics.set_if_some(
+                GlobalMetric::VheaAscender,
+                pos.clone(),
+                font_info
+                    .open_type_vhea_vert_typo_ascender
+                    .map(|v| v as f64),
+            );
+            metrics.set_if_some(
+                GlobalMetric::VheaDescender,
+                pos.clone(),
+                font_info
+                    .open_type_vhea_vert_typo_descender
+                    .map(|v| v as f64),
+            );
+            metrics.set_if_some(
+                GlobalMetric::VheaLineGap,
+                pos.clone(),
+                font_info
+                    .open_type_vhea_vert_typo_line_gap
+                    .map(|v| v as f64),
+            );
+            metrics.set_if_some(
+                GlobalMetric::VheaCaretSlopeRise,
+                pos.clone(),
+                font_info.open_type_vhea_caret_slope_rise.map(|v| v as f64),
+            );
+            metrics.set_if_some(
+                GlobalMetric::VheaCaretSlopeRun,
+                pos.clone(),
+                font_info.open_type_vhea_caret_slope_run.map(|v| v as f64),
+            );
+            metrics.set_if_some(
+                GlobalMetric::VheaCaretOffset,
+                pos.clone(),
+                font_info.open_type_vhea_caret_offset.map(|v| v as f64),
+            );
             metrics.set_if_some(
                 GlobalMetric::UnderlineThickness,
                 pos.clone(),
@@ -1220,6 +1506,132 @@ impl Work<Context, WorkId, Error> for FeatureWork {
     }
 }
 
+/// The features to register rules under.
+///
+/// See <https://github.com/fonttools/fonttools/blob/a6b2ac2d5/Lib/fontTools/varLib/__init__.py#L800-L805>
+fn rules_features(designspace: &DesignSpaceDocument) -> Result<Vec<Tag>, Error> {
+    if let Some(raw_tags) = designspace
+        .lib
+        .get(FEATURE_VARS_FEATURE_TAG)
+        .and_then(|v| v.as_string())
+    {
+        return raw_tags
+            .split(',')
+            .map(|raw_tag| {
+                Tag::from_str(raw_tag.trim()).map_err(|cause| Error::InvalidTag {
+                    raw_tag: raw_tag.to_string(),
+                    cause,
+                })
+            })
+            .collect();
+    }
+    Ok(vec![match designspace.rules.processing {
+        designspace::RuleProcessing::First => FeatureVariations::PROCESS_FIRST_FEATURE,
+        designspace::RuleProcessing::Last => FeatureVariations::PROCESS_LAST_FEATURE,
+    }])
+}
+
+/// Convert a designspace rule, whose conditions are in design coordinates, to IR
+fn to_ir_rule(
+    rule_name: &str,
+    rule: &designspace::Rule,
+    axes_by_name: &HashMap<&str, fontdrasil::types::Axis>,
+) -> Result<ConditionalSubstitution, Error> {
+    let condition_sets = rule
+        .condition_sets
+        .iter()
+        .map(|condition_set| {
+            let conditions = condition_set
+                .conditions
+                .iter()
+                .map(|condition| {
+                    let Some(axis) = axes_by_name.get(condition.name.as_str()) else {
+                        return Err(Error::NoAxisForCondition {
+                            rule: rule_name.to_string(),
+                            axis: condition.name.clone(),
+                        });
+                    };
+                    let normalize = |design: Option<f32>, unbounded: f64| {
+                        design
+                            .map(|v| {
+                                DesignCoord::new(v as f64)
+                                    .to_normalized(&axis.converter)
+                                    .to_f64()
+                                    .clamp(-1.0, 1.0)
+                            })
+                            .unwrap_or(unbounded)
+                    };
+                    let min = normalize(condition.minimum, -1.0);
+                    let max = normalize(condition.maximum, 1.0);
+                    if min > max {
+                        return Err(Error::InvalidCondition {
+                            rule: rule_name.to_string(),
+                            axis: condition.name.clone(),
+                            min,
+                            max,
+                        });
+                    }
+                    Ok(Condition {
+                        axis: axis.tag,
+                        min: NormalizedCoord::new(min),
+                        max: NormalizedCoord::new(max),
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(ConditionSet::new(conditions))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    let substitutions = rule
+        .substitutions
+        .iter()
+        .map(|sub| {
+            (
+                GlyphName::new(sub.name.as_str()),
+                GlyphName::new(sub.with.as_str()),
+            )
+        })
+        .collect();
+    Ok(ConditionalSubstitution {
+        condition_sets,
+        substitutions,
+    })
+}
+
+impl Work<Context, WorkId, Error> for FeatureVariationsWork {
+    fn id(&self) -> WorkId {
+        WorkId::FeatureVariations
+    }
+
+    fn exec(&self, context: &Context) -> Result<(), Error> {
+        let rules = &self.designspace.rules.rules;
+        if rules.is_empty() {
+            return Ok(());
+        }
+        let axes_by_name = self
+            .designspace
+            .axes
+            .iter()
+            .map(|axis| to_ir_axis(axis).map(|ir_axis| (axis.name.as_str(), ir_axis)))
+            .collect::<Result<HashMap<_, _>, _>>()?;
+
+        let rules = rules
+            .iter()
+            .enumerate()
+            .map(|(i, rule)| {
+                let rule_name = rule.name.clone().unwrap_or_else(|| format!("rule_{i}"));
+                to_ir_rule(&rule_name, rule, &axes_by_name)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let features = rules_features(&self.designspace)?;
+        debug!("{} rules registered under {features:?}", rules.len());
+
+        context
+            .feature_variations
+            .set(FeatureVariations::new(features, rules));
+        Ok(())
+    }
+}
+
 fn kerning_groups_for(
     designspace_dir: &Path,
     glyph_order: &GlyphOrder,
@@ -1556,8 +1968,17 @@ impl Work<Context, WorkId, Error> for ColorPaletteWork {
         Access::Variant(WorkId::ColorPalettes)
     }
 
-    fn exec(&self, _context: &Context) -> Result<(), Error> {
-        debug!("Color palettes not implemented for UFO");
+    fn exec(&self, context: &Context) -> Result<(), Error> {
+        debug!("Color palettes for {:#?}", self.designspace_or_ufo);
+        let Some((_, default_master)) = default_master(&self.designspace) else {
+            return Err(Error::NoDefaultMaster(
+                self.designspace_or_ufo.to_path_buf(),
+            ));
+        };
+        let lib_plist = load_lib_plist(&self.designspace_dir.join(&default_master.filename))?;
+        if let Some(palettes) = ColorPalettes::new(color_palettes(&lib_plist)?)? {
+            context.colors.set(palettes);
+        }
         Ok(())
     }
 }
@@ -1568,19 +1989,123 @@ impl Work<Context, WorkId, Error> for PaintGraphWork {
     }
 
     fn read_access(&self) -> Access<WorkId> {
-        Access::None
+        AccessBuilder::new()
+            .variant(WorkId::StaticMetadata)
+            .variant(WorkId::ColorPalettes)
+            .build()
     }
 
     fn write_access(&self) -> Access<WorkId> {
         Access::Variant(WorkId::PaintGraph)
     }
 
-    fn exec(&self, _context: &Context) -> Result<(), Error> {
-        debug!("Paint graph not implemented for UFO");
+    fn exec(&self, context: &Context) -> Result<(), Error> {
+        debug!("Paint graph for {:#?}", self.designspace_or_ufo);
+        let designspace_dir = self.designspace_dir.as_ref();
+        let static_metadata = context.static_metadata.get();
+        let master_locations =
+            master_locations(&static_metadata.all_source_axes, &self.designspace.sources);
+        let Some((_, default_master)) = default_master(&self.designspace) else {
+            return Err(Error::NoDefaultMaster(
+                self.designspace_or_ufo.to_path_buf(),
+            ));
+        };
+
+        // Paints use indices into the source palettes, which are reordered in IR
+        let source_palettes = color_palettes(&load_lib_plist(
+            &designspace_dir.join(&default_master.filename),
+        )?)?;
+        let palettes = context.colors.try_get();
+        let palette_indices = palette_indices(&source_palettes, palettes.as_deref());
+
+        let mut paint_graph = PaintGraph::default();
+        for source in self
+            .designspace
+            .sources
+            .iter()
+            .filter(|s| !is_glyph_only(s))
+        {
+            let pos = master_locations.get(source.name.as_ref().unwrap()).unwrap();
+            let lib_plist = load_lib_plist(&designspace_dir.join(&source.filename))?;
+            for (glyph_name, mut paint) in color_glyphs(&lib_plist)? {
+                remap_palette_indices(&mut paint, &palette_indices).map_err(|index| {
+                    BadSource::custom(
+                        designspace_dir.join(&source.filename).join("lib.plist"),
+                        format!(
+                            "'{glyph_name}' uses palette index {index}, there is no such color"
+                        ),
+                    )
+                })?;
+                paint_graph
+                    .base_glyphs
+                    .entry(glyph_name)
+                    .or_insert_with(|| ColorGlyph {
+                        sources: HashMap::new(),
+                    })
+                    .sources
+                    .insert(pos.clone(), paint);
+            }
+        }
+        debug!("{} color glyphs", paint_graph.base_glyphs.len());
+        if !paint_graph.is_empty() {
+            context.paint_graph.set(paint_graph);
+        }
         Ok(())
     }
 }
 
+/// The IR palette index of each source palette index.
+///
+/// A single palette is deduplicated and sorted, many palettes keep their order.
+fn palette_indices(source_palettes: &[Vec<Color>], palettes: Option<&ColorPalettes>) -> Vec<u16> {
+    let (Some(source_palette), Some(palettes)) = (source_palettes.first(), palettes) else {
+        return Vec::new();
+    };
+    source_palette
+        .iter()
+        .enumerate()
+        .map(|(i, color)| match palettes.palettes.as_slice() {
+            [palette] => palette.iter().position(|c| c == color).unwrap_or(i) as u16,
+            _ => i as u16,
+        })
+        .collect()
+}
+
+/// Replace source palette indices with IR ones, failing with any index that has no color
+fn remap_palette_indices(paint: &mut Paint, palette_indices: &[u16]) -> Result<(), u16> {
+    let remap = |index: &mut u16| {
+        if *index != FOREGROUND_PALETTE_INDEX {
+            *index = *palette_indices.get(*index as usize).ok_or(*index)?;
+        }
+        Ok(())
+    };
+    match paint {
+        Paint::Layers(layers) => layers
+            .iter_mut()
+            .try_for_each(|layer| remap_palette_indices(layer, palette_indices)),
+        Paint::Solid { palette_index, .. } => remap(palette_index),
+        Paint::LinearGradient { color_line, .. }
+        | Paint::RadialGradient { color_line, .. }
+        | Paint::SweepGradient { color_line, .. } => color_line
+            .stops
+            .iter_mut()
+            .try_for_each(|stop| remap(&mut stop.palette_index)),
+        Paint::ColrGlyph(_) => Ok(()),
+        Paint::Glyph { paint, .. }
+        | Paint::Transform { paint, .. }
+        | Paint::Translate { paint, .. }
+        | Paint::Scale { paint, .. }
+        | Paint::Rotate { paint, .. }
+        | Paint::Skew { paint, .. } => remap_palette_indices(paint, palette_indices),
+        Paint::Composite {
+            source, backdrop, ..
+        } => {
+            remap_palette_indices(source, palette_indices)?;
+            remap_palette_indices(backdrop, palette_indices)
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::{
@@ -2024,6 +2549,10 @@ mod tests {
                 hhea_ascender: 1194.0.into(),
                 hhea_descender: (-290.0).into(),
                 hhea_line_gap: 43.0.into(),
+                vhea_ascender: 500.0.into(),
+                vhea_descender: (-500.0).into(),
+                vhea_line_gap: 1000.0.into(),
+                vhea_caret_slope_run: 1.0.into(),
                 underline_thickness: 50.0.into(),
                 underline_position: (-75.0).into(),
                 ..Default::default()
@@ -2318,6 +2847,72 @@ mod tests {
         );
     }
 
+    fn build_feature_variations(name: &str) -> Option<Arc<FeatureVariations>> {
+        let source = load_designspace(name);
+        let context = Context::new_root(
+            default_test_flags(),
+            Paths::new(Path::new("/nothing/should/write/here")),
+        );
+        let work = source.create_feature_variations_work().unwrap();
+        work.exec(&context.copy_for_work(work.read_access(), work.write_access()))
+            .unwrap();
+        context.feature_variations.try_get()
+    }
+
+    #[test]
+    fn no_rules_no_feature_variations() {
+        assert_eq!(None, build_feature_variations("wght_var.designspace"));
+    }
+
+    #[test]
+    fn rules_to_feature_variations() {
+        let wght = Tag::new(b"wght");
+        let condition = |min: f64, max: f64| Condition {
+            axis: wght,
+            min: NormalizedCoord::new(min),
+            max: NormalizedCoord::new(max),
+        };
+        assert_eq!(
+            Some(Arc::new(FeatureVariations::new(
+                vec![Tag::new(b"rvrn")],
+                vec![
+                    ConditionalSubstitution {
+                        condition_sets: vec![ConditionSet::new([condition(0.5, 1.0)])],
+                        substitutions: [("bar".into(), "plus".into())].into(),
+                    },
+                    ConditionalSubstitution {
+                        condition_sets: vec![ConditionSet::new([condition(0.75, 1.0)])],
+                        substitutions: [("plus".into(), "bar".into())].into(),
+                    },
+                ]
+            ))),
+            build_feature_variations("wght_var_rules.designspace")
+        );
+    }
+
+    #[test]
+    fn rules_processed_last() {
+        let mut designspace = DesignSpaceDocument::default();
+        designspace.rules.processing = designspace::RuleProcessing::Last;
+        assert_eq!(
+            vec![Tag::new(b"rclt")],
+            rules_features(&designspace).unwrap()
+        );
+    }
+
+    #[test]
+    fn rules_feature_from_lib() {
+        let mut designspace = DesignSpaceDocument::default();
+        designspace.lib.insert(
+            FEATURE_VARS_FEATURE_TAG.to_string(),
+            plist::Value::String("calt,rlig".to_string()),
+        );
+        assert_eq!(
+            vec![Tag::new(b"calt"), Tag::new(b"rlig")],
+            rules_features(&designspace).unwrap()
+        );
+    }
+
     fn assert_fs_type(src: &str, expected: u16) {
         let (_, context) = build_static_metadata(src, default_test_flags());
         let static_metadata = context.static_metadata.get();
@@ -2352,6 +2947,24 @@ mod tests {
         assert_eq!(Some(expected), static_metadata.misc.panose);
     }
 
+    #[test]
+    fn captures_postscript_hints() {
+        let (_, context) = build_static_metadata(
+            "designspace_from_glyphs/WghtVar.designspace",
+            default_test_flags(),
+        );
+        let static_metadata = context.static_metadata.get();
+        assert_eq!(
+            PostscriptHints {
+                blue_values: vec![-16, 0, 737, 753],
+                other_blues: vec![-58, -42],
+                stem_snap_h: Vec::new(),
+                stem_snap_v: Vec::new(),
+            },
+            static_metadata.misc.postscript_hints
+        );
+    }
+
     #[test]
     fn parse_meta_table_values() {
         let (_, context) = build_static_metadata("MetaTable.ufo", default_test_flags());
@@ -2361,6 +2974,46 @@ mod tests {
         assert_eq!(meta_table.slng, ["Latn", "Cyrl"]);
     }
 
+    #[test]
+    fn captures_glyph_filters() {
+        let (_, context) = build_static_metadata("Filters.ufo", default_test_flags());
+        let static_metadata = context.static_metadata.get();
+        assert_eq!(
+            vec![true, false, false],
+            static_metadata
+                .glyph_filters
+                .iter()
+                .map(|filter| filter.pre)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn captures_unicode_variation_sequences() {
+        let (_, context) =
+            build_static_metadata("UnicodeVariationSequences.ufo", default_test_flags());
+        let static_metadata = context.static_metadata.get();
+        assert_eq!(
+            vec![
+                (0xFE0E, 0x2764, "heart"),
+                (0xFE0F, 0x2764, "heart.emoji"),
+                (0xE0100, 0x82A6, "uni82A6"),
+                (0xE0101, 0x82A6, "uni82A6.jp"),
+            ],
+            static_metadata
+                .variation_sequences
+                .iter()
+                .flat_map(
+                    |(selector, mappings)| mappings.iter().map(|(base, glyph_name)| (
+                        *selector,
+                        *base,
+                        glyph_name.as_str()
+                    ))
+                )
+                .collect::<Vec<_>>()
+        );
+    }
+
     #[test]
     fn ignore_empty_meta_table_values() {
         let mut plist = plist::Dictionary::new();
@@ -75,6 +75,8 @@ fn to_ir_component(component: &norad::Component) -> ir::Component {
             component.transform.x_offset,
             component.transform.y_offset,
         ]),
+        variable: None,
+        anchor: None,
     }
 }
 
@@ -83,9 +85,15 @@ fn to_ir_glyph_instance(glyph: &norad::Glyph) -> Result<ir::GlyphInstance, Error
     for contour in glyph.contours.iter() {
         contours.push(to_ir_contour(glyph.name().as_str().into(), contour)?);
     }
+    // <https://unifiedfontobject.org/versions/ufo3/glyphs/glif/#publicverticalorigin>
+    let vertical_origin = glyph.lib.get("public.verticalOrigin").and_then(|v| {
+        v.as_real()
+            .or_else(|| v.as_signed_integer().map(|v| v as f64))
+    });
     Ok(ir::GlyphInstance {
         width: glyph.width,
         height: Some(glyph.height),
+        vertical_origin,
         contours,
         components: glyph.components.iter().map(to_ir_component).collect(),
     })
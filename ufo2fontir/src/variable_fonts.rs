@@ -0,0 +1,277 @@
+//! Restrict a designspace to one of the variable fonts it describes.
+//!
+//! Port of the parts of fontTools [splitVariableFonts](https://github.com/fonttools/fonttools/blob/main/Lib/fontTools/designspaceLib/split.py)
+//! we need. Rather than removing the axes a font doesn't vary along we pin them, making
+//! them point axes, so locations that mention them still resolve.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+};
+
+use fontdrasil::coords::{DesignCoord, UserCoord};
+use fontir::error::{BadSource, Error};
+use norad::designspace::{self, DesignSpaceDocument, Dimension};
+
+use crate::{
+    designspace5::{AxisSubset, VariableFontDescriptor},
+    toir::to_ir_axis,
+};
+
+/// The design range of an axis a variable font covers
+#[derive(Debug, Clone, Copy)]
+struct DesignRange {
+    min: DesignCoord,
+    /// Where locations that don't mention the axis are, the default of the whole designspace
+    implicit: DesignCoord,
+    max: DesignCoord,
+}
+
+impl DesignRange {
+    fn contains(&self, value: DesignCoord) -> bool {
+        // tolerate values that were rounded to f32 by norad
+        const TOLERANCE: f64 = 1e-4;
+        value.to_f64() >= self.min.to_f64() - TOLERANCE
+            && value.to_f64() <= self.max.to_f64() + TOLERANCE
+    }
+
+    fn contains_location(&self, axis_name: &str, location: &[Dimension]) -> bool {
+        let value = location
+            .iter()
+            .find(|dim| dim.name == axis_name)
+            .and_then(|dim| dim.xvalue)
+            .map(|value| DesignCoord::new(value as f64))
+            .unwrap_or(self.implicit);
+        self.contains(value)
+    }
+}
+
+/// A copy of `designspace` with only the axes, sources, instances and rules of `variable_font`.
+///
+/// `discrete_axes` are the values of discrete axes by name, each is pinned at one of its values.
+pub(crate) fn subset_designspace(
+    designspace_file: &Path,
+    designspace: &DesignSpaceDocument,
+    discrete_axes: &BTreeMap<String, Vec<UserCoord>>,
+    variable_font: &VariableFontDescriptor,
+) -> Result<DesignSpaceDocument, Error> {
+    let bad_source =
+        |message: String| Error::BadSource(BadSource::custom(designspace_file, message));
+    for axis_name in variable_font.axis_subsets.keys() {
+        if !designspace.axes.iter().any(|axis| &axis.name == axis_name) {
+            return Err(bad_source(format!(
+                "variable font '{}' refers to undefined axis '{axis_name}'",
+                variable_font.name
+            )));
+        }
+    }
+
+    let mut subset = designspace.clone();
+    let mut ranges = HashMap::new();
+    for axis in subset.axes.iter_mut() {
+        let discrete_values = discrete_axes.get(&axis.name);
+        if let Some(values) = discrete_values {
+            // the values span the axis for the purpose of converting coordinates
+            axis.minimum = values.iter().min().map(|v| v.to_f64() as f32);
+            axis.maximum = values.iter().max().map(|v| v.to_f64() as f32);
+        }
+        let ir_axis = to_ir_axis(axis)?;
+        let (min, default, max) = match variable_font.axis_subsets.get(&axis.name) {
+            // Any axis not mentioned is pinned at its default
+            None => (ir_axis.default, ir_axis.default, ir_axis.default),
+            Some(AxisSubset::Value(value)) => (*value, *value, *value),
+            Some(AxisSubset::Range { .. }) if discrete_values.is_some() => {
+                return Err(bad_source(format!(
+                    "variable font '{}' needs a single value for discrete axis '{}'",
+                    variable_font.name, axis.name
+                )));
+            }
+            Some(AxisSubset::Range { min, default, max }) => (
+                min.unwrap_or(ir_axis.min).max(ir_axis.min),
+                default.unwrap_or(ir_axis.default),
+                max.unwrap_or(ir_axis.max).min(ir_axis.max),
+            ),
+        };
+        if !(min..=max).contains(&default) {
+            return Err(bad_source(format!(
+                "variable font '{}' has default {default:?} outside {min:?}..{max:?} for axis '{}'",
+                variable_font.name, axis.name
+            )));
+        }
+
+        let to_design = |user: UserCoord| user.to_design(&ir_axis.converter);
+        axis.minimum = Some(min.to_f64() as f32);
+        axis.default = default.to_f64() as f32;
+        axis.maximum = Some(max.to_f64() as f32);
+        if let Some(map) = axis.map.as_mut() {
+            // keep the part of the mapping the font covers, making sure its ends are included
+            map.retain(|m| (min..=max).contains(&UserCoord::new(m.input as f64)));
+            for user in [min, default, max] {
+                if !map.iter().any(|m| m.input as f64 == user.to_f64()) {
+                    map.push(designspace::AxisMapping {
+                        input: user.to_f64() as f32,
+                        output: to_design(user).to_f64() as f32,
+                    });
+                }
+            }
+            map.sort_by(|a, b| a.input.total_cmp(&b.input));
+        }
+        ranges.insert(
+            axis.name.clone(),
+            DesignRange {
+                min: to_design(min),
+                implicit: to_design(ir_axis.default),
+                max: to_design(max),
+            },
+        );
+    }
+
+    let within = |location: &[Dimension]| {
+        ranges
+            .iter()
+            .all(|(axis_name, range)| range.contains_location(axis_name, location))
+    };
+    subset.sources.retain(|source| within(&source.location));
+    subset
+        .instances
+        .retain(|instance| within(&instance.location));
+
+    // Conditions on pinned axes either always or never hold
+    let pinned = |axis_name: &str| ranges.get(axis_name).filter(|range| range.min == range.max);
+    subset.rules.rules.retain_mut(|rule| {
+        if rule.condition_sets.is_empty() {
+            return true;
+        }
+        rule.condition_sets.retain_mut(|condition_set| {
+            let mut applies = true;
+            condition_set.conditions.retain(|condition| {
+                let Some(range) = pinned(&condition.name) else {
+                    return true;
+                };
+                let value = range.min.to_f64();
+                applies &= condition.minimum.is_none_or(|min| value >= min as f64)
+                    && condition.maximum.is_none_or(|max| value <= max as f64);
+                false
+            });
+            applies
+        });
+        // A rule whose conditions can't hold here doesn't belong to this font
+        !rule.condition_sets.is_empty()
+    });
+
+    Ok(subset)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use norad::designspace::DesignSpaceDocument;
+
+    use crate::designspace5::{discrete_axes, variable_fonts};
+
+    use super::*;
+
+    fn subsets(name: &str) -> Vec<(String, DesignSpaceDocument)> {
+        let path = Path::new("../resources/testdata").join(name);
+        let designspace = DesignSpaceDocument::load(&path).unwrap();
+        let discrete_axes = discrete_axes(&path).unwrap();
+        variable_fonts(&path)
+            .unwrap()
+            .iter()
+            .map(|vf| {
+                (
+                    vf.file_stem().to_string(),
+                    subset_designspace(&path, &designspace, &discrete_axes, vf).unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    fn axis_ranges(designspace: &DesignSpaceDocument) -> Vec<(&str, f32, f32, f32)> {
+        designspace
+            .axes
+            .iter()
+            .map(|axis| {
+                (
+                    axis.tag.as_str(),
+                    axis.minimum.unwrap(),
+                    axis.default,
+                    axis.maximum.unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    fn source_names(designspace: &DesignSpaceDocument) -> Vec<&str> {
+        designspace
+            .sources
+            .iter()
+            .map(|source| source.name.as_deref().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn subsets_declared_variable_fonts() {
+        let subsets = subsets("wght_ital_vfs.designspace");
+        assert_eq!(
+            vec!["WghtItal-Roman", "WghtItal-Italic", "WghtItal-RomanLight"],
+            subsets
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>()
+        );
+
+        let (_, roman) = &subsets[0];
+        assert_eq!(
+            vec![("wght", 400.0, 400.0, 700.0), ("ital", 0.0, 0.0, 0.0)],
+            axis_ranges(roman)
+        );
+        assert_eq!(vec!["Regular", "Bold"], source_names(roman));
+        // the rule only applies to italics
+        assert!(roman.rules.rules.is_empty());
+
+        let (_, italic) = &subsets[1];
+        assert_eq!(
+            vec![("wght", 400.0, 400.0, 700.0), ("ital", 1.0, 1.0, 1.0)],
+            axis_ranges(italic)
+        );
+        assert_eq!(vec!["Italic", "Bold Italic"], source_names(italic));
+        // the rule applies throughout, its condition on ital is gone
+        assert_eq!(1, italic.rules.rules.len());
+        assert_eq!(
+            vec!["Weight"],
+            italic.rules.rules[0].condition_sets[0]
+                .conditions
+                .iter()
+                .map(|c| c.name.as_str())
+                .collect::<Vec<_>>()
+        );
+
+        let (_, light) = &subsets[2];
+        assert_eq!(
+            vec![("wght", 400.0, 400.0, 550.0), ("ital", 0.0, 0.0, 0.0)],
+            axis_ranges(light)
+        );
+        assert_eq!(vec!["Regular"], source_names(light));
+    }
+
+    #[test]
+    fn discrete_axes_imply_variable_fonts() {
+        let subsets = subsets("wght_ital_discrete.designspace");
+        assert_eq!(
+            vec!["wght_ital_discrete-VF-ital0", "wght_ital_discrete-VF-ital1"],
+            subsets
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(vec!["Regular", "Bold"], source_names(&subsets[0].1));
+        assert_eq!(vec!["Italic", "Bold Italic"], source_names(&subsets[1].1));
+    }
+
+    #[test]
+    fn single_font_has_no_variable_fonts() {
+        assert!(subsets("wght_var.designspace").is_empty());
+    }
+}